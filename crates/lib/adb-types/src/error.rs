@@ -0,0 +1,68 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::fmt::{Display, Formatter};
+
+#[cfg(not(feature = "std"))]
+use core::error::Error;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+/// A value failed to parse, e.g. a socket family string.
+///
+/// The `adb` crate converts it into `AdbError::Parse`, which has the same fields.
+#[derive(Debug)]
+pub struct ParseError {
+    pub value: String,
+    pub source_type: &'static str,
+    pub target_type: &'static str,
+    pub source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "failed when parsing `{}` from `{}` into `{}`",
+            self.value, self.source_type, self.target_type
+        )?;
+        if let Some(reason) = &self.source {
+            write!(f, ": {}", reason)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn test_error_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ParseError>();
+    }
+
+    #[test]
+    fn test_display() {
+        let err = ParseError {
+            value: "jdwp:0".to_string(),
+            source_type: "&str",
+            target_type: "Jdwp",
+            source: Some("no process has the pid 0".into()),
+        };
+        assert_eq!(
+            "failed when parsing `jdwp:0` from `&str` into `Jdwp`: no process has the pid 0",
+            err.to_string()
+        );
+        assert!(err.source().is_some());
+    }
+}
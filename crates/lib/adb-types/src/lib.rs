@@ -0,0 +1,43 @@
+//! The adb socket family types, parsed from and formatted as the strings of the adb
+//! protocol, without the client stack of the `adb` crate, which re-exports them.
+//!
+//! # Features
+//!
+//! - `std` (default): resolution of hostnames with [`socket::Tcp::from_host`], on top of
+//!   the resolver of the system. Without it, the crate is `no_std` with `alloc`, e.g. for
+//!   agents running on devices, and paths of the device are plain strings.
+//! - `serde`: `Serialize` and `Deserialize` for socket families, as their string form.
+//!
+//! # MSRV
+//!
+//! The minimum supported Rust version is 1.70 with `std`, and 1.81 without it, for
+//! `core::net` and `core::error`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+/// Implements `Serialize` and `Deserialize` through the `Display` and `FromStr`
+/// implementations, like the `AdbSocketFamily` derive.
+#[cfg(feature = "serde")]
+macro_rules! serde_via_str {
+    ($($ty:ty),*) => {
+        $(
+            impl serde::Serialize for $ty {
+                fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> serde::Deserialize<'de> for $ty {
+                fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+                    s.parse().map_err(serde::de::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+pub mod error;
+pub mod socket;
@@ -0,0 +1,1140 @@
+//! This module provides some types representing the adb socket families.
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+
+#[cfg(not(feature = "std"))]
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+#[cfg(feature = "std")]
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use derive::AdbSocketFamily;
+
+use crate::error::ParseError;
+
+/// A path on the device, a `String` without the `std` feature.
+#[cfg(not(feature = "std"))]
+pub type PathBuf = String;
+#[cfg(not(feature = "std"))]
+type Path = str;
+
+/// Displays the paths of the device without `std`, like `Path::display` does, for the
+/// `Display` implementations of the derive.
+#[cfg(not(feature = "std"))]
+trait DisplayPath {
+    fn display(&self) -> &str;
+}
+
+#[cfg(not(feature = "std"))]
+impl DisplayPath for String {
+    fn display(&self) -> &str {
+        self
+    }
+}
+
+/// Returns `path` as a string, replacing invalid UTF-8 with `U+FFFD`.
+fn path_str(path: &Path) -> Cow<'_, str> {
+    #[cfg(feature = "std")]
+    return path.to_string_lossy();
+    #[cfg(not(feature = "std"))]
+    return Cow::Borrowed(path);
+}
+
+/// A marker trait for adb socket families.
+///
+/// By implementing this trait, a type guarantees that:
+///
+/// - It can be parsed from a valid adb socket family string.
+/// - It can be displayed as a valid argument for an adb command.
+/// - It can be converted from a string with [`TryFrom`], which also validates the values,
+///   like the `new` constructors of the socket families.
+///
+/// [`FromStr`] only checks the syntax, so that anything listed by the adb server parses.
+///
+/// ```
+/// use adb_types::socket::LocalAbstract;
+///
+/// assert!("localabstract:".parse::<LocalAbstract>().is_ok());
+/// assert!(LocalAbstract::try_from("localabstract:").is_err());
+/// ```
+pub trait AdbSocketFamily:
+    FromStr + Display + for<'a> TryFrom<&'a str, Error = ParseError>
+{
+    /// The family, the prefix before the first colon, e.g. `tcp` or `dev-raw`.
+    ///
+    /// Empty for [`AdbSocketFamilies`], which spans all the families.
+    const FAMILY: &'static str;
+
+    /// Returns the family of the socket, [`Self::FAMILY`] unless the type spans several
+    /// families.
+    ///
+    /// ```
+    /// use adb_types::socket::{AdbSocketFamilies, AdbSocketFamily, Jdwp};
+    ///
+    /// assert_eq!("jdwp", AdbSocketFamilies::Jdwp(Jdwp(1234)).family_name());
+    /// ```
+    fn family_name(&self) -> &'static str {
+        Self::FAMILY
+    }
+}
+
+/// Parses a socket of any family, dispatching on the prefix before the first colon.
+///
+/// Like parsing an [`AdbSocketFamilies`], but fails on unknown families instead of returning
+/// [`AdbSocketFamilies::Other`].
+///
+/// # Examples
+///
+/// ```
+/// use adb_types::socket::{parse_any, AdbSocketFamilies, Vsock};
+///
+/// assert_eq!(
+///     AdbSocketFamilies::Vsock(Vsock { cid: 2, port: 5555 }),
+///     parse_any("vsock:2:5555").unwrap()
+/// );
+/// let err = parse_any("udp:5555").unwrap_err();
+/// assert!(err.to_string().contains("unknown family `udp`, expected one of `tcp`"));
+/// ```
+pub fn parse_any(s: &str) -> Result<AdbSocketFamilies, ParseError> {
+    match s.parse()? {
+        // Conversions reject unknown families.
+        AdbSocketFamilies::Other(_) => AdbSocketFamilies::try_from(s),
+        family => Ok(family),
+    }
+}
+
+/// Returns the error of a socket family constructor rejecting `value`.
+fn invalid(
+    value: impl Display,
+    source_type: &'static str,
+    target_type: &'static str,
+    reason: &str,
+) -> ParseError {
+    ParseError {
+        value: value.to_string(),
+        source_type,
+        target_type,
+        source: Some(reason.into()),
+    }
+}
+
+/// Checks that `name` is a valid socket name or path: not empty, without NULs or newlines.
+fn check_name(
+    name: &str,
+    source_type: &'static str,
+    target_type: &'static str,
+) -> Result<(), ParseError> {
+    if name.is_empty() {
+        Err(invalid(name, source_type, target_type, "empty name"))
+    } else if name.contains(['\0', '\n', '\r']) {
+        Err(invalid(
+            name.escape_debug(),
+            source_type,
+            target_type,
+            "NUL or newline in name",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that `path` is a valid absolute path on the device.
+fn check_absolute(path: &Path, target_type: &'static str) -> Result<(), ParseError> {
+    let name = path_str(path);
+    check_name(&name, "PathBuf", target_type)?;
+    if name.starts_with('/') {
+        Ok(())
+    } else {
+        Err(invalid(name, "PathBuf", target_type, "relative path"))
+    }
+}
+
+/// The address families of the `adb` command.
+///
+/// Families added by future adb versions are parsed as [`AdbSocketFamilies::Other`], but
+/// rejected by [`TryFrom`] and [`parse_any`].
+#[derive(AdbSocketFamily, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[adb(error = "crate::error::ParseError")]
+#[cfg_attr(feature = "serde", adb(serde))]
+#[non_exhaustive]
+pub enum AdbSocketFamilies {
+    Tcp(Tcp),
+    LocalAbstract(LocalAbstract),
+    LocalReserved(LocalReserved),
+    LocalFileSystem(LocalFileSystem),
+    Dev(Dev),
+    DevRaw(DevRaw),
+    Jdwp(Jdwp),
+    Vsock(Vsock),
+    AcceptFd(AcceptFd),
+    /// A socket of a family unknown to this crate, e.g. `udp:5555`.
+    #[adb(other)]
+    Other(String),
+}
+
+impl AdbSocketFamilies {
+    /// Returns the family of the socket, the prefix before the first colon, also for
+    /// [`Self::Other`].
+    ///
+    /// ```
+    /// use adb_types::socket::AdbSocketFamilies;
+    ///
+    /// let socket: AdbSocketFamilies = "udp:5555".parse().unwrap();
+    /// assert_eq!("udp", socket.family());
+    /// assert!(!socket.is_known());
+    /// ```
+    pub fn family(&self) -> &str {
+        match self {
+            Self::Other(s) => s.split_once(':').map_or(s.as_str(), |(family, _)| family),
+            family => family.family_name(),
+        }
+    }
+
+    /// Returns `false` for [`Self::Other`].
+    pub fn is_known(&self) -> bool {
+        !matches!(self, Self::Other(_))
+    }
+}
+
+/// A TCP socket. Both IPv4 and IPv6 addresses are supported.
+///
+/// # Syntax
+///
+/// `tcp:[host:[port]]`
+///
+/// - `host`: Optional hostname or IP address.
+///   If an IPv6 address is provided, it should be enclosed in square brackets.
+/// - `port`: Optional port number.
+///
+/// # Note
+///
+/// Semantically, `host` and `port` should not be None at the same time.
+///
+/// In this case, the `Tcp` socket is considered invalid and behaves as follows:
+/// - The [`Display`] implementation will return an empty string.
+/// - The [`FromStr`] implementation will return an error.
+///
+/// ```
+/// # use adb_types::socket::Tcp;
+/// assert!("tcp:".parse::<Tcp>().is_err());
+/// assert_eq!(Tcp { ip: None, port: None }.to_string(), "");
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Tcp {
+    // The IP address of the host.
+    pub ip: Option<IpAddr>,
+    // The port number.
+    pub port: Option<u16>,
+}
+
+impl Tcp {
+    /// Creates a new `Tcp` socket with the given IP address and port number.
+    pub const fn new(host: IpAddr, port: u16) -> Self {
+        Self {
+            ip: Some(host),
+            port: Some(port),
+        }
+    }
+
+    /// Creates a new `Tcp` socket with the given IP address.
+    pub const fn from_ip(host: IpAddr) -> Self {
+        Self {
+            ip: Some(host),
+            port: None,
+        }
+    }
+
+    /// Creates a new `Tcp` socket with the given IPv4 address.
+    pub const fn from_ipv4(host: Ipv4Addr) -> Self {
+        Self {
+            ip: Some(IpAddr::V4(host)),
+            port: None,
+        }
+    }
+
+    /// Creates a new `Tcp` socket with the given IPv6 address.
+    pub const fn from_ipv6(host: Ipv6Addr) -> Self {
+        Self {
+            ip: Some(IpAddr::V6(host)),
+            port: None,
+        }
+    }
+
+    /// Creates a new `Tcp` socket with the given port number.
+    pub const fn from_port(port: u16) -> Self {
+        Self {
+            ip: None,
+            port: Some(port),
+        }
+    }
+
+    /// Creates a `Tcp` socket on port 0, asking the listener to pick a free port, e.g. the
+    /// local port of `Device::forward`.
+    ///
+    /// ```
+    /// # use adb_types::socket::Tcp;
+    /// assert_eq!("tcp:0", Tcp::any_local().to_string());
+    /// assert!(Tcp::any_local().is_wildcard());
+    /// ```
+    pub const fn any_local() -> Self {
+        Self::from_port(0)
+    }
+
+    /// Returns `true` if the port is 0, left for the listener to pick.
+    pub const fn is_wildcard(&self) -> bool {
+        matches!(self.port, Some(0))
+    }
+
+    /// Resolves the given hostname into an IP address. If the resolution results
+    /// in multiple IP addresses, IPv4 addresses are preferred.
+    ///
+    /// # Note
+    ///
+    /// The resolution may block the current thread while resolution is performed.
+    /// If this is not desired, consider using [`FromStr`] which is non-blocking, or
+    /// `TcpExt::from_host_with` of the `adb` crate which gives up after a timeout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use adb_types::socket::Tcp;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let tcp = Tcp::from_host("localhost").unwrap();
+    /// assert_eq!(tcp, Tcp::from_ipv4(Ipv4Addr::new(127, 0, 0, 1)));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_host(host: &str) -> Result<Self, ParseError> {
+        host.parse().or_else(|_| {
+            Self::resolve(host).or_else(|e| {
+                // ToSocketAddrs requires a hostname with a port number.
+                // Retry if the input hostname does not contain a port number,
+                match Self::resolve(&format!("{host}:0")) {
+                    Ok(tcp) => Ok(Self {
+                        ip: tcp.ip,
+                        port: None,
+                    }),
+                    _ => Err(e),
+                }
+            })
+        })
+    }
+
+    #[cfg(feature = "std")]
+    fn resolve(host: &str) -> Result<Self, ParseError> {
+        let mut addrs = host.to_socket_addrs().map_err(|e| ParseError {
+            value: host.to_string(),
+            source_type: "&str",
+            target_type: "std::vec::IntoIter<SocketAddr>",
+            source: Some(Box::new(e)),
+        })?;
+        let first = addrs.next();
+        match first {
+            None => Err(ParseError {
+                value: host.to_string(),
+                source_type: "&str",
+                target_type: "SocketAddr",
+                source: None,
+            }),
+            Some(SocketAddr::V4(v4)) => Ok(v4.into()),
+            _ => Ok(addrs.find(SocketAddr::is_ipv4).or(first).unwrap().into()),
+        }
+    }
+}
+
+impl Display for Tcp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match (self.ip, self.port) {
+            (Some(IpAddr::V4(v4)), Some(port)) => write!(f, "tcp:{}:{}", v4, port),
+            (Some(IpAddr::V6(v6)), Some(port)) => write!(f, "tcp:[{}]:{}", v6, port),
+            (Some(IpAddr::V4(v4)), None) => write!(f, "tcp:{}", v4),
+            (Some(IpAddr::V6(v6)), None) => write!(f, "tcp:[{}]", v6),
+            (None, Some(port)) => write!(f, "tcp:{}", port),
+            (None, None) => write!(f, ""),
+        }
+    }
+}
+
+impl FromStr for Tcp {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("tcp:") {
+            None | Some("") => Err(ParseError {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Tcp",
+                source: None,
+            }),
+            Some(value) => {
+                if let Ok(port) = value.parse::<u16>() {
+                    Ok(port.into())
+                } else if let Ok(addr) = value.parse::<SocketAddr>() {
+                    Ok(addr.into())
+                } else if let Ok(v4) = value.parse::<Ipv4Addr>() {
+                    Ok(v4.into())
+                } else {
+                    value
+                        .strip_prefix('[')
+                        .and_then(|value| value.strip_suffix(']'))
+                        .map_or_else(
+                            || {
+                                Err(ParseError {
+                                    value: value.to_string(),
+                                    source_type: "&str",
+                                    target_type: "Ipv6Addr",
+                                    source: None,
+                                })
+                            },
+                            |value| {
+                                value.parse::<Ipv6Addr>().map_or_else(
+                                    |e| {
+                                        Err(ParseError {
+                                            value: value.to_string(),
+                                            source_type: "&str",
+                                            target_type: "Ipv6Addr",
+                                            source: Some(Box::new(e)),
+                                        })
+                                    },
+                                    |v6| Ok(v6.into()),
+                                )
+                            },
+                        )
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for Tcp {
+    type Error = ParseError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl AdbSocketFamily for Tcp {
+    const FAMILY: &'static str = "tcp";
+}
+
+impl From<SocketAddr> for Tcp {
+    fn from(addr: SocketAddr) -> Self {
+        Self::new(addr.ip(), addr.port())
+    }
+}
+
+impl From<SocketAddrV4> for Tcp {
+    fn from(addr: SocketAddrV4) -> Self {
+        Self::new(IpAddr::V4(*addr.ip()), addr.port())
+    }
+}
+
+impl From<SocketAddrV6> for Tcp {
+    fn from(addr: SocketAddrV6) -> Self {
+        Self::new(IpAddr::V6(*addr.ip()), addr.port())
+    }
+}
+
+impl From<IpAddr> for Tcp {
+    fn from(ip: IpAddr) -> Self {
+        Self::from_ip(ip)
+    }
+}
+
+impl From<Ipv4Addr> for Tcp {
+    fn from(ipv4: Ipv4Addr) -> Self {
+        Self::from_ipv4(ipv4)
+    }
+}
+
+impl From<Ipv6Addr> for Tcp {
+    fn from(ipv6: Ipv6Addr) -> Self {
+        Self::from_ipv6(ipv6)
+    }
+}
+
+impl From<u16> for Tcp {
+    fn from(port: u16) -> Self {
+        Self::from_port(port)
+    }
+}
+
+/// A Unix domain socket in the abstract namespace.
+///
+/// # Syntax
+///
+/// `localabstract:<unix domain socket name>`
+#[derive(AdbSocketFamily, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[adb(error = "crate::error::ParseError", validate)]
+#[cfg_attr(feature = "serde", adb(serde))]
+pub struct LocalAbstract(pub String);
+
+impl LocalAbstract {
+    /// Creates a new `LocalAbstract` socket, failing if `name` is empty or contains a NUL
+    /// or a newline.
+    pub fn new(name: impl Into<String>) -> Result<Self, ParseError> {
+        let name = name.into();
+        check_name(&name, "String", "LocalAbstract")?;
+        Ok(Self(name))
+    }
+}
+
+/// A Unix domain socket in the reserved namespace.
+///
+/// # Syntax
+///
+///`localreserved:<unix domain socket name>`
+#[derive(AdbSocketFamily, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[adb(error = "crate::error::ParseError", validate)]
+#[cfg_attr(feature = "serde", adb(serde))]
+pub struct LocalReserved(pub String);
+
+impl LocalReserved {
+    /// Creates a new `LocalReserved` socket, failing if `name` is empty or contains a NUL
+    /// or a newline.
+    pub fn new(name: impl Into<String>) -> Result<Self, ParseError> {
+        let name = name.into();
+        check_name(&name, "String", "LocalReserved")?;
+        Ok(Self(name))
+    }
+}
+
+/// A Unix domain socket in the file system.
+///
+/// # Syntax
+///
+/// `localfilesystem:<unix domain socket name>`
+#[derive(AdbSocketFamily, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[adb(error = "crate::error::ParseError", validate)]
+#[cfg_attr(feature = "serde", adb(serde))]
+pub struct LocalFileSystem(pub PathBuf);
+
+impl LocalFileSystem {
+    /// Creates a new `LocalFileSystem` socket, failing if `path` is empty or contains a NUL
+    /// or a newline.
+    ///
+    /// Relative paths are resolved by adbd from its working directory.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, ParseError> {
+        let path = path.into();
+        check_name(&path_str(&path), "PathBuf", "LocalFileSystem")?;
+        Ok(Self(path))
+    }
+}
+
+/// A character device.
+///
+/// # Syntax
+///
+/// `dev:<character device name>`
+#[derive(AdbSocketFamily, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[adb(error = "crate::error::ParseError", validate)]
+#[cfg_attr(feature = "serde", adb(serde))]
+pub struct Dev(pub PathBuf);
+
+impl Dev {
+    /// Creates a new `Dev` socket, failing if `path` isn't absolute, e.g. `/dev/ttyS0`.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, ParseError> {
+        let path = path.into();
+        check_absolute(&path, "Dev")?;
+        Ok(Self(path))
+    }
+}
+
+/// Open device in raw mode.
+///
+/// # Syntax
+///
+/// `dev-raw:<character device name>`
+#[derive(AdbSocketFamily, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[adb(rename = "dev-raw", error = "crate::error::ParseError", validate)]
+#[cfg_attr(feature = "serde", adb(serde))]
+pub struct DevRaw(pub PathBuf);
+
+impl DevRaw {
+    /// Creates a new `DevRaw` socket, failing if `path` isn't absolute, e.g. `/dev/ttyS0`.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, ParseError> {
+        let path = path.into();
+        check_absolute(&path, "DevRaw")?;
+        Ok(Self(path))
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_str!(Tcp);
+
+/// A Java Debug Wire Protocol process.
+///
+/// # Syntax
+///
+/// `jdwp:<process pid>`
+#[derive(AdbSocketFamily, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[adb(error = "crate::error::ParseError", validate)]
+#[cfg_attr(feature = "serde", adb(serde))]
+pub struct Jdwp(pub u32);
+
+impl Jdwp {
+    /// Creates a new `Jdwp` socket, failing if `pid` is 0.
+    pub fn new(pid: u32) -> Result<Self, ParseError> {
+        if pid == 0 {
+            return Err(invalid(pid, "u32", "Jdwp", "no process has the pid 0"));
+        }
+        Ok(Self(pid))
+    }
+}
+
+/// A VSOCK address.
+///
+/// # Syntax
+///
+/// `vsock:<cid>:<port>`
+#[derive(AdbSocketFamily, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[adb(error = "crate::error::ParseError", validate)]
+#[cfg_attr(feature = "serde", adb(serde))]
+pub struct Vsock {
+    pub cid: u32,
+    pub port: u32,
+}
+
+impl Vsock {
+    /// `VMADDR_CID_ANY` and `VMADDR_PORT_ANY`, which only bind and can't be connected to.
+    const ANY: u32 = u32::MAX;
+
+    /// Creates a new `Vsock` address, failing if `cid` or `port` is the wildcard
+    /// `VMADDR_CID_ANY` or `VMADDR_PORT_ANY`.
+    pub fn new(cid: u32, port: u32) -> Result<Self, ParseError> {
+        if cid == Self::ANY {
+            return Err(invalid(
+                cid,
+                "u32",
+                "Vsock",
+                "VMADDR_CID_ANY is not an address",
+            ));
+        }
+        if port == Self::ANY {
+            return Err(invalid(
+                port,
+                "u32",
+                "Vsock",
+                "VMADDR_PORT_ANY is not a port",
+            ));
+        }
+        Ok(Self { cid, port })
+    }
+}
+
+/// A file descriptor for a socket.
+///
+/// # Syntax
+///
+/// `acceptfd:<fd>`
+#[derive(AdbSocketFamily, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[adb(error = "crate::error::ParseError", validate)]
+#[cfg_attr(feature = "serde", adb(serde))]
+pub struct AcceptFd(pub u32);
+
+impl AcceptFd {
+    /// Creates a new `AcceptFd` socket, failing if `fd` doesn't fit in a C `int`.
+    pub fn new(fd: u32) -> Result<Self, ParseError> {
+        if fd > i32::MAX as u32 {
+            return Err(invalid(fd, "u32", "AcceptFd", "not a file descriptor"));
+        }
+        Ok(Self(fd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TCP_COMMON: [(&str, Tcp); 5] = [
+        ("tcp:5555", Tcp::from_port(5555)),
+        ("tcp:127.0.0.1", Tcp::from_ipv4(Ipv4Addr::new(127, 0, 0, 1))),
+        (
+            "tcp:[::1]",
+            Tcp::from_ipv6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)),
+        ),
+        (
+            "tcp:127.0.0.1:5555",
+            Tcp::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 5555),
+        ),
+        (
+            "tcp:[::1]:5555",
+            Tcp::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)), 5555),
+        ),
+    ];
+
+    const TCP_PARSE_ERR: [&str; 30] = [
+        "",
+        "tcp:",
+        // incomplete address
+        "tcp:127.0",
+        "tcp:127.0:5555",
+        "tcp:[]",
+        "tcp:[]:5555",
+        "tcp:[:]",
+        "tcp:[:5555]",
+        "tcp:5555:",
+        // Ipv6 address without square brackets
+        "tcp:::",
+        "tcp:::1",
+        "tcp:::1:5555",
+        "tcp:ffff::1:5555",
+        "tcp:1111:2222:3333:4444:5555:6666:7777:8888",
+        "tcp:1111:2222:3333:4444:5555:6666:7777:8888:5555",
+        // IpAddr out of range
+        "tcp:256.0.0.0",
+        "tcp:256.-1.0.0",
+        "tcp:[gggg::]",
+        "tcp:[::gggg]",
+        // port out of range
+        "tcp:-1",
+        "tcp:65536",
+        // SocketAddr out of range
+        "tcp:256.0.0.0:-1",
+        "tcp:256.0.0.0:5555",
+        "tcp:256.0.0.0:65536",
+        "tcp:256.-1.0.0:5555",
+        "tcp:[gggg::]:5555",
+        "tcp:[::gggg]:5555",
+        // invalid characters
+        "tcp:abcd",
+        "tcp:a.b.c.d",
+        "tcp:a.b.c.d:p",
+    ];
+
+    #[test]
+    fn test_tcp_wildcard() {
+        assert_eq!(Tcp::from_port(0), Tcp::any_local());
+        assert!("tcp:127.0.0.1:0".parse::<Tcp>().unwrap().is_wildcard());
+        assert!(!Tcp::from_port(5555).is_wildcard());
+        assert!(!Tcp::from_ipv4(Ipv4Addr::LOCALHOST).is_wildcard());
+    }
+
+    #[test]
+    fn test_tcp_display() {
+        for (s, tcp) in TCP_COMMON {
+            assert_eq!(s, tcp.to_string());
+        }
+    }
+
+    #[test]
+    fn test_tcp_parse() {
+        for (s, tcp) in TCP_COMMON {
+            assert_eq!(tcp, s.parse().unwrap());
+        }
+        for s in TCP_PARSE_ERR {
+            assert!(s.parse::<Tcp>().is_err(), "{}", s);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    const TCP_RESOLVE_OK: [(&str, Tcp); 2] = [
+        (
+            "localhost:5555",
+            Tcp::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 5555),
+        ),
+        ("localhost", Tcp::from_ipv4(Ipv4Addr::new(127, 0, 0, 1))),
+    ];
+
+    #[cfg(feature = "std")]
+    const TCP_RESOLVE_ERR: [&str; 6] = [
+        "local-host",
+        "local-host:5555",
+        "localhost:",
+        "abcd",
+        "a.b.c.d",
+        "a.b.c.d:p",
+    ];
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_tcp_resolve() {
+        for (s, tcp) in TCP_RESOLVE_OK {
+            assert_eq!(tcp, Tcp::from_host(s).unwrap());
+        }
+        for s in TCP_RESOLVE_ERR {
+            assert!(Tcp::from_host(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_local_abstract_display() {
+        let local_abstract = LocalAbstract("socket".to_string());
+        assert_eq!("localabstract:socket", local_abstract.to_string());
+    }
+
+    #[test]
+    fn test_local_abstract_parse() {
+        let local_abstract = LocalAbstract("socket".to_string());
+        assert_eq!(local_abstract, "localabstract:socket".parse().unwrap());
+    }
+
+    #[test]
+    fn test_local_reserved_display() {
+        let local_reserved = LocalReserved("socket".to_string());
+        assert_eq!("localreserved:socket", local_reserved.to_string());
+    }
+
+    #[test]
+    fn test_local_reserved_parse() {
+        let local_reserved = LocalReserved("socket".to_string());
+        assert_eq!(local_reserved, "localreserved:socket".parse().unwrap());
+    }
+
+    #[test]
+    fn test_local_file_system_display() {
+        let local_file_system = LocalFileSystem(PathBuf::from("/path/to/socket"));
+        assert_eq!(
+            "localfilesystem:/path/to/socket",
+            local_file_system.to_string()
+        );
+    }
+
+    #[test]
+    fn test_local_file_system_parse() {
+        let local_file_system = LocalFileSystem(PathBuf::from("/path/to/socket"));
+        assert_eq!(
+            local_file_system,
+            "localfilesystem:/path/to/socket".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_dev_display() {
+        let dev = Dev(PathBuf::from("/dev/tty"));
+        assert_eq!("dev:/dev/tty", dev.to_string());
+    }
+
+    #[test]
+    fn test_dev_parse() {
+        let dev = Dev(PathBuf::from("/dev/tty"));
+        assert_eq!(dev, "dev:/dev/tty".parse().unwrap());
+    }
+
+    #[test]
+    fn test_dev_raw_display() {
+        let dev_raw = DevRaw(PathBuf::from("/dev/tty"));
+        assert_eq!("dev-raw:/dev/tty", dev_raw.to_string());
+    }
+
+    #[test]
+    fn test_dev_raw_parse() {
+        let dev_raw = DevRaw(PathBuf::from("/dev/tty"));
+        assert_eq!(dev_raw, "dev-raw:/dev/tty".parse().unwrap());
+    }
+
+    #[test]
+    fn test_dev_raw_try_from() {
+        assert_eq!(DevRaw::FAMILY, "dev-raw");
+        assert!(DevRaw::try_from("dev-raw:/dev/tty").is_ok());
+        assert!(DevRaw::try_from("dev-raw:tty").is_err());
+        assert!("dev:/dev/tty".parse::<DevRaw>().is_err());
+    }
+
+    /// A family customized by the options of the derive.
+    #[derive(AdbSocketFamily, Clone, Eq, PartialEq, Debug)]
+    #[adb(
+        rename = "x-test",
+        separator = ',',
+        error = "crate::error::ParseError",
+        validate
+    )]
+    struct Custom {
+        name: String,
+        #[adb(skip)]
+        hits: u32,
+        #[adb(default)]
+        port: Option<u16>,
+    }
+
+    impl Custom {
+        fn new(name: String, port: Option<u16>) -> Result<Self, ParseError> {
+            Ok(Self {
+                name,
+                hits: 1,
+                port,
+            })
+        }
+    }
+
+    #[test]
+    fn test_derive_options() {
+        assert_eq!("x-test", Custom::FAMILY);
+        let custom = Custom {
+            name: "a:b".to_string(),
+            hits: 3,
+            port: Some(5555),
+        };
+        assert_eq!("x-test:a:b,5555", custom.to_string());
+        let parsed: Custom = "x-test:a:b,5555".parse().unwrap();
+        assert_eq!(0, parsed.hits);
+        assert_eq!(Some(5555), parsed.port);
+        let parsed: Custom = "x-test:a".parse().unwrap();
+        assert_eq!(None, parsed.port);
+        assert_eq!("x-test:a", parsed.to_string());
+        assert_eq!(1, Custom::try_from("x-test:a,1").unwrap().hits);
+        assert!("x-test:a,".parse::<Custom>().is_err());
+        assert!("custom:a".parse::<Custom>().is_err());
+    }
+
+    /// A generic family, bounded by the derive on the fields of type `T`, whose errors are
+    /// bounded by `std::error::Error`.
+    #[cfg(feature = "std")]
+    #[derive(AdbSocketFamily, Clone, Eq, PartialEq, Debug)]
+    #[adb(error = "crate::error::ParseError")]
+    struct Pair<T> {
+        first: T,
+        #[adb(default)]
+        second: Option<T>,
+    }
+
+    #[cfg(feature = "std")]
+    impl<T> Pair<T> {
+        fn new(first: T, second: Option<T>) -> Result<Self, ParseError> {
+            Ok(Self { first, second })
+        }
+    }
+
+    /// A generic wrapper of any family.
+    #[cfg(feature = "std")]
+    #[derive(AdbSocketFamily, Clone, Eq, PartialEq, Debug)]
+    #[adb(error = "crate::error::ParseError")]
+    enum Wrapped<T>
+    where
+        T: AdbSocketFamily,
+    {
+        Inner(T),
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_derive_generics() {
+        let pair: Pair<u16> = "pair:1:2".parse().unwrap();
+        assert_eq!(Pair::new(1, Some(2)).unwrap(), pair);
+        assert_eq!(
+            "pair:1",
+            Pair::<u16>::try_from("pair:1").unwrap().to_string()
+        );
+        assert!("pair:1:x".parse::<Pair<u16>>().is_err());
+        assert_eq!("pair", Pair::<Jdwp>::FAMILY);
+
+        let wrapped: Wrapped<Jdwp> = "jdwp:42".parse().unwrap();
+        assert_eq!(Wrapped::from(Jdwp(42)), wrapped);
+        assert_eq!("jdwp", wrapped.family_name());
+        assert_eq!(["jdwp"], Wrapped::<Jdwp>::FAMILIES);
+        assert!(Wrapped::<Jdwp>::try_from("tcp:5555").is_err());
+    }
+
+    const OVERFLOW: u64 = u32::MAX as u64 + 1;
+
+    #[test]
+    fn test_jdwp_display() {
+        let jdwp = Jdwp(1234);
+        assert_eq!("jdwp:1234", jdwp.to_string());
+    }
+
+    #[test]
+    fn test_jdwp_parse() {
+        let jdwp = Jdwp(1234);
+        assert_eq!(jdwp, "jdwp:1234".parse().unwrap());
+        let err = ["jdwp", "jdwp:", "jdwp:-1", &format!("jdwp:{}", OVERFLOW)];
+        for s in &err {
+            assert!(s.parse::<Jdwp>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_vsock_display() {
+        let vsock = Vsock { cid: 1, port: 2 };
+        assert_eq!("vsock:1:2", vsock.to_string());
+    }
+
+    #[test]
+    fn test_vsock_parse() {
+        let vsock = Vsock { cid: 1, port: 2 };
+        assert_eq!(vsock, "vsock:1:2".parse().unwrap());
+        let err = [
+            "vsock",
+            "vsock:",
+            "vsock:1",
+            "vsock::1",
+            "vsock:1:",
+            "vsock:-1",
+            "vsock:-1:-1",
+            &format!("vsock:1:{}", OVERFLOW),
+            &format!("vsock:{}:2", OVERFLOW),
+            &format!("vsock:{}:{}", OVERFLOW, OVERFLOW),
+        ];
+        for s in &err {
+            assert!(s.parse::<Vsock>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_accept_fd_display() {
+        let accept_fd = AcceptFd(1);
+        assert_eq!("acceptfd:1", accept_fd.to_string());
+    }
+
+    #[test]
+    fn test_accept_fd_parse() {
+        let accept_fd = AcceptFd(1);
+        assert_eq!(accept_fd, "acceptfd:1".parse().unwrap());
+        let err = [
+            "acceptfd",
+            "acceptfd:",
+            "acceptfd:-1",
+            &format!("acceptfd:{}", OVERFLOW),
+        ];
+        for s in &err {
+            assert!(s.parse::<AcceptFd>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_new_validates() {
+        assert!(LocalAbstract::new("chrome_devtools_remote").is_ok());
+        assert!(LocalAbstract::new("").is_err());
+        assert!(LocalAbstract::new("socket\0").is_err());
+        assert!(LocalAbstract::new("socket\n").is_err());
+        assert!(LocalReserved::new("").is_err());
+        assert!(LocalFileSystem::new("tmp/socket").is_ok());
+        assert!(LocalFileSystem::new("").is_err());
+        assert!(Dev::new("/dev/ttyS0").is_ok());
+        assert!(Dev::new("ttyS0").is_err());
+        assert!(DevRaw::new("").is_err());
+        assert!(Jdwp::new(0).is_err());
+        assert!(Vsock::new(2, 5555).is_ok());
+        assert!(Vsock::new(u32::MAX, 5555).is_err());
+        assert!(Vsock::new(2, u32::MAX).is_err());
+        assert!(AcceptFd::new(3).is_ok());
+        assert!(AcceptFd::new(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn test_try_from_validates() {
+        assert_eq!(
+            Dev(PathBuf::from("/dev/tty")),
+            Dev::try_from("dev:/dev/tty").unwrap()
+        );
+        assert!(Dev::try_from("dev:tty").is_err());
+        assert!(DevRaw::try_from("dev-raw:tty").is_err());
+        assert!(Jdwp::try_from("jdwp:0").is_err());
+        assert!(Tcp::try_from("tcp:").is_err());
+        assert_eq!(
+            AdbSocketFamilies::Vsock(Vsock { cid: 2, port: 5555 }),
+            AdbSocketFamilies::try_from("vsock:2:5555").unwrap()
+        );
+        assert!(AdbSocketFamilies::try_from("localabstract:").is_err());
+        assert!("localabstract:".parse::<AdbSocketFamilies>().is_ok());
+    }
+
+    #[test]
+    fn test_family_name() {
+        assert_eq!("localfilesystem", LocalFileSystem::FAMILY);
+        assert_eq!("dev-raw", DevRaw::FAMILY);
+        assert_eq!("", AdbSocketFamilies::FAMILY);
+        let families = [
+            "tcp:5555",
+            "localabstract:socket",
+            "localreserved:socket",
+            "localfilesystem:/socket",
+            "dev:/dev/tty",
+            "dev-raw:/dev/tty",
+            "jdwp:1234",
+            "vsock:2:5555",
+            "acceptfd:3",
+        ];
+        for s in families {
+            let family = parse_any(s).unwrap();
+            assert_eq!(s.split(':').next().unwrap(), family.family_name());
+            assert_eq!(s, family.to_string());
+        }
+    }
+
+    #[test]
+    fn test_other_family() {
+        let other: AdbSocketFamilies = "udp:5555".parse().unwrap();
+        assert_eq!(AdbSocketFamilies::Other("udp:5555".to_string()), other);
+        assert_eq!("udp:5555", other.to_string());
+        assert_eq!("udp", other.family());
+        assert!(!other.is_known());
+        assert!(AdbSocketFamilies::try_from("udp:5555").is_err());
+        let tcp: AdbSocketFamilies = "tcp:5555".parse().unwrap();
+        assert_eq!("tcp", tcp.family());
+        assert!(tcp.is_known());
+        // Known families still report their own errors.
+        assert!("jdwp:pid".parse::<AdbSocketFamilies>().is_err());
+        assert!("".parse::<AdbSocketFamilies>().is_err());
+    }
+
+    #[test]
+    fn test_parse_any_errors() {
+        for s in ["", "tcp", "udp:5555", "dev-raw-x:/dev/tty"] {
+            assert!(parse_any(s).is_err(), "{}", s);
+        }
+        let ParseError { source, .. } = parse_any("udp:5555").unwrap_err();
+        let families = AdbSocketFamilies::FAMILIES.join("`, `");
+        assert_eq!(
+            format!("unknown family `udp`, expected one of `{}`", families),
+            source.unwrap().to_string()
+        );
+        assert_eq!(9, AdbSocketFamilies::FAMILIES.len());
+        assert_eq!("dev-raw", AdbSocketFamilies::FAMILIES[5]);
+        // A known family names the variant, with the error of its type as the source.
+        for err in [
+            parse_any("jdwp:pid").unwrap_err(),
+            "jdwp:pid".parse::<AdbSocketFamilies>().unwrap_err(),
+        ] {
+            let ParseError {
+                target_type,
+                source,
+                ..
+            } = err;
+            assert_eq!("AdbSocketFamilies::Jdwp", target_type);
+            let source = source.unwrap();
+            assert!(!source.to_string().contains("unknown family"));
+            assert!(matches!(
+                source.downcast_ref::<ParseError>(),
+                Some(ParseError {
+                    target_type: "u32",
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_deserialize() {
+        use serde::de::value::{Error, StrDeserializer};
+        use serde::de::IntoDeserializer;
+        use serde::Deserialize;
+
+        fn deserialize<'de, T: Deserialize<'de>>(s: &'de str) -> Result<T, Error> {
+            let deserializer: StrDeserializer<Error> = s.into_deserializer();
+            T::deserialize(deserializer)
+        }
+
+        let tcp = Tcp::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5555);
+        assert_eq!(tcp, deserialize("tcp:127.0.0.1:5555").unwrap());
+        assert_eq!(
+            AdbSocketFamilies::Tcp(tcp),
+            deserialize("tcp:127.0.0.1:5555").unwrap()
+        );
+        assert_eq!(
+            Vsock { cid: 2, port: 5555 },
+            deserialize("vsock:2:5555").unwrap()
+        );
+        assert_eq!(
+            DevRaw(PathBuf::from("/dev/ttyS0")),
+            deserialize("dev-raw:/dev/ttyS0").unwrap()
+        );
+        assert!(deserialize::<Jdwp>("tcp:5555").is_err());
+    }
+}
@@ -0,0 +1,23 @@
+//! Prints devices as they appear, disappear or change state, until interrupted.
+//!
+//! ```text
+//! cargo run --example device_watcher
+//! ```
+
+use adb::server::AdbServer;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let server = AdbServer::default();
+    let tracker = server.track_devices()?.reconnect(true);
+    println!("watching devices of {}", server.addr());
+    for event in tracker {
+        let event = event?;
+        match (event.old_state, event.new_state) {
+            (None, Some(state)) => println!("+ {} ({})", event.serial, state),
+            (Some(state), None) => println!("- {} ({})", event.serial, state),
+            (Some(old), Some(new)) => println!("~ {} ({} -> {})", event.serial, old, new),
+            (None, None) => {}
+        }
+    }
+    Ok(())
+}
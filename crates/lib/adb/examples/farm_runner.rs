@@ -0,0 +1,54 @@
+//! Runs a shell command on every connected device in parallel and summarizes the results.
+//!
+//! ```text
+//! cargo run --example farm_runner [command]
+//! ```
+//!
+//! Defaults to `getprop ro.product.model`.
+
+use std::env;
+
+use adb::pool::DevicePool;
+use adb::server::AdbServer;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = if args.is_empty() {
+        "getprop ro.product.model".to_string()
+    } else {
+        args.join(" ")
+    };
+    let pool = DevicePool::online(&AdbServer::default())?;
+    if pool.devices().is_empty() {
+        return Err("no device is ready".into());
+    }
+
+    let mut results: Vec<_> = pool
+        .run(|device| device.shell(&command))
+        .into_iter()
+        .collect();
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut failed = 0;
+    for (serial, result) in results {
+        match result {
+            Ok(output) if output.success() => {
+                println!("[{}] {}", serial, output.stdout_lossy().trim_end())
+            }
+            Ok(output) => {
+                failed += 1;
+                println!(
+                    "[{}] exit {}: {}",
+                    serial,
+                    output.exit_code,
+                    output.stderr_lossy().trim_end()
+                );
+            }
+            Err(e) => {
+                failed += 1;
+                println!("[{}] error: {}", serial, e);
+            }
+        }
+    }
+    println!("{} failed", failed);
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! Pushes a file to the only connected device, lists its directory and pulls it back.
+//!
+//! ```text
+//! cargo run --example file_sync [local file] [remote directory]
+//! ```
+//!
+//! Defaults to pushing this example's `Cargo.toml` to `/data/local/tmp`.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use adb::server::AdbServer;
+use adb::sync::DEFAULT_MODE;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = env::args().skip(1);
+    let local = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"));
+    let remote_dir = args.next().unwrap_or_else(|| "/data/local/tmp".to_string());
+    let name = local.file_name().ok_or("not a file")?.to_string_lossy();
+    let remote = format!("{}/{}", remote_dir.trim_end_matches('/'), name);
+
+    let device = AdbServer::default().any_device();
+    let stats = device.push(&local, &remote, DEFAULT_MODE)?;
+    println!(
+        "pushed {} bytes to {} ({:.0} B/s)",
+        stats.bytes,
+        remote,
+        stats.bytes_per_second()
+    );
+
+    let mut sync = device.sync()?;
+    for entry in sync.list(&remote_dir)? {
+        let kind = if entry.stat.is_dir() { "d" } else { "-" };
+        println!("{} {:>10} {}", kind, entry.stat.size, entry.name);
+    }
+    sync.quit()?;
+
+    let pulled = env::temp_dir().join(format!("pulled-{}", name));
+    let stats = device.pull(&remote, &pulled)?;
+    println!("pulled {} bytes to {}", stats.bytes, pulled.display());
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! Follows the log of the only connected device, like `adb logcat`.
+//!
+//! ```text
+//! cargo run --example log_tail [min priority] [tag...]
+//! ```
+//!
+//! With tags, only the entries of these tags are shown.
+
+use std::env;
+
+use adb::logcat::{LogPriority, LogcatOptions, Tag};
+use adb::server::AdbServer;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = env::args().skip(1);
+    let priority: LogPriority = match args.next() {
+        Some(priority) => priority.parse()?,
+        None => LogPriority::Verbose,
+    };
+    let mut options = LogcatOptions::new();
+    let mut filtered = false;
+    for tag in args {
+        options = options.filter(tag.parse::<Tag>()?, priority);
+        filtered = true;
+    }
+    options = options.min_priority(if filtered {
+        LogPriority::Silent
+    } else {
+        priority
+    });
+
+    let device = AdbServer::default().any_device();
+    for entry in device.logcat(&options)? {
+        let entry = entry?;
+        println!(
+            "{:>5} {:>5} {} {}: {}",
+            entry.pid,
+            entry.tid,
+            entry.priority,
+            entry.tag,
+            entry.message.trim_end()
+        );
+    }
+    Ok(())
+}
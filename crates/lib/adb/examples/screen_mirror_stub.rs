@@ -0,0 +1,40 @@
+//! Grabs screenshots of the only connected device in a loop and reports the frame rate.
+//!
+//! A real mirror would decode and display the frames; this stub only measures how fast
+//! frames can be pulled and saves the last one.
+//!
+//! ```text
+//! cargo run --example screen_mirror_stub [frames]
+//! ```
+
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::time::Instant;
+
+use adb::server::AdbServer;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let frames: u32 = match env::args().nth(1) {
+        Some(frames) => frames.parse()?,
+        None => 10,
+    };
+    let device = AdbServer::default().any_device();
+    let start = Instant::now();
+    let mut frame = Vec::new();
+    for i in 1..=frames {
+        frame.clear();
+        // `exec:` doesn't allocate a pty, so the PNG arrives unmangled.
+        device.open("exec:screencap -p")?.read_to_end(&mut frame)?;
+        if !frame.starts_with(b"\x89PNG") {
+            return Err("screencap didn't return a PNG".into());
+        }
+        println!("frame {}: {} bytes", i, frame.len());
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    println!("{:.2} frames per second", f64::from(frames) / seconds);
+    let path = env::temp_dir().join("screen_mirror_stub.png");
+    fs::write(&path, &frame)?;
+    println!("last frame saved to {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,346 @@
+//! This module provides the services of adbd managing the device and the daemon itself:
+//! rebooting (`reboot:`), restarting adbd as root (`root:`, `unroot:`), remounting the
+//! system partitions read-write (`remount:`) and toggling dm-verity (`enable-verity:`,
+//! `disable-verity:`).
+//!
+//! These services reply with a human-readable message, parsed into a result enum. Restarting
+//! adbd or rebooting drops the connection to the device, see [`Device::wait_for`].
+
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Read};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::device::{Device, DeviceState, Transport};
+use crate::error::AdbError;
+use crate::protocol;
+
+/// The mode to reboot a device into.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub enum RebootTarget {
+    /// Android.
+    #[default]
+    System,
+    Bootloader,
+    Recovery,
+    /// Recovery, waiting for an OTA package (`adb sideload`).
+    Sideload,
+    /// Like [`Self::Sideload`], rebooting once the package is installed.
+    SideloadAutoReboot,
+    /// The userspace fastboot of recovery (fastbootd).
+    Fastboot,
+}
+
+impl Display for RebootTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::System => "",
+            Self::Bootloader => "bootloader",
+            Self::Recovery => "recovery",
+            Self::Sideload => "sideload",
+            Self::SideloadAutoReboot => "sideload-auto-reboot",
+            Self::Fastboot => "fastboot",
+        })
+    }
+}
+
+/// Returns a parse error of the reply `s` of a service.
+fn reply_error(s: &str, target_type: &'static str) -> AdbError {
+    AdbError::Parse {
+        value: s.to_string(),
+        source_type: "&str",
+        target_type,
+        source: None,
+    }
+}
+
+/// The outcome of [`Device::root`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum RootResult {
+    /// adbd is restarting as root.
+    Restarted,
+    AlreadyRunningAsRoot,
+    /// adbd can't run as root on `user` builds (`ro.debuggable` is 0).
+    ProductionBuild,
+}
+
+impl FromStr for RootResult {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_end() {
+            "restarting adbd as root" => Ok(Self::Restarted),
+            "adbd is already running as root" => Ok(Self::AlreadyRunningAsRoot),
+            "adbd cannot run as root in production builds" => Ok(Self::ProductionBuild),
+            _ => Err(reply_error(s, "RootResult")),
+        }
+    }
+}
+
+/// The outcome of [`Device::unroot`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum UnrootResult {
+    /// adbd is restarting as the `shell` user.
+    Restarted,
+    NotRunningAsRoot,
+}
+
+impl FromStr for UnrootResult {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_end() {
+            "restarting adbd as non root" => Ok(Self::Restarted),
+            "adbd not running as root" => Ok(Self::NotRunningAsRoot),
+            _ => Err(reply_error(s, "UnrootResult")),
+        }
+    }
+}
+
+/// The outcome of [`Device::remount`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum RemountResult {
+    /// The partitions are writable.
+    Remounted,
+    /// Verity was disabled or an overlay was set up, the partitions are writable after a
+    /// reboot.
+    RebootRequired,
+}
+
+impl FromStr for RemountResult {
+    type Err = AdbError;
+
+    /// Parses the output of the `remount` tool, which lists the partitions first.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.to_ascii_lowercase().contains("reboot") {
+            Ok(Self::RebootRequired)
+        } else if s.contains("remount succeeded") {
+            Ok(Self::Remounted)
+        } else {
+            Err(reply_error(s, "RemountResult"))
+        }
+    }
+}
+
+/// The outcome of [`Device::set_verity_enabled`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum VerityResult {
+    /// The state changes after a reboot.
+    RebootRequired,
+    /// Verity was already in the requested state.
+    AlreadySet,
+}
+
+impl FromStr for VerityResult {
+    type Err = AdbError;
+
+    /// Parses replies like `Verity already disabled on /system`, or
+    /// `Successfully disabled verification` followed by a reboot hint with AVB.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        if lower.contains("already") {
+            Ok(Self::AlreadySet)
+        } else if lower.contains("reboot") {
+            Ok(Self::RebootRequired)
+        } else {
+            Err(reply_error(s, "VerityResult"))
+        }
+    }
+}
+
+/// Returns the `wait-for` host service waiting for `state` on `transport`, e.g.
+/// `wait-for-usb-recovery`, or `None` if the server can't wait for `state`.
+fn wait_for_service(transport: &Transport, state: Option<&DeviceState>) -> Option<String> {
+    let transport = match transport {
+        Transport::Usb => "usb",
+        Transport::Local => "local",
+        _ => "any",
+    };
+    let state = match state {
+        Some(
+            state @ (DeviceState::Device
+            | DeviceState::Recovery
+            | DeviceState::Rescue
+            | DeviceState::Sideload
+            | DeviceState::Bootloader),
+        ) => state.name(),
+        Some(_) => return None,
+        None => "disconnect",
+    };
+    Some(format!("wait-for-{}-{}", transport, state))
+}
+
+impl Device {
+    /// Requests `service` and returns the reply, read until adbd closes the connection.
+    fn daemon_request(&self, service: &str) -> Result<String, AdbError> {
+        let mut reply = String::new();
+        self.open(service)?.read_to_string(&mut reply)?;
+        Ok(reply)
+    }
+
+    /// Reboots the device into `target` (`reboot:<target>`).
+    pub fn reboot(&self, target: RebootTarget) -> Result<(), AdbError> {
+        self.daemon_request(&format!("reboot:{}", target)).map(drop)
+    }
+
+    /// Restarts adbd as root (`root:`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use adb::adbd::RootResult;
+    /// use adb::device::DeviceState;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// if device.root().unwrap() == RootResult::Restarted {
+    ///     device
+    ///         .wait_for(DeviceState::Device, Duration::from_secs(10))
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn root(&self) -> Result<RootResult, AdbError> {
+        self.daemon_request("root:")?.parse()
+    }
+
+    /// Restarts adbd as the `shell` user (`unroot:`).
+    pub fn unroot(&self) -> Result<UnrootResult, AdbError> {
+        self.daemon_request("unroot:")?.parse()
+    }
+
+    /// Remounts the system partitions read-write (`remount:`), disabling verity if needed.
+    ///
+    /// Requires adbd running as root.
+    pub fn remount(&self) -> Result<RemountResult, AdbError> {
+        self.daemon_request("remount:")?.parse()
+    }
+
+    /// Enables (`enabled` is `true`) or disables dm-verity (`enable-verity:`,
+    /// `disable-verity:`).
+    ///
+    /// Requires adbd running as root on a `userdebug` or `eng` build.
+    pub fn set_verity_enabled(&self, enabled: bool) -> Result<VerityResult, AdbError> {
+        let service = if enabled {
+            "enable-verity:"
+        } else {
+            "disable-verity:"
+        };
+        self.daemon_request(service)?.parse()
+    }
+
+    /// Waits until the device is in `state` (`host-serial:<serial>:wait-for-any-<state>`),
+    /// failing with [`AdbError::Timeout`] after `timeout`.
+    ///
+    /// The server can wait for [`DeviceState::Device`], [`DeviceState::Recovery`],
+    /// [`DeviceState::Rescue`], [`DeviceState::Sideload`] and [`DeviceState::Bootloader`].
+    /// A zero `timeout` waits forever.
+    pub fn wait_for(&self, state: DeviceState, timeout: Duration) -> Result<(), AdbError> {
+        self.wait_for_state(Some(state), timeout)
+    }
+
+    /// Waits until the device disconnects, e.g. while rebooting.
+    pub fn wait_for_disconnect(&self, timeout: Duration) -> Result<(), AdbError> {
+        self.wait_for_state(None, timeout)
+    }
+
+    /// Waits until the device is in `state`, or disconnects if `state` is `None`.
+    fn wait_for_state(
+        &self,
+        state: Option<DeviceState>,
+        timeout: Duration,
+    ) -> Result<(), AdbError> {
+        let service =
+            wait_for_service(self.transport(), state.as_ref()).ok_or_else(|| AdbError::Server {
+                message: format!("can't wait for a device in the {:?} state", state),
+            })?;
+        let prefix = self.transport().host_prefix();
+        // The server acknowledges the request, then replies again once the state is reached.
+        let mut stream = self.server().open(&format!("{}{}", prefix, service))?;
+        stream.set_read_timeout(Some(timeout).filter(|timeout| !timeout.is_zero()))?;
+        match protocol::read_status(&mut stream) {
+            Err(AdbError::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                Err(AdbError::Timeout {
+                    condition: match state {
+                        Some(state) => format!("device in the {} state", state),
+                        None => "device disconnected".to_string(),
+                    },
+                    timeout,
+                })
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_results_parse() {
+        assert_eq!(
+            RootResult::AlreadyRunningAsRoot,
+            "adbd is already running as root\n".parse().unwrap()
+        );
+        assert_eq!(
+            RootResult::ProductionBuild,
+            "adbd cannot run as root in production builds\n"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            UnrootResult::Restarted,
+            "restarting adbd as non root\n".parse().unwrap()
+        );
+        assert!("error: closed".parse::<RootResult>().is_err());
+        assert_eq!(
+            RemountResult::Remounted,
+            "Remounted /system as RW\nremount succeeded\n"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            RemountResult::RebootRequired,
+            "Disabling verity for /system\nNow reboot your device for settings to take effect\n"
+                .parse()
+                .unwrap()
+        );
+        assert!("Not running as root. Try \"adb root\" first.\n"
+            .parse::<RemountResult>()
+            .is_err());
+        assert_eq!(
+            VerityResult::AlreadySet,
+            "Verity already disabled on /system\n".parse().unwrap()
+        );
+        assert_eq!(
+            VerityResult::RebootRequired,
+            "Successfully disabled verification\nNow reboot your device for settings to take effect\n"
+                .parse()
+                .unwrap()
+        );
+        assert!("verity cannot be disabled/enabled - USER build\n"
+            .parse::<VerityResult>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_wait_for_service() {
+        assert_eq!(
+            Some("wait-for-usb-recovery"),
+            wait_for_service(&Transport::Usb, Some(&DeviceState::Recovery)).as_deref()
+        );
+        assert_eq!(
+            Some("wait-for-any-disconnect"),
+            wait_for_service(&Transport::Serial("emulator-5554".to_string()), None).as_deref()
+        );
+        assert_eq!(
+            None,
+            wait_for_service(&Transport::Any, Some(&DeviceState::Offline))
+        );
+        assert_eq!(
+            "sideload-auto-reboot",
+            RebootTarget::SideloadAutoReboot.to_string()
+        );
+    }
+}
@@ -0,0 +1,536 @@
+//! This module wraps the activity manager shell command, `am`, with a typed [`Intent`].
+//!
+//! Intents are rendered into the arguments of `am start`, `am startservice` and
+//! `am broadcast`, each quoted for the device shell, so actions, URIs and extras may hold
+//! spaces, quotes or `&`. Extras are typed by their flag, e.g. `--ei` for an `int`, and the
+//! elements of string arrays are separated by commas, escaped as `\,` inside elements.
+//!
+//! `am` exits with 0 even when it fails on older versions, reporting errors on its output
+//! as `Error: <message>` lines, or a `Status:` other than `ok` when waiting for the launch.
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// The value of an extra of an [`Intent`].
+#[derive(Clone, PartialEq, Debug)]
+pub enum ExtraValue {
+    /// A `null` string (`--esn`).
+    Null,
+    /// `--es`.
+    String(String),
+    /// `--ez`.
+    Bool(bool),
+    /// `--ei`.
+    Int(i32),
+    /// `--el`.
+    Long(i64),
+    /// `--ef`.
+    Float(f32),
+    /// `--ed`, Android 10 and later.
+    Double(f64),
+    /// A URI (`--eu`).
+    Uri(String),
+    /// A component name, e.g. `com.example/.Receiver` (`--ecn`).
+    Component(String),
+    /// `--eia`.
+    IntArray(Vec<i32>),
+    /// `--ela`.
+    LongArray(Vec<i64>),
+    /// `--efa`.
+    FloatArray(Vec<f32>),
+    /// `--esa`.
+    StringArray(Vec<String>),
+}
+
+impl ExtraValue {
+    /// Returns the flag of the extra, e.g. `--ei`.
+    pub fn flag(&self) -> &'static str {
+        match self {
+            Self::Null => "--esn",
+            Self::String(_) => "--es",
+            Self::Bool(_) => "--ez",
+            Self::Int(_) => "--ei",
+            Self::Long(_) => "--el",
+            Self::Float(_) => "--ef",
+            Self::Double(_) => "--ed",
+            Self::Uri(_) => "--eu",
+            Self::Component(_) => "--ecn",
+            Self::IntArray(_) => "--eia",
+            Self::LongArray(_) => "--ela",
+            Self::FloatArray(_) => "--efa",
+            Self::StringArray(_) => "--esa",
+        }
+    }
+
+    /// Returns the argument of the value, `None` for [`Self::Null`].
+    fn arg(&self) -> Option<String> {
+        fn join<T: ToString>(values: &[T]) -> String {
+            values
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+        Some(match self {
+            Self::Null => return None,
+            Self::String(s) | Self::Uri(s) | Self::Component(s) => s.clone(),
+            Self::Bool(b) => b.to_string(),
+            Self::Int(i) => i.to_string(),
+            Self::Long(l) => l.to_string(),
+            Self::Float(f) => f.to_string(),
+            Self::Double(d) => d.to_string(),
+            Self::IntArray(values) => join(values),
+            Self::LongArray(values) => join(values),
+            Self::FloatArray(values) => join(values),
+            Self::StringArray(values) => values
+                .iter()
+                .map(|value| value.replace(',', "\\,"))
+                .collect::<Vec<_>>()
+                .join(","),
+        })
+    }
+}
+
+impl From<&str> for ExtraValue {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<String> for ExtraValue {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<bool> for ExtraValue {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<i32> for ExtraValue {
+    fn from(i: i32) -> Self {
+        Self::Int(i)
+    }
+}
+
+impl From<i64> for ExtraValue {
+    fn from(l: i64) -> Self {
+        Self::Long(l)
+    }
+}
+
+impl From<f32> for ExtraValue {
+    fn from(f: f32) -> Self {
+        Self::Float(f)
+    }
+}
+
+impl From<f64> for ExtraValue {
+    fn from(d: f64) -> Self {
+        Self::Double(d)
+    }
+}
+
+impl From<Vec<String>> for ExtraValue {
+    fn from(values: Vec<String>) -> Self {
+        Self::StringArray(values)
+    }
+}
+
+/// An intent, rendered into the arguments of `am`.
+///
+/// # Examples
+///
+/// ```
+/// use adb::am::{ExtraValue, Intent};
+///
+/// let intent = Intent::new()
+///     .action("android.intent.action.VIEW")
+///     .data("https://example.com/search?q=a&b")
+///     .component("com.example/.MainActivity")
+///     .extra("user", "O'Brien")
+///     .extra("retries", 3)
+///     .extra("debug", true)
+///     .extra("ids", ExtraValue::LongArray(vec![1, 2]));
+/// assert_eq!(
+///     "-a 'android.intent.action.VIEW' -d 'https://example.com/search?q=a&b' \
+///      -n 'com.example/.MainActivity' --es 'user' 'O'\\''Brien' --ei 'retries' '3' \
+///      --ez 'debug' 'true' --ela 'ids' '1,2'",
+///     intent.to_command()
+/// );
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Intent {
+    action: Option<String>,
+    data: Option<String>,
+    mime_type: Option<String>,
+    categories: Vec<String>,
+    component: Option<String>,
+    package: Option<String>,
+    flags: Option<u32>,
+    extras: Vec<(String, ExtraValue)>,
+}
+
+impl Intent {
+    /// Creates an empty intent.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the action, e.g. `android.intent.action.VIEW` (`-a`).
+    pub fn action(mut self, action: impl Into<String>) -> Self {
+        self.action = Some(action.into());
+        self
+    }
+
+    /// Sets the data URI (`-d`).
+    pub fn data(mut self, uri: impl Into<String>) -> Self {
+        self.data = Some(uri.into());
+        self
+    }
+
+    /// Sets the MIME type (`-t`).
+    pub fn mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    /// Adds a category, e.g. `android.intent.category.LAUNCHER` (`-c`).
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.categories.push(category.into());
+        self
+    }
+
+    /// Sets the component, e.g. `com.example/.MainActivity` (`-n`).
+    pub fn component(mut self, component: impl Into<String>) -> Self {
+        self.component = Some(component.into());
+        self
+    }
+
+    /// Restricts the intent to the components of `package`, passed last.
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.package = Some(package.into());
+        self
+    }
+
+    /// Sets the flags of the intent, e.g. `0x10000000` for `FLAG_ACTIVITY_NEW_TASK` (`-f`).
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    /// Adds the extra `key`, typed by `value`.
+    pub fn extra(mut self, key: impl Into<String>, value: impl Into<ExtraValue>) -> Self {
+        self.extras.push((key.into(), value.into()));
+        self
+    }
+
+    /// Returns the arguments of the intent, unquoted.
+    pub fn args(&self) -> Vec<String> {
+        self.tokens().into_iter().map(|(_, arg)| arg).collect()
+    }
+
+    /// Returns the arguments of the intent, the values quoted for the device shell.
+    pub fn to_command(&self) -> String {
+        let args: Vec<_> = self
+            .tokens()
+            .into_iter()
+            .map(|(flag, arg)| if flag { arg } else { shell::quote(&arg) })
+            .collect();
+        args.join(" ")
+    }
+
+    /// Returns the arguments of the intent, and whether each is a flag.
+    fn tokens(&self) -> Vec<(bool, String)> {
+        fn option(tokens: &mut Vec<(bool, String)>, flag: &str, value: String) {
+            tokens.push((true, flag.to_string()));
+            tokens.push((false, value));
+        }
+        let mut tokens = Vec::new();
+        for (flag, value) in [
+            ("-a", &self.action),
+            ("-d", &self.data),
+            ("-t", &self.mime_type),
+        ] {
+            if let Some(value) = value {
+                option(&mut tokens, flag, value.clone());
+            }
+        }
+        for category in &self.categories {
+            option(&mut tokens, "-c", category.clone());
+        }
+        if let Some(component) = &self.component {
+            option(&mut tokens, "-n", component.clone());
+        }
+        if let Some(flags) = self.flags {
+            option(&mut tokens, "-f", format!("{:#x}", flags));
+        }
+        for (key, value) in &self.extras {
+            option(&mut tokens, value.flag(), key.clone());
+            if let Some(arg) = value.arg() {
+                tokens.push((false, arg));
+            }
+        }
+        if let Some(package) = &self.package {
+            tokens.push((false, package.clone()));
+        }
+        tokens
+    }
+}
+
+/// The result of [`Device::start_activity`].
+///
+/// # Syntax
+///
+/// With `-W`, `am start` prints `Status: ok`, then `LaunchState: COLD`,
+/// `Activity: <component>`, `TotalTime: <ms>` and `WaitTime: <ms>` lines, depending on the
+/// version.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct StartResult {
+    /// How the activity was launched, e.g. `COLD`, `WARM` or `HOT`.
+    pub launch_state: Option<String>,
+    /// The started activity.
+    pub activity: Option<String>,
+    /// The time to draw the first frame of the activity.
+    pub total_time: Option<Duration>,
+    /// The time until `am` was notified.
+    pub wait_time: Option<Duration>,
+    /// `true` if the activity was already running and only brought to the front.
+    pub brought_to_front: bool,
+}
+
+/// Fails if the output of `am` reports an error, `am` exiting with 0 regardless on older
+/// versions.
+pub(crate) fn check_am_output(output: &str) -> Result<(), AdbError> {
+    for line in output.lines() {
+        let line = line.trim_end();
+        let failed = line.starts_with("Error")
+            || line.starts_with("Exception")
+            || line
+                .strip_prefix("Status: ")
+                .is_some_and(|status| status != "ok");
+        if failed {
+            return Err(AdbError::Server {
+                message: line.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Parses the output of `am start -W`, failing if it reports an error.
+pub fn parse_start(output: &str) -> Result<StartResult, AdbError> {
+    check_am_output(output)?;
+    let mut result = StartResult::default();
+    for line in output.lines().map(str::trim_end) {
+        let millis = |value: &str| value.parse().ok().map(Duration::from_millis);
+        if let Some(state) = line.strip_prefix("LaunchState: ") {
+            result.launch_state = Some(state.to_string());
+        } else if let Some(activity) = line.strip_prefix("Activity: ") {
+            result.activity = Some(activity.to_string());
+        } else if let Some(time) = line.strip_prefix("TotalTime: ") {
+            result.total_time = millis(time);
+        } else if let Some(time) = line.strip_prefix("WaitTime: ") {
+            result.wait_time = millis(time);
+        } else if line.starts_with("Warning: Activity not started") {
+            result.brought_to_front = true;
+        }
+    }
+    Ok(result)
+}
+
+/// The result of [`Device::broadcast`].
+///
+/// # Syntax
+///
+/// `Broadcast completed: result=<code>[, data="<data>"]`
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct BroadcastResult {
+    /// The result code set by the receivers, 0 if none did.
+    pub code: i32,
+    /// The result data set by the receivers.
+    pub data: Option<String>,
+}
+
+/// Parses the output of `am broadcast`, failing if it reports an error.
+pub fn parse_broadcast(output: &str) -> Result<BroadcastResult, AdbError> {
+    check_am_output(output)?;
+    let line = output
+        .lines()
+        .find_map(|line| line.trim_end().strip_prefix("Broadcast completed: result="))
+        .ok_or_else(|| AdbError::Server {
+            message: format!("broadcast didn't complete: {}", output.trim()),
+        })?;
+    let (code, data) = match line.split_once(", ") {
+        Some((code, rest)) => (code, rest.strip_prefix("data=")),
+        None => (line, None),
+    };
+    Ok(BroadcastResult {
+        code: code.parse().map_err(|e| AdbError::Parse {
+            value: line.to_string(),
+            source_type: "&str",
+            target_type: "BroadcastResult",
+            source: Some(Box::new(e)),
+        })?,
+        data: data.map(|data| data.trim_matches('"').to_string()),
+    })
+}
+
+impl Device {
+    /// Runs `am <command> <intent>`, failing if it reports an error, and returns its output.
+    pub(crate) fn am(&self, command: &str, intent: &Intent) -> Result<String, AdbError> {
+        let mut line = format!("am {}", command);
+        let args = intent.to_command();
+        if !args.is_empty() {
+            let _ = write!(line, " {}", args);
+        }
+        self.shell_checked(&line)
+    }
+
+    /// Starts the activity of `intent`, and waits until it's drawn (`am start -W`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::am::Intent;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let intent = Intent::new()
+    ///     .action("android.intent.action.VIEW")
+    ///     .data("myapp://orders/42?source=test&debug=1");
+    /// let result = device.start_activity(&intent).unwrap();
+    /// println!("{:?} in {:?}", result.launch_state, result.total_time);
+    /// ```
+    pub fn start_activity(&self, intent: &Intent) -> Result<StartResult, AdbError> {
+        parse_start(&self.am("start -W", intent)?)
+    }
+
+    /// Starts the service of `intent` (`am startservice`).
+    pub fn start_service(&self, intent: &Intent) -> Result<(), AdbError> {
+        check_am_output(&self.am("startservice", intent)?)
+    }
+
+    /// Sends `intent` to the broadcast receivers, and returns the result set by them
+    /// (`am broadcast`).
+    pub fn broadcast(&self, intent: &Intent) -> Result<BroadcastResult, AdbError> {
+        parse_broadcast(&self.am("broadcast", intent)?)
+    }
+
+    /// Force-stops `package` and its background work (`am force-stop`).
+    pub fn force_stop(&self, package: &str) -> Result<(), AdbError> {
+        self.shell_checked(&format!("am force-stop {}", shell::quote(package)))
+            .map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intent_args() {
+        let intent = Intent::new()
+            .action("android.intent.action.MAIN")
+            .category("android.intent.category.LAUNCHER")
+            .flags(0x10000000)
+            .extra("name", ExtraValue::Null)
+            .extra("ratio", 0.5f32)
+            .extra("tags", vec!["a,b".to_string(), "c d".to_string()])
+            .package("com.example");
+        assert_eq!(
+            [
+                "-a",
+                "android.intent.action.MAIN",
+                "-c",
+                "android.intent.category.LAUNCHER",
+                "-f",
+                "0x10000000",
+                "--esn",
+                "name",
+                "--ef",
+                "ratio",
+                "0.5",
+                "--esa",
+                "tags",
+                "a\\,b,c d",
+                "com.example"
+            ],
+            &intent.args()[..]
+        );
+        assert_eq!(
+            "-a 'android.intent.action.MAIN' -c 'android.intent.category.LAUNCHER' \
+             -f '0x10000000' --esn 'name' --ef 'ratio' '0.5' --esa 'tags' 'a\\,b,c d' \
+             'com.example'",
+            intent.to_command()
+        );
+        // Values starting with a dash are quoted like the rest.
+        assert_eq!(
+            "--ei 'offset' '-1'",
+            Intent::new().extra("offset", -1).to_command()
+        );
+        assert_eq!("", Intent::new().to_command());
+    }
+
+    #[test]
+    fn test_parse_start() {
+        let result = parse_start(
+            "Starting: Intent { act=android.intent.action.MAIN cmp=com.example/.MainActivity }\n\
+             Status: ok\n\
+             LaunchState: COLD\n\
+             Activity: com.example/.MainActivity\n\
+             TotalTime: 612\n\
+             WaitTime: 618\n\
+             Complete\n",
+        )
+        .unwrap();
+        assert_eq!(Some("COLD"), result.launch_state.as_deref());
+        assert_eq!(Some(Duration::from_millis(612)), result.total_time);
+        assert_eq!(Some(Duration::from_millis(618)), result.wait_time);
+        assert!(!result.brought_to_front);
+        let result = parse_start(
+            "Starting: Intent { cmp=com.example/.MainActivity }\n\
+             Warning: Activity not started, its current task has been brought to the front\n\
+             Status: ok\n",
+        )
+        .unwrap();
+        assert!(result.brought_to_front);
+        assert!(parse_start("Status: timeout\n").is_err());
+    }
+
+    #[test]
+    fn test_check_am_output() {
+        assert!(check_am_output(
+            "Starting: Intent { act=android.intent.action.MAIN cmp=com.example/.MainActivity }\n"
+        )
+        .is_ok());
+        assert!(check_am_output(
+            "Starting: Intent { cmp=com.example/.Missing }\n\
+             Error type 3\n\
+             Error: Activity class {com.example/com.example.Missing} does not exist.\n"
+        )
+        .is_err());
+        assert!(check_am_output("Error: Not found; no service started.\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_broadcast() {
+        let result = parse_broadcast(
+            "Broadcasting: Intent { act=com.example.PING flg=0x400000 }\n\
+             Broadcast completed: result=-1, data=\"pong\"\n",
+        )
+        .unwrap();
+        assert_eq!(-1, result.code);
+        assert_eq!(Some("pong"), result.data.as_deref());
+        let result =
+            parse_broadcast("Broadcasting: Intent { }\nBroadcast completed: result=0\n").unwrap();
+        assert_eq!(BroadcastResult::default(), result);
+        assert!(parse_broadcast("Broadcasting: Intent { }\n").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! This module provides [`AppHandle`], the lifecycle of an installed application on top of
+//! the `pm`, `am` and `pidof` shell commands.
+//!
+//! The launcher activity is resolved with `cmd package resolve-activity --brief`, which
+//! prints the matching component on its last line, or `No activity found`.
+
+use crate::am::{check_am_output, Intent};
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// The version of an installed application.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct AppVersion {
+    /// The `versionCode` of the manifest.
+    pub code: Option<u64>,
+    /// The `versionName` of the manifest, e.g. `1.2.0`.
+    pub name: Option<String>,
+}
+
+/// An application installed on a device, created by [`Device::app`].
+#[derive(Clone, Debug)]
+pub struct AppHandle {
+    device: Device,
+    package: String,
+}
+
+/// Parses the component printed by `cmd package resolve-activity --brief`.
+fn parse_resolved_activity(s: &str) -> Option<&str> {
+    s.lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .filter(|line| line.contains('/') && !line.contains(' '))
+}
+
+/// Parses the output of `pidof`, the first pid if several processes match.
+fn parse_pid(s: &str) -> Option<u32> {
+    s.split_whitespace().next()?.parse().ok()
+}
+
+impl AppHandle {
+    /// Returns the device the application is installed on.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Returns the package name of the application.
+    pub fn package(&self) -> &str {
+        &self.package
+    }
+
+    /// Returns the launcher activity of the application, e.g. `com.example/.MainActivity`.
+    pub fn launcher_activity(&self) -> Result<String, AdbError> {
+        let output = self.device.shell_checked(&format!(
+            "cmd package resolve-activity --brief -a android.intent.action.MAIN \
+             -c android.intent.category.LAUNCHER {}",
+            shell::quote(&self.package)
+        ))?;
+        parse_resolved_activity(&output)
+            .map(str::to_string)
+            .ok_or_else(|| AdbError::Server {
+                message: format!("no launcher activity found for {}", self.package),
+            })
+    }
+
+    /// Starts the launcher activity of the application, like tapping its icon.
+    pub fn launch(&self) -> Result<(), AdbError> {
+        let intent = Intent::new()
+            .action("android.intent.action.MAIN")
+            .category("android.intent.category.LAUNCHER")
+            .component(self.launcher_activity()?);
+        check_am_output(&self.device.am("start", &intent)?)
+    }
+
+    /// Force-stops the application and its background work.
+    pub fn stop(&self) -> Result<(), AdbError> {
+        self.device.force_stop(&self.package)
+    }
+
+    /// Deletes the data of the application, stopping it and revoking its runtime
+    /// permissions as well.
+    pub fn clear_data(&self) -> Result<(), AdbError> {
+        self.device.clear_package(&self.package)
+    }
+
+    /// Returns the pid of the main process of the application, `None` if it isn't running.
+    pub fn pid(&self) -> Result<Option<u32>, AdbError> {
+        // `pidof` exits with 1 if no process matches.
+        let output = self
+            .device
+            .shell(&format!("pidof {}", shell::quote(&self.package)))?;
+        Ok(parse_pid(&output.stdout_lossy()))
+    }
+
+    /// Returns `true` if the main process of the application is running.
+    pub fn is_running(&self) -> Result<bool, AdbError> {
+        Ok(self.pid()?.is_some())
+    }
+
+    /// Returns the version of the application.
+    pub fn version(&self) -> Result<AppVersion, AdbError> {
+        let details = self.device.package_details(&self.package)?;
+        Ok(AppVersion {
+            code: details.version_code,
+            name: details.version_name,
+        })
+    }
+
+    /// Grants every runtime permission requested by the application and not granted yet,
+    /// returning the permissions granted.
+    ///
+    /// Install permissions are granted at install time and can't be granted by `pm grant`.
+    pub fn grant_all_requested_permissions(&self) -> Result<Vec<String>, AdbError> {
+        let details = self.device.package_details(&self.package)?;
+        let mut granted = Vec::new();
+        for state in &details.permissions {
+            if state.user.is_none() || state.granted || granted.contains(&state.name) {
+                continue;
+            }
+            self.device.grant_permission(&self.package, &state.name)?;
+            granted.push(state.name.clone());
+        }
+        Ok(granted)
+    }
+}
+
+impl Device {
+    /// Returns a handle to the application `package`, without checking it is installed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let app = device.app("com.example.app");
+    /// app.clear_data().unwrap();
+    /// app.grant_all_requested_permissions().unwrap();
+    /// app.launch().unwrap();
+    /// assert!(app.is_running().unwrap());
+    /// ```
+    pub fn app(&self, package: &str) -> AppHandle {
+        AppHandle {
+            device: self.clone(),
+            package: package.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resolved_activity() {
+        assert_eq!(
+            Some("com.example/.MainActivity"),
+            parse_resolved_activity(
+                "priority=0 preferredOrder=0 match=0x108000 specificIndex=-1 isDefault=true\n\
+                 com.example/.MainActivity\n"
+            )
+        );
+        assert_eq!(None, parse_resolved_activity("No activity found\n"));
+        assert_eq!(None, parse_resolved_activity(""));
+    }
+
+    #[test]
+    fn test_parse_pid() {
+        assert_eq!(Some(1234), parse_pid("1234\n"));
+        assert_eq!(Some(1234), parse_pid("1234 5678\n"));
+        assert_eq!(None, parse_pid(""));
+    }
+}
@@ -0,0 +1,1361 @@
+//! This module provides async variants of the client API on top of tokio.
+//!
+//! [`AdbServer`], [`Device`], [`SyncConnection`] and [`ShellStream`] mirror their blocking
+//! counterparts in [`crate::server`], [`crate::device`], [`crate::sync`] and [`crate::shell`]. Messages are framed and parsed
+//! by the same decoders as the blocking client, only the IO is async.
+//!
+//! Operations are cancelled by dropping their future, e.g. in `tokio::select!`, which closes
+//! their connection. A [`CancellationToken`](crate::cancel::CancellationToken) is only needed
+//! by the blocking client.
+
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::clock::Clock;
+use crate::device::{DeviceState, Transport};
+use crate::error::AdbError;
+use crate::features::Features;
+use crate::op::OpObserver;
+use crate::protocol::{self, Decoded};
+use crate::server::{self, DeviceInfo};
+use crate::socket::{AdbSocketFamilies, Tcp};
+use crate::track::{self, DeviceEvent, TrackerState};
+use crate::version::{AdbVersion, VersionAction, VersionMismatchPolicy};
+
+#[cfg(feature = "logcat")]
+pub use self::logcat::LogStream;
+#[cfg(feature = "shell")]
+pub use self::shell::ShellStream;
+#[cfg(feature = "sync")]
+pub use self::sync::SyncConnection;
+
+/// Reads exactly as many bytes as `decode` needs to decode a complete message.
+async fn read_decoded<R, T, F>(reader: &mut R, decode: F) -> Result<T, AdbError>
+where
+    R: AsyncRead + Unpin,
+    F: Fn(&[u8]) -> Result<Decoded<T>, AdbError>,
+{
+    let mut buffer = Vec::new();
+    loop {
+        let needed = match decode(&buffer)? {
+            Decoded::Complete { value, .. } => return Ok(value),
+            Decoded::Incomplete { needed } => needed,
+        };
+        let filled = buffer.len();
+        buffer.resize(needed, 0);
+        reader.read_exact(&mut buffer[filled..]).await?;
+    }
+}
+
+/// Polls `reader` until `decode` decodes a complete message, for [`Stream`] implementations.
+///
+/// The bytes read so far are kept in `buffer` between polls, and cleared once a message is
+/// decoded. Returns `None` if the reader ends between two messages.
+#[cfg(feature = "shell")]
+fn poll_decoded<R, T, F>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    decode: F,
+    cx: &mut Context<'_>,
+) -> Poll<Option<Result<T, AdbError>>>
+where
+    R: AsyncRead + Unpin,
+    F: Fn(&[u8]) -> Result<Decoded<T>, AdbError>,
+{
+    loop {
+        let needed = match decode(buffer) {
+            Ok(Decoded::Complete { value, .. }) => {
+                buffer.clear();
+                return Poll::Ready(Some(Ok(value)));
+            }
+            Ok(Decoded::Incomplete { needed }) => needed,
+            Err(e) => return Poll::Ready(Some(Err(e))),
+        };
+        let filled = buffer.len();
+        buffer.resize(needed, 0);
+        let mut read_buf = tokio::io::ReadBuf::new(&mut buffer[filled..]);
+        let result = Pin::new(&mut *reader).poll_read(cx, &mut read_buf);
+        let read = read_buf.filled().len();
+        buffer.truncate(filled + read);
+        match result {
+            Poll::Ready(Ok(())) if read == 0 && filled == 0 => return Poll::Ready(None),
+            Poll::Ready(Ok(())) if read == 0 => {
+                let eof = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+                return Poll::Ready(Some(Err(eof.into())));
+            }
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+            Poll::Pending => return Poll::Pending,
+        }
+    }
+}
+
+/// Sends a service request and reads its status.
+async fn request(stream: &mut TcpStream, service: &str) -> Result<(), AdbError> {
+    stream.write_all(&protocol::encode_request(service)).await?;
+    protocol::check_status(read_decoded(stream, protocol::decode_status).await?)
+}
+
+/// Reads a length-prefixed payload as a string.
+async fn read_string(stream: &mut TcpStream) -> Result<String, AdbError> {
+    protocol::into_string(read_decoded(stream, protocol::decode_length_prefixed).await?)
+}
+
+/// An async client of the adb server, see [`server::AdbServer`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::r#async::AdbServer;
+///
+/// # async fn run() -> Result<(), adb::error::AdbError> {
+/// let server = AdbServer::connect(AdbServer::DEFAULT_ADDR).await?;
+/// for device in server.devices().await? {
+///     println!("{:?}: {:?}", device.serial(), device.state());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct AdbServer {
+    inner: server::AdbServer,
+}
+
+impl AdbServer {
+    /// The default port of the adb server.
+    pub const DEFAULT_PORT: u16 = server::AdbServer::DEFAULT_PORT;
+
+    /// The default address of the adb server, `tcp:127.0.0.1:5037`.
+    pub const DEFAULT_ADDR: Tcp = server::AdbServer::DEFAULT_ADDR;
+
+    /// Creates a client of the adb server listening on `addr`, without connecting to it.
+    ///
+    /// A missing IP address defaults to `127.0.0.1`, and a missing port to [`Self::DEFAULT_PORT`].
+    /// Only servers listening on TCP are supported, connections to other sockets fail with
+    /// [`std::io::ErrorKind::Unsupported`].
+    pub fn new(addr: impl Into<AdbSocketFamilies>) -> Self {
+        Self {
+            inner: server::AdbServer::new(addr),
+        }
+    }
+
+    /// Creates a client of the adb server listening on `addr`,
+    /// and [checks](Self::check_version) the server version.
+    pub async fn connect(addr: impl Into<AdbSocketFamilies>) -> Result<Self, AdbError> {
+        let server = Self::new(addr);
+        server.check_version().await?;
+        Ok(server)
+    }
+
+    /// Sets the policy applied when the server version doesn't match this client.
+    pub fn version_policy(self, policy: VersionMismatchPolicy) -> Self {
+        Self {
+            inner: self.inner.version_policy(policy),
+        }
+    }
+
+    /// Sets the clock used for transfer rates, timeouts and retries, see [`crate::clock`].
+    pub fn clock(self, clock: impl Clock + 'static) -> Self {
+        Self {
+            inner: self.inner.clock(clock),
+        }
+    }
+
+    /// Sets whether [`Self::connect_or_start`] starts a server when none listens, see
+    /// [`server::AdbServer::auto_start`].
+    pub fn auto_start(self, auto_start: bool) -> Self {
+        Self {
+            inner: self.inner.auto_start(auto_start),
+        }
+    }
+
+    /// Sets how long [`Self::connect_or_start`] waits for a started server to listen.
+    pub fn start_timeout(self, timeout: Duration) -> Self {
+        Self {
+            inner: self.inner.start_timeout(timeout),
+        }
+    }
+
+    /// Sets whether queries which change nothing are sent again once when the server goes
+    /// away before replying, see [`server::AdbServer::reissue_idempotent`].
+    pub fn reissue_idempotent(self, reissue: bool) -> Self {
+        Self {
+            inner: self.inner.reissue_idempotent(reissue),
+        }
+    }
+
+    /// Sets the observer of the operations of this client and its devices, see
+    /// [`crate::op`].
+    pub fn observer(self, observer: impl OpObserver + 'static) -> Self {
+        Self {
+            inner: self.inner.observer(observer),
+        }
+    }
+
+    /// Returns the address of the adb server.
+    pub fn addr(&self) -> &AdbSocketFamilies {
+        self.inner.addr()
+    }
+
+    /// Returns the TCP address of the adb server, failing if it listens on another socket.
+    fn tcp_addr(&self) -> Result<SocketAddr, AdbError> {
+        self.inner.tcp_addr().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("can't connect to an adb server on `{}`", self.inner.addr()),
+            )
+            .into()
+        })
+    }
+
+    /// Returns a blocking client of the same server.
+    pub fn blocking(&self) -> &server::AdbServer {
+        &self.inner
+    }
+
+    /// Opens a connection to the server and requests `service`.
+    ///
+    /// The returned stream is positioned right after the `OKAY` status. A connection closed
+    /// before the status fails with [`AdbError::ServerGone`].
+    async fn open(&self, service: &str) -> Result<TcpStream, AdbError> {
+        let op = self.inner.start_op(None, service);
+        op.finish(self.open_untracked(service).await)
+    }
+
+    /// Like [`Self::open`], without reporting an operation.
+    async fn open_untracked(&self, service: &str) -> Result<TcpStream, AdbError> {
+        let addr = self.tcp_addr()?;
+        let mut stream = TcpStream::connect(addr).await?;
+        request(&mut stream, service)
+            .await
+            .map_err(|e| e.server_gone(service))?;
+        Ok(stream)
+    }
+
+    /// Requests `service` and reads the length-prefixed reply as a string.
+    async fn request_string(&self, service: &str) -> Result<String, AdbError> {
+        let mut stream = self.open(service).await?;
+        read_string(&mut stream)
+            .await
+            .map_err(|e| e.server_gone(service))
+    }
+
+    /// Requests `service`, which must change nothing on the server, and reads the reply as a
+    /// string, sending it again once if the server goes away and reissuing is enabled.
+    async fn query_string(&self, service: &str) -> Result<String, AdbError> {
+        match self.request_string(service).await {
+            Err(e)
+                if self.inner.reissue_idempotent
+                    && matches!(e.inner(), AdbError::ServerGone { .. }) =>
+            {
+                self.request_string(service).await
+            }
+            result => result,
+        }
+    }
+
+    /// [Checks](Self::check_version) the server version, starting a server first if none
+    /// listens, see [`server::AdbServer::connect_or_start`].
+    pub async fn connect_or_start(&self) -> Result<Option<AdbVersion>, AdbError> {
+        match self.check_version().await {
+            Err(e)
+                if self.inner.auto_start
+                    && matches!(
+                        e.inner(),
+                        AdbError::Io(e) if e.kind() == std::io::ErrorKind::ConnectionRefused
+                    ) =>
+            {
+                self.start().await?;
+                let addr = self.tcp_addr()?;
+                let clock = &self.inner.clock;
+                let deadline = clock.now() + self.inner.start_timeout;
+                while TcpStream::connect(addr).await.is_err() {
+                    if clock.now() >= deadline {
+                        return Err(AdbError::Timeout {
+                            condition: format!("adb server listening on {}", addr),
+                            timeout: self.inner.start_timeout,
+                        });
+                    }
+                    tokio::time::sleep(server::START_POLL_INTERVAL).await;
+                }
+                self.check_version().await
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the protocol version of the server (`host:version`).
+    pub async fn version(&self) -> Result<AdbVersion, AdbError> {
+        self.query_string("host:version").await?.parse()
+    }
+
+    /// Checks the server version and applies the [version policy](Self::version_policy).
+    ///
+    /// With [`VersionMismatchPolicy::Restart`], a mismatching server is killed and a new one
+    /// is started with the adb command found in `PATH`. With [`VersionMismatchPolicy::Warn`],
+    /// the version of the mismatching server is returned, and `None` otherwise.
+    pub async fn check_version(&self) -> Result<Option<AdbVersion>, AdbError> {
+        let version = self.version().await?;
+        let action = self.inner.version_policy.check(version)?;
+        match action {
+            VersionAction::Continue => Ok(None),
+            VersionAction::Mismatch(version) => Ok(Some(version)),
+            VersionAction::Restart => {
+                self.kill().await?;
+                self.start().await.map(|()| None)
+            }
+        }
+    }
+
+    /// Returns the devices known to the server (`host:devices`).
+    pub async fn devices(&self) -> Result<Vec<Device>, AdbError> {
+        let devices = server::parse_devices(&self.query_string("host:devices").await?)?;
+        Ok(devices
+            .into_iter()
+            .map(|info| {
+                Device::with_state(
+                    self.clone(),
+                    Transport::Serial(info.serial),
+                    Some(info.state),
+                )
+            })
+            .collect())
+    }
+
+    /// Returns a handle to the device with the given serial number.
+    pub fn device(&self, serial: &str) -> Device {
+        Device::new(self.clone(), Transport::Serial(serial.to_string()))
+    }
+
+    /// Returns a handle to the device with the given transport id, see
+    /// [`AdbServer::device_by_transport_id`](crate::server::AdbServer::device_by_transport_id).
+    pub fn device_by_transport_id(&self, id: u64) -> Device {
+        Device::new(self.clone(), Transport::TransportId(id))
+    }
+
+    /// Returns the devices known to the server, selected by transport id
+    /// (`host:devices-l`).
+    pub async fn devices_by_transport_id(&self) -> Result<Vec<Device>, AdbError> {
+        Ok(self
+            .devices_long()
+            .await?
+            .into_iter()
+            .map(|info| Device::with_state(self.clone(), info.transport(), Some(info.state)))
+            .collect())
+    }
+
+    /// Returns a handle to the only connected device.
+    pub fn any_device(&self) -> Device {
+        Device::new(self.clone(), Transport::Any)
+    }
+
+    /// Returns the devices known to the server with their qualifiers (`host:devices-l`).
+    pub async fn devices_long(&self) -> Result<Vec<DeviceInfo>, AdbError> {
+        server::parse_devices(&self.query_string("host:devices-l").await?)
+    }
+
+    /// Kills the server (`host:kill`).
+    pub async fn kill(&self) -> Result<(), AdbError> {
+        let mut stream = match self.open("host:kill").await {
+            Err(e) if matches!(e.inner(), AdbError::ServerGone { .. }) => return Ok(()),
+            result => result?,
+        };
+        // The server closes the connection once it exits.
+        let _ = stream.read_to_end(&mut Vec::new()).await;
+        Ok(())
+    }
+
+    /// Starts the server with `adb start-server`, using the adb command found in `PATH`.
+    pub async fn start(&self) -> Result<(), AdbError> {
+        let mut command = tokio::process::Command::from(self.inner.start_command());
+        server::check_start_status(command.status().await?)
+    }
+}
+
+impl From<server::AdbServer> for AdbServer {
+    fn from(inner: server::AdbServer) -> Self {
+        Self { inner }
+    }
+}
+
+/// An async handle to a device connected to the adb server, see [`crate::device::Device`].
+#[derive(Clone, Debug)]
+pub struct Device {
+    inner: Arc<DeviceInner>,
+}
+
+#[derive(Debug)]
+struct DeviceInner {
+    server: AdbServer,
+    transport: Transport,
+    state: Option<DeviceState>,
+}
+
+impl Device {
+    /// Creates a handle to the device selected by `transport`, without connecting to it.
+    pub fn new(server: AdbServer, transport: Transport) -> Self {
+        Self::with_state(server, transport, None)
+    }
+
+    fn with_state(server: AdbServer, transport: Transport, state: Option<DeviceState>) -> Self {
+        Self {
+            inner: Arc::new(DeviceInner {
+                server,
+                transport,
+                state,
+            }),
+        }
+    }
+
+    /// Returns the adb server the device is connected to.
+    pub fn server(&self) -> &AdbServer {
+        &self.inner.server
+    }
+
+    /// Returns how the device is selected.
+    pub fn transport(&self) -> &Transport {
+        &self.inner.transport
+    }
+
+    /// Returns the serial number of the device, if it's selected by serial number.
+    pub fn serial(&self) -> Option<&str> {
+        match &self.inner.transport {
+            Transport::Serial(serial) => Some(serial),
+            _ => None,
+        }
+    }
+
+    /// Returns the transport id of the device, if it's selected by transport id.
+    pub fn transport_id(&self) -> Option<u64> {
+        match self.inner.transport {
+            Transport::TransportId(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Returns the state of the device when it was listed by [`AdbServer::devices`].
+    ///
+    /// Use [`Self::get_state`] to query the current state.
+    pub fn state(&self) -> Option<DeviceState> {
+        self.inner.state.clone()
+    }
+
+    /// Queries the current state of the device (`host-serial:<serial>:get-state`).
+    pub async fn get_state(&self) -> Result<DeviceState, AdbError> {
+        self.host_request_string("get-state").await?.parse()
+    }
+
+    /// Queries the serial number of the device (`host-serial:<serial>:get-serialno`).
+    pub async fn get_serialno(&self) -> Result<String, AdbError> {
+        self.host_request_string("get-serialno").await
+    }
+
+    /// Returns the features supported by both the device and the server
+    /// (`host-serial:<serial>:features`).
+    pub async fn features(&self) -> Result<Features, AdbError> {
+        self.host_request_string("features").await?.parse()
+    }
+
+    /// Requests a host service scoped to this device and reads the reply as a string.
+    async fn host_request_string(&self, service: &str) -> Result<String, AdbError> {
+        let prefix = self.inner.transport.host_prefix();
+        self.inner
+            .server
+            .query_string(&format!("{}{}", prefix, service))
+            .await
+    }
+
+    /// Opens a connection to the device and requests `service`, e.g. `shell:ls`.
+    ///
+    /// The returned stream is positioned right after the `OKAY` status of the service.
+    ///
+    /// The request is reported as a single operation to the observer of the server.
+    pub async fn open(&self, service: &str) -> Result<TcpStream, AdbError> {
+        let op = self.inner.server.inner.start_op(self.serial(), service);
+        op.finish(self.open_untracked(service).await)
+    }
+
+    async fn open_untracked(&self, service: &str) -> Result<TcpStream, AdbError> {
+        let mut stream = self
+            .inner
+            .server
+            .open_untracked(&self.inner.transport.service())
+            .await?;
+        request(&mut stream, service).await?;
+        Ok(stream)
+    }
+}
+
+/// The next event of a [`DeviceTracker`], along with the tracker to resume from.
+type NextEvent =
+    Pin<Box<dyn Future<Output = (TrackerInner, Option<Result<DeviceEvent, AdbError>>)> + Send>>;
+
+/// A stream of [`DeviceEvent`]s, created by [`AdbServer::track_devices`],
+/// see [`crate::track::DeviceTracker`].
+pub struct DeviceTracker {
+    inner: Option<TrackerInner>,
+    next: Option<NextEvent>,
+}
+
+struct TrackerInner {
+    server: AdbServer,
+    stream: Option<TcpStream>,
+    state: TrackerState,
+    reconnect: bool,
+    done: bool,
+}
+
+impl TrackerInner {
+    async fn next(mut self) -> (Self, Option<Result<DeviceEvent, AdbError>>) {
+        loop {
+            if let Some(event) = self.state.pop() {
+                return (self, Some(Ok(event)));
+            }
+            if self.done {
+                return (self, None);
+            }
+            let retry = {
+                let result = match &mut self.stream {
+                    Some(stream) => match read_string(stream).await {
+                        Ok(list) => self.state.update(&list),
+                        Err(e) => Err(e),
+                    },
+                    None => match self.server.open(track::TRACK_DEVICES_SERVICE).await {
+                        Ok(stream) => {
+                            self.stream = Some(stream);
+                            continue;
+                        }
+                        Err(e) => Err(e),
+                    },
+                };
+                match result {
+                    Ok(()) => continue,
+                    Err(e) if track::should_reconnect(&e, self.reconnect) => self.stream.is_none(),
+                    Err(e) => {
+                        self.done = true;
+                        return (self, Some(Err(e)));
+                    }
+                }
+            };
+            if retry {
+                tokio::time::sleep(track::RECONNECT_DELAY).await;
+            }
+            self.stream = None;
+        }
+    }
+}
+
+impl DeviceTracker {
+    /// Sets whether to reconnect when the server goes away, e.g. when it restarts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stream was already polled.
+    pub fn reconnect(mut self, reconnect: bool) -> Self {
+        self.inner
+            .as_mut()
+            .expect("reconnect must be set before polling")
+            .reconnect = reconnect;
+        self
+    }
+}
+
+impl Debug for DeviceTracker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceTracker").finish_non_exhaustive()
+    }
+}
+
+impl Stream for DeviceTracker {
+    type Item = Result<DeviceEvent, AdbError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let next = match &mut this.next {
+            Some(next) => next,
+            None => {
+                let inner = this.inner.take().expect("tracker is polled while resuming");
+                this.next.insert(Box::pin(inner.next()))
+            }
+        };
+        let (inner, event) = ready!(next.as_mut().poll(cx));
+        this.inner = Some(inner);
+        this.next = None;
+        Poll::Ready(event)
+    }
+}
+
+impl AdbServer {
+    /// Tracks the devices known to the server (`host:track-devices-l`),
+    /// see [`server::AdbServer::track_devices`].
+    pub async fn track_devices(&self) -> Result<DeviceTracker, AdbError> {
+        let stream = self.open(track::TRACK_DEVICES_SERVICE).await?;
+        Ok(DeviceTracker {
+            inner: Some(TrackerInner {
+                server: self.clone(),
+                stream: Some(stream),
+                state: TrackerState::default(),
+                reconnect: false,
+                done: false,
+            }),
+            next: None,
+        })
+    }
+}
+
+#[cfg(feature = "logcat")]
+mod logcat {
+    use std::pin::Pin;
+    use std::task::{ready, Context, Poll};
+
+    use futures_core::Stream;
+    use tokio::io::AsyncRead;
+    use tokio::net::TcpStream;
+
+    use super::{poll_decoded, Device};
+    use crate::error::AdbError;
+    use crate::logcat::{self, LogEntry, LogcatOptions};
+
+    /// A stream of the entries of the device log, created by [`Device::logcat`],
+    /// see [`logcat::LogReader`].
+    #[derive(Debug)]
+    pub struct LogStream<R: AsyncRead + Unpin = TcpStream> {
+        reader: R,
+        buffer: Vec<u8>,
+        done: bool,
+    }
+
+    impl<R: AsyncRead + Unpin> LogStream<R> {
+        /// Wraps a reader of the output of `logcat -B`.
+        pub fn new(reader: R) -> Self {
+            Self {
+                reader,
+                buffer: Vec::new(),
+                done: false,
+            }
+        }
+    }
+
+    impl<R: AsyncRead + Unpin> Stream for LogStream<R> {
+        type Item = Result<LogEntry, AdbError>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = &mut *self;
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let entry = ready!(poll_decoded(
+                &mut this.reader,
+                &mut this.buffer,
+                logcat::decode_entry,
+                cx
+            ));
+            this.done = !matches!(entry, Some(Ok(_)));
+            Poll::Ready(entry)
+        }
+    }
+
+    impl Device {
+        /// Reads the device log (`logcat -B`), see [`crate::device::Device::logcat`].
+        pub async fn logcat(&self, options: &LogcatOptions) -> Result<LogStream, AdbError> {
+            let command = format!("exec:{}", options.command());
+            Ok(LogStream::new(self.open(&command).await?))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::future::poll_fn;
+
+        use super::*;
+
+        async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+            poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+        }
+
+        #[tokio::test]
+        async fn test_log_stream() {
+            let mut bytes = vec![9, 0, 20, 0];
+            bytes.extend_from_slice(&[0; 16]);
+            bytes.extend_from_slice(b"\x04T\0hello\0");
+            let mut stream = LogStream::new(&bytes[..]);
+            let entry = next(&mut stream).await.unwrap().unwrap();
+            assert_eq!(("T", "hello"), (entry.tag.as_str(), &entry.message[..]));
+            assert!(next(&mut stream).await.is_none());
+            let mut stream = LogStream::new(&bytes[..10]);
+            assert!(next(&mut stream).await.unwrap().is_err());
+            assert!(next(&mut stream).await.is_none());
+        }
+    }
+}
+
+#[cfg(feature = "shell")]
+mod shell {
+    use std::future::poll_fn;
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{ready, Context, Poll};
+
+    use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+    use tokio::net::TcpStream;
+
+    use super::{poll_decoded, Device};
+    use crate::error::AdbError;
+    use crate::features::Feature;
+    use crate::shell::{self, Packet, PacketId, MAX_PACKET_SIZE};
+
+    /// A stream of the stdin and stdout of a running shell command, created by
+    /// [`Device::shell_stream`], see [`shell::ShellStream`].
+    #[derive(Debug)]
+    pub struct ShellStream<S: AsyncRead + AsyncWrite + Unpin = TcpStream> {
+        stream: S,
+        v2: bool,
+        read_buffer: Vec<u8>,
+        stdout: Vec<u8>,
+        position: usize,
+        stderr: Vec<u8>,
+        exit_code: Option<u8>,
+        write_buffer: Vec<u8>,
+        written: usize,
+    }
+
+    impl<S: AsyncRead + AsyncWrite + Unpin> ShellStream<S> {
+        /// Wraps a stream of the `shell,v2` service if `v2` is set, or of the legacy `shell`
+        /// service otherwise.
+        pub fn new(stream: S, v2: bool) -> Self {
+            Self {
+                stream,
+                v2,
+                read_buffer: Vec::new(),
+                stdout: Vec::new(),
+                position: 0,
+                stderr: Vec::new(),
+                exit_code: None,
+                write_buffer: Vec::new(),
+                written: 0,
+            }
+        }
+
+        /// Returns `true` if the stream speaks the shell v2 protocol.
+        pub fn is_v2(&self) -> bool {
+            self.v2
+        }
+
+        /// Returns the exit code of the command, once it exited and its output was read.
+        pub fn exit_code(&self) -> Option<u8> {
+            self.exit_code
+        }
+
+        /// Returns the stderr received so far, and clears it.
+        pub fn take_stderr(&mut self) -> Vec<u8> {
+            std::mem::take(&mut self.stderr)
+        }
+
+        /// Returns the underlying stream.
+        pub fn into_inner(self) -> S {
+            self.stream
+        }
+
+        /// Writes the rest of the packet started by [`AsyncWrite::poll_write`].
+        fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            while self.written < self.write_buffer.len() {
+                let buffer = &self.write_buffer[self.written..];
+                match ready!(Pin::new(&mut self.stream).poll_write(cx, buffer))? {
+                    0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                    written => self.written += written,
+                }
+            }
+            self.write_buffer.clear();
+            self.written = 0;
+            Poll::Ready(Ok(()))
+        }
+
+        async fn write_packet(&mut self, id: PacketId, data: &[u8]) -> Result<(), AdbError> {
+            if !self.v2 {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{:?} needs the shell v2 protocol", id),
+                )
+                .into());
+            }
+            poll_fn(|cx| self.poll_drain(cx)).await?;
+            let packet = Packet {
+                id,
+                data: data.to_vec(),
+            };
+            self.stream.write_all(&packet.encode()).await?;
+            Ok(self.stream.flush().await?)
+        }
+
+        /// Resizes the terminal of a command run with a pty, see
+        /// [`shell::ShellStream::resize`].
+        pub async fn resize(&mut self, rows: u16, cols: u16) -> Result<(), AdbError> {
+            let size = format!("{}x{},0x0\0", rows, cols);
+            self.write_packet(PacketId::WindowSizeChange, size.as_bytes())
+                .await
+        }
+
+        /// Closes the stdin of the command, see [`shell::ShellStream::close_stdin`].
+        pub async fn close_stdin(&mut self) -> Result<(), AdbError> {
+            self.write_packet(PacketId::CloseStdin, &[]).await
+        }
+    }
+
+    impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for ShellStream<S> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = &mut *self;
+            if !this.v2 {
+                return Pin::new(&mut this.stream).poll_read(cx, buf);
+            }
+            while this.position == this.stdout.len() && this.exit_code.is_none() {
+                let packet = match ready!(poll_decoded(
+                    &mut this.stream,
+                    &mut this.read_buffer,
+                    shell::decode_packet,
+                    cx
+                )) {
+                    Some(packet) => packet.map_err(AdbError::into_io)?,
+                    // The connection closed without an exit packet.
+                    None => return Poll::Ready(Ok(())),
+                };
+                match packet.id {
+                    PacketId::Stdout => {
+                        this.stdout = packet.data;
+                        this.position = 0;
+                    }
+                    PacketId::Stderr => this.stderr.extend(packet.data),
+                    PacketId::Exit => {
+                        this.exit_code = Some(packet.data.first().copied().unwrap_or_default())
+                    }
+                    id => {
+                        let message = format!("unexpected shell packet {:?} from device", id);
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            message,
+                        )));
+                    }
+                }
+            }
+            let remaining = &this.stdout[this.position..];
+            let length = remaining.len().min(buf.remaining());
+            buf.put_slice(&remaining[..length]);
+            this.position += length;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for ShellStream<S> {
+        /// Sends `buf` as a stdin packet with the shell v2 protocol.
+        ///
+        /// If the packet is only partly written, it's completed by the next call, which
+        /// must pass the same `buf`.
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = &mut *self;
+            if !this.v2 {
+                return Pin::new(&mut this.stream).poll_write(cx, buf);
+            }
+            let length = buf.len().min(MAX_PACKET_SIZE);
+            if this.write_buffer.is_empty() {
+                this.write_buffer = Packet {
+                    id: PacketId::Stdin,
+                    data: buf[..length].to_vec(),
+                }
+                .encode();
+            }
+            ready!(this.poll_drain(cx))?;
+            Poll::Ready(Ok(length))
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            ready!(self.poll_drain(cx))?;
+            Pin::new(&mut self.stream).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            ready!(self.poll_drain(cx))?;
+            Pin::new(&mut self.stream).poll_shutdown(cx)
+        }
+    }
+
+    impl Device {
+        /// Starts `command` without a pty, see [`crate::device::Device::shell_stream`].
+        pub async fn shell_stream(&self, command: &str) -> Result<ShellStream, AdbError> {
+            self.open_shell_stream("raw", command).await
+        }
+
+        /// Starts `command` with a pty, see [`crate::device::Device::shell_stream_pty`].
+        pub async fn shell_stream_pty(&self, command: &str) -> Result<ShellStream, AdbError> {
+            self.open_shell_stream("pty", command).await
+        }
+
+        async fn open_shell_stream(
+            &self,
+            mode: &str,
+            command: &str,
+        ) -> Result<ShellStream, AdbError> {
+            Ok(if self.features().await?.contains(&Feature::ShellV2) {
+                let service = format!("shell,v2,{}:{}", mode, command);
+                ShellStream::new(self.open(&service).await?, true)
+            } else {
+                ShellStream::new(self.open(&format!("shell:{}", command)).await?, false)
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::io::Cursor;
+
+        use tokio::io::AsyncReadExt;
+
+        use super::*;
+
+        /// A stream replaying canned packets and recording the written ones.
+        struct MockStream {
+            replies: Cursor<Vec<u8>>,
+            requests: Vec<u8>,
+        }
+
+        impl AsyncRead for MockStream {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.replies).poll_read(cx, buf)
+            }
+        }
+
+        impl AsyncWrite for MockStream {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.requests).poll_write(cx, buf)
+            }
+
+            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.requests).poll_flush(cx)
+            }
+
+            fn poll_shutdown(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.requests).poll_shutdown(cx)
+            }
+        }
+
+        fn packet(id: PacketId, data: &[u8]) -> Vec<u8> {
+            Packet {
+                id,
+                data: data.to_vec(),
+            }
+            .encode()
+        }
+
+        #[tokio::test]
+        async fn test_shell_stream() {
+            let mut replies = packet(PacketId::Stdout, b"hello ");
+            replies.extend(packet(PacketId::Stderr, b"oops"));
+            replies.extend(packet(PacketId::Stdout, b"world"));
+            replies.extend(packet(PacketId::Exit, b"\x01"));
+            let mut stream = ShellStream::new(
+                MockStream {
+                    replies: Cursor::new(replies),
+                    requests: Vec::new(),
+                },
+                true,
+            );
+            stream.write_all(b"ls\n").await.unwrap();
+            stream.resize(24, 80).await.unwrap();
+            stream.close_stdin().await.unwrap();
+            let mut output = Vec::new();
+            stream.read_to_end(&mut output).await.unwrap();
+            assert_eq!(b"hello world", &output[..]);
+            assert_eq!(b"oops", &stream.take_stderr()[..]);
+            assert_eq!(Some(1), stream.exit_code());
+            let mut requests = packet(PacketId::Stdin, b"ls\n");
+            requests.extend(packet(PacketId::WindowSizeChange, b"24x80,0x0\0"));
+            requests.extend(packet(PacketId::CloseStdin, b""));
+            assert_eq!(requests, stream.into_inner().requests);
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+mod sync {
+    use std::path::Path;
+    use std::time::SystemTime;
+
+    use tokio::fs::File;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::{read_decoded, Device};
+    use crate::compat;
+    use crate::error::AdbError;
+    use crate::sync::{self, DirEntry, FileStat, SyncReply, SyncRequest, TransferStats};
+
+    /// An async connection to the sync service of a device, see [`sync::SyncConnection`].
+    ///
+    /// The connection can serve any number of requests, and is closed with [`Self::quit`] or on drop.
+    #[derive(Debug)]
+    pub struct SyncConnection<S: AsyncRead + AsyncWrite + Unpin = TcpStream> {
+        stream: S,
+    }
+
+    impl<S: AsyncRead + AsyncWrite + Unpin> SyncConnection<S> {
+        /// Wraps a stream already switched to the sync service.
+        pub fn new(stream: S) -> Self {
+            Self { stream }
+        }
+
+        async fn send_request(&mut self, request: Vec<u8>) -> Result<(), AdbError> {
+            self.stream.write_all(&request).await?;
+            Ok(())
+        }
+
+        async fn read_reply(&mut self, request: SyncRequest) -> Result<SyncReply, AdbError> {
+            sync::check_reply(
+                read_decoded(&mut self.stream, |bytes| sync::decode_reply(bytes, request)).await?,
+            )
+        }
+
+        /// Returns the metadata of a file on the device (`STAT`).
+        pub async fn stat(&mut self, path: &str) -> Result<FileStat, AdbError> {
+            let request = sync::encode_path_request(b"STAT", path)?;
+            self.send_request(request).await?;
+            match self.read_reply(SyncRequest::Stat).await? {
+                SyncReply::Stat(stat) => Ok(stat),
+                reply => Err(sync::unexpected(reply)),
+            }
+        }
+
+        /// Returns the metadata of a file on the device, not following symbolic links
+        /// (`LST2`).
+        pub async fn lstat_v2(&mut self, path: &str) -> Result<FileStat, AdbError> {
+            let request = sync::encode_path_request(b"LST2", path)?;
+            self.send_request(request).await?;
+            match self.read_reply(SyncRequest::StatV2).await? {
+                SyncReply::Stat(stat) => Ok(stat),
+                reply => Err(sync::unexpected(reply)),
+            }
+        }
+
+        /// Lists a directory on the device (`LIST`), excluding `.` and `..`.
+        pub async fn list(&mut self, path: &str) -> Result<Vec<DirEntry>, AdbError> {
+            self.list_with(path, b"LIST", SyncRequest::List).await
+        }
+
+        /// Lists a directory on the device (`LIS2`), excluding `.` and `..`.
+        pub async fn list_v2(&mut self, path: &str) -> Result<Vec<DirEntry>, AdbError> {
+            self.list_with(path, b"LIS2", SyncRequest::ListV2).await
+        }
+
+        async fn list_with(
+            &mut self,
+            path: &str,
+            id: &[u8; 4],
+            request: SyncRequest,
+        ) -> Result<Vec<DirEntry>, AdbError> {
+            let request_bytes = sync::encode_path_request(id, path)?;
+            self.send_request(request_bytes).await?;
+            let mut entries = Vec::new();
+            loop {
+                match self.read_reply(request).await? {
+                    SyncReply::Dent(entry) => {
+                        if !sync::is_dot_entry(&entry) {
+                            entries.push(entry);
+                        }
+                    }
+                    SyncReply::Done => return Ok(entries),
+                    reply => return Err(sync::unexpected(reply)),
+                }
+            }
+        }
+
+        /// Sends the content of `reader` to `path` on the device (`SEND`),
+        /// creating the file with `mode` and setting its modification time to `mtime`.
+        ///
+        /// Returns the number of sent bytes.
+        pub async fn send<R: AsyncRead + Unpin>(
+            &mut self,
+            reader: &mut R,
+            path: &str,
+            mode: u32,
+            mtime: SystemTime,
+        ) -> Result<u64, AdbError> {
+            let request = sync::encode_send(path, mode)?;
+            self.send_request(request).await?;
+            let mut buffer = vec![0; sync::MAX_CHUNK_SIZE];
+            let mut sent = 0;
+            loop {
+                let read = reader.read(&mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                let request = sync::encode_request(b"DATA", &buffer[..read])?;
+                self.send_request(request).await?;
+                sent += read as u64;
+            }
+            self.send_request(sync::encode_done(mtime)).await?;
+            match self.read_reply(SyncRequest::Send).await? {
+                SyncReply::Okay => Ok(sent),
+                reply => Err(sync::unexpected(reply)),
+            }
+        }
+
+        /// Receives the content of `path` on the device into `writer` (`RECV`).
+        ///
+        /// Returns the number of received bytes.
+        pub async fn recv<W: AsyncWrite + Unpin>(
+            &mut self,
+            path: &str,
+            writer: &mut W,
+        ) -> Result<u64, AdbError> {
+            let request = sync::encode_path_request(b"RECV", path)?;
+            self.send_request(request).await?;
+            let mut received = 0;
+            loop {
+                let reply = self.read_reply(SyncRequest::Recv).await?;
+                match reply {
+                    SyncReply::Data(data) => {
+                        writer.write_all(&data).await?;
+                        received += data.len() as u64;
+                    }
+                    SyncReply::Done => return Ok(received),
+                    reply => return Err(sync::unexpected(reply)),
+                }
+            }
+        }
+
+        /// Closes the sync connection (`QUIT`).
+        pub async fn quit(mut self) -> Result<(), AdbError> {
+            let request = sync::encode_request(b"QUIT", &[])?;
+            self.send_request(request).await
+        }
+    }
+
+    impl Device {
+        /// Opens a connection to the sync service of the device (`sync:`).
+        pub async fn sync(&self) -> Result<SyncConnection, AdbError> {
+            Ok(SyncConnection::new(self.open("sync:").await?))
+        }
+
+        /// Pushes the local file `local` to `remote` on the device, creating it with `mode`.
+        ///
+        /// The modification time of the local file is preserved.
+        pub async fn push(
+            &self,
+            local: &Path,
+            remote: &str,
+            mode: u32,
+        ) -> Result<TransferStats, AdbError> {
+            let mut file = File::open(local).await?;
+            let mtime = file.metadata().await?.modified()?;
+            let clock = &self.server().inner.clock;
+            let start = clock.now();
+            let mut sync = self.sync().await?;
+            let bytes = sync.send(&mut file, remote, mode, mtime).await?;
+            sync.quit().await?;
+            Ok(TransferStats {
+                bytes,
+                duration: clock.now() - start,
+            })
+        }
+
+        /// Pulls `remote` on the device to the local file `local`.
+        ///
+        /// The file is written next to `local` with a `.part` suffix, and renamed to `local`
+        /// once complete: a failed pull leaves `local` untouched. The modification time of the
+        /// remote file is preserved on Rust 1.75 and later.
+        pub async fn pull(&self, remote: &str, local: &Path) -> Result<TransferStats, AdbError> {
+            let clock = &self.server().inner.clock;
+            let start = clock.now();
+            let mut sync = self.sync().await?;
+            let stat = sync.stat(remote).await?;
+            if !stat.exists() {
+                return Err(sync::missing(remote));
+            }
+            let partial = sync::partial_path(local);
+            let bytes = match recv_file(sync, remote, &partial, stat.modified()).await {
+                Ok(bytes) => (tokio::fs::rename(&partial, local).await)
+                    .map(|()| bytes)
+                    .map_err(AdbError::from),
+                Err(e) => Err(e),
+            };
+            let bytes = match bytes {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    let _ = tokio::fs::remove_file(&partial).await;
+                    return Err(e);
+                }
+            };
+            Ok(TransferStats {
+                bytes,
+                duration: clock.now() - start,
+            })
+        }
+    }
+
+    /// Receives `remote` into a new file at `path`, with the modification time `modified`.
+    async fn recv_file(
+        mut sync: SyncConnection,
+        remote: &str,
+        path: &Path,
+        modified: std::time::SystemTime,
+    ) -> Result<u64, AdbError> {
+        let mut file = File::create(path).await?;
+        let bytes = sync.recv(remote, &mut file).await?;
+        sync.quit().await?;
+        // Wait for the buffered writes before touching the file synchronously.
+        file.flush().await?;
+        compat::set_modified(&file.into_std().await, modified)?;
+        Ok(bytes)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::io::Cursor;
+        use std::pin::Pin;
+        use std::task::{Context, Poll};
+
+        use tokio::io::ReadBuf;
+
+        use super::*;
+
+        /// A stream replaying canned replies and recording the requests.
+        struct MockStream {
+            replies: Cursor<Vec<u8>>,
+            requests: Vec<u8>,
+        }
+
+        impl MockStream {
+            fn new(replies: &[u8]) -> Self {
+                Self {
+                    replies: Cursor::new(replies.to_vec()),
+                    requests: Vec::new(),
+                }
+            }
+        }
+
+        impl AsyncRead for MockStream {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<std::io::Result<()>> {
+                Pin::new(&mut self.replies).poll_read(cx, buf)
+            }
+        }
+
+        impl AsyncWrite for MockStream {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<std::io::Result<usize>> {
+                Pin::new(&mut self.requests).poll_write(cx, buf)
+            }
+
+            fn poll_flush(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<std::io::Result<()>> {
+                Pin::new(&mut self.requests).poll_flush(cx)
+            }
+
+            fn poll_shutdown(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<std::io::Result<()>> {
+                Pin::new(&mut self.requests).poll_shutdown(cx)
+            }
+        }
+
+        #[tokio::test]
+        async fn test_recv() {
+            let mut sync = SyncConnection::new(MockStream::new(
+                b"DATA\x03\x00\x00\x00abcDATA\x02\x00\x00\x00deDONE\x00\x00\x00\x00",
+            ));
+            let mut output = Vec::new();
+            assert_eq!(5, sync.recv("/a", &mut output).await.unwrap());
+            assert_eq!(b"abcde", &output[..]);
+            assert_eq!(b"RECV\x02\x00\x00\x00/a", &sync.stream.requests[..]);
+        }
+
+        #[tokio::test]
+        async fn test_stat_fail() {
+            let mut sync = SyncConnection::new(MockStream::new(b"FAIL\x04\x00\x00\x00nope"));
+            match sync.stat("/a").await {
+                Err(AdbError::Server { message }) => assert_eq!("nope", message),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Device>();
+    }
+
+    #[test]
+    fn test_futures_are_send() {
+        fn assert_send<T: Send>(_: T) {}
+        let device = AdbServer::default().any_device();
+        assert_send(device.server().check_version());
+        assert_send(device.server().devices());
+        assert_send(device.get_state());
+        assert_send(device.server().track_devices());
+        fn assert_stream_send<T: Stream + Send>() {}
+        assert_stream_send::<DeviceTracker>();
+        #[cfg(feature = "sync")]
+        assert_send(device.pull("/a", std::path::Path::new("a")));
+    }
+
+    #[tokio::test]
+    async fn test_read_decoded() {
+        let mut reader = &b"FAIL0006failedOKAY"[..];
+        assert_eq!(
+            protocol::Status::Fail("failed".to_string()),
+            read_decoded(&mut reader, protocol::decode_status)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            protocol::Status::Okay,
+            read_decoded(&mut reader, protocol::decode_status)
+                .await
+                .unwrap()
+        );
+        assert!(read_decoded(&mut reader, protocol::decode_status)
+            .await
+            .is_err());
+    }
+}
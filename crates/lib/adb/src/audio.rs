@@ -0,0 +1,339 @@
+//! This module provides the volumes of the audio streams and their routing.
+//!
+//! Volumes are read and set with `cmd media_session volume`, or `media volume` before
+//! Android 8, which print lines like `[v] volume is 7 in range [0..15]`. Streams are muted
+//! with `cmd audio adj-mute`, available since Android 14. The routing is parsed from the
+//! `Stream volumes` section of `dumpsys audio`.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::device::Device;
+use crate::error::AdbError;
+
+/// An audio stream, with the ids of `AudioManager.STREAM_*`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[repr(u8)]
+pub enum AudioStream {
+    VoiceCall = 0,
+    System = 1,
+    Ring = 2,
+    Music = 3,
+    Alarm = 4,
+    Notification = 5,
+    BluetoothSco = 6,
+    SystemEnforced = 7,
+    Dtmf = 8,
+    Tts = 9,
+    Accessibility = 10,
+    Assistant = 11,
+}
+
+impl AudioStream {
+    const ALL: [Self; 12] = [
+        Self::VoiceCall,
+        Self::System,
+        Self::Ring,
+        Self::Music,
+        Self::Alarm,
+        Self::Notification,
+        Self::BluetoothSco,
+        Self::SystemEnforced,
+        Self::Dtmf,
+        Self::Tts,
+        Self::Accessibility,
+        Self::Assistant,
+    ];
+
+    /// Returns the id of the stream, e.g. 3 for [`Self::Music`].
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl Display for AudioStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::VoiceCall => "STREAM_VOICE_CALL",
+            Self::System => "STREAM_SYSTEM",
+            Self::Ring => "STREAM_RING",
+            Self::Music => "STREAM_MUSIC",
+            Self::Alarm => "STREAM_ALARM",
+            Self::Notification => "STREAM_NOTIFICATION",
+            Self::BluetoothSco => "STREAM_BLUETOOTH_SCO",
+            Self::SystemEnforced => "STREAM_SYSTEM_ENFORCED",
+            Self::Dtmf => "STREAM_DTMF",
+            Self::Tts => "STREAM_TTS",
+            Self::Accessibility => "STREAM_ACCESSIBILITY",
+            Self::Assistant => "STREAM_ASSISTANT",
+        })
+    }
+}
+
+impl FromStr for AudioStream {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|stream| stream.to_string() == s)
+            .ok_or_else(|| AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "AudioStream",
+                source: None,
+            })
+    }
+}
+
+/// The volume of a stream, a level between `min` and `max`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Volume {
+    pub level: u32,
+    pub min: u32,
+    pub max: u32,
+}
+
+impl FromStr for Volume {
+    type Err = AdbError;
+
+    /// Parses the output of `media volume --get`, from the line
+    /// `[v] volume is 7 in range [0..15]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || AdbError::Parse {
+            value: s.to_string(),
+            source_type: "&str",
+            target_type: "Volume",
+            source: None,
+        };
+        let line = s
+            .lines()
+            .find_map(|line| line.split_once("volume is ").map(|(_, line)| line))
+            .ok_or_else(err)?;
+        let (level, range) = line.split_once(" in range [").ok_or_else(err)?;
+        let (min, max) = range
+            .trim_end()
+            .strip_suffix(']')
+            .and_then(|range| range.split_once(".."))
+            .ok_or_else(err)?;
+        Ok(Self {
+            level: level.parse().map_err(|_| err())?,
+            min: min.parse().map_err(|_| err())?,
+            max: max.parse().map_err(|_| err())?,
+        })
+    }
+}
+
+/// The state of a stream in `dumpsys audio`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct StreamState {
+    pub stream: AudioStream,
+    pub muted: bool,
+    pub min: u32,
+    pub max: u32,
+    /// The output devices the stream is routed to, e.g. `speaker` or `headset`.
+    pub devices: Vec<String>,
+}
+
+/// The routing of the audio streams, parsed from `dumpsys audio`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct AudioState {
+    /// The streams, in the order of `dumpsys audio`.
+    pub streams: Vec<StreamState>,
+    /// The name of the connected Bluetooth audio device.
+    pub bluetooth_name: Option<String>,
+}
+
+impl AudioState {
+    /// Returns the state of `stream`.
+    pub fn stream(&self, stream: AudioStream) -> Option<&StreamState> {
+        self.streams.iter().find(|state| state.stream == stream)
+    }
+}
+
+impl FromStr for AudioState {
+    type Err = AdbError;
+
+    /// Parses the output of `dumpsys audio`, from sections like:
+    ///
+    /// ```text
+    /// Stream volumes (device: index)
+    /// - STREAM_MUSIC:
+    ///    Muted: false
+    ///    Min: 0
+    ///    Max: 15
+    ///    Devices: speaker
+    /// ```
+    ///
+    /// Streams unknown to [`AudioStream`] are skipped.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut state = Self::default();
+        let mut current: Option<StreamState> = None;
+        let mut in_volumes = false;
+        for line in s.lines() {
+            let trimmed = line.trim();
+            if let Some(name) = trimmed.strip_prefix("mBluetoothName=") {
+                state.bluetooth_name = Some(name.to_string()).filter(|name| name != "null");
+            }
+            if line.starts_with("Stream volumes") {
+                in_volumes = true;
+                continue;
+            }
+            if !in_volumes {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("- ") {
+                state.streams.extend(current.take());
+                current = name
+                    .trim_end_matches(':')
+                    .parse()
+                    .ok()
+                    .map(|stream| StreamState {
+                        stream,
+                        muted: false,
+                        min: 0,
+                        max: 0,
+                        devices: Vec::new(),
+                    });
+                continue;
+            }
+            if !line.starts_with(' ') {
+                // The end of the section.
+                in_volumes = false;
+                state.streams.extend(current.take());
+                continue;
+            }
+            let Some(stream) = &mut current else {
+                continue;
+            };
+            let Some((key, value)) = trimmed.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            let parse_err = || AdbError::Parse {
+                value: line.to_string(),
+                source_type: "&str",
+                target_type: "AudioState",
+                source: None,
+            };
+            match key {
+                "Muted" => stream.muted = value == "true",
+                "Min" => stream.min = value.parse().map_err(|_| parse_err())?,
+                "Max" => stream.max = value.parse().map_err(|_| parse_err())?,
+                "Devices" => {
+                    stream.devices = value.split_whitespace().map(str::to_string).collect()
+                }
+                _ => {}
+            }
+        }
+        state.streams.extend(current);
+        Ok(state)
+    }
+}
+
+impl Device {
+    /// Returns the volume of `stream`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::audio::AudioStream;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let volume = device.volume(AudioStream::Music).unwrap();
+    /// device.set_volume(AudioStream::Music, volume.max).unwrap();
+    /// ```
+    pub fn volume(&self, stream: AudioStream) -> Result<Volume, AdbError> {
+        self.media_session(&format!("volume --stream {} --get", stream.id()))?
+            .parse()
+    }
+
+    /// Sets the volume of `stream` to `level`, without showing the volume UI.
+    pub fn set_volume(&self, stream: AudioStream, level: u32) -> Result<(), AdbError> {
+        self.media_session(&format!("volume --stream {} --set {}", stream.id(), level))
+            .map(drop)
+    }
+
+    /// Mutes (`muted` is `true`) or unmutes `stream`, keeping its volume.
+    ///
+    /// Requires Android 14.
+    pub fn set_muted(&self, stream: AudioStream, muted: bool) -> Result<(), AdbError> {
+        let action = if muted { "adj-mute" } else { "adj-unmute" };
+        self.shell_checked(&format!("cmd audio {} {}", action, stream.id()))
+            .map(drop)
+    }
+
+    /// Returns the routing of the audio streams (`dumpsys audio`).
+    pub fn audio_state(&self) -> Result<AudioState, AdbError> {
+        self.shell_checked("dumpsys audio")?.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_parse() {
+        let output = "\
+[v] will control stream=3 (STREAM_MUSIC)
+[v] will get volume
+[v] Connecting to AudioService
+[v] volume is 7 in range [0..15]
+";
+        assert_eq!(
+            Volume {
+                level: 7,
+                min: 0,
+                max: 15
+            },
+            output.parse().unwrap()
+        );
+        assert!("[v] will get volume\n".parse::<Volume>().is_err());
+    }
+
+    #[test]
+    fn test_audio_state_parse() {
+        let dump = "\
+Audio event log: ...
+Stream volumes (device: index)
+- STREAM_VOICE_CALL:
+   Muted: false
+   Min: 1
+   Max: 5
+   Current: 2 (earpiece): 4, 40000000 (default): 4
+   Devices: earpiece
+- STREAM_MUSIC:
+   Muted: true
+   Min: 0
+   Max: 15
+   Devices: speaker bt_a2dp
+- STREAM_FUTURE:
+   Muted: false
+   Min: 0
+   Max: 1
+
+Audio routes:
+  mMainType=0x0
+  mBluetoothName=Headphones
+";
+        let state: AudioState = dump.parse().unwrap();
+        assert_eq!(2, state.streams.len());
+        assert_eq!(
+            &StreamState {
+                stream: AudioStream::Music,
+                muted: true,
+                min: 0,
+                max: 15,
+                devices: vec!["speaker".to_string(), "bt_a2dp".to_string()],
+            },
+            state.stream(AudioStream::Music).unwrap()
+        );
+        assert_eq!(
+            ["earpiece"],
+            &state.stream(AudioStream::VoiceCall).unwrap().devices[..]
+        );
+        assert_eq!(Some("Headphones"), state.bluetooth_name.as_deref());
+        assert_eq!(AudioStream::Assistant, "STREAM_ASSISTANT".parse().unwrap());
+    }
+}
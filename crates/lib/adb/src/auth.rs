@@ -0,0 +1,442 @@
+//! This module provides the RSA keys authenticating this host to devices, compatible with the
+//! `~/.android/adbkey` of the adb command.
+//!
+//! A device with `ro.adb.secure=1` replies to `CNXN` with `AUTH(TOKEN)`, a random token of
+//! 20 bytes. The host signs it as a SHA-1 digest with PKCS#1 v1.5 padding and replies with
+//! `AUTH(SIGNATURE)`, trying every key until one is accepted. If none is, the host sends
+//! `AUTH(RSAPUBLICKEY)` with its public key, and the device asks the user to allow it.
+//!
+//! Public keys are sent in the Android encoding, base64 encoded and followed by
+//! ` <user>@<host>`, as stored in `adbkey.pub`.
+//!
+//! Hosts shared by several teams can keep separate identities, e.g. `work` and `ci`, each
+//! with its own key in `identities/<name>/adbkey` of [`android_dir`]. A device only trusts
+//! the identities it was allowed with, and a connection tells which one it authenticated
+//! with.
+
+use std::env;
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding};
+use rsa::traits::PublicKeyParts;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPrivateKey};
+use sha1::Sha1;
+
+use crate::base64;
+use crate::compat;
+use crate::error::AdbError;
+
+/// The size of the keys generated by [`AdbKey::generate`], in bits.
+pub const KEY_BITS: usize = 2048;
+
+/// The size of an `AUTH(TOKEN)` token, the size of a SHA-1 digest.
+pub const TOKEN_SIZE: usize = 20;
+
+/// The type of an `AUTH` message, its first argument.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum AuthType {
+    /// A token to sign, sent by the device.
+    Token,
+    /// The signature of the token, sent by the host.
+    Signature,
+    /// The public key of the host, to be allowed by the user.
+    RsaPublicKey,
+}
+
+impl AuthType {
+    /// Returns the type as sent over the wire.
+    pub const fn to_u32(self) -> u32 {
+        match self {
+            Self::Token => 1,
+            Self::Signature => 2,
+            Self::RsaPublicKey => 3,
+        }
+    }
+
+    /// Returns the type sent over the wire as `id`.
+    pub const fn from_u32(id: u32) -> Option<Self> {
+        Some(match id {
+            1 => Self::Token,
+            2 => Self::Signature,
+            3 => Self::RsaPublicKey,
+            _ => return None,
+        })
+    }
+}
+
+/// Converts an error of the `rsa` crate.
+pub(crate) fn key_error<E: std::fmt::Display>(e: E) -> AdbError {
+    compat::io_other(format!("RSA key error: {}", e)).into()
+}
+
+/// Returns `-1 / n0 mod 2^32`, for Montgomery multiplication on the device.
+fn n0inv(n0: u32) -> u32 {
+    // Newton's iteration doubles the correct low bits every step, from 3 bits for odd n0.
+    let mut inverse = n0;
+    for _ in 0..5 {
+        inverse = inverse.wrapping_mul(2u32.wrapping_sub(n0.wrapping_mul(inverse)));
+    }
+    inverse.wrapping_neg()
+}
+
+/// Encodes a public key in the Android encoding (`android_pubkey_encode`).
+///
+/// `modulus` and `rr` (`R^2 mod n` with `R = 2^(8 * size)`) are little-endian, padded to the
+/// key size.
+fn encode_android_public_key(modulus: &[u8], rr: &[u8], exponent: u32) -> Vec<u8> {
+    let words = modulus.len() / 4;
+    let n0 = u32::from_le_bytes(modulus[..4].try_into().unwrap());
+    let mut encoded = Vec::with_capacity(12 + 2 * modulus.len());
+    encoded.extend_from_slice(&(words as u32).to_le_bytes());
+    encoded.extend_from_slice(&n0inv(n0).to_le_bytes());
+    encoded.extend_from_slice(modulus);
+    encoded.extend_from_slice(rr);
+    encoded.extend_from_slice(&exponent.to_le_bytes());
+    encoded
+}
+
+/// Returns the little-endian bytes of `n`, padded to `size` bytes.
+fn to_padded_le(n: &BigUint, size: usize) -> Vec<u8> {
+    let mut bytes = n.to_bytes_le();
+    bytes.resize(size, 0);
+    bytes
+}
+
+/// Returns the `<user>@<host>` suffix of public keys.
+fn default_comment() -> String {
+    let user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    let host = env::var("HOSTNAME")
+        .or_else(|_| env::var("COMPUTERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    format!("{}@{}", user, host)
+}
+
+/// Returns the directory of the adb keys, `$ANDROID_USER_HOME`, or `~/.android`.
+pub fn android_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("ANDROID_USER_HOME") {
+        return Some(dir.into());
+    }
+    let home = env::var_os("ANDROID_SDK_HOME")
+        .or_else(|| env::var_os("HOME"))
+        .or_else(|| env::var_os("USERPROFILE"))?;
+    Some(Path::new(&home).join(".android"))
+}
+
+/// Fails if `name` isn't a valid identity name: ASCII letters, digits, `-`, `_` and `.`,
+/// not starting with a `.`.
+fn check_identity_name(name: &str) -> Result<(), AdbError> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if name.is_empty() || name.starts_with('.') || !valid {
+        return Err(AdbError::Parse {
+            value: name.to_string(),
+            source_type: "&str",
+            target_type: "identity",
+            source: None,
+        });
+    }
+    Ok(())
+}
+
+/// Returns the directory of the identity `name`, `identities/<name>` in [`android_dir`].
+///
+/// Fails if the name isn't made of ASCII letters, digits, `-`, `_` and `.`, or starts with a
+/// `.`.
+pub fn identity_dir(name: &str) -> Result<PathBuf, AdbError> {
+    check_identity_name(name)?;
+    let dir = android_dir().ok_or_else(|| compat::io_other("no home directory"))?;
+    Ok(dir.join("identities").join(name))
+}
+
+/// An RSA key authenticating this host.
+#[derive(Clone)]
+pub struct AdbKey {
+    key: RsaPrivateKey,
+    comment: String,
+    identity: Option<String>,
+}
+
+impl Debug for AdbKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdbKey")
+            .field("comment", &self.comment)
+            .field("identity", &self.identity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AdbKey {
+    /// Generates a new key of [`KEY_BITS`] bits.
+    pub fn generate() -> Result<Self, AdbError> {
+        let key = RsaPrivateKey::new(&mut rand::rngs::OsRng, KEY_BITS).map_err(key_error)?;
+        Ok(Self {
+            key,
+            comment: default_comment(),
+            identity: None,
+        })
+    }
+
+    /// Parses a PEM encoded private key, in PKCS#8 (`BEGIN PRIVATE KEY`) like the keys
+    /// written by adb, or PKCS#1 (`BEGIN RSA PRIVATE KEY`).
+    pub fn from_pem(pem: &str) -> Result<Self, AdbError> {
+        let key = if pem.contains("BEGIN RSA PRIVATE KEY") {
+            RsaPrivateKey::from_pkcs1_pem(pem).map_err(key_error)?
+        } else {
+            RsaPrivateKey::from_pkcs8_pem(pem).map_err(key_error)?
+        };
+        Ok(Self {
+            key,
+            comment: default_comment(),
+            identity: None,
+        })
+    }
+
+    /// Loads the private key at `path`, taking the comment from `<path>.pub` if it exists.
+    pub fn load(path: &Path) -> Result<Self, AdbError> {
+        let mut key = Self::from_pem(&fs::read_to_string(path)?)?;
+        let mut public = path.as_os_str().to_os_string();
+        public.push(".pub");
+        if let Ok(public) = fs::read_to_string(public) {
+            if let Some((_, comment)) = public.trim_end_matches(['\0', '\n']).split_once(' ') {
+                key.comment = comment.to_string();
+            }
+        }
+        Ok(key)
+    }
+
+    /// Writes the private key to `path` in PKCS#8, and the public key to `<path>.pub`,
+    /// like `adb keygen`.
+    pub fn save(&self, path: &Path) -> Result<(), AdbError> {
+        let pem = self.key.to_pkcs8_pem(LineEnding::LF).map_err(key_error)?;
+        fs::write(path, pem.as_bytes())?;
+        let mut public = path.as_os_str().to_os_string();
+        public.push(".pub");
+        fs::write(public, self.public_key())?;
+        Ok(())
+    }
+
+    /// Returns the comment appended to the public key, usually `<user>@<host>`.
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    /// Sets the comment appended to the public key, shown to the user by the device.
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment = comment.to_string();
+        self
+    }
+
+    /// Returns the name of the identity the key belongs to, `None` for the default key of
+    /// adb and vendor keys.
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+
+    /// Sets the name of the identity the key belongs to.
+    pub fn with_identity(mut self, identity: &str) -> Self {
+        self.identity = Some(identity.to_string());
+        self
+    }
+
+    /// Returns the public key in the Android encoding, as sent in `AUTH(RSAPUBLICKEY)`
+    /// without the trailing NUL, and stored in `adbkey.pub`.
+    pub fn public_key(&self) -> String {
+        let size = self.key.size();
+        let n = self.key.n();
+        let rr = (BigUint::from(1u32) << (2 * 8 * size)) % n;
+        let exponent = self.key.e().to_bytes_le();
+        let mut e = [0; 4];
+        for (dest, byte) in e.iter_mut().zip(exponent) {
+            *dest = byte;
+        }
+        let encoded = encode_android_public_key(
+            &to_padded_le(n, size),
+            &to_padded_le(&rr, size),
+            u32::from_le_bytes(e),
+        );
+        format!("{} {}", base64::encode(&encoded), self.comment)
+    }
+
+    /// Returns the RSA key, e.g. to build the TLS client certificate.
+    #[cfg(feature = "tls")]
+    pub(crate) fn rsa_key(&self) -> &RsaPrivateKey {
+        &self.key
+    }
+
+    /// Signs an `AUTH(TOKEN)` token.
+    pub fn sign(&self, token: &[u8]) -> Result<Vec<u8>, AdbError> {
+        if token.len() != TOKEN_SIZE {
+            return Err(AdbError::Protocol {
+                code: None,
+                message: format!("invalid AUTH token of {} bytes", token.len()),
+            });
+        }
+        self.key
+            .sign(Pkcs1v15Sign::new::<Sha1>(), token)
+            .map_err(key_error)
+    }
+}
+
+/// A set of keys, tried in order when authenticating.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::auth::KeyStore;
+///
+/// // Loads `~/.android/adbkey`, generating it like adb does if it's missing,
+/// // and the keys listed in `ADB_VENDOR_KEYS`.
+/// let keys = KeyStore::load_default().unwrap();
+/// println!("{}", keys.keys()[0].public_key());
+///
+/// // Loads `~/.android/identities/ci/adbkey` instead, kept apart from the keys of adb.
+/// let keys = KeyStore::load_identity("ci").unwrap();
+/// assert_eq!(Some("ci"), keys.keys()[0].identity());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct KeyStore {
+    keys: Vec<AdbKey>,
+}
+
+impl KeyStore {
+    /// Creates an empty key store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the default key of adb, `adbkey` in [`android_dir`], generating it if it's
+    /// missing, then the keys of `ADB_VENDOR_KEYS`.
+    pub fn load_default() -> Result<Self, AdbError> {
+        let mut store = Self::new();
+        let dir = android_dir().ok_or_else(|| compat::io_other("no home directory"))?;
+        store.add(load_or_generate(&dir)?);
+        if let Some(paths) = env::var_os("ADB_VENDOR_KEYS") {
+            for path in env::split_paths(&paths) {
+                store.load_path(&path)?;
+            }
+        }
+        Ok(store)
+    }
+
+    /// Loads the key of the identity `name`, `adbkey` in [`identity_dir`], generating it if
+    /// it's missing. Its public key is commented `<name>@<host>`, telling identities apart
+    /// in the prompt of the device and in its list of allowed keys.
+    ///
+    /// Neither the default key of adb nor the keys of `ADB_VENDOR_KEYS` are loaded, so that
+    /// the device only sees keys of this identity.
+    pub fn load_identity(name: &str) -> Result<Self, AdbError> {
+        let dir = identity_dir(name)?;
+        let path = dir.join("adbkey");
+        let key = if path.exists() {
+            AdbKey::load(&path)?
+        } else {
+            let key = AdbKey::generate()?;
+            let comment = format!("{}@{}", name, host_of(key.comment()));
+            let key = key.with_comment(&comment);
+            fs::create_dir_all(&dir)?;
+            key.save(&path)?;
+            key
+        };
+        let mut store = Self::new();
+        store.add(key.with_identity(name));
+        Ok(store)
+    }
+
+    /// Loads the key at `path`, or the `*.adb_key` files of the directory at `path`.
+    pub fn load_path(&mut self, path: &Path) -> Result<(), AdbError> {
+        if path.is_dir() {
+            let mut paths: Vec<_> = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()?;
+            paths.sort();
+            for path in paths {
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "adb_key")
+                {
+                    self.add(AdbKey::load(&path)?);
+                }
+            }
+            Ok(())
+        } else {
+            self.add(AdbKey::load(path)?);
+            Ok(())
+        }
+    }
+
+    /// Adds `key`, tried after the keys already in the store.
+    pub fn add(&mut self, key: AdbKey) {
+        self.keys.push(key);
+    }
+
+    /// Returns the keys, in the order they're tried.
+    pub fn keys(&self) -> &[AdbKey] {
+        &self.keys
+    }
+}
+
+/// Loads `adbkey` in `dir`, generating it if it's missing.
+fn load_or_generate(dir: &Path) -> Result<AdbKey, AdbError> {
+    let path = dir.join("adbkey");
+    if path.exists() {
+        return AdbKey::load(&path);
+    }
+    fs::create_dir_all(dir)?;
+    let key = AdbKey::generate()?;
+    key.save(&path)?;
+    Ok(key)
+}
+
+/// Returns the host of a `<user>@<host>` comment.
+fn host_of(comment: &str) -> &str {
+    comment.rsplit_once('@').map_or(comment, |(_, host)| host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_n0inv() {
+        for n0 in [1u32, 3, 0xffff_ffff, 0x1234_5679] {
+            assert_eq!(u32::MAX, n0.wrapping_mul(n0inv(n0)), "{:#x}", n0);
+        }
+    }
+
+    #[test]
+    fn test_encode_android_public_key() {
+        let modulus = [0x0f, 0, 0, 0, 0, 0, 0, 0];
+        let encoded = encode_android_public_key(&modulus, &[1; 8], 65537);
+        assert_eq!(4 + 4 + 8 + 8 + 4, encoded.len());
+        assert_eq!([2, 0, 0, 0], encoded[..4]);
+        assert_eq!(modulus, encoded[8..16]);
+        assert_eq!([1; 8], encoded[16..24]);
+        assert_eq!(65537u32.to_le_bytes(), encoded[24..]);
+    }
+
+    #[test]
+    fn test_identity_name() {
+        for name in ["work", "ci-runner_2", "team.a"] {
+            assert!(check_identity_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", ".", "..", ".hidden", "a/b", "a\\b", "my key"] {
+            assert!(check_identity_name(name).is_err(), "{}", name);
+        }
+        assert_eq!("host", host_of("user@host"));
+        assert_eq!("unknown", host_of("unknown"));
+    }
+
+    #[test]
+    fn test_auth_type() {
+        for t in [AuthType::Token, AuthType::Signature, AuthType::RsaPublicKey] {
+            assert_eq!(Some(t), AuthType::from_u32(t.to_u32()));
+        }
+        assert_eq!(None, AuthType::from_u32(0));
+    }
+}
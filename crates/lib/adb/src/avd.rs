@@ -0,0 +1,544 @@
+//! This module manages the Android Virtual Devices (AVDs) of the host, so test runs can
+//! bring up their own emulators instead of relying on whatever is plugged in.
+//!
+//! Each AVD is described by a `<name>.ini` file in the AVD home, `$ANDROID_AVD_HOME` or
+//! `~/.android/avd`, pointing to the `<name>.avd` directory holding its disks.
+//! [`Avd::boot`] runs the `emulator` executable of the SDK, headless by default, and waits
+//! for the emulator to come online in the adb server. The returned [`RunningEmulator`]
+//! shuts it down through its console when [killed](RunningEmulator::kill), or kills the
+//! process when dropped.
+
+use std::env;
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use crate::device::{Device, DeviceState};
+use crate::emulator::{EmulatorConsole, SERIAL_PREFIX};
+use crate::error::AdbError;
+use crate::server::AdbServer;
+
+/// The console ports the emulator accepts, the adb port being the next one.
+const CONSOLE_PORTS: std::ops::RangeInclusive<u16> = 5554..=5682;
+
+/// How often the devices of the server are polled while an emulator boots.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the emulator has to exit after the console `kill` command.
+const KILL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns the directory of the AVDs, `$ANDROID_AVD_HOME`, or the `avd` directory of
+/// `$ANDROID_USER_HOME`, `$ANDROID_EMULATOR_HOME` or `~/.android`.
+pub fn avd_home() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("ANDROID_AVD_HOME") {
+        return Some(dir.into());
+    }
+    if let Some(dir) =
+        env::var_os("ANDROID_USER_HOME").or_else(|| env::var_os("ANDROID_EMULATOR_HOME"))
+    {
+        return Some(Path::new(&dir).join("avd"));
+    }
+    let home = env::var_os("ANDROID_SDK_HOME")
+        .or_else(|| env::var_os("HOME"))
+        .or_else(|| env::var_os("USERPROFILE"))?;
+    Some(Path::new(&home).join(".android").join("avd"))
+}
+
+/// An Android Virtual Device.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Avd {
+    name: String,
+    path: PathBuf,
+    target: Option<String>,
+}
+
+impl Avd {
+    /// Lists the AVDs of [`avd_home`], sorted by name.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::avd::Avd;
+    ///
+    /// for avd in Avd::list().unwrap() {
+    ///     println!("{} ({})", avd.name(), avd.target().unwrap_or("unknown target"));
+    /// }
+    /// ```
+    pub fn list() -> Result<Vec<Avd>, AdbError> {
+        match avd_home() {
+            Some(dir) => Ok(list_in(&dir)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the name of the AVD, passed to `emulator -avd`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the directory of the AVD, e.g. `~/.android/avd/Pixel_7.avd`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the target of the AVD, e.g. `android-34`.
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Boots the AVD with `options`, and waits until it's online in `server`.
+    ///
+    /// The emulator is killed if it doesn't come online within
+    /// [`EmulatorOptions::boot_timeout`], failing with [`AdbError::Timeout`]. Only the adb
+    /// connection is waited for, Android may still be booting, see `sys.boot_completed`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::avd::{Avd, EmulatorOptions, GpuMode};
+    /// use adb::server::AdbServer;
+    ///
+    /// let server = AdbServer::default();
+    /// let avd = Avd::list().unwrap().into_iter().next().expect("no AVD");
+    /// let options = EmulatorOptions::new().cold_boot(true).gpu(GpuMode::SwiftshaderIndirect);
+    /// let emulator = avd.boot(&server, &options).unwrap();
+    /// println!("{} is online", emulator.device().serial().unwrap());
+    /// emulator.kill().unwrap();
+    /// ```
+    pub fn boot(
+        &self,
+        server: &AdbServer,
+        options: &EmulatorOptions,
+    ) -> Result<RunningEmulator, AdbError> {
+        let port = match options.port {
+            Some(port) => port,
+            None => free_port(server)?,
+        };
+        let program = match &options.program {
+            Some(program) => program.clone(),
+            None => crate::sdk::locate_emulator()
+                .map(Into::into)
+                .unwrap_or_else(|| "emulator".into()),
+        };
+        let child = Command::new(program)
+            .args(options.args(&self.name, port))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let serial = format!("{}{}", SERIAL_PREFIX, port);
+        // Dropping the emulator on errors kills the process.
+        let mut emulator = RunningEmulator {
+            device: server.device(&serial),
+            port,
+            child: Some(child),
+        };
+        let clock = &server.clock;
+        let start = clock.now();
+        loop {
+            if let Some(status) = emulator.child_mut().try_wait()? {
+                return Err(AdbError::Server {
+                    message: format!(
+                        "emulator {} exited with {} before booting",
+                        self.name, status
+                    ),
+                });
+            }
+            let online = server.devices()?.iter().any(|device| {
+                device.serial() == Some(serial.as_str())
+                    && device.state() == Some(DeviceState::Device)
+            });
+            if online {
+                return Ok(emulator);
+            }
+            if clock.now() - start >= options.boot_timeout {
+                return Err(AdbError::Timeout {
+                    condition: format!("{} is online", serial),
+                    timeout: options.boot_timeout,
+                });
+            }
+            clock.sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Lists the AVDs described by the `*.ini` files of `dir`.
+fn list_in(dir: &Path) -> io::Result<Vec<Avd>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut avds = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .map_or(true, |extension| extension != "ini")
+        {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        avds.push(parse_ini(name, dir, &fs::read_to_string(&path)?));
+    }
+    avds.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(avds)
+}
+
+/// Parses the `<name>.ini` file of the AVD `name` in `dir`, made of `key=value` lines.
+///
+/// The directory of the AVD is `path`, or `path.rel` relative to the parent of `dir`, or
+/// `<name>.avd` in `dir`.
+fn parse_ini(name: &str, dir: &Path, ini: &str) -> Avd {
+    let value = |key: &str| {
+        ini.lines().find_map(|line| {
+            let (k, v) = line.split_once('=')?;
+            (k.trim() == key).then(|| v.trim().to_string())
+        })
+    };
+    let path = match (value("path"), value("path.rel")) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, Some(rel)) => dir.parent().unwrap_or(dir).join(rel),
+        (None, None) => dir.join(format!("{}.avd", name)),
+    };
+    Avd {
+        name: name.to_string(),
+        path,
+        target: value("target"),
+    }
+}
+
+/// Returns the first console port of [`CONSOLE_PORTS`] free for an emulator: not used by a
+/// device of `server`, and with both it and the adb port free on `localhost`.
+fn free_port(server: &AdbServer) -> Result<u16, AdbError> {
+    let devices = server.devices()?;
+    let used: Vec<&str> = devices.iter().filter_map(Device::serial).collect();
+    CONSOLE_PORTS
+        .step_by(2)
+        .find(|&port| {
+            !used.contains(&format!("{}{}", SERIAL_PREFIX, port).as_str())
+                && TcpListener::bind((Ipv4Addr::LOCALHOST, port)).is_ok()
+                && TcpListener::bind((Ipv4Addr::LOCALHOST, port + 1)).is_ok()
+        })
+        .ok_or_else(|| AdbError::Server {
+            message: "no console port is free for an emulator".to_string(),
+        })
+}
+
+/// The renderer of the emulator (`-gpu`).
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum GpuMode {
+    /// Lets the emulator choose.
+    Auto,
+    /// The GPU of the host.
+    Host,
+    /// The SwiftShader software renderer, working on hosts without a GPU, e.g. CI runners.
+    SwiftshaderIndirect,
+    /// The ANGLE renderer, on Windows.
+    AngleIndirect,
+    /// Software rendering in the guest.
+    Guest,
+}
+
+impl Display for GpuMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Host => "host",
+            Self::SwiftshaderIndirect => "swiftshader_indirect",
+            Self::AngleIndirect => "angle_indirect",
+            Self::Guest => "guest",
+        })
+    }
+}
+
+/// How [`Avd::boot`] runs the emulator.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::avd::EmulatorOptions;
+///
+/// let options = EmulatorOptions::new()
+///     .port(5580)
+///     .wipe_data(true)
+///     .arg("-camera-back")
+///     .arg("none")
+///     .boot_timeout(Duration::from_secs(600));
+/// ```
+#[derive(Clone, Debug)]
+pub struct EmulatorOptions {
+    program: Option<OsString>,
+    port: Option<u16>,
+    cold_boot: bool,
+    gpu: Option<GpuMode>,
+    window: bool,
+    audio: bool,
+    wipe_data: bool,
+    args: Vec<String>,
+    boot_timeout: Duration,
+}
+
+impl EmulatorOptions {
+    /// Creates options running the emulator of the SDK without window nor audio, on the
+    /// first free port, and waiting 5 minutes for it to come online.
+    pub fn new() -> Self {
+        Self {
+            program: None,
+            port: None,
+            cold_boot: false,
+            gpu: None,
+            window: false,
+            audio: false,
+            wipe_data: false,
+            args: Vec::new(),
+            boot_timeout: Duration::from_secs(300),
+        }
+    }
+
+    /// Sets the path of the `emulator` executable, searched in `PATH` then in the
+    /// `emulator` directory of the SDK by default.
+    pub fn program(mut self, program: impl Into<OsString>) -> Self {
+        self.program = Some(program.into());
+        self
+    }
+
+    /// Sets the console port, even and between 5554 and 5682, making the serial number
+    /// `emulator-<port>` (`-port`).
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Boots from scratch instead of the quick-boot snapshot, without saving one on exit
+    /// (`-no-snapshot`).
+    pub fn cold_boot(mut self, cold_boot: bool) -> Self {
+        self.cold_boot = cold_boot;
+        self
+    }
+
+    /// Sets the renderer (`-gpu`).
+    pub fn gpu(mut self, gpu: GpuMode) -> Self {
+        self.gpu = Some(gpu);
+        self
+    }
+
+    /// Shows the window of the emulator, headless (`-no-window`) by default.
+    pub fn window(mut self, window: bool) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Enables audio, disabled (`-no-audio`) by default.
+    pub fn audio(mut self, audio: bool) -> Self {
+        self.audio = audio;
+        self
+    }
+
+    /// Resets the user data of the AVD (`-wipe-data`).
+    pub fn wipe_data(mut self, wipe_data: bool) -> Self {
+        self.wipe_data = wipe_data;
+        self
+    }
+
+    /// Appends an argument of the emulator.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Sets how long to wait for the emulator to come online.
+    pub fn boot_timeout(mut self, timeout: Duration) -> Self {
+        self.boot_timeout = timeout;
+        self
+    }
+
+    /// Returns the console port, or `None` for the first free one.
+    pub fn get_port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// Returns how long to wait for the emulator to come online.
+    pub fn get_boot_timeout(&self) -> Duration {
+        self.boot_timeout
+    }
+
+    /// Returns the arguments running the AVD `name` on `port`.
+    fn args(&self, name: &str, port: u16) -> Vec<String> {
+        let mut args = vec![
+            "-avd".to_string(),
+            name.to_string(),
+            "-port".to_string(),
+            port.to_string(),
+        ];
+        if !self.window {
+            args.push("-no-window".to_string());
+        }
+        if !self.audio {
+            args.push("-no-audio".to_string());
+        }
+        if self.cold_boot {
+            args.push("-no-snapshot".to_string());
+        }
+        if self.wipe_data {
+            args.push("-wipe-data".to_string());
+        }
+        if let Some(gpu) = self.gpu {
+            args.extend(["-gpu".to_string(), gpu.to_string()]);
+        }
+        args.extend(self.args.iter().cloned());
+        args
+    }
+}
+
+impl Default for EmulatorOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An emulator booted by [`Avd::boot`], killed when dropped.
+#[derive(Debug)]
+pub struct RunningEmulator {
+    device: Device,
+    port: u16,
+    child: Option<Child>,
+}
+
+impl RunningEmulator {
+    /// Returns the device of the emulator.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Returns the console port of the emulator.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Shuts the emulator down with the console `kill` command, and waits for it to exit,
+    /// killing the process if it doesn't.
+    pub fn kill(mut self) -> Result<(), AdbError> {
+        let asked = EmulatorConsole::connect(self.port).and_then(|mut console| {
+            // The emulator exits without answering.
+            match console.command("kill") {
+                Err(AdbError::Io(_)) => Ok(()),
+                result => result.map(drop),
+            }
+        });
+        let clock = self.device.server().clock.clone();
+        let start = clock.now();
+        if asked.is_ok() {
+            while clock.now() - start < KILL_TIMEOUT {
+                if self.child_mut().try_wait()?.is_some() {
+                    self.child = None;
+                    return Ok(());
+                }
+                clock.sleep(Duration::from_millis(200));
+            }
+        }
+        let mut child = self.child.take().expect("the emulator was already reaped");
+        // Fails if the emulator exited in the meantime, which `wait` reports.
+        let _ = child.kill();
+        child.wait()?;
+        Ok(())
+    }
+
+    fn child_mut(&mut self) -> &mut Child {
+        self.child
+            .as_mut()
+            .expect("the emulator was already reaped")
+    }
+}
+
+impl Drop for RunningEmulator {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ini() {
+        let dir = Path::new("/home/user/.android/avd");
+        let avd = parse_ini(
+            "Pixel_7",
+            dir,
+            "avd.ini.encoding=UTF-8\npath=/data/avd/Pixel_7.avd\npath.rel=avd/Pixel_7.avd\ntarget=android-34\n",
+        );
+        assert_eq!("Pixel_7", avd.name());
+        assert_eq!(Path::new("/data/avd/Pixel_7.avd"), avd.path());
+        assert_eq!(Some("android-34"), avd.target());
+        let avd = parse_ini("Tablet", dir, "path.rel = avd/Tablet.avd\n");
+        assert_eq!(Path::new("/home/user/.android/avd/Tablet.avd"), avd.path());
+        assert_eq!(None, avd.target());
+        assert_eq!(dir.join("Empty.avd"), parse_ini("Empty", dir, "").path());
+    }
+
+    #[test]
+    fn test_list_in() {
+        let dir = std::env::temp_dir().join(format!("adb-avd-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("b.avd")).unwrap();
+        fs::write(dir.join("b.ini"), "target=android-33\n").unwrap();
+        fs::write(dir.join("a.ini"), "target=android-34\n").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let avds = list_in(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<_> = avds.iter().map(Avd::name).collect();
+        assert_eq!(["a", "b"], &names[..]);
+        assert_eq!(Some("android-33"), avds[1].target());
+        assert!(list_in(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_emulator_args() {
+        assert_eq!(
+            [
+                "-avd",
+                "Pixel_7",
+                "-port",
+                "5556",
+                "-no-window",
+                "-no-audio"
+            ],
+            &EmulatorOptions::new().args("Pixel_7", 5556)[..]
+        );
+        let options = EmulatorOptions::new()
+            .window(true)
+            .cold_boot(true)
+            .wipe_data(true)
+            .gpu(GpuMode::SwiftshaderIndirect)
+            .arg("-memory")
+            .arg("2048");
+        assert_eq!(
+            [
+                "-avd",
+                "Pixel_7",
+                "-port",
+                "5580",
+                "-no-audio",
+                "-no-snapshot",
+                "-wipe-data",
+                "-gpu",
+                "swiftshader_indirect",
+                "-memory",
+                "2048"
+            ],
+            &options.args("Pixel_7", 5580)[..]
+        );
+    }
+}
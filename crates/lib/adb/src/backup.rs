@@ -0,0 +1,355 @@
+//! This module provides the backup services of adbd: `backup:` streams an Android backup
+//! (`.ab`) of the device, and `restore:` restores one.
+//!
+//! An Android backup starts with a header of text lines, `ANDROID BACKUP`, the format
+//! version, `1` if the payload is compressed with zlib and the encryption (`none` or
+//! `AES-256` followed by its parameters), see [`BackupHeader`]. The payload is a tar
+//! archive, so an uncompressed and unencrypted backup is a tar after its header.
+//!
+//! Both services are deprecated since Android 12, where most applications opt out of them.
+
+use std::io::{self, BufRead, Read, Write};
+use std::net::Shutdown;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::server::ServerStream;
+
+/// The first line of an Android backup.
+const MAGIC: &str = "ANDROID BACKUP";
+
+/// The longest header line accepted, way longer than the hex of a key blob.
+const MAX_LINE_LENGTH: u64 = 1024;
+
+/// What [`Device::backup`] includes in the backup.
+///
+/// # Examples
+///
+/// ```
+/// use adb::backup::BackupOptions;
+///
+/// // Back up the data and the APKs of two applications.
+/// let options = BackupOptions::new()
+///     .apk(true)
+///     .package("com.example.app")
+///     .package("com.example.other");
+/// assert_eq!(
+///     vec!["-apk", "-noobb", "-noshared", "-system", "com.example.app", "com.example.other"],
+///     options.args()
+/// );
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BackupOptions {
+    apk: bool,
+    obb: bool,
+    shared: bool,
+    all: bool,
+    system: bool,
+    packages: Vec<String>,
+}
+
+impl BackupOptions {
+    /// Creates options backing up the data of the given packages, including system
+    /// applications with [`Self::all`], like `adb backup`.
+    pub fn new() -> Self {
+        Self {
+            apk: false,
+            obb: false,
+            shared: false,
+            all: false,
+            system: true,
+            packages: Vec::new(),
+        }
+    }
+
+    /// Sets whether to include the APKs of the applications (`-apk`).
+    pub fn apk(mut self, apk: bool) -> Self {
+        self.apk = apk;
+        self
+    }
+
+    /// Sets whether to include the expansion files of the applications (`-obb`).
+    pub fn obb(mut self, obb: bool) -> Self {
+        self.obb = obb;
+        self
+    }
+
+    /// Sets whether to include the shared storage (`-shared`).
+    pub fn shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Sets whether to include all the installed applications (`-all`).
+    pub fn all(mut self, all: bool) -> Self {
+        self.all = all;
+        self
+    }
+
+    /// Sets whether [`Self::all`] includes system applications (`-system`).
+    pub fn system(mut self, system: bool) -> Self {
+        self.system = system;
+        self
+    }
+
+    /// Adds a package to include.
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.packages.push(package.into());
+        self
+    }
+
+    /// Returns the packages to include.
+    pub fn get_packages(&self) -> &[String] {
+        &self.packages
+    }
+
+    /// Returns the arguments of `bu backup` on the device.
+    pub fn args(&self) -> Vec<String> {
+        let flag =
+            |enabled: bool, name: &str| format!("-{}{}", if enabled { "" } else { "no" }, name);
+        let mut args = vec![
+            flag(self.apk, "apk"),
+            flag(self.obb, "obb"),
+            flag(self.shared, "shared"),
+        ];
+        if self.all {
+            args.push("-all".to_string());
+        }
+        args.push(flag(self.system, "system"));
+        args.extend(self.packages.iter().cloned());
+        args
+    }
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The encryption of an Android backup.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum BackupEncryption {
+    None,
+    /// AES-256 with a key derived from the password of the user with PBKDF2, the salts, IV
+    /// and key blob being hex strings.
+    Aes256 {
+        user_salt: String,
+        checksum_salt: String,
+        rounds: u32,
+        user_iv: String,
+        master_key_blob: String,
+    },
+}
+
+/// The header of an Android backup, followed by its payload.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BackupHeader {
+    /// The version of the format, 5 on recent devices.
+    pub version: u32,
+    /// Whether the payload is compressed with zlib.
+    pub compressed: bool,
+    pub encryption: BackupEncryption,
+}
+
+impl BackupHeader {
+    /// Reads the header of the backup in `reader`, leaving it at the start of the payload.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use adb::backup::BackupHeader;
+    ///
+    /// let mut backup = &b"ANDROID BACKUP\n5\n0\nnone\nustar..."[..];
+    /// let header = BackupHeader::read(&mut backup).unwrap();
+    /// assert!(header.is_plain_tar());
+    /// assert_eq!(b"ustar...", backup);
+    /// ```
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self, AdbError> {
+        let mut line = || read_line(reader);
+        let magic = line()?;
+        if magic != MAGIC {
+            return Err(parse_error(magic));
+        }
+        let version = line()?;
+        let version = version.parse().map_err(|_| parse_error(version))?;
+        let compressed = match line()?.as_str() {
+            "0" => false,
+            "1" => true,
+            s => return Err(parse_error(s.to_string())),
+        };
+        let encryption = match line()?.as_str() {
+            "none" => BackupEncryption::None,
+            "AES-256" => {
+                let user_salt = line()?;
+                let checksum_salt = line()?;
+                let rounds = line()?;
+                BackupEncryption::Aes256 {
+                    user_salt,
+                    checksum_salt,
+                    rounds: rounds.parse().map_err(|_| parse_error(rounds))?,
+                    user_iv: line()?,
+                    master_key_blob: line()?,
+                }
+            }
+            s => return Err(parse_error(s.to_string())),
+        };
+        Ok(Self {
+            version,
+            compressed,
+            encryption,
+        })
+    }
+
+    /// Writes the header, e.g. to turn a tar archive into a backup to restore.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "{}\n{}\n{}\n",
+            MAGIC,
+            self.version,
+            u8::from(self.compressed)
+        )?;
+        match &self.encryption {
+            BackupEncryption::None => writer.write_all(b"none\n"),
+            BackupEncryption::Aes256 {
+                user_salt,
+                checksum_salt,
+                rounds,
+                user_iv,
+                master_key_blob,
+            } => write!(
+                writer,
+                "AES-256\n{}\n{}\n{}\n{}\n{}\n",
+                user_salt, checksum_salt, rounds, user_iv, master_key_blob
+            ),
+        }
+    }
+
+    /// Returns `true` if the payload is a plain tar archive, neither compressed nor
+    /// encrypted.
+    pub fn is_plain_tar(&self) -> bool {
+        !self.compressed && self.encryption == BackupEncryption::None
+    }
+}
+
+fn parse_error(value: String) -> AdbError {
+    AdbError::Parse {
+        value,
+        source_type: "&[u8]",
+        target_type: "BackupHeader",
+        source: None,
+    }
+}
+
+/// Reads a header line, without its line feed.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, AdbError> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE_LENGTH).read_until(b'\n', &mut line)?;
+    if line.pop() != Some(b'\n') {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    String::from_utf8(line).map_err(|e| AdbError::Parse {
+        value: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        source_type: "&[u8]",
+        target_type: "BackupHeader",
+        source: Some(Box::new(e)),
+    })
+}
+
+impl Device {
+    /// Backs up the device (`adb backup`), returning the stream of the backup, a header and
+    /// its payload.
+    ///
+    /// The device asks the user to confirm the backup, and optionally encrypt it, before
+    /// streaming anything.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::io;
+    /// use adb::backup::BackupOptions;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let options = BackupOptions::new().package("com.example.app");
+    /// let mut backup = device.backup(&options).unwrap();
+    /// io::copy(&mut backup, &mut File::create("app.ab").unwrap()).unwrap();
+    /// ```
+    pub fn backup(&self, options: &BackupOptions) -> Result<ServerStream, AdbError> {
+        let mut service = "backup:".to_string();
+        for arg in options.args() {
+            service.push(' ');
+            service.push_str(&arg);
+        }
+        self.open(&service)
+    }
+
+    /// Restores the backup read from `backup` (`adb restore`), returning its length.
+    ///
+    /// The device asks the user to confirm the restore, and returns once it's done.
+    pub fn restore<R: Read>(&self, mut backup: R) -> Result<u64, AdbError> {
+        let mut stream = self.open("restore:")?;
+        let length = io::copy(&mut backup, &mut stream)?;
+        stream.shutdown(Shutdown::Write)?;
+        // The device closes the connection once the backup is restored.
+        io::copy(&mut stream, &mut io::sink())?;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_options_args() {
+        assert_eq!(
+            vec!["-noapk", "-noobb", "-noshared", "-system"],
+            BackupOptions::new().args()
+        );
+        assert_eq!(
+            vec!["-apk", "-obb", "-shared", "-all", "-nosystem"],
+            BackupOptions::new()
+                .apk(true)
+                .obb(true)
+                .shared(true)
+                .all(true)
+                .system(false)
+                .args()
+        );
+    }
+
+    #[test]
+    fn test_backup_header() {
+        let header = BackupHeader {
+            version: 5,
+            compressed: true,
+            encryption: BackupEncryption::Aes256 {
+                user_salt: "00ff".to_string(),
+                checksum_salt: "ff00".to_string(),
+                rounds: 10000,
+                user_iv: "0123".to_string(),
+                master_key_blob: "4567".to_string(),
+            },
+        };
+        let mut backup = Vec::new();
+        header.write(&mut backup).unwrap();
+        backup.extend_from_slice(b"payload");
+        let mut reader = &backup[..];
+        assert_eq!(header, BackupHeader::read(&mut reader).unwrap());
+        assert!(!header.is_plain_tar());
+        assert_eq!(b"payload", reader);
+
+        for backup in [
+            &b"ANDROID BACKUP\n5\n0\n"[..],
+            b"ANDROID BACKUP\n5\n2\nnone\n",
+            b"ANDROID BACKUP\nv5\n0\nnone\n",
+            b"ANDROID BACKUP\n5\n0\nAES-128\n",
+            b"PK\x03\x04\n",
+        ] {
+            assert!(BackupHeader::read(&mut &backup[..]).is_err());
+        }
+    }
+}
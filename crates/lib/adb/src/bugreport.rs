@@ -0,0 +1,335 @@
+//! This module provides bug reports, the dumps of the state of the whole device.
+//!
+//! Devices shipping `bugreportz` (Android 7 and later) write a zip on the device, printing
+//! `BEGIN:<path>`, then `PROGRESS:<current>/<total>` lines with `-p`, and finally
+//! `OK:<path>` or `FAIL:<message>`. The zip is then pulled over the sync protocol. Older
+//! devices stream a plain text report from `bugreport`.
+//!
+//! Bug reports take minutes and weigh hundreds of megabytes, so every step reports its
+//! progress to a callback, which can cancel the report by returning
+//! [`ControlFlow::Break`].
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use crate::compat;
+use crate::device::Device;
+use crate::error::AdbError;
+
+/// The progress of a bug report, passed to the callback of [`Device::bugreport`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum BugreportProgress {
+    /// `bugreportz` started writing the zip at `path` on the device.
+    Started { path: String },
+    /// `bugreportz` generated `current` out of `total` units of work.
+    Generating { current: u64, total: u64 },
+    /// `bytes` out of the `total` bytes of the zip were pulled.
+    Pulling { bytes: u64, total: u64 },
+    /// `bytes` of the plain text report of an older device were received.
+    Streaming { bytes: u64 },
+}
+
+/// A line printed by `bugreportz -p`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+enum BugreportzLine {
+    Begin(String),
+    Progress {
+        current: u64,
+        total: u64,
+    },
+    Ok(String),
+    Fail(String),
+    /// Anything else, e.g. a warning.
+    Other,
+}
+
+impl BugreportzLine {
+    fn parse(line: &str) -> Result<Self, AdbError> {
+        // Legacy shells translate line breaks into `\r\n`.
+        let line = line.trim_end();
+        Ok(if let Some(path) = line.strip_prefix("BEGIN:") {
+            Self::Begin(path.to_string())
+        } else if let Some(progress) = line.strip_prefix("PROGRESS:") {
+            progress
+                .split_once('/')
+                .and_then(|(current, total)| {
+                    Some(Self::Progress {
+                        current: current.parse().ok()?,
+                        total: total.parse().ok()?,
+                    })
+                })
+                .ok_or_else(|| AdbError::Parse {
+                    value: line.to_string(),
+                    source_type: "&str",
+                    target_type: "BugreportzLine",
+                    source: None,
+                })?
+        } else if let Some(path) = line.strip_prefix("OK:") {
+            Self::Ok(path.to_string())
+        } else if let Some(message) = line.strip_prefix("FAIL:") {
+            Self::Fail(message.to_string())
+        } else {
+            Self::Other
+        })
+    }
+}
+
+/// The error of a bug report cancelled by its callback.
+fn cancelled() -> AdbError {
+    io::Error::new(io::ErrorKind::Interrupted, "bug report cancelled").into()
+}
+
+/// A writer reporting the number of bytes written so far, failing once `report` breaks.
+///
+/// The failure isn't [`io::ErrorKind::Interrupted`], which [`Write::write_all`] retries.
+struct ProgressWriter<W, F> {
+    writer: W,
+    bytes: u64,
+    report: F,
+    cancelled: bool,
+}
+
+impl<W, F> ProgressWriter<W, F> {
+    fn new(writer: W, report: F) -> Self {
+        Self {
+            writer,
+            bytes: 0,
+            report,
+            cancelled: false,
+        }
+    }
+
+    /// Replaces the failure of a cancelled transfer with [`cancelled`].
+    fn check<T>(&self, result: Result<T, AdbError>) -> Result<T, AdbError> {
+        if self.cancelled {
+            Err(cancelled())
+        } else {
+            result
+        }
+    }
+}
+
+impl<W: Write, F: FnMut(u64) -> ControlFlow<()>> Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.bytes += written as u64;
+        match (self.report)(self.bytes) {
+            ControlFlow::Continue(()) => Ok(written),
+            ControlFlow::Break(()) => {
+                self.cancelled = true;
+                Err(compat::io_other("bug report cancelled"))
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Returns the local path of a report named after `remote`, inside `dest` if it's a
+/// directory.
+fn local_path(dest: &Path, remote: &str) -> PathBuf {
+    if dest.is_dir() {
+        dest.join(remote.rsplit('/').next().unwrap_or(remote))
+    } else {
+        dest.to_path_buf()
+    }
+}
+
+impl Device {
+    /// Takes a bug report and writes it to `dest`, a file or a directory (`adb bugreport`),
+    /// returning the path of the written report.
+    ///
+    /// Devices with `bugreportz` produce a zip, named after the zip on the device if `dest`
+    /// is a directory. Older devices produce a plain text report, named `bugreport.txt` if
+    /// `dest` is a directory. `progress` is called as the report is generated and
+    /// transferred, and cancels the report, failing with [`io::ErrorKind::Interrupted`], by
+    /// returning [`ControlFlow::Break`]. A partial report is removed. A
+    /// [`CancellationToken`](crate::cancel::CancellationToken) cancels it from another
+    /// thread with `|_| token.control_flow()`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::ops::ControlFlow;
+    /// use std::path::Path;
+    /// use adb::bugreport::BugreportProgress;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let path = device
+    ///     .bugreport(Path::new("."), |progress| {
+    ///         if let BugreportProgress::Generating { current, total } = progress {
+    ///             println!("{}%", current * 100 / total.max(1));
+    ///         }
+    ///         ControlFlow::Continue(())
+    ///     })
+    ///     .unwrap();
+    /// println!("written to {}", path.display());
+    /// ```
+    pub fn bugreport<F>(&self, dest: &Path, mut progress: F) -> Result<PathBuf, AdbError>
+    where
+        F: FnMut(BugreportProgress) -> ControlFlow<()>,
+    {
+        if self.has_bugreportz()? {
+            self.bugreportz(dest, &mut progress)
+        } else {
+            self.bugreport_legacy(dest, &mut progress)
+        }
+    }
+
+    /// Returns `true` if the device ships `bugreportz`, which prints its version with `-v`.
+    fn has_bugreportz(&self) -> Result<bool, AdbError> {
+        let output = self.shell("bugreportz -v")?;
+        Ok(output.success()
+            && (output.stdout_lossy() + &output.stderr_lossy()).contains("bugreportz version"))
+    }
+
+    fn bugreportz(
+        &self,
+        dest: &Path,
+        progress: &mut dyn FnMut(BugreportProgress) -> ControlFlow<()>,
+    ) -> Result<PathBuf, AdbError> {
+        let mut report = |event| match progress(event) {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(()) => Err(cancelled()),
+        };
+        let mut lines = BufReader::new(self.shell_stream("bugreportz -p")?).lines();
+        let remote = loop {
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => {
+                    return Err(AdbError::Protocol {
+                        code: None,
+                        message: "`bugreportz` exited without reporting a result".to_string(),
+                    })
+                }
+            };
+            match BugreportzLine::parse(&line)? {
+                BugreportzLine::Begin(path) => report(BugreportProgress::Started { path })?,
+                BugreportzLine::Progress { current, total } => {
+                    report(BugreportProgress::Generating { current, total })?
+                }
+                BugreportzLine::Ok(path) => break path,
+                BugreportzLine::Fail(message) => return Err(AdbError::Server { message }),
+                BugreportzLine::Other => {}
+            }
+        };
+        drop(lines);
+        let local = local_path(dest, &remote);
+        let result = (|| {
+            let mut sync = self.sync()?;
+            let total = sync.stat(&remote)?.size;
+            let mut writer = ProgressWriter::new(File::create(&local)?, |bytes| {
+                progress(BugreportProgress::Pulling { bytes, total })
+            });
+            let result = sync.recv(&remote, &mut writer);
+            writer.check(result)?;
+            sync.quit()
+        })();
+        match result {
+            Ok(()) => Ok(local),
+            Err(e) => {
+                let _ = fs::remove_file(&local);
+                Err(e)
+            }
+        }
+    }
+
+    fn bugreport_legacy(
+        &self,
+        dest: &Path,
+        progress: &mut dyn FnMut(BugreportProgress) -> ControlFlow<()>,
+    ) -> Result<PathBuf, AdbError> {
+        let local = local_path(dest, "bugreport.txt");
+        let result = (|| {
+            let mut stream = self.shell_stream("bugreport")?;
+            let mut writer = ProgressWriter::new(File::create(&local)?, |bytes| {
+                progress(BugreportProgress::Streaming { bytes })
+            });
+            let result = io::copy(&mut stream, &mut writer).map_err(AdbError::from);
+            writer.check(result).map(drop)
+        })();
+        match result {
+            Ok(()) => Ok(local),
+            Err(e) => {
+                let _ = fs::remove_file(&local);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bugreportz_line_parse() {
+        let path = "/bugreports/bugreport-sdk-2024-01-01-00-00-00.zip";
+        assert_eq!(
+            BugreportzLine::Begin(path.to_string()),
+            BugreportzLine::parse(&format!("BEGIN:{}", path)).unwrap()
+        );
+        assert_eq!(
+            BugreportzLine::Progress {
+                current: 42,
+                total: 1000
+            },
+            BugreportzLine::parse("PROGRESS:42/1000\r").unwrap()
+        );
+        assert_eq!(
+            BugreportzLine::Ok(path.to_string()),
+            BugreportzLine::parse(&format!("OK:{}\r\n", path)).unwrap()
+        );
+        assert_eq!(
+            BugreportzLine::Fail("dumpstate failed".to_string()),
+            BugreportzLine::parse("FAIL:dumpstate failed").unwrap()
+        );
+        assert_eq!(
+            BugreportzLine::Other,
+            BugreportzLine::parse("WARNING: low storage").unwrap()
+        );
+        for s in ["PROGRESS:42", "PROGRESS:a/1000", "PROGRESS:42/-1"] {
+            assert!(BugreportzLine::parse(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_progress_writer() {
+        let mut reported = Vec::new();
+        let mut writer = ProgressWriter::new(Vec::new(), |bytes| {
+            reported.push(bytes);
+            if bytes < 6 {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        });
+        writer.write_all(b"abc").unwrap();
+        let result = writer.write_all(b"def").map_err(AdbError::from);
+        let Err(AdbError::Io(e)) = writer.check(result) else {
+            panic!("not cancelled");
+        };
+        assert_eq!(io::ErrorKind::Interrupted, e.kind());
+        assert_eq!(b"abcdef", &writer.writer[..]);
+        drop(writer);
+        assert_eq!(vec![3, 6], reported);
+    }
+
+    #[test]
+    fn test_local_path() {
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            dir.join("bugreport.zip"),
+            local_path(&dir, "/bugreports/bugreport.zip")
+        );
+        assert_eq!(
+            Path::new("report.zip"),
+            local_path(Path::new("report.zip"), "/bugreports/bugreport.zip")
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! This module provides helpers for camera tests on emulators.
+//!
+//! The back camera of an AVD can show a virtual scene, a 3D room whose posters display
+//! images set through the emulator console (`virtualscene-image`), or the webcam of the
+//! host. The sources are chosen when the emulator starts, with [`camera_args`].
+//!
+//! [`Device::take_photo_via_intent`] drives the camera app of the device: it opens it in
+//! still image mode (`android.media.action.STILL_IMAGE_CAMERA`), presses the shutter
+//! (`KEYCODE_CAMERA`) and pulls the new photo from [`CAMERA_DIR`].
+
+#[cfg(feature = "sync")]
+use std::cell::RefCell;
+#[cfg(feature = "sync")]
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::device::Device;
+use crate::emulator::EmulatorConsole;
+use crate::error::AdbError;
+#[cfg(feature = "sync")]
+use crate::wait::PollOptions;
+
+/// The directory the camera app saves photos to.
+pub const CAMERA_DIR: &str = "/sdcard/DCIM/Camera";
+
+/// What an emulated camera shows, the values of `-camera-back` and `-camera-front`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub enum CameraSource {
+    /// No camera.
+    None,
+    /// A moving checkerboard.
+    #[default]
+    Emulated,
+    /// A 3D room navigated with the sensors, for the back camera only.
+    VirtualScene,
+    /// The webcam of the host with the given index.
+    Webcam(u32),
+}
+
+impl Display for CameraSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => f.write_str("none"),
+            Self::Emulated => f.write_str("emulated"),
+            Self::VirtualScene => f.write_str("virtualscene"),
+            Self::Webcam(index) => write!(f, "webcam{}", index),
+        }
+    }
+}
+
+/// Returns the arguments of the `emulator` command selecting the camera sources.
+pub fn camera_args(back: CameraSource, front: CameraSource) -> [String; 4] {
+    [
+        "-camera-back".to_string(),
+        back.to_string(),
+        "-camera-front".to_string(),
+        front.to_string(),
+    ]
+}
+
+/// A poster of the virtual scene.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ScenePoster {
+    /// The poster on the wall.
+    Wall,
+    /// The poster on the table.
+    Table,
+}
+
+impl Display for ScenePoster {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Wall => "wall",
+            Self::Table => "table",
+        })
+    }
+}
+
+impl EmulatorConsole {
+    /// Displays the image `path` of the host, a PNG or JPEG file, on a poster of the
+    /// virtual scene (`virtualscene-image`).
+    pub fn set_scene_image(&mut self, poster: ScenePoster, path: &Path) -> Result<(), AdbError> {
+        let path = path.canonicalize()?;
+        self.command(&format!("virtualscene-image {} {}", poster, path.display()))
+            .map(drop)
+    }
+}
+
+/// A photo taken by [`Device::take_photo_via_intent`].
+#[cfg(feature = "sync")]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Photo {
+    /// The path of the photo on the device.
+    pub path: String,
+    /// The content of the JPEG file.
+    pub jpeg: Vec<u8>,
+}
+
+/// Parses the photos listed by `ls -1`, skipping the hidden files of pending writes.
+#[cfg(feature = "sync")]
+fn parse_photos(list: &str) -> BTreeSet<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|name| !name.starts_with('.'))
+        .filter(|name| {
+            let name = name.to_ascii_lowercase();
+            name.ends_with(".jpg") || name.ends_with(".jpeg")
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+impl Device {
+    /// Displays the image `path` of the host on a poster of the virtual scene of the
+    /// emulator.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use adb::camera::ScenePoster;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().device("emulator-5554");
+    /// device
+    ///     .set_scene_image(ScenePoster::Wall, Path::new("qr_code.png"))
+    ///     .unwrap();
+    /// ```
+    pub fn set_scene_image(&self, poster: ScenePoster, path: &Path) -> Result<(), AdbError> {
+        let mut console = self.emulator_console()?.ok_or_else(|| AdbError::Server {
+            message: "virtual scene images require an emulator".to_string(),
+        })?;
+        console.set_scene_image(poster, path)
+    }
+
+    /// Returns the photos in [`CAMERA_DIR`].
+    #[cfg(feature = "sync")]
+    fn photos(&self) -> Result<BTreeSet<String>, AdbError> {
+        // `ls` fails until the camera app creates the directory.
+        let output = self.shell(&format!("ls -1 {}", CAMERA_DIR))?;
+        Ok(parse_photos(&output.stdout_lossy()))
+    }
+
+    /// Takes a photo with the camera app and returns it, failing with
+    /// [`AdbError::Timeout`] if no photo is saved before the timeout of `options`.
+    ///
+    /// The screen must be on and unlocked.
+    #[cfg(feature = "sync")]
+    pub fn take_photo_via_intent(&self, options: &PollOptions) -> Result<Photo, AdbError> {
+        let before = self.photos()?;
+        self.shell_checked("am start -W -a android.media.action.STILL_IMAGE_CAMERA")?;
+        self.shell_checked("input keyevent KEYCODE_CAMERA")?;
+        let taken = RefCell::new(None);
+        let saved = |device: &Device| {
+            let name = device.photos()?.difference(&before).next().cloned();
+            let found = name.is_some();
+            *taken.borrow_mut() = name;
+            Ok(found)
+        };
+        self.wait_until(&saved, options)?;
+        let path = format!("{}/{}", CAMERA_DIR, taken.into_inner().unwrap_or_default());
+        let mut jpeg = Vec::new();
+        let mut sync = self.sync()?;
+        sync.recv(&path, &mut jpeg)?;
+        sync.quit()?;
+        Ok(Photo { path, jpeg })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera_args() {
+        assert_eq!(
+            ["-camera-back", "virtualscene", "-camera-front", "webcam0"],
+            camera_args(CameraSource::VirtualScene, CameraSource::Webcam(0))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sync")]
+    fn test_parse_photos() {
+        let list = "IMG_20240101_120000.jpg\n.pending-1704110400-IMG_20240101_120001.jpg\n\
+                    VID_20240101_120002.mp4\nPXL_20240101_120003.JPG\n";
+        assert_eq!(
+            ["IMG_20240101_120000.jpg", "PXL_20240101_120003.JPG"],
+            &parse_photos(list).into_iter().collect::<Vec<_>>()[..]
+        );
+    }
+}
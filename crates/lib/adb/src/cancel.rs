@@ -0,0 +1,216 @@
+//! This module provides [`CancellationToken`], to stop long-running operations from another
+//! thread.
+//!
+//! A blocking read on a socket doesn't return until data arrives, so a token doesn't only set
+//! a flag: it also shuts down the connections registered on it, which wakes the threads
+//! blocked on them. The operation then fails with [`AdbError::Cancelled`], instead of the I/O
+//! error of the closed connection.
+//!
+//! Handles of long-running services register their connection with `cancel_on`:
+//! [`SyncConnection`](crate::sync::SyncConnection), [`LogReader`](crate::logcat::LogReader),
+//! [`DeviceTracker`](crate::track::DeviceTracker) and `ScreenRecording`. Bug reports are
+//! cancelled from their progress callback, see [`CancellationToken::control_flow`]. Async
+//! operations are cancelled by dropping their future, e.g. in `tokio::select!`.
+
+use std::fmt::{self, Debug, Formatter};
+use std::net::Shutdown;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::error::AdbError;
+use crate::server::ServerStream;
+
+/// A flag shared by the threads running operations and the threads cancelling them.
+///
+/// Clones share the same flag. Once cancelled, a token stays cancelled.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use std::thread;
+/// use std::time::Duration;
+/// use adb::cancel::CancellationToken;
+/// use adb::error::AdbError;
+/// use adb::server::AdbServer;
+///
+/// let device = AdbServer::default().any_device();
+/// let token = CancellationToken::new();
+/// let canceller = token.clone();
+/// thread::spawn(move || {
+///     thread::sleep(Duration::from_secs(10));
+///     canceller.cancel();
+/// });
+/// let result = device.pull_cancellable("/sdcard/big.bin", Path::new("big.bin"), &token);
+/// assert!(matches!(result, Err(AdbError::Cancelled)));
+/// ```
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    next_id: AtomicU64,
+    /// Clones of the registered connections, shut down on cancellation.
+    streams: Mutex<Vec<(u64, ServerStream)>>,
+}
+
+impl CancellationToken {
+    /// Creates a token which isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operations of the token, shutting down their connections.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        let streams = self.inner.streams.lock().unwrap_or_else(|e| e.into_inner());
+        for (_, stream) in streams.iter() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// Returns `true` once the token is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns [`ControlFlow::Break`] once the token is cancelled, for progress callbacks,
+    /// e.g. of [`Device::bugreport`](crate::device::Device::bugreport).
+    pub fn control_flow(&self) -> ControlFlow<()> {
+        if self.is_cancelled() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    /// Fails with [`AdbError::Cancelled`] once the token is cancelled.
+    #[cfg(feature = "sync")]
+    pub(crate) fn check(&self) -> Result<(), AdbError> {
+        if self.is_cancelled() {
+            Err(AdbError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Replaces the error of an operation failing after the token was cancelled, usually the
+    /// I/O error of its closed connection, with [`AdbError::Cancelled`].
+    pub(crate) fn map_result<T>(&self, result: Result<T, AdbError>) -> Result<T, AdbError> {
+        match result {
+            Err(_) if self.is_cancelled() => Err(AdbError::Cancelled),
+            result => result,
+        }
+    }
+
+    /// Registers `stream` to be shut down on cancellation, until the guard is dropped.
+    ///
+    /// Shuts it down at once, and fails with [`AdbError::Cancelled`], if the token is
+    /// already cancelled.
+    pub(crate) fn register(&self, stream: &ServerStream) -> Result<CancelGuard, AdbError> {
+        let clone = stream.try_clone()?;
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut streams = self.inner.streams.lock().unwrap_or_else(|e| e.into_inner());
+        // Checked under the lock, so that a concurrent cancellation shuts it down either way.
+        if self.is_cancelled() {
+            let _ = clone.shutdown(Shutdown::Both);
+            return Err(AdbError::Cancelled);
+        }
+        streams.push((id, clone));
+        Ok(CancelGuard {
+            token: self.clone(),
+            id,
+        })
+    }
+}
+
+impl Debug for CancellationToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// A connection registered on a [`CancellationToken`], unregistered when dropped.
+#[derive(Debug)]
+pub(crate) struct CancelGuard {
+    token: CancellationToken,
+    id: u64,
+}
+
+impl CancelGuard {
+    /// Returns the token the connection is registered on.
+    #[cfg(any(feature = "sync", feature = "logcat"))]
+    pub(crate) fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        let mut streams = self
+            .token
+            .inner
+            .streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        streams.retain(|(id, _)| *id != self.id);
+    }
+}
+
+/// Maps the result of an operation on a connection guarded by `guard`, if any, see
+/// [`CancellationToken::map_result`].
+#[cfg(any(feature = "sync", feature = "logcat"))]
+pub(crate) fn map_result<T>(
+    guard: &Option<CancelGuard>,
+    result: Result<T, AdbError>,
+) -> Result<T, AdbError> {
+    match guard {
+        Some(guard) => guard.token().map_result(result),
+        None => result,
+    }
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod tests {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_cancel_wakes_blocked_read() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut stream = ServerStream::Tcp(TcpStream::connect(addr).unwrap());
+        // The accepted connection is kept open and silent.
+        let _peer = listener.accept().unwrap();
+
+        let token = CancellationToken::new();
+        let guard = token.register(&stream).unwrap();
+        assert!(token.control_flow().is_continue());
+        let canceller = token.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+        // The shut down connection reads as an early end, like in the protocol readers.
+        let result = stream.read_exact(&mut [0; 16]).map_err(AdbError::from);
+        handle.join().unwrap();
+        assert!(matches!(token.map_result(result), Err(AdbError::Cancelled)));
+        assert!(token.control_flow().is_break());
+        assert!(map_result(&Some(guard), Ok(())).is_ok());
+        assert!(token.inner.streams.lock().unwrap().is_empty());
+
+        // Registering on a cancelled token shuts the connection down at once.
+        assert!(matches!(token.register(&stream), Err(AdbError::Cancelled)));
+        assert!(matches!(token.check(), Err(AdbError::Cancelled)));
+    }
+}
@@ -0,0 +1,356 @@
+//! This module installs CA certificates, e.g. the CA of an intercepting proxy for HTTPS
+//! tests.
+//!
+//! The system store is `/system/etc/security/cacerts`, whose files are named after the
+//! OpenSSL `subject_hash_old` of the certificate, the first 4 bytes of the MD5 digest of the
+//! DER encoded subject read as a little-endian integer, followed by `.0` (`31c5d9ae.0`).
+//! Writing it requires adbd running as root and remounted system partitions. Android 14
+//! and later read the store from the Conscrypt APEX instead, which can't be remounted.
+//!
+//! Apps only trust the user store if their network security config opts in, and Android
+//! only installs user CA certificates after the user confirms in Settings, so installing
+//! one pushes the certificate to the device and opens the installer.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::adbd::RemountResult;
+use crate::am::Intent;
+use crate::base64;
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// The directory of the system CA store.
+const SYSTEM_CACERTS: &str = "/system/etc/security/cacerts";
+/// The directory user CA certificates are pushed to before the user installs them.
+const USER_CACERTS: &str = "/sdcard/Download";
+/// The SDK version of Android 14, which moved the system store to the Conscrypt APEX.
+const APEX_CACERTS_SDK: u32 = 34;
+/// The SDK version of Android 11, which stopped installing CA certificates from intents.
+const SETTINGS_ONLY_SDK: u32 = 30;
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+/// The CA store to install a certificate into.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum CaStore {
+    /// The user store, trusted by apps opting in. Installing needs the user to confirm.
+    User,
+    /// The system store, trusted by all apps. Installing needs adbd running as root.
+    System,
+}
+
+/// The outcome of [`Device::install_ca_certificate`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum CaInstallResult {
+    /// The certificate was written to the system store at the path.
+    Installed(String),
+    /// The certificate was pushed to the path and the installer was opened, waiting for the
+    /// user to confirm.
+    ConfirmationRequired(String),
+}
+
+/// An X.509 certificate, in DER.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct CaCertificate {
+    der: Vec<u8>,
+}
+
+/// Returns a parse error of the certificate `value`.
+fn parse_error(value: &str, source_type: &'static str) -> AdbError {
+    AdbError::Parse {
+        value: value.to_string(),
+        source_type,
+        target_type: "CaCertificate",
+        source: None,
+    }
+}
+
+impl CaCertificate {
+    /// Creates a certificate from its DER encoding, failing if it has no subject.
+    pub fn from_der(der: Vec<u8>) -> Result<Self, AdbError> {
+        let certificate = Self { der };
+        match certificate.subject() {
+            Some(_) => Ok(certificate),
+            None => Err(parse_error(&format!("{:02x?}", certificate.der), "&[u8]")),
+        }
+    }
+
+    /// Returns the DER encoding of the certificate.
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// Returns the DER encoded subject of the certificate.
+    fn subject(&self) -> Option<&[u8]> {
+        let (certificate, _) = der_element(&self.der)?;
+        let (tbs, _) = der_element(der_content(certificate)?)?;
+        let mut rest = der_content(tbs)?;
+        // The optional version is tagged `[0]`.
+        if rest.first() == Some(&0xa0) {
+            rest = der_element(rest)?.1;
+        }
+        // The serial number, the signature algorithm, the issuer and the validity come first.
+        for _ in 0..4 {
+            rest = der_element(rest)?.1;
+        }
+        let (subject, _) = der_element(rest)?;
+        Some(subject)
+    }
+
+    /// Returns the OpenSSL `subject_hash_old` of the certificate.
+    pub fn subject_hash_old(&self) -> u32 {
+        let digest = md5(self.subject().expect("checked by from_der"));
+        u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]])
+    }
+
+    /// Returns the file name of the certificate in the system store, e.g. `31c5d9ae.0`.
+    pub fn file_name(&self) -> String {
+        format!("{:08x}.0", self.subject_hash_old())
+    }
+
+    /// Returns the PEM encoding of the certificate.
+    pub fn to_pem(&self) -> String {
+        let encoded = base64::encode(&self.der);
+        let mut pem = format!("{}\n", PEM_BEGIN);
+        for line in encoded.as_bytes().chunks(64) {
+            pem.push_str(std::str::from_utf8(line).unwrap());
+            pem.push('\n');
+        }
+        pem.push_str(PEM_END);
+        pem.push('\n');
+        pem
+    }
+}
+
+impl Display for CaCertificate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_pem())
+    }
+}
+
+impl FromStr for CaCertificate {
+    type Err = AdbError;
+
+    /// Parses the first PEM encoded certificate of `s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let encoded = s
+            .split_once(PEM_BEGIN)
+            .and_then(|(_, rest)| rest.split_once(PEM_END))
+            .map(|(encoded, _)| encoded)
+            .ok_or_else(|| parse_error(s, "&str"))?;
+        let der = base64::decode(encoded).ok_or_else(|| parse_error(s, "&str"))?;
+        Self::from_der(der).map_err(|_| parse_error(s, "&str"))
+    }
+}
+
+/// Splits the DER element at the start of `der` from the rest.
+fn der_element(der: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (header, length) = match *der.get(1)? {
+        length @ 0..=0x7f => (2, length as usize),
+        0x81..=0x84 => {
+            let size = (der[1] & 0x7f) as usize;
+            let bytes = der.get(2..2 + size)?;
+            let length = bytes.iter().fold(0, |length, &b| length << 8 | b as usize);
+            (2 + size, length)
+        }
+        _ => return None,
+    };
+    let end = header.checked_add(length)?;
+    (der.len() >= end).then(|| der.split_at(end))
+}
+
+/// Returns the content of the DER element `element`, without its tag and length.
+fn der_content(element: &[u8]) -> Option<&[u8]> {
+    let header = match element.get(1)? {
+        0..=0x7f => 2,
+        length => 2 + (length & 0x7f) as usize,
+    };
+    element.get(header..)
+}
+
+/// Returns the MD5 digest of `data`, as in RFC 1321.
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let constants: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32)
+        .collect();
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64).wrapping_mul(8).to_le_bytes());
+    for block in message.chunks(64) {
+        let words: Vec<u32> = block
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(constants[i])
+                .wrapping_add(words[g])
+                .rotate_left(SHIFTS[i / 16 * 4 + i % 4]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (state, value) in state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut digest = [0; 16];
+    for (bytes, word) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+impl Device {
+    /// Installs the CA certificate `certificate` into `store`.
+    ///
+    /// The system store needs adbd running as root, see [`Device::root`], and fails if
+    /// remounting the system partitions needs a reboot first, or on Android 14 and later.
+    /// The user store pushes the certificate to `/sdcard/Download` and opens the installer,
+    /// or the security settings on Android 11 and later, where the user picks the file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::cert::{CaCertificate, CaStore};
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let pem = std::fs::read_to_string("mitmproxy-ca-cert.pem").unwrap();
+    /// let certificate: CaCertificate = pem.parse().unwrap();
+    /// device.root().unwrap();
+    /// device
+    ///     .install_ca_certificate(&certificate, CaStore::System)
+    ///     .unwrap();
+    /// ```
+    pub fn install_ca_certificate(
+        &self,
+        certificate: &CaCertificate,
+        store: CaStore,
+    ) -> Result<CaInstallResult, AdbError> {
+        let sdk = self.properties()?.sdk().unwrap_or(0);
+        match store {
+            CaStore::System => {
+                if sdk >= APEX_CACERTS_SDK {
+                    return Err(AdbError::Server {
+                        message: "the system CA store is read-only on Android 14 and later"
+                            .to_string(),
+                    });
+                }
+                if !self.is_root()? {
+                    return Err(AdbError::Server {
+                        message: "installing system CA certificates requires adbd running as root"
+                            .to_string(),
+                    });
+                }
+                if self.remount()? == RemountResult::RebootRequired {
+                    return Err(AdbError::Server {
+                        message: "remounting the system partitions requires a reboot".to_string(),
+                    });
+                }
+                let path = format!("{}/{}", SYSTEM_CACERTS, certificate.file_name());
+                let path_arg = shell::quote(&path);
+                self.shell_checked(&format!(
+                    "printf %s {} > {} && chmod 644 {} && chcon u:object_r:system_file:s0 {}",
+                    shell::quote(&certificate.to_pem()),
+                    path_arg,
+                    path_arg,
+                    path_arg
+                ))?;
+                Ok(CaInstallResult::Installed(path))
+            }
+            CaStore::User => {
+                let path = format!(
+                    "{}/{:08x}.crt",
+                    USER_CACERTS,
+                    certificate.subject_hash_old()
+                );
+                self.shell_checked(&format!(
+                    "printf %s {} > {}",
+                    shell::quote(&certificate.to_pem()),
+                    shell::quote(&path)
+                ))?;
+                let intent = if sdk >= SETTINGS_ONLY_SDK {
+                    Intent::new().action("android.settings.SECURITY_SETTINGS")
+                } else {
+                    Intent::new()
+                        .action("android.intent.action.VIEW")
+                        .data(format!("file://{}", path))
+                        .mime_type("application/x-x509-ca-cert")
+                        .component("com.android.certinstaller/.CertInstallerMain")
+                };
+                self.start_activity(&intent)?;
+                Ok(CaInstallResult::ConfirmationRequired(path))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBsDCCAVegAwIBAgIUafKtbaH6dFkv+7hXRGVCFM8KuhswCgYIKoZIzj0EAwIw
+LjEZMBcGA1UEAwwQZGlzYW5nZXIgdGVzdCBDQTERMA8GA1UECgwIZGlzYW5nZXIw
+HhcNMjYxMDE2MDQzNzQ4WhcNMzYxMDEzMDQzNzQ4WjAuMRkwFwYDVQQDDBBkaXNh
+bmdlciB0ZXN0IENBMREwDwYDVQQKDAhkaXNhbmdlcjBZMBMGByqGSM49AgEGCCqG
+SM49AwEHA0IABL2jS/FkNelQAAeTEBfO/uqKerTTt++orIx6L8Y7HNTj6C3q+DhM
+XT6A19BjLxEMSON9VbrJ/ekMqICIK0kc/rijUzBRMB0GA1UdDgQWBBSp7lEr9ipC
+2gaUbLigqQWgn+VocDAfBgNVHSMEGDAWgBSp7lEr9ipC2gaUbLigqQWgn+VocDAP
+BgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0cAMEQCIG+VHoUATmK2u89tlEAq
+XZ7uCfaXcHFbQqa+rplXVSIxAiB4XrTivprlBBfYO9d4p6Gd69BurH2FsclrH5gC
+6cTElw==
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn test_md5() {
+        assert_eq!(
+            "d41d8cd98f00b204e9800998ecf8427e",
+            md5(b"")
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
+        assert_eq!(
+            "9e107d9d372bb6826bd81d3542a419d6",
+            md5(b"The quick brown fox jumps over the lazy dog")
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
+    }
+
+    #[test]
+    fn test_certificate() {
+        let certificate: CaCertificate = PEM.parse().unwrap();
+        // `openssl x509 -noout -subject_hash_old`
+        assert_eq!(0x31c5d9ae, certificate.subject_hash_old());
+        assert_eq!("31c5d9ae.0", certificate.file_name());
+        assert_eq!(PEM, certificate.to_pem());
+        assert_eq!(
+            certificate,
+            CaCertificate::from_der(certificate.der().to_vec()).unwrap()
+        );
+        assert!("not a certificate".parse::<CaCertificate>().is_err());
+        assert!(CaCertificate::from_der(vec![0x30, 0x03, 0x02, 0x01, 0x00]).is_err());
+    }
+}
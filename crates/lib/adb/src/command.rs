@@ -0,0 +1,290 @@
+//! This module provides [`Adb`], a builder running the `adb` executable with typed
+//! arguments, for users who'd rather wrap the command line than talk the protocol.
+//!
+//! The executable is looked up in `PATH`, then in the `platform-tools` directory of the
+//! Android SDK (`ANDROID_HOME`, or the deprecated `ANDROID_SDK_ROOT`). Socket families are
+//! rendered with their [`Display`](std::fmt::Display) implementations, the format `adb`
+//! expects.
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::error::AdbError;
+use crate::socket::AdbSocketFamily;
+
+/// The arguments of an `adb` command.
+///
+/// Strings are passed as a single argument.
+pub trait AdbArgs {
+    /// Returns the arguments, e.g. `["forward", "tcp:8080", "localabstract:app"]`.
+    fn args(&self) -> Vec<OsString>;
+}
+
+impl AdbArgs for &str {
+    fn args(&self) -> Vec<OsString> {
+        vec![self.into()]
+    }
+}
+
+impl AdbArgs for String {
+    fn args(&self) -> Vec<OsString> {
+        vec![self.into()]
+    }
+}
+
+/// `adb devices -l`
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Devices;
+
+/// `adb forward <local> <remote>`
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Forward<L, R>(pub L, pub R);
+
+/// `adb reverse <remote> <local>`, the first socket listening on the device.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Reverse<R, L>(pub R, pub L);
+
+/// `adb shell <command>`
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Shell(pub String);
+
+/// `adb push <local> <remote>`
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Push(pub PathBuf, pub String);
+
+/// `adb pull <remote> <local>`
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Pull(pub String, pub PathBuf);
+
+/// `adb install -r <apk>`
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Install(pub PathBuf);
+
+/// `adb uninstall <package>`
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Uninstall(pub String);
+
+impl AdbArgs for Devices {
+    fn args(&self) -> Vec<OsString> {
+        vec!["devices".into(), "-l".into()]
+    }
+}
+
+impl<L: AdbSocketFamily, R: AdbSocketFamily> AdbArgs for Forward<L, R> {
+    fn args(&self) -> Vec<OsString> {
+        vec![
+            "forward".into(),
+            self.0.to_string().into(),
+            self.1.to_string().into(),
+        ]
+    }
+}
+
+impl<R: AdbSocketFamily, L: AdbSocketFamily> AdbArgs for Reverse<R, L> {
+    fn args(&self) -> Vec<OsString> {
+        vec![
+            "reverse".into(),
+            self.0.to_string().into(),
+            self.1.to_string().into(),
+        ]
+    }
+}
+
+impl AdbArgs for Shell {
+    fn args(&self) -> Vec<OsString> {
+        vec!["shell".into(), (&self.0).into()]
+    }
+}
+
+impl AdbArgs for Push {
+    fn args(&self) -> Vec<OsString> {
+        vec!["push".into(), self.0.clone().into(), (&self.1).into()]
+    }
+}
+
+impl AdbArgs for Pull {
+    fn args(&self) -> Vec<OsString> {
+        vec!["pull".into(), (&self.0).into(), self.1.clone().into()]
+    }
+}
+
+impl AdbArgs for Install {
+    fn args(&self) -> Vec<OsString> {
+        vec!["install".into(), "-r".into(), self.0.clone().into()]
+    }
+}
+
+impl AdbArgs for Uninstall {
+    fn args(&self) -> Vec<OsString> {
+        vec!["uninstall".into(), (&self.0).into()]
+    }
+}
+
+/// The output of a finished `adb` command.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct CommandOutput {
+    /// The exit code, `None` if the process was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// Returns `true` if the command exited with 0.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Returns the path of the `adb` executable, searched in `PATH`, then in the SDK.
+pub fn locate() -> Option<PathBuf> {
+    crate::sdk::locate_adb()
+}
+
+/// A builder of `adb` commands.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::command::{Adb, Forward};
+/// use adb::socket::{LocalAbstract, Tcp};
+///
+/// let output = Adb::new()
+///     .serial("emulator-5554")
+///     .arg(Forward(Tcp::from_port(8080), LocalAbstract("app".to_string())))
+///     .run()
+///     .unwrap();
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Adb {
+    program: Option<PathBuf>,
+    options: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    args: Vec<OsString>,
+}
+
+impl Adb {
+    /// Creates a command running the `adb` found by [`locate`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the path of the `adb` executable.
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = Some(program.into());
+        self
+    }
+
+    /// Selects the device with the serial number `serial` (`-s`).
+    pub fn serial(mut self, serial: &str) -> Self {
+        self.options.extend(["-s".into(), serial.into()]);
+        self
+    }
+
+    /// Selects the device with the transport id `id` (`-t`).
+    pub fn transport_id(mut self, id: u64) -> Self {
+        self.options.extend(["-t".into(), id.to_string().into()]);
+        self
+    }
+
+    /// Sets the port of the adb server (`-P`).
+    pub fn server_port(mut self, port: u16) -> Self {
+        self.options.extend(["-P".into(), port.to_string().into()]);
+        self
+    }
+
+    /// Sets an environment variable of the process, e.g. `ADB_TRACE`.
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.envs
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    /// Appends arguments, a typed command like [`Forward`] or a single string.
+    pub fn arg<A: AdbArgs>(mut self, arg: A) -> Self {
+        self.args.extend(arg.args());
+        self
+    }
+
+    /// Returns the arguments passed to `adb`, global options first.
+    pub fn args(&self) -> Vec<OsString> {
+        self.options.iter().chain(&self.args).cloned().collect()
+    }
+
+    /// Runs the command to completion and returns its output, whatever its exit code.
+    pub fn output(&self) -> Result<CommandOutput, AdbError> {
+        let program = match &self.program {
+            Some(program) => program.clone(),
+            None => locate().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "`adb` not found in PATH nor in ANDROID_HOME",
+                )
+            })?,
+        };
+        let output = Command::new(program)
+            .args(self.args())
+            .envs(self.envs.iter().map(|(key, value)| (key, value)))
+            .output()?;
+        Ok(CommandOutput {
+            exit_code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    /// Runs the command and returns its stdout, failing if it exits with a non-zero code.
+    pub fn run(&self) -> Result<String, AdbError> {
+        let output = self.output()?;
+        if output.success() {
+            Ok(output.stdout)
+        } else {
+            Err(AdbError::Server {
+                message: format!(
+                    "`adb` exited with {:?}: {}",
+                    output.exit_code,
+                    output.stderr.trim_end()
+                ),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::{LocalAbstract, Tcp};
+
+    #[test]
+    fn test_adb_args() {
+        let adb = Adb::new()
+            .serial("emulator-5554")
+            .arg(Forward(
+                Tcp::from_port(8080),
+                LocalAbstract("app".to_string()),
+            ))
+            .server_port(5038);
+        assert_eq!(
+            [
+                "-s",
+                "emulator-5554",
+                "-P",
+                "5038",
+                "forward",
+                "tcp:8080",
+                "localabstract:app"
+            ],
+            &adb.args()[..]
+        );
+        let adb = Adb::new().arg(Shell("echo a b".to_string())).arg("-x");
+        assert_eq!(["shell", "echo a b", "-x"], &adb.args()[..]);
+    }
+
+    #[test]
+    fn test_adb_missing_program() {
+        let adb = Adb::new().program("/nonexistent/adb").arg(Devices);
+        assert!(matches!(adb.output(), Err(AdbError::Io(_))));
+    }
+}
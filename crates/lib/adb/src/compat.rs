@@ -0,0 +1,63 @@
+//! Shims for std APIs newer than the minimum supported Rust version.
+//!
+//! Each shim uses the std API when the compiler provides it, and falls back to an
+//! equivalent implementation otherwise. Enabling the `msrv` feature forces the fallbacks,
+//! so CI on a recent toolchain still exercises the code paths older toolchains compile.
+
+use std::error::Error;
+use std::io;
+
+/// Creates an [`io::Error`] of kind [`io::ErrorKind::Other`].
+///
+/// `io::Error::other` is stable since Rust 1.74.
+#[cfg(not(feature = "msrv"))]
+#[rustversion::since(1.74)]
+#[allow(clippy::incompatible_msrv)] // gated by `rustversion`
+pub(crate) fn io_other<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    io::Error::other(error)
+}
+
+/// Creates an [`io::Error`] of kind [`io::ErrorKind::Other`].
+#[cfg(not(feature = "msrv"))]
+#[rustversion::before(1.74)]
+pub(crate) fn io_other<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::Other, error)
+}
+
+/// Creates an [`io::Error`] of kind [`io::ErrorKind::Other`].
+#[cfg(feature = "msrv")]
+pub(crate) fn io_other<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::Other, error)
+}
+
+/// Sets the modification time of a file.
+///
+/// `File::set_modified` is stable since Rust 1.75. Older toolchains leave the time untouched.
+#[cfg(all(feature = "sync", not(feature = "msrv")))]
+#[rustversion::since(1.75)]
+#[allow(clippy::incompatible_msrv)] // gated by `rustversion`
+pub(crate) fn set_modified(file: &std::fs::File, time: std::time::SystemTime) -> io::Result<()> {
+    file.set_modified(time)
+}
+
+/// Sets the modification time of a file.
+#[cfg(all(feature = "sync", not(feature = "msrv")))]
+#[rustversion::before(1.75)]
+pub(crate) fn set_modified(_file: &std::fs::File, _time: std::time::SystemTime) -> io::Result<()> {
+    Ok(())
+}
+
+/// Sets the modification time of a file.
+#[cfg(all(feature = "sync", feature = "msrv"))]
+pub(crate) fn set_modified(_file: &std::fs::File, _time: std::time::SystemTime) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,261 @@
+//! This module provides the timeouts and retries of blocking network operations.
+//!
+//! [`ConnectOptions`] bound connecting to the adb server, and the reads and writes of the
+//! connections, see [`AdbServer::connect_options`](crate::server::AdbServer) and
+//! [`TcpExt::from_host_with`](crate::socket::TcpExt::from_host_with). Failures that may be
+//! transient, as told by [`AdbError::is_retryable`], are retried as set by a
+//! [`RetryPolicy`], waiting longer after each attempt.
+
+use std::time::Duration;
+
+use crate::error::AdbError;
+
+/// The default time to wait for a connection, or a host name resolution.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How to retry an operation failing with a [retryable](AdbError::is_retryable) error.
+///
+/// The delay before the first retry is the initial backoff, then it's multiplied by the
+/// multiplier before each retry, up to the maximum backoff.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::connect::RetryPolicy;
+///
+/// let policy = RetryPolicy::new()
+///     .retries(4)
+///     .backoff(Duration::from_millis(50));
+/// assert_eq!(Duration::from_millis(200), policy.delay(2));
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RetryPolicy {
+    retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    multiplier: u32,
+}
+
+impl RetryPolicy {
+    /// Creates a policy retrying twice, after 100 ms then 200 ms, and at most 2 s apart.
+    pub fn new() -> Self {
+        Self {
+            retries: 2,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2,
+        }
+    }
+
+    /// Creates a policy never retrying.
+    pub fn none() -> Self {
+        Self::new().retries(0)
+    }
+
+    /// Sets how many times an operation is retried after its first attempt.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the longest delay between two attempts.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets the factor the delay grows by after each retry, 1 for a constant delay.
+    pub fn multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Returns the delay before the retry `retry`, starting at 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.saturating_pow(retry);
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+
+    /// Runs `operation` until it succeeds, fails with an error that isn't retryable, or the
+    /// retries are exhausted, calling `sleep` between two attempts.
+    pub fn run<T, F, S>(&self, mut operation: F, sleep: S) -> Result<T, AdbError>
+    where
+        F: FnMut() -> Result<T, AdbError>,
+        S: Fn(Duration),
+    {
+        let mut retry = 0;
+        loop {
+            match operation() {
+                Err(e) if e.is_retryable() && retry < self.retries => {
+                    sleep(self.delay(retry));
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The timeouts and retries of connections.
+///
+/// Read and write timeouts are off by default, since some services, like logcat, wait for
+/// new output forever. Operations waiting for a condition on purpose set their own timeouts.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::connect::{ConnectOptions, RetryPolicy};
+///
+/// let options = ConnectOptions::new()
+///     .connect_timeout(Duration::from_secs(2))
+///     .read_timeout(Some(Duration::from_secs(30)))
+///     .retry(RetryPolicy::none());
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ConnectOptions {
+    connect_timeout: Duration,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    retry: RetryPolicy,
+}
+
+impl ConnectOptions {
+    /// Creates options connecting within [`DEFAULT_CONNECT_TIMEOUT`], without read and write
+    /// timeouts, and retrying with the default [`RetryPolicy`].
+    pub fn new() -> Self {
+        Self {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: None,
+            write_timeout: None,
+            retry: RetryPolicy::new(),
+        }
+    }
+
+    /// Sets the time to wait for a connection, or a host name resolution.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the time a read waits for data, `None` waiting forever.
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Sets the time a write waits for the peer, `None` waiting forever.
+    pub fn write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Sets how failed connections are retried.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Returns the time to wait for a connection.
+    pub fn get_connect_timeout(&self) -> Duration {
+        self.connect_timeout
+    }
+
+    /// Returns the time a read waits for data.
+    pub fn get_read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    /// Returns the time a write waits for the peer.
+    pub fn get_write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    /// Returns the retry policy.
+    pub fn get_retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::io::ErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy::new();
+        assert_eq!(Duration::from_millis(100), policy.delay(0));
+        assert_eq!(Duration::from_millis(400), policy.delay(2));
+        assert_eq!(Duration::from_secs(2), policy.delay(10));
+        assert_eq!(Duration::from_secs(2), policy.delay(u32::MAX));
+        let constant = policy.multiplier(1);
+        assert_eq!(Duration::from_millis(100), constant.delay(7));
+    }
+
+    #[test]
+    fn test_retry_run() {
+        let attempts = Cell::new(0);
+        let slept = RefCell::new(Vec::new());
+        let result: Result<(), _> = RetryPolicy::new().retries(3).run(
+            || {
+                attempts.set(attempts.get() + 1);
+                Err(AdbError::Io(ErrorKind::ConnectionRefused.into()))
+            },
+            |delay| slept.borrow_mut().push(delay),
+        );
+        assert!(result.is_err());
+        assert_eq!(4, attempts.get());
+        assert_eq!(
+            vec![100, 200, 400],
+            slept
+                .borrow()
+                .iter()
+                .map(Duration::as_millis)
+                .collect::<Vec<_>>()
+        );
+
+        attempts.set(0);
+        let result: Result<(), _> = RetryPolicy::new().run(
+            || {
+                attempts.set(attempts.get() + 1);
+                Err(AdbError::Unauthorized)
+            },
+            |_| {},
+        );
+        assert!(matches!(result, Err(AdbError::Unauthorized)));
+        assert_eq!(1, attempts.get());
+
+        let result = RetryPolicy::new().run(
+            || match attempts.replace(attempts.get() + 1) {
+                1 => Err(AdbError::DeviceOffline),
+                n => Ok(n),
+            },
+            |_| {},
+        );
+        assert_eq!(2, result.unwrap());
+    }
+}
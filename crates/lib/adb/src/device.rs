@@ -0,0 +1,570 @@
+//! This module provides a handle addressing a single device through the adb server.
+
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::connect::ConnectOptions;
+use crate::error::AdbError;
+use crate::features::Features;
+#[cfg(feature = "shell")]
+use crate::properties::Properties;
+use crate::protocol;
+use crate::server::{AdbServer, RawService, ServerStream};
+use crate::version::ServerCapability;
+
+/// How the adb server selects the device a request is forwarded to.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Transport {
+    /// The device with the given serial number.
+    Serial(String),
+    /// The device with the given transport id, see `adb devices -l`.
+    TransportId(u64),
+    /// The only connected device.
+    Any,
+    /// The only device connected over USB.
+    Usb,
+    /// The only device connected over TCP, e.g. an emulator.
+    Local,
+}
+
+impl Transport {
+    /// Returns the host service switching the connection to this transport,
+    /// e.g. `host:transport:<serial>`.
+    pub fn service(&self) -> String {
+        match self {
+            Self::Serial(serial) => format!("host:transport:{}", serial),
+            Self::TransportId(id) => format!("host:transport-id:{}", id),
+            Self::Any => "host:transport-any".to_string(),
+            Self::Usb => "host:transport-usb".to_string(),
+            Self::Local => "host:transport-local".to_string(),
+        }
+    }
+
+    /// Returns the host service switching the connection to this transport and replying
+    /// with its transport id, e.g. `host:tport:serial:<serial>`, `None` for a transport id.
+    pub fn tport_service(&self) -> Option<String> {
+        Some(match self {
+            Self::Serial(serial) => format!("host:tport:serial:{}", serial),
+            Self::TransportId(_) => return None,
+            Self::Any => "host:tport:any".to_string(),
+            Self::Usb => "host:tport:usb".to_string(),
+            Self::Local => "host:tport:local".to_string(),
+        })
+    }
+
+    /// Returns the prefix of host services scoped to this transport,
+    /// e.g. `host-serial:<serial>:` for `host-serial:<serial>:get-state`.
+    pub fn host_prefix(&self) -> String {
+        match self {
+            Self::Serial(serial) => format!("host-serial:{}:", serial),
+            Self::TransportId(id) => format!("host-transport-id:{}:", id),
+            Self::Any => "host:".to_string(),
+            Self::Usb => "host-usb:".to_string(),
+            Self::Local => "host-local:".to_string(),
+        }
+    }
+}
+
+/// The connection state of a device.
+///
+/// States added by future adb servers are parsed as [`DeviceState::Other`].
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[non_exhaustive]
+pub enum DeviceState {
+    /// The device is connected and ready.
+    Device,
+    /// The device is not responding.
+    Offline,
+    /// The user hasn't accepted the RSA key of this host on the device yet.
+    Unauthorized,
+    /// The device is authenticating this host.
+    Authorizing,
+    /// The adb server is connecting to the device.
+    Connecting,
+    /// The device is in recovery mode.
+    Recovery,
+    /// The device is in rescue mode.
+    Rescue,
+    /// The device is waiting for an OTA package, see `adb sideload`.
+    Sideload,
+    /// The device is in bootloader mode.
+    Bootloader,
+    /// The device is another host.
+    Host,
+    /// The USB device is detached from the adb server.
+    Detached,
+    /// The adb server has no permission to access the USB device.
+    NoPermissions,
+    /// A state unknown to this crate.
+    Other(String),
+}
+
+impl DeviceState {
+    /// Returns the state as printed by the adb server.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Device => "device",
+            Self::Offline => "offline",
+            Self::Unauthorized => "unauthorized",
+            Self::Authorizing => "authorizing",
+            Self::Connecting => "connecting",
+            Self::Recovery => "recovery",
+            Self::Rescue => "rescue",
+            Self::Sideload => "sideload",
+            Self::Bootloader => "bootloader",
+            Self::Host => "host",
+            Self::Detached => "detached",
+            Self::NoPermissions => "no permissions",
+            Self::Other(name) => name,
+        }
+    }
+
+    /// Returns `false` for [`DeviceState::Other`].
+    pub fn is_known(&self) -> bool {
+        !matches!(self, Self::Other(_))
+    }
+}
+
+impl Display for DeviceState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DeviceState {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "device" => Self::Device,
+            "offline" => Self::Offline,
+            "unauthorized" => Self::Unauthorized,
+            "authorizing" => Self::Authorizing,
+            "connecting" => Self::Connecting,
+            "recovery" => Self::Recovery,
+            "rescue" => Self::Rescue,
+            "sideload" => Self::Sideload,
+            "bootloader" => Self::Bootloader,
+            "host" => Self::Host,
+            "detached" => Self::Detached,
+            // The server appends the reason, e.g. `no permissions (...); see [...]`.
+            _ if s.starts_with("no permissions") => Self::NoPermissions,
+            _ if !s.is_empty() && !s.contains(char::is_whitespace) => Self::Other(s.to_string()),
+            _ => {
+                return Err(AdbError::Parse {
+                    value: s.to_string(),
+                    source_type: "&str",
+                    target_type: "DeviceState",
+                    source: None,
+                })
+            }
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_str!(DeviceState);
+
+/// A handle to a device connected to the adb server.
+///
+/// A `Device` is cheap to clone and can be shared between threads.
+/// Like [`AdbServer`], it opens a new connection for every request.
+///
+/// On servers supporting it, connections switch to the device with `host:tport`, which
+/// replies with the id of the transport the connection is bound to: a request never races
+/// with the device reconnecting under the same serial, and [`Device::transport_id`] tells
+/// which transport served the last request.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::device::DeviceState;
+/// use adb::server::AdbServer;
+///
+/// let server = AdbServer::default();
+/// for device in server.devices().unwrap() {
+///     if device.state() == Some(DeviceState::Device) {
+///         println!("{:?} is ready", device.serial());
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Device {
+    inner: Arc<DeviceInner>,
+}
+
+#[derive(Debug)]
+struct DeviceInner {
+    server: AdbServer,
+    transport: Transport,
+    state: Option<DeviceState>,
+    /// Whether the server supports `host:tport`, checked on the first request.
+    tport: OnceLock<bool>,
+    /// The transport id the last `host:tport` connection was bound to.
+    bound_id: Mutex<Option<u64>>,
+    /// The features cached by [`Device::features`].
+    features: Mutex<Option<Features>>,
+    /// The properties cached by [`Device::properties`].
+    #[cfg(feature = "shell")]
+    properties: Mutex<Option<Properties>>,
+}
+
+impl Device {
+    /// Creates a handle to the device selected by `transport`, without connecting to it.
+    pub fn new(server: AdbServer, transport: Transport) -> Self {
+        Self::with_state(server, transport, None)
+    }
+
+    pub(crate) fn with_state(
+        server: AdbServer,
+        transport: Transport,
+        state: Option<DeviceState>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(DeviceInner {
+                server,
+                transport,
+                state,
+                tport: OnceLock::new(),
+                bound_id: Mutex::new(None),
+                features: Mutex::new(None),
+                #[cfg(feature = "shell")]
+                properties: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Returns the adb server the device is connected to.
+    pub fn server(&self) -> &AdbServer {
+        &self.inner.server
+    }
+
+    /// Returns a handle to the same device, connecting to the server with `options` instead
+    /// of the [connect options](AdbServer::connect_options) of the server.
+    ///
+    /// The new handle doesn't share the cached features and properties of this one.
+    pub fn connect_options(&self, options: ConnectOptions) -> Self {
+        Self::with_state(
+            self.server().clone().connect_options(options),
+            self.transport().clone(),
+            self.state(),
+        )
+    }
+
+    /// Returns the cache of [`Device::features`], shared by the clones of the handle.
+    pub(crate) fn features_cache(&self) -> &Mutex<Option<Features>> {
+        &self.inner.features
+    }
+
+    /// Returns the cache of [`Device::properties`], shared by the clones of the handle.
+    #[cfg(feature = "shell")]
+    pub(crate) fn properties_cache(&self) -> &Mutex<Option<Properties>> {
+        &self.inner.properties
+    }
+
+    /// Returns how the device is selected.
+    pub fn transport(&self) -> &Transport {
+        &self.inner.transport
+    }
+
+    /// Returns the serial number of the device, if it's selected by serial number.
+    pub fn serial(&self) -> Option<&str> {
+        match &self.inner.transport {
+            Transport::Serial(serial) => Some(serial),
+            _ => None,
+        }
+    }
+
+    /// Returns the transport id of the device, if it's selected by transport id, or else the
+    /// id of the transport the last request was bound to with `host:tport`.
+    ///
+    /// Use [`Self::acquire_transport_id`] to query it.
+    pub fn transport_id(&self) -> Option<u64> {
+        match self.inner.transport {
+            Transport::TransportId(id) => Some(id),
+            _ => *self.inner.bound_id.lock().unwrap(),
+        }
+    }
+
+    /// Queries the id of the transport of the device (`host:tport:<selector>`).
+    ///
+    /// Fails with [`AdbError::ServerUnsupported`] if the server doesn't support `host:tport`.
+    /// A device selected by transport id returns it as is.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let server = AdbServer::default();
+    /// let id = server.device("emulator-5554").acquire_transport_id().unwrap();
+    /// // Requests of this handle fail once the emulator reconnects, instead of reaching the
+    /// // new transport.
+    /// let device = server.device_by_transport_id(id);
+    /// ```
+    pub fn acquire_transport_id(&self) -> Result<u64, AdbError> {
+        let Some(service) = self.inner.transport.tport_service() else {
+            // Only devices selected by transport id have no `host:tport` service.
+            return Ok(self.transport_id().unwrap_or_default());
+        };
+        self.inner.server.require(ServerCapability::Tport)?;
+        let mut stream = self.inner.server.open(&service)?;
+        self.read_transport_id(&mut stream)
+    }
+
+    /// Reads the transport id replying to `host:tport`, and records it.
+    fn read_transport_id(&self, stream: &mut ServerStream) -> Result<u64, AdbError> {
+        let mut id = [0; 8];
+        stream.read_exact(&mut id)?;
+        let id = u64::from_le_bytes(id);
+        *self.inner.bound_id.lock().unwrap() = Some(id);
+        Ok(id)
+    }
+
+    /// Returns the `host:tport` service of the device, `None` if it's selected by transport
+    /// id or if the server doesn't support it.
+    fn tport_service(&self) -> Result<Option<String>, AdbError> {
+        let service = match self.inner.transport.tport_service() {
+            Some(service) => service,
+            None => return Ok(None),
+        };
+        let supported = match self.inner.tport.get() {
+            Some(&supported) => supported,
+            None => {
+                let supported = self.inner.server.supports(ServerCapability::Tport)?;
+                *self.inner.tport.get_or_init(|| supported)
+            }
+        };
+        Ok(supported.then_some(service))
+    }
+
+    /// Returns the state of the device when it was listed by [`AdbServer::devices`].
+    ///
+    /// Use [`Self::get_state`] to query the current state.
+    pub fn state(&self) -> Option<DeviceState> {
+        self.inner.state.clone()
+    }
+
+    /// Queries the current state of the device (`host-serial:<serial>:get-state`).
+    pub fn get_state(&self) -> Result<DeviceState, AdbError> {
+        self.host_request_string("get-state")?.parse()
+    }
+
+    /// Queries the serial number of the device (`host-serial:<serial>:get-serialno`).
+    pub fn get_serialno(&self) -> Result<String, AdbError> {
+        self.host_request_string("get-serialno")
+    }
+
+    /// Requests a host service scoped to this device and reads the reply as a string.
+    ///
+    /// The service must change nothing, it's sent again if the server goes away, see
+    /// [`AdbServer::reissue_idempotent`].
+    pub(crate) fn host_request_string(&self, service: &str) -> Result<String, AdbError> {
+        let prefix = self.inner.transport.host_prefix();
+        self.inner
+            .server
+            .query_string(&format!("{}{}", prefix, service))
+    }
+
+    /// Opens a connection to the device and requests `service`, e.g. `shell:ls`.
+    ///
+    /// The returned stream is positioned right after the `OKAY` status of the service.
+    ///
+    /// The request is reported as a single operation to the observer of the server, see
+    /// [`crate::op`].
+    pub fn open(&self, service: &str) -> Result<ServerStream, AdbError> {
+        let server = &self.inner.server;
+        let op = server.start_op(self.serial(), service);
+        let result = op.in_scope(|| self.open_untracked(service));
+        op.finish(result)
+    }
+
+    fn open_untracked(&self, service: &str) -> Result<ServerStream, AdbError> {
+        self.raw_untracked(service)?.into_stream()
+    }
+
+    /// Requests `service` on the device, returning the connection with the status of the
+    /// request, whether `OKAY` or `FAIL`.
+    ///
+    /// An escape hatch for the services this crate doesn't wrap, see
+    /// [`AdbServer::raw_host_service`]. Selecting the device is done first, a missing or
+    /// offline device failing with an error as for [`Self::open`]; only the status of
+    /// `service` itself is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::io::Read;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let raw = device.raw_service("exec:cat /proc/version").unwrap();
+    /// let mut output = String::new();
+    /// raw.into_stream().unwrap().read_to_string(&mut output).unwrap();
+    /// ```
+    pub fn raw_service(&self, service: &str) -> Result<RawService, AdbError> {
+        let op = self.inner.server.start_op(self.serial(), service);
+        match op.in_scope(|| self.raw_untracked(service)) {
+            Ok(raw) => {
+                op.finish_status(&raw.status);
+                Ok(raw)
+            }
+            Err(e) => op.finish(Err(e)),
+        }
+    }
+
+    fn raw_untracked(&self, service: &str) -> Result<RawService, AdbError> {
+        let mut stream = match self.tport_service()? {
+            Some(tport) => {
+                let mut stream = self.inner.server.open_untracked(&tport)?;
+                self.read_transport_id(&mut stream)?;
+                stream
+            }
+            None => self
+                .inner
+                .server
+                .open_untracked(&self.inner.transport.service())?,
+        };
+        protocol::send_request(&mut stream, service)?;
+        let status = protocol::read_decoded(&mut stream, protocol::decode_status)?;
+        Ok(RawService { status, stream })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Device>();
+    }
+
+    #[test]
+    fn test_transport_service() {
+        let serial = Transport::Serial("emulator-5554".to_string());
+        assert_eq!("host:transport:emulator-5554", serial.service());
+        assert_eq!("host-serial:emulator-5554:", serial.host_prefix());
+        assert_eq!("host:transport-id:3", Transport::TransportId(3).service());
+        assert_eq!(
+            "host-transport-id:3:",
+            Transport::TransportId(3).host_prefix()
+        );
+        assert_eq!("host:transport-any", Transport::Any.service());
+        assert_eq!("host:", Transport::Any.host_prefix());
+        assert_eq!(
+            Some("host:tport:serial:emulator-5554"),
+            serial.tport_service().as_deref()
+        );
+        assert_eq!(None, Transport::TransportId(3).tport_service());
+        assert_eq!(
+            Some("host:tport:usb"),
+            Transport::Usb.tport_service().as_deref()
+        );
+    }
+
+    #[test]
+    fn test_device_open_tport() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        use crate::socket::Tcp;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in [&b"OKAY00040029"[..], &b"OKAY\x07\0\0\0\0\0\0\0"[..]] {
+                let (mut stream, _) = listener.accept().unwrap();
+                requests.push(protocol::read_string(&mut stream).unwrap());
+                stream.write_all(reply).unwrap();
+                if requests.len() == 2 {
+                    // The service is requested on the connection bound to the transport.
+                    requests.push(protocol::read_string(&mut stream).unwrap());
+                    stream.write_all(b"OKAY").unwrap();
+                }
+            }
+            requests
+        });
+        let device = AdbServer::new(Tcp::from_port(port)).device("emulator-5554");
+        assert_eq!(None, device.transport_id());
+        device.open("shell:true").unwrap();
+        assert_eq!(Some(7), device.transport_id());
+        assert_eq!(
+            [
+                "host:version",
+                "host:tport:serial:emulator-5554",
+                "shell:true"
+            ],
+            &handle.join().unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn test_device_open_error_op_id() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        use crate::op::{OpEvent, OpObserver, OpStatus};
+        use crate::socket::Tcp;
+
+        #[derive(Debug, Default)]
+        struct Events(Mutex<Vec<OpEvent>>);
+
+        impl OpObserver for Arc<Events> {
+            fn on_event(&self, event: &OpEvent) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            for reply in [&b"OKAY00040029"[..], b"FAIL000edevice offline"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                protocol::read_string(&mut stream).unwrap();
+                stream.write_all(reply).unwrap();
+            }
+        });
+        let events = Arc::new(Events::default());
+        let server = AdbServer::new(Tcp::from_port(port)).observer(events.clone());
+        let error = server
+            .device("emulator-5554")
+            .open("shell:true")
+            .unwrap_err();
+        handle.join().unwrap();
+        assert!(matches!(error.inner(), AdbError::DeviceOffline));
+        let events = events.0.lock().unwrap();
+        let failed = events.last().unwrap();
+        assert_eq!(Some(failed.id), error.op_id());
+        assert_eq!(
+            OpStatus::Failed("device offline".to_string()),
+            failed.status
+        );
+        assert_eq!(format!("{}: device offline", failed.id), error.to_string());
+    }
+
+    #[test]
+    fn test_device_state_parse() {
+        for state in [
+            DeviceState::Device,
+            DeviceState::Offline,
+            DeviceState::Unauthorized,
+            DeviceState::Recovery,
+        ] {
+            assert_eq!(state, state.to_string().parse().unwrap());
+        }
+        assert_eq!(
+            DeviceState::NoPermissions,
+            "no permissions (missing udev rules?)".parse().unwrap()
+        );
+        let other: DeviceState = "online".parse().unwrap();
+        assert_eq!(DeviceState::Other("online".to_string()), other);
+        assert_eq!("online", other.to_string());
+        assert!(!other.is_known());
+        assert!("".parse::<DeviceState>().is_err());
+        assert!("no device".parse::<DeviceState>().is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! This module provides [`DeviceConfig`], the server-pushed flags of a device on top of the
+//! `cmd device_config` shell command (Android 10 and later).
+//!
+//! Flags are grouped in namespaces, e.g. `activity_manager`, and experiments roll out by
+//! changing them, which makes tests flaky. [`DeviceConfig::set_sync_disabled`] stops the
+//! server from overriding values, and a [`FlagGuard`] pins values for the duration of a
+//! test, restoring the previous ones when dropped.
+//!
+//! `list` prints one flag per line, `<namespace>/<key>=<value>`, or `<key>=<value>` when
+//! listing a single namespace. `get` prints `null` for flags without a value.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell::quote;
+
+/// Whether the server can override flags, the argument of `set_sync_disabled_for_tests`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum SyncDisabledMode {
+    /// The server overrides flags.
+    None,
+    /// The server doesn't override flags until the next reboot.
+    UntilReboot,
+    /// The server doesn't override flags, across reboots.
+    Persistent,
+}
+
+impl Display for SyncDisabledMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::UntilReboot => "until_reboot",
+            Self::Persistent => "persistent",
+        })
+    }
+}
+
+/// Parses the reply of `get`, `null` for flags without a value.
+fn parse_value(reply: &str) -> Option<String> {
+    let value = reply.strip_suffix('\n').unwrap_or(reply);
+    (value != "null").then(|| value.to_string())
+}
+
+/// Parses the flags of `list`, keyed by `<namespace>/<key>`, or `<key>` when listing a single
+/// namespace. Values may contain `=`, keys can't.
+fn parse_flags(list: &str) -> BTreeMap<String, String> {
+    list.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// The flags of a device, created by [`Device::device_config`].
+#[derive(Clone, Debug)]
+pub struct DeviceConfig {
+    device: Device,
+}
+
+impl DeviceConfig {
+    /// Returns the value of the flag `key` in `namespace`, or `None` if it has no value.
+    pub fn get(&self, namespace: &str, key: &str) -> Result<Option<String>, AdbError> {
+        let reply = self.device.shell_checked(&format!(
+            "cmd device_config get {} {}",
+            quote(namespace),
+            quote(key)
+        ))?;
+        Ok(parse_value(&reply))
+    }
+
+    /// Sets the flag `key` in `namespace` to `value`.
+    pub fn put(&self, namespace: &str, key: &str, value: &str) -> Result<(), AdbError> {
+        self.device
+            .shell_checked(&format!(
+                "cmd device_config put {} {} {}",
+                quote(namespace),
+                quote(key),
+                quote(value)
+            ))
+            .map(drop)
+    }
+
+    /// Deletes the flag `key` in `namespace`.
+    pub fn delete(&self, namespace: &str, key: &str) -> Result<(), AdbError> {
+        self.device
+            .shell_checked(&format!(
+                "cmd device_config delete {} {}",
+                quote(namespace),
+                quote(key)
+            ))
+            .map(drop)
+    }
+
+    /// Returns the flags of `namespace` keyed by name, or of all namespaces keyed by
+    /// `<namespace>/<key>` if `namespace` is `None`.
+    pub fn list(&self, namespace: Option<&str>) -> Result<BTreeMap<String, String>, AdbError> {
+        let command = match namespace {
+            Some(namespace) => format!("cmd device_config list {}", quote(namespace)),
+            None => "cmd device_config list".to_string(),
+        };
+        Ok(parse_flags(&self.device.shell_checked(&command)?))
+    }
+
+    /// Sets whether the server can override flags (`set_sync_disabled_for_tests`).
+    pub fn set_sync_disabled(&self, mode: SyncDisabledMode) -> Result<(), AdbError> {
+        self.device
+            .shell_checked(&format!(
+                "cmd device_config set_sync_disabled_for_tests {}",
+                mode
+            ))
+            .map(drop)
+    }
+
+    /// Returns a guard pinning flags with [`FlagGuard::put`] and [`FlagGuard::delete`].
+    pub fn guard(&self) -> FlagGuard {
+        FlagGuard {
+            config: self.clone(),
+            saved: Vec::new(),
+        }
+    }
+}
+
+/// A guard changing flags, created by [`DeviceConfig::guard`].
+///
+/// The flags are restored to the values they had before the first change when the guard
+/// is dropped, ignoring errors, or by [`FlagGuard::restore`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::server::AdbServer;
+///
+/// let device = AdbServer::default().any_device();
+/// let mut guard = device.device_config().guard();
+/// guard.put("activity_manager", "max_cached_processes", "8").unwrap();
+/// // Run the test with at most 8 cached processes.
+/// guard.restore().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FlagGuard {
+    config: DeviceConfig,
+    /// The changed flags, `(namespace, key, previous value)`, in order of change.
+    saved: Vec<(String, String, Option<String>)>,
+}
+
+impl FlagGuard {
+    /// Saves the value of a flag, unless it was already changed through the guard.
+    fn save(&mut self, namespace: &str, key: &str) -> Result<(), AdbError> {
+        if !self
+            .saved
+            .iter()
+            .any(|(ns, k, _)| ns == namespace && k == key)
+        {
+            let value = self.config.get(namespace, key)?;
+            self.saved
+                .push((namespace.to_string(), key.to_string(), value));
+        }
+        Ok(())
+    }
+
+    /// Sets the flag `key` in `namespace` to `value`.
+    pub fn put(&mut self, namespace: &str, key: &str, value: &str) -> Result<(), AdbError> {
+        self.save(namespace, key)?;
+        self.config.put(namespace, key, value)
+    }
+
+    /// Deletes the flag `key` in `namespace`.
+    pub fn delete(&mut self, namespace: &str, key: &str) -> Result<(), AdbError> {
+        self.save(namespace, key)?;
+        self.config.delete(namespace, key)
+    }
+
+    /// Restores the changed flags, in reverse order of change, returning the first error.
+    pub fn restore(mut self) -> Result<(), AdbError> {
+        self.restore_saved()
+    }
+
+    fn restore_saved(&mut self) -> Result<(), AdbError> {
+        let mut result = Ok(());
+        while let Some((namespace, key, value)) = self.saved.pop() {
+            let restored = match value {
+                Some(value) => self.config.put(&namespace, &key, &value),
+                None => self.config.delete(&namespace, &key),
+            };
+            result = result.and(restored);
+        }
+        result
+    }
+}
+
+impl Drop for FlagGuard {
+    fn drop(&mut self) {
+        let _ = self.restore_saved();
+    }
+}
+
+impl Device {
+    /// Returns a handle to the flags of the device.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::device_config::SyncDisabledMode;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let config = device.device_config();
+    /// config.set_sync_disabled(SyncDisabledMode::UntilReboot).unwrap();
+    /// for (key, value) in config.list(Some("activity_manager")).unwrap() {
+    ///     println!("{} = {}", key, value);
+    /// }
+    /// ```
+    pub fn device_config(&self) -> DeviceConfig {
+        DeviceConfig {
+            device: self.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(Some("8"), parse_value("8\n").as_deref());
+        assert_eq!(Some(""), parse_value("\n").as_deref());
+        assert_eq!(None, parse_value("null\n"));
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let flags = parse_flags(
+            "activity_manager/max_cached_processes=32\n\
+             privacy/location_access_check_enabled=true\n\
+             runtime_native/usap_pool_enabled=\n\
+             window_manager/filter=a=b\n",
+        );
+        assert_eq!(4, flags.len());
+        assert_eq!("32", flags["activity_manager/max_cached_processes"]);
+        assert_eq!("", flags["runtime_native/usap_pool_enabled"]);
+        assert_eq!("a=b", flags["window_manager/filter"]);
+        assert_eq!(
+            "32",
+            parse_flags("max_cached_processes=32\n")["max_cached_processes"]
+        );
+    }
+}
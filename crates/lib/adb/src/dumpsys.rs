@@ -0,0 +1,515 @@
+//! This module provides [`Device::dumpsys`], the raw dump of a system service, and typed
+//! parsers for the most used services: [`BatteryState`] from `dumpsys battery`, the focused
+//! activity from `dumpsys window`, and [`MemInfo`] from `dumpsys meminfo <process>`. The
+//! versions of a package in `dumpsys package <package>` are parsed by
+//! [`Device::package_details`], along with the rest of the package.
+//!
+//! Dumps are meant for humans, and change with every Android version. The parsers read the
+//! fields shared by Android 7 to 14, and leave the others `None`; the raw dump stays
+//! available for everything else.
+//!
+//! `dumpsys meminfo` sizes are in KiB. The `App Summary` section, Android 6 and later, ends
+//! with `TOTAL PSS:` (or `TOTAL:` before Android 10), `TOTAL RSS:` (Android 10 and later)
+//! and `TOTAL SWAP PSS:` fields, several on a line.
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// The charging status of the battery, the `status` field of `dumpsys battery`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum BatteryStatus {
+    #[default]
+    Unknown,
+    Charging,
+    Discharging,
+    /// Plugged, but not charging, e.g. when the battery is too hot.
+    NotCharging,
+    Full,
+}
+
+impl BatteryStatus {
+    /// Returns the status of a `BatteryManager.BATTERY_STATUS_*` code, unknown codes
+    /// included in [`Self::Unknown`].
+    pub fn from_code(code: u32) -> Self {
+        match code {
+            2 => Self::Charging,
+            3 => Self::Discharging,
+            4 => Self::NotCharging,
+            5 => Self::Full,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// The battery of a device, parsed from `dumpsys battery`.
+///
+/// # Syntax
+///
+/// ```text
+/// Current Battery Service state:
+///   AC powered: false
+///   USB powered: true
+///   Wireless powered: false
+///   status: 2
+///   present: true
+///   level: 85
+///   scale: 100
+///   voltage: 4273
+///   temperature: 281
+///   technology: Li-ion
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct BatteryState {
+    pub ac_powered: bool,
+    pub usb_powered: bool,
+    pub wireless_powered: bool,
+    pub status: BatteryStatus,
+    pub present: Option<bool>,
+    /// The charge level, out of [`Self::scale`].
+    pub level: Option<u32>,
+    pub scale: Option<u32>,
+    /// The voltage in mV.
+    pub voltage: Option<u32>,
+    /// The temperature in tenths of degree Celsius.
+    pub temperature: Option<i32>,
+    /// The battery technology, e.g. `Li-ion`.
+    pub technology: Option<String>,
+}
+
+impl BatteryState {
+    /// Parses the output of `dumpsys battery`, failing if it isn't a battery dump, e.g.
+    /// `Can't find service: battery`.
+    pub fn parse(dump: &str) -> Result<Self, AdbError> {
+        let mut lines = dump.lines().map(str::trim);
+        if !lines.any(|line| line == "Current Battery Service state:") {
+            return Err(AdbError::Parse {
+                value: dump.to_string(),
+                source_type: "&str",
+                target_type: "BatteryState",
+                source: None,
+            });
+        }
+        let mut state = Self::default();
+        for (key, value) in lines.filter_map(|line| line.split_once(": ")) {
+            match key {
+                "AC powered" => state.ac_powered = value == "true",
+                "USB powered" => state.usb_powered = value == "true",
+                "Wireless powered" => state.wireless_powered = value == "true",
+                "status" => {
+                    state.status = value
+                        .parse()
+                        .map_or(BatteryStatus::Unknown, BatteryStatus::from_code)
+                }
+                "present" => state.present = value.parse().ok(),
+                "level" => state.level = value.parse().ok(),
+                "scale" => state.scale = value.parse().ok(),
+                "voltage" => state.voltage = value.parse().ok(),
+                "temperature" => state.temperature = value.parse().ok(),
+                "technology" => state.technology = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Ok(state)
+    }
+
+    /// Returns the charge level in percent, `None` if the level or the scale is missing.
+    pub fn percent(&self) -> Option<u32> {
+        match (self.level, self.scale) {
+            (Some(level), Some(scale)) if scale > 0 => Some(level * 100 / scale),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the device is plugged to a charger of any kind.
+    pub fn is_plugged(&self) -> bool {
+        self.ac_powered || self.usb_powered || self.wireless_powered
+    }
+}
+
+/// Parses the activity of a window record, e.g. `ActivityRecord{6f0c0c u0
+/// com.example/.MainActivity t12}`: the word following the user id.
+fn parse_record_activity(record: &str) -> Option<&str> {
+    let mut words = record.split_whitespace();
+    while let Some(word) = words.next() {
+        let is_user = word
+            .strip_prefix('u')
+            .is_some_and(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()));
+        if is_user {
+            return words
+                .next()
+                .map(|activity| activity.trim_end_matches('}'))
+                .filter(|activity| activity.contains('/'));
+        }
+    }
+    None
+}
+
+/// Parses the focused activity from the output of `dumpsys window`, e.g.
+/// `com.example/.MainActivity`.
+///
+/// The `mFocusedApp=` line is read first, then `mCurrentFocus=`, whose focused window may
+/// not be an activity, e.g. the notification shade.
+///
+/// # Syntax
+///
+/// ```text
+///   mCurrentFocus=Window{8f1d6a3 u0 com.example/com.example.MainActivity}
+///   mFocusedApp=ActivityRecord{6f0c0c u0 com.example/.MainActivity t12}
+/// ```
+///
+/// Before Android 10, the record is wrapped, e.g. `mFocusedApp=AppWindowToken{...
+/// token=Token{... ActivityRecord{... u0 com.example/.MainActivity t12}}}`.
+pub fn parse_focused_activity(dump: &str) -> Option<&str> {
+    ["mFocusedApp=", "mCurrentFocus="]
+        .into_iter()
+        .find_map(|key| {
+            dump.lines()
+                .filter_map(|line| line.trim().strip_prefix(key))
+                .find_map(parse_record_activity)
+        })
+}
+
+/// The memory of a process, parsed from `dumpsys meminfo <process>`, in KiB.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct MemInfo {
+    /// The proportional set size, shared pages split between the processes sharing them.
+    pub total_pss: u64,
+    /// The resident set size, Android 10 and later.
+    pub total_rss: Option<u64>,
+    /// The proportional size of the pages swapped out.
+    pub total_swap_pss: Option<u64>,
+    /// The PSS of the Java heap, in the `App Summary` section.
+    pub java_heap: Option<u64>,
+    /// The PSS of the native heap, in the `App Summary` section.
+    pub native_heap: Option<u64>,
+}
+
+/// Splits a line into its `<key>: <value>` fields, several fields being separated by
+/// spaces, e.g. `TOTAL PSS:    23624            TOTAL RSS:    58108`.
+fn parse_fields(line: &str) -> Vec<(String, &str)> {
+    let mut parts = line.split(':');
+    let mut key = parts.next().unwrap_or_default().trim().to_string();
+    let mut fields = Vec::new();
+    for part in parts {
+        let mut words = part.split_whitespace();
+        let Some(value) = words.next() else {
+            break;
+        };
+        fields.push((key, value));
+        key = words.collect::<Vec<_>>().join(" ");
+    }
+    fields
+}
+
+impl MemInfo {
+    /// Parses the output of `dumpsys meminfo <process>`, failing without a total, e.g. with
+    /// `No process found for: <process>`.
+    pub fn parse(dump: &str) -> Result<Self, AdbError> {
+        let mut info = Self::default();
+        // The `TOTAL` row of the table, read before Android 6 only.
+        let mut table_total = None;
+        let mut summary_total = None;
+        for line in dump.lines().map(str::trim) {
+            if let Some(row) = line.strip_prefix("TOTAL ").filter(|_| !line.contains(':')) {
+                table_total = table_total.or_else(|| row.split_whitespace().next()?.parse().ok());
+                continue;
+            }
+            for (key, value) in parse_fields(line) {
+                let value = value.parse().ok();
+                match key.as_str() {
+                    "TOTAL PSS" | "TOTAL" => summary_total = value,
+                    "TOTAL RSS" => info.total_rss = value,
+                    "TOTAL SWAP PSS" => info.total_swap_pss = value,
+                    "Java Heap" => info.java_heap = value,
+                    "Native Heap" => info.native_heap = value,
+                    _ => {}
+                }
+            }
+        }
+        info.total_pss = summary_total
+            .or(table_total)
+            .ok_or_else(|| AdbError::Parse {
+                value: dump.lines().next().unwrap_or_default().to_string(),
+                source_type: "&str",
+                target_type: "MemInfo",
+                source: None,
+            })?;
+        Ok(info)
+    }
+}
+
+impl Device {
+    /// Returns the dump of the system service `service` (`dumpsys <service>`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let dump = device.dumpsys("connectivity").unwrap();
+    /// assert!(dump.contains("NetworkAgentInfo"));
+    /// ```
+    pub fn dumpsys(&self, service: &str) -> Result<String, AdbError> {
+        self.dumpsys_args::<&str>(service, &[])
+    }
+
+    /// Returns the dump of the system service `service` with the arguments `args`, e.g.
+    /// `activity` and `["processes"]` (`dumpsys <service> <args>...`).
+    pub fn dumpsys_args<S: AsRef<str>>(
+        &self,
+        service: &str,
+        args: &[S],
+    ) -> Result<String, AdbError> {
+        self.shell_checked(&dumpsys_command(service, args))
+    }
+
+    /// Returns the state of the battery (`dumpsys battery`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::dumpsys::BatteryStatus;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let battery = device.battery().unwrap();
+    /// if battery.status == BatteryStatus::Charging {
+    ///     println!("charging, {:?}%", battery.percent());
+    /// }
+    /// ```
+    pub fn battery(&self) -> Result<BatteryState, AdbError> {
+        BatteryState::parse(&self.dumpsys("battery")?)
+    }
+
+    /// Returns the focused activity, e.g. `com.example/.MainActivity`, `None` if no activity
+    /// is focused (`dumpsys window`).
+    pub fn focused_activity(&self) -> Result<Option<String>, AdbError> {
+        let dump = self.dumpsys("window")?;
+        Ok(parse_focused_activity(&dump).map(str::to_string))
+    }
+
+    /// Returns the memory of the process `process`, a pid or a process name
+    /// (`dumpsys meminfo <process>`).
+    pub fn meminfo(&self, process: &str) -> Result<MemInfo, AdbError> {
+        MemInfo::parse(&self.dumpsys_args("meminfo", &[process])?)
+    }
+}
+
+fn dumpsys_command<S: AsRef<str>>(service: &str, args: &[S]) -> String {
+    let mut command = format!("dumpsys {}", shell::quote(service));
+    for arg in args {
+        command.push(' ');
+        command.push_str(&shell::quote(arg.as_ref()));
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BATTERY_ANDROID_7: &str = "\
+Current Battery Service state:
+  AC powered: false
+  USB powered: true
+  Wireless powered: false
+  Max charging current: 500000
+  Max charging voltage: 5000000
+  Charge counter: 2716000
+  status: 2
+  health: 2
+  present: true
+  level: 85
+  scale: 100
+  voltage: 4273
+  temperature: 281
+  technology: Li-ion
+";
+
+    // An emulator, with updates stopped by `dumpsys battery unplug`.
+    const BATTERY_ANDROID_14: &str = "\
+Current Battery Service state:
+(UPDATES STOPPED -- use 'reset' to restart)
+  AC powered: false
+  USB powered: false
+  Wireless powered: false
+  Dock powered: false
+  Max charging current: 0
+  Max charging voltage: 0
+  Charge counter: 10000
+  status: 4
+  health: 2
+  present: true
+  level: 50
+  scale: 200
+  voltage: 5000
+  temperature: 250
+  technology: Li-ion
+  Charging state: 1
+  Charging policy: 1
+  Capacity level: 3
+";
+
+    #[test]
+    fn test_battery_parse() {
+        let battery = BatteryState::parse(BATTERY_ANDROID_7).unwrap();
+        assert_eq!(
+            BatteryState {
+                ac_powered: false,
+                usb_powered: true,
+                wireless_powered: false,
+                status: BatteryStatus::Charging,
+                present: Some(true),
+                level: Some(85),
+                scale: Some(100),
+                voltage: Some(4273),
+                temperature: Some(281),
+                technology: Some("Li-ion".to_string()),
+            },
+            battery
+        );
+        assert!(battery.is_plugged());
+        assert_eq!(Some(85), battery.percent());
+
+        let battery = BatteryState::parse(BATTERY_ANDROID_14).unwrap();
+        assert_eq!(BatteryStatus::NotCharging, battery.status);
+        assert!(!battery.is_plugged());
+        assert_eq!(Some(25), battery.percent());
+
+        assert!(BatteryState::parse("Can't find service: battery\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_focused_activity() {
+        let android_14 = "\
+WINDOW MANAGER WINDOWS (dumpsys window windows)
+  Window #0 Window{2b4c1f0 u0 NavigationBar0}:
+  mCurrentFocus=Window{8f1d6a3 u0 com.example/com.example.MainActivity}
+  mFocusedApp=ActivityRecord{6f0c0c u0 com.example/.MainActivity t12}
+";
+        assert_eq!(
+            Some("com.example/.MainActivity"),
+            parse_focused_activity(android_14)
+        );
+        let android_9 = "\
+  mCurrentFocus=Window{5d1e2a u0 StatusBar}
+  mFocusedApp=AppWindowToken{a1b2c3 token=Token{d4e5f6 ActivityRecord{778899 u0 com.android.settings/.Settings t7}}}
+";
+        assert_eq!(
+            Some("com.android.settings/.Settings"),
+            parse_focused_activity(android_9)
+        );
+        // The focused window of the app, without a focused app line.
+        let focus_only = "  mCurrentFocus=Window{8f1d6a3 u10 com.example/com.example.Main}\n";
+        assert_eq!(
+            Some("com.example/com.example.Main"),
+            parse_focused_activity(focus_only)
+        );
+        assert_eq!(
+            None,
+            parse_focused_activity("  mCurrentFocus=null\n  mFocusedApp=null\n")
+        );
+    }
+
+    const MEMINFO_ANDROID_14: &str = "\
+Applications Memory Usage (in Kilobytes):
+Uptime: 2716042 Realtime: 2716042
+
+** MEMINFO in pid 4242 [com.example] **
+                   Pss  Private  Private  SwapPss      Rss     Heap     Heap     Heap
+                 Total    Dirty    Clean    Dirty    Total     Size    Alloc     Free
+                ------   ------   ------   ------   ------   ------   ------   ------
+  Native Heap     8612     8564        0       24     9964    12288     9870     2417
+  Dalvik Heap     2874     2812        0       55    12624     4096     2048     2048
+        TOTAL    23624    16220     3716       79    58108    16384    11918     4465
+
+ App Summary
+                       Pss(KB)                        Rss(KB)
+                        ------                         ------
+           Java Heap:     3052                          12624
+         Native Heap:     8664                           9964
+                Code:     3428                          30276
+
+           TOTAL PSS:    23624            TOTAL RSS:    58108       TOTAL SWAP PSS:       79
+";
+
+    const MEMINFO_ANDROID_8: &str = "\
+** MEMINFO in pid 3131 [com.example] **
+                   Pss  Private  Private  SwapPss     Heap     Heap     Heap
+                 Total    Dirty    Clean    Dirty     Size    Alloc     Free
+                ------   ------   ------   ------   ------   ------   ------
+        TOTAL    27468    20140     3904       27    16384    11918     4465
+
+ App Summary
+                       Pss(KB)
+                        ------
+           Java Heap:     3416
+         Native Heap:     8232
+
+               TOTAL:    27468       TOTAL SWAP PSS:       27
+";
+
+    const MEMINFO_ANDROID_5: &str = "\
+** MEMINFO in pid 2020 [com.example] **
+                   Pss  Private  Private  Swapped     Heap     Heap     Heap
+                 Total    Dirty    Clean    Dirty     Size    Alloc     Free
+                ------   ------   ------   ------   ------   ------   ------
+  Native Heap     4120     4076        0        0     8192     5213     2978
+        TOTAL    15320    11032     2540        0    12288     8412     3875
+";
+
+    #[test]
+    fn test_meminfo_parse() {
+        assert_eq!(
+            MemInfo {
+                total_pss: 23624,
+                total_rss: Some(58108),
+                total_swap_pss: Some(79),
+                java_heap: Some(3052),
+                native_heap: Some(8664),
+            },
+            MemInfo::parse(MEMINFO_ANDROID_14).unwrap()
+        );
+        assert_eq!(
+            MemInfo {
+                total_pss: 27468,
+                total_rss: None,
+                total_swap_pss: Some(27),
+                java_heap: Some(3416),
+                native_heap: Some(8232),
+            },
+            MemInfo::parse(MEMINFO_ANDROID_8).unwrap()
+        );
+        assert_eq!(
+            MemInfo {
+                total_pss: 15320,
+                ..MemInfo::default()
+            },
+            MemInfo::parse(MEMINFO_ANDROID_5).unwrap()
+        );
+        assert!(MemInfo::parse("No process found for: com.example\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        assert_eq!(
+            [
+                ("TOTAL PSS".to_string(), "23624"),
+                ("TOTAL RSS".to_string(), "58108")
+            ],
+            &parse_fields("TOTAL PSS:    23624            TOTAL RSS:    58108")[..]
+        );
+        assert!(parse_fields("Unknown:").is_empty());
+    }
+
+    #[test]
+    fn test_dumpsys_command() {
+        assert_eq!("dumpsys 'battery'", dumpsys_command::<&str>("battery", &[]));
+        assert_eq!(
+            "dumpsys 'meminfo' 'com.example; reboot'",
+            dumpsys_command("meminfo", &["com.example; reboot"])
+        );
+    }
+}
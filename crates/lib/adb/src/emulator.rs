@@ -0,0 +1,390 @@
+//! This module provides a client of the emulator console, the telnet-like interface each
+//! emulator exposes on `localhost`, at the port in its serial number (`emulator-<port>`).
+//!
+//! Commands are lines, answered by lines of output terminated by `OK`, or by `KO: <error>`.
+//! The console greets clients with a banner terminated by `OK` as well, and since emulator
+//! 28.0.3 requires an `auth <token>` command first, with the token stored in
+//! `~/.emulator_console_auth_token`.
+//!
+//! The common commands of test farms are typed, like `adb -e emu`: moving the device
+//! ([`EmulatorConsole::geo_fix`]), receiving text messages, simulating the battery, rotating
+//! the screen and saving and loading snapshots.
+//!
+//! Snapshots restore a pristine emulator in seconds, so [`Device`] saves, loads and lists
+//! them directly, like `adb emu avd snapshot`.
+
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::path::PathBuf;
+
+use crate::device::Device;
+use crate::error::AdbError;
+
+/// The prefix of the serial numbers of emulators.
+pub const SERIAL_PREFIX: &str = "emulator-";
+
+/// Returns the console port of the emulator with the serial number `serial`.
+pub fn console_port(serial: &str) -> Option<u16> {
+    serial.strip_prefix(SERIAL_PREFIX)?.parse().ok()
+}
+
+/// Returns the path of the auth token of the console, `~/.emulator_console_auth_token`.
+pub fn auth_token_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".emulator_console_auth_token"))
+}
+
+/// The state of the simulated battery, set by [`EmulatorConsole::power_status`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum PowerStatus {
+    Unknown,
+    Charging,
+    Discharging,
+    NotCharging,
+    Full,
+}
+
+impl Display for PowerStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Unknown => "unknown",
+            Self::Charging => "charging",
+            Self::Discharging => "discharging",
+            Self::NotCharging => "not-charging",
+            Self::Full => "full",
+        })
+    }
+}
+
+/// A snapshot of an emulator, listed by [`EmulatorConsole::snapshot_list`].
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Snapshot {
+    /// The name of the snapshot, e.g. `default_boot`.
+    pub name: String,
+    /// The size of the saved memory, e.g. `94M`.
+    pub vm_size: String,
+    /// The date the snapshot was saved, e.g. `2024-01-01 12:00:00`.
+    pub date: String,
+    /// The time the emulator had been running when saved, e.g. `00:01:23.456`.
+    pub vm_clock: String,
+}
+
+/// Parses the output of `avd snapshot list`, a table with the columns `ID`, `TAG`,
+/// `VM SIZE`, `DATE` and `VM CLOCK`.
+fn parse_snapshots(list: &str) -> Vec<Snapshot> {
+    list.lines()
+        .filter_map(|line| {
+            let columns: Vec<_> = line.split_whitespace().collect();
+            match columns[..] {
+                [id, name, vm_size, day, time, vm_clock] if id != "ID" => Some(Snapshot {
+                    name: name.to_string(),
+                    vm_size: vm_size.to_string(),
+                    date: format!("{} {}", day, time),
+                    vm_clock: vm_clock.to_string(),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Checks that `arg` doesn't end the command line, or split into several arguments unless
+/// `spaces` is set.
+fn check_arg(arg: &str, spaces: bool) -> Result<&str, AdbError> {
+    if arg.is_empty() || arg.contains(['\r', '\n']) || (!spaces && arg.contains(' ')) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid emulator console argument {:?}", arg),
+        )
+        .into());
+    }
+    Ok(arg)
+}
+
+/// Reads lines until `OK` or `KO`, returning the lines before `OK`.
+fn read_reply<R: BufRead>(reader: &mut R) -> Result<String, AdbError> {
+    let mut reply = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(AdbError::Protocol {
+                code: None,
+                message: "the emulator console closed the connection".to_string(),
+            });
+        }
+        let trimmed = line.trim_end();
+        if trimmed == "OK" {
+            return Ok(reply);
+        }
+        if let Some(error) = trimmed.strip_prefix("KO:") {
+            return Err(AdbError::Server {
+                message: error.trim().to_string(),
+            });
+        }
+        reply.push_str(trimmed);
+        reply.push('\n');
+    }
+}
+
+/// A connection to the console of an emulator.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::emulator::EmulatorConsole;
+///
+/// let mut console = EmulatorConsole::for_serial("emulator-5554").unwrap();
+/// println!("{}", console.command("avd name").unwrap());
+/// ```
+#[derive(Debug)]
+pub struct EmulatorConsole {
+    reader: BufReader<TcpStream>,
+}
+
+impl EmulatorConsole {
+    /// Connects to the console listening on `port` of `localhost`, authenticating with the
+    /// token of [`auth_token_path`] if required.
+    pub fn connect(port: u16) -> Result<Self, AdbError> {
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+        let mut console = Self {
+            reader: BufReader::new(stream),
+        };
+        let banner = read_reply(&mut console.reader)?;
+        if banner.contains("Authentication required") {
+            let path = auth_token_path().ok_or_else(|| AdbError::Server {
+                message: "no home directory to find the console auth token".to_string(),
+            })?;
+            let token = fs::read_to_string(path)?;
+            console.command(&format!("auth {}", token.trim()))?;
+        }
+        Ok(console)
+    }
+
+    /// Connects to the console of the emulator with the serial number `serial`, e.g.
+    /// `emulator-5554`.
+    pub fn for_serial(serial: &str) -> Result<Self, AdbError> {
+        let port = console_port(serial).ok_or_else(|| AdbError::Server {
+            message: format!("{} isn't an emulator", serial),
+        })?;
+        Self::connect(port)
+    }
+
+    /// Runs `command`, e.g. `network speed full`, and returns its output.
+    pub fn command(&mut self, command: &str) -> Result<String, AdbError> {
+        let stream = self.reader.get_mut();
+        stream.write_all(command.as_bytes())?;
+        stream.write_all(b"\n")?;
+        read_reply(&mut self.reader)
+    }
+
+    /// Moves the device to `longitude` and `latitude` in degrees, and `altitude` in meters
+    /// (`geo fix`).
+    pub fn geo_fix(
+        &mut self,
+        longitude: f64,
+        latitude: f64,
+        altitude: Option<f64>,
+    ) -> Result<(), AdbError> {
+        let mut command = format!("geo fix {} {}", longitude, latitude);
+        if let Some(altitude) = altitude {
+            command.push_str(&format!(" {}", altitude));
+        }
+        self.command(&command).map(drop)
+    }
+
+    /// Receives a text message from `sender` (`sms send`).
+    pub fn sms_send(&mut self, sender: &str, text: &str) -> Result<(), AdbError> {
+        let command = format!(
+            "sms send {} {}",
+            check_arg(sender, false)?,
+            check_arg(text, true)?
+        );
+        self.command(&command).map(drop)
+    }
+
+    /// Sets the state of the battery (`power status`).
+    pub fn power_status(&mut self, status: PowerStatus) -> Result<(), AdbError> {
+        self.command(&format!("power status {}", status)).map(drop)
+    }
+
+    /// Sets the level of the battery, from 0 to 100 (`power capacity`).
+    pub fn power_capacity(&mut self, percent: u8) -> Result<(), AdbError> {
+        self.command(&format!("power capacity {}", percent.min(100)))
+            .map(drop)
+    }
+
+    /// Plugs or unplugs the charger (`power ac`).
+    pub fn power_ac(&mut self, connected: bool) -> Result<(), AdbError> {
+        let state = if connected { "on" } else { "off" };
+        self.command(&format!("power ac {}", state)).map(drop)
+    }
+
+    /// Rotates the screen by 90 degrees counterclockwise (`rotate`).
+    pub fn rotate(&mut self) -> Result<(), AdbError> {
+        self.command("rotate").map(drop)
+    }
+
+    /// Saves the state of the emulator to the snapshot `name` (`avd snapshot save`).
+    pub fn snapshot_save(&mut self, name: &str) -> Result<(), AdbError> {
+        let command = format!("avd snapshot save {}", check_arg(name, false)?);
+        self.command(&command).map(drop)
+    }
+
+    /// Restores the state of the emulator from the snapshot `name` (`avd snapshot load`).
+    pub fn snapshot_load(&mut self, name: &str) -> Result<(), AdbError> {
+        let command = format!("avd snapshot load {}", check_arg(name, false)?);
+        self.command(&command).map(drop)
+    }
+
+    /// Lists the snapshots of the emulator (`avd snapshot list`).
+    pub fn snapshot_list(&mut self) -> Result<Vec<Snapshot>, AdbError> {
+        Ok(parse_snapshots(&self.command("avd snapshot list")?))
+    }
+}
+
+impl Device {
+    /// Returns the console of the emulator, or `None` if the device isn't an emulator.
+    pub(crate) fn emulator_console(&self) -> Result<Option<EmulatorConsole>, AdbError> {
+        let serial = match self.serial() {
+            Some(serial) => serial.to_string(),
+            None => self.get_serialno()?,
+        };
+        match console_port(&serial) {
+            Some(port) => Ok(Some(EmulatorConsole::connect(port)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the console of the emulator, failing if the device isn't an emulator.
+    fn snapshot_console(&self) -> Result<EmulatorConsole, AdbError> {
+        self.emulator_console()?.ok_or_else(|| AdbError::Server {
+            message: "snapshots require an emulator".to_string(),
+        })
+    }
+
+    /// Saves the state of the emulator to the snapshot `name` (`adb emu avd snapshot save`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let emulator = AdbServer::default().device("emulator-5554");
+    /// emulator.save_snapshot("pristine").unwrap();
+    /// // Run a test dirtying the device.
+    /// emulator.load_snapshot("pristine").unwrap();
+    /// ```
+    pub fn save_snapshot(&self, name: &str) -> Result<(), AdbError> {
+        self.snapshot_console()?.snapshot_save(name)
+    }
+
+    /// Restores the state of the emulator from the snapshot `name`
+    /// (`adb emu avd snapshot load`).
+    pub fn load_snapshot(&self, name: &str) -> Result<(), AdbError> {
+        self.snapshot_console()?.snapshot_load(name)
+    }
+
+    /// Lists the snapshots of the emulator (`adb emu avd snapshot list`).
+    pub fn list_snapshots(&self) -> Result<Vec<Snapshot>, AdbError> {
+        self.snapshot_console()?.snapshot_list()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_console_port() {
+        assert_eq!(Some(5554), console_port("emulator-5554"));
+        assert_eq!(None, console_port("R58M123ABC"));
+    }
+
+    #[test]
+    fn test_read_reply() {
+        let mut reader = Cursor::new(
+            "Android Console: Authentication required\r\n\
+             Android Console: type 'auth <auth_token>' to authenticate\r\n\
+             OK\r\n\
+             KO: unknown command, try 'help'\r\n",
+        );
+        let banner = read_reply(&mut reader).unwrap();
+        assert!(banner.contains("Authentication required"));
+        assert!(matches!(
+            read_reply(&mut reader),
+            Err(AdbError::Server { message }) if message == "unknown command, try 'help'"
+        ));
+        assert!(matches!(
+            read_reply(&mut reader),
+            Err(AdbError::Protocol { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_snapshots() {
+        let list = "\
+List of snapshots present on all disks:
+ID        TAG                 VM SIZE                DATE       VM CLOCK
+--        default_boot            94M 2024-01-01 12:00:00   00:01:23.456
+--        pristine               101M 2024-01-02 08:30:00   00:00:42.000
+";
+        let snapshots = parse_snapshots(list);
+        assert_eq!(2, snapshots.len());
+        assert_eq!(
+            Snapshot {
+                name: "pristine".to_string(),
+                vm_size: "101M".to_string(),
+                date: "2024-01-02 08:30:00".to_string(),
+                vm_clock: "00:00:42.000".to_string(),
+            },
+            snapshots[1]
+        );
+        assert!(parse_snapshots("There is no snapshot available.\n").is_empty());
+    }
+
+    #[test]
+    fn test_typed_commands() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let emulator = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            reader
+                .get_mut()
+                .write_all(b"Android Console\r\nOK\r\n")
+                .unwrap();
+            let mut commands = Vec::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                commands.push(line.trim_end().to_string());
+                line.clear();
+                reader.get_mut().write_all(b"OK\r\n").unwrap();
+            }
+            commands
+        });
+        let mut console = EmulatorConsole::connect(port).unwrap();
+        console.geo_fix(-122.084, 37.422, Some(5.0)).unwrap();
+        console.sms_send("5551234", "hello world").unwrap();
+        console.power_status(PowerStatus::NotCharging).unwrap();
+        console.snapshot_save("boot").unwrap();
+        assert!(console.snapshot_load("two words").is_err());
+        assert!(console.sms_send("5551234", "hello\nkill").is_err());
+        drop(console);
+        assert_eq!(
+            vec![
+                "geo fix -122.084 37.422 5",
+                "sms send 5551234 hello world",
+                "power status not-charging",
+                "avd snapshot save boot",
+            ],
+            emulator.join().unwrap()
+        );
+    }
+}
@@ -1,21 +1,227 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::time::Duration;
+
+pub use adb_types::error::ParseError;
 
 /// Error type for the adb crate.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum AdbError {
-    /// Failed to parse a value.
+    /// Failed to parse a value, converted from a [`ParseError`] for the socket families.
     Parse {
         value: String,
         source_type: &'static str,
         target_type: &'static str,
-        source: Option<Box<dyn Error>>,
+        source: Option<Box<dyn Error + Send + Sync>>,
+    },
+    /// An I/O error occurred while talking to the adb server or a device.
+    Io(std::io::Error),
+    /// The adb server or a device rejected a request with `FAIL`.
+    Server { message: String },
+    /// The adb server or a device replied with something this client doesn't understand.
+    Protocol {
+        /// The unexpected id received, if any: a message command, a shell packet id, or a
+        /// status or sync id as a little-endian `u32`, e.g. `0x54414857` for `WHAT`.
+        code: Option<u32>,
+        message: String,
+    },
+    /// The device isn't connected, or no device is when any would do.
+    DeviceNotFound {
+        /// The serial of the device, `None` if any device was requested.
+        serial: Option<String>,
+    },
+    /// The device is connected, but doesn't respond.
+    DeviceOffline,
+    /// The device didn't accept the key of the host, and shows a prompt to authorize it.
+    Unauthorized,
+    /// The adb server speaks a different protocol version than this client.
+    VersionMismatch { server: u32, client: u32 },
+    /// A condition wasn't met before the timeout.
+    Timeout {
+        /// What was waited for, e.g. `file /sdcard/done exists`.
+        condition: String,
+        timeout: Duration,
+    },
+    /// The adb server closed the connection of a host service before replying, usually
+    /// because another client killed or restarted it.
+    ServerGone {
+        /// The service requested, e.g. `host:devices`.
+        service: String,
     },
+    /// The operation was cancelled with a [`CancellationToken`](crate::cancel::CancellationToken).
+    #[cfg(feature = "client")]
+    Cancelled,
+    /// The device or the server lacks a feature the operation needs.
+    #[cfg(feature = "client")]
+    Unsupported {
+        needed: crate::features::Feature,
+        /// The features supported by both the device and the server.
+        have: crate::features::Features,
+    },
+    /// The adb server is too old for the operation.
+    #[cfg(feature = "client")]
+    ServerUnsupported {
+        needed: crate::version::ServerCapability,
+        /// The protocol version of the server.
+        found: crate::version::AdbVersion,
+    },
+    /// The package manager rejected an install or uninstall.
+    #[cfg(feature = "install")]
+    Install(crate::install::InstallError),
+    /// A request failed with `source`, in the operation `id` reported to the observer of the
+    /// server, see [`crate::op`].
+    ///
+    /// [`AdbError::inner`] returns `source`, to match the error whether it's in an operation
+    /// or not.
+    #[cfg(feature = "client")]
+    Op {
+        id: crate::op::OpId,
+        source: Box<AdbError>,
+    },
+}
+
+impl AdbError {
+    /// Returns the id of the operation the error happened in, if any.
+    #[cfg(feature = "client")]
+    pub fn op_id(&self) -> Option<crate::op::OpId> {
+        match self {
+            Self::Op { id, .. } => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Returns the error without the [operation](Self::Op) it happened in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use adb::error::AdbError;
+    /// use adb::op::OpId;
+    ///
+    /// let error = AdbError::Op {
+    ///     id: OpId::from_raw(7),
+    ///     source: Box::new(AdbError::DeviceOffline),
+    /// };
+    /// assert_eq!("op#7: device offline", error.to_string());
+    /// assert!(matches!(error.inner(), AdbError::DeviceOffline));
+    /// ```
+    pub fn inner(&self) -> &AdbError {
+        match self {
+            #[cfg(feature = "client")]
+            Self::Op { source, .. } => source.inner(),
+            e => e,
+        }
+    }
+
+    /// Like [`Self::inner`], taking the error.
+    pub fn into_inner(self) -> AdbError {
+        match self {
+            #[cfg(feature = "client")]
+            Self::Op { source, .. } => source.into_inner(),
+            e => e,
+        }
+    }
+
+    /// Converts the error into an I/O error, for [`std::io::Read`] and [`std::io::Write`]
+    /// implementations.
+    #[cfg(any(feature = "shell", feature = "usb", feature = "tls", feature = "scan"))]
+    pub(crate) fn into_io(self) -> std::io::Error {
+        match self {
+            Self::Io(e) => e,
+            // Keeps the kind of the I/O error of an operation.
+            e if matches!(e.inner(), Self::Io(_)) => e.into_inner().into_io(),
+            e => crate::compat::io_other(e.to_string()),
+        }
+    }
+
+    /// Returns the error of a `FAIL` reply with `message`, recognizing the device errors of
+    /// the adb server, e.g. `device 'emulator-5554' not found`.
+    #[cfg(feature = "client")]
+    pub(crate) fn from_fail(message: String) -> Self {
+        if let Some(serial) = message
+            .strip_prefix("device '")
+            .and_then(|rest| rest.strip_suffix("' not found"))
+        {
+            return Self::DeviceNotFound {
+                serial: Some(serial.to_string()),
+            };
+        }
+        // The reply to `host:disconnect:` for a device which isn't connected.
+        if let Some(serial) = message
+            .strip_prefix("no such device '")
+            .and_then(|rest| rest.strip_suffix('\''))
+        {
+            return Self::DeviceNotFound {
+                serial: Some(serial.to_string()),
+            };
+        }
+        match message.lines().next().unwrap_or_default() {
+            "no devices/emulators found" | "no devices found" | "no emulators found" => {
+                Self::DeviceNotFound { serial: None }
+            }
+            "device offline" | "device still connecting" => Self::DeviceOffline,
+            "device unauthorized." | "device still authorizing" => Self::Unauthorized,
+            _ => Self::Server { message },
+        }
+    }
+
+    /// Returns [`Self::ServerGone`] for the I/O errors of a server closing the connection of
+    /// a request for `service`, and the error itself otherwise.
+    #[cfg(feature = "client")]
+    pub(crate) fn server_gone(self, service: &str) -> Self {
+        match self {
+            Self::Io(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::ConnectionReset
+                        | ErrorKind::ConnectionAborted
+                        | ErrorKind::BrokenPipe
+                        | ErrorKind::UnexpectedEof
+                ) =>
+            {
+                Self::ServerGone {
+                    service: service.to_string(),
+                }
+            }
+            e => e,
+        }
+    }
+
+    /// Returns `true` if the operation may succeed when retried as is: transient I/O errors,
+    /// a server going away, offline devices and timeouts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::ErrorKind;
+    /// use adb::error::AdbError;
+    ///
+    /// assert!(AdbError::Io(ErrorKind::ConnectionReset.into()).is_retryable());
+    /// assert!(!AdbError::DeviceNotFound { serial: None }.is_retryable());
+    /// ```
+    pub fn is_retryable(&self) -> bool {
+        match self.inner() {
+            Self::Io(e) => matches!(
+                e.kind(),
+                ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::TimedOut
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::Interrupted
+                    | ErrorKind::UnexpectedEof
+            ),
+            Self::ServerGone { .. } | Self::DeviceOffline | Self::Timeout { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 impl Display for AdbError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        std::io::Error::new(std::io::ErrorKind::Other, "test").source();
         match self {
             Self::Parse {
                 value,
@@ -34,6 +240,59 @@ impl Display for AdbError {
                     Ok(())
                 }
             }
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Server { message } => write!(f, "request failed: {}", message),
+            Self::Protocol { message, .. } => write!(f, "protocol error: {}", message),
+            Self::DeviceNotFound {
+                serial: Some(serial),
+            } => write!(f, "device `{}` not found", serial),
+            Self::DeviceNotFound { serial: None } => f.write_str("no device found"),
+            Self::DeviceOffline => f.write_str("device offline"),
+            Self::Unauthorized => {
+                f.write_str("device unauthorized, accept the debugging prompt on the device")
+            }
+            Self::VersionMismatch { server, client } => write!(
+                f,
+                "adb server version ({}) doesn't match this client ({})",
+                server, client
+            ),
+            Self::Timeout { condition, timeout } => {
+                write!(f, "timed out after {:?} waiting for {}", timeout, condition)
+            }
+            Self::ServerGone { service } => write!(
+                f,
+                "adb server went away while answering `{}`, another client may have \
+                killed or restarted it",
+                service
+            ),
+            #[cfg(feature = "client")]
+            Self::Cancelled => f.write_str("operation cancelled"),
+            #[cfg(feature = "client")]
+            Self::Unsupported { needed, have } if have.is_empty() => {
+                write!(
+                    f,
+                    "unsupported: needs the `{}` feature, none supported",
+                    needed
+                )
+            }
+            #[cfg(feature = "client")]
+            Self::Unsupported { needed, have } => write!(
+                f,
+                "unsupported: needs the `{}` feature, only {} supported",
+                needed, have
+            ),
+            #[cfg(feature = "client")]
+            Self::ServerUnsupported { needed, found } => write!(
+                f,
+                "unsupported by the adb server: {} needs version {}, found {}",
+                needed,
+                needed.min_version().0,
+                found.0
+            ),
+            #[cfg(feature = "install")]
+            Self::Install(e) => write!(f, "install failed: {}", e),
+            #[cfg(feature = "client")]
+            Self::Op { id, source } => write!(f, "{}: {}", id, source),
         }
     }
 }
@@ -41,7 +300,138 @@ impl Display for AdbError {
 impl Error for AdbError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::Parse { source, .. } => source.as_deref(),
+            Self::Parse { source, .. } => source.as_deref().map(|e| e as &(dyn Error + 'static)),
+            Self::Io(e) => Some(e),
+            Self::Server { .. }
+            | Self::Protocol { .. }
+            | Self::DeviceNotFound { .. }
+            | Self::DeviceOffline
+            | Self::Unauthorized
+            | Self::VersionMismatch { .. }
+            | Self::Timeout { .. }
+            | Self::ServerGone { .. } => None,
+            #[cfg(feature = "client")]
+            Self::Cancelled | Self::Unsupported { .. } | Self::ServerUnsupported { .. } => None,
+            #[cfg(feature = "install")]
+            Self::Install(e) => Some(e),
+            #[cfg(feature = "client")]
+            Self::Op { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AdbError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ParseError> for AdbError {
+    fn from(e: ParseError) -> Self {
+        Self::Parse {
+            value: e.value,
+            source_type: e.source_type,
+            target_type: e.target_type,
+            source: e.source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<AdbError>();
+    }
+
+    #[test]
+    #[cfg(feature = "client")]
+    fn test_from_fail() {
+        assert!(matches!(
+            AdbError::from_fail("device 'emulator-5554' not found".to_string()),
+            AdbError::DeviceNotFound { serial: Some(serial) } if serial == "emulator-5554"
+        ));
+        assert!(matches!(
+            AdbError::from_fail("no such device '192.168.1.20:5555'".to_string()),
+            AdbError::DeviceNotFound { serial: Some(serial) } if serial == "192.168.1.20:5555"
+        ));
+        assert!(matches!(
+            AdbError::from_fail("no devices/emulators found".to_string()),
+            AdbError::DeviceNotFound { serial: None }
+        ));
+        assert!(matches!(
+            AdbError::from_fail("device offline".to_string()),
+            AdbError::DeviceOffline
+        ));
+        assert!(matches!(
+            AdbError::from_fail(
+                "device unauthorized.\nThis adb server's $ADB_VENDOR_KEYS is not set\n".to_string()
+            ),
+            AdbError::Unauthorized
+        ));
+        assert!(matches!(
+            AdbError::from_fail("more than one device/emulator".to_string()),
+            AdbError::Server { .. }
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "client")]
+    fn test_server_gone() {
+        assert!(matches!(
+            AdbError::Io(ErrorKind::UnexpectedEof.into()).server_gone("host:version"),
+            AdbError::ServerGone { service } if service == "host:version"
+        ));
+        assert!(matches!(
+            AdbError::Io(ErrorKind::PermissionDenied.into()).server_gone("host:version"),
+            AdbError::Io(_)
+        ));
+        assert!(matches!(
+            AdbError::DeviceOffline.server_gone("host:version"),
+            AdbError::DeviceOffline
+        ));
+    }
+
+    #[test]
+    fn test_from_parse_error() {
+        let err = AdbError::from(crate::socket::Jdwp::try_from("jdwp:0").unwrap_err());
+        assert!(matches!(
+            &err,
+            AdbError::Parse {
+                target_type: "Jdwp",
+                source: Some(_),
+                ..
+            }
+        ));
+        assert_eq!(
+            "failed when parsing `0` from `u32` into `Jdwp`: no process has the pid 0",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(AdbError::Io(ErrorKind::ConnectionRefused.into()).is_retryable());
+        assert!(!AdbError::Io(ErrorKind::PermissionDenied.into()).is_retryable());
+        assert!(AdbError::DeviceOffline.is_retryable());
+        assert!(AdbError::Timeout {
+            condition: "boot completed".to_string(),
+            timeout: Duration::from_secs(1),
+        }
+        .is_retryable());
+        assert!(AdbError::ServerGone {
+            service: "host:devices".to_string()
+        }
+        .is_retryable());
+        assert!(!AdbError::Unauthorized.is_retryable());
+        assert!(!AdbError::Server {
+            message: "closed".to_string()
         }
+        .is_retryable());
+        #[cfg(feature = "client")]
+        assert!(!AdbError::Cancelled.is_retryable());
     }
 }
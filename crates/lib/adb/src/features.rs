@@ -0,0 +1,388 @@
+//! This module provides the features negotiated between the adb server and devices.
+//!
+//! Devices list their features in the banner of their `CNXN` message, and the server
+//! reports those it supports as well (`host-serial:<serial>:features`), or its own
+//! (`host:host-features`). The higher-level APIs consult them to pick the best protocol
+//! variant, e.g. the shell v2 protocol for shell commands.
+
+use std::collections::btree_set::{self, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::server::AdbServer;
+
+/// A feature of the adb protocol, as listed in `host:features`.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[non_exhaustive]
+pub enum Feature {
+    /// The shell v2 protocol, with separate stdout and stderr and exit codes.
+    ShellV2,
+    /// The `cmd` binary, e.g. `cmd package install` streaming APKs from stdin.
+    Cmd,
+    /// The `STA2` and `LST2` sync requests, with 64-bit sizes and all the metadata.
+    StatV2,
+    /// The `LIS2` sync request, listing directories with all the metadata.
+    LsV2,
+    Libusb,
+    PushSync,
+    /// APEX packages can be installed.
+    Apex,
+    /// Pushing a file creates its missing parent directories.
+    FixedPushMkdir,
+    /// The binder bridge, `abb:`.
+    Abb,
+    FixedPushSymlinkTimestamp,
+    /// The binder bridge without a pty, `abb_exec:`.
+    AbbExec,
+    RemountShell,
+    TrackApp,
+    /// The `SND2` and `RCV2` sync requests, with compression.
+    SendrecvV2,
+    SendrecvV2Brotli,
+    SendrecvV2Lz4,
+    SendrecvV2Zstd,
+    SendrecvV2DryRunSend,
+    DelayedAck,
+    OpenscreenMdns,
+    DeviceTrackerProtoFormat,
+    DevRaw,
+    AppInfo,
+    ServerStatus,
+    /// A feature unknown to this crate.
+    Other(String),
+}
+
+impl Feature {
+    /// Returns the name of the feature in the protocol, e.g. `shell_v2`.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::ShellV2 => "shell_v2",
+            Self::Cmd => "cmd",
+            Self::StatV2 => "stat_v2",
+            Self::LsV2 => "ls_v2",
+            Self::Libusb => "libusb",
+            Self::PushSync => "push_sync",
+            Self::Apex => "apex",
+            Self::FixedPushMkdir => "fixed_push_mkdir",
+            Self::Abb => "abb",
+            Self::FixedPushSymlinkTimestamp => "fixed_push_symlink_timestamp",
+            Self::AbbExec => "abb_exec",
+            Self::RemountShell => "remount_shell",
+            Self::TrackApp => "track_app",
+            Self::SendrecvV2 => "sendrecv_v2",
+            Self::SendrecvV2Brotli => "sendrecv_v2_brotli",
+            Self::SendrecvV2Lz4 => "sendrecv_v2_lz4",
+            Self::SendrecvV2Zstd => "sendrecv_v2_zstd",
+            Self::SendrecvV2DryRunSend => "sendrecv_v2_dry_run_send",
+            Self::DelayedAck => "delayed_ack",
+            Self::OpenscreenMdns => "openscreen_mdns",
+            Self::DeviceTrackerProtoFormat => "devicetracker_proto_format",
+            Self::DevRaw => "devraw",
+            Self::AppInfo => "app_info",
+            Self::ServerStatus => "server_status",
+            Self::Other(name) => name,
+        }
+    }
+
+    /// Returns `false` for [`Self::Other`].
+    pub fn is_known(&self) -> bool {
+        !matches!(self, Self::Other(_))
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Feature {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "shell_v2" => Self::ShellV2,
+            "cmd" => Self::Cmd,
+            "stat_v2" => Self::StatV2,
+            "ls_v2" => Self::LsV2,
+            "libusb" => Self::Libusb,
+            "push_sync" => Self::PushSync,
+            "apex" => Self::Apex,
+            "fixed_push_mkdir" => Self::FixedPushMkdir,
+            "abb" => Self::Abb,
+            "fixed_push_symlink_timestamp" => Self::FixedPushSymlinkTimestamp,
+            "abb_exec" => Self::AbbExec,
+            "remount_shell" => Self::RemountShell,
+            "track_app" => Self::TrackApp,
+            "sendrecv_v2" => Self::SendrecvV2,
+            "sendrecv_v2_brotli" => Self::SendrecvV2Brotli,
+            "sendrecv_v2_lz4" => Self::SendrecvV2Lz4,
+            "sendrecv_v2_zstd" => Self::SendrecvV2Zstd,
+            "sendrecv_v2_dry_run_send" => Self::SendrecvV2DryRunSend,
+            "delayed_ack" => Self::DelayedAck,
+            "openscreen_mdns" => Self::OpenscreenMdns,
+            "devicetracker_proto_format" => Self::DeviceTrackerProtoFormat,
+            "devraw" => Self::DevRaw,
+            "app_info" => Self::AppInfo,
+            "server_status" => Self::ServerStatus,
+            _ if !s.is_empty() && !s.contains(',') => Self::Other(s.to_string()),
+            _ => {
+                return Err(AdbError::Parse {
+                    value: s.to_string(),
+                    source_type: "&str",
+                    target_type: "Feature",
+                    source: None,
+                })
+            }
+        })
+    }
+}
+
+/// A set of features.
+///
+/// # Syntax
+///
+/// Comma-separated feature names, e.g. `shell_v2,cmd,stat_v2`.
+///
+/// ```
+/// use adb::features::{Feature, Features};
+///
+/// let features: Features = "cmd,shell_v2".parse().unwrap();
+/// assert!(features.contains(&Feature::Cmd));
+/// assert_eq!("shell_v2,cmd", features.to_string());
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Features(BTreeSet<Feature>);
+
+impl Features {
+    /// Returns `true` if `feature` is in the set.
+    pub fn contains(&self, feature: &Feature) -> bool {
+        self.0.contains(feature)
+    }
+
+    /// Returns an iterator over the features, sorted by [`Feature`] order.
+    pub fn iter(&self) -> btree_set::Iter<'_, Feature> {
+        self.0.iter()
+    }
+
+    /// Returns the number of features.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Fails with [`AdbError::Unsupported`] if `feature` isn't in the set.
+    ///
+    /// ```
+    /// use adb::error::AdbError;
+    /// use adb::features::{Feature, Features};
+    ///
+    /// let features: Features = "shell_v2".parse().unwrap();
+    /// assert!(features.require(Feature::ShellV2).is_ok());
+    /// assert!(matches!(
+    ///     features.require(Feature::AbbExec),
+    ///     Err(AdbError::Unsupported { needed: Feature::AbbExec, .. })
+    /// ));
+    /// ```
+    pub fn require(&self, feature: Feature) -> Result<(), AdbError> {
+        if self.contains(&feature) {
+            Ok(())
+        } else {
+            Err(AdbError::Unsupported {
+                needed: feature,
+                have: self.clone(),
+            })
+        }
+    }
+}
+
+impl Display for Features {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, feature) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(feature.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Features {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim_end()
+            .split(',')
+            .filter(|feature| !feature.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl FromIterator<Feature> for Features {
+    fn from_iter<I: IntoIterator<Item = Feature>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl<'a> IntoIterator for &'a Features {
+    type Item = &'a Feature;
+    type IntoIter = btree_set::Iter<'a, Feature>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl AdbServer {
+    /// Returns the features supported by the server (`host:host-features`).
+    pub fn features(&self) -> Result<Features, AdbError> {
+        self.query_string("host:host-features")?.parse()
+    }
+}
+
+impl Device {
+    /// Returns the features supported by both the device and the server
+    /// (`host-serial:<serial>:features`).
+    ///
+    /// The features are queried once, then cached and shared by the clones of the handle
+    /// until [`Device::refresh_features`] is called.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::features::Feature;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// if device.features().unwrap().contains(&Feature::AbbExec) {
+    ///     println!("binder bridge available");
+    /// }
+    /// ```
+    pub fn features(&self) -> Result<Features, AdbError> {
+        if let Some(features) = &*self.features_cache().lock().unwrap() {
+            return Ok(features.clone());
+        }
+        self.refresh_features()
+    }
+
+    /// Queries the features again and replaces the cached ones, e.g. after the device was
+    /// updated or the server restarted with another version.
+    ///
+    /// The cache isn't locked during the query, so the clones of the handle never wait for
+    /// the server. Clones missing the cache at the same time query it once each.
+    pub fn refresh_features(&self) -> Result<Features, AdbError> {
+        let features: Features = self.host_request_string("features")?.parse()?;
+        *self.features_cache().lock().unwrap() = Some(features.clone());
+        Ok(features)
+    }
+
+    /// Fails with [`AdbError::Unsupported`] unless the device and the server both support
+    /// `feature`, so that callers can fall back before requesting a service.
+    pub fn require_feature(&self, feature: Feature) -> Result<(), AdbError> {
+        self.features()?.require(feature)
+    }
+
+    /// Replaces `error`, the rejection of a service needing `feature`, with
+    /// [`AdbError::Unsupported`] if the device or the server lacks `feature`.
+    #[cfg(feature = "shell")]
+    pub(crate) fn unsupported_or(&self, feature: Feature, error: AdbError) -> AdbError {
+        match error.inner() {
+            AdbError::Server { .. } | AdbError::Protocol { .. } => {
+                match self.require_feature(feature) {
+                    Err(unsupported @ AdbError::Unsupported { .. }) => unsupported,
+                    _ => error,
+                }
+            }
+            _ => error,
+        }
+    }
+
+    /// Returns `true` if the device and the server both support `feature`.
+    #[cfg(any(feature = "shell", feature = "sync"))]
+    pub(crate) fn has_feature(&self, feature: Feature) -> Result<bool, AdbError> {
+        Ok(self.features()?.contains(&feature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_features_parse() {
+        let features: Features = "shell_v2,cmd,stat_v2,ls_v2,fixed_push_mkdir,apex,abb,\
+                                  fixed_push_symlink_timestamp,abb_exec,remount_shell,\
+                                  track_app,sendrecv_v2,sendrecv_v2_brotli,sendrecv_v2_lz4,\
+                                  sendrecv_v2_zstd,sendrecv_v2_dry_run_send,openscreen_mdns,\
+                                  future_feature\n"
+            .parse()
+            .unwrap();
+        assert_eq!(18, features.len());
+        assert!(features.contains(&Feature::AbbExec));
+        assert!(features.contains(&Feature::Other("future_feature".to_string())));
+        assert!(!features.contains(&Feature::DelayedAck));
+        for feature in &features {
+            assert_eq!(feature, &feature.name().parse().unwrap());
+        }
+        assert!("".parse::<Features>().unwrap().is_empty());
+        assert!("".parse::<Feature>().is_err());
+    }
+
+    #[test]
+    fn test_features_require() {
+        let features: Features = "cmd,shell_v2".parse().unwrap();
+        assert!(features.require(Feature::Cmd).is_ok());
+        let err = features.require(Feature::SendrecvV2).unwrap_err();
+        assert_eq!(
+            "unsupported: needs the `sendrecv_v2` feature, only shell_v2,cmd supported",
+            err.to_string()
+        );
+        assert!(!err.is_retryable());
+        let err = Features::default().require(Feature::Abb).unwrap_err();
+        assert_eq!(
+            "unsupported: needs the `abb` feature, none supported",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_device_features_cache() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        use crate::protocol;
+        use crate::server::AdbServer;
+        use crate::socket::Tcp;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in [&b"OKAY0003cmd"[..], b"OKAY000ccmd,shell_v2"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                requests.push(protocol::read_string(&mut stream).unwrap());
+                stream.write_all(reply).unwrap();
+            }
+            requests
+        });
+        let device = AdbServer::new(Tcp::from_port(port)).device("emulator-5554");
+        assert_eq!("cmd", device.features().unwrap().to_string());
+        let cached = device.clone().features().unwrap();
+        assert!(!cached.contains(&Feature::ShellV2));
+        assert_eq!(
+            "shell_v2,cmd",
+            device.refresh_features().unwrap().to_string()
+        );
+        assert!(device.features().unwrap().contains(&Feature::ShellV2));
+        assert_eq!(
+            ["host-serial:emulator-5554:features"; 2],
+            &handle.join().unwrap()[..]
+        );
+    }
+}
@@ -0,0 +1,715 @@
+//! This module provides port forwarding between the host and a device, in both directions.
+
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::device::{Device, DeviceState};
+use crate::error::AdbError;
+use crate::protocol;
+use crate::server::ServerStream;
+use crate::socket::{AdbSocketFamilies, AdbSocketFamily, Jdwp, Tcp};
+use crate::track::DeviceEvent;
+
+/// The handshake exchanged by a debugger and a JDWP agent, sent first by the debugger
+/// and echoed back by the agent.
+pub const JDWP_HANDSHAKE: &[u8; 14] = b"JDWP-Handshake";
+
+/// How long [`Device::attach_debugger`] waits for the process to echo the JDWP handshake.
+pub const JDWP_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A forward listed by `adb forward --list` or `adb reverse --list`.
+///
+/// # Syntax
+///
+/// `<serial> <local> <remote>`
+///
+/// For a reverse forward, `local` is the socket listening on the device,
+/// and `remote` the socket connected to on the host.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Forward {
+    /// The serial number of the device, or `(reverse)` for reverse forwards on older devices.
+    pub serial: String,
+    /// The listening socket.
+    pub local: AdbSocketFamilies,
+    /// The socket connections are forwarded to.
+    pub remote: AdbSocketFamilies,
+}
+
+impl Display for Forward {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.serial, self.local, self.remote)
+    }
+}
+
+impl FromStr for Forward {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split(' ');
+        match (words.next(), words.next(), words.next(), words.next()) {
+            (Some(serial), Some(local), Some(remote), None) if !serial.is_empty() => Ok(Self {
+                serial: serial.to_string(),
+                local: local.parse()?,
+                remote: remote.parse()?,
+            }),
+            _ => Err(AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Forward",
+                source: None,
+            }),
+        }
+    }
+}
+
+/// Resolves the port of `local` from the reply of the server to a forward, the port it
+/// listens on as a length-prefixed decimal number.
+///
+/// Servers predating `tcp:0` don't reply, which only resolves a port set by the caller.
+fn resolve_local(local: Tcp, reply: &[u8]) -> Result<Tcp, AdbError> {
+    if reply.is_empty() && local.is_wildcard() {
+        return Err(AdbError::Protocol {
+            code: None,
+            message: format!(
+                "the server didn't report the port listening for `{}`",
+                local
+            ),
+        });
+    } else if reply.is_empty() {
+        return Ok(local);
+    }
+    let port = protocol::read_string(&mut &reply[..])?;
+    match port.parse() {
+        Ok(port) => Ok(Tcp {
+            port: Some(port),
+            ..local
+        }),
+        Err(e) => Err(AdbError::Parse {
+            value: port,
+            source_type: "&str",
+            target_type: "u16",
+            source: Some(Box::new(e)),
+        }),
+    }
+}
+
+/// Exchanges the JDWP handshake over `stream`, failing if the agent doesn't echo it.
+fn jdwp_handshake<S: Read + Write>(stream: &mut S) -> Result<(), AdbError> {
+    stream.write_all(JDWP_HANDSHAKE)?;
+    let mut reply = [0; JDWP_HANDSHAKE.len()];
+    stream.read_exact(&mut reply)?;
+    if &reply == JDWP_HANDSHAKE {
+        Ok(())
+    } else {
+        Err(AdbError::Protocol {
+            code: None,
+            message: format!(
+                "invalid JDWP handshake `{}`",
+                String::from_utf8_lossy(&reply).escape_debug()
+            ),
+        })
+    }
+}
+
+/// A forward from a local port to the JDWP agent of a process, set up by
+/// [`Device::attach_debugger`].
+///
+/// The forward is removed when the guard is dropped.
+#[derive(Debug)]
+pub struct DebuggerForward {
+    device: Device,
+    port: u16,
+}
+
+impl DebuggerForward {
+    /// Returns the local port a debugger should attach to.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the local socket a debugger should attach to.
+    pub fn local(&self) -> Tcp {
+        Tcp::from_port(self.port)
+    }
+}
+
+impl Drop for DebuggerForward {
+    fn drop(&mut self) {
+        let _ = self.device.forward_remove(self.local());
+    }
+}
+
+/// Parses the pids of debuggable processes sent by `jdwp` and `track-jdwp`, one per line.
+fn parse_jdwp_pids(s: &str) -> Result<Vec<Jdwp>, AdbError> {
+    s.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.trim().parse().map(Jdwp).map_err(|e| AdbError::Parse {
+                value: line.to_string(),
+                source_type: "&str",
+                target_type: "Jdwp",
+                source: Some(Box::new(e)),
+            })
+        })
+        .collect()
+}
+
+/// An iterator of the debuggable processes of a device, created by [`Device::track_jdwp`].
+///
+/// Each item is the whole list of processes, sent first when tracking starts, then every
+/// time a process starts or exits. The iterator ends after the first error.
+#[derive(Debug)]
+pub struct JdwpTracker {
+    stream: ServerStream,
+    done: bool,
+}
+
+impl Iterator for JdwpTracker {
+    type Item = Result<Vec<Jdwp>, AdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = protocol::read_length_prefixed(&mut self.stream)
+            .and_then(|list| parse_jdwp_pids(&String::from_utf8_lossy(&list)));
+        self.done = result.is_err();
+        Some(result)
+    }
+}
+
+/// Parses the list of forwards replied to `list-forward`.
+fn parse_forwards(s: &str) -> Result<Vec<Forward>, AdbError> {
+    s.lines()
+        .filter(|line| !line.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+impl Device {
+    fn host_command(&self, service: &str) -> Result<(), AdbError> {
+        let prefix = self.transport().host_prefix();
+        let mut stream = self.server().open(&format!("{}{}", prefix, service))?;
+        // The first `OKAY` acknowledges the request, the second one reports the result.
+        protocol::read_status(&mut stream)
+    }
+
+    fn reverse_command(&self, service: &str) -> Result<(), AdbError> {
+        let mut stream = self.open(&format!("reverse:{}", service))?;
+        protocol::read_status(&mut stream)
+    }
+
+    fn forward_command(
+        &self,
+        service: &str,
+        local: impl AdbSocketFamily,
+        remote: impl AdbSocketFamily,
+    ) -> Result<Option<Tcp>, AdbError> {
+        let prefix = self.transport().host_prefix();
+        let mut stream = self
+            .server()
+            .open(&format!("{}{}:{};{}", prefix, service, local, remote))?;
+        // The first `OKAY` acknowledges the request, the second one reports the result.
+        protocol::read_status(&mut stream)?;
+        let Ok(local) = Tcp::try_from(local.to_string().as_str()) else {
+            return Ok(None);
+        };
+        // The server closes the connection after reporting the port.
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        resolve_local(local, &reply).map(Some)
+    }
+
+    /// Forwards connections to `local` on the host to `remote` on the device
+    /// (`adb forward <local> <remote>`), replacing an existing forward of `local`.
+    ///
+    /// Returns the local TCP socket listening, with the port chosen by the server for
+    /// [`Tcp::any_local`], or `None` if `local` isn't a TCP socket.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    /// use adb::socket::{LocalAbstract, Tcp};
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let remote = LocalAbstract("chrome_devtools_remote".to_string());
+    /// let local = device.forward(Tcp::any_local(), remote).unwrap().unwrap();
+    /// println!("http://localhost:{}/json", local.port.unwrap());
+    /// ```
+    pub fn forward(
+        &self,
+        local: impl AdbSocketFamily,
+        remote: impl AdbSocketFamily,
+    ) -> Result<Option<Tcp>, AdbError> {
+        self.forward_command("forward", local, remote)
+    }
+
+    /// Like [`Self::forward`], but fails if `local` is already forwarded
+    /// (`adb forward --no-rebind <local> <remote>`).
+    pub fn forward_no_rebind(
+        &self,
+        local: impl AdbSocketFamily,
+        remote: impl AdbSocketFamily,
+    ) -> Result<Option<Tcp>, AdbError> {
+        self.forward_command("forward:norebind", local, remote)
+    }
+
+    /// Forwards a local port chosen by the server to `remote` (`adb forward tcp:0 <remote>`),
+    /// returning the port.
+    fn forward_any_port(&self, remote: impl AdbSocketFamily) -> Result<u16, AdbError> {
+        let local = self.forward(Tcp::any_local(), remote)?;
+        // `resolve_local` never leaves the port of a TCP socket unset.
+        Ok(local.and_then(|local| local.port).unwrap_or_default())
+    }
+
+    /// Lists the processes of the device a debugger can attach to (`jdwp`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// for process in device.jdwp_processes().unwrap() {
+    ///     println!("{}", process);
+    /// }
+    /// ```
+    pub fn jdwp_processes(&self) -> Result<Vec<Jdwp>, AdbError> {
+        let mut list = String::new();
+        // The device sends the list once, then closes the connection.
+        self.open("jdwp")?.read_to_string(&mut list)?;
+        parse_jdwp_pids(&list)
+    }
+
+    /// Tracks the processes of the device a debugger can attach to (`track-jdwp`).
+    pub fn track_jdwp(&self) -> Result<JdwpTracker, AdbError> {
+        Ok(JdwpTracker {
+            stream: self.open("track-jdwp")?,
+            done: false,
+        })
+    }
+
+    /// Forwards a free local port to the JDWP agent of the process `pid`, returning the
+    /// local socket a debugger attaches to.
+    ///
+    /// Unlike [`Self::attach_debugger`], the forward outlives the call, and isn't checked.
+    pub fn forward_jdwp(&self, pid: u32) -> Result<Tcp, AdbError> {
+        let port = self.forward_any_port(Jdwp(pid))?;
+        Ok(Tcp::new(Ipv4Addr::LOCALHOST.into(), port))
+    }
+
+    /// Forwards a free local port to the JDWP agent of the process `pid`, and checks that
+    /// the process answers the JDWP handshake.
+    ///
+    /// The connection used for the check is closed before returning, so that a debugger
+    /// can attach to [`DebuggerForward::port`]. Fails if the process isn't debuggable.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let forward = device.attach_debugger(1234).unwrap();
+    /// println!("jdb -attach localhost:{}", forward.port());
+    /// ```
+    pub fn attach_debugger(&self, pid: u32) -> Result<DebuggerForward, AdbError> {
+        let forward = DebuggerForward {
+            device: self.clone(),
+            port: self.forward_any_port(Jdwp(pid))?,
+        };
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, forward.port))?;
+        stream.set_read_timeout(Some(JDWP_HANDSHAKE_TIMEOUT))?;
+        // The server closes the connection right away if there's no such debuggable process.
+        jdwp_handshake(&mut stream).map_err(|e| match e {
+            AdbError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => AdbError::Server {
+                message: format!("process {} isn't debuggable", pid),
+            },
+            e => e,
+        })?;
+        Ok(forward)
+    }
+
+    /// Removes the forward of `local` (`adb forward --remove <local>`).
+    pub fn forward_remove(&self, local: impl AdbSocketFamily) -> Result<(), AdbError> {
+        self.host_command(&format!("killforward:{}", local))
+    }
+
+    /// Removes all forwards of the device (`adb forward --remove-all`).
+    pub fn forward_remove_all(&self) -> Result<(), AdbError> {
+        self.host_command("killforward-all")
+    }
+
+    /// Lists the forwards of the device (`adb forward --list`).
+    pub fn forward_list(&self) -> Result<Vec<Forward>, AdbError> {
+        parse_forwards(&self.host_request_string("list-forward")?)
+    }
+
+    /// Forwards connections to `remote` on the device to `local` on the host
+    /// (`adb reverse <remote> <local>`), replacing an existing reverse forward of `remote`.
+    pub fn reverse(
+        &self,
+        remote: impl AdbSocketFamily,
+        local: impl AdbSocketFamily,
+    ) -> Result<(), AdbError> {
+        self.reverse_command(&format!("forward:{};{}", remote, local))
+    }
+
+    /// Like [`Self::reverse`], but fails if `remote` is already forwarded
+    /// (`adb reverse --no-rebind <remote> <local>`).
+    pub fn reverse_no_rebind(
+        &self,
+        remote: impl AdbSocketFamily,
+        local: impl AdbSocketFamily,
+    ) -> Result<(), AdbError> {
+        self.reverse_command(&format!("forward:norebind:{};{}", remote, local))
+    }
+
+    /// Removes the reverse forward of `remote` (`adb reverse --remove <remote>`).
+    pub fn reverse_remove(&self, remote: impl AdbSocketFamily) -> Result<(), AdbError> {
+        self.reverse_command(&format!("killforward:{}", remote))
+    }
+
+    /// Removes all reverse forwards of the device (`adb reverse --remove-all`).
+    pub fn reverse_remove_all(&self) -> Result<(), AdbError> {
+        self.reverse_command("killforward-all")
+    }
+
+    /// Lists the reverse forwards of the device (`adb reverse --list`).
+    pub fn reverse_list(&self) -> Result<Vec<Forward>, AdbError> {
+        let mut stream = self.open("reverse:list-forward")?;
+        parse_forwards(&protocol::read_string(&mut stream)?)
+    }
+}
+
+/// The direction of a [`PendingForward`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ForwardDirection {
+    /// From the host to the device, see [`Device::forward`].
+    Forward,
+    /// From the device to the host, see [`Device::reverse`].
+    Reverse,
+}
+
+/// A forward waiting for its device, see [`PendingForwards`].
+///
+/// Like in [`Forward`], `local` is the listening socket, on the device for a reverse
+/// forward.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct PendingForward {
+    pub direction: ForwardDirection,
+    pub local: AdbSocketFamilies,
+    pub remote: AdbSocketFamilies,
+}
+
+/// Returns `true` if `error` means the device isn't ready yet, rather than the forward
+/// being invalid.
+fn is_pending(error: &AdbError) -> bool {
+    matches!(
+        error.inner(),
+        AdbError::DeviceNotFound { .. } | AdbError::DeviceOffline | AdbError::Unauthorized
+    )
+}
+
+/// Forwards of a device that may not be connected yet, e.g. while it boots.
+///
+/// Forwards are requested with the `host-serial:<serial>:` services, which the server
+/// rejects until the device is online. Those rejected because the device is missing,
+/// offline or unauthorized are kept pending, and applied by [`Self::reconcile`] once the
+/// device appears: after [`Self::wait_and_reconcile`], or on the events of a
+/// [device tracker](crate::track) passed to [`Self::on_event`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use adb::forward::PendingForwards;
+/// use adb::server::AdbServer;
+/// use adb::socket::{LocalAbstract, Tcp};
+///
+/// let mut forwards = PendingForwards::new(AdbServer::default().device("emulator-5554"));
+/// forwards
+///     .forward(Tcp::from_port(9222), LocalAbstract("chrome_devtools_remote".to_string()))
+///     .unwrap();
+/// forwards.wait_and_reconcile(Duration::from_secs(120)).unwrap();
+/// assert!(forwards.pending().is_empty());
+/// ```
+#[derive(Clone, Debug)]
+pub struct PendingForwards {
+    device: Device,
+    pending: Vec<PendingForward>,
+}
+
+impl PendingForwards {
+    /// Creates an empty set of forwards of `device`.
+    pub fn new(device: Device) -> Self {
+        Self {
+            device,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns the device of the forwards.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Returns the forwards not applied yet, in the order they were requested.
+    pub fn pending(&self) -> &[PendingForward] {
+        &self.pending
+    }
+
+    /// Forwards `local` on the host to `remote` on the device, like [`Device::forward`],
+    /// returning `false` if the forward is pending.
+    pub fn forward(
+        &mut self,
+        local: impl Into<AdbSocketFamilies>,
+        remote: impl Into<AdbSocketFamilies>,
+    ) -> Result<bool, AdbError> {
+        self.add(PendingForward {
+            direction: ForwardDirection::Forward,
+            local: local.into(),
+            remote: remote.into(),
+        })
+    }
+
+    /// Forwards `remote` on the device to `local` on the host, like [`Device::reverse`],
+    /// returning `false` if the forward is pending.
+    pub fn reverse(
+        &mut self,
+        remote: impl Into<AdbSocketFamilies>,
+        local: impl Into<AdbSocketFamilies>,
+    ) -> Result<bool, AdbError> {
+        self.add(PendingForward {
+            direction: ForwardDirection::Reverse,
+            local: remote.into(),
+            remote: local.into(),
+        })
+    }
+
+    fn add(&mut self, forward: PendingForward) -> Result<bool, AdbError> {
+        match self.apply(&forward) {
+            Ok(()) => Ok(true),
+            Err(e) if is_pending(&e) => {
+                self.pending.push(forward);
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn apply(&self, forward: &PendingForward) -> Result<(), AdbError> {
+        let (local, remote) = (forward.local.clone(), forward.remote.clone());
+        match forward.direction {
+            ForwardDirection::Forward => self.device.forward(local, remote).map(drop),
+            ForwardDirection::Reverse => self.device.reverse(local, remote),
+        }
+    }
+
+    /// Applies the pending forwards the device accepts, returning how many were applied.
+    ///
+    /// Forwards still rejected because the device isn't ready stay pending. On any other
+    /// error, the failed forward and the following ones stay pending too.
+    pub fn reconcile(&mut self) -> Result<usize, AdbError> {
+        let mut applied = 0;
+        let mut kept = Vec::new();
+        let mut pending = std::mem::take(&mut self.pending).into_iter();
+        let mut result = Ok(());
+        for forward in pending.by_ref() {
+            match self.apply(&forward) {
+                Ok(()) => applied += 1,
+                Err(e) if is_pending(&e) => kept.push(forward),
+                Err(e) => {
+                    kept.push(forward);
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        kept.extend(pending);
+        self.pending = kept;
+        result.map(|()| applied)
+    }
+
+    /// Waits for the device to be online, like [`Device::wait_for`], then applies the
+    /// pending forwards.
+    pub fn wait_and_reconcile(&mut self, timeout: Duration) -> Result<usize, AdbError> {
+        if self.pending.is_empty() {
+            return Ok(0);
+        }
+        self.device.wait_for(DeviceState::Device, timeout)?;
+        self.reconcile()
+    }
+
+    /// Applies the pending forwards if `event` reports the device online, returning how many
+    /// were applied.
+    ///
+    /// Devices not selected by serial match the events of any device.
+    pub fn on_event(&mut self, event: &DeviceEvent) -> Result<usize, AdbError> {
+        let matches = self
+            .device
+            .serial()
+            .map_or(true, |serial| serial == event.serial);
+        if matches && event.new_state == Some(DeviceState::Device) && !self.pending.is_empty() {
+            self.reconcile()
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::{ConnectOptions, RetryPolicy};
+    use crate::socket::{LocalAbstract, Tcp};
+
+    #[test]
+    fn test_forward_parse() {
+        let forward = Forward {
+            serial: "emulator-5554".to_string(),
+            local: Tcp::from_port(8080).into(),
+            remote: LocalAbstract("chrome_devtools_remote".to_string()).into(),
+        };
+        let s = "emulator-5554 tcp:8080 localabstract:chrome_devtools_remote";
+        assert_eq!(forward, s.parse().unwrap());
+        assert_eq!(s, forward.to_string());
+        let err = [
+            "",
+            "emulator-5554",
+            "emulator-5554 tcp:8080",
+            "emulator-5554 tcp:8080 tcp:8081 tcp:8082",
+            "emulator-5554 tcp:8080 jdwp:pid",
+        ];
+        for s in err {
+            assert!(s.parse::<Forward>().is_err(), "{}", s);
+        }
+        // Families unknown to this crate are kept.
+        let forward: Forward = "emulator-5554 tcp:8080 udp:8081".parse().unwrap();
+        assert_eq!(
+            AdbSocketFamilies::Other("udp:8081".to_string()),
+            forward.remote
+        );
+    }
+
+    #[test]
+    fn test_jdwp_handshake() {
+        struct Agent {
+            reply: io::Cursor<Vec<u8>>,
+            received: Vec<u8>,
+        }
+        impl Read for Agent {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reply.read(buf)
+            }
+        }
+        impl Write for Agent {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.received.write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let agent = |reply: &[u8]| Agent {
+            reply: io::Cursor::new(reply.to_vec()),
+            received: Vec::new(),
+        };
+        let mut ok = agent(JDWP_HANDSHAKE);
+        jdwp_handshake(&mut ok).unwrap();
+        assert_eq!(JDWP_HANDSHAKE, &ok.received[..]);
+        assert!(jdwp_handshake(&mut agent(b"JDWP-Handshak!")).is_err());
+        assert!(jdwp_handshake(&mut agent(b"")).is_err());
+    }
+
+    #[test]
+    fn test_parse_forwards() {
+        let forwards =
+            parse_forwards("emulator-5554 tcp:8080 tcp:80\n(reverse) localabstract:foo tcp:9000\n")
+                .unwrap();
+        assert_eq!(2, forwards.len());
+        assert_eq!("(reverse)", forwards[1].serial);
+        assert!(parse_forwards("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_local() {
+        let resolved = resolve_local(Tcp::any_local(), b"000538741").unwrap();
+        assert_eq!(Tcp::from_port(38741), resolved);
+        let local = Tcp::new(Ipv4Addr::LOCALHOST.into(), 0);
+        assert_eq!(
+            Tcp::new(Ipv4Addr::LOCALHOST.into(), 5000),
+            resolve_local(local, b"00045000").unwrap()
+        );
+        // Servers predating `tcp:0` don't reply.
+        let local = Tcp::from_port(8080);
+        assert_eq!(local, resolve_local(local, b"").unwrap());
+        assert!(resolve_local(Tcp::any_local(), b"").is_err());
+        assert!(resolve_local(Tcp::any_local(), b"0005port!").is_err());
+    }
+
+    #[test]
+    fn test_parse_jdwp_pids() {
+        assert_eq!(
+            vec![Jdwp(1234), Jdwp(5678)],
+            parse_jdwp_pids("1234\n5678\n").unwrap()
+        );
+        assert!(parse_jdwp_pids("").unwrap().is_empty());
+        assert!(parse_jdwp_pids("1234\nabc\n").is_err());
+    }
+
+    #[test]
+    fn test_pending_forwards() {
+        assert!(is_pending(&AdbError::DeviceNotFound {
+            serial: Some("emulator-5554".to_string())
+        }));
+        assert!(is_pending(&AdbError::DeviceOffline));
+        assert!(!is_pending(&AdbError::Server {
+            message: "cannot bind listener".to_string()
+        }));
+
+        // A server nothing listens on fails the forward instead of keeping it pending.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server = crate::server::AdbServer::new(Tcp::from_port(port))
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::none()));
+        let mut forwards = PendingForwards::new(server.device("emulator-5554"));
+        assert!(forwards
+            .forward(Tcp::from_port(8080), Tcp::from_port(80))
+            .is_err());
+        assert!(forwards.pending().is_empty());
+
+        forwards.pending.push(PendingForward {
+            direction: ForwardDirection::Reverse,
+            local: Tcp::from_port(9000).into(),
+            remote: Tcp::from_port(9000).into(),
+        });
+        let event = |serial: &str, state| DeviceEvent {
+            serial: serial.to_string(),
+            old_state: None,
+            new_state: Some(state),
+        };
+        assert_eq!(
+            0,
+            forwards
+                .on_event(&event("emulator-5556", DeviceState::Device))
+                .unwrap()
+        );
+        assert_eq!(
+            0,
+            forwards
+                .on_event(&event("emulator-5554", DeviceState::Offline))
+                .unwrap()
+        );
+        assert!(forwards
+            .on_event(&event("emulator-5554", DeviceState::Device))
+            .is_err());
+        assert_eq!(1, forwards.pending().len());
+    }
+}
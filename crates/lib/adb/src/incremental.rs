@@ -0,0 +1,561 @@
+//! This module provides incremental installs (`adb install --incremental`).
+//!
+//! The package manager commits an incremental install before it received the APK, and reads
+//! its blocks on demand through the incremental file system, so large test APKs are usable
+//! within seconds. The host serves the blocks over the connection of
+//! `cmd package install-incremental`:
+//!
+//! - The host sends `OKAY` once the command is opened.
+//! - The device interleaves its requests with the output of the command. A request is the
+//!   magic `INCR`, then the type, the file id, and a block index, as big-endian 16, 16 and
+//!   32 bits integers. The types are `SERVING_COMPLETE` (0), `BLOCK_MISSING` (1),
+//!   `PREFETCH` (2) and `DESTROY` (3).
+//! - The host replies with chunks: a big-endian 32 bits length, then blocks, each one a
+//!   header (file id, block type, compression, block index and size, as big-endian 16, 8,
+//!   8, 32 and 16 bits integers) and the data. Blocks are the 4 KiB blocks of the APK, and
+//!   of the Merkle tree of its v4 signature, which the device verifies them with.
+//!
+//! The v4 signature is read from `<apk>.idsig`, written by
+//! `apksigner sign --v4-signing-enabled true`.
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::base64;
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::features::Feature;
+use crate::install::{self, InstallOptions};
+
+/// The size of the blocks served to the device.
+pub const BLOCK_SIZE: usize = 4096;
+
+/// The only version of v4 signatures supported by incremental installs.
+pub const V4_SIGNATURE_VERSION: i32 = 2;
+
+/// The magic prefixing every request of the device.
+const REQUEST_MAGIC: &[u8; 4] = b"INCR";
+
+/// The size of a request, including its magic.
+const REQUEST_SIZE: usize = 12;
+
+/// The size reached by a chunk before it's flushed.
+const CHUNK_FLUSH_SIZE: usize = 31 * BLOCK_SIZE;
+
+const SERVING_COMPLETE: i16 = 0;
+const BLOCK_MISSING: i16 = 1;
+const PREFETCH: i16 = 2;
+const DESTROY: i16 = 3;
+
+const BLOCK_TYPE_DATA: i8 = 0;
+const BLOCK_TYPE_HASH: i8 = 1;
+
+const COMPRESSION_NONE: i8 = 0;
+
+/// The v4 signature of an APK, as written to `.idsig` files by apksigner.
+///
+/// # Syntax
+///
+/// Little-endian 32 bits integers: the version, then the hashing info and the signing info,
+/// each prefixed by its size, then the size of the Merkle tree of the APK, followed by the
+/// tree itself.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct V4Signature {
+    /// The version, the hashing info and the signing info, as sent to the package manager.
+    header: Vec<u8>,
+    tree: Vec<u8>,
+}
+
+impl V4Signature {
+    /// Reads the signature at `path`.
+    pub fn read(path: &Path) -> Result<Self, AdbError> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    /// Parses the content of an `.idsig` file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, AdbError> {
+        let err = |reason: &str| AdbError::Parse {
+            value: format!("{} bytes", bytes.len()),
+            source_type: "&[u8]",
+            target_type: "V4Signature",
+            source: Some(reason.into()),
+        };
+        let mut rest = bytes;
+        let read_i32 = |rest: &mut &[u8]| -> Result<i32, AdbError> {
+            let value = rest.get(..4).ok_or_else(|| err("truncated"))?;
+            let value = i32::from_le_bytes(value.try_into().unwrap());
+            *rest = &rest[4..];
+            Ok(value)
+        };
+        let version = read_i32(&mut rest)?;
+        if version != V4_SIGNATURE_VERSION {
+            return Err(err("unsupported version"));
+        }
+        for _ in 0..2 {
+            let size = usize::try_from(read_i32(&mut rest)?).map_err(|_| err("negative size"))?;
+            rest = rest.get(size..).ok_or_else(|| err("truncated"))?;
+        }
+        let header = bytes[..bytes.len() - rest.len()].to_vec();
+        let tree_size = usize::try_from(read_i32(&mut rest)?).map_err(|_| err("negative size"))?;
+        if rest.len() != tree_size {
+            return Err(err("tree size mismatch"));
+        }
+        Ok(Self {
+            header,
+            tree: rest.to_vec(),
+        })
+    }
+
+    /// Returns the Merkle tree of the APK.
+    pub fn tree(&self) -> &[u8] {
+        &self.tree
+    }
+
+    /// Returns the signature without the tree in base64, as passed to
+    /// `install-incremental`.
+    pub fn encode(&self) -> String {
+        base64::encode(&self.header)
+    }
+}
+
+/// Returns the path of the v4 signature of the APK at `path`, `<path>.idsig`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature = path.as_os_str().to_os_string();
+    signature.push(".idsig");
+    PathBuf::from(signature)
+}
+
+/// Returns the size of the Merkle tree of a file of `size` bytes, 32 bytes digests of 4 KiB
+/// blocks, level by level up to a single block.
+pub fn verity_tree_size(size: u64) -> u64 {
+    let block_size = BLOCK_SIZE as u64;
+    let hashes_per_block = block_size / 32;
+    let mut blocks = (size + block_size - 1) / block_size;
+    let mut tree_blocks = 0;
+    while blocks > 1 {
+        blocks = (blocks + hashes_per_block - 1) / hashes_per_block;
+        tree_blocks += blocks;
+    }
+    tree_blocks * block_size
+}
+
+/// A file served to the device, with the Merkle tree of its signature.
+struct ServedFile<R> {
+    reader: R,
+    size: u64,
+    tree: Vec<u8>,
+    tree_sent: bool,
+}
+
+impl<R> ServedFile<R> {
+    fn blocks(&self) -> u64 {
+        (self.size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64
+    }
+}
+
+/// Serves the blocks of files to the device over the connection of `install-incremental`.
+struct BlockServer<S, R> {
+    stream: S,
+    files: Vec<ServedFile<R>>,
+    chunk: Vec<u8>,
+}
+
+impl<S: Read + Write, R: Read + Seek> BlockServer<S, R> {
+    fn new(stream: S, files: Vec<ServedFile<R>>) -> Self {
+        Self {
+            stream,
+            files,
+            chunk: Vec::new(),
+        }
+    }
+
+    /// Serves the requests of the device until it's done, returning the output of the
+    /// command.
+    fn serve(mut self) -> Result<String, AdbError> {
+        self.stream.write_all(b"OKAY")?;
+        let mut output = Vec::new();
+        let mut buffer = Vec::new();
+        let mut read = [0; 8192];
+        let mut serving = true;
+        loop {
+            let n = match self.stream.read(&mut read) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if !serving {
+                output.extend_from_slice(&read[..n]);
+                continue;
+            }
+            buffer.extend_from_slice(&read[..n]);
+            while let Some(start) = find_magic(&buffer) {
+                output.extend(buffer.drain(..start));
+                let Some(request) = buffer.get(..REQUEST_SIZE) else {
+                    break;
+                };
+                let request_type = i16::from_be_bytes([request[4], request[5]]);
+                let file_id = i16::from_be_bytes([request[6], request[7]]);
+                let block = i32::from_be_bytes([request[8], request[9], request[10], request[11]]);
+                buffer.drain(..REQUEST_SIZE);
+                if !self.handle(request_type, file_id, block)? {
+                    serving = false;
+                    output.append(&mut buffer);
+                    break;
+                }
+            }
+            if serving && find_magic(&buffer).is_none() {
+                // Keep a trailing partial magic for the next read.
+                let keep = partial_magic_len(&buffer);
+                output.extend(buffer.drain(..buffer.len() - keep));
+            }
+        }
+        output.append(&mut buffer);
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Handles a request, returning `false` once serving is over.
+    fn handle(&mut self, request_type: i16, file_id: i16, block: i32) -> Result<bool, AdbError> {
+        match request_type {
+            SERVING_COMPLETE | DESTROY => {
+                self.flush()?;
+                return Ok(false);
+            }
+            BLOCK_MISSING => {
+                let block = u64::try_from(block).map_err(|_| bad_request(request_type, block))?;
+                self.send_tree(file_id)?;
+                self.send_block(file_id, block)?;
+            }
+            PREFETCH => {
+                self.send_tree(file_id)?;
+                for block in 0..self.file(file_id)?.blocks() {
+                    self.send_block(file_id, block)?;
+                }
+            }
+            _ => return Err(bad_request(request_type, block)),
+        }
+        self.flush()?;
+        Ok(true)
+    }
+
+    fn file(&mut self, file_id: i16) -> Result<&mut ServedFile<R>, AdbError> {
+        usize::try_from(file_id)
+            .ok()
+            .and_then(|id| self.files.get_mut(id))
+            .ok_or_else(|| AdbError::Protocol {
+                code: None,
+                message: format!("request for unknown file {}", file_id),
+            })
+    }
+
+    /// Sends the Merkle tree of a file, unless it was already sent.
+    fn send_tree(&mut self, file_id: i16) -> Result<(), AdbError> {
+        let file = self.file(file_id)?;
+        if std::mem::replace(&mut file.tree_sent, true) {
+            return Ok(());
+        }
+        let tree = std::mem::take(&mut file.tree);
+        for (i, block) in tree.chunks(BLOCK_SIZE).enumerate() {
+            self.push_block(file_id, BLOCK_TYPE_HASH, i as i32, block)?;
+        }
+        Ok(())
+    }
+
+    /// Sends a data block of a file.
+    fn send_block(&mut self, file_id: i16, block: u64) -> Result<(), AdbError> {
+        let file = self.file(file_id)?;
+        if block >= file.blocks() {
+            return Err(bad_request(BLOCK_MISSING, block as i32));
+        }
+        let offset = block * BLOCK_SIZE as u64;
+        let mut data = vec![0; (file.size - offset).min(BLOCK_SIZE as u64) as usize];
+        file.reader.seek(SeekFrom::Start(offset))?;
+        file.reader.read_exact(&mut data)?;
+        self.push_block(file_id, BLOCK_TYPE_DATA, block as i32, &data)
+    }
+
+    /// Appends a block to the current chunk, flushing it once it's large enough.
+    fn push_block(
+        &mut self,
+        file_id: i16,
+        block_type: i8,
+        block: i32,
+        data: &[u8],
+    ) -> Result<(), AdbError> {
+        self.chunk.extend_from_slice(&file_id.to_be_bytes());
+        self.chunk.extend_from_slice(&block_type.to_be_bytes());
+        self.chunk
+            .extend_from_slice(&COMPRESSION_NONE.to_be_bytes());
+        self.chunk.extend_from_slice(&block.to_be_bytes());
+        self.chunk
+            .extend_from_slice(&(data.len() as i16).to_be_bytes());
+        self.chunk.extend_from_slice(data);
+        if self.chunk.len() >= CHUNK_FLUSH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Sends the current chunk, prefixed by its length.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        self.stream
+            .write_all(&(self.chunk.len() as i32).to_be_bytes())?;
+        self.stream.write_all(&self.chunk)?;
+        self.chunk.clear();
+        self.stream.flush()
+    }
+}
+
+/// Returns the start of the first request magic in `buffer`.
+fn find_magic(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(REQUEST_MAGIC.len())
+        .position(|window| window == REQUEST_MAGIC)
+}
+
+/// Returns the length of the longest suffix of `buffer` which starts a request magic.
+fn partial_magic_len(buffer: &[u8]) -> usize {
+    (1..REQUEST_MAGIC.len())
+        .rev()
+        .find(|&len| buffer.ends_with(&REQUEST_MAGIC[..len]))
+        .unwrap_or(0)
+}
+
+fn bad_request(request_type: i16, block: i32) -> AdbError {
+    AdbError::Protocol {
+        code: None,
+        message: format!(
+            "unexpected incremental request of type {} for block {}",
+            request_type, block
+        ),
+    }
+}
+
+impl Device {
+    /// Installs the APK at `path` incrementally (`adb install --incremental`), serving its
+    /// blocks as the device reads them, see [`crate::incremental`].
+    ///
+    /// Falls back to [`Self::install`] if the APK has no v4 signature next to it, or if the
+    /// device doesn't support the `abb_exec` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use adb::install::InstallOptions;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let options = InstallOptions::new().replace(true);
+    /// device
+    ///     .install_incremental(Path::new("app-debug.apk"), &options)
+    ///     .unwrap();
+    /// ```
+    pub fn install_incremental(
+        &self,
+        path: &Path,
+        options: &InstallOptions,
+    ) -> Result<(), AdbError> {
+        let signature = match V4Signature::read(&signature_path(path)) {
+            Err(AdbError::Io(e)) if e.kind() == ErrorKind::NotFound => {
+                return self.install(path, options)
+            }
+            result => result?,
+        };
+        if !self.has_feature(Feature::AbbExec)? {
+            return self.install(path, options);
+        }
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        if signature.tree().len() as u64 != verity_tree_size(size) {
+            return Err(AdbError::Parse {
+                value: signature_path(path).display().to_string(),
+                source_type: "Path",
+                target_type: "V4Signature",
+                source: Some("the signature doesn't match the size of the APK".into()),
+            });
+        }
+        let mut args = vec!["package".to_string(), "install-incremental".to_string()];
+        args.extend(options.args());
+        args.push(format!(
+            "{}:{}:0:{}:1",
+            install::file_name(path)?,
+            size,
+            signature.encode()
+        ));
+        let stream = self.abb_exec(&args)?;
+        let files = vec![ServedFile {
+            reader: file,
+            size,
+            tree: signature.tree,
+            tree_sent: false,
+        }];
+        let output = BlockServer::new(stream, files).serve()?;
+        install::check_output(&output).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A connection replaying what the device sends, and recording what the host sends.
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            // Short reads split requests across reads.
+            let len = buf.len().min(5);
+            self.input.read(&mut buf[..len])
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn request(request_type: i16, file_id: i16, block: i32) -> Vec<u8> {
+        let mut request = REQUEST_MAGIC.to_vec();
+        request.extend_from_slice(&request_type.to_be_bytes());
+        request.extend_from_slice(&file_id.to_be_bytes());
+        request.extend_from_slice(&block.to_be_bytes());
+        request
+    }
+
+    fn idsig(tree: &[u8]) -> Vec<u8> {
+        let mut bytes = V4_SIGNATURE_VERSION.to_le_bytes().to_vec();
+        for info in [&b"hash"[..], b"sign"] {
+            bytes.extend_from_slice(&(info.len() as i32).to_le_bytes());
+            bytes.extend_from_slice(info);
+        }
+        bytes.extend_from_slice(&(tree.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(tree);
+        bytes
+    }
+
+    #[test]
+    fn test_v4_signature_parse() {
+        let bytes = idsig(&[7; 5]);
+        let signature = V4Signature::parse(&bytes).unwrap();
+        assert_eq!(&[7; 5], signature.tree());
+        assert_eq!(base64::encode(&bytes[..20]), signature.encode());
+        let mut version = bytes.clone();
+        version[0] = 3;
+        let mut tree_size = bytes.clone();
+        tree_size[20] = 6;
+        for bytes in [&bytes[..3], &bytes[..19], &version, &tree_size] {
+            assert!(V4Signature::parse(bytes).is_err(), "{:?}", bytes);
+        }
+        assert_eq!(
+            PathBuf::from("out/app.apk.idsig"),
+            signature_path(Path::new("out/app.apk"))
+        );
+    }
+
+    #[test]
+    fn test_verity_tree_size() {
+        assert_eq!(0, verity_tree_size(0));
+        assert_eq!(0, verity_tree_size(4096));
+        assert_eq!(4096, verity_tree_size(4097));
+        assert_eq!(4096, verity_tree_size(128 * 4096));
+        // 129 blocks hash into 2 blocks, hashed into the root block.
+        assert_eq!(3 * 4096, verity_tree_size(129 * 4096));
+    }
+
+    #[test]
+    fn test_block_server() {
+        let data: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        let mut input = b"Performing Incremental Install\n".to_vec();
+        input.extend(request(BLOCK_MISSING, 0, 1));
+        input.extend(request(PREFETCH, 0, 0));
+        input.extend(request(SERVING_COMPLETE, 0, 0));
+        input.extend_from_slice(b"Success\n");
+        let mut stream = MockStream {
+            input: Cursor::new(input),
+            written: Vec::new(),
+        };
+        let files = vec![ServedFile {
+            reader: Cursor::new(data.clone()),
+            size: data.len() as u64,
+            tree: vec![0xaa; 10],
+            tree_sent: false,
+        }];
+        let output = BlockServer::new(&mut stream, files).serve().unwrap();
+        assert_eq!("Performing Incremental Install\nSuccess\n", output);
+
+        let written = stream.written;
+        assert_eq!(b"OKAY", &written[..4]);
+        // The tree and the missing block, then both blocks prefetched.
+        let mut blocks = Vec::new();
+        let mut rest = &written[4..];
+        while !rest.is_empty() {
+            let length = i32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (mut chunk, tail) = rest[4..].split_at(length);
+            rest = tail;
+            while !chunk.is_empty() {
+                let (header, tail) = chunk.split_at(10);
+                let size = i16::from_be_bytes([header[8], header[9]]) as usize;
+                let (data, tail) = tail.split_at(size);
+                let block = i32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+                blocks.push((header[2] as i8, block, data.to_vec()));
+                chunk = tail;
+            }
+        }
+        assert_eq!(
+            vec![
+                (BLOCK_TYPE_HASH, 0, vec![0xaa; 10]),
+                (BLOCK_TYPE_DATA, 1, data[4096..].to_vec()),
+                (BLOCK_TYPE_DATA, 0, data[..4096].to_vec()),
+                (BLOCK_TYPE_DATA, 1, data[4096..].to_vec()),
+            ],
+            blocks
+        );
+    }
+
+    #[test]
+    fn test_block_server_bad_request() {
+        for input in [
+            request(BLOCK_MISSING, 1, 0),
+            request(BLOCK_MISSING, 0, 2),
+            request(9, 0, 0),
+        ] {
+            let stream = MockStream {
+                input: Cursor::new(input),
+                written: Vec::new(),
+            };
+            let files = vec![ServedFile {
+                reader: Cursor::new(vec![0; 100]),
+                size: 100,
+                tree: Vec::new(),
+                tree_sent: false,
+            }];
+            assert!(matches!(
+                BlockServer::new(stream, files).serve(),
+                Err(AdbError::Protocol { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_partial_magic_len() {
+        assert_eq!(0, partial_magic_len(b"Success"));
+        assert_eq!(2, partial_magic_len(b"abcIN"));
+        assert_eq!(3, partial_magic_len(b"INC"));
+        assert_eq!(Some(1), find_magic(b"xINCR"));
+    }
+}
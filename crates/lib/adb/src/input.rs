@@ -0,0 +1,522 @@
+//! This module injects input events with the `input` shell command: taps, swipes, text,
+//! key events and raw motion events.
+//!
+//! `input text` splits its argument on spaces and has no escape for them, but translates
+//! `%s` into a space, so [`Device::text`] sends spaces as `%s` and quotes the rest for the
+//! device shell. A literal `%s` can't be typed, and only ASCII text is supported.
+//!
+//! `input motionevent` (Android 11 and later) sends a single `DOWN`, `MOVE`, `UP` or
+//! `CANCEL` event, so a [`MotionEvent`] gesture runs its events in a single shell command.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// A point on the screen, in pixels.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    /// Creates the point at `x`, `y`.
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+}
+
+impl From<(i32, i32)> for Point {
+    fn from((x, y): (i32, i32)) -> Self {
+        Self { x, y }
+    }
+}
+
+impl Display for Point {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.x, self.y)
+    }
+}
+
+/// Defines [`KeyCode`] from the `KEYCODE_*` constants of `android.view.KeyEvent`.
+macro_rules! key_codes {
+    ($($variant:ident = $code:literal, $name:literal;)*) => {
+        /// An Android key code, the `KEYCODE_*` constants of `android.view.KeyEvent`.
+        ///
+        /// Displayed as its constant name, e.g. `KEYCODE_HOME`. Codes without a variant are
+        /// kept as [`KeyCode::Other`].
+        #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+        #[non_exhaustive]
+        pub enum KeyCode {
+            $(
+                #[doc = concat!("`KEYCODE_", $name, "` (", stringify!($code), ").")]
+                $variant,
+            )*
+            /// Any other key code.
+            Other(u32),
+        }
+
+        impl KeyCode {
+            /// Returns the value of the key code.
+            pub fn code(&self) -> u32 {
+                match self {
+                    $(Self::$variant => $code,)*
+                    Self::Other(code) => *code,
+                }
+            }
+
+            /// Returns the key code of `code`.
+            pub fn from_code(code: u32) -> Self {
+                match code {
+                    $($code => Self::$variant,)*
+                    code => Self::Other(code),
+                }
+            }
+
+            /// Returns the name of the key code without the `KEYCODE_` prefix, e.g. `HOME`,
+            /// `None` for [`KeyCode::Other`].
+            pub fn name(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($name),)*
+                    Self::Other(_) => None,
+                }
+            }
+
+            /// Returns the key code named `name`, without the `KEYCODE_` prefix.
+            fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+key_codes! {
+    Unknown = 0, "UNKNOWN";
+    SoftLeft = 1, "SOFT_LEFT";
+    SoftRight = 2, "SOFT_RIGHT";
+    Home = 3, "HOME";
+    Back = 4, "BACK";
+    Call = 5, "CALL";
+    Endcall = 6, "ENDCALL";
+    Num0 = 7, "0";
+    Num1 = 8, "1";
+    Num2 = 9, "2";
+    Num3 = 10, "3";
+    Num4 = 11, "4";
+    Num5 = 12, "5";
+    Num6 = 13, "6";
+    Num7 = 14, "7";
+    Num8 = 15, "8";
+    Num9 = 16, "9";
+    Star = 17, "STAR";
+    Pound = 18, "POUND";
+    DpadUp = 19, "DPAD_UP";
+    DpadDown = 20, "DPAD_DOWN";
+    DpadLeft = 21, "DPAD_LEFT";
+    DpadRight = 22, "DPAD_RIGHT";
+    DpadCenter = 23, "DPAD_CENTER";
+    VolumeUp = 24, "VOLUME_UP";
+    VolumeDown = 25, "VOLUME_DOWN";
+    Power = 26, "POWER";
+    Camera = 27, "CAMERA";
+    Clear = 28, "CLEAR";
+    A = 29, "A";
+    B = 30, "B";
+    C = 31, "C";
+    D = 32, "D";
+    E = 33, "E";
+    F = 34, "F";
+    G = 35, "G";
+    H = 36, "H";
+    I = 37, "I";
+    J = 38, "J";
+    K = 39, "K";
+    L = 40, "L";
+    M = 41, "M";
+    N = 42, "N";
+    O = 43, "O";
+    P = 44, "P";
+    Q = 45, "Q";
+    R = 46, "R";
+    S = 47, "S";
+    T = 48, "T";
+    U = 49, "U";
+    V = 50, "V";
+    W = 51, "W";
+    X = 52, "X";
+    Y = 53, "Y";
+    Z = 54, "Z";
+    Comma = 55, "COMMA";
+    Period = 56, "PERIOD";
+    AltLeft = 57, "ALT_LEFT";
+    AltRight = 58, "ALT_RIGHT";
+    ShiftLeft = 59, "SHIFT_LEFT";
+    ShiftRight = 60, "SHIFT_RIGHT";
+    Tab = 61, "TAB";
+    Space = 62, "SPACE";
+    Sym = 63, "SYM";
+    Explorer = 64, "EXPLORER";
+    Envelope = 65, "ENVELOPE";
+    Enter = 66, "ENTER";
+    Del = 67, "DEL";
+    Grave = 68, "GRAVE";
+    Minus = 69, "MINUS";
+    Equals = 70, "EQUALS";
+    LeftBracket = 71, "LEFT_BRACKET";
+    RightBracket = 72, "RIGHT_BRACKET";
+    Backslash = 73, "BACKSLASH";
+    Semicolon = 74, "SEMICOLON";
+    Apostrophe = 75, "APOSTROPHE";
+    Slash = 76, "SLASH";
+    At = 77, "AT";
+    Num = 78, "NUM";
+    Headsethook = 79, "HEADSETHOOK";
+    Focus = 80, "FOCUS";
+    Plus = 81, "PLUS";
+    Menu = 82, "MENU";
+    Notification = 83, "NOTIFICATION";
+    Search = 84, "SEARCH";
+    MediaPlayPause = 85, "MEDIA_PLAY_PAUSE";
+    MediaStop = 86, "MEDIA_STOP";
+    MediaNext = 87, "MEDIA_NEXT";
+    MediaPrevious = 88, "MEDIA_PREVIOUS";
+    MediaRewind = 89, "MEDIA_REWIND";
+    MediaFastForward = 90, "MEDIA_FAST_FORWARD";
+    Mute = 91, "MUTE";
+    PageUp = 92, "PAGE_UP";
+    PageDown = 93, "PAGE_DOWN";
+    Escape = 111, "ESCAPE";
+    ForwardDel = 112, "FORWARD_DEL";
+    CtrlLeft = 113, "CTRL_LEFT";
+    CtrlRight = 114, "CTRL_RIGHT";
+    CapsLock = 115, "CAPS_LOCK";
+    MetaLeft = 117, "META_LEFT";
+    MetaRight = 118, "META_RIGHT";
+    MoveHome = 122, "MOVE_HOME";
+    MoveEnd = 123, "MOVE_END";
+    Insert = 124, "INSERT";
+    MediaPlay = 126, "MEDIA_PLAY";
+    MediaPause = 127, "MEDIA_PAUSE";
+    F1 = 131, "F1";
+    F2 = 132, "F2";
+    F3 = 133, "F3";
+    F4 = 134, "F4";
+    F5 = 135, "F5";
+    F6 = 136, "F6";
+    F7 = 137, "F7";
+    F8 = 138, "F8";
+    F9 = 139, "F9";
+    F10 = 140, "F10";
+    F11 = 141, "F11";
+    F12 = 142, "F12";
+    VolumeMute = 164, "VOLUME_MUTE";
+    Info = 165, "INFO";
+    ChannelUp = 166, "CHANNEL_UP";
+    ChannelDown = 167, "CHANNEL_DOWN";
+    ZoomIn = 168, "ZOOM_IN";
+    ZoomOut = 169, "ZOOM_OUT";
+    Settings = 176, "SETTINGS";
+    AppSwitch = 187, "APP_SWITCH";
+    Assist = 219, "ASSIST";
+    BrightnessDown = 220, "BRIGHTNESS_DOWN";
+    BrightnessUp = 221, "BRIGHTNESS_UP";
+    Sleep = 223, "SLEEP";
+    Wakeup = 224, "WAKEUP";
+    VoiceAssist = 231, "VOICE_ASSIST";
+    Cut = 277, "CUT";
+    Copy = 278, "COPY";
+    Paste = 279, "PASTE";
+    SystemNavigationUp = 280, "SYSTEM_NAVIGATION_UP";
+    SystemNavigationDown = 281, "SYSTEM_NAVIGATION_DOWN";
+    SystemNavigationLeft = 282, "SYSTEM_NAVIGATION_LEFT";
+    SystemNavigationRight = 283, "SYSTEM_NAVIGATION_RIGHT";
+    AllApps = 284, "ALL_APPS";
+    Refresh = 285, "REFRESH";
+}
+
+/// Parses a key code from its constant name, e.g. `KEYCODE_HOME`, its name without the
+/// prefix, e.g. `HOME`, or its value, e.g. `3`.
+impl FromStr for KeyCode {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = match s.strip_prefix("KEYCODE_") {
+            Some(name) => Self::from_name(name),
+            // Digits are key code values, not the names of the digit keys.
+            None => match s.parse() {
+                Ok(code) => Some(Self::from_code(code)),
+                Err(_) => Self::from_name(s),
+            },
+        };
+        key.ok_or_else(|| AdbError::Parse {
+            value: s.to_string(),
+            source_type: "&str",
+            target_type: "KeyCode",
+            source: None,
+        })
+    }
+}
+
+impl Display for KeyCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "KEYCODE_{}", name),
+            None => write!(f, "{}", self.code()),
+        }
+    }
+}
+
+/// The action of a [`MotionEvent`] step.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MotionAction {
+    Down,
+    Move,
+    Up,
+    Cancel,
+}
+
+impl Display for MotionAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Down => "DOWN",
+            Self::Move => "MOVE",
+            Self::Up => "UP",
+            Self::Cancel => "CANCEL",
+        })
+    }
+}
+
+/// A gesture made of raw motion events (`input motionevent`), e.g. a drag with a pause.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::input::{MotionEvent, Point};
+///
+/// let drag = MotionEvent::new()
+///     .down(Point::new(100, 800))
+///     .pause(Duration::from_millis(600))
+///     .move_to(Point::new(100, 500))
+///     .up(Point::new(100, 200));
+/// assert_eq!(
+///     "input motionevent DOWN 100 800; sleep 0.6; input motionevent MOVE 100 500; \
+///      input motionevent UP 100 200",
+///     drag.to_command()
+/// );
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct MotionEvent {
+    source: Option<String>,
+    steps: Vec<MotionStep>,
+}
+
+/// A step of a [`MotionEvent`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+enum MotionStep {
+    Event(MotionAction, Point),
+    Pause(Duration),
+}
+
+impl MotionEvent {
+    /// Creates an empty gesture.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the input source, e.g. `touchscreen` or `mouse`.
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Appends the event `action` at `point`.
+    pub fn event(mut self, action: MotionAction, point: Point) -> Self {
+        self.steps.push(MotionStep::Event(action, point));
+        self
+    }
+
+    /// Appends a `DOWN` event at `point`.
+    pub fn down(self, point: Point) -> Self {
+        self.event(MotionAction::Down, point)
+    }
+
+    /// Appends a `MOVE` event to `point`.
+    pub fn move_to(self, point: Point) -> Self {
+        self.event(MotionAction::Move, point)
+    }
+
+    /// Appends an `UP` event at `point`.
+    pub fn up(self, point: Point) -> Self {
+        self.event(MotionAction::Up, point)
+    }
+
+    /// Appends a pause of `duration`, with a precision of a millisecond.
+    pub fn pause(mut self, duration: Duration) -> Self {
+        self.steps.push(MotionStep::Pause(duration));
+        self
+    }
+
+    /// Returns the shell command sending the gesture.
+    pub fn to_command(&self) -> String {
+        let source = match &self.source {
+            Some(source) => format!("{} ", shell::quote(source)),
+            None => String::new(),
+        };
+        let commands: Vec<_> = self
+            .steps
+            .iter()
+            .map(|step| match step {
+                MotionStep::Event(action, point) => {
+                    format!("input {}motionevent {} {}", source, action, point)
+                }
+                MotionStep::Pause(duration) => {
+                    format!("sleep {}", duration.as_millis() as f64 / 1000.0)
+                }
+            })
+            .collect();
+        commands.join("; ")
+    }
+}
+
+/// Returns the argument of `input text` typing `text`, spaces replaced by `%s`.
+///
+/// Fails if `text` isn't ASCII, or contains `%s` which can't be typed.
+pub fn encode_text(text: &str) -> Result<String, AdbError> {
+    if !text.is_ascii() || text.contains("%s") {
+        return Err(AdbError::Parse {
+            value: text.to_string(),
+            source_type: "&str",
+            target_type: "input text",
+            source: None,
+        });
+    }
+    Ok(shell::quote(&text.replace(' ', "%s")))
+}
+
+/// Fails if the output of `input` reports an error, `input` exiting with 0 regardless on
+/// older versions.
+fn check_input_output(output: &str) -> Result<(), AdbError> {
+    match output
+        .lines()
+        .find(|line| line.starts_with("Error") || line.starts_with("Exception"))
+    {
+        Some(line) => Err(AdbError::Server {
+            message: line.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+impl Device {
+    /// Runs `command`, failing if `input` reports an error.
+    fn input(&self, command: &str) -> Result<(), AdbError> {
+        check_input_output(&self.shell_checked(command)?)
+    }
+
+    /// Taps the screen at `x`, `y` (`input tap`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use adb::input::{KeyCode, Point};
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// device.tap(540, 1200).unwrap();
+    /// device.text("hello world").unwrap();
+    /// device.keyevent(KeyCode::Enter).unwrap();
+    /// device
+    ///     .swipe(Point::new(540, 1800), Point::new(540, 600), Duration::from_millis(300))
+    ///     .unwrap();
+    /// ```
+    pub fn tap(&self, x: i32, y: i32) -> Result<(), AdbError> {
+        self.input(&format!("input tap {} {}", x, y))
+    }
+
+    /// Swipes from `from` to `to` in `duration` (`input swipe`). Swiping without moving
+    /// long-presses.
+    pub fn swipe(&self, from: Point, to: Point, duration: Duration) -> Result<(), AdbError> {
+        self.input(&format!(
+            "input swipe {} {} {}",
+            from,
+            to,
+            duration.as_millis()
+        ))
+    }
+
+    /// Types `text` in the focused field (`input text`), see [`encode_text`].
+    pub fn text(&self, text: &str) -> Result<(), AdbError> {
+        self.input(&format!("input text {}", encode_text(text)?))
+    }
+
+    /// Presses and releases `key` (`input keyevent`).
+    pub fn keyevent(&self, key: KeyCode) -> Result<(), AdbError> {
+        self.input(&format!("input keyevent {}", key.code()))
+    }
+
+    /// Long-presses `key` (`input keyevent --longpress`).
+    pub fn long_keyevent(&self, key: KeyCode) -> Result<(), AdbError> {
+        self.input(&format!("input keyevent --longpress {}", key.code()))
+    }
+
+    /// Sends the events of `gesture` (`input motionevent`, Android 11 and later).
+    pub fn motion_event(&self, gesture: &MotionEvent) -> Result<(), AdbError> {
+        self.input(&gesture.to_command())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_code() {
+        assert_eq!(3, KeyCode::Home.code());
+        assert_eq!(KeyCode::Enter, KeyCode::from_code(66));
+        assert_eq!(KeyCode::Other(1000), KeyCode::from_code(1000));
+        assert_eq!(KeyCode::Home, "KEYCODE_HOME".parse().unwrap());
+        assert_eq!(KeyCode::Num0, "KEYCODE_0".parse().unwrap());
+        assert_eq!(KeyCode::DpadUp, "DPAD_UP".parse().unwrap());
+        assert_eq!(KeyCode::Back, "4".parse().unwrap());
+        assert!("KEYCODE_NOPE".parse::<KeyCode>().is_err());
+        assert_eq!(
+            "KEYCODE_MEDIA_PLAY_PAUSE",
+            KeyCode::MediaPlayPause.to_string()
+        );
+        assert_eq!("1000", KeyCode::Other(1000).to_string());
+    }
+
+    #[test]
+    fn test_encode_text() {
+        assert_eq!("'hello%sworld'", encode_text("hello world").unwrap());
+        assert_eq!(
+            "'it'\\''s%s$HOME%s&%s\"quoted\"'",
+            encode_text("it's $HOME & \"quoted\"").unwrap()
+        );
+        assert!(encode_text("100%s").is_err());
+        assert!(encode_text("héllo").is_err());
+    }
+
+    #[test]
+    fn test_motion_event() {
+        let tap = MotionEvent::new()
+            .source("touchscreen")
+            .down(Point::new(10, 20))
+            .up((10, 20).into());
+        assert_eq!(
+            "input 'touchscreen' motionevent DOWN 10 20; input 'touchscreen' motionevent UP 10 20",
+            tap.to_command()
+        );
+        assert_eq!("", MotionEvent::new().to_command());
+        assert!(check_input_output("Error: Unknown command: motionevent\n").is_err());
+        assert!(check_input_output("").is_ok());
+    }
+}
@@ -0,0 +1,513 @@
+//! This module provides APK install and uninstall.
+//!
+//! Devices supporting the `cmd` feature (Android 7 and later) receive the APK directly on the
+//! stdin of `cmd package install`, run through the binder bridge on devices supporting
+//! `abb_exec`. Older devices get the APK pushed to a temporary file installed with
+//! `pm install`.
+//!
+//! The package manager replies with `Success`, or `Failure [<code>: <message>]`.
+//!
+//! Large APKs signed with a v4 signature install faster incrementally, see
+//! [`crate::incremental`].
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::features::Feature;
+use crate::shell;
+use crate::sync::DEFAULT_MODE;
+
+/// The directory APKs are pushed to on devices without the `cmd` feature.
+pub const TEMP_DIR: &str = "/data/local/tmp";
+
+/// The options of `pm install`.
+///
+/// # Examples
+///
+/// ```
+/// use adb::install::InstallOptions;
+///
+/// let options = InstallOptions::new().replace(true).grant_permissions(true);
+/// assert_eq!(options.args(), ["-r", "-g"]);
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct InstallOptions {
+    replace: bool,
+    allow_downgrade: bool,
+    grant_permissions: bool,
+    allow_test: bool,
+    user: Option<String>,
+}
+
+impl InstallOptions {
+    /// Creates options without any flag.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to replace an installed package, keeping its data (`-r`).
+    pub fn replace(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+
+    /// Sets whether to allow replacing a package with an older version (`-d`).
+    pub fn allow_downgrade(mut self, allow: bool) -> Self {
+        self.allow_downgrade = allow;
+        self
+    }
+
+    /// Sets whether to grant all the runtime permissions of the manifest (`-g`).
+    pub fn grant_permissions(mut self, grant: bool) -> Self {
+        self.grant_permissions = grant;
+        self
+    }
+
+    /// Sets whether to allow test-only packages (`-t`).
+    pub fn allow_test(mut self, allow: bool) -> Self {
+        self.allow_test = allow;
+        self
+    }
+
+    /// Installs for the given user id, or `all` or `current` (`--user <user>`).
+    pub fn user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Returns the arguments of `pm install` for these options.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (enabled, flag) in [
+            (self.replace, "-r"),
+            (self.allow_downgrade, "-d"),
+            (self.grant_permissions, "-g"),
+            (self.allow_test, "-t"),
+        ] {
+            if enabled {
+                args.push(flag.to_string());
+            }
+        }
+        if let Some(user) = &self.user {
+            args.push("--user".to_string());
+            args.push(user.clone());
+        }
+        args
+    }
+}
+
+/// The reason the package manager rejected an install or uninstall.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum InstallErrorKind {
+    AlreadyExists,
+    InvalidApk,
+    InsufficientStorage,
+    DuplicatePackage,
+    UpdateIncompatible,
+    SharedUserIncompatible,
+    MissingSharedLibrary,
+    OlderSdk,
+    NewerSdk,
+    TestOnly,
+    CpuAbiIncompatible,
+    NoMatchingAbis,
+    VersionDowngrade,
+    VerificationFailure,
+    UserRestricted,
+    Aborted,
+    InternalError,
+    NoCertificates,
+    InconsistentCertificates,
+    ManifestMalformed,
+    /// Uninstalling failed for an internal reason, e.g. the package isn't installed.
+    DeleteFailedInternalError,
+    /// The package is a device admin and can't be uninstalled.
+    DeleteFailedDevicePolicyManager,
+    /// Another code, or a failure without code.
+    Other(String),
+}
+
+impl InstallErrorKind {
+    /// Returns the code printed by the package manager, e.g. `INSTALL_FAILED_ALREADY_EXISTS`.
+    pub fn code(&self) -> &str {
+        match self {
+            Self::AlreadyExists => "INSTALL_FAILED_ALREADY_EXISTS",
+            Self::InvalidApk => "INSTALL_FAILED_INVALID_APK",
+            Self::InsufficientStorage => "INSTALL_FAILED_INSUFFICIENT_STORAGE",
+            Self::DuplicatePackage => "INSTALL_FAILED_DUPLICATE_PACKAGE",
+            Self::UpdateIncompatible => "INSTALL_FAILED_UPDATE_INCOMPATIBLE",
+            Self::SharedUserIncompatible => "INSTALL_FAILED_SHARED_USER_INCOMPATIBLE",
+            Self::MissingSharedLibrary => "INSTALL_FAILED_MISSING_SHARED_LIBRARY",
+            Self::OlderSdk => "INSTALL_FAILED_OLDER_SDK",
+            Self::NewerSdk => "INSTALL_FAILED_NEWER_SDK",
+            Self::TestOnly => "INSTALL_FAILED_TEST_ONLY",
+            Self::CpuAbiIncompatible => "INSTALL_FAILED_CPU_ABI_INCOMPATIBLE",
+            Self::NoMatchingAbis => "INSTALL_FAILED_NO_MATCHING_ABIS",
+            Self::VersionDowngrade => "INSTALL_FAILED_VERSION_DOWNGRADE",
+            Self::VerificationFailure => "INSTALL_FAILED_VERIFICATION_FAILURE",
+            Self::UserRestricted => "INSTALL_FAILED_USER_RESTRICTED",
+            Self::Aborted => "INSTALL_FAILED_ABORTED",
+            Self::InternalError => "INSTALL_FAILED_INTERNAL_ERROR",
+            Self::NoCertificates => "INSTALL_PARSE_FAILED_NO_CERTIFICATES",
+            Self::InconsistentCertificates => "INSTALL_PARSE_FAILED_INCONSISTENT_CERTIFICATES",
+            Self::ManifestMalformed => "INSTALL_PARSE_FAILED_MANIFEST_MALFORMED",
+            Self::DeleteFailedInternalError => "DELETE_FAILED_INTERNAL_ERROR",
+            Self::DeleteFailedDevicePolicyManager => "DELETE_FAILED_DEVICE_POLICY_MANAGER",
+            Self::Other(code) => code,
+        }
+    }
+}
+
+impl Display for InstallErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for InstallErrorKind {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "INSTALL_FAILED_ALREADY_EXISTS" => Self::AlreadyExists,
+            "INSTALL_FAILED_INVALID_APK" => Self::InvalidApk,
+            "INSTALL_FAILED_INSUFFICIENT_STORAGE" => Self::InsufficientStorage,
+            "INSTALL_FAILED_DUPLICATE_PACKAGE" => Self::DuplicatePackage,
+            "INSTALL_FAILED_UPDATE_INCOMPATIBLE" => Self::UpdateIncompatible,
+            "INSTALL_FAILED_SHARED_USER_INCOMPATIBLE" => Self::SharedUserIncompatible,
+            "INSTALL_FAILED_MISSING_SHARED_LIBRARY" => Self::MissingSharedLibrary,
+            "INSTALL_FAILED_OLDER_SDK" => Self::OlderSdk,
+            "INSTALL_FAILED_NEWER_SDK" => Self::NewerSdk,
+            "INSTALL_FAILED_TEST_ONLY" => Self::TestOnly,
+            "INSTALL_FAILED_CPU_ABI_INCOMPATIBLE" => Self::CpuAbiIncompatible,
+            "INSTALL_FAILED_NO_MATCHING_ABIS" => Self::NoMatchingAbis,
+            "INSTALL_FAILED_VERSION_DOWNGRADE" => Self::VersionDowngrade,
+            "INSTALL_FAILED_VERIFICATION_FAILURE" => Self::VerificationFailure,
+            "INSTALL_FAILED_USER_RESTRICTED" => Self::UserRestricted,
+            "INSTALL_FAILED_ABORTED" => Self::Aborted,
+            "INSTALL_FAILED_INTERNAL_ERROR" => Self::InternalError,
+            "INSTALL_PARSE_FAILED_NO_CERTIFICATES" => Self::NoCertificates,
+            "INSTALL_PARSE_FAILED_INCONSISTENT_CERTIFICATES" => Self::InconsistentCertificates,
+            "INSTALL_PARSE_FAILED_MANIFEST_MALFORMED" => Self::ManifestMalformed,
+            "DELETE_FAILED_INTERNAL_ERROR" => Self::DeleteFailedInternalError,
+            "DELETE_FAILED_DEVICE_POLICY_MANAGER" => Self::DeleteFailedDevicePolicyManager,
+            _ => Self::Other(s.to_string()),
+        })
+    }
+}
+
+/// A failed install or uninstall.
+///
+/// # Syntax
+///
+/// `Failure [<code>]` or `Failure [<code>: <message>]`
+///
+/// ```
+/// # use adb::install::{InstallError, InstallErrorKind};
+/// let error: InstallError = "Failure [INSTALL_FAILED_OLDER_SDK: Requires newer sdk version #34]"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(error.kind, InstallErrorKind::OlderSdk);
+/// assert_eq!(error.message, "Requires newer sdk version #34");
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct InstallError {
+    pub kind: InstallErrorKind,
+    /// The explanation following the code, empty if there's none.
+    pub message: String,
+}
+
+impl Display for InstallError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failure [{}", self.kind)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl FromStr for InstallError {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let reason = s
+            .strip_prefix("Failure [")
+            .and_then(|s| s.strip_suffix(']'))
+            .ok_or_else(|| AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "InstallError",
+                source: None,
+            })?;
+        let (code, message) = reason.split_once(": ").unwrap_or((reason, ""));
+        Ok(Self {
+            kind: code.parse()?,
+            message: message.to_string(),
+        })
+    }
+}
+
+impl Error for InstallError {}
+
+/// Checks the output of a package manager command.
+///
+/// Returns the `Success` line, which carries the session id of `install-create`.
+pub(crate) fn check_output(output: &str) -> Result<&str, AdbError> {
+    if let Some(success) = output.lines().find(|line| line.starts_with("Success")) {
+        return Ok(success);
+    }
+    let error = match output.lines().find(|line| line.starts_with("Failure [")) {
+        Some(failure) => failure.trim_end().parse()?,
+        // e.g. `Error: Unable to open file: ...` from old package managers.
+        None => InstallError {
+            kind: InstallErrorKind::Other(String::new()),
+            message: output.trim().to_string(),
+        },
+    };
+    Err(AdbError::Install(error))
+}
+
+/// Parses the session id from `Success: created install session [<id>]`.
+fn parse_session(success: &str) -> Result<u32, AdbError> {
+    success
+        .rsplit_once('[')
+        .and_then(|(_, id)| id.strip_suffix(']'))
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| AdbError::Protocol {
+            code: None,
+            message: format!("unexpected reply to `install-create`: {}", success),
+        })
+}
+
+/// Returns the file name of a local APK.
+pub(crate) fn file_name(path: &Path) -> Result<String, AdbError> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput).into())
+}
+
+impl Device {
+    /// Runs `cmd package <args>`, streaming `input` to its stdin, and checks its output.
+    ///
+    /// The command runs through the binder bridge if the device supports it, and is quoted
+    /// for the shell of `exec:` otherwise.
+    fn package_command(&self, args: &[String], input: Option<&Path>) -> Result<String, AdbError> {
+        let mut stream = if self.has_feature(Feature::AbbExec)? {
+            let mut abb_args = vec!["package"];
+            abb_args.extend(args.iter().map(String::as_str));
+            self.abb_exec(&abb_args)?
+        } else {
+            let args: Vec<_> = args.iter().map(|arg| shell::quote(arg)).collect();
+            self.open(&format!("exec:cmd package {}", args.join(" ")))?
+        };
+        if let Some(input) = input {
+            io::copy(&mut File::open(input)?, &mut stream)?;
+        }
+        let mut output = String::new();
+        stream.read_to_string(&mut output)?;
+        check_output(&output).map(str::to_string)
+    }
+
+    /// Runs `pm <args>` over the shell and checks its output.
+    fn pm_command(&self, args: &str) -> Result<String, AdbError> {
+        let output = self.shell(&format!("pm {}", args))?;
+        let mut text = output.stdout_lossy();
+        text.push_str(&output.stderr_lossy());
+        check_output(&text).map(str::to_string)
+    }
+
+    /// Installs the APK at `path` (`adb install`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use adb::error::AdbError;
+    /// use adb::install::{InstallErrorKind, InstallOptions};
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// match device.install(Path::new("app.apk"), &InstallOptions::new()) {
+    ///     Err(AdbError::Install(e)) if e.kind == InstallErrorKind::AlreadyExists => {
+    ///         println!("already installed")
+    ///     }
+    ///     result => result.unwrap(),
+    /// }
+    /// ```
+    pub fn install(&self, path: &Path, options: &InstallOptions) -> Result<(), AdbError> {
+        self.install_multiple(&[path], options)
+    }
+
+    /// Installs a package split into several APKs in one session (`adb install-multiple`).
+    pub fn install_multiple(
+        &self,
+        paths: &[&Path],
+        options: &InstallOptions,
+    ) -> Result<(), AdbError> {
+        let options = options.args();
+        if self.has_feature(Feature::Cmd)? {
+            let command = |name: &str, size: u64| {
+                let mut args = vec![name.to_string(), "-S".to_string(), size.to_string()];
+                args.extend(options.iter().cloned());
+                args
+            };
+            if let [path] = paths {
+                let args = command("install", path.metadata()?.len());
+                return self.package_command(&args, Some(path)).map(drop);
+            }
+            let total: u64 = paths
+                .iter()
+                .map(|path| path.metadata().map(|metadata| metadata.len()))
+                .sum::<io::Result<_>>()?;
+            let session =
+                parse_session(&self.package_command(&command("install-create", total), None)?)?;
+            let session_command = |name: &str| vec![name.to_string(), session.to_string()];
+            for (i, path) in paths.iter().enumerate() {
+                let size = path.metadata()?.len();
+                let args = [
+                    "install-write".to_string(),
+                    "-S".to_string(),
+                    size.to_string(),
+                    session.to_string(),
+                    format!("{}_{}", i, file_name(path)?),
+                    "-".to_string(),
+                ];
+                if let Err(e) = self.package_command(&args, Some(path)) {
+                    let _ = self.package_command(&session_command("install-abandon"), None);
+                    return Err(e);
+                }
+            }
+            self.package_command(&session_command("install-commit"), None)
+                .map(drop)
+        } else {
+            self.install_pushed(paths, &options.join(" "))
+        }
+    }
+
+    /// Installs APKs pushed to [`TEMP_DIR`], for devices without the `cmd` feature.
+    fn install_pushed(&self, paths: &[&Path], options: &str) -> Result<(), AdbError> {
+        let mut remotes = Vec::new();
+        let result = (|| {
+            for path in paths {
+                let remote = format!("{}/{}", TEMP_DIR, file_name(path)?);
+                self.push(path, &remote, DEFAULT_MODE)?;
+                remotes.push(remote);
+            }
+            if let [remote] = &remotes[..] {
+                return self
+                    .pm_command(&format!("install {} {}", options, shell::quote(remote)))
+                    .map(drop);
+            }
+            let total: u64 = paths
+                .iter()
+                .map(|path| path.metadata().map(|metadata| metadata.len()))
+                .sum::<io::Result<_>>()?;
+            let session = parse_session(
+                &self.pm_command(&format!("install-create -S {} {}", total, options))?,
+            )?;
+            for (i, (path, remote)) in paths.iter().zip(&remotes).enumerate() {
+                let size = path.metadata()?.len();
+                self.pm_command(&format!(
+                    "install-write -S {} {} {} {}",
+                    size,
+                    session,
+                    i,
+                    shell::quote(remote)
+                ))?;
+            }
+            self.pm_command(&format!("install-commit {}", session))
+                .map(drop)
+        })();
+        for remote in remotes {
+            let _ = self.shell(&format!("rm -f {}", shell::quote(&remote)));
+        }
+        result
+    }
+
+    /// Uninstalls `package`, keeping its data and cache directories if `keep_data` is set
+    /// (`adb uninstall [-k]`).
+    pub fn uninstall(&self, package: &str, keep_data: bool) -> Result<(), AdbError> {
+        let mut args = vec!["uninstall".to_string()];
+        if keep_data {
+            args.push("-k".to_string());
+        }
+        args.push(package.to_string());
+        if self.has_feature(Feature::Cmd)? {
+            self.package_command(&args, None).map(drop)
+        } else {
+            self.pm_command(&args.join(" ")).map(drop)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_options_args() {
+        assert!(InstallOptions::new().args().is_empty());
+        let options = InstallOptions::new()
+            .allow_downgrade(true)
+            .allow_test(true)
+            .user("current");
+        assert_eq!(["-d", "-t", "--user", "current"], &options.args()[..]);
+    }
+
+    #[test]
+    fn test_install_error_parse() {
+        let error = InstallError {
+            kind: InstallErrorKind::AlreadyExists,
+            message: "Attempt to re-install com.example without first uninstalling.".to_string(),
+        };
+        let s = "Failure [INSTALL_FAILED_ALREADY_EXISTS: Attempt to re-install com.example without first uninstalling.]";
+        assert_eq!(error, s.parse().unwrap());
+        assert_eq!(s, error.to_string());
+        let error: InstallError = "Failure [DELETE_FAILED_INTERNAL_ERROR]".parse().unwrap();
+        assert_eq!(InstallErrorKind::DeleteFailedInternalError, error.kind);
+        assert!(error.message.is_empty());
+        let error: InstallError = "Failure [not installed for 0]".parse().unwrap();
+        assert_eq!(
+            InstallErrorKind::Other("not installed for 0".to_string()),
+            error.kind
+        );
+        for s in [
+            "",
+            "Success",
+            "Failure INSTALL_FAILED_ABORTED",
+            "Failure [INSTALL_FAILED_ABORTED",
+        ] {
+            assert!(s.parse::<InstallError>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_check_output() {
+        assert_eq!(
+            "Success",
+            check_output("Performing Streamed Install\nSuccess\n").unwrap()
+        );
+        match check_output("Failure [INSTALL_FAILED_TEST_ONLY: installPackageLI]\n") {
+            Err(AdbError::Install(e)) => assert_eq!(InstallErrorKind::TestOnly, e.kind),
+            other => panic!("{:?}", other),
+        }
+        match check_output("Error: Unable to open file: a.apk\n") {
+            Err(AdbError::Install(e)) => assert_eq!("Error: Unable to open file: a.apk", e.message),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_session() {
+        assert_eq!(
+            1234,
+            parse_session("Success: created install session [1234]").unwrap()
+        );
+        assert!(parse_session("Success").is_err());
+    }
+}
@@ -0,0 +1,456 @@
+//! This module transfers whole directory trees, resuming interrupted transfers from a journal
+//! on disk.
+//!
+//! [`Device::push_dir`] and [`Device::pull_dir`] record every completed file in a
+//! [`TransferJournal`]: its path relative to the transferred directory, size, modification
+//! time and checksum. The next run skips the files whose journal entry still matches both
+//! sides, so a sync of gigabytes interrupted by a disconnected cable only transfers what's
+//! left. [`DirTransferOptions::force`] transfers everything again.
+//!
+//! The journal is a text file, one tab-separated `<size> <mtime> <checksum> <path>` line per
+//! file, appended and flushed as files complete. A line torn by a crash is ignored, so at
+//! worst its file is transferred again.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::device::Device;
+use crate::error::AdbError;
+
+/// The first line of journal files.
+const JOURNAL_HEADER: &str = "# adb transfer journal v1";
+
+/// Returns the 64-bit FNV-1a hash of the content of `reader`, the checksum of the journal.
+///
+/// It detects changed and torn files, not tampering.
+pub fn checksum<R: Read>(mut reader: R) -> io::Result<u64> {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(hash),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for &byte in &buf[..n] {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// A file completed by a directory transfer.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct JournalEntry {
+    /// The path relative to the transferred directory, with `/` separators.
+    pub path: String,
+    /// The size in bytes.
+    pub size: u64,
+    /// The modification time of the source, in seconds since the unix epoch.
+    pub mtime: i64,
+    /// The [`checksum`] of the content.
+    pub checksum: u64,
+}
+
+impl JournalEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, '\t');
+        let size = fields.next()?.parse().ok()?;
+        let mtime = fields.next()?.parse().ok()?;
+        let checksum = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let path = fields.next().filter(|path| !path.is_empty())?;
+        Some(Self {
+            path: path.to_string(),
+            size,
+            mtime,
+            checksum,
+        })
+    }
+}
+
+/// The completed files of a directory transfer, persisted in a file.
+#[derive(Debug)]
+pub struct TransferJournal {
+    file: File,
+    entries: HashMap<String, JournalEntry>,
+}
+
+impl TransferJournal {
+    /// Opens the journal at `path`, creating it if it doesn't exist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let mut entries = HashMap::new();
+        for line in String::from_utf8_lossy(&content).lines() {
+            if let Some(entry) = JournalEntry::parse(line) {
+                entries.insert(entry.path.clone(), entry);
+            }
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        match content.last() {
+            None => writeln!(file, "{}", JOURNAL_HEADER)?,
+            // Terminates a torn line, so it isn't merged with the next entry.
+            Some(b'\n') => {}
+            Some(_) => writeln!(file)?,
+        }
+        Ok(Self { file, entries })
+    }
+
+    /// Creates an empty journal at `path`, discarding the existing one.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", JOURNAL_HEADER)?;
+        Ok(Self {
+            file,
+            entries: HashMap::new(),
+        })
+    }
+
+    /// Returns the entry of `path`.
+    pub fn get(&self, path: &str) -> Option<&JournalEntry> {
+        self.entries.get(path)
+    }
+
+    /// Returns the number of completed files.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if no file completed.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records that `entry` completed, persisting it before returning.
+    pub fn record(&mut self, entry: JournalEntry) -> io::Result<()> {
+        // Paths with line breaks can't be journaled, they are transferred every time.
+        if !entry.path.contains(['\n', '\r']) {
+            writeln!(
+                self.file,
+                "{}\t{}\t{:016x}\t{}",
+                entry.size, entry.mtime, entry.checksum, entry.path
+            )?;
+            self.file.flush()?;
+        }
+        self.entries.insert(entry.path.clone(), entry);
+        Ok(())
+    }
+}
+
+/// How [`Device::push_dir`] and [`Device::pull_dir`] transfer a directory.
+///
+/// # Examples
+///
+/// ```
+/// use adb::journal::DirTransferOptions;
+///
+/// let options = DirTransferOptions::new().journal("assets.journal");
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct DirTransferOptions {
+    journal: Option<PathBuf>,
+    force: bool,
+}
+
+impl DirTransferOptions {
+    /// Creates options transferring every file, without a journal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the completed files in the journal at `path`, and skips those it already
+    /// lists and which didn't change.
+    pub fn journal(mut self, path: impl Into<PathBuf>) -> Self {
+        self.journal = Some(path.into());
+        self
+    }
+
+    /// Transfers every file, starting a new journal.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Returns the path of the journal.
+    pub fn get_journal(&self) -> Option<&Path> {
+        self.journal.as_deref()
+    }
+
+    /// Opens the journal, or creates a new one if forced.
+    fn open_journal(&self) -> io::Result<Option<TransferJournal>> {
+        match &self.journal {
+            Some(path) if self.force => TransferJournal::create(path).map(Some),
+            Some(path) => TransferJournal::open(path).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The statistics of a directory transfer.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct DirTransferStats {
+    /// The transferred files.
+    pub files: u64,
+    /// The files skipped as already transferred according to the journal.
+    pub skipped: u64,
+    /// The transferred bytes.
+    pub bytes: u64,
+    /// The duration of the transfer.
+    pub duration: Duration,
+}
+
+/// Returns the regular files under `dir`, by path relative to `dir` with `/` separators,
+/// sorted.
+fn local_files(dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![(String::new(), dir.to_path_buf())];
+    while let Some((prefix, dir)) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let relative = format!("{}{}", prefix, name);
+            // Follows symbolic links, like `adb push`.
+            let metadata = fs::metadata(entry.path())?;
+            if metadata.is_dir() {
+                dirs.push((format!("{}/", relative), entry.path()));
+            } else if metadata.is_file() {
+                files.push((relative, entry.path()));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the mode of a pushed file, its permissions on unix.
+fn push_mode(metadata: &fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o777
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        crate::sync::DEFAULT_MODE
+    }
+}
+
+/// Returns `remote` joined with the relative path `relative`.
+fn remote_join(remote: &str, relative: &str) -> String {
+    format!("{}/{}", remote.trim_end_matches('/'), relative)
+}
+
+impl Device {
+    /// Pushes the files under the local directory `local` to `remote` on the device,
+    /// creating the missing directories.
+    ///
+    /// With a journal, the files it lists are skipped if the local file still has the
+    /// journaled size, modification time and checksum, and the remote file the journaled
+    /// size.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use adb::journal::DirTransferOptions;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let options = DirTransferOptions::new().journal("/tmp/media.journal");
+    /// // Run again after an interruption to push the remaining files only.
+    /// let stats = device
+    ///     .push_dir(Path::new("media"), "/sdcard/Movies", &options)
+    ///     .unwrap();
+    /// println!("{} pushed, {} skipped", stats.files, stats.skipped);
+    /// ```
+    pub fn push_dir(
+        &self,
+        local: &Path,
+        remote: &str,
+        options: &DirTransferOptions,
+    ) -> Result<DirTransferStats, AdbError> {
+        let clock = self.server().clock.clone();
+        let start = clock.now();
+        let mut journal = options.open_journal()?;
+        let mut stats = DirTransferStats::default();
+        for (relative, path) in local_files(local)? {
+            let remote_path = remote_join(remote, &relative);
+            let metadata = fs::metadata(&path)?;
+            let mtime = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |mtime| mtime.as_secs() as i64);
+            let checksum = checksum(File::open(&path)?)?;
+            let entry = JournalEntry {
+                path: relative,
+                size: metadata.len(),
+                mtime,
+                checksum,
+            };
+            if let Some(journal) = &journal {
+                if journal.get(&entry.path) == Some(&entry)
+                    && self.stat(&remote_path)?.size == entry.size
+                {
+                    stats.skipped += 1;
+                    continue;
+                }
+            }
+            stats.bytes += self.push(&path, &remote_path, push_mode(&metadata))?.bytes;
+            stats.files += 1;
+            if let Some(journal) = &mut journal {
+                journal.record(entry)?;
+            }
+        }
+        stats.duration = clock.now() - start;
+        Ok(stats)
+    }
+
+    /// Pulls the files under the directory `remote` on the device to the local directory
+    /// `local`, creating the missing directories.
+    ///
+    /// With a journal, the files it lists are skipped if the remote file still has the
+    /// journaled size and modification time, and the local file the journaled size and
+    /// checksum. Symbolic links and special files are skipped.
+    pub fn pull_dir(
+        &self,
+        remote: &str,
+        local: &Path,
+        options: &DirTransferOptions,
+    ) -> Result<DirTransferStats, AdbError> {
+        let clock = self.server().clock.clone();
+        let start = clock.now();
+        let mut journal = options.open_journal()?;
+        let mut stats = DirTransferStats::default();
+        let mut dirs = vec![String::new()];
+        while let Some(prefix) = dirs.pop() {
+            let mut entries = self.list_dir(&remote_join(remote, &prefix))?;
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            fs::create_dir_all(local.join(&prefix))?;
+            for dir_entry in entries {
+                if dir_entry.name == "." || dir_entry.name == ".." {
+                    continue;
+                }
+                let relative = format!("{}{}", prefix, dir_entry.name);
+                if dir_entry.stat.is_dir() {
+                    dirs.push(format!("{}/", relative));
+                    continue;
+                }
+                if !dir_entry.stat.is_file() {
+                    continue;
+                }
+                let path = local.join(&relative);
+                if let Some(journal) = &journal {
+                    let unchanged = journal.get(&relative).filter(|entry| {
+                        entry.size == dir_entry.stat.size && entry.mtime == dir_entry.stat.mtime
+                    });
+                    if let Some(entry) = unchanged {
+                        let local_unchanged = match File::open(&path) {
+                            Ok(file) => {
+                                file.metadata()?.len() == entry.size
+                                    && checksum(file)? == entry.checksum
+                            }
+                            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+                            Err(e) => return Err(e.into()),
+                        };
+                        if local_unchanged {
+                            stats.skipped += 1;
+                            continue;
+                        }
+                    }
+                }
+                stats.bytes += self.pull(&remote_join(remote, &relative), &path)?.bytes;
+                stats.files += 1;
+                if let Some(journal) = &mut journal {
+                    journal.record(JournalEntry {
+                        path: relative,
+                        size: dir_entry.stat.size,
+                        mtime: dir_entry.stat.mtime,
+                        checksum: checksum(File::open(&path)?)?,
+                    })?;
+                }
+            }
+        }
+        stats.duration = clock.now() - start;
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("adb-journal-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(0xcbf29ce484222325, checksum(&b""[..]).unwrap());
+        assert_eq!(0xa430d84680aabd0b, checksum(&b"hello"[..]).unwrap());
+    }
+
+    #[test]
+    fn test_transfer_journal() {
+        let dir = temp_dir("journal");
+        let path = dir.join("transfer.journal");
+        let entry = |path: &str, size| JournalEntry {
+            path: path.to_string(),
+            size,
+            mtime: 1700000000,
+            checksum: 0xa430d84680aabd0b,
+        };
+        let mut journal = TransferJournal::open(&path).unwrap();
+        assert!(journal.is_empty());
+        journal.record(entry("a.bin", 5)).unwrap();
+        journal.record(entry("sub/b c\tq.bin", 7)).unwrap();
+        drop(journal);
+        // A line torn by a crash.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"12\t17000").unwrap();
+        drop(file);
+
+        let mut journal = TransferJournal::open(&path).unwrap();
+        journal.record(entry("c.bin", 9)).unwrap();
+        drop(journal);
+        let journal = TransferJournal::open(&path).unwrap();
+        assert_eq!(3, journal.len());
+        assert_eq!(Some(&entry("a.bin", 5)), journal.get("a.bin"));
+        assert_eq!(
+            Some(&entry("sub/b c\tq.bin", 7)),
+            journal.get("sub/b c\tq.bin")
+        );
+        drop(journal);
+        assert!(TransferJournal::create(&path).unwrap().is_empty());
+        assert!(TransferJournal::open(&path).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_local_files() {
+        let dir = temp_dir("walk");
+        fs::create_dir_all(dir.join("b/c")).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("b/c/d.txt"), "d").unwrap();
+        fs::write(dir.join("b/e.txt"), "e").unwrap();
+        let files: Vec<_> = local_files(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|(relative, _)| relative)
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(["a.txt", "b/c/d.txt", "b/e.txt"], &files[..]);
+        assert_eq!("/sdcard/x/a.txt", remote_join("/sdcard/x/", "a.txt"));
+    }
+}
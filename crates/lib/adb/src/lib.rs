@@ -1,2 +1,216 @@
+//! A library for talking to Android devices through adb.
+//!
+//! # Features
+//!
+//! The socket family types in [`socket`], the [`error`] types, the timeouts and retries of
+//! [`connect`] and the log priorities and tags of [`log`] are always available. The socket
+//! families and their parse errors come from the `adb-types` crate, which also builds in
+//! `no_std` environments with `alloc`, e.g. agents running on devices.
+//! Everything else is split into cargo features, so users who only need to parse
+//! and format adb socket specs can disable the default features and skip the client stack:
+//!
+//! - `client` (default): the host protocol client talking to the adb server, the services
+//!   of adbd managing the device, backups, the emulator console client, booting AVDs,
+//!   device locks shared by the processes of the host, the USB ports of devices,
+//!   power-cycling hooks, pools running work on many devices at once, remaining time
+//!   estimates of long workflows, operation ids correlating the logs of concurrent runs,
+//!   and cancellation tokens stopping transfers and streams.
+//! - `sync` (default): file transfer over the sync protocol, and directory transfers
+//!   resumed from a journal.
+//! - `shell` (default): shell services, system properties, device config flags, package
+//!   details, system service dumps, the package and activity managers, application
+//!   lifecycle, input injection, waiting for conditions on the device, network condition
+//!   simulation, Bluetooth and NFC toggling, audio volumes, media sessions, camera tests,
+//!   thermal monitoring, UI modes such as the dark theme, localized string resources of
+//!   applications, waking and unlocking the screen or disabling its keyguard, Wi-Fi network
+//!   provisioning, installing CA certificates, the global HTTP proxy, and statsd metrics.
+//! - `logcat` (default): binary logcat reader.
+//! - `install` (default): APK install and uninstall, including incremental installs.
+//! - `forward` (default): port forwarding and reverse forwarding.
+//! - `profile` (default): heap dumps, start-up latency of applications and other profiling
+//!   helpers.
+//! - `screen` (default): screenshots and screen recordings.
+//! - `bugreport` (default): bug reports, with progress and cancellation.
+//! - `symbolicate` (default): parsing and symbolication of native backtraces.
+//! - `command`: a builder running the `adb` executable, without the client stack.
+//! - `script`: plans of pushes, installs, shell commands and other operations, saved as
+//!   text files and replayed on devices, resuming from the step which failed.
+//! - `mdns`: discovery of wireless debugging services.
+//! - `scan`: discovery of adb daemons listening on a subnet, for networks blocking mDNS.
+//! - `power`: power-cycling through USB hubs controlled by `uhubctl` or `ykushcmd`.
+//! - `usb`: direct USB transport without an adb server, on top of rusb.
+//! - `auth`: RSA keys authenticating this host to devices, on top of rsa.
+//! - `tls`: direct TCP transport with TLS for wireless debugging, on top of rustls.
+//! - `png`: decoding of screenshots, on top of png.
+//! - `async`: async variants of the client API on top of tokio.
+//! - `proto`: device and app tracking in protobuf, with the connection types and USB speeds
+//!   of devices, and decoding of statsd reports, on top of prost.
+//! - `serde`: `Serialize` and `Deserialize` for socket families, device listings and the
+//!   steps of scripts, as their string form.
+//! - `tracing`: spans and events of the requests and messages, and hex dumps of the
+//!   protocol traffic toggled at runtime, on top of tracing.
+//! - `msrv`: only use std APIs available at the minimum supported Rust version.
+//!
+//! # MSRV
+//!
+//! The minimum supported Rust version is 1.70.
+//! Newer std APIs are only used behind `rustversion` checks, see the `compat` module.
+
+/// Implements `Serialize` and `Deserialize` through the `Display` and `FromStr`
+/// implementations, like the `AdbSocketFamily` derive.
+#[cfg(all(feature = "serde", feature = "client"))]
+macro_rules! serde_via_str {
+    ($($ty:ty),*) => {
+        $(
+            impl serde::Serialize for $ty {
+                fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> serde::Deserialize<'de> for $ty {
+                fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+                    s.parse().map_err(serde::de::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "client")]
+pub mod adbd;
+#[cfg(feature = "shell")]
+pub mod am;
+#[cfg(feature = "shell")]
+pub mod app;
+#[cfg(feature = "async")]
+pub mod r#async;
+#[cfg(feature = "shell")]
+pub mod audio;
+#[cfg(feature = "auth")]
+pub mod auth;
+#[cfg(feature = "client")]
+pub mod avd;
+#[cfg(feature = "client")]
+pub mod backup;
+#[cfg_attr(not(feature = "shell"), allow(dead_code))]
+mod base64;
+#[cfg(feature = "bugreport")]
+pub mod bugreport;
+#[cfg(feature = "shell")]
+pub mod camera;
+#[cfg(feature = "client")]
+pub mod cancel;
+#[cfg(feature = "shell")]
+pub mod cert;
+#[cfg(feature = "client")]
+pub mod clock;
+#[cfg(feature = "command")]
+pub mod command;
+#[cfg_attr(not(feature = "client"), allow(dead_code))]
+mod compat;
+pub mod connect;
+#[cfg(feature = "client")]
+pub mod device;
+#[cfg(feature = "shell")]
+pub mod device_config;
+#[cfg(feature = "shell")]
+pub mod dumpsys;
+#[cfg(feature = "client")]
+pub mod emulator;
 pub mod error;
+#[cfg(feature = "client")]
+pub mod features;
+#[cfg(feature = "forward")]
+pub mod forward;
+#[cfg(feature = "install")]
+pub mod incremental;
+#[cfg(feature = "shell")]
+pub mod input;
+#[cfg(feature = "install")]
+pub mod install;
+#[cfg(feature = "sync")]
+pub mod journal;
+#[cfg(feature = "client")]
+pub mod lock;
+#[cfg(feature = "shell")]
+pub mod lockscreen;
+pub mod log;
+#[cfg(feature = "logcat")]
+pub mod logcat;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+#[cfg(feature = "shell")]
+pub mod media;
+#[cfg(feature = "shell")]
+pub mod network;
+#[cfg(feature = "client")]
+pub mod op;
+#[cfg(feature = "shell")]
+pub mod package;
+#[cfg(feature = "client")]
+pub mod pair;
+#[cfg(feature = "shell")]
+pub mod pm;
+#[cfg(feature = "client")]
+pub mod pool;
+#[cfg(feature = "client")]
+pub mod power;
+#[cfg(feature = "profile")]
+pub mod profile;
+#[cfg(feature = "client")]
+pub mod progress;
+#[cfg(feature = "shell")]
+pub mod properties;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(feature = "client")]
+pub mod protocol;
+#[cfg(feature = "shell")]
+pub mod proxy;
+#[cfg(feature = "shell")]
+pub mod radio;
+#[cfg(feature = "shell")]
+pub mod resources;
+#[cfg(feature = "scan")]
+pub mod scan;
+#[cfg(feature = "screen")]
+pub mod screen;
+#[cfg(feature = "script")]
+pub mod script;
+#[cfg(any(feature = "client", feature = "command"))]
+mod sdk;
+#[cfg(feature = "client")]
+pub mod server;
+#[cfg(feature = "shell")]
+pub mod shell;
 pub mod socket;
+#[cfg(feature = "profile")]
+pub mod startup;
+#[cfg(feature = "shell")]
+pub mod stats;
+#[cfg(any(feature = "sync", feature = "logcat"))]
+pub mod stream;
+#[cfg(feature = "symbolicate")]
+pub mod symbolicate;
+#[cfg(feature = "sync")]
+pub mod sync;
+#[cfg(feature = "shell")]
+pub mod thermal;
+#[cfg(feature = "client")]
+pub mod topology;
+#[cfg(feature = "tracing")]
+pub mod trace;
+#[cfg(feature = "client")]
+pub mod track;
+#[cfg(any(feature = "usb", feature = "tls", feature = "scan"))]
+pub mod transport;
+#[cfg(feature = "shell")]
+pub mod uimode;
+#[cfg(feature = "client")]
+pub mod version;
+#[cfg(feature = "shell")]
+pub mod wait;
+#[cfg(feature = "shell")]
+pub mod wifi;
@@ -0,0 +1,550 @@
+//! This module provides [`DeviceLock`], an exclusive lock on a device shared by the
+//! processes of a host, so that tools like CI agents don't drive the same device at once.
+//!
+//! A lock is a file named after the serial of the device in [`default_dir`], created with
+//! `O_EXCL` so that only one process wins. It records its holder and an expiry time:
+//!
+//! ```text
+//! pid=1234
+//! owner=ci@build-7
+//! expires=1718000000000
+//! token=1234-1717999990000000000-0
+//! ```
+//!
+//! `expires` is in milliseconds since the Unix epoch. Locks are time-boxed: a lock past its
+//! expiry, or whose process is gone (on Linux), is stale and broken by the next process
+//! acquiring it. Holders of long tasks [renew](DeviceLock::renew) their lock.
+//!
+//! Breaking a stale lock is best-effort: two processes breaking the same stale lock at once
+//! may both acquire it.
+
+use std::fmt::{Debug, Display, Formatter};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::clock::{self, Clock};
+use crate::device::{Device, Transport};
+use crate::error::AdbError;
+
+/// The default interval between two attempts to acquire a held lock.
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How old an unreadable lock file must be to be broken, since lock files are empty for a
+/// moment while being created.
+const TORN_LOCK_AGE: Duration = Duration::from_secs(5);
+
+/// Returns the default directory of the lock files, `adb-device-locks` in the temporary
+/// directory.
+pub fn default_dir() -> PathBuf {
+    std::env::temp_dir().join("adb-device-locks")
+}
+
+/// Returns the name of the lock file of `serial`, replacing the characters not allowed in
+/// file names, like the colons of `host:port` serials.
+fn file_name(serial: &str) -> String {
+    let name: String = serial
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{}.lock", name)
+}
+
+/// Returns milliseconds since the Unix epoch.
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Returns a token unique to this acquisition among all the processes of the host.
+fn new_token() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    format!(
+        "{}-{}-{}",
+        std::process::id(),
+        nanos,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Returns the default owner of locks, `<user>@<host>`.
+fn default_owner() -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    let host = std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    format!("{}@{}", user, host)
+}
+
+/// The holder of a lock, as recorded in its file.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LockHolder {
+    /// The process holding the lock.
+    pub pid: u32,
+    /// Who holds the lock, `<user>@<host>` unless set with [`LockOptions::owner`].
+    pub owner: String,
+    /// When the lock expires.
+    pub expires: SystemTime,
+    /// Identifies one acquisition of the lock.
+    token: String,
+}
+
+impl LockHolder {
+    /// Returns `true` if the lock expired at `now`, or if its process is gone.
+    pub fn is_stale(&self, now: SystemTime) -> bool {
+        if self.expires <= now {
+            return true;
+        }
+        // Lock files are local to the host, so the pid is one of its processes.
+        cfg!(target_os = "linux")
+            && Path::new("/proc/self").exists()
+            && !Path::new(&format!("/proc/{}", self.pid)).exists()
+    }
+}
+
+impl Display for LockHolder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "pid={}", self.pid)?;
+        writeln!(f, "owner={}", self.owner)?;
+        writeln!(f, "expires={}", unix_millis(self.expires))?;
+        writeln!(f, "token={}", self.token)
+    }
+}
+
+impl FromStr for LockHolder {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || AdbError::Parse {
+            value: s.to_string(),
+            source_type: "&str",
+            target_type: "LockHolder",
+            source: None,
+        };
+        let (mut pid, mut owner, mut expires, mut token) = (None, None, None, None);
+        for line in s.lines() {
+            match line.split_once('=') {
+                Some(("pid", value)) => pid = value.parse().ok(),
+                Some(("owner", value)) => owner = Some(value.to_string()),
+                Some(("expires", value)) => expires = value.parse().ok(),
+                Some(("token", value)) => token = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Ok(Self {
+            pid: pid.ok_or_else(error)?,
+            owner: owner.unwrap_or_default(),
+            expires: UNIX_EPOCH + Duration::from_millis(expires.ok_or_else(error)?),
+            token: token.ok_or_else(error)?,
+        })
+    }
+}
+
+/// What happened while acquiring a lock, reported to a [`LockObserver`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum LockEvent {
+    /// The lock of `serial` is held by `holder`, the acquisition waits or fails.
+    Contended { serial: String, holder: LockHolder },
+    /// The stale lock of `serial`, held by `holder`, was broken.
+    StaleBroken { serial: String, holder: LockHolder },
+}
+
+/// Receives the [`LockEvent`]s of acquisitions, e.g. to log contention on shared devices.
+pub trait LockObserver: Debug + Send + Sync {
+    fn on_event(&self, event: &LockEvent);
+}
+
+/// How to acquire a [`DeviceLock`].
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::lock::LockOptions;
+///
+/// let options = LockOptions::new()
+///     .owner("nightly-tests")
+///     .wait(Duration::from_secs(60));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LockOptions {
+    dir: PathBuf,
+    owner: String,
+    wait: Duration,
+    interval: Duration,
+    clock: Arc<dyn Clock>,
+    observer: Option<Arc<dyn LockObserver>>,
+}
+
+impl LockOptions {
+    /// Creates options failing at once if the lock is held, with locks in [`default_dir`].
+    pub fn new() -> Self {
+        Self {
+            dir: default_dir(),
+            owner: default_owner(),
+            wait: Duration::ZERO,
+            interval: DEFAULT_RETRY_INTERVAL,
+            clock: clock::system(),
+            observer: None,
+        }
+    }
+
+    /// Sets the directory of the lock files, which must be the same for all the processes.
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Sets the owner recorded in the lock file, for diagnostics.
+    pub fn owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = owner.into();
+        self
+    }
+
+    /// Sets how long to wait for a held lock before failing with [`AdbError::Timeout`].
+    pub fn wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Sets the interval between two attempts to acquire a held lock.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the clock timing the waits.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the observer of contention.
+    pub fn observer(mut self, observer: impl LockObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    fn notify(&self, event: LockEvent) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(&event);
+        }
+    }
+}
+
+impl Default for LockOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An exclusive lock on a device, released when dropped.
+#[derive(Debug)]
+pub struct DeviceLock {
+    serial: String,
+    path: PathBuf,
+    holder: LockHolder,
+}
+
+impl DeviceLock {
+    /// Acquires the lock of the device `serial` for `ttl`, failing at once if it's held.
+    pub fn acquire(serial: &str, ttl: Duration) -> Result<Self, AdbError> {
+        Self::acquire_with(serial, ttl, &LockOptions::new())
+    }
+
+    /// Acquires the lock of the device `serial` for `ttl`, waiting for it as set in
+    /// `options`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use adb::lock::{DeviceLock, LockOptions};
+    ///
+    /// let options = LockOptions::new().wait(Duration::from_secs(600));
+    /// let lock = DeviceLock::acquire_with("emulator-5554", Duration::from_secs(900), &options)
+    ///     .unwrap();
+    /// // Drive the device.
+    /// lock.release().unwrap();
+    /// ```
+    pub fn acquire_with(
+        serial: &str,
+        ttl: Duration,
+        options: &LockOptions,
+    ) -> Result<Self, AdbError> {
+        fs::create_dir_all(&options.dir)?;
+        let path = options.dir.join(file_name(serial));
+        let start = options.clock.now();
+        loop {
+            let now = SystemTime::now();
+            let holder = LockHolder {
+                pid: std::process::id(),
+                owner: options.owner.clone(),
+                expires: now + ttl,
+                token: new_token(),
+            };
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(holder.to_string().as_bytes())?;
+                    return Ok(Self {
+                        serial: serial.to_string(),
+                        path,
+                        holder,
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                // Released in the meantime.
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            match content.parse::<LockHolder>() {
+                Ok(current) if current.is_stale(now) => {
+                    if fs::read_to_string(&path).ok().as_deref() == Some(&*content) {
+                        remove_if_exists(&path)?;
+                    }
+                    options.notify(LockEvent::StaleBroken {
+                        serial: serial.to_string(),
+                        holder: current,
+                    });
+                    continue;
+                }
+                Ok(current) => {
+                    options.notify(LockEvent::Contended {
+                        serial: serial.to_string(),
+                        holder: current.clone(),
+                    });
+                    if options.clock.now() - start >= options.wait {
+                        return Err(AdbError::Timeout {
+                            condition: format!(
+                                "lock of {} held by {} (pid {})",
+                                serial, current.owner, current.pid
+                            ),
+                            timeout: options.wait,
+                        });
+                    }
+                }
+                // A lock being written is empty for a moment, a lock torn by a crash forever.
+                Err(_) if is_torn(&path, now) => {
+                    remove_if_exists(&path)?;
+                    continue;
+                }
+                Err(_) if options.clock.now() - start >= options.wait => {
+                    return Err(AdbError::Timeout {
+                        condition: format!("lock of {}", serial),
+                        timeout: options.wait,
+                    })
+                }
+                Err(_) => {}
+            }
+            options.clock.sleep(options.interval);
+        }
+    }
+
+    /// Returns the serial of the locked device.
+    pub fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    /// Returns when the lock expires.
+    pub fn expires(&self) -> SystemTime {
+        self.holder.expires
+    }
+
+    /// Extends the lock to `ttl` from now, failing if it was broken by another process.
+    pub fn renew(&mut self, ttl: Duration) -> Result<(), AdbError> {
+        self.check_held()?;
+        let mut holder = self.holder.clone();
+        holder.expires = SystemTime::now() + ttl;
+        // Write then rename, so that other processes never read a partial lock.
+        let tmp = self.path.with_extension(format!("lock.{}", holder.pid));
+        fs::write(&tmp, holder.to_string())?;
+        fs::rename(&tmp, &self.path)?;
+        self.holder = holder;
+        Ok(())
+    }
+
+    /// Releases the lock, failing if it was broken by another process.
+    pub fn release(self) -> Result<(), AdbError> {
+        self.check_held()?;
+        remove_if_exists(&self.path)
+    }
+
+    /// Checks that the lock file is still the one of this lock.
+    fn check_held(&self) -> Result<(), AdbError> {
+        let current = fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|content| content.parse::<LockHolder>().ok());
+        match current {
+            Some(current) if current.token == self.holder.token => Ok(()),
+            _ => Err(AdbError::Server {
+                message: format!("the lock of {} was broken by another process", self.serial),
+            }),
+        }
+    }
+}
+
+impl Drop for DeviceLock {
+    fn drop(&mut self) {
+        if self.check_held().is_ok() {
+            let _ = remove_if_exists(&self.path);
+        }
+    }
+}
+
+/// Returns `true` if the unreadable lock file `path` is older than [`TORN_LOCK_AGE`].
+fn is_torn(path: &Path, now: SystemTime) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified + TORN_LOCK_AGE <= now)
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), AdbError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+impl Device {
+    /// Acquires the lock of the device for `ttl`, waiting for it as set in `options`, and
+    /// timing the waits with the clock of the server.
+    ///
+    /// Fails if the device isn't selected by serial or transport id. Devices selected by
+    /// transport id are locked as `transport-id-<id>`, apart from their serial number.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use adb::lock::LockOptions;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().device("emulator-5554");
+    /// let _lock = device
+    ///     .lock(Duration::from_secs(900), &LockOptions::new().wait(Duration::from_secs(60)))
+    ///     .unwrap();
+    /// ```
+    pub fn lock(&self, ttl: Duration, options: &LockOptions) -> Result<DeviceLock, AdbError> {
+        let serial = match self.transport() {
+            Transport::Serial(serial) => serial.clone(),
+            Transport::TransportId(id) => format!("transport-id-{}", id),
+            _ => {
+                return Err(AdbError::Server {
+                    message: "only devices selected by serial or transport id can be locked"
+                        .to_string(),
+                })
+            }
+        };
+        let mut options = options.clone();
+        options.clock = self.server().clock.clone();
+        DeviceLock::acquire_with(&serial, ttl, &options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[derive(Debug, Default)]
+    struct Events(Mutex<Vec<LockEvent>>);
+
+    impl LockObserver for Arc<Events> {
+        fn on_event(&self, event: &LockEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("adb-lock-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!("emulator-5554.lock", file_name("emulator-5554"));
+        assert_eq!("192.168.1.2_5555.lock", file_name("192.168.1.2:5555"));
+    }
+
+    #[test]
+    fn test_lock_holder_parse() {
+        let holder: LockHolder =
+            "pid=1234\nowner=ci@build-7\nexpires=1718000000000\ntoken=1234-1\n"
+                .parse()
+                .unwrap();
+        assert_eq!(1234, holder.pid);
+        assert_eq!("ci@build-7", holder.owner);
+        assert_eq!(holder, holder.to_string().parse().unwrap());
+        assert!(holder.is_stale(SystemTime::now()));
+        assert!("".parse::<LockHolder>().is_err());
+    }
+
+    #[test]
+    fn test_lock_contention() {
+        let dir = test_dir("contention");
+        let events = Arc::new(Events::default());
+        let clock = MockClock::new();
+        let options = LockOptions::new()
+            .dir(&dir)
+            .wait(Duration::from_secs(2))
+            .clock(clock.clone())
+            .observer(events.clone());
+        let lock =
+            DeviceLock::acquire_with("emulator-5554", Duration::from_secs(60), &options).unwrap();
+        let start = clock.now();
+        let err = DeviceLock::acquire_with("emulator-5554", Duration::from_secs(60), &options)
+            .unwrap_err();
+        assert!(matches!(err, AdbError::Timeout { .. }));
+        assert!(clock.now() - start >= Duration::from_secs(2));
+        assert!(matches!(
+            events.0.lock().unwrap()[0],
+            LockEvent::Contended { .. }
+        ));
+        assert!(
+            DeviceLock::acquire_with("emulator-5556", Duration::from_secs(60), &options).is_ok()
+        );
+        lock.release().unwrap();
+        assert!(
+            DeviceLock::acquire_with("emulator-5554", Duration::from_secs(60), &options).is_ok()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lock_stale() {
+        let dir = test_dir("stale");
+        let events = Arc::new(Events::default());
+        let options = LockOptions::new().dir(&dir).observer(events.clone());
+        let expired = DeviceLock::acquire_with("emulator-5554", Duration::ZERO, &options).unwrap();
+        let mut lock =
+            DeviceLock::acquire_with("emulator-5554", Duration::from_secs(60), &options).unwrap();
+        assert!(matches!(
+            events.0.lock().unwrap()[0],
+            LockEvent::StaleBroken { .. }
+        ));
+        assert!(expired.release().is_err());
+        lock.renew(Duration::from_secs(120)).unwrap();
+        drop(lock);
+        assert!(!dir.join("emulator-5554.lock").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,397 @@
+//! This module wakes, sleeps and unlocks the screen of devices, so automation starts from an
+//! awake and unlocked device.
+//!
+//! The screen state is read from `dumpsys power`: the `Display Power: state=` line, then
+//! `mWakefulness=`, and `mScreenOn=` before Android 5. Whether the keyguard shows is read
+//! from `dumpsys window policy`: the `showing=` line of the `KeyguardServiceDelegate`, and
+//! `mShowingLockscreen=` before Android 8.
+//!
+//! [`Device::unlock`] sends `MENU`, which dismisses an insecure keyguard and brings up the
+//! PIN or password entry of a secure one, then types the PIN followed by `ENTER`.
+//!
+//! [`Device::disable_keyguard`] removes the keyguard for good, e.g. on lab devices which
+//! must not get stuck on it after reboots, with `locksettings` (Android 8 and later): `clear`
+//! removes the credential, then `set-disabled true` the insecure keyguard. `locksettings`
+//! exits with 0 on errors, which it prints instead.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::input::KeyCode;
+use crate::shell;
+use crate::wait::PollOptions;
+
+/// The time to wait for the screen or the keyguard to change state.
+const STATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The power state of a device, `mWakefulness` in `dumpsys power`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Wakefulness {
+    /// The screen is off.
+    Asleep,
+    /// The screen is on.
+    Awake,
+    /// The screen shows a screensaver.
+    Dreaming,
+    /// The screen shows an ambient display, in a low power state.
+    Dozing,
+}
+
+impl FromStr for Wakefulness {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Asleep" => Ok(Self::Asleep),
+            "Awake" => Ok(Self::Awake),
+            "Dreaming" => Ok(Self::Dreaming),
+            "Dozing" => Ok(Self::Dozing),
+            _ => Err(AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Wakefulness",
+                source: None,
+            }),
+        }
+    }
+}
+
+/// A lock screen credential.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum LockCredential {
+    Pin(String),
+    Password(String),
+    /// The dots of the pattern, numbered from 1 at the top left to 9 at the bottom right,
+    /// e.g. `1236` for an L.
+    Pattern(String),
+}
+
+impl LockCredential {
+    /// Returns the credential.
+    pub fn secret(&self) -> &str {
+        match self {
+            Self::Pin(secret) | Self::Password(secret) | Self::Pattern(secret) => secret,
+        }
+    }
+
+    /// Returns the `locksettings` command setting the credential.
+    fn set_command(&self) -> &'static str {
+        match self {
+            Self::Pin(_) => "set-pin",
+            Self::Password(_) => "set-password",
+            Self::Pattern(_) => "set-pattern",
+        }
+    }
+}
+
+/// The keyguard before [`Device::disable_keyguard`], to restore it with
+/// [`Device::restore_keyguard`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct KeyguardState {
+    /// The credential that was cleared.
+    pub credential: Option<LockCredential>,
+    /// `true` if the keyguard was already disabled.
+    pub disabled: bool,
+}
+
+/// Fails if `locksettings` reports an error, e.g. `Old password '1234' didn't match`.
+fn check_locksettings_output(command: &str, output: &str) -> Result<(), AdbError> {
+    match output.lines().map(str::trim).find(|line| {
+        line.starts_with("Error")
+            || line.starts_with("Old password")
+            || line.contains("didn't match")
+            || line.contains("Exception")
+    }) {
+        Some(line) => Err(AdbError::Server {
+            message: format!("`{}`: {}", command, line),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Returns the value of the first `<key>=<value>` field of `dump`.
+fn field<'a>(dump: &'a str, key: &str) -> Option<&'a str> {
+    dump.split_whitespace()
+        .find_map(|token| token.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// Parses the wakefulness of `dumpsys power`.
+fn parse_wakefulness(dump: &str) -> Option<Wakefulness> {
+    field(dump, "mWakefulness")?.parse().ok()
+}
+
+/// Parses whether the screen is on from `dumpsys power`.
+fn parse_screen_on(dump: &str) -> Option<bool> {
+    let display = dump
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Display Power: state="));
+    if let Some(state) = display {
+        return Some(state.trim() == "ON");
+    }
+    if let Some(wakefulness) = parse_wakefulness(dump) {
+        return Some(wakefulness == Wakefulness::Awake);
+    }
+    field(dump, "mScreenOn")?.parse().ok()
+}
+
+/// Parses whether the keyguard shows from `dumpsys window policy`.
+fn parse_keyguard_showing(dump: &str) -> Option<bool> {
+    let mut delegate = false;
+    for line in dump.lines().map(str::trim) {
+        if line.starts_with("KeyguardServiceDelegate") {
+            delegate = true;
+        } else if let Some(showing) = line.strip_prefix("showing=").filter(|_| delegate) {
+            return showing.parse().ok();
+        }
+    }
+    field(dump, "mShowingLockscreen")?.parse().ok()
+}
+
+impl Device {
+    /// Returns the power state of the device (`dumpsys power`).
+    pub fn wakefulness(&self) -> Result<Wakefulness, AdbError> {
+        let dump = self.shell_checked("dumpsys power")?;
+        parse_wakefulness(&dump).ok_or_else(|| AdbError::Parse {
+            value: "dumpsys power".to_string(),
+            source_type: "&str",
+            target_type: "Wakefulness",
+            source: None,
+        })
+    }
+
+    /// Returns `true` if the screen is on (`dumpsys power`).
+    pub fn is_screen_on(&self) -> Result<bool, AdbError> {
+        let dump = self.shell_checked("dumpsys power")?;
+        parse_screen_on(&dump).ok_or_else(|| AdbError::Parse {
+            value: "dumpsys power".to_string(),
+            source_type: "&str",
+            target_type: "bool",
+            source: Some("no screen state".into()),
+        })
+    }
+
+    /// Returns `true` if the keyguard shows, locked or not (`dumpsys window policy`).
+    pub fn is_keyguard_showing(&self) -> Result<bool, AdbError> {
+        let dump = self.shell_checked("dumpsys window policy")?;
+        parse_keyguard_showing(&dump).ok_or_else(|| AdbError::Parse {
+            value: "dumpsys window policy".to_string(),
+            source_type: "&str",
+            target_type: "bool",
+            source: Some("no keyguard state".into()),
+        })
+    }
+
+    /// Turns the screen on, waiting until it is (`KEYCODE_WAKEUP`).
+    pub fn wake(&self) -> Result<(), AdbError> {
+        if self.is_screen_on()? {
+            return Ok(());
+        }
+        self.keyevent(KeyCode::Wakeup)?;
+        self.wait_for_screen(true)
+    }
+
+    /// Turns the screen off, waiting until it is (`KEYCODE_SLEEP`).
+    pub fn sleep(&self) -> Result<(), AdbError> {
+        if !self.is_screen_on()? {
+            return Ok(());
+        }
+        self.keyevent(KeyCode::Sleep)?;
+        self.wait_for_screen(false)
+    }
+
+    /// Wakes the device and dismisses the keyguard, typing `pin` if the keyguard is secure,
+    /// then waits until the keyguard is gone.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// device.unlock(Some("1234")).unwrap();
+    /// assert!(!device.is_keyguard_showing().unwrap());
+    /// ```
+    pub fn unlock(&self, pin: Option<&str>) -> Result<(), AdbError> {
+        self.wake()?;
+        if !self.is_keyguard_showing()? {
+            return Ok(());
+        }
+        self.keyevent(KeyCode::Menu)?;
+        if let Some(pin) = pin {
+            self.text(pin)?;
+            self.keyevent(KeyCode::Enter)?;
+        }
+        let options = PollOptions::new().timeout(STATE_TIMEOUT);
+        let unlocked = |device: &Device| device.is_keyguard_showing().map(|showing| !showing);
+        self.wait_until(&unlocked, &options).map_err(|e| match e {
+            AdbError::Timeout { timeout, .. } => AdbError::Timeout {
+                condition: "keyguard dismissed".to_string(),
+                timeout,
+            },
+            e => e,
+        })
+    }
+
+    /// Runs `locksettings <args>`, failing if it reports an error, and returns its output.
+    fn locksettings(&self, args: &str) -> Result<String, AdbError> {
+        let command = format!("locksettings {}", args);
+        let output = self.shell_checked(&command)?;
+        check_locksettings_output(&command, &output)?;
+        Ok(output)
+    }
+
+    /// Returns `true` if the insecure keyguard is disabled (`locksettings get-disabled`).
+    pub fn is_keyguard_disabled(&self) -> Result<bool, AdbError> {
+        let output = self.locksettings("get-disabled")?;
+        output.trim().parse().map_err(|e| AdbError::Parse {
+            value: output.trim().to_string(),
+            source_type: "&str",
+            target_type: "bool",
+            source: Some(Box::new(e)),
+        })
+    }
+
+    /// Clears the lock screen `credential` if any, then disables the keyguard, and checks
+    /// that it is, returning the previous state for [`Device::restore_keyguard`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::lockscreen::LockCredential;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let pin = LockCredential::Pin("1234".to_string());
+    /// let previous = device.disable_keyguard(Some(&pin)).unwrap();
+    /// // Run the tests, across reboots.
+    /// device.restore_keyguard(&previous).unwrap();
+    /// ```
+    pub fn disable_keyguard(
+        &self,
+        credential: Option<&LockCredential>,
+    ) -> Result<KeyguardState, AdbError> {
+        let state = KeyguardState {
+            credential: credential.cloned(),
+            disabled: self.is_keyguard_disabled()?,
+        };
+        if let Some(credential) = credential {
+            self.locksettings(&format!(
+                "clear --old {}",
+                shell::quote(credential.secret())
+            ))?;
+        }
+        self.locksettings("set-disabled true")?;
+        if !self.is_keyguard_disabled()? {
+            return Err(AdbError::Server {
+                message: "the keyguard is still enabled, a credential may be set".to_string(),
+            });
+        }
+        Ok(state)
+    }
+
+    /// Restores the keyguard disabled by [`Device::disable_keyguard`], and its credential.
+    pub fn restore_keyguard(&self, state: &KeyguardState) -> Result<(), AdbError> {
+        self.locksettings(&format!("set-disabled {}", state.disabled))?;
+        if let Some(credential) = &state.credential {
+            self.locksettings(&format!(
+                "{} {}",
+                credential.set_command(),
+                shell::quote(credential.secret())
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Waits until the screen is `on` or off.
+    fn wait_for_screen(&self, on: bool) -> Result<(), AdbError> {
+        let options = PollOptions::new().timeout(STATE_TIMEOUT);
+        let screen = |device: &Device| device.is_screen_on().map(|state| state == on);
+        self.wait_until(&screen, &options).map_err(|e| match e {
+            AdbError::Timeout { timeout, .. } => AdbError::Timeout {
+                condition: format!("screen {}", if on { "on" } else { "off" }),
+                timeout,
+            },
+            e => e,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An excerpt of `dumpsys power` of an Android 14 emulator.
+    const POWER: &str = "\
+POWER MANAGER (dumpsys power)
+
+Power Manager State:
+  mDirty=0x0
+  mWakefulness=Asleep
+  mWakefulnessChanging=false
+  mIsPowered=true
+  mStayOn=false
+
+Display Power: state=OFF
+";
+
+    /// An excerpt of `dumpsys window policy` of an Android 14 emulator.
+    const WINDOW_POLICY: &str = "\
+WINDOW MANAGER POLICY STATE (dumpsys window policy)
+    mSafeMode=false mSystemReady=true mSystemBooted=true
+    mKeyguardDrawComplete=true mWindowManagerDrawComplete=true
+    KeyguardServiceDelegate
+      showing=true
+      inputRestricted=true
+      occluded=false
+      secure=true
+";
+
+    #[test]
+    fn test_parse_screen_on() {
+        assert_eq!(Some(Wakefulness::Asleep), parse_wakefulness(POWER));
+        assert_eq!(Some(false), parse_screen_on(POWER));
+        assert_eq!(Some(true), parse_screen_on("  Display Power: state=ON\n"));
+        // The display doesn't show while dozing.
+        assert_eq!(Some(false), parse_screen_on("mWakefulness=Dozing\n"));
+        assert_eq!(Some(true), parse_screen_on("  mScreenOn=true\n"));
+        assert_eq!(None, parse_screen_on(""));
+    }
+
+    #[test]
+    fn test_check_locksettings_output() {
+        assert!(check_locksettings_output(
+            "locksettings clear --old 1234",
+            "Lock credential cleared\n"
+        )
+        .is_ok());
+        assert!(check_locksettings_output("locksettings set-disabled true", "").is_ok());
+        assert!(matches!(
+            check_locksettings_output(
+                "locksettings clear --old 0000",
+                "Old password '0000' didn't match\n"
+            ),
+            Err(AdbError::Server { .. })
+        ));
+        let error = "Error while executing command: set-disabled\n\
+                     java.lang.IllegalStateException: credential set\n";
+        assert!(check_locksettings_output("locksettings set-disabled true", error).is_err());
+        assert_eq!(
+            "set-pattern",
+            LockCredential::Pattern("1236".to_string()).set_command()
+        );
+    }
+
+    #[test]
+    fn test_parse_keyguard_showing() {
+        assert_eq!(Some(true), parse_keyguard_showing(WINDOW_POLICY));
+        let unlocked = WINDOW_POLICY.replace("showing=true", "showing=false");
+        assert_eq!(Some(false), parse_keyguard_showing(&unlocked));
+        assert_eq!(
+            Some(false),
+            parse_keyguard_showing("    mShowingLockscreen=false mShowingDream=false\n")
+        );
+        // `showing=` of other sections doesn't count.
+        assert_eq!(None, parse_keyguard_showing("  showing=true\n"));
+    }
+}
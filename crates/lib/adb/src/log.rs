@@ -0,0 +1,243 @@
+//! This module provides the priority and the tag of Android log entries, shared by the
+//! [`logcat`](crate::logcat) reader and its filters.
+//!
+//! Both format as logcat does with `-v color` off: the priority as its letter, e.g. `W`, and
+//! the tag verbatim. Entries of binary buffers like `events` are tagged with a number, named
+//! in `/system/etc/event-log-tags`. Kernel messages carry a syslog level instead, which
+//! [`LogPriority::from_kernel_level`] maps like logd does.
+
+use std::fmt::{Display, Formatter, Write as _};
+use std::str::FromStr;
+
+use crate::error::AdbError;
+
+/// The priority of a log entry, ordered from the least to the most severe.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum LogPriority {
+    Unknown,
+    Default,
+    Verbose,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+    /// Only used in filters, to hide every entry.
+    Silent,
+}
+
+impl LogPriority {
+    /// Returns the priority with the given value, as found in the log payload.
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Self::Unknown,
+            1 => Self::Default,
+            2 => Self::Verbose,
+            3 => Self::Debug,
+            4 => Self::Info,
+            5 => Self::Warn,
+            6 => Self::Error,
+            7 => Self::Fatal,
+            8 => Self::Silent,
+            _ => return None,
+        })
+    }
+
+    /// Returns the priority of the syslog level `level` of a kernel message, e.g. `3` in
+    /// `<3>` prefixes of `/dev/kmsg`.
+    pub const fn from_kernel_level(level: u8) -> Option<Self> {
+        Some(match level {
+            // KERN_EMERG, KERN_ALERT and KERN_CRIT.
+            0..=2 => Self::Fatal,
+            3 => Self::Error,
+            4 => Self::Warn,
+            // KERN_NOTICE and KERN_INFO.
+            5 | 6 => Self::Info,
+            7 => Self::Debug,
+            _ => return None,
+        })
+    }
+
+    /// Returns the priority of the letter `letter`, the inverse of [`Self::letter`].
+    pub const fn from_letter(letter: char) -> Option<Self> {
+        Some(match letter {
+            '?' => Self::Unknown,
+            '*' => Self::Default,
+            'V' => Self::Verbose,
+            'D' => Self::Debug,
+            'I' => Self::Info,
+            'W' => Self::Warn,
+            'E' => Self::Error,
+            'F' => Self::Fatal,
+            'S' => Self::Silent,
+            _ => return None,
+        })
+    }
+
+    /// Returns the letter of the priority, as used in logcat filters.
+    pub const fn letter(self) -> char {
+        match self {
+            Self::Unknown => '?',
+            Self::Default => '*',
+            Self::Verbose => 'V',
+            Self::Debug => 'D',
+            Self::Info => 'I',
+            Self::Warn => 'W',
+            Self::Error => 'E',
+            Self::Fatal => 'F',
+            Self::Silent => 'S',
+        }
+    }
+}
+
+impl Display for LogPriority {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_char(self.letter())
+    }
+}
+
+impl FromStr for LogPriority {
+    type Err = AdbError;
+
+    /// Parses the letter of a priority usable in filters, `V` to `F` and `S`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(Self::from_letter), chars.next()) {
+            (Some(priority), None) if priority >= Self::Verbose => Ok(priority),
+            _ => Err(AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "LogPriority",
+                source: None,
+            }),
+        }
+    }
+}
+
+/// The tag of a log entry, e.g. `ActivityManager`, or the tag number of a binary entry.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct Tag(String);
+
+impl Tag {
+    /// Creates a tag, as is. Tags read from the device aren't validated, see
+    /// [`Tag::from_str`] for the tags usable in filters.
+    pub fn new(tag: impl Into<String>) -> Self {
+        Self(tag.into())
+    }
+
+    /// Returns the tag of the tag number `number` of a binary entry.
+    pub fn from_event_number(number: u32) -> Self {
+        Self(number.to_string())
+    }
+
+    /// Returns the tag number of a binary entry, `None` if the tag isn't a number.
+    pub fn event_number(&self) -> Option<u32> {
+        self.0.parse().ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Tag {
+    type Err = AdbError;
+
+    /// Parses a tag usable in logcat filters, failing if it's empty, `*`, or has a colon or
+    /// whitespace.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s == "*" || s.contains(|c: char| c == ':' || c.is_whitespace()) {
+            return Err(AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Tag",
+                source: None,
+            });
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl From<&str> for Tag {
+    fn from(tag: &str) -> Self {
+        Self::new(tag)
+    }
+}
+
+impl From<String> for Tag {
+    fn from(tag: String) -> Self {
+        Self(tag)
+    }
+}
+
+impl AsRef<str> for Tag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for Tag {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Tag {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<Tag> for str {
+    fn eq(&self, other: &Tag) -> bool {
+        self == other.0
+    }
+}
+
+impl PartialEq<Tag> for &str {
+    fn eq(&self, other: &Tag) -> bool {
+        *self == other.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_priority() {
+        for value in 0..=8 {
+            let priority = LogPriority::from_u8(value).unwrap();
+            assert_eq!(Some(priority), LogPriority::from_letter(priority.letter()));
+        }
+        assert_eq!(None, LogPriority::from_u8(9));
+        assert_eq!(Some(LogPriority::Fatal), LogPriority::from_kernel_level(0));
+        assert_eq!(Some(LogPriority::Warn), LogPriority::from_kernel_level(4));
+        assert_eq!(Some(LogPriority::Info), LogPriority::from_kernel_level(5));
+        assert_eq!(None, LogPriority::from_kernel_level(8));
+        assert!("?".parse::<LogPriority>().is_err());
+        assert!("*".parse::<LogPriority>().is_err());
+    }
+
+    #[test]
+    fn test_tag() {
+        let tag: Tag = "ActivityManager".parse().unwrap();
+        assert_eq!("ActivityManager", tag.to_string());
+        assert_eq!(tag, "ActivityManager");
+        assert_eq!("ActivityManager", tag);
+        assert_eq!(None, tag.event_number());
+        for s in ["", "*", "a:b", "My Tag"] {
+            assert!(s.parse::<Tag>().is_err(), "{}", s);
+        }
+        // Tags read from the device are kept as is.
+        assert_eq!("My Tag", Tag::new("My Tag").as_str());
+        assert_eq!(Some(42), Tag::from_event_number(42).event_number());
+        assert!(Tag::new("a") < Tag::new("b"));
+    }
+}
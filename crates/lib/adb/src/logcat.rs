@@ -0,0 +1,827 @@
+//! This module provides a reader of the device log, and types for the log buffers.
+//!
+//! The log is read with `logcat -B`, which prints every entry as a binary `logger_entry`:
+//! a header starting with the length of the payload and the size of the header,
+//! followed by the payload. Text buffers hold the priority, the tag and the message
+//! in the payload, binary buffers like `events` a tag number and typed values.
+
+use std::fmt::{Display, Formatter, Write as _};
+use std::io::Read;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cancel::{self, CancelGuard, CancellationToken};
+use crate::device::Device;
+use crate::error::AdbError;
+pub use crate::log::{LogPriority, Tag};
+use crate::protocol::{self, Decoded};
+use crate::server::ServerStream;
+use crate::stream::{self, ServiceStream, StreamDropPolicy};
+
+/// A device log buffer, as selected by `logcat -b <buffer>`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum LogBuffer {
+    Main,
+    System,
+    Radio,
+    Events,
+    Crash,
+    Kernel,
+    Security,
+    Stats,
+    /// The `main`, `system` and `crash` buffers.
+    Default,
+    /// All buffers.
+    All,
+}
+
+impl LogBuffer {
+    /// Returns the name of the buffer accepted by `logcat -b`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Main => "main",
+            Self::System => "system",
+            Self::Radio => "radio",
+            Self::Events => "events",
+            Self::Crash => "crash",
+            Self::Kernel => "kernel",
+            Self::Security => "security",
+            Self::Stats => "stats",
+            Self::Default => "default",
+            Self::All => "all",
+        }
+    }
+}
+
+impl Display for LogBuffer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for LogBuffer {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "main" => Self::Main,
+            "system" => Self::System,
+            "radio" => Self::Radio,
+            "events" => Self::Events,
+            "crash" => Self::Crash,
+            "kernel" => Self::Kernel,
+            "security" => Self::Security,
+            "stats" => Self::Stats,
+            "default" => Self::Default,
+            "all" => Self::All,
+            _ => {
+                return Err(AdbError::Parse {
+                    value: s.to_string(),
+                    source_type: "&str",
+                    target_type: "LogBuffer",
+                    source: None,
+                })
+            }
+        })
+    }
+}
+
+impl LogBuffer {
+    /// Returns the buffer with the given log id, as found in `logger_entry`.
+    pub const fn from_id(id: u32) -> Option<Self> {
+        Some(match id {
+            0 => Self::Main,
+            1 => Self::Radio,
+            2 => Self::Events,
+            3 => Self::System,
+            4 => Self::Crash,
+            5 => Self::Stats,
+            6 => Self::Security,
+            7 => Self::Kernel,
+            _ => return None,
+        })
+    }
+
+    /// Returns `true` if the entries of the buffer hold binary events instead of text.
+    pub const fn is_binary(self) -> bool {
+        matches!(self, Self::Events | Self::Stats | Self::Security)
+    }
+}
+
+/// An entry of the device log.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LogEntry {
+    /// The time the entry was logged.
+    pub timestamp: SystemTime,
+    pub pid: i32,
+    pub tid: u32,
+    /// The priority, always [`LogPriority::Info`] in binary buffers.
+    pub priority: LogPriority,
+    /// The tag, or the tag number in binary buffers.
+    pub tag: Tag,
+    /// The message, or the values of the event formatted like logcat in binary buffers.
+    pub message: String,
+    /// The buffer of the entry, unknown for devices older than Android 5.
+    pub buffer: Option<LogBuffer>,
+}
+
+/// Formats the entry like `logcat -v brief`, e.g. `W/ActivityManager( 1234): message`.
+impl Display for LogEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}({:5}): {}",
+            self.priority, self.tag, self.pid, self.message
+        )
+    }
+}
+
+/// The size of the header of the first `logger_entry` version, which has no `hdr_size`.
+const V1_HEADER_SIZE: usize = 20;
+
+/// The header size from which `logger_entry` includes the log id.
+const LID_HEADER_SIZE: usize = 24;
+
+/// Decodes a `logger_entry` printed by `logcat -B`.
+pub fn decode_entry(bytes: &[u8]) -> Result<Decoded<LogEntry>, AdbError> {
+    let Some(header) = bytes.get(..4) else {
+        return Ok(Decoded::Incomplete { needed: 4 });
+    };
+    let payload_len = u16::from_le_bytes([header[0], header[1]]) as usize;
+    let header_len = match u16::from_le_bytes([header[2], header[3]]) as usize {
+        0 => V1_HEADER_SIZE,
+        len if len < V1_HEADER_SIZE => {
+            return Err(AdbError::Protocol {
+                code: None,
+                message: format!("log entry header of {} bytes is too small", len),
+            })
+        }
+        len => len,
+    };
+    let length = header_len + payload_len;
+    let Some(entry) = bytes.get(..length) else {
+        return Ok(Decoded::Incomplete { needed: length });
+    };
+    let buffer = if header_len >= LID_HEADER_SIZE {
+        LogBuffer::from_id(le_u32(entry, 20))
+    } else {
+        None
+    };
+    let payload = &entry[header_len..];
+    let (priority, tag, message) = match buffer {
+        Some(buffer) if buffer.is_binary() => decode_event(payload),
+        _ => decode_text(payload),
+    };
+    let value = LogEntry {
+        timestamp: UNIX_EPOCH
+            + Duration::new(le_u32(entry, 12).into(), le_u32(entry, 16) % 1_000_000_000),
+        pid: le_u32(entry, 4) as i32,
+        tid: le_u32(entry, 8),
+        priority,
+        tag,
+        message,
+        buffer,
+    };
+    Ok(Decoded::Complete { value, length })
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Decodes the payload of a text entry, `<priority><tag>\0<message>\0`.
+fn decode_text(payload: &[u8]) -> (LogPriority, Tag, String) {
+    let Some((&priority, rest)) = payload.split_first() else {
+        return (LogPriority::Unknown, Tag::default(), String::new());
+    };
+    let priority = LogPriority::from_u8(priority).unwrap_or(LogPriority::Unknown);
+    let mut parts = rest.splitn(2, |&b| b == 0);
+    let tag = parts.next().unwrap_or_default();
+    let message = parts.next().unwrap_or_default();
+    let message = message.strip_suffix(b"\0").unwrap_or(message);
+    (
+        priority,
+        Tag::new(String::from_utf8_lossy(tag)),
+        String::from_utf8_lossy(message).into_owned(),
+    )
+}
+
+/// Decodes the payload of a binary entry, a tag number followed by a typed value.
+fn decode_event(payload: &[u8]) -> (LogPriority, Tag, String) {
+    let Some(tag) = payload.get(..4) else {
+        return (LogPriority::Info, Tag::default(), String::new());
+    };
+    let tag = Tag::from_event_number(u32::from_le_bytes(tag.try_into().unwrap()));
+    let mut message = String::new();
+    let mut values = &payload[4..];
+    if format_event_value(&mut values, &mut message, 0).is_none() {
+        // Show what's left of a malformed event rather than dropping the entry.
+        message.push_str(&String::from_utf8_lossy(values));
+    }
+    (LogPriority::Info, tag, message)
+}
+
+/// Formats the event value at the start of `bytes` like logcat, and advances past it.
+fn format_event_value(bytes: &mut &[u8], out: &mut String, depth: usize) -> Option<()> {
+    fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = (bytes.get(..n)?, bytes.get(n..)?);
+        *bytes = rest;
+        Some(taken)
+    }
+    // Lists can't nest deeper than this in a 64 KiB payload anyway.
+    if depth > 16 {
+        return None;
+    }
+    match take(bytes, 1)?[0] {
+        0 => write!(
+            out,
+            "{}",
+            i32::from_le_bytes(take(bytes, 4)?.try_into().ok()?)
+        )
+        .ok(),
+        1 => write!(
+            out,
+            "{}",
+            i64::from_le_bytes(take(bytes, 8)?.try_into().ok()?)
+        )
+        .ok(),
+        2 => {
+            let len = u32::from_le_bytes(take(bytes, 4)?.try_into().ok()?) as usize;
+            out.push_str(&String::from_utf8_lossy(take(bytes, len)?));
+            Some(())
+        }
+        3 => {
+            let count = take(bytes, 1)?[0];
+            out.push('[');
+            for i in 0..count {
+                if i > 0 {
+                    out.push(',');
+                }
+                format_event_value(bytes, out, depth + 1)?;
+            }
+            out.push(']');
+            Some(())
+        }
+        4 => write!(
+            out,
+            "{}",
+            f32::from_le_bytes(take(bytes, 4)?.try_into().ok()?)
+        )
+        .ok(),
+        _ => None,
+    }
+}
+
+/// The options of a logcat reader, selecting the buffers and filtering the entries.
+///
+/// # Examples
+///
+/// ```
+/// use adb::logcat::{LogBuffer, LogcatOptions, LogPriority};
+///
+/// let options = LogcatOptions::new()
+///     .buffer(LogBuffer::Main)
+///     .filter("ActivityManager", LogPriority::Info)
+///     .min_priority(LogPriority::Silent)
+///     .dump(true);
+/// assert_eq!(options.command(), "logcat -B -d -b main ActivityManager:I *:S");
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct LogcatOptions {
+    buffers: Vec<LogBuffer>,
+    filters: Vec<(Tag, LogPriority)>,
+    min_priority: Option<LogPriority>,
+    dump: bool,
+}
+
+impl LogcatOptions {
+    /// Creates options reading the default buffers, without filters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a buffer to read (`-b <buffer>`).
+    pub fn buffer(mut self, buffer: LogBuffer) -> Self {
+        self.buffers.push(buffer);
+        self
+    }
+
+    /// Only reads entries of `tag` with at least `priority` (`<tag>:<priority>`).
+    ///
+    /// Entries of other tags are still read, unless [`Self::min_priority`] hides them.
+    pub fn filter(mut self, tag: impl Into<Tag>, priority: LogPriority) -> Self {
+        self.filters.push((tag.into(), priority));
+        self
+    }
+
+    /// Only reads entries of tags without a [filter](Self::filter) with at least
+    /// `priority` (`*:<priority>`). Use [`LogPriority::Silent`] to hide them.
+    pub fn min_priority(mut self, priority: LogPriority) -> Self {
+        self.min_priority = Some(priority);
+        self
+    }
+
+    /// Sets whether to stop at the end of the log instead of waiting for new entries (`-d`).
+    pub fn dump(mut self, dump: bool) -> Self {
+        self.dump = dump;
+        self
+    }
+
+    /// Returns the logcat command reading the log with these options.
+    pub fn command(&self) -> String {
+        let mut command = "logcat -B".to_string();
+        if self.dump {
+            command.push_str(" -d");
+        }
+        for buffer in &self.buffers {
+            write!(command, " -b {}", buffer).unwrap();
+        }
+        for (tag, priority) in &self.filters {
+            write!(command, " {}:{}", tag, priority).unwrap();
+        }
+        if let Some(priority) = self.min_priority {
+            write!(command, " *:{}", priority).unwrap();
+        }
+        command
+    }
+}
+
+/// An iterator of the entries of the device log, created by [`Device::logcat`].
+///
+/// The iterator ends with the log when [dumping](LogcatOptions::dump), and otherwise
+/// waits for new entries until the connection is closed. Dropping the reader closes the
+/// connection, which ends `logcat`, as set by [`Self::drop_policy`].
+#[derive(Debug)]
+pub struct LogReader<R: Read + ServiceStream = ServerStream> {
+    reader: R,
+    done: bool,
+    drop_policy: StreamDropPolicy,
+    cancel: Option<CancelGuard>,
+}
+
+impl<R: Read + ServiceStream> LogReader<R> {
+    /// Wraps a reader of the output of `logcat -B`, closing it at once when dropped.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            done: false,
+            drop_policy: StreamDropPolicy::Abort,
+            cancel: None,
+        }
+    }
+
+    /// Sets how the connection is closed when dropped, [`StreamDropPolicy::Abort`] by
+    /// default. `logcat` has no close message, so [`StreamDropPolicy::Close`] is the same
+    /// as aborting.
+    pub fn drop_policy(mut self, policy: StreamDropPolicy) -> Self {
+        self.drop_policy = policy;
+        self
+    }
+}
+
+impl LogReader<ServerStream> {
+    /// Ends the iterator when `token` is cancelled, with a last [`AdbError::Cancelled`].
+    ///
+    /// Cancelling shuts the connection down, which wakes a reader waiting for new entries.
+    pub fn cancel_on(mut self, token: &CancellationToken) -> Result<Self, AdbError> {
+        self.cancel = Some(token.register(&self.reader)?);
+        Ok(self)
+    }
+}
+
+impl<R: Read + ServiceStream> Drop for LogReader<R> {
+    fn drop(&mut self) {
+        stream::close(&mut self.reader, self.drop_policy);
+    }
+}
+
+impl<R: Read + ServiceStream> Iterator for LogReader<R> {
+    type Item = Result<LogEntry, AdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        // Read the first byte separately, the end of the log is only valid between entries.
+        let mut first = [0];
+        let result = loop {
+            match self.reader.read(&mut first) {
+                // The connection shut down by a cancellation reads as the end of the log.
+                Ok(0)
+                    if self
+                        .cancel
+                        .as_ref()
+                        .is_some_and(|g| g.token().is_cancelled()) =>
+                {
+                    break Err(AdbError::Cancelled)
+                }
+                Ok(0) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(_) => {
+                    break protocol::read_decoded(&mut first.chain(&mut self.reader), decode_entry)
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => break Err(e.into()),
+            }
+        };
+        let result = cancel::map_result(&self.cancel, result);
+        self.done = result.is_err();
+        Some(result)
+    }
+}
+
+/// The size of a log buffer, as reported by `logcat -g`.
+///
+/// # Syntax
+///
+/// `<buffer>: ring buffer is <size> (<consumed> consumed), max entry is <size>, max payload is <size>`
+///
+/// Sizes are a number followed by a unit, either `B`/`KiB`/`MiB` or the legacy `b`/`Kb`/`Mb`.
+///
+/// ```
+/// # use adb::logcat::{LogBuffer, LogBufferSize};
+/// let line = "main: ring buffer is 256 KiB (252 KiB consumed), max entry is 5120 B, max payload is 4068 B";
+/// let size: LogBufferSize = line.parse().unwrap();
+/// assert_eq!(size.buffer, LogBuffer::Main);
+/// assert_eq!(size.size, 256 * 1024);
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LogBufferSize {
+    pub buffer: LogBuffer,
+    /// The capacity of the ring buffer in bytes.
+    pub size: u64,
+    /// The bytes currently used in the ring buffer.
+    pub consumed: u64,
+    /// The maximum size of an entry in bytes.
+    pub max_entry: u64,
+    /// The maximum size of an entry payload in bytes.
+    pub max_payload: u64,
+}
+
+impl FromStr for LogBufferSize {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || AdbError::Parse {
+            value: s.to_string(),
+            source_type: "&str",
+            target_type: "LogBufferSize",
+            source: None,
+        };
+        let (buffer, rest) = s.split_once(": ring buffer is ").ok_or_else(err)?;
+        let (size, rest) = rest.split_once(" (").ok_or_else(err)?;
+        let (consumed, rest) = rest
+            .split_once(" consumed), max entry is ")
+            .ok_or_else(err)?;
+        let (max_entry, max_payload) = rest.split_once(", max payload is ").ok_or_else(err)?;
+        Ok(Self {
+            buffer: buffer.trim().parse()?,
+            size: parse_size(size).ok_or_else(err)?,
+            consumed: parse_size(consumed).ok_or_else(err)?,
+            max_entry: parse_size(max_entry).ok_or_else(err)?,
+            max_payload: parse_size(max_payload.trim()).ok_or_else(err)?,
+        })
+    }
+}
+
+/// Parses a size printed by logcat, e.g. `256 KiB` or `256Kb`.
+fn parse_size(s: &str) -> Option<u64> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (value, unit) = s.split_at(split);
+    let multiplier = match unit.trim() {
+        "B" | "b" => 1,
+        "KiB" | "Kb" => 1 << 10,
+        "MiB" | "Mb" => 1 << 20,
+        "GiB" | "Gb" => 1 << 30,
+        _ => return None,
+    };
+    value.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// The usage statistics of a log buffer, as reported by `logcat -S`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LogBufferStats {
+    pub buffer: LogBuffer,
+    /// The bytes logged to the buffer since boot.
+    pub total_bytes: u64,
+    /// The lines logged to the buffer since boot.
+    pub total_lines: u64,
+    /// The bytes currently held by the buffer.
+    pub now_bytes: u64,
+    /// The lines currently held by the buffer.
+    pub now_lines: u64,
+}
+
+impl LogBufferStats {
+    /// Returns the number of lines dropped due to ring buffer overflow.
+    pub fn dropped_lines(&self) -> u64 {
+        self.total_lines.saturating_sub(self.now_lines)
+    }
+}
+
+/// Parses the `size/num` table printed by `logcat -S`.
+///
+/// # Syntax
+///
+/// ```text
+/// size/num main               system             crash              Total
+/// Total    29616929/217316    5608453/34086      0/0                35225382/251402
+/// Now      2074893/13908      2087862/11985      0/0                4162755/25893
+/// ```
+///
+/// The `Total` column is not a buffer and is skipped.
+pub fn parse_buffer_stats(s: &str) -> Result<Vec<LogBufferStats>, AdbError> {
+    let err = || AdbError::Parse {
+        value: s.to_string(),
+        source_type: "&str",
+        target_type: "Vec<LogBufferStats>",
+        source: None,
+    };
+    let mut lines = s.lines().skip_while(|line| !line.starts_with("size/num"));
+    let buffers = lines
+        .next()
+        .ok_or_else(err)?
+        .split_whitespace()
+        .skip(1)
+        .filter(|column| *column != "Total")
+        .map(str::parse)
+        .collect::<Result<Vec<LogBuffer>, _>>()?;
+    let mut row = |label: &str| -> Result<Vec<(u64, u64)>, AdbError> {
+        let line = lines.find(|line| line.starts_with(label)).ok_or_else(err)?;
+        line.split_whitespace()
+            .skip(1)
+            .take(buffers.len())
+            .map(|cell| {
+                let (bytes, count) = cell.split_once('/').ok_or_else(err)?;
+                Ok((
+                    bytes.parse().map_err(|_| err())?,
+                    count.parse().map_err(|_| err())?,
+                ))
+            })
+            .collect()
+    };
+    let total = row("Total")?;
+    let now = row("Now")?;
+    if total.len() != buffers.len() || now.len() != buffers.len() {
+        return Err(err());
+    }
+    Ok(buffers
+        .into_iter()
+        .zip(total.into_iter().zip(now))
+        .map(
+            |(buffer, ((total_bytes, total_lines), (now_bytes, now_lines)))| LogBufferStats {
+                buffer,
+                total_bytes,
+                total_lines,
+                now_bytes,
+                now_lines,
+            },
+        )
+        .collect())
+}
+
+impl Device {
+    /// Reads the device log (`logcat -B`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::logcat::{LogcatOptions, LogPriority};
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let options = LogcatOptions::new().min_priority(LogPriority::Warn);
+    /// for entry in device.logcat(&options).unwrap() {
+    ///     let entry = entry.unwrap();
+    ///     println!("{} {}: {}", entry.priority, entry.tag, entry.message);
+    /// }
+    /// ```
+    pub fn logcat(&self, options: &LogcatOptions) -> Result<LogReader, AdbError> {
+        // `exec:` doesn't allocate a pty, which would mangle the binary output.
+        Ok(LogReader::new(
+            self.open(&format!("exec:{}", options.command()))?,
+        ))
+    }
+
+    /// Clears the given log buffers (`logcat -c`).
+    pub fn logcat_clear(&self, buffers: &[LogBuffer]) -> Result<(), AdbError> {
+        let mut command = "logcat -c".to_string();
+        for buffer in buffers {
+            write!(command, " -b {}", buffer).unwrap();
+        }
+        self.shell_checked(&command).map(drop)
+    }
+
+    /// Returns the sizes of the default log buffers (`logcat -g`).
+    pub fn log_buffer_sizes(&self) -> Result<Vec<LogBufferSize>, AdbError> {
+        self.shell_checked("logcat -g")?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    /// Sets the size of the default log buffers in bytes (`logcat -G`).
+    pub fn set_log_buffer_size(&self, size: u64) -> Result<(), AdbError> {
+        self.shell_checked(&format!("logcat -G {}", size)).map(drop)
+    }
+
+    /// Returns the usage statistics of the log buffers (`logcat -S`).
+    pub fn log_buffer_stats(&self) -> Result<Vec<LogBufferStats>, AdbError> {
+        parse_buffer_stats(&self.shell_checked("logcat -S")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Encodes a v4 `logger_entry`.
+    fn entry(lid: u32, payload: &[u8]) -> Vec<u8> {
+        let mut entry = Vec::new();
+        entry.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        entry.extend_from_slice(&28u16.to_le_bytes());
+        for field in [1234, 5678, 1_700_000_000, 500_000_000, lid, 1000u32] {
+            entry.extend_from_slice(&field.to_le_bytes());
+        }
+        entry.extend_from_slice(payload);
+        entry
+    }
+
+    #[test]
+    fn test_decode_entry() {
+        let bytes = entry(0, b"\x04MyTag\0hello world\0");
+        let Decoded::Complete { value, length } = decode_entry(&bytes).unwrap() else {
+            panic!("incomplete entry");
+        };
+        assert_eq!(bytes.len(), length);
+        assert_eq!(
+            LogEntry {
+                timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500_000_000),
+                pid: 1234,
+                tid: 5678,
+                priority: LogPriority::Info,
+                tag: Tag::new("MyTag"),
+                message: "hello world".to_string(),
+                buffer: Some(LogBuffer::Main),
+            },
+            value
+        );
+        assert_eq!("I/MyTag( 1234): hello world", value.to_string());
+        assert_eq!(
+            Decoded::Incomplete {
+                needed: bytes.len()
+            },
+            decode_entry(&bytes[..30]).unwrap()
+        );
+        assert_eq!(
+            Decoded::Incomplete { needed: 4 },
+            decode_entry(&bytes[..2]).unwrap()
+        );
+        assert!(decode_entry(b"\x00\x00\x10\x00").is_err());
+    }
+
+    #[test]
+    fn test_decode_entry_v1() {
+        let mut bytes = entry(0, b"\x06Tag\0failed\0");
+        bytes[2..4].copy_from_slice(&[0, 0]);
+        bytes.drain(20..28);
+        match decode_entry(&bytes).unwrap() {
+            Decoded::Complete { value, .. } => {
+                assert_eq!(LogPriority::Error, value.priority);
+                assert_eq!("failed", value.message);
+                assert_eq!(None, value.buffer);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_event() {
+        // Tag 42 with the list [1, "ab", 2].
+        let mut payload = 42u32.to_le_bytes().to_vec();
+        payload.extend_from_slice(b"\x03\x03\x00\x01\x00\x00\x00\x02\x02\x00\x00\x00ab");
+        payload.extend_from_slice(b"\x01\x02\x00\x00\x00\x00\x00\x00\x00");
+        assert_eq!(
+            (
+                LogPriority::Info,
+                Tag::from_event_number(42),
+                "[1,ab,2]".to_string()
+            ),
+            decode_event(&payload)
+        );
+        let (_, _, message) = decode_event(b"\x2a\x00\x00\x00\x02\xff\x00\x00\x00ab");
+        assert!(message.ends_with("ab"), "{}", message);
+    }
+
+    #[test]
+    fn test_log_reader() {
+        let mut bytes = entry(3, b"\x03A\0first\0");
+        bytes.extend(entry(3, b"\x05B\0second\0"));
+        let entries: Vec<_> = LogReader::new(Cursor::new(bytes.clone()))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!("second", entries[1].message);
+        assert_eq!(Some(LogBuffer::System), entries[1].buffer);
+        let mut reader = LogReader::new(Cursor::new(&bytes[..bytes.len() - 1]));
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_logcat_options_command() {
+        assert_eq!("logcat -B", LogcatOptions::new().command());
+        let options = LogcatOptions::new()
+            .buffer(LogBuffer::Main)
+            .buffer(LogBuffer::Crash)
+            .min_priority(LogPriority::Warn);
+        assert_eq!("logcat -B -b main -b crash *:W", options.command());
+    }
+
+    #[test]
+    fn test_log_priority_parse() {
+        assert!(LogPriority::Verbose < LogPriority::Error);
+        for priority in [
+            LogPriority::Verbose,
+            LogPriority::Fatal,
+            LogPriority::Silent,
+        ] {
+            assert_eq!(priority, priority.to_string().parse().unwrap());
+        }
+        for s in ["", "X", "v", "VV"] {
+            assert!(s.parse::<LogPriority>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_log_buffer_parse() {
+        for buffer in [LogBuffer::Main, LogBuffer::Crash, LogBuffer::All] {
+            assert_eq!(buffer, buffer.to_string().parse().unwrap());
+        }
+        assert!("mian".parse::<LogBuffer>().is_err());
+    }
+
+    #[test]
+    fn test_log_buffer_size_parse() {
+        let size: LogBufferSize =
+            "system: ring buffer is 2 MiB (1 MiB consumed), max entry is 5120 B, max payload is 4068 B"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            LogBufferSize {
+                buffer: LogBuffer::System,
+                size: 2 << 20,
+                consumed: 1 << 20,
+                max_entry: 5120,
+                max_payload: 4068,
+            },
+            size
+        );
+        let legacy: LogBufferSize =
+            "main: ring buffer is 256Kb (255Kb consumed), max entry is 5120b, max payload is 4076b"
+                .parse()
+                .unwrap();
+        assert_eq!(256 << 10, legacy.size);
+        assert_eq!(4076, legacy.max_payload);
+        let err = [
+            "",
+            "main: ring buffer is 256Kb",
+            "main: ring buffer is 256 XiB (255Kb consumed), max entry is 5120b, max payload is 4076b",
+            "foo: ring buffer is 256Kb (255Kb consumed), max entry is 5120b, max payload is 4076b",
+        ];
+        for s in err {
+            assert!(s.parse::<LogBufferSize>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_buffer_stats() {
+        let output = "\
+size/num main               system             crash              kernel             Total
+Total    29616929/217316    5608453/34086      0/0                5451447/47043      40676829/298445
+Now      2074893/13908      2087862/11985      0/0                2091906/17993      6254661/43886
+Logspan  3:42:44.906        20:20:07.906                          10:39:59.68        20:20:07.974
+Overhead 252906             252972                                253276             759154
+";
+        let stats = parse_buffer_stats(output).unwrap();
+        assert_eq!(4, stats.len());
+        assert_eq!(
+            LogBufferStats {
+                buffer: LogBuffer::Main,
+                total_bytes: 29616929,
+                total_lines: 217316,
+                now_bytes: 2074893,
+                now_lines: 13908,
+            },
+            stats[0]
+        );
+        assert_eq!(LogBuffer::Kernel, stats[3].buffer);
+        assert_eq!(0, stats[2].dropped_lines());
+        assert!(parse_buffer_stats("").is_err());
+        assert!(parse_buffer_stats("size/num main\nTotal 1/1\n").is_err());
+    }
+}
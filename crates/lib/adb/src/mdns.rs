@@ -0,0 +1,207 @@
+//! This module provides discovery of wireless debugging services through the mDNS browser
+//! of the adb server (`host:mdns:services`).
+//!
+//! Devices with wireless debugging enabled advertise `_adb-tls-connect._tcp`, and
+//! `_adb-tls-pairing._tcp` while the pairing dialog is open. Devices listening with
+//! `adb tcpip` may advertise the legacy `_adb._tcp`.
+
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::error::AdbError;
+use crate::server::AdbServer;
+use crate::socket::Tcp;
+use crate::version::ServerCapability;
+
+/// The kind of an adb service advertised over mDNS.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum MdnsServiceKind {
+    /// `_adb._tcp`, a device listening with `adb tcpip`.
+    Connect,
+    /// `_adb-tls-connect._tcp`, the debugging service of a paired device.
+    TlsConnect,
+    /// `_adb-tls-pairing._tcp`, the pairing service, see [`AdbServer::pair`].
+    TlsPairing,
+}
+
+impl MdnsServiceKind {
+    /// Returns the mDNS service type, e.g. `_adb-tls-connect._tcp`.
+    pub const fn service_type(self) -> &'static str {
+        match self {
+            Self::Connect => "_adb._tcp",
+            Self::TlsConnect => "_adb-tls-connect._tcp",
+            Self::TlsPairing => "_adb-tls-pairing._tcp",
+        }
+    }
+}
+
+impl Display for MdnsServiceKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.service_type())
+    }
+}
+
+impl FromStr for MdnsServiceKind {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Some servers print fully qualified types, with a trailing dot.
+        match s.strip_suffix('.').unwrap_or(s) {
+            "_adb._tcp" => Ok(Self::Connect),
+            "_adb-tls-connect._tcp" => Ok(Self::TlsConnect),
+            "_adb-tls-pairing._tcp" => Ok(Self::TlsPairing),
+            _ => Err(AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "MdnsServiceKind",
+                source: None,
+            }),
+        }
+    }
+}
+
+/// An adb service discovered over mDNS.
+///
+/// # Syntax
+///
+/// `<name>\t<kind>\t<ip>:<port>`
+///
+/// ```
+/// # use adb::mdns::{MdnsService, MdnsServiceKind};
+/// let service: MdnsService = "adb-1A2B3C4D-XyZ123\t_adb-tls-connect._tcp\t192.168.1.20:41234"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(service.name, "adb-1A2B3C4D-XyZ123");
+/// assert_eq!(service.kind, MdnsServiceKind::TlsConnect);
+/// assert_eq!(service.socket.to_string(), "tcp:192.168.1.20:41234");
+/// ```
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct MdnsService {
+    /// The instance name, e.g. `adb-<serial>-<random>`.
+    pub name: String,
+    pub kind: MdnsServiceKind,
+    /// The address of the service, with both ip and port.
+    pub socket: Tcp,
+}
+
+impl MdnsService {
+    /// Returns the address of the service.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        Some(SocketAddr::new(self.socket.ip?, self.socket.port?))
+    }
+}
+
+impl Display for MdnsService {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{}\t", self.name, self.kind)?;
+        match self.addr() {
+            Some(addr) => write!(f, "{}", addr),
+            None => write!(f, "{}", self.socket),
+        }
+    }
+}
+
+impl FromStr for MdnsService {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split('\t');
+        match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(name), Some(kind), Some(addr), None) if !name.is_empty() => Ok(Self {
+                name: name.to_string(),
+                kind: kind.parse()?,
+                socket: addr
+                    .parse::<SocketAddr>()
+                    .map_err(|e| AdbError::Parse {
+                        value: addr.to_string(),
+                        source_type: "&str",
+                        target_type: "SocketAddr",
+                        source: Some(Box::new(e)),
+                    })?
+                    .into(),
+            }),
+            _ => Err(AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "MdnsService",
+                source: None,
+            }),
+        }
+    }
+}
+
+/// Parses the list of services replied to `host:mdns:services`.
+fn parse_services(s: &str) -> Result<Vec<MdnsService>, AdbError> {
+    s.lines()
+        .filter(|line| !line.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+impl AdbServer {
+    /// Checks that the mDNS browser of the server is running (`adb mdns check`),
+    /// and returns its description, e.g. `mdns daemon version [Openscreen discovery 0.0.0]`.
+    ///
+    /// Fails with [`AdbError::ServerUnsupported`] if the server predates mDNS.
+    pub fn mdns_check(&self) -> Result<String, AdbError> {
+        self.require(ServerCapability::Mdns)?;
+        Ok(self.query_string("host:mdns:check")?.trim_end().to_string())
+    }
+
+    /// Returns the adb services discovered by the server (`adb mdns services`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::mdns::MdnsServiceKind;
+    /// use adb::server::AdbServer;
+    ///
+    /// let server = AdbServer::default();
+    /// for service in server.mdns_services().unwrap() {
+    ///     if service.kind == MdnsServiceKind::TlsConnect {
+    ///         println!("{} at {}", service.name, service.socket);
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// Fails with [`AdbError::ServerUnsupported`] if the server predates mDNS.
+    pub fn mdns_services(&self) -> Result<Vec<MdnsService>, AdbError> {
+        self.require(ServerCapability::Mdns)?;
+        parse_services(&self.query_string("host:mdns:services")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mdns_service_parse() {
+        let s = "adb-1A2B3C4D-XyZ123\t_adb-tls-pairing._tcp\t[fe80::1]:37123";
+        let service: MdnsService = s.parse().unwrap();
+        assert_eq!(MdnsServiceKind::TlsPairing, service.kind);
+        assert_eq!(Some("[fe80::1]:37123".parse().unwrap()), service.addr());
+        assert_eq!(s, service.to_string());
+        let err = [
+            "",
+            "adb-1A2B3C4D\t_adb-tls-connect._tcp",
+            "adb-1A2B3C4D\t_http._tcp\t192.168.1.20:80",
+            "adb-1A2B3C4D\t_adb._tcp\t192.168.1.20",
+            "\t_adb._tcp\t192.168.1.20:5555",
+        ];
+        for s in err {
+            assert!(s.parse::<MdnsService>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_services() {
+        let services = parse_services(
+            "adb-1A2B\t_adb-tls-connect._tcp.\t192.168.1.20:41234\nadb-1A2B\t_adb._tcp\t192.168.1.20:5555\n",
+        )
+        .unwrap();
+        assert_eq!(2, services.len());
+        assert_eq!(MdnsServiceKind::TlsConnect, services[0].kind);
+        assert_eq!(MdnsServiceKind::Connect, services[1].kind);
+        assert!(parse_services("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+//! This module provides [`MediaControl`], the media sessions of a device on top of the
+//! `cmd media_session` shell command, or `media` before Android 8.
+//!
+//! Media keys are dispatched to the session receiving them, like the buttons of a headset.
+//! Sessions are parsed from `dumpsys media_session`, where each session starts with a line
+//! `<tag> <package>/<tag> (userId=<user>)`, followed by indented `key=value` fields.
+
+use std::fmt::{Display, Formatter};
+
+use crate::device::Device;
+use crate::error::AdbError;
+
+/// A media key, as named by `media_session dispatch`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum MediaKey {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    Rewind,
+    FastForward,
+    Record,
+    Mute,
+    HeadsetHook,
+}
+
+impl Display for MediaKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Play => "play",
+            Self::Pause => "pause",
+            Self::PlayPause => "play-pause",
+            Self::Stop => "stop",
+            Self::Next => "next",
+            Self::Previous => "previous",
+            Self::Rewind => "rewind",
+            Self::FastForward => "fast-forward",
+            Self::Record => "record",
+            Self::Mute => "mute",
+            Self::HeadsetHook => "headsethook",
+        })
+    }
+}
+
+/// The playback state of a session, `PlaybackState.STATE_*`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum PlaybackState {
+    None,
+    Stopped,
+    Paused,
+    Playing,
+    FastForwarding,
+    Rewinding,
+    Buffering,
+    Error,
+    Connecting,
+    SkippingToPrevious,
+    SkippingToNext,
+    SkippingToQueueItem,
+    /// A state unknown to this crate.
+    Other(i32),
+}
+
+impl From<i32> for PlaybackState {
+    fn from(state: i32) -> Self {
+        match state {
+            0 => Self::None,
+            1 => Self::Stopped,
+            2 => Self::Paused,
+            3 => Self::Playing,
+            4 => Self::FastForwarding,
+            5 => Self::Rewinding,
+            6 => Self::Buffering,
+            7 => Self::Error,
+            8 => Self::Connecting,
+            9 => Self::SkippingToPrevious,
+            10 => Self::SkippingToNext,
+            11 => Self::SkippingToQueueItem,
+            state => Self::Other(state),
+        }
+    }
+}
+
+/// A media session, as listed by `dumpsys media_session`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct MediaSession {
+    pub package: String,
+    /// The tag the application gave to the session.
+    pub tag: String,
+    pub user_id: u32,
+    /// `true` if the session receives media keys and transport controls.
+    pub active: bool,
+    pub state: Option<PlaybackState>,
+    /// The description of the metadata, e.g. `Title, Artist, Album`.
+    pub description: Option<String>,
+}
+
+/// Parses the header of a session, `<tag> <package>/<tag> (userId=<user>)`, the tag
+/// possibly containing spaces and slashes.
+fn parse_session_header(line: &str) -> Option<MediaSession> {
+    let (rest, user_id) = line.trim().strip_suffix(')')?.rsplit_once(" (userId=")?;
+    let user_id = user_id.parse().ok()?;
+    rest.match_indices(' ').find_map(|(i, _)| {
+        let tag = &rest[..i];
+        let package = rest[i + 1..].strip_suffix(tag)?.strip_suffix('/')?;
+        Some(MediaSession {
+            package: package.to_string(),
+            tag: tag.to_string(),
+            user_id,
+            active: false,
+            state: None,
+            description: None,
+        })
+    })
+}
+
+/// Parses the sessions of `dumpsys media_session`.
+fn parse_sessions(dump: &str) -> Vec<MediaSession> {
+    let mut sessions: Vec<MediaSession> = Vec::new();
+    for line in dump.lines() {
+        if let Some(session) = parse_session_header(line) {
+            sessions.push(session);
+            continue;
+        }
+        let Some(session) = sessions.last_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if let Some(active) = line.strip_prefix("active=") {
+            session.active = active == "true";
+        } else if let Some(state) = line.strip_prefix("state=PlaybackState {state=") {
+            session.state = state
+                .split(|c: char| !c.is_ascii_digit() && c != '-')
+                .next()
+                .and_then(|state| state.parse::<i32>().ok())
+                .map(PlaybackState::from);
+        } else if let Some((_, description)) = line
+            .strip_prefix("metadata:")
+            .and_then(|metadata| metadata.split_once("description="))
+        {
+            session.description = Some(description.to_string());
+        }
+    }
+    sessions
+}
+
+/// The media sessions of a device, created by [`Device::media`].
+#[derive(Clone, Debug)]
+pub struct MediaControl {
+    device: Device,
+}
+
+impl MediaControl {
+    /// Dispatches `key` to the session receiving media keys (`media_session dispatch`).
+    pub fn dispatch(&self, key: MediaKey) -> Result<(), AdbError> {
+        self.device
+            .media_session(&format!("dispatch {}", key))
+            .map(drop)
+    }
+
+    /// Resumes the playback.
+    pub fn play(&self) -> Result<(), AdbError> {
+        self.dispatch(MediaKey::Play)
+    }
+
+    /// Pauses the playback.
+    pub fn pause(&self) -> Result<(), AdbError> {
+        self.dispatch(MediaKey::Pause)
+    }
+
+    /// Skips to the next item.
+    pub fn next(&self) -> Result<(), AdbError> {
+        self.dispatch(MediaKey::Next)
+    }
+
+    /// Skips to the previous item.
+    pub fn previous(&self) -> Result<(), AdbError> {
+        self.dispatch(MediaKey::Previous)
+    }
+
+    /// Returns the media sessions, most recently active first.
+    pub fn sessions(&self) -> Result<Vec<MediaSession>, AdbError> {
+        Ok(parse_sessions(
+            &self.device.shell_checked("dumpsys media_session")?,
+        ))
+    }
+}
+
+impl Device {
+    /// Runs `media_session` with `args`, through `cmd` if available, or `media`.
+    pub(crate) fn media_session(&self, args: &str) -> Result<String, AdbError> {
+        let output = self.shell(&format!("cmd media_session {}", args))?;
+        if output.success() && output.stderr.is_empty() {
+            Ok(output.stdout_lossy())
+        } else {
+            self.shell_checked(&format!("media {}", args))
+        }
+    }
+
+    /// Returns a handle to the media sessions of the device.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::media::PlaybackState;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let media = device.media();
+    /// media.play().unwrap();
+    /// for session in media.sessions().unwrap() {
+    ///     if session.state == Some(PlaybackState::Playing) {
+    ///         println!("{} is playing {:?}", session.package, session.description);
+    ///     }
+    /// }
+    /// ```
+    pub fn media(&self) -> MediaControl {
+        MediaControl {
+            device: self.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_session_header() {
+        let session =
+            parse_session_header("    My Player com.example.player/My Player (userId=10)").unwrap();
+        assert_eq!("com.example.player", session.package);
+        assert_eq!("My Player", session.tag);
+        assert_eq!(10, session.user_id);
+        assert!(parse_session_header("  Sessions Stack - have 1 sessions:").is_none());
+    }
+
+    #[test]
+    fn test_parse_sessions() {
+        let dump = "\
+MEDIA SESSION SERVICE (dumpsys media_session)
+
+  Sessions Stack - have 2 sessions:
+    MusicSession com.example.music/MusicSession (userId=0)
+      ownerPid=1234, ownerUid=10123, userId=0
+      package=com.example.music
+      active=true
+      flags=3
+      state=PlaybackState {state=3, position=1234, buffered position=0, speed=1.0, updated=0}
+      metadata: size=5, description=Song, Artist, Album
+    podcast com.example.podcast/podcast (userId=0)
+      active=false
+      state=null
+";
+        let sessions = parse_sessions(dump);
+        assert_eq!(2, sessions.len());
+        assert_eq!(
+            MediaSession {
+                package: "com.example.music".to_string(),
+                tag: "MusicSession".to_string(),
+                user_id: 0,
+                active: true,
+                state: Some(PlaybackState::Playing),
+                description: Some("Song, Artist, Album".to_string()),
+            },
+            sessions[0]
+        );
+        assert!(!sessions[1].active);
+        assert_eq!(None, sessions[1].state);
+    }
+}
@@ -0,0 +1,328 @@
+//! This module provides network condition simulation, for network-resilience testing.
+//!
+//! On emulators, the profile is applied through the emulator console (`network delay` and
+//! `network speed`), shaping both directions but without packet loss.
+//!
+//! On other devices, adbd must run as root (`adb root`). The profile is applied to the
+//! interface of the default route with `tc` and `iptables`: a `netem` qdisc delays,
+//! drops and rate-limits outgoing packets, and incoming packets are dropped by a dedicated
+//! `iptables` chain. Incoming traffic isn't rate-limited, as that requires an `ifb` device
+//! most kernels lack.
+//!
+//! The network of a single app is blocked with the OEM deny chain of the connectivity
+//! service (`cmd connectivity set-package-networking-enabled`, Android 14 and later), or
+//! with `iptables` rules matching the uid of the app when adbd runs as root. The background
+//! restrictions of `cmd netpolicy` aren't used, they leave the foreground app online.
+
+use std::time::Duration;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// The `iptables` chain dropping incoming packets.
+pub const IPTABLES_CHAIN: &str = "adb_network_shaping";
+
+/// Network conditions to simulate.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct NetworkProfile {
+    /// The average latency added to packets.
+    pub delay: Duration,
+    /// The maximum deviation from the average latency.
+    pub jitter: Duration,
+    /// The upload rate in kbit/s, 0 for unlimited.
+    pub uplink_kbps: u32,
+    /// The download rate in kbit/s, 0 for unlimited.
+    pub downlink_kbps: u32,
+    /// The percentage of dropped packets, ignored on emulators.
+    pub loss_percent: u8,
+}
+
+impl NetworkProfile {
+    /// No added latency, rate limit nor loss.
+    pub const UNLIMITED: Self = Self {
+        delay: Duration::ZERO,
+        jitter: Duration::ZERO,
+        uplink_kbps: 0,
+        downlink_kbps: 0,
+        loss_percent: 0,
+    };
+
+    /// EDGE, with the rates and latencies of the emulator presets.
+    pub const EDGE: Self = Self {
+        delay: Duration::from_millis(240),
+        jitter: Duration::from_millis(160),
+        uplink_kbps: 118,
+        downlink_kbps: 236,
+        loss_percent: 0,
+    };
+
+    /// 3G (UMTS), with the rates and latencies of the emulator presets.
+    pub const UMTS: Self = Self {
+        delay: Duration::from_millis(117),
+        jitter: Duration::from_millis(82),
+        uplink_kbps: 1920,
+        downlink_kbps: 1920,
+        loss_percent: 0,
+    };
+
+    /// A flaky connection, with a high and variable latency and lost packets.
+    pub const FLAKY: Self = Self {
+        delay: Duration::from_millis(500),
+        jitter: Duration::from_millis(400),
+        uplink_kbps: 1000,
+        downlink_kbps: 1000,
+        loss_percent: 10,
+    };
+}
+
+/// Returns the emulator console commands applying `profile`.
+fn console_commands(profile: &NetworkProfile) -> [String; 2] {
+    let min = profile.delay.saturating_sub(profile.jitter).as_millis();
+    let max = (profile.delay + profile.jitter).as_millis();
+    [
+        format!("network delay {}:{}", min, max),
+        // A rate of 0 is unlimited, like `network speed full`.
+        format!(
+            "network speed {}:{}",
+            profile.uplink_kbps, profile.downlink_kbps
+        ),
+    ]
+}
+
+/// Returns the commands removing the shaping of `interface`, which may fail if there's none.
+fn reset_commands(interface: &str) -> Vec<String> {
+    vec![
+        format!("tc qdisc del dev {} root", interface),
+        format!("iptables -D INPUT -i {} -j {}", interface, IPTABLES_CHAIN),
+        format!("iptables -F {}", IPTABLES_CHAIN),
+        format!("iptables -X {}", IPTABLES_CHAIN),
+    ]
+}
+
+/// Returns the commands applying `profile` to `interface`, after [`reset_commands`].
+fn tc_commands(interface: &str, profile: &NetworkProfile) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut netem = Vec::new();
+    if !profile.delay.is_zero() {
+        netem.push(format!("delay {}ms", profile.delay.as_millis()));
+        if !profile.jitter.is_zero() {
+            netem.push(format!("{}ms", profile.jitter.as_millis()));
+        }
+    }
+    if profile.loss_percent > 0 {
+        netem.push(format!("loss {}%", profile.loss_percent));
+    }
+    if profile.uplink_kbps > 0 {
+        netem.push(format!("rate {}kbit", profile.uplink_kbps));
+    }
+    if !netem.is_empty() {
+        commands.push(format!(
+            "tc qdisc add dev {} root netem {}",
+            interface,
+            netem.join(" ")
+        ));
+    }
+    if profile.loss_percent > 0 {
+        commands.extend([
+            format!("iptables -N {}", IPTABLES_CHAIN),
+            format!(
+                "iptables -A {} -m statistic --mode random --probability {:.2} -j DROP",
+                IPTABLES_CHAIN,
+                f64::from(profile.loss_percent.min(100)) / 100.0
+            ),
+            format!("iptables -I INPUT -i {} -j {}", interface, IPTABLES_CHAIN),
+        ]);
+    }
+    commands
+}
+
+/// Parses the uid of `package` in the output of `pm list packages -U`, from lines like
+/// `package:com.example uid:10123`.
+fn parse_package_uid(list: &str, package: &str) -> Option<u32> {
+    list.lines().find_map(|line| {
+        let mut tokens = line.split_whitespace();
+        if tokens.next()?.strip_prefix("package:")? != package {
+            return None;
+        }
+        // Apps installed for several users list one uid per user, e.g. `uid:10123,1010123`.
+        let uids = tokens.find_map(|token| token.strip_prefix("uid:"))?;
+        uids.split(',').next()?.parse().ok()
+    })
+}
+
+/// Returns the `iptables` command checking (`-C`), inserting (`-I`) or deleting (`-D`) the
+/// rule rejecting the packets of `uid`.
+fn uid_rule(iptables: &str, action: &str, uid: u32) -> String {
+    format!(
+        "{} {} OUTPUT -m owner --uid-owner {} -j REJECT",
+        iptables, action, uid
+    )
+}
+
+/// Parses the interface of a route, e.g. `8.8.8.8 via 10.0.2.2 dev eth0 table 1003 ...`.
+fn parse_route_interface(route: &str) -> Option<&str> {
+    let mut tokens = route.split_whitespace();
+    tokens.find(|&token| token == "dev")?;
+    tokens.next()
+}
+
+impl Device {
+    /// Returns `true` if adbd runs as root.
+    pub(crate) fn is_root(&self) -> Result<bool, AdbError> {
+        Ok(self.shell_checked("id -u")?.trim() == "0")
+    }
+
+    /// Returns the interface of the default route, failing if adbd doesn't run as root.
+    fn shaped_interface(&self) -> Result<String, AdbError> {
+        if !self.is_root()? {
+            return Err(AdbError::Server {
+                message: "network shaping requires an emulator or adbd running as root".to_string(),
+            });
+        }
+        let route = self.shell_checked("ip route get 8.8.8.8")?;
+        parse_route_interface(&route)
+            .map(str::to_string)
+            .ok_or_else(|| AdbError::Server {
+                message: format!("no interface in route `{}`", route.trim()),
+            })
+    }
+
+    /// Simulates the network conditions of `profile`, replacing the previous ones.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::network::NetworkProfile;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// device.network_shaping(&NetworkProfile::EDGE).unwrap();
+    /// // ...
+    /// device.reset_network_shaping().unwrap();
+    /// ```
+    pub fn network_shaping(&self, profile: &NetworkProfile) -> Result<(), AdbError> {
+        if let Some(mut console) = self.emulator_console()? {
+            for command in console_commands(profile) {
+                console.command(&command)?;
+            }
+            return Ok(());
+        }
+        let interface = self.shaped_interface()?;
+        for command in reset_commands(&interface) {
+            let _ = self.shell(&command)?;
+        }
+        for command in tc_commands(&interface, profile) {
+            self.shell_checked(&command)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the simulated network conditions.
+    pub fn reset_network_shaping(&self) -> Result<(), AdbError> {
+        if let Some(mut console) = self.emulator_console()? {
+            console.command("network delay none")?;
+            console.command("network speed full")?;
+            return Ok(());
+        }
+        let interface = self.shaped_interface()?;
+        for command in reset_commands(&interface) {
+            let _ = self.shell(&command)?;
+        }
+        Ok(())
+    }
+
+    /// Blocks (`blocked` is `true`) or restores the network access of `package`, leaving the
+    /// other apps online, e.g. to test the offline mode of an app.
+    ///
+    /// Requires Android 14, or adbd running as root.
+    pub fn block_network(&self, package: &str, blocked: bool) -> Result<(), AdbError> {
+        let output = self.shell(&format!(
+            "cmd connectivity set-chain3-enabled true && \
+             cmd connectivity set-package-networking-enabled {} {}",
+            !blocked,
+            shell::quote(package)
+        ))?;
+        if output.success() && output.stderr.is_empty() {
+            return Ok(());
+        }
+        if !self.is_root()? {
+            return Err(AdbError::Server {
+                message: format!(
+                    "blocking the network of an app requires Android 14 or adbd running as \
+                     root: {}",
+                    output.stderr_lossy().trim()
+                ),
+            });
+        }
+        let list = self.shell_checked(&format!("pm list packages -U {}", shell::quote(package)))?;
+        let uid = parse_package_uid(&list, package).ok_or_else(|| AdbError::Server {
+            message: format!("package {} not found", package),
+        })?;
+        for iptables in ["iptables", "ip6tables"] {
+            let exists = self.shell(&uid_rule(iptables, "-C", uid))?.success();
+            if blocked && !exists {
+                self.shell_checked(&uid_rule(iptables, "-I", uid))?;
+            } else if !blocked && exists {
+                self.shell_checked(&uid_rule(iptables, "-D", uid))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_commands() {
+        assert_eq!(
+            ["network delay 80:400", "network speed 118:236"],
+            console_commands(&NetworkProfile::EDGE)
+        );
+        assert_eq!(
+            ["network delay 0:0", "network speed 0:0"],
+            console_commands(&NetworkProfile::UNLIMITED)
+        );
+    }
+
+    #[test]
+    fn test_tc_commands() {
+        assert_eq!(
+            [
+                "tc qdisc add dev wlan0 root netem delay 500ms 400ms loss 10% rate 1000kbit",
+                "iptables -N adb_network_shaping",
+                "iptables -A adb_network_shaping -m statistic --mode random --probability 0.10 -j DROP",
+                "iptables -I INPUT -i wlan0 -j adb_network_shaping",
+            ],
+            &tc_commands("wlan0", &NetworkProfile::FLAKY)[..]
+        );
+        assert!(tc_commands("wlan0", &NetworkProfile::UNLIMITED).is_empty());
+    }
+
+    #[test]
+    fn test_parse_package_uid() {
+        let list =
+            "package:com.example.app.test uid:10124\npackage:com.example.app uid:10123,1010123\n";
+        assert_eq!(Some(10123), parse_package_uid(list, "com.example.app"));
+        assert_eq!(Some(10124), parse_package_uid(list, "com.example.app.test"));
+        assert_eq!(None, parse_package_uid(list, "com.example"));
+        assert_eq!(
+            "iptables -I OUTPUT -m owner --uid-owner 10123 -j REJECT",
+            uid_rule("iptables", "-I", 10123)
+        );
+    }
+
+    #[test]
+    fn test_parse_route_interface() {
+        assert_eq!(
+            Some("eth0"),
+            parse_route_interface("8.8.8.8 via 10.0.2.2 dev eth0 table 1003 src 10.0.2.16 uid 0\n")
+        );
+        assert_eq!(
+            None,
+            parse_route_interface("RTNETLINK answers: Network is unreachable")
+        );
+    }
+}
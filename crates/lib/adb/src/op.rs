@@ -0,0 +1,280 @@
+//! This module provides the ids of client operations, reported to an [`OpObserver`] to
+//! correlate the logs of concurrent runs on many devices.
+//!
+//! Every request to the server or a device, e.g. `host:devices` or `shell:ls` on
+//! `emulator-5554`, is an operation with an [`OpId`], unique and increasing in the process.
+//! Requests made by several clones of a server, or on several threads, never share an id.
+//!
+//! An operation reports [`OpStatus::Started`] before connecting, then
+//! [`OpStatus::Accepted`] once the service replied `OKAY`, or [`OpStatus::Failed`] with the
+//! error. What's read afterwards belongs to the caller, e.g. the output of a shell command,
+//! and isn't reported. The error of a failed operation is an [`AdbError::Op`] with its id,
+//! to find its events in the logs.
+//!
+//! With the `tracing` feature, operations are also `request` spans, see [`crate::trace`].
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+use crate::error::AdbError;
+use crate::protocol::Status;
+
+/// The id of an operation, increasing with the start of operations in the process.
+///
+/// # Examples
+///
+/// ```
+/// use adb::op::OpId;
+///
+/// assert_eq!("op#42", OpId::from_raw(42).to_string());
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct OpId(u64);
+
+impl OpId {
+    /// Returns the id of a new operation, greater than all the ids returned before.
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Creates an id from its number, e.g. to match an id read back from a log.
+    pub const fn from_raw(id: u64) -> Self {
+        Self(id)
+    }
+
+    /// Returns the number of the id.
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for OpId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "op#{}", self.0)
+    }
+}
+
+/// Where an operation stands, in an [`OpEvent`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum OpStatus {
+    /// The operation is about to connect to the server.
+    Started,
+    /// The server or the device accepted the request.
+    Accepted,
+    /// The operation failed, with the message of the error.
+    Failed(String),
+}
+
+/// What happened to an operation, reported to an [`OpObserver`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct OpEvent {
+    pub id: OpId,
+    /// The serial of the device, `None` for host services and devices selected otherwise.
+    pub serial: Option<String>,
+    /// The requested service, e.g. `host:devices` or `shell:ls`.
+    pub service: String,
+    pub status: OpStatus,
+}
+
+impl Display for OpEvent {
+    /// Formats the event as a log line, e.g. `op#7 emulator-5554 shell:ls: accepted`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.id)?;
+        if let Some(serial) = &self.serial {
+            write!(f, "{} ", serial)?;
+        }
+        write!(f, "{}: ", self.service)?;
+        match &self.status {
+            OpStatus::Started => f.write_str("started"),
+            OpStatus::Accepted => f.write_str("accepted"),
+            OpStatus::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// Receives the [`OpEvent`]s of the operations of a server, e.g. to prefix logs with their
+/// ids.
+///
+/// Events are reported on the thread running the operation, which waits for the observer.
+pub trait OpObserver: Debug + Send + Sync {
+    fn on_event(&self, event: &OpEvent);
+}
+
+/// An operation in progress, reporting its events to an observer.
+pub(crate) struct Op<'a> {
+    observer: Option<&'a dyn OpObserver>,
+    id: OpId,
+    serial: Option<&'a str>,
+    service: &'a str,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    start: Instant,
+}
+
+impl<'a> Op<'a> {
+    /// Starts an operation requesting `service`, reporting [`OpStatus::Started`].
+    pub(crate) fn start(
+        observer: Option<&'a dyn OpObserver>,
+        serial: Option<&'a str>,
+        service: &'a str,
+    ) -> Self {
+        let id = OpId::next();
+        let op = Self {
+            observer,
+            id,
+            serial,
+            service,
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                target: "adb::request",
+                "request",
+                op = id.get(),
+                service,
+                serial
+            ),
+            #[cfg(feature = "tracing")]
+            start: Instant::now(),
+        };
+        op.notify(OpStatus::Started);
+        op
+    }
+
+    /// Runs `f`, the request, in the span of the operation.
+    pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        return self.span.in_scope(f);
+        #[cfg(not(feature = "tracing"))]
+        f()
+    }
+
+    /// Reports the outcome of the request, and returns it with the id of the operation in
+    /// the error.
+    ///
+    /// The error of a nested operation, e.g. querying the server version before the
+    /// request, is reported with the id of this one.
+    pub(crate) fn finish<T>(self, result: Result<T, AdbError>) -> Result<T, AdbError> {
+        result
+            .map(|value| {
+                self.notify(OpStatus::Accepted);
+                value
+            })
+            .map_err(|e| {
+                let source = e.into_inner();
+                self.notify(OpStatus::Failed(source.to_string()));
+                AdbError::Op {
+                    id: self.id,
+                    source: Box::new(source),
+                }
+            })
+    }
+
+    /// Reports the outcome of a request whose `FAIL` status is returned to the caller
+    /// instead of an error.
+    pub(crate) fn finish_status(self, status: &Status) {
+        match status {
+            Status::Okay => self.notify(OpStatus::Accepted),
+            Status::Fail(message) => {
+                let error = AdbError::from_fail(message.clone());
+                self.notify(OpStatus::Failed(error.to_string()))
+            }
+        }
+    }
+
+    fn notify(&self, status: OpStatus) {
+        #[cfg(feature = "tracing")]
+        self.trace(&status);
+        if let Some(observer) = self.observer {
+            observer.on_event(&OpEvent {
+                id: self.id,
+                serial: self.serial.map(str::to_string),
+                service: self.service.to_string(),
+                status,
+            });
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Op<'_> {
+    fn trace(&self, status: &OpStatus) {
+        let elapsed_us = self.start.elapsed().as_micros() as u64;
+        match status {
+            OpStatus::Started => {
+                tracing::debug!(target: "adb::request", parent: &self.span, "started")
+            }
+            OpStatus::Accepted => {
+                tracing::debug!(target: "adb::request", parent: &self.span, elapsed_us, "accepted")
+            }
+            OpStatus::Failed(error) => tracing::debug!(
+                target: "adb::request",
+                parent: &self.span,
+                elapsed_us,
+                error = error.as_str(),
+                "failed"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Events(Mutex<Vec<OpEvent>>);
+
+    impl OpObserver for Arc<Events> {
+        fn on_event(&self, event: &OpEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_op_id_next() {
+        let ids: Vec<_> = (0..4).map(|_| OpId::next()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        let threads: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| (0..100).map(|_| OpId::next()).collect::<Vec<_>>()))
+            .collect();
+        let mut ids: Vec<_> = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(400, ids.len());
+    }
+
+    #[test]
+    fn test_op_events() {
+        let events = Arc::new(Events::default());
+        let observer: &dyn OpObserver = &events;
+        let op = Op::start(Some(observer), Some("emulator-5554"), "shell:ls");
+        op.finish(Ok(())).unwrap();
+        let op = Op::start(Some(observer), None, "host:version");
+        let error = AdbError::Server {
+            message: "closed".to_string(),
+        };
+        assert!(op.finish::<()>(Err(error)).is_err());
+
+        let events = events.0.lock().unwrap();
+        let lines: Vec<_> = events.iter().map(ToString::to_string).collect();
+        let (first, second) = (events[0].id, events[2].id);
+        assert!(first < second);
+        assert_eq!(
+            [
+                format!("{} emulator-5554 shell:ls: started", first),
+                format!("{} emulator-5554 shell:ls: accepted", first),
+                format!("{} host:version: started", second),
+                format!("{} host:version: failed: request failed: closed", second),
+            ],
+            &lines[..]
+        );
+    }
+}
@@ -0,0 +1,660 @@
+//! This module provides typed details of installed packages, parsed from
+//! `dumpsys package <package>`.
+//!
+//! The dump starts with the intent resolver tables, listing the components of the package
+//! with an intent filter, followed by one `Package [<package>] (<hash>):` section per
+//! package. Sections are nested by indentation.
+//!
+//! Signing certificates are read from the APK Signing Block of the APK (schemes v2 and
+//! later), stored right before the ZIP central directory. Its entries are id-value pairs
+//! prefixed by their `u64` length, and the values of signature schemes are nested sequences
+//! of `u32` length-prefixed fields. APKs signed with the v1 scheme only aren't supported.
+
+use std::fmt::Write as _;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// The magic at the end of the APK Signing Block.
+pub const APK_SIG_BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
+
+/// The grant state of a permission.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct PermissionState {
+    /// The permission, e.g. `android.permission.CAMERA`.
+    pub name: String,
+    pub granted: bool,
+    /// The user the runtime permission is granted to, `None` for install permissions.
+    pub user: Option<u32>,
+    /// The permission flags, e.g. `USER_SET`.
+    pub flags: Vec<String>,
+}
+
+/// The details of a package, as dumped by `dumpsys package <package>`.
+///
+/// Times are in the local time of the device, formatted as `YYYY-MM-DD HH:MM:SS`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct PackageDetails {
+    pub package: String,
+    pub version_code: Option<u64>,
+    pub version_name: Option<String>,
+    pub min_sdk: Option<u32>,
+    pub target_sdk: Option<u32>,
+    /// The application flags, e.g. `DEBUGGABLE` or `ALLOW_BACKUP`.
+    pub flags: Vec<String>,
+    /// The private application flags, e.g. `PRIVILEGED`.
+    pub private_flags: Vec<String>,
+    /// The directory of the APKs.
+    pub code_path: Option<String>,
+    /// The ABI of the native libraries.
+    pub primary_cpu_abi: Option<String>,
+    /// The package which installed this package, e.g. `com.android.vending`.
+    pub installer: Option<String>,
+    pub first_install_time: Option<String>,
+    pub last_update_time: Option<String>,
+    /// The version of the APK signature scheme, e.g. 2 for v2.
+    pub apk_signing_version: Option<u32>,
+    /// The signatures, as dumped, e.g. `PackageSignatures{... signatures:[1a2b3c4d], ...}`.
+    pub signatures: Option<String>,
+    /// The permissions listed in the manifest.
+    pub requested_permissions: Vec<String>,
+    /// The install permissions, then the runtime permissions of every user.
+    pub permissions: Vec<PermissionState>,
+    /// The activities with an intent filter, e.g. `com.example/.MainActivity`.
+    pub activities: Vec<String>,
+    /// The services with an intent filter.
+    pub services: Vec<String>,
+    /// The broadcast receivers with an intent filter.
+    pub receivers: Vec<String>,
+}
+
+impl PackageDetails {
+    /// Returns `true` if `permission` is granted, as an install permission or to any user.
+    pub fn is_granted(&self, permission: &str) -> bool {
+        self.permissions
+            .iter()
+            .any(|state| state.name == permission && state.granted)
+    }
+
+    /// Returns `true` if the application has the flag `DEBUGGABLE`.
+    pub fn is_debuggable(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "DEBUGGABLE")
+    }
+}
+
+/// Returns the indentation of `line`.
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Parses the items of a flag list, e.g. `[ HAS_CODE ALLOW_BACKUP ]`.
+fn parse_flags(s: &str) -> Vec<String> {
+    s.trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(|c: char| c.is_whitespace() || c == '|')
+        .filter(|flag| !flag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parses a permission state, e.g. `android.permission.CAMERA: granted=false, flags=[ USER_SET ]`.
+fn parse_permission(s: &str, user: Option<u32>) -> Option<PermissionState> {
+    let (name, state) = s.split_once(": ")?;
+    let granted = state.strip_prefix("granted=")?;
+    let (granted, flags) = match granted.split_once(", flags=") {
+        Some((granted, flags)) => (granted, parse_flags(flags)),
+        None => (granted, Vec::new()),
+    };
+    Some(PermissionState {
+        name: name.to_string(),
+        granted: granted.parse().ok()?,
+        user,
+        flags,
+    })
+}
+
+/// Adds the components of `package` listed in the resolver tables of `dump`.
+fn parse_components(package: &str, dump: &str, details: &mut PackageDetails) {
+    let prefix = format!("{}/", package);
+    let mut table = None;
+    for line in dump.lines() {
+        if indent(line) == 0 {
+            table = match line.trim_end() {
+                "Activity Resolver Table:" => Some(&mut details.activities),
+                "Service Resolver Table:" => Some(&mut details.services),
+                "Receiver Resolver Table:" => Some(&mut details.receivers),
+                _ => None,
+            };
+            continue;
+        }
+        let Some(components) = &mut table else {
+            continue;
+        };
+        // `<hash> <package>/<class> filter <hash>`
+        let mut words = line.split_whitespace();
+        if let (Some(_), Some(component), Some("filter")) =
+            (words.next(), words.next(), words.next())
+        {
+            if component.starts_with(&prefix) && !components.iter().any(|c| c == component) {
+                components.push(component.to_string());
+            }
+        }
+    }
+}
+
+/// Parses the details of `package` from the output of `dumpsys package <package>`.
+pub fn parse_package_details(package: &str, dump: &str) -> Result<PackageDetails, AdbError> {
+    let header = format!("Package [{}] (", package);
+    let mut lines = dump
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with(&header));
+    let section_indent = lines.next().map(indent).ok_or_else(|| AdbError::Parse {
+        value: package.to_string(),
+        source_type: "&str",
+        target_type: "PackageDetails",
+        source: None,
+    })?;
+    let mut details = PackageDetails {
+        package: package.to_string(),
+        ..PackageDetails::default()
+    };
+    // The list the following, more indented lines belong to, with its indentation.
+    let mut list: Option<(&str, usize)> = None;
+    let mut user = None;
+    for line in lines.take_while(|line| line.trim().is_empty() || indent(line) > section_indent) {
+        let trimmed = line.trim();
+        if let Some((name, list_indent)) = list {
+            if indent(line) > list_indent {
+                match name {
+                    "requested permissions:" => {
+                        let permission = trimmed.split([':', ',']).next().unwrap_or(trimmed);
+                        details.requested_permissions.push(permission.to_string());
+                    }
+                    "install permissions:" => {
+                        details.permissions.extend(parse_permission(trimmed, None))
+                    }
+                    "runtime permissions:" => {
+                        details.permissions.extend(parse_permission(trimmed, user))
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            list = None;
+        }
+        if trimmed.ends_with(':') && !trimmed.contains('=') {
+            list = Some((trimmed, indent(line)));
+            continue;
+        }
+        if let Some(id) = trimmed
+            .strip_prefix("User ")
+            .and_then(|s| s.split_once(':'))
+        {
+            user = id.0.parse().ok();
+            continue;
+        }
+        // Values which may contain spaces take the rest of the line.
+        let (key, rest) = trimmed.split_once('=').unwrap_or((trimmed, ""));
+        match key {
+            "versionName" => details.version_name = Some(rest.to_string()),
+            "flags" => details.flags = parse_flags(rest),
+            "privateFlags" => details.private_flags = parse_flags(rest),
+            "signatures" => details.signatures = Some(rest.to_string()),
+            "firstInstallTime" => details.first_install_time = Some(rest.to_string()),
+            "lastUpdateTime" => details.last_update_time = Some(rest.to_string()),
+            _ => {
+                for (key, value) in trimmed.split(' ').filter_map(|word| word.split_once('=')) {
+                    match key {
+                        "versionCode" => details.version_code = value.parse().ok(),
+                        "minSdk" => details.min_sdk = value.parse().ok(),
+                        "targetSdk" => details.target_sdk = value.parse().ok(),
+                        "codePath" => details.code_path = Some(value.to_string()),
+                        "primaryCpuAbi" if value != "null" => {
+                            details.primary_cpu_abi = Some(value.to_string())
+                        }
+                        "installerPackageName" if value != "null" => {
+                            details.installer = Some(value.to_string())
+                        }
+                        "apkSigningVersion" => details.apk_signing_version = value.parse().ok(),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    parse_components(package, dump, &mut details);
+    Ok(details)
+}
+
+/// An APK signature scheme storing its signatures in the APK Signing Block.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum SignatureScheme {
+    V2,
+    V3,
+    /// v3.1, Android 13 and later, supporting key rotation targeted by SDK version.
+    V31,
+}
+
+impl SignatureScheme {
+    /// Returns the id of the scheme in the APK Signing Block.
+    pub const fn block_id(self) -> u32 {
+        match self {
+            Self::V2 => 0x7109_871a,
+            Self::V3 => 0xf053_68c0,
+            Self::V31 => 0x1b93_ad61,
+        }
+    }
+}
+
+/// The certificate of a signer of an APK.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct SigningCertificate {
+    pub scheme: SignatureScheme,
+    /// The DER encoded X.509 certificate.
+    pub der: Vec<u8>,
+}
+
+impl SigningCertificate {
+    /// Returns the SHA-256 digest of the certificate.
+    pub fn sha256(&self) -> [u8; 32] {
+        sha256(&self.der)
+    }
+
+    /// Returns the SHA-256 digest of the certificate as lowercase hex, as printed by
+    /// `apksigner verify --print-certs`.
+    pub fn sha256_hex(&self) -> String {
+        self.sha256()
+            .iter()
+            .fold(String::with_capacity(64), |mut hex, byte| {
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            })
+    }
+}
+
+/// Computes the SHA-256 digest of `data`.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let mut v = h;
+        for i in 0..64 {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+            v = [
+                t1.wrapping_add(t2),
+                v[0],
+                v[1],
+                v[2],
+                v[3].wrapping_add(t1),
+                v[4],
+                v[5],
+                v[6],
+            ];
+        }
+        for (h, v) in h.iter_mut().zip(v) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 32];
+    for (chunk, h) in digest.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+/// Reads a little-endian integer of `N` bytes at `offset`.
+fn read_le<const N: usize>(bytes: &[u8], offset: usize) -> Option<[u8; N]> {
+    bytes.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+/// Splits the `u32` length-prefixed fields of a sequence.
+fn length_prefixed(mut bytes: &[u8]) -> impl Iterator<Item = Option<&[u8]>> {
+    std::iter::from_fn(move || {
+        if bytes.is_empty() {
+            return None;
+        }
+        let field = read_le::<4>(bytes, 0).and_then(|length| {
+            let end = 4usize.checked_add(u32::from_le_bytes(length) as usize)?;
+            let field = bytes.get(4..end)?;
+            bytes = &bytes[end..];
+            Some(field)
+        });
+        if field.is_none() {
+            bytes = &[];
+        }
+        Some(field)
+    })
+}
+
+/// Returns the id-value pairs of the APK Signing Block of `apk`.
+fn signing_block_pairs(apk: &[u8]) -> Option<Vec<(u32, &[u8])>> {
+    // The End of Central Directory record is at least 22 bytes, followed by a comment
+    // of at most 65535 bytes.
+    let min = apk.len().checked_sub(22)?;
+    let eocd = (min.saturating_sub(0xffff)..=min)
+        .rev()
+        .find(|&offset| apk[offset..].starts_with(b"PK\x05\x06"))?;
+    let cd_offset = u32::from_le_bytes(read_le(apk, eocd + 16)?) as usize;
+    let footer = cd_offset.checked_sub(24)?;
+    if apk.get(footer + 8..cd_offset)? != APK_SIG_BLOCK_MAGIC {
+        return None;
+    }
+    let size = u64::from_le_bytes(read_le(apk, footer)?) as usize;
+    let start = cd_offset.checked_sub(size.checked_add(8)?)?;
+    let mut pairs = apk.get(start + 8..footer)?;
+    let mut result = Vec::new();
+    while !pairs.is_empty() {
+        let length = u64::from_le_bytes(read_le(pairs, 0)?) as usize;
+        let pair = pairs.get(8..8usize.checked_add(length)?)?;
+        result.push((u32::from_le_bytes(read_le(pair, 0)?), &pair[4..]));
+        pairs = &pairs[8 + length..];
+    }
+    Some(result)
+}
+
+/// Returns the certificate of every signer of `apk`, for the latest signature scheme found
+/// in its APK Signing Block.
+///
+/// Only the first certificate of a signer, its own, is returned, without the rest of its chain.
+pub fn apk_signing_certificates(apk: &[u8]) -> Result<Vec<SigningCertificate>, AdbError> {
+    let invalid = || AdbError::Protocol {
+        code: None,
+        message: "invalid or missing APK Signing Block".to_string(),
+    };
+    let pairs = signing_block_pairs(apk).ok_or_else(invalid)?;
+    let (scheme, value) = [
+        SignatureScheme::V31,
+        SignatureScheme::V3,
+        SignatureScheme::V2,
+    ]
+    .into_iter()
+    .find_map(|scheme| {
+        let (_, value) = pairs.iter().find(|(id, _)| *id == scheme.block_id())?;
+        Some((scheme, *value))
+    })
+    .ok_or_else(invalid)?;
+    let signers = length_prefixed(value)
+        .next()
+        .flatten()
+        .ok_or_else(invalid)?;
+    let mut certificates = Vec::new();
+    for signer in length_prefixed(signers) {
+        // signer: signed data, ...; signed data: digests, certificates, ...
+        let signed_data = signer
+            .and_then(|signer| length_prefixed(signer).next().flatten())
+            .ok_or_else(invalid)?;
+        let certificate = length_prefixed(signed_data)
+            .nth(1)
+            .flatten()
+            .and_then(|certificates| length_prefixed(certificates).next().flatten())
+            .ok_or_else(invalid)?;
+        certificates.push(SigningCertificate {
+            scheme,
+            der: certificate.to_vec(),
+        });
+    }
+    Ok(certificates)
+}
+
+impl Device {
+    /// Returns the details of an installed package (`dumpsys package <package>`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let details = device.package_details("com.example.app").unwrap();
+    /// assert!(details.is_granted("android.permission.INTERNET"));
+    /// println!("{:?} launches {:?}", details.version_name, details.activities);
+    /// ```
+    pub fn package_details(&self, package: &str) -> Result<PackageDetails, AdbError> {
+        let dump = self.shell_checked(&format!("dumpsys package {}", shell::quote(package)))?;
+        parse_package_details(package, &dump)
+    }
+
+    /// Returns the signing certificates of an installed package, read from its base APK.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// for certificate in device.package_signatures("com.example.app").unwrap() {
+    ///     println!("{:?}: {}", certificate.scheme, certificate.sha256_hex());
+    /// }
+    /// ```
+    #[cfg(feature = "sync")]
+    pub fn package_signatures(&self, package: &str) -> Result<Vec<SigningCertificate>, AdbError> {
+        let paths = self.shell_checked(&format!("pm path {}", shell::quote(package)))?;
+        let path = paths
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("package:"))
+            .find(|path| path.ends_with("/base.apk"))
+            .or_else(|| {
+                paths
+                    .lines()
+                    .find_map(|line| line.trim().strip_prefix("package:"))
+            })
+            .ok_or_else(|| AdbError::Server {
+                message: format!("package {} not found", package),
+            })?;
+        let mut apk = Vec::new();
+        self.sync()?.recv(path, &mut apk)?;
+        apk_signing_certificates(&apk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "\
+Activity Resolver Table:
+  Non-Data Actions:
+      android.intent.action.MAIN:
+        5b3c2a1 com.example.app/.MainActivity filter 8d7e6f5
+          Action: \"android.intent.action.MAIN\"
+          Category: \"android.intent.category.LAUNCHER\"
+      android.intent.action.VIEW:
+        5b3c2a1 com.example.app/.MainActivity filter 1a2b3c4
+
+Receiver Resolver Table:
+  Non-Data Actions:
+      android.intent.action.BOOT_COMPLETED:
+        9f8e7d6 com.example.app/.BootReceiver filter 4c5d6e7
+
+Key Set Manager:
+  [com.example.app]
+      Signing KeySets: 51
+
+Packages:
+  Package [com.example.app] (c0ffee1):
+    userId=10123
+    pkg=Package{7a6b5c4 com.example.app}
+    codePath=/data/app/~~AbC==/com.example.app-XyZ==
+    primaryCpuAbi=arm64-v8a
+    versionCode=42 minSdk=24 targetSdk=34
+    versionName=1.2.3 (beta)
+    apkSigningVersion=2
+    flags=[ HAS_CODE ALLOW_CLEAR_USER_DATA DEBUGGABLE ALLOW_BACKUP ]
+    privateFlags=[ PRIVATE_FLAG_ACTIVITIES_RESIZE_MODE_RESIZEABLE ]
+    timeStamp=2024-05-01 10:00:00
+    firstInstallTime=2024-05-01 10:00:01
+    lastUpdateTime=2024-05-02 11:30:00
+    installerPackageName=com.android.vending
+    signatures=PackageSignatures{3e4f5a6 version:2, signatures:[1a2b3c4d], past signatures:[]}
+    requested permissions:
+      android.permission.INTERNET
+      android.permission.CAMERA
+      android.permission.POST_NOTIFICATIONS: restricted=true
+    install permissions:
+      android.permission.INTERNET: granted=true
+    User 0: ceDataInode=12345 installed=true hidden=false suspended=false
+      gids=[3003]
+      runtime permissions:
+        android.permission.CAMERA: granted=false, flags=[ USER_SENSITIVE_WHEN_GRANTED|USER_SET ]
+        android.permission.POST_NOTIFICATIONS: granted=true, flags=[ USER_SET ]
+
+Queries:
+  system apps queryable: false
+";
+
+    #[test]
+    fn test_parse_package_details() {
+        let details = parse_package_details("com.example.app", DUMP).unwrap();
+        assert_eq!(Some(42), details.version_code);
+        assert_eq!(Some("1.2.3 (beta)"), details.version_name.as_deref());
+        assert_eq!((Some(24), Some(34)), (details.min_sdk, details.target_sdk));
+        assert!(details.is_debuggable());
+        assert_eq!(1, details.private_flags.len());
+        assert_eq!(Some("arm64-v8a"), details.primary_cpu_abi.as_deref());
+        assert_eq!(Some("com.android.vending"), details.installer.as_deref());
+        assert_eq!(
+            Some("2024-05-01 10:00:01"),
+            details.first_install_time.as_deref()
+        );
+        assert_eq!(
+            Some("2024-05-02 11:30:00"),
+            details.last_update_time.as_deref()
+        );
+        assert_eq!(Some(2), details.apk_signing_version);
+        assert!(details.signatures.unwrap().contains("[1a2b3c4d]"));
+        assert_eq!(
+            [
+                "android.permission.INTERNET",
+                "android.permission.CAMERA",
+                "android.permission.POST_NOTIFICATIONS"
+            ],
+            &details.requested_permissions[..]
+        );
+        assert_eq!(3, details.permissions.len());
+        assert_eq!(
+            PermissionState {
+                name: "android.permission.CAMERA".to_string(),
+                granted: false,
+                user: Some(0),
+                flags: vec![
+                    "USER_SENSITIVE_WHEN_GRANTED".to_string(),
+                    "USER_SET".to_string()
+                ],
+            },
+            details.permissions[1]
+        );
+        assert_eq!(None, details.permissions[0].user);
+        assert!(details.permissions[2].granted);
+        assert_eq!(["com.example.app/.MainActivity"], &details.activities[..]);
+        assert_eq!(["com.example.app/.BootReceiver"], &details.receivers[..]);
+        assert!(details.services.is_empty());
+    }
+
+    #[test]
+    fn test_sha256() {
+        let hex = |data: &[u8]| {
+            SigningCertificate {
+                scheme: SignatureScheme::V2,
+                der: data.to_vec(),
+            }
+            .sha256_hex()
+        };
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            hex(b"")
+        );
+        assert_eq!(
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")
+        );
+    }
+
+    /// Builds an APK with an APK Signing Block holding one v2 signer, without entries.
+    fn signed_apk(certificate: &[u8]) -> Vec<u8> {
+        let prefixed = |field: &[u8]| {
+            let mut prefixed = (field.len() as u32).to_le_bytes().to_vec();
+            prefixed.extend_from_slice(field);
+            prefixed
+        };
+        let certificates = prefixed(&prefixed(certificate));
+        let signed_data = [prefixed(&[]), certificates].concat();
+        let signer = prefixed(&[prefixed(&signed_data), prefixed(&[]), prefixed(&[])].concat());
+        let mut value = SignatureScheme::V2.block_id().to_le_bytes().to_vec();
+        value.extend(prefixed(&signer));
+        let mut pairs = (value.len() as u64).to_le_bytes().to_vec();
+        pairs.extend(value);
+        let size = (pairs.len() + 24) as u64;
+        let mut apk = size.to_le_bytes().to_vec();
+        apk.extend(pairs);
+        apk.extend(size.to_le_bytes());
+        apk.extend(APK_SIG_BLOCK_MAGIC);
+        let cd_offset = apk.len() as u32;
+        apk.extend(b"PK\x05\x06");
+        apk.extend([0; 12]);
+        apk.extend(cd_offset.to_le_bytes());
+        apk.extend([0; 2]);
+        apk
+    }
+
+    #[test]
+    fn test_apk_signing_certificates() {
+        let certificates = apk_signing_certificates(&signed_apk(b"certificate")).unwrap();
+        assert_eq!(1, certificates.len());
+        assert_eq!(SignatureScheme::V2, certificates[0].scheme);
+        assert_eq!(b"certificate", &certificates[0].der[..]);
+        let mut unsigned = signed_apk(b"certificate");
+        let magic = unsigned.len() - 22 - 16;
+        unsigned[magic] ^= 1;
+        assert!(apk_signing_certificates(&unsigned).is_err());
+        assert!(apk_signing_certificates(b"PK\x05\x06").is_err());
+        assert!(apk_signing_certificates(b"").is_err());
+    }
+
+    #[test]
+    fn test_parse_package_details_missing() {
+        assert!(parse_package_details("com.example.other", DUMP).is_err());
+        assert!(parse_package_details("com.example.app", "Unable to find package: x\n").is_err());
+    }
+}
@@ -0,0 +1,641 @@
+//! This module provides wireless debugging setup (Android 11 and later).
+//!
+//! Wireless debugging takes two steps: pairing once with the pairing service of the device,
+//! using the code shown in the developer options, then connecting to its debugging service.
+//! The pairing itself (SPAKE2 and TLS) is run by the adb server, driven with `host:pair:`.
+//! Both services reply with a free-form message, length-prefixed.
+//!
+//! Connecting goes through several states, reported by a [`ConnectFlow`]: the server first
+//! connects to the device, which is `unauthorized` until the user accepts the key of the
+//! host on the device, then `authorizing` while checking it, and finally online.
+//! [`AdbServer::connect_device`] takes a single step instead, returning the
+//! [`ConnectOutcome`] of `adb connect`.
+
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::device::{Device, DeviceState};
+use crate::error::AdbError;
+use crate::server::AdbServer;
+use crate::socket::Tcp;
+
+/// A device paired through [`AdbServer::pair`].
+///
+/// # Syntax
+///
+/// `Successfully paired to <addr> [guid=<guid>]`
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Paired {
+    /// The address of the pairing service, as resolved by the server.
+    pub addr: String,
+    /// The id of the device, e.g. `adb-1A2B3C4D-XyZ123`.
+    pub guid: String,
+}
+
+impl FromStr for Paired {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix("Successfully paired to ")
+            .and_then(|s| s.strip_suffix(']'))
+            .and_then(|s| s.split_once(" [guid="))
+            .map(|(addr, guid)| Self {
+                addr: addr.to_string(),
+                guid: guid.to_string(),
+            })
+            .ok_or_else(|| AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Paired",
+                source: None,
+            })
+    }
+}
+
+/// Checks the reply to `host:pair:`.
+fn parse_pair_reply(reply: &str) -> Result<Paired, AdbError> {
+    let reply = reply.trim_end();
+    if reply.starts_with("Successfully paired to ") {
+        reply.parse()
+    } else {
+        Err(AdbError::Server {
+            message: reply.to_string(),
+        })
+    }
+}
+
+/// The result of connecting to a network device, returned by
+/// [`AdbServer::connect_device`].
+///
+/// # Syntax
+///
+/// The server replies with a free-form message, e.g. `connected to 10.0.0.2:5555`,
+/// `already connected to 10.0.0.2:5555`, `failed to authenticate to 10.0.0.2:5555` or
+/// `failed to connect to '10.0.0.2:5555': Connection refused`.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ConnectOutcome {
+    /// The server connected to the device, whose serial number is `serial`.
+    Connected { serial: String },
+    /// The server was already connected to the device.
+    AlreadyConnected { serial: String },
+    /// The device rejected the key of the host, e.g. while the user hasn't accepted it yet.
+    Unauthorized { serial: String },
+    /// The server couldn't connect, for the reason in `message`.
+    Failed { message: String },
+}
+
+impl ConnectOutcome {
+    /// Parses the reply to `host:connect:`.
+    pub fn parse(reply: &str) -> Self {
+        let reply = reply.trim_end();
+        let serial = |prefix| reply.strip_prefix(prefix).map(str::to_string);
+        if let Some(serial) = serial("connected to ") {
+            Self::Connected { serial }
+        } else if let Some(serial) = serial("already connected to ") {
+            Self::AlreadyConnected { serial }
+        } else if let Some(serial) = serial("failed to authenticate to ") {
+            Self::Unauthorized { serial }
+        } else {
+            Self::Failed {
+                message: reply.to_string(),
+            }
+        }
+    }
+
+    /// Returns `true` if the server is connected to the device.
+    pub fn is_connected(&self) -> bool {
+        matches!(self, Self::Connected { .. } | Self::AlreadyConnected { .. })
+    }
+
+    /// Returns the serial number of the device, unless connecting failed.
+    pub fn serial(&self) -> Option<&str> {
+        match self {
+            Self::Connected { serial }
+            | Self::AlreadyConnected { serial }
+            | Self::Unauthorized { serial } => Some(serial),
+            Self::Failed { .. } => None,
+        }
+    }
+}
+
+/// Checks the reply to `host:connect:`, e.g. `already connected to 10.0.0.2:5555`.
+fn check_connect_reply(reply: &str) -> Result<(), AdbError> {
+    if ConnectOutcome::parse(reply).is_connected() {
+        Ok(())
+    } else {
+        Err(AdbError::Server {
+            message: reply.trim_end().to_string(),
+        })
+    }
+}
+
+/// Returns the address of `tcp` in `host:connect:` and `host:disconnect:`, its display
+/// without the `tcp:` family, e.g. `10.0.0.2:5555`, or `10.0.0.2` for the default port.
+fn connect_address(tcp: &Tcp) -> String {
+    let spec = tcp.to_string();
+    spec.strip_prefix("tcp:").unwrap_or(&spec).to_string()
+}
+
+/// A state of a connection to a network device, reported by a [`ConnectFlow`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ConnectState {
+    /// The server is connecting to the device, or the device is offline.
+    Connecting,
+    /// The device waits for the user to accept the key of the host.
+    Unauthorized,
+    /// The device checks the key of the host.
+    Authorizing,
+    /// The device is online.
+    Device,
+}
+
+impl ConnectState {
+    /// Returns the state of a connection to a device in `state`.
+    pub fn from_device_state(state: &DeviceState) -> Self {
+        match state {
+            DeviceState::Offline | DeviceState::Connecting => Self::Connecting,
+            DeviceState::Unauthorized => Self::Unauthorized,
+            DeviceState::Authorizing => Self::Authorizing,
+            _ => Self::Device,
+        }
+    }
+}
+
+impl Display for ConnectState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Connecting => "connecting",
+            Self::Unauthorized => "unauthorized",
+            Self::Authorizing => "authorizing",
+            Self::Device => "device",
+        })
+    }
+}
+
+/// How long a [`ConnectFlow`] stays in each state before failing.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::pair::ConnectTimeouts;
+///
+/// // Give the user two minutes to accept the prompt, showing it again once.
+/// let timeouts = ConnectTimeouts::new()
+///     .unauthorized(Duration::from_secs(120))
+///     .auth_retries(1);
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ConnectTimeouts {
+    connecting: Duration,
+    unauthorized: Duration,
+    authorizing: Duration,
+    poll_interval: Duration,
+    auth_retries: u32,
+}
+
+impl ConnectTimeouts {
+    /// Creates timeouts of 30 s to connect, 60 s for the user to accept the prompt and 10 s
+    /// to check the key, polling every 250 ms, without showing the prompt again.
+    pub fn new() -> Self {
+        Self {
+            connecting: Duration::from_secs(30),
+            unauthorized: Duration::from_secs(60),
+            authorizing: Duration::from_secs(10),
+            poll_interval: Duration::from_millis(250),
+            auth_retries: 0,
+        }
+    }
+
+    /// Sets how long to try connecting, or wait for an offline device.
+    pub fn connecting(mut self, timeout: Duration) -> Self {
+        self.connecting = timeout;
+        self
+    }
+
+    /// Sets how long to wait for the user to accept the prompt on the device.
+    pub fn unauthorized(mut self, timeout: Duration) -> Self {
+        self.unauthorized = timeout;
+        self
+    }
+
+    /// Sets how long to wait for the device to check the key.
+    pub fn authorizing(mut self, timeout: Duration) -> Self {
+        self.authorizing = timeout;
+        self
+    }
+
+    /// Sets the delay between two polls of the state of the device.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sets how many times to reconnect, showing the prompt again, when the user didn't
+    /// answer it in time.
+    pub fn auth_retries(mut self, retries: u32) -> Self {
+        self.auth_retries = retries;
+        self
+    }
+
+    /// Returns how long the flow may stay in `state`.
+    pub fn get_timeout(&self, state: ConnectState) -> Duration {
+        match state {
+            ConnectState::Connecting => self.connecting,
+            ConnectState::Unauthorized => self.unauthorized,
+            ConnectState::Authorizing => self.authorizing,
+            ConnectState::Device => Duration::MAX,
+        }
+    }
+}
+
+impl Default for ConnectTimeouts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a [`ConnectFlow`] does after observing a state.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+enum Step {
+    /// The state changed, and is reported.
+    Enter(ConnectState),
+    /// The state didn't change, poll again.
+    Wait,
+    /// The user didn't answer the prompt in time, reconnect to show it again.
+    Reconnect,
+    /// The state didn't change in time.
+    TimedOut(ConnectState),
+}
+
+/// The transitions of a [`ConnectFlow`], apart from the server.
+#[derive(Clone, Debug)]
+struct ConnectMachine {
+    timeouts: ConnectTimeouts,
+    state: Option<ConnectState>,
+    since: Instant,
+    retries: u32,
+}
+
+impl ConnectMachine {
+    fn new(timeouts: ConnectTimeouts, now: Instant) -> Self {
+        Self {
+            timeouts,
+            state: None,
+            since: now,
+            retries: timeouts.auth_retries,
+        }
+    }
+
+    fn observe(&mut self, state: ConnectState, now: Instant) -> Step {
+        if self.state != Some(state) {
+            self.state = Some(state);
+            self.since = now;
+            return Step::Enter(state);
+        }
+        if now - self.since < self.timeouts.get_timeout(state) {
+            Step::Wait
+        } else if state == ConnectState::Unauthorized && self.retries > 0 {
+            self.retries -= 1;
+            Step::Reconnect
+        } else {
+            Step::TimedOut(state)
+        }
+    }
+}
+
+/// Checks the reply to `host:connect:`, returning `None` if it's worth retrying, e.g. when
+/// the device refused the connection, and the state if the server connected.
+fn connect_reply_state(reply: &str) -> Option<ConnectState> {
+    match ConnectOutcome::parse(reply) {
+        ConnectOutcome::Connected { .. } | ConnectOutcome::AlreadyConnected { .. } => {
+            Some(ConnectState::Connecting)
+        }
+        ConnectOutcome::Unauthorized { .. } => Some(ConnectState::Unauthorized),
+        ConnectOutcome::Failed { .. } => None,
+    }
+}
+
+/// The steps of `adb connect`, created by [`AdbServer::connect_flow`].
+///
+/// Iterating yields the state of the connection every time it changes, until the device is
+/// online, or the flow fails, e.g. with [`AdbError::Timeout`] when the user doesn't accept
+/// the prompt on the device in time.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::pair::{ConnectState, ConnectTimeouts};
+/// use adb::server::AdbServer;
+///
+/// let server = AdbServer::default();
+/// let addr = "192.168.1.20:5555".parse().unwrap();
+/// for state in server.connect_flow(addr, ConnectTimeouts::new()) {
+///     match state.unwrap() {
+///         ConnectState::Unauthorized => println!("accept the prompt on the device"),
+///         state => println!("{}", state),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ConnectFlow {
+    server: AdbServer,
+    addr: SocketAddr,
+    machine: ConnectMachine,
+    connected: bool,
+    last_reply: Option<String>,
+    done: bool,
+}
+
+impl ConnectFlow {
+    /// Returns the address of the device.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the last state reported, `None` before the first one.
+    pub fn state(&self) -> Option<ConnectState> {
+        self.machine.state
+    }
+
+    /// Runs the flow until the device is online, and returns it.
+    pub fn wait(mut self) -> Result<Device, AdbError> {
+        for state in self.by_ref() {
+            state?;
+        }
+        Ok(self.server.device(&self.addr.to_string()))
+    }
+
+    /// Asks the server to connect, unless it's connected, then polls the state of the device.
+    fn poll_state(&mut self) -> Result<ConnectState, AdbError> {
+        if !self.connected {
+            let reply = self
+                .server
+                .request_string(&format!("host:connect:{}", self.addr))?;
+            match connect_reply_state(&reply) {
+                Some(ConnectState::Unauthorized) => return Ok(ConnectState::Unauthorized),
+                Some(_) => self.connected = true,
+                None => {
+                    self.last_reply = Some(reply.trim_end().to_string());
+                    return Ok(ConnectState::Connecting);
+                }
+            }
+        }
+        match self.server.device(&self.addr.to_string()).get_state() {
+            Ok(state) => Ok(ConnectState::from_device_state(&state)),
+            Err(e) => match e.inner() {
+                AdbError::DeviceOffline => Ok(ConnectState::Connecting),
+                AdbError::Unauthorized => Ok(ConnectState::Unauthorized),
+                // The server dropped the device, e.g. after a failed handshake.
+                AdbError::DeviceNotFound { .. } => {
+                    self.connected = false;
+                    Ok(ConnectState::Connecting)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+}
+
+impl Iterator for ConnectFlow {
+    type Item = Result<ConnectState, AdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let state = match self.poll_state() {
+                Ok(state) => state,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            let clock = self.server.clock.clone();
+            match self.machine.observe(state, clock.now()) {
+                Step::Enter(state) => {
+                    self.done = state == ConnectState::Device;
+                    return Some(Ok(state));
+                }
+                Step::Wait => clock.sleep(self.machine.timeouts.poll_interval),
+                Step::Reconnect => {
+                    let _ = self
+                        .server
+                        .request_string(&format!("host:disconnect:{}", self.addr));
+                    self.connected = false;
+                }
+                Step::TimedOut(state) => {
+                    self.done = true;
+                    let mut condition = format!("{} to leave the {} state", self.addr, state);
+                    if let Some(reply) = &self.last_reply {
+                        condition.push_str(&format!(", last reply `{}`", reply));
+                    }
+                    return Some(Err(AdbError::Timeout {
+                        condition,
+                        timeout: self.machine.timeouts.get_timeout(state),
+                    }));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl AdbServer {
+    /// Pairs with the wireless debugging pairing service at `addr` (`adb pair <addr> <code>`).
+    ///
+    /// `addr` and `code` are shown on the device in *Developer options > Wireless debugging >
+    /// Pair device with pairing code*. The pairing port differs from the debugging port.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let server = AdbServer::default();
+    /// let paired = server.pair("192.168.1.20:37123".parse().unwrap(), "482913").unwrap();
+    /// println!("paired with {}", paired.guid);
+    /// let device = server.connect_wireless("192.168.1.20:41234".parse().unwrap()).unwrap();
+    /// ```
+    pub fn pair(&self, addr: SocketAddr, code: &str) -> Result<Paired, AdbError> {
+        parse_pair_reply(&self.request_string(&format!("host:pair:{}:{}", code, addr))?)
+    }
+
+    /// Connects to the wireless debugging service at `addr` (`adb connect <addr>`),
+    /// and returns the device, whose serial number is `addr`.
+    ///
+    /// The device must have been paired with [`Self::pair`] first. Connecting to a device
+    /// which is already connected succeeds.
+    pub fn connect_wireless(&self, addr: SocketAddr) -> Result<Device, AdbError> {
+        check_connect_reply(&self.request_string(&format!("host:connect:{}", addr))?)?;
+        Ok(self.device(&addr.to_string()))
+    }
+
+    /// Connects to the device at `tcp` (`adb connect <addr>`), on port 5555 if `tcp` has no
+    /// port.
+    ///
+    /// Failing to connect isn't an error, but a [`ConnectOutcome::Failed`] with the reason
+    /// given by the server.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::pair::ConnectOutcome;
+    /// use adb::server::AdbServer;
+    /// use adb::socket::Tcp;
+    ///
+    /// let server = AdbServer::default();
+    /// let tcp: Tcp = "tcp:192.168.1.20:5555".parse().unwrap();
+    /// match server.connect_device(tcp).unwrap() {
+    ///     ConnectOutcome::Failed { message } => eprintln!("{}", message),
+    ///     outcome => println!("{:?}", outcome.serial()),
+    /// }
+    /// server.disconnect_device(Some(tcp)).unwrap();
+    /// ```
+    pub fn connect_device(&self, tcp: Tcp) -> Result<ConnectOutcome, AdbError> {
+        let reply = self.request_string(&format!("host:connect:{}", connect_address(&tcp)))?;
+        Ok(ConnectOutcome::parse(&reply))
+    }
+
+    /// Disconnects from the device at `tcp`, or from every network device if `None`
+    /// (`adb disconnect [<addr>]`).
+    ///
+    /// Fails with [`AdbError::DeviceNotFound`] if the server isn't connected to `tcp`.
+    pub fn disconnect_device(&self, tcp: Option<Tcp>) -> Result<(), AdbError> {
+        let addr = tcp.as_ref().map(connect_address).unwrap_or_default();
+        self.request_string(&format!("host:disconnect:{}", addr))
+            .map(drop)
+    }
+
+    /// Connects to the device at `addr` (`adb connect <addr>`) step by step, reporting every
+    /// state of the connection, and failing when a state lasts longer than its timeout.
+    pub fn connect_flow(&self, addr: SocketAddr, timeouts: ConnectTimeouts) -> ConnectFlow {
+        ConnectFlow {
+            server: self.clone(),
+            addr,
+            machine: ConnectMachine::new(timeouts, self.clock.now()),
+            connected: false,
+            last_reply: None,
+            done: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paired_parse() {
+        let paired = parse_pair_reply(
+            "Successfully paired to 192.168.1.20:37123 [guid=adb-1A2B3C4D-XyZ123]\n",
+        )
+        .unwrap();
+        assert_eq!("192.168.1.20:37123", paired.addr);
+        assert_eq!("adb-1A2B3C4D-XyZ123", paired.guid);
+        let err = parse_pair_reply("Failed: Wrong password or connection was dropped.");
+        assert!(matches!(err, Err(AdbError::Server { .. })));
+        assert!("Successfully paired to 192.168.1.20:37123"
+            .parse::<Paired>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_connect_reply() {
+        assert!(check_connect_reply("connected to 192.168.1.20:41234").is_ok());
+        assert!(check_connect_reply("already connected to 192.168.1.20:41234").is_ok());
+        let failed = "failed to connect to '192.168.1.20:41234': Connection refused";
+        assert!(check_connect_reply(failed).is_err());
+        assert!(check_connect_reply("failed to authenticate to 192.168.1.20:41234").is_err());
+    }
+
+    #[test]
+    fn test_connect_outcome() {
+        assert_eq!(
+            ConnectOutcome::Connected {
+                serial: "192.168.1.20:5555".to_string()
+            },
+            ConnectOutcome::parse("connected to 192.168.1.20:5555\n")
+        );
+        let outcome = ConnectOutcome::parse("already connected to [fe80::1]:5555");
+        assert!(outcome.is_connected());
+        assert_eq!(Some("[fe80::1]:5555"), outcome.serial());
+        let outcome = ConnectOutcome::parse("failed to authenticate to 192.168.1.20:5555");
+        assert!(matches!(outcome, ConnectOutcome::Unauthorized { .. }));
+        assert!(!outcome.is_connected());
+        let failed = "failed to connect to '192.168.1.20:5555': Connection refused";
+        assert_eq!(
+            ConnectOutcome::Failed {
+                message: failed.to_string()
+            },
+            ConnectOutcome::parse(failed)
+        );
+        assert_eq!(None, ConnectOutcome::parse(failed).serial());
+        let tcp = |s: &str| s.parse::<Tcp>().unwrap();
+        assert_eq!(
+            "192.168.1.20:5555",
+            connect_address(&tcp("tcp:192.168.1.20:5555"))
+        );
+        assert_eq!("[::1]:5555", connect_address(&tcp("tcp:[::1]:5555")));
+        assert_eq!("192.168.1.20", connect_address(&tcp("tcp:192.168.1.20")));
+    }
+
+    #[test]
+    fn test_connect_reply_state() {
+        assert_eq!(
+            Some(ConnectState::Connecting),
+            connect_reply_state("already connected to 192.168.1.20:5555\n")
+        );
+        assert_eq!(
+            Some(ConnectState::Unauthorized),
+            connect_reply_state("failed to authenticate to 192.168.1.20:5555")
+        );
+        assert_eq!(
+            None,
+            connect_reply_state("failed to connect to '192.168.1.20:5555': Connection refused")
+        );
+        assert_eq!(
+            ConnectState::Authorizing,
+            ConnectState::from_device_state(&DeviceState::Authorizing)
+        );
+        assert_eq!(
+            ConnectState::Device,
+            ConnectState::from_device_state(&DeviceState::Recovery)
+        );
+    }
+
+    #[test]
+    fn test_connect_machine() {
+        use ConnectState::*;
+        let timeouts = ConnectTimeouts::new()
+            .connecting(Duration::from_secs(5))
+            .unauthorized(Duration::from_secs(10))
+            .auth_retries(1);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut machine = ConnectMachine::new(timeouts, start);
+        assert_eq!(Step::Enter(Connecting), machine.observe(Connecting, at(0)));
+        assert_eq!(Step::Wait, machine.observe(Connecting, at(4)));
+        assert_eq!(
+            Step::Enter(Unauthorized),
+            machine.observe(Unauthorized, at(4))
+        );
+        assert_eq!(Step::Wait, machine.observe(Unauthorized, at(13)));
+        assert_eq!(Step::Reconnect, machine.observe(Unauthorized, at(14)));
+        assert_eq!(Step::Enter(Connecting), machine.observe(Connecting, at(15)));
+        assert_eq!(
+            Step::Enter(Unauthorized),
+            machine.observe(Unauthorized, at(16))
+        );
+        assert_eq!(
+            Step::TimedOut(Unauthorized),
+            machine.observe(Unauthorized, at(26))
+        );
+        let mut machine = ConnectMachine::new(timeouts, start);
+        machine.observe(Connecting, at(0));
+        assert_eq!(
+            Step::TimedOut(Connecting),
+            machine.observe(Connecting, at(5))
+        );
+        assert_eq!(Step::Enter(Device), machine.observe(Device, at(5)));
+    }
+}
@@ -0,0 +1,381 @@
+//! This module wraps the package manager shell command, `pm`.
+//!
+//! `pm list packages -f -U` prints one `package:<apk>=<package> uid:<uid>` line per
+//! package. The path of the APK may contain `=` itself, e.g. the random directories of
+//! `/data/app/~~<base64>==/`, so the package name is what follows the last one. Devices
+//! with several users list the uids of all of them, comma-separated.
+//!
+//! `pm` reports most failures on its output, and only exits with a non-zero code since
+//! Android 7, so the methods of [`Device`] check both.
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// The directory of the APKs of the packages installed by users.
+const DATA_APP_DIR: &str = "/data/app/";
+
+/// The packages listed by [`Device::packages`].
+///
+/// # Examples
+///
+/// ```
+/// use adb::pm::PackageFilter;
+///
+/// let filter = PackageFilter::new().third_party(true).name("example");
+/// assert_eq!(["-3", "example"], &filter.args()[..]);
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct PackageFilter {
+    system: bool,
+    third_party: bool,
+    enabled: bool,
+    disabled: bool,
+    user: Option<u32>,
+    name: Option<String>,
+}
+
+impl PackageFilter {
+    /// Creates a filter listing every package of the current user.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only lists system packages (`-s`).
+    pub fn system(mut self, system: bool) -> Self {
+        self.system = system;
+        self
+    }
+
+    /// Only lists third party packages (`-3`).
+    pub fn third_party(mut self, third_party: bool) -> Self {
+        self.third_party = third_party;
+        self
+    }
+
+    /// Only lists enabled packages (`-e`).
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Only lists disabled packages (`-d`).
+    pub fn disabled(mut self, disabled: bool) -> Self {
+        self.disabled = disabled;
+        self
+    }
+
+    /// Lists the packages of `user` (`--user`).
+    pub fn user(mut self, user: u32) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    /// Only lists the packages whose name contains `name`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Returns the arguments of `pm list packages`.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (set, arg) in [
+            (self.system, "-s"),
+            (self.third_party, "-3"),
+            (self.enabled, "-e"),
+            (self.disabled, "-d"),
+        ] {
+            if set {
+                args.push(arg.to_string());
+            }
+        }
+        if let Some(user) = self.user {
+            args.extend(["--user".to_string(), user.to_string()]);
+        }
+        args.extend(self.name.clone());
+        args
+    }
+}
+
+/// What is known about a listed package.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct PackageFlags {
+    /// `true` if the APK is on a system partition. Updates of system packages are
+    /// installed in `/data/app` like third party packages.
+    pub system: bool,
+    /// `true` or `false` if the listing was filtered on enabled or disabled packages.
+    pub enabled: Option<bool>,
+}
+
+/// A package listed by [`Device::packages`].
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct PackageInfo {
+    /// The package name, e.g. `com.android.settings`.
+    pub name: String,
+    /// The path of the base APK.
+    pub apk_path: Option<String>,
+    /// The uid of the package for the first user listed, `None` before Android 8.
+    pub uid: Option<u32>,
+    pub flags: PackageFlags,
+}
+
+/// Parses the output of `pm list packages -f -U`.
+pub fn parse_packages(output: &str) -> Result<Vec<PackageInfo>, AdbError> {
+    output
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .map(parse_package_line)
+        .collect()
+}
+
+/// Parses a `package:<apk>=<package> uid:<uid>[,<uid>...]` line.
+fn parse_package_line(line: &str) -> Result<PackageInfo, AdbError> {
+    let error = || AdbError::Parse {
+        value: line.to_string(),
+        source_type: "&str",
+        target_type: "PackageInfo",
+        source: None,
+    };
+    let rest = line.strip_prefix("package:").ok_or_else(error)?;
+    let mut words = rest.split(' ');
+    let package = words.next().unwrap_or_default();
+    let mut uid = None;
+    for word in words {
+        if let Some(uids) = word.strip_prefix("uid:") {
+            let first = uids.split(',').next().unwrap_or_default();
+            uid = Some(first.parse().map_err(|_| error())?);
+        }
+    }
+    let (apk_path, name) = match package.rsplit_once('=') {
+        Some((apk, name)) => (Some(apk.to_string()), name),
+        None => (None, package),
+    };
+    if name.is_empty() {
+        return Err(error());
+    }
+    let system = apk_path
+        .as_deref()
+        .is_some_and(|apk| !apk.starts_with(DATA_APP_DIR));
+    Ok(PackageInfo {
+        name: name.to_string(),
+        apk_path,
+        uid,
+        flags: PackageFlags {
+            system,
+            enabled: None,
+        },
+    })
+}
+
+/// Parses the output of `pm path`, the paths of the base APK and of the splits.
+pub fn parse_paths(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim_end().strip_prefix("package:"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Fails if the output of `pm` reports an error, e.g. `Error: ...`, `Failed`, or
+/// `Exception occurred while executing 'grant':`.
+fn check_pm_output(command: &str, output: &str) -> Result<(), AdbError> {
+    match output.lines().map(str::trim).find(|line| {
+        line.starts_with("Error")
+            || line.starts_with("Failed")
+            || line.starts_with("Exception")
+            || line.starts_with("Security exception")
+    }) {
+        Some(line) => Err(AdbError::Server {
+            message: format!("`{}`: {}", command, line),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Returns the `pm` subcommand `command` with the quoted arguments `args`.
+fn pm_command(command: &str, args: &[&str]) -> String {
+    let mut line = command.to_string();
+    for arg in args {
+        line.push(' ');
+        line.push_str(&shell::quote(arg));
+    }
+    line
+}
+
+impl Device {
+    /// Runs `pm <args>`, failing if it reports an error, and returns its output.
+    fn pm(&self, args: &str) -> Result<String, AdbError> {
+        let command = format!("pm {}", args);
+        let output = self.shell_checked(&command)?;
+        check_pm_output(&command, &output)?;
+        Ok(output)
+    }
+
+    /// Lists the installed packages matching `filter` (`pm list packages -f -U`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::pm::PackageFilter;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// for package in device.packages(&PackageFilter::new().third_party(true)).unwrap() {
+    ///     println!("{} {:?} {:?}", package.name, package.uid, package.apk_path);
+    /// }
+    /// ```
+    pub fn packages(&self, filter: &PackageFilter) -> Result<Vec<PackageInfo>, AdbError> {
+        let mut command = "list packages -f -U".to_string();
+        for arg in filter.args() {
+            command.push(' ');
+            command.push_str(&shell::quote(&arg));
+        }
+        let mut packages = parse_packages(&self.pm(&command)?)?;
+        let enabled = match (filter.enabled, filter.disabled) {
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            _ => None,
+        };
+        for package in &mut packages {
+            package.flags.enabled = enabled;
+            package.flags.system |= filter.system;
+        }
+        Ok(packages)
+    }
+
+    /// Returns the paths of the APKs of `package`, the base APK first, then the splits
+    /// (`pm path`).
+    pub fn package_paths(&self, package: &str) -> Result<Vec<String>, AdbError> {
+        let paths = parse_paths(&self.pm(&pm_command("path", &[package]))?);
+        if paths.is_empty() {
+            return Err(AdbError::Server {
+                message: format!("package {} isn't installed", package),
+            });
+        }
+        Ok(paths)
+    }
+
+    /// Grants the runtime `permission` to `package` (`pm grant`).
+    pub fn grant_permission(&self, package: &str, permission: &str) -> Result<(), AdbError> {
+        self.pm(&pm_command("grant", &[package, permission]))
+            .map(drop)
+    }
+
+    /// Revokes the runtime `permission` from `package` (`pm revoke`).
+    pub fn revoke_permission(&self, package: &str, permission: &str) -> Result<(), AdbError> {
+        self.pm(&pm_command("revoke", &[package, permission]))
+            .map(drop)
+    }
+
+    /// Deletes the data of `package`, stopping it and revoking its runtime permissions as
+    /// well (`pm clear`).
+    pub fn clear_package(&self, package: &str) -> Result<(), AdbError> {
+        let output = self.pm(&pm_command("clear", &[package]))?;
+        if output.trim() == "Success" {
+            Ok(())
+        } else {
+            Err(AdbError::Server {
+                message: format!("pm clear {}: {}", package, output.trim()),
+            })
+        }
+    }
+
+    /// Enables `package` (`pm enable`).
+    pub fn enable_package(&self, package: &str) -> Result<(), AdbError> {
+        self.pm(&pm_command("enable", &[package])).map(drop)
+    }
+
+    /// Disables `package` for the current user (`pm disable-user`), which unlike
+    /// `pm disable` doesn't require root.
+    pub fn disable_package(&self, package: &str) -> Result<(), AdbError> {
+        self.pm(&pm_command("disable-user", &[package])).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `pm list packages -f -U` of an Android 14 emulator.
+    const PACKAGES: &str = "\
+package:/system/priv-app/Settings/Settings.apk=com.android.settings uid:1000
+package:/data/app/~~Xk3_nFh2Yb9q5OQ1Bf0KzA==/com.example.app-3yJ2xkq5R7wGg0lJ1vF2sA==/base.apk=com.example.app uid:10190,1010190
+package:/product/app/Chrome/Chrome.apk=com.android.chrome uid:10127
+package:/apex/com.android.permission/priv-app/GooglePermissionController@340090000/GooglePermissionController.apk=com.google.android.permissioncontroller uid:10095
+";
+
+    #[test]
+    fn test_parse_packages() {
+        let packages = parse_packages(PACKAGES).unwrap();
+        assert_eq!(4, packages.len());
+        assert_eq!(
+            PackageInfo {
+                name: "com.android.settings".to_string(),
+                apk_path: Some("/system/priv-app/Settings/Settings.apk".to_string()),
+                uid: Some(1000),
+                flags: PackageFlags {
+                    system: true,
+                    enabled: None
+                },
+            },
+            packages[0]
+        );
+        let app = &packages[1];
+        assert_eq!("com.example.app", app.name);
+        assert_eq!(
+            Some("/data/app/~~Xk3_nFh2Yb9q5OQ1Bf0KzA==/com.example.app-3yJ2xkq5R7wGg0lJ1vF2sA==/base.apk"),
+            app.apk_path.as_deref()
+        );
+        assert_eq!(Some(10190), app.uid);
+        assert!(!app.flags.system);
+        assert!(packages[3].flags.system);
+
+        // Without `-f` and `-U`, e.g. on Android 7.
+        let packages = parse_packages("package:com.android.shell\r\n").unwrap();
+        assert_eq!("com.android.shell", packages[0].name);
+        assert_eq!(None, packages[0].apk_path);
+        assert_eq!(None, packages[0].uid);
+        assert!(parse_packages("Error: unknown option -U").is_err());
+    }
+
+    #[test]
+    fn test_parse_paths() {
+        let output = "package:/data/app/~~a==/com.example.app-b==/base.apk\n\
+                      package:/data/app/~~a==/com.example.app-b==/split_config.arm64_v8a.apk\n";
+        let paths = parse_paths(output);
+        assert_eq!(2, paths.len());
+        assert!(paths[0].ends_with("/base.apk"));
+        assert!(parse_paths("").is_empty());
+    }
+
+    #[test]
+    fn test_check_pm_output() {
+        assert!(check_pm_output(
+            "pm enable com.example.app",
+            "Package com.example.app new state: enabled\n"
+        )
+        .is_ok());
+        let grant = "Exception occurred while executing 'grant':\n\
+                     java.lang.SecurityException: Package com.example.app has not requested permission android.permission.CAMERA\n";
+        assert!(matches!(
+            check_pm_output("pm grant com.example.app android.permission.CAMERA", grant),
+            Err(AdbError::Server { .. })
+        ));
+        assert!(check_pm_output("pm path x", "Error: package x not found\n").is_err());
+        assert_eq!(
+            "grant 'com.example.app' 'android.permission.CAMERA'",
+            pm_command("grant", &["com.example.app", "android.permission.CAMERA"])
+        );
+        assert_eq!("clear 'x; reboot'", pm_command("clear", &["x; reboot"]));
+        assert_eq!(
+            ["-s", "-e", "--user", "10"],
+            &PackageFilter::new()
+                .system(true)
+                .enabled(true)
+                .user(10)
+                .args()[..]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! This module runs the same work on many devices at once, e.g. a command on every device of
+//! a farm.
+//!
+//! A [`DevicePool`] runs a closure on each of its devices, on at most
+//! [`DevicePool::concurrency`] threads, and collects the result of each device by serial
+//! number. A failing device doesn't stop the others.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::device::{Device, DeviceState, Transport};
+use crate::error::AdbError;
+use crate::server::AdbServer;
+
+/// A set of devices to run work on concurrently.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::pool::DevicePool;
+/// use adb::server::AdbServer;
+///
+/// let pool = DevicePool::online(&AdbServer::default())
+///     .unwrap()
+///     .filter(|device| !device.serial().unwrap_or_default().starts_with("emulator-"))
+///     .concurrency(4);
+/// for (serial, result) in pool.run(|device| device.features()) {
+///     match result {
+///         Ok(features) => println!("{}: {}", serial, features),
+///         Err(e) => println!("{}: {}", serial, e),
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DevicePool {
+    devices: Vec<Device>,
+    concurrency: usize,
+}
+
+impl DevicePool {
+    /// Creates a pool of `devices`, running on all of them at once.
+    pub fn new(devices: Vec<Device>) -> Self {
+        Self {
+            devices,
+            concurrency: usize::MAX,
+        }
+    }
+
+    /// Creates a pool of the devices of `server` which are online, i.e. in the
+    /// [`DeviceState::Device`] state.
+    pub fn online(server: &AdbServer) -> Result<Self, AdbError> {
+        let devices = server
+            .devices()?
+            .into_iter()
+            .filter(|device| device.state() == Some(DeviceState::Device))
+            .collect();
+        Ok(Self::new(devices))
+    }
+
+    /// Keeps the devices for which `predicate` returns `true`.
+    pub fn filter(mut self, mut predicate: impl FnMut(&Device) -> bool) -> Self {
+        self.devices.retain(|device| predicate(device));
+        self
+    }
+
+    /// Sets how many devices are worked on at once, at least one.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Returns the devices of the pool.
+    pub fn devices(&self) -> &[Device] {
+        &self.devices
+    }
+
+    /// Runs `work` on every device, and returns the result of each device by its key, see
+    /// [`device_key`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `work` panics, once the other devices are done.
+    pub fn run<T, F>(&self, work: F) -> HashMap<String, Result<T, AdbError>>
+    where
+        T: Send,
+        F: Fn(&Device) -> Result<T, AdbError> + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(HashMap::with_capacity(self.devices.len()));
+        thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(self.devices.len()) {
+                scope.spawn(|| {
+                    while let Some(device) = self.devices.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        let result = work(device);
+                        let mut results = results.lock().unwrap_or_else(|e| e.into_inner());
+                        results.insert(device_key(device), result);
+                    }
+                });
+            }
+        });
+        results.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Returns the key of the results of `device` in [`DevicePool::run`]: its serial number, or
+/// its transport id, e.g. `transport-id:3`, or `any`, `usb` or `local` for the other
+/// transports.
+pub fn device_key(device: &Device) -> String {
+    match device.transport() {
+        Transport::Serial(serial) => serial.clone(),
+        Transport::TransportId(id) => format!("transport-id:{}", id),
+        Transport::Any => "any".to_string(),
+        Transport::Usb => "usb".to_string(),
+        Transport::Local => "local".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_device_pool_run() {
+        let server = AdbServer::default();
+        let serials = ["emulator-5554", "emulator-5556", "R58M123ABC", "fail"];
+        let pool = DevicePool::new(serials.iter().map(|s| server.device(s)).collect())
+            .filter(|device| device.serial() != Some("R58M123ABC"))
+            .concurrency(2);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let results = pool.run(|device| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            running.fetch_sub(1, Ordering::SeqCst);
+            match device.serial() {
+                Some("fail") => Err(AdbError::DeviceOffline),
+                serial => Ok(serial.unwrap_or_default().len()),
+            }
+        });
+        assert_eq!(3, results.len());
+        assert_eq!(13, *results["emulator-5554"].as_ref().unwrap());
+        assert!(matches!(results["fail"], Err(AdbError::DeviceOffline)));
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(
+            "transport-id:3",
+            device_key(&server.device_by_transport_id(3))
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! This module provides hooks cutting and restoring the power of devices, the last resort to
+//! recover a device wedged beyond what adb can fix, e.g. stuck in a boot loop or with a hung
+//! USB stack.
+//!
+//! Labs implement [`PowerControl`] for their relays or power distribution units. The `power`
+//! feature adds implementations for USB hubs switching the power of their ports:
+//! [`Uhubctl`] for hubs supported by `uhubctl`, and [`Ykush`] for Yepkit YKUSH boards.
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use crate::device::{Device, DeviceState};
+use crate::error::AdbError;
+
+#[cfg(feature = "power")]
+use std::collections::HashMap;
+#[cfg(feature = "power")]
+use std::ffi::OsString;
+#[cfg(feature = "power")]
+use std::process::Command;
+
+#[cfg(feature = "power")]
+use crate::topology::UsbPort;
+
+/// Cuts and restores the power of devices, identified by their serial numbers.
+pub trait PowerControl: Debug + Send + Sync {
+    /// Cuts the power of the device `serial`.
+    fn power_off(&self, serial: &str) -> Result<(), AdbError>;
+
+    /// Restores the power of the device `serial`.
+    fn power_on(&self, serial: &str) -> Result<(), AdbError>;
+}
+
+/// How [`Device::power_cycle`] cycles the power of a device.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::power::PowerCycleOptions;
+///
+/// let options = PowerCycleOptions::new().off_time(Duration::from_secs(10));
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct PowerCycleOptions {
+    off_time: Duration,
+    boot_timeout: Duration,
+}
+
+impl PowerCycleOptions {
+    /// Creates options cutting the power for 5 s, and waiting 2 minutes for the device to
+    /// come back.
+    pub fn new() -> Self {
+        Self {
+            off_time: Duration::from_secs(5),
+            boot_timeout: Duration::from_secs(120),
+        }
+    }
+
+    /// Sets how long the power stays off, long enough for the device to discharge.
+    pub fn off_time(mut self, off_time: Duration) -> Self {
+        self.off_time = off_time;
+        self
+    }
+
+    /// Sets how long to wait for the device to come back once powered, failing with
+    /// [`AdbError::Timeout`].
+    pub fn boot_timeout(mut self, timeout: Duration) -> Self {
+        self.boot_timeout = timeout;
+        self
+    }
+}
+
+impl Default for PowerCycleOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device {
+    /// Cuts the power of the device through `control`, restores it, and waits for the device
+    /// to come back online.
+    ///
+    /// The device must have a serial number. Devices with a battery only restart once
+    /// powered if set to boot on charger, otherwise this only resets their USB connection.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::power::{PowerControl, PowerCycleOptions};
+    /// use adb::server::AdbServer;
+    ///
+    /// fn recover(control: &dyn PowerControl) {
+    ///     let device = AdbServer::default().device("R58M123ABC");
+    ///     if device.get_state().is_err() {
+    ///         device.power_cycle(control, &PowerCycleOptions::new()).unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn power_cycle<P: PowerControl + ?Sized>(
+        &self,
+        control: &P,
+        options: &PowerCycleOptions,
+    ) -> Result<(), AdbError> {
+        let serial = self.serial().ok_or_else(|| AdbError::Server {
+            message: "cannot power-cycle a device without a serial number".to_string(),
+        })?;
+        control.power_off(serial)?;
+        self.server().clock.sleep(options.off_time);
+        control.power_on(serial)?;
+        self.wait_for(DeviceState::Device, options.boot_timeout)
+    }
+}
+
+/// Runs `program` with `args`, failing with its error output if it fails.
+#[cfg(feature = "power")]
+fn run(program: &OsString, args: &[String]) -> Result<(), AdbError> {
+    let output = Command::new(program).args(args).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(AdbError::Server {
+        message: format!(
+            "`{} {}` failed with {}: {}",
+            program.to_string_lossy(),
+            args.join(" "),
+            output.status,
+            stderr.trim()
+        ),
+    })
+}
+
+/// The ports of USB hubs switched by `uhubctl`.
+///
+/// Devices are mapped to the port they're plugged in, or looked up in sysfs on Linux, see
+/// [`UsbPort::find`]. The port must be on a hub supporting per-port power switching.
+#[cfg(feature = "power")]
+#[derive(Clone, Debug)]
+pub struct Uhubctl {
+    program: OsString,
+    ports: HashMap<String, UsbPort>,
+}
+
+#[cfg(feature = "power")]
+impl Uhubctl {
+    /// Creates a controller running `uhubctl` from the `PATH`.
+    pub fn new() -> Self {
+        Self {
+            program: "uhubctl".into(),
+            ports: HashMap::new(),
+        }
+    }
+
+    /// Sets the path of `uhubctl`.
+    pub fn program(mut self, program: impl Into<OsString>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Maps the device `serial` to `port`, e.g. `1-1.3`, overriding sysfs.
+    pub fn port(mut self, serial: impl Into<String>, port: UsbPort) -> Self {
+        self.ports.insert(serial.into(), port);
+        self
+    }
+
+    /// Returns the arguments switching the port of `serial` on or off.
+    fn args(&self, serial: &str, on: bool) -> Result<Vec<String>, AdbError> {
+        let port = match self.ports.get(serial) {
+            Some(port) => port.clone(),
+            None => UsbPort::find(serial)?.ok_or_else(|| AdbError::DeviceNotFound {
+                serial: Some(serial.to_string()),
+            })?,
+        };
+        let (Some(hub), Some(number)) = (port.hub(), port.port()) else {
+            return Err(AdbError::Server {
+                message: format!("{} isn't plugged in a hub port", port),
+            });
+        };
+        Ok(vec![
+            "-l".to_string(),
+            hub.to_string(),
+            "-p".to_string(),
+            number.to_string(),
+            "-a".to_string(),
+            if on { "on" } else { "off" }.to_string(),
+        ])
+    }
+}
+
+#[cfg(feature = "power")]
+impl Default for Uhubctl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "power")]
+impl PowerControl for Uhubctl {
+    fn power_off(&self, serial: &str) -> Result<(), AdbError> {
+        run(&self.program, &self.args(serial, false)?)
+    }
+
+    fn power_on(&self, serial: &str) -> Result<(), AdbError> {
+        run(&self.program, &self.args(serial, true)?)
+    }
+}
+
+/// The downstream ports of Yepkit YKUSH boards, switched by `ykushcmd`.
+///
+/// Devices must be mapped to their port, and board if several are plugged in.
+#[cfg(feature = "power")]
+#[derive(Clone, Debug)]
+pub struct Ykush {
+    program: OsString,
+    ports: HashMap<String, (Option<String>, u8)>,
+}
+
+#[cfg(feature = "power")]
+impl Ykush {
+    /// Creates a controller running `ykushcmd` from the `PATH`.
+    pub fn new() -> Self {
+        Self {
+            program: "ykushcmd".into(),
+            ports: HashMap::new(),
+        }
+    }
+
+    /// Sets the path of `ykushcmd`.
+    pub fn program(mut self, program: impl Into<OsString>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Maps the device `serial` to the downstream `port` of the board with the serial
+    /// number `board`, or of the only board if `None`.
+    pub fn port(mut self, serial: impl Into<String>, board: Option<String>, port: u8) -> Self {
+        self.ports.insert(serial.into(), (board, port));
+        self
+    }
+
+    /// Returns the arguments switching the port of `serial` on (`-u`) or off (`-d`).
+    fn args(&self, serial: &str, on: bool) -> Result<Vec<String>, AdbError> {
+        let (board, port) = self
+            .ports
+            .get(serial)
+            .ok_or_else(|| AdbError::DeviceNotFound {
+                serial: Some(serial.to_string()),
+            })?;
+        let mut args = Vec::new();
+        if let Some(board) = board {
+            args.extend(["-s".to_string(), board.clone()]);
+        }
+        args.push(if on { "-u" } else { "-d" }.to_string());
+        args.push(port.to_string());
+        Ok(args)
+    }
+}
+
+#[cfg(feature = "power")]
+impl Default for Ykush {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "power")]
+impl PowerControl for Ykush {
+    fn power_off(&self, serial: &str) -> Result<(), AdbError> {
+        run(&self.program, &self.args(serial, false)?)
+    }
+
+    fn power_on(&self, serial: &str) -> Result<(), AdbError> {
+        run(&self.program, &self.args(serial, true)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::clock::{Clock, MockClock};
+    use crate::connect::{ConnectOptions, RetryPolicy};
+    use crate::server::AdbServer;
+    use crate::socket::Tcp;
+
+    #[derive(Debug, Default)]
+    struct Relay(Mutex<Vec<(String, bool)>>);
+
+    impl PowerControl for Relay {
+        fn power_off(&self, serial: &str) -> Result<(), AdbError> {
+            self.0.lock().unwrap().push((serial.to_string(), false));
+            Ok(())
+        }
+
+        fn power_on(&self, serial: &str) -> Result<(), AdbError> {
+            self.0.lock().unwrap().push((serial.to_string(), true));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_power_cycle() {
+        let clock = MockClock::new();
+        // No server listens on this port, so waiting for the device fails right away.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server = AdbServer::new(Tcp::from_port(port))
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::none()))
+            .auto_start(false)
+            .clock(clock.clone());
+        let relay = Relay::default();
+        let start = clock.now();
+        let options = PowerCycleOptions::new().off_time(Duration::from_secs(10));
+        assert!(server
+            .device("R58M123ABC")
+            .power_cycle(&relay, &options)
+            .is_err());
+        assert!(clock.now() - start >= Duration::from_secs(10));
+        assert_eq!(
+            vec![
+                ("R58M123ABC".to_string(), false),
+                ("R58M123ABC".to_string(), true)
+            ],
+            *relay.0.lock().unwrap()
+        );
+    }
+
+    #[cfg(feature = "power")]
+    #[test]
+    fn test_power_args() {
+        let uhubctl = Uhubctl::new().port("R58M123ABC", "2-1.4".parse().unwrap());
+        assert_eq!(
+            ["-l", "2-1", "-p", "4", "-a", "off"],
+            &uhubctl.args("R58M123ABC", false).unwrap()[..]
+        );
+        let ykush = Ykush::new()
+            .port("R58M123ABC", Some("YK21234".to_string()), 2)
+            .port("emulator-5554", None, 3);
+        assert_eq!(
+            ["-s", "YK21234", "-u", "2"],
+            &ykush.args("R58M123ABC", true).unwrap()[..]
+        );
+        assert_eq!(
+            ["-d", "3"],
+            &ykush.args("emulator-5554", false).unwrap()[..]
+        );
+        assert!(ykush.args("unknown", true).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! This module provides profiling helpers, like heap dumps of running processes.
+//!
+//! Heap dumps are written on the device by `am dumpheap`, which may return before the
+//! process finishes writing, so the dump is considered complete once its size stops
+//! changing. Java heap dumps use an Android flavor of hprof, which most host tools only read
+//! after conversion with `hprof-conv` from the Android SDK platform tools.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::compat;
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+use crate::sync::TransferStats;
+
+/// The directory heap dumps are written to on the device.
+pub const TEMP_DIR: &str = "/data/local/tmp";
+
+/// The default interval between two checks of the size of a heap dump being written.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The default time to wait for a heap dump to be written.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The options of [`Device::heap_dump`].
+///
+/// # Examples
+///
+/// ```
+/// use adb::profile::HeapDumpOptions;
+///
+/// let options = HeapDumpOptions::new().gc(true);
+/// assert_eq!(options.args(), ["-g"]);
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct HeapDumpOptions {
+    native: bool,
+    gc: bool,
+    convert: bool,
+    poll_interval: Duration,
+    timeout: Duration,
+}
+
+impl HeapDumpOptions {
+    /// Creates options for a Java heap dump in the Android hprof flavor.
+    pub fn new() -> Self {
+        Self {
+            native: false,
+            gc: false,
+            convert: false,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets whether to dump the native heap instead of the Java heap (`-n`).
+    pub fn native(mut self, native: bool) -> Self {
+        self.native = native;
+        self
+    }
+
+    /// Sets whether to run a garbage collection before dumping (`-g`, Android 10 and later).
+    pub fn gc(mut self, gc: bool) -> Self {
+        self.gc = gc;
+        self
+    }
+
+    /// Sets whether to convert a Java heap dump to the standard hprof format with the
+    /// `hprof-conv` command found in `PATH`. Native heap dumps are never converted.
+    pub fn convert(mut self, convert: bool) -> Self {
+        self.convert = convert;
+        self
+    }
+
+    /// Sets the interval between two checks of the size of the dump being written.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sets how long to wait for the dump to be written.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the options of `am dumpheap`.
+    pub fn args(&self) -> Vec<&'static str> {
+        let mut args = Vec::new();
+        if self.native {
+            args.push("-n");
+        }
+        if self.gc {
+            args.push("-g");
+        }
+        args
+    }
+}
+
+impl Default for HeapDumpOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the path of the heap dump of `process` on the device.
+fn remote_path(process: &str) -> String {
+    let name: String = process
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}/disanger-{}.hprof", TEMP_DIR, name)
+}
+
+/// Returns the path the unconverted dump is pulled to before being converted into `dest`.
+fn unconverted_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".android");
+    dest.with_file_name(name)
+}
+
+/// Converts an Android hprof file into a standard one with `hprof-conv`.
+fn convert(src: &Path, dest: &Path) -> Result<(), AdbError> {
+    let status = Command::new("hprof-conv").arg(src).arg(dest).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(compat::io_other(format!("`hprof-conv` exited with {}", status)).into())
+    }
+}
+
+impl Device {
+    /// Dumps the heap of `process`, a pid or a process name, and pulls the dump to `dest`
+    /// (`am dumpheap`).
+    ///
+    /// The process must be debuggable, or the device must be running a userdebug build.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use adb::profile::HeapDumpOptions;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let options = HeapDumpOptions::new().gc(true).convert(true);
+    /// device.heap_dump("com.example.app", Path::new("app.hprof"), &options).unwrap();
+    /// ```
+    pub fn heap_dump(
+        &self,
+        process: &str,
+        dest: &Path,
+        options: &HeapDumpOptions,
+    ) -> Result<TransferStats, AdbError> {
+        let remote = remote_path(process);
+        self.shell_checked(&format!("rm -f {}", shell::quote(&remote)))?;
+        let mut command = vec!["am", "dumpheap"];
+        command.extend(options.args());
+        let output = self.shell_checked(&format!(
+            "{} {} {}",
+            command.join(" "),
+            shell::quote(process),
+            shell::quote(&remote)
+        ))?;
+        // Older versions of `am` exit with 0 when failing.
+        if let Some(error) = output.lines().find(|line| line.starts_with("Error")) {
+            return Err(AdbError::Server {
+                message: error.to_string(),
+            });
+        }
+        let result = self.wait_for_dump(&remote, options).and_then(|()| {
+            if options.convert && !options.native {
+                let android = unconverted_path(dest);
+                let stats = self.pull(&remote, &android)?;
+                let converted = convert(&android, dest);
+                let _ = std::fs::remove_file(&android);
+                converted.map(|()| stats)
+            } else {
+                self.pull(&remote, dest)
+            }
+        });
+        let _ = self.shell(&format!("rm -f {}", shell::quote(&remote)));
+        result
+    }
+
+    /// Waits until the size of the file at `remote` stops changing.
+    fn wait_for_dump(&self, remote: &str, options: &HeapDumpOptions) -> Result<(), AdbError> {
+        let clock = &self.server().clock;
+        let start = clock.now();
+        let mut sync = self.sync()?;
+        let mut last_size = 0;
+        loop {
+            // A missing file is reported with a zero mode.
+            let stat = sync.stat(remote)?;
+            if stat.mode != 0 && stat.size > 0 && stat.size == last_size {
+                return Ok(());
+            }
+            last_size = stat.size;
+            if clock.now() - start >= options.timeout {
+                return Err(AdbError::Server {
+                    message: format!(
+                        "heap dump `{}` not written after {:?}",
+                        remote, options.timeout
+                    ),
+                });
+            }
+            clock.sleep(options.poll_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heap_dump_options_args() {
+        assert!(HeapDumpOptions::new().args().is_empty());
+        assert_eq!(
+            ["-n", "-g"],
+            &HeapDumpOptions::new().native(true).gc(true).args()[..]
+        );
+    }
+
+    #[test]
+    fn test_remote_path() {
+        assert_eq!(
+            "/data/local/tmp/disanger-com.example.app_remote.hprof",
+            remote_path("com.example.app:remote")
+        );
+        assert_eq!("/data/local/tmp/disanger-1234.hprof", remote_path("1234"));
+        assert_eq!(
+            Path::new("dumps/app.hprof.android"),
+            unconverted_path(Path::new("dumps/app.hprof"))
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! This module estimates the remaining time of long workflows, e.g. provisioning a device
+//! by installing packages, pushing files and pulling a bug report.
+//!
+//! Each step of a workflow is an operation reporting [`ProgressEvent`]s, in units which
+//! should be comparable across operations, usually bytes. An [`EtaEstimator`] smooths the
+//! throughput of each operation and of the whole workflow with an exponentially weighted
+//! moving average, so estimates don't jump with every chunk, and reports them to an
+//! [`EtaObserver`].
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::{self, Clock};
+
+/// The default weight of the latest throughput sample, see [`EtaEstimator::smoothing`].
+pub const DEFAULT_SMOOTHING: f64 = 0.3;
+
+/// The progress of an operation of a workflow.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ProgressEvent {
+    /// The operation started, with `total` units of work if known.
+    Started {
+        operation: String,
+        total: Option<u64>,
+    },
+    /// The operation did `done` out of `total` units of work.
+    Advanced {
+        operation: String,
+        done: u64,
+        total: Option<u64>,
+    },
+    /// The operation finished.
+    Finished { operation: String },
+}
+
+impl ProgressEvent {
+    /// Returns the operation the event is about.
+    pub fn operation(&self) -> &str {
+        match self {
+            Self::Started { operation, .. }
+            | Self::Advanced { operation, .. }
+            | Self::Finished { operation } => operation,
+        }
+    }
+}
+
+/// The estimated progress of an operation or workflow.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Estimate {
+    /// The units of work done.
+    pub done: u64,
+    /// The units of work in total, `None` if unknown.
+    pub total: Option<u64>,
+    /// The smoothed throughput in units per second, 0 until two samples were seen.
+    pub throughput: f64,
+    /// The remaining time, `None` if the total or the throughput is unknown.
+    pub eta: Option<Duration>,
+}
+
+impl Estimate {
+    /// Returns the completed fraction, from 0 to 1, `None` if the total is unknown.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.done as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+}
+
+/// An [`Estimate`] updated by an [`EtaEstimator`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct EtaEvent {
+    /// The operation whose progress was reported.
+    pub operation: String,
+    /// The estimate of the operation.
+    pub estimate: Estimate,
+    /// The estimate of the whole workflow.
+    pub workflow: Estimate,
+}
+
+/// Receives the [`EtaEvent`]s of an [`EtaEstimator`].
+pub trait EtaObserver: Debug + Send + Sync {
+    fn on_event(&self, event: &EtaEvent);
+}
+
+/// A throughput smoothed over samples of done work.
+#[derive(Copy, Clone, Debug)]
+struct Rate {
+    done: u64,
+    at: Instant,
+    throughput: Option<f64>,
+}
+
+impl Rate {
+    fn new(now: Instant) -> Self {
+        Self {
+            done: 0,
+            at: now,
+            throughput: None,
+        }
+    }
+
+    /// Records that `done` units of work were done at `now`.
+    fn sample(&mut self, done: u64, now: Instant, smoothing: f64) {
+        let elapsed = now.saturating_duration_since(self.at).as_secs_f64();
+        if elapsed <= 0.0 {
+            // Samples of the same instant are merged into the next one.
+            if done < self.done {
+                self.done = done;
+            }
+            return;
+        }
+        let rate = done.saturating_sub(self.done) as f64 / elapsed;
+        self.throughput = Some(match self.throughput {
+            Some(throughput) => smoothing * rate + (1.0 - smoothing) * throughput,
+            None => rate,
+        });
+        self.done = done;
+        self.at = now;
+    }
+
+    fn estimate(&self, done: u64, total: Option<u64>) -> Estimate {
+        let throughput = self.throughput.unwrap_or_default();
+        let eta = match total {
+            Some(total) if done >= total => Some(Duration::ZERO),
+            Some(total) if throughput > 0.0 => {
+                Some(Duration::from_secs_f64((total - done) as f64 / throughput))
+            }
+            _ => None,
+        };
+        Estimate {
+            done,
+            total,
+            throughput,
+            eta,
+        }
+    }
+}
+
+/// The state of an operation.
+#[derive(Copy, Clone, Debug)]
+struct Operation {
+    done: u64,
+    total: Option<u64>,
+    rate: Rate,
+    finished: bool,
+}
+
+/// Estimates the throughput and remaining time of the operations of a workflow, and of the
+/// whole workflow, from their [`ProgressEvent`]s.
+///
+/// The workflow spans every operation reported so far: its total is only known while the
+/// totals of all of them are.
+///
+/// # Examples
+///
+/// ```
+/// use adb::progress::{EtaEstimator, ProgressEvent};
+///
+/// let mut estimator = EtaEstimator::new();
+/// estimator.update(&ProgressEvent::Started {
+///     operation: "push system.img".to_string(),
+///     total: Some(1 << 30),
+/// });
+/// // Report every chunk.
+/// let event = estimator.update(&ProgressEvent::Advanced {
+///     operation: "push system.img".to_string(),
+///     done: 1 << 20,
+///     total: Some(1 << 30),
+/// });
+/// if let Some(eta) = event.workflow.eta {
+///     println!("{:.0?} left", eta);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct EtaEstimator {
+    smoothing: f64,
+    clock: Arc<dyn Clock>,
+    observer: Option<Arc<dyn EtaObserver>>,
+    operations: HashMap<String, Operation>,
+    workflow: Option<Rate>,
+}
+
+impl EtaEstimator {
+    /// Creates an estimator weighting the latest sample by [`DEFAULT_SMOOTHING`].
+    pub fn new() -> Self {
+        Self {
+            smoothing: DEFAULT_SMOOTHING,
+            clock: clock::system(),
+            observer: None,
+            operations: HashMap::new(),
+            workflow: None,
+        }
+    }
+
+    /// Sets the weight of the latest throughput sample, from 0 (exclusive), only trusting
+    /// the first sample, to 1, ignoring the previous ones.
+    pub fn smoothing(mut self, smoothing: f64) -> Self {
+        self.smoothing = smoothing.clamp(f64::MIN_POSITIVE, 1.0);
+        self
+    }
+
+    /// Sets the clock timing the events.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the observer of the estimates.
+    pub fn observer(mut self, observer: impl EtaObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Records `event`, reporting and returning the updated estimates.
+    pub fn update(&mut self, event: &ProgressEvent) -> EtaEvent {
+        let now = self.clock.now();
+        let smoothing = self.smoothing;
+        let operation = self
+            .operations
+            .entry(event.operation().to_string())
+            .or_insert_with(|| Operation {
+                done: 0,
+                total: None,
+                rate: Rate::new(now),
+                finished: false,
+            });
+        match *event {
+            ProgressEvent::Started { total, .. } => {
+                *operation = Operation {
+                    done: 0,
+                    total,
+                    rate: Rate::new(now),
+                    finished: false,
+                };
+            }
+            ProgressEvent::Advanced { done, total, .. } => {
+                operation.done = done;
+                operation.total = total.or(operation.total);
+                operation.rate.sample(done, now, smoothing);
+            }
+            ProgressEvent::Finished { .. } => {
+                if let Some(total) = operation.total {
+                    operation.done = operation.done.max(total);
+                } else {
+                    operation.total = Some(operation.done);
+                }
+                operation.finished = true;
+            }
+        }
+        let estimate = operation.rate.estimate(operation.done, operation.total);
+
+        let done = self
+            .operations
+            .values()
+            .map(|operation| operation.done)
+            .sum();
+        let total = self.total();
+        let workflow = self.workflow.get_or_insert_with(|| Rate::new(now));
+        workflow.sample(done, now, smoothing);
+        let event = EtaEvent {
+            operation: event.operation().to_string(),
+            estimate,
+            workflow: workflow.estimate(done, total),
+        };
+        if let Some(observer) = &self.observer {
+            observer.on_event(&event);
+        }
+        event
+    }
+
+    /// Returns the estimate of `operation`, `None` if it wasn't reported.
+    pub fn operation(&self, operation: &str) -> Option<Estimate> {
+        self.operations
+            .get(operation)
+            .map(|operation| operation.rate.estimate(operation.done, operation.total))
+    }
+
+    /// Returns the estimate of the whole workflow.
+    pub fn workflow(&self) -> Estimate {
+        let done = self
+            .operations
+            .values()
+            .map(|operation| operation.done)
+            .sum();
+        match &self.workflow {
+            Some(rate) => rate.estimate(done, self.total()),
+            None => Estimate::default(),
+        }
+    }
+
+    /// Returns `true` if every reported operation finished.
+    pub fn is_finished(&self) -> bool {
+        self.operations.values().all(|operation| operation.finished)
+    }
+
+    /// Returns the total of the workflow, known if the totals of all operations are.
+    fn total(&self) -> Option<u64> {
+        self.operations
+            .values()
+            .map(|operation| operation.total)
+            .sum()
+    }
+}
+
+impl Default for EtaEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::clock::MockClock;
+
+    #[derive(Debug, Default, Clone)]
+    struct Log(Arc<Mutex<Vec<EtaEvent>>>);
+
+    impl EtaObserver for Log {
+        fn on_event(&self, event: &EtaEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn advanced(operation: &str, done: u64, total: Option<u64>) -> ProgressEvent {
+        ProgressEvent::Advanced {
+            operation: operation.to_string(),
+            done,
+            total,
+        }
+    }
+
+    #[test]
+    fn test_eta_estimator() {
+        let clock = MockClock::new();
+        let log = Log::default();
+        let mut estimator = EtaEstimator::new()
+            .smoothing(0.5)
+            .clock(clock.clone())
+            .observer(log.clone());
+        estimator.update(&ProgressEvent::Started {
+            operation: "push".to_string(),
+            total: Some(1000),
+        });
+        estimator.update(&ProgressEvent::Started {
+            operation: "install".to_string(),
+            total: Some(400),
+        });
+        assert_eq!(None, estimator.workflow().eta);
+
+        clock.advance(Duration::from_secs(1));
+        let event = estimator.update(&advanced("push", 100, None));
+        assert_eq!(100.0, event.estimate.throughput);
+        assert_eq!(Some(Duration::from_secs(9)), event.estimate.eta);
+        assert_eq!(Some(1400), event.workflow.total);
+        assert_eq!(Some(Duration::from_secs(13)), event.workflow.eta);
+
+        // The throughput halves, the estimate moves half way.
+        clock.advance(Duration::from_secs(2));
+        let event = estimator.update(&advanced("push", 200, Some(1000)));
+        assert_eq!(75.0, event.estimate.throughput);
+        assert_eq!(Some(0.2), event.estimate.fraction());
+
+        let event = estimator.update(&ProgressEvent::Finished {
+            operation: "push".to_string(),
+        });
+        assert_eq!(Some(Duration::ZERO), event.estimate.eta);
+        assert_eq!(1000, event.workflow.done);
+        assert!(!estimator.is_finished());
+
+        // An operation of unknown size makes the workflow total unknown.
+        estimator.update(&advanced("logs", 10, None));
+        assert_eq!(None, estimator.workflow().total);
+        assert_eq!(None, estimator.workflow().eta);
+        assert_eq!(None, estimator.operation("logs").unwrap().fraction());
+        assert!(estimator.operation("unknown").is_none());
+        assert_eq!(6, log.0.lock().unwrap().len());
+    }
+}
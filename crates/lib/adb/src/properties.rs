@@ -0,0 +1,271 @@
+//! This module provides the system properties of a device, parsed from `getprop`.
+//!
+//! `getprop` lists one `[<key>]: [<value>]` pair per line, values spanning several lines
+//! when they contain newlines. The properties are cached by the [`Device`] handle and its
+//! clones until [`Device::invalidate_properties`] or [`Device::set_prop`] is called, since
+//! most of them (`ro.*`) never change while the device runs.
+
+use std::collections::btree_map::{self, BTreeMap};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// An Android ABI, as listed in `ro.product.cpu.abilist`.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Abi {
+    /// `armeabi`
+    Armeabi,
+    /// `armeabi-v7a`
+    ArmeabiV7a,
+    /// `arm64-v8a`
+    Arm64V8a,
+    /// `x86`
+    X86,
+    /// `x86_64`
+    X86_64,
+    /// `riscv64`
+    Riscv64,
+    /// Any other ABI.
+    Other(String),
+}
+
+impl Display for Abi {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Armeabi => "armeabi",
+            Self::ArmeabiV7a => "armeabi-v7a",
+            Self::Arm64V8a => "arm64-v8a",
+            Self::X86 => "x86",
+            Self::X86_64 => "x86_64",
+            Self::Riscv64 => "riscv64",
+            Self::Other(abi) => abi,
+        })
+    }
+}
+
+impl FromStr for Abi {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "armeabi" => Self::Armeabi,
+            "armeabi-v7a" => Self::ArmeabiV7a,
+            "arm64-v8a" => Self::Arm64V8a,
+            "x86" => Self::X86,
+            "x86_64" => Self::X86_64,
+            "riscv64" => Self::Riscv64,
+            _ if !s.is_empty() => Self::Other(s.to_string()),
+            _ => {
+                return Err(AdbError::Parse {
+                    value: s.to_string(),
+                    source_type: "&str",
+                    target_type: "Abi",
+                    source: None,
+                })
+            }
+        })
+    }
+}
+
+/// The system properties of a device.
+///
+/// # Syntax
+///
+/// The output of `getprop`, e.g. `[ro.build.version.sdk]: [34]`.
+///
+/// ```
+/// use adb::properties::{Abi, Properties};
+///
+/// let properties: Properties = "[ro.build.version.sdk]: [34]\n\
+///     [ro.product.cpu.abilist]: [arm64-v8a,armeabi-v7a]\n"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(Some(34), properties.sdk());
+/// assert_eq!(vec![Abi::Arm64V8a, Abi::ArmeabiV7a], properties.abis());
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Properties(BTreeMap<String, String>);
+
+impl Properties {
+    /// Returns the value of `key`, `None` if it's unset.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Returns an iterator over the properties, sorted by key.
+    pub fn iter(&self) -> btree_map::Iter<'_, String, String> {
+        self.0.iter()
+    }
+
+    /// Returns the SDK version, e.g. 34 for Android 14 (`ro.build.version.sdk`).
+    pub fn sdk(&self) -> Option<u32> {
+        self.get("ro.build.version.sdk")?.parse().ok()
+    }
+
+    /// Returns the Android version, e.g. `14` (`ro.build.version.release`).
+    pub fn release(&self) -> Option<&str> {
+        self.get("ro.build.version.release")
+    }
+
+    /// Returns the model, e.g. `Pixel 7` (`ro.product.model`).
+    pub fn model(&self) -> Option<&str> {
+        self.get("ro.product.model")
+    }
+
+    /// Returns the manufacturer, e.g. `Google` (`ro.product.manufacturer`).
+    pub fn manufacturer(&self) -> Option<&str> {
+        self.get("ro.product.manufacturer")
+    }
+
+    /// Returns the build fingerprint (`ro.build.fingerprint`).
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.get("ro.build.fingerprint")
+    }
+
+    /// Returns the supported ABIs, preferred first (`ro.product.cpu.abilist`, or
+    /// `ro.product.cpu.abi` before Android 5).
+    pub fn abis(&self) -> Vec<Abi> {
+        self.get("ro.product.cpu.abilist")
+            .or_else(|| self.get("ro.product.cpu.abi"))
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|abi| abi.parse().ok())
+            .collect()
+    }
+
+    /// Returns `true` if the build is debuggable, e.g. `userdebug` (`ro.debuggable`).
+    pub fn is_debuggable(&self) -> bool {
+        self.get("ro.debuggable") == Some("1")
+    }
+}
+
+impl FromStr for Properties {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut properties = BTreeMap::new();
+        let mut lines = s.lines();
+        while let Some(line) = lines.next() {
+            if line.is_empty() {
+                continue;
+            }
+            let err = || AdbError::Parse {
+                value: line.to_string(),
+                source_type: "&str",
+                target_type: "Properties",
+                source: None,
+            };
+            let (key, value) = line
+                .strip_prefix('[')
+                .and_then(|line| line.split_once("]: ["))
+                .ok_or_else(err)?;
+            let mut value = value.to_string();
+            // Multi-line values continue until a line ending with `]`.
+            while !value.ends_with(']') {
+                value.push('\n');
+                value.push_str(lines.next().ok_or_else(err)?);
+            }
+            value.pop();
+            properties.insert(key.to_string(), value);
+        }
+        Ok(Self(properties))
+    }
+}
+
+impl<'a> IntoIterator for &'a Properties {
+    type Item = (&'a String, &'a String);
+    type IntoIter = btree_map::Iter<'a, String, String>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Device {
+    /// Returns the system properties (`getprop`), cached after the first call.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let properties = device.properties().unwrap();
+    /// println!("{:?} on SDK {:?}", properties.model(), properties.sdk());
+    /// ```
+    pub fn properties(&self) -> Result<Properties, AdbError> {
+        let mut cache = self.properties_cache().lock().unwrap();
+        if let Some(properties) = &*cache {
+            return Ok(properties.clone());
+        }
+        let properties: Properties = self.shell_checked("getprop")?.parse()?;
+        *cache = Some(properties.clone());
+        Ok(properties)
+    }
+
+    /// Forgets the cached properties, e.g. after a reboot or an external `setprop`.
+    pub fn invalidate_properties(&self) {
+        *self.properties_cache().lock().unwrap() = None;
+    }
+
+    /// Sets the system property `key` to `value` (`setprop`), invalidating the cache.
+    ///
+    /// Most properties can only be set by root, and `ro.*` ones only once.
+    pub fn set_prop(&self, key: &str, value: &str) -> Result<(), AdbError> {
+        self.invalidate_properties();
+        self.shell_checked(&format!(
+            "setprop {} {}",
+            shell::quote(key),
+            shell::quote(value)
+        ))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_properties_parse() {
+        let properties: Properties = "\
+[dalvik.vm.heapsize]: [512m]
+[ro.build.version.release]: [14]
+[ro.build.version.sdk]: [34]
+[ro.product.cpu.abi]: [x86_64]
+[ro.product.model]: [sdk_gphone64_x86_64]
+[persist.sys.motd]: [line 1
+line 2]
+[sys.empty]: []
+"
+        .parse()
+        .unwrap();
+        assert_eq!(Some(34), properties.sdk());
+        assert_eq!(Some("14"), properties.release());
+        assert_eq!(Some("sdk_gphone64_x86_64"), properties.model());
+        assert_eq!(vec![Abi::X86_64], properties.abis());
+        assert_eq!(Some("line 1\nline 2"), properties.get("persist.sys.motd"));
+        assert_eq!(Some(""), properties.get("sys.empty"));
+        assert_eq!(None, properties.manufacturer());
+        assert_eq!(7, properties.iter().count());
+        for s in ["ro.build.version.sdk=34", "[a]: [unterminated"] {
+            assert!(s.parse::<Properties>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_abi_parse() {
+        for abi in [
+            "armeabi-v7a",
+            "arm64-v8a",
+            "x86",
+            "x86_64",
+            "riscv64",
+            "mips",
+        ] {
+            assert_eq!(abi, abi.parse::<Abi>().unwrap().to_string());
+        }
+        assert_eq!(Abi::Other("mips".to_string()), "mips".parse().unwrap());
+        assert!("".parse::<Abi>().is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! This module provides device and app tracking in protobuf, decoded with prost:
+//! `host:track-devices-proto-binary` and `track-app`.
+//!
+//! Unlike the text of `host:track-devices-l`, the protobuf listing of a device always tells
+//! how it's connected, and the negotiated and maximum speeds of USB devices. The messages
+//! mirror `adb_host.proto` and `app_processes.proto` of adb, and every message sent by the
+//! server or the device is a full listing, see [`ProtoTracker`].
+
+use std::marker::PhantomData;
+
+use prost::Message;
+
+use crate::device::DeviceState;
+use crate::error::AdbError;
+use crate::protocol;
+use crate::server::{AdbServer, DeviceInfo, DeviceQualifiers, ServerStream};
+use crate::track::AppProcessEvent;
+use crate::version::ServerCapability;
+
+/// The state of a device in [`Device::state`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, prost::Enumeration)]
+#[repr(i32)]
+pub enum ConnectionState {
+    Connecting = 0,
+    Authorizing = 1,
+    Unauthorized = 2,
+    NoPermission = 3,
+    Detached = 4,
+    Offline = 5,
+    Bootloader = 6,
+    Device = 7,
+    Host = 8,
+    Recovery = 9,
+    Sideload = 10,
+    Rescue = 11,
+}
+
+impl From<ConnectionState> for DeviceState {
+    fn from(state: ConnectionState) -> Self {
+        match state {
+            ConnectionState::Connecting => Self::Connecting,
+            ConnectionState::Authorizing => Self::Authorizing,
+            ConnectionState::Unauthorized => Self::Unauthorized,
+            ConnectionState::NoPermission => Self::NoPermissions,
+            ConnectionState::Detached => Self::Detached,
+            ConnectionState::Offline => Self::Offline,
+            ConnectionState::Bootloader => Self::Bootloader,
+            ConnectionState::Device => Self::Device,
+            ConnectionState::Host => Self::Host,
+            ConnectionState::Recovery => Self::Recovery,
+            ConnectionState::Sideload => Self::Sideload,
+            ConnectionState::Rescue => Self::Rescue,
+        }
+    }
+}
+
+/// How a device is connected to the adb server, in [`Device::connection_type`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, prost::Enumeration)]
+#[repr(i32)]
+pub enum ConnectionType {
+    Unknown = 0,
+    Usb = 1,
+    /// A TCP connection, e.g. wireless debugging or an emulator.
+    Socket = 2,
+}
+
+/// A device listed by `host:track-devices-proto-binary`.
+///
+/// Strings are empty and numbers are 0 when the server doesn't know them. The
+/// [`state`](Self::state) and [`connection_type`](Self::connection_type) accessors decode
+/// the raw enum fields, unknown values decoding as the default variant.
+#[derive(Clone, Eq, PartialEq, Hash, Message)]
+pub struct Device {
+    #[prost(string, tag = "1")]
+    pub serial: String,
+    #[prost(enumeration = "ConnectionState", tag = "2")]
+    pub state: i32,
+    /// The USB bus path of the device, e.g. `1-1`.
+    #[prost(string, tag = "3")]
+    pub bus_address: String,
+    #[prost(string, tag = "4")]
+    pub product: String,
+    #[prost(string, tag = "5")]
+    pub model: String,
+    #[prost(string, tag = "6")]
+    pub device: String,
+    #[prost(enumeration = "ConnectionType", tag = "7")]
+    pub connection_type: i32,
+    /// The negotiated USB speed in Mbit/s.
+    #[prost(int64, tag = "8")]
+    pub negotiated_speed: i64,
+    /// The maximum USB speed supported by the device in Mbit/s.
+    #[prost(int64, tag = "9")]
+    pub max_speed: i64,
+    #[prost(int64, tag = "10")]
+    pub transport_id: i64,
+}
+
+impl From<&Device> for DeviceInfo {
+    /// Converts the device as if it was listed by `host:devices-l`, dropping the connection
+    /// type.
+    fn from(device: &Device) -> Self {
+        let string = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        let number = |n: i64| u64::try_from(n).ok().filter(|&n| n > 0);
+        Self {
+            serial: device.serial.clone(),
+            state: device.state().into(),
+            qualifiers: DeviceQualifiers {
+                usb: string(&device.bus_address),
+                product: string(&device.product),
+                model: string(&device.model),
+                device: string(&device.device),
+                transport_id: number(device.transport_id),
+                negotiated_speed: number(device.negotiated_speed),
+                max_speed: number(device.max_speed),
+            },
+        }
+    }
+}
+
+/// The devices known to the server, sent by `host:track-devices-proto-binary`.
+#[derive(Clone, Eq, PartialEq, Hash, Message)]
+pub struct Devices {
+    #[prost(message, repeated, tag = "1")]
+    pub device: Vec<Device>,
+}
+
+/// A debuggable or profileable process listed by `track-app`.
+#[derive(Clone, Eq, PartialEq, Hash, Message)]
+pub struct ProcessEntry {
+    #[prost(int64, tag = "1")]
+    pub pid: i64,
+    #[prost(bool, tag = "2")]
+    pub debuggable: bool,
+    #[prost(bool, tag = "3")]
+    pub profileable: bool,
+    /// The ABI of the process, e.g. `arm64`.
+    #[prost(string, tag = "4")]
+    pub architecture: String,
+}
+
+impl From<&ProcessEntry> for AppProcessEvent {
+    /// Converts the process into an event of an alive process, without its package name.
+    fn from(process: &ProcessEntry) -> Self {
+        Self {
+            pid: process.pid,
+            package: None,
+            debuggable: process.debuggable,
+            profileable: process.profileable,
+            arch: process.architecture.clone(),
+            alive: true,
+        }
+    }
+}
+
+/// The debuggable and profileable processes of a device, sent by `track-app`.
+#[derive(Clone, Eq, PartialEq, Hash, Message)]
+pub struct AppProcesses {
+    #[prost(message, repeated, tag = "1")]
+    pub process: Vec<ProcessEntry>,
+}
+
+/// Decodes a protobuf message sent by the server or a device.
+pub fn decode<M: Message + Default>(bytes: &[u8]) -> Result<M, AdbError> {
+    M::decode(bytes).map_err(|e| AdbError::Protocol {
+        code: None,
+        message: format!("malformed protobuf message: {}", e),
+    })
+}
+
+/// An iterator of the listings sent by a tracking service in protobuf, created by
+/// [`AdbServer::track_devices_proto`] and [`crate::device::Device::track_app_proto`].
+///
+/// Every item is the full listing at the time of a change, the first one the listing when
+/// tracking starts. The iterator ends after the first error.
+#[derive(Debug)]
+pub struct ProtoTracker<M> {
+    stream: ServerStream,
+    done: bool,
+    message: PhantomData<fn() -> M>,
+}
+
+impl<M> ProtoTracker<M> {
+    fn new(stream: ServerStream) -> Self {
+        Self {
+            stream,
+            done: false,
+            message: PhantomData,
+        }
+    }
+}
+
+impl<M: Message + Default> Iterator for ProtoTracker<M> {
+    type Item = Result<M, AdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result =
+            protocol::read_length_prefixed(&mut self.stream).and_then(|message| decode(&message));
+        self.done = result.is_err();
+        Some(result)
+    }
+}
+
+impl AdbServer {
+    /// Tracks the devices known to the server in protobuf
+    /// (`host:track-devices-proto-binary`).
+    ///
+    /// Fails with [`AdbError::ServerUnsupported`] if the server predates it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::proto::ConnectionType;
+    /// use adb::server::AdbServer;
+    ///
+    /// for devices in AdbServer::default().track_devices_proto().unwrap() {
+    ///     for device in devices.unwrap().device {
+    ///         if device.connection_type() == ConnectionType::Usb {
+    ///             println!("{}: {} Mbit/s", device.serial, device.negotiated_speed);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn track_devices_proto(&self) -> Result<ProtoTracker<Devices>, AdbError> {
+        let capability = ServerCapability::TrackDevicesProto;
+        self.require(capability)?;
+        Ok(ProtoTracker::new(self.open(capability.service())?))
+    }
+}
+
+impl crate::device::Device {
+    /// Tracks the debuggable and profileable processes of the device (`track-app`), as the
+    /// listings sent by the device.
+    ///
+    /// Unlike [`Self::track_app`], this doesn't diff the listings, nor read the package names
+    /// of the processes. Requires Android 11 or later.
+    pub fn track_app_proto(&self) -> Result<ProtoTracker<AppProcesses>, AdbError> {
+        Ok(ProtoTracker::new(self.open("track-app")?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_devices() {
+        let devices = Devices {
+            device: vec![Device {
+                serial: "0123456789ABCDEF".to_string(),
+                state: ConnectionState::Device as i32,
+                bus_address: "1-1".to_string(),
+                model: "Pixel_7".to_string(),
+                connection_type: ConnectionType::Usb as i32,
+                negotiated_speed: 480,
+                max_speed: 5000,
+                transport_id: 3,
+                ..Device::default()
+            }],
+        };
+        let decoded: Devices = decode(&devices.encode_to_vec()).unwrap();
+        assert_eq!(devices, decoded);
+        let device = &decoded.device[0];
+        assert_eq!(ConnectionType::Usb, device.connection_type());
+        let info = DeviceInfo::from(device);
+        assert_eq!(
+            "0123456789ABCDEF\tdevice usb:1-1 model:Pixel_7 transport_id:3 \
+            negotiated_speed:480 max_speed:5000",
+            info.to_string()
+        );
+        assert!(info.qualifiers.slow_usb_warning().is_some());
+
+        // An emulator, with an unknown state and the optional fields unset.
+        let emulator = Device {
+            serial: "emulator-5554".to_string(),
+            state: 42,
+            connection_type: ConnectionType::Socket as i32,
+            ..Device::default()
+        };
+        let info = DeviceInfo::from(&emulator);
+        assert_eq!(DeviceState::Connecting, info.state);
+        assert_eq!(DeviceQualifiers::default(), info.qualifiers);
+        assert!(decode::<Devices>(b"\x0a\x05\x0a").is_err());
+    }
+
+    #[test]
+    fn test_decode_app_processes() {
+        // The same listing as in the tests of `decode_app_processes`.
+        let mut message = b"\x0a\x0c\x08\x90\x4e\x10\x01\x22\x05arm64".to_vec();
+        message.extend_from_slice(b"\x0a\x0f\x08\x01\x18\x01\x48\x07\x51");
+        message.extend_from_slice(&[0; 8]);
+        let processes: AppProcesses = decode(&message).unwrap();
+        let events: Vec<_> = processes
+            .process
+            .iter()
+            .map(AppProcessEvent::from)
+            .collect();
+        assert_eq!(
+            crate::track::decode_app_processes(&message).unwrap(),
+            events
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! This module provides the framing of the adb host protocol.
+//!
+//! A request is the service name prefixed by its length as 4 hexadecimal digits.
+//! The server replies with `OKAY` on success, or `FAIL` followed by a length-prefixed message.
+//!
+//! The `decode_*` functions operate on byte slices and are shared by the blocking and
+//! async clients.
+
+use std::io::{Read, Write};
+
+use crate::error::AdbError;
+
+/// The result of decoding a message from the start of a byte slice.
+///
+/// Decoders never consume input themselves, so the same decoder serves blocking and
+/// async readers, and can be fed arbitrary bytes by fuzzers.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Decoded<T> {
+    /// A complete message of `length` bytes was decoded.
+    Complete { value: T, length: usize },
+    /// The slice is too short, at least `needed` bytes in total are required.
+    Incomplete { needed: usize },
+}
+
+/// Reads exactly as many bytes as `decode` needs to decode a complete message.
+pub(crate) fn read_decoded<R, T, F>(reader: &mut R, decode: F) -> Result<T, AdbError>
+where
+    R: Read,
+    F: Fn(&[u8]) -> Result<Decoded<T>, AdbError>,
+{
+    let mut buffer = Vec::new();
+    loop {
+        match decode(&buffer)? {
+            Decoded::Complete { value, .. } => return Ok(value),
+            Decoded::Incomplete { needed } => {
+                let filled = buffer.len();
+                buffer.resize(needed, 0);
+                reader.read_exact(&mut buffer[filled..])?;
+            }
+        }
+    }
+}
+
+/// The status the adb server replies to a request with.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Status {
+    /// `OKAY`
+    Okay,
+    /// `FAIL` followed by a length-prefixed message.
+    Fail(String),
+}
+
+/// Encodes a service request, e.g. `host:version` into `000chost:version`.
+pub fn encode_request(service: &str) -> Vec<u8> {
+    let mut request = format!("{:04x}", service.len()).into_bytes();
+    request.extend_from_slice(service.as_bytes());
+    request
+}
+
+/// Parses a length written as 4 hexadecimal digits.
+pub fn parse_length(bytes: &[u8; 4]) -> Result<usize, AdbError> {
+    bytes
+        .iter()
+        .all(u8::is_ascii_hexdigit)
+        .then(|| std::str::from_utf8(bytes).ok())
+        .flatten()
+        .and_then(|s| usize::from_str_radix(s, 16).ok())
+        .ok_or_else(|| AdbError::Protocol {
+            code: None,
+            message: format!("invalid length `{}`", String::from_utf8_lossy(bytes)),
+        })
+}
+
+/// Decodes a payload prefixed by its length as 4 hexadecimal digits.
+pub fn decode_length_prefixed(bytes: &[u8]) -> Result<Decoded<Vec<u8>>, AdbError> {
+    let Some(length) = bytes.get(..4) else {
+        return Ok(Decoded::Incomplete { needed: 4 });
+    };
+    let needed = 4 + parse_length(length.try_into().unwrap())?;
+    Ok(match bytes.get(4..needed) {
+        Some(payload) => Decoded::Complete {
+            value: payload.to_vec(),
+            length: needed,
+        },
+        None => Decoded::Incomplete { needed },
+    })
+}
+
+/// Decodes the `OKAY` or `FAIL` status of a request.
+pub fn decode_status(bytes: &[u8]) -> Result<Decoded<Status>, AdbError> {
+    let Some(status) = bytes.get(..4) else {
+        return Ok(Decoded::Incomplete { needed: 4 });
+    };
+    match status {
+        b"OKAY" => Ok(Decoded::Complete {
+            value: Status::Okay,
+            length: 4,
+        }),
+        b"FAIL" => Ok(match decode_length_prefixed(&bytes[4..])? {
+            Decoded::Complete { value, length } => Decoded::Complete {
+                value: Status::Fail(String::from_utf8_lossy(&value).into_owned()),
+                length: 4 + length,
+            },
+            Decoded::Incomplete { needed } => Decoded::Incomplete { needed: 4 + needed },
+        }),
+        _ => Err(AdbError::Protocol {
+            code: Some(u32::from_le_bytes(status.try_into().unwrap())),
+            message: format!("unexpected status `{}`", String::from_utf8_lossy(status)),
+        }),
+    }
+}
+
+/// Sends a service request.
+pub(crate) fn send_request<W: Write>(writer: &mut W, service: &str) -> Result<(), AdbError> {
+    writer.write_all(&encode_request(service))?;
+    Ok(())
+}
+
+/// Turns a `FAIL` status into an error.
+pub(crate) fn check_status(status: Status) -> Result<(), AdbError> {
+    match status {
+        Status::Okay => Ok(()),
+        Status::Fail(message) => Err(AdbError::from_fail(message)),
+    }
+}
+
+/// Reads the `OKAY` or `FAIL` status of a request.
+pub(crate) fn read_status<R: Read>(reader: &mut R) -> Result<(), AdbError> {
+    check_status(read_decoded(reader, decode_status)?)
+}
+
+/// Reads a payload prefixed by its length as 4 hexadecimal digits.
+pub(crate) fn read_length_prefixed<R: Read>(reader: &mut R) -> Result<Vec<u8>, AdbError> {
+    read_decoded(reader, decode_length_prefixed)
+}
+
+/// Converts a payload to a string.
+pub(crate) fn into_string(payload: Vec<u8>) -> Result<String, AdbError> {
+    String::from_utf8(payload).map_err(|e| AdbError::Parse {
+        value: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        source_type: "Vec<u8>",
+        target_type: "String",
+        source: Some(Box::new(e)),
+    })
+}
+
+/// Reads a length-prefixed payload as a string.
+pub(crate) fn read_string<R: Read>(reader: &mut R) -> Result<String, AdbError> {
+    into_string(read_length_prefixed(reader)?)
+}
+
+/// Quotes `arg` for the device shell running the `shell:` and `exec:` services, for the
+/// features without the `shell` module, see `shell::quote`.
+#[cfg(any(feature = "shell", feature = "sync"))]
+pub(crate) fn quote_arg(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_request() {
+        assert_eq!(b"000chost:version", &encode_request("host:version")[..]);
+        assert_eq!(b"0000", &encode_request("")[..]);
+    }
+
+    #[test]
+    fn test_parse_length() {
+        assert_eq!(0x29, parse_length(b"0029").unwrap());
+        assert_eq!(0xffff, parse_length(b"ffff").unwrap());
+        for s in [b"zzzz", b"-001", b"+001", b" 001"] {
+            assert!(parse_length(s).is_err());
+        }
+    }
+
+    #[test]
+    fn test_read_status() {
+        assert!(read_status(&mut Cursor::new(b"OKAY")).is_ok());
+        match read_status(&mut Cursor::new(b"FAIL0006failed")) {
+            Err(AdbError::Server { message }) => assert_eq!("failed", message),
+            other => panic!("{:?}", other),
+        }
+        for s in [&b"OKA"[..], b"FAIL", b"FAIL0006fail", b"WHAT"] {
+            assert!(read_status(&mut Cursor::new(s)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_decode_status() {
+        assert_eq!(
+            Decoded::Incomplete { needed: 4 },
+            decode_status(b"OK").unwrap()
+        );
+        assert_eq!(
+            Decoded::Complete {
+                value: Status::Okay,
+                length: 4
+            },
+            decode_status(b"OKAYtrailing").unwrap()
+        );
+        assert_eq!(
+            Decoded::Incomplete { needed: 8 },
+            decode_status(b"FAIL00").unwrap()
+        );
+        assert_eq!(
+            Decoded::Incomplete { needed: 10 },
+            decode_status(b"FAIL0002a").unwrap()
+        );
+        assert_eq!(
+            Decoded::Complete {
+                value: Status::Fail("ab".to_string()),
+                length: 10
+            },
+            decode_status(b"FAIL0002ab").unwrap()
+        );
+        assert!(decode_status(b"WHAT").is_err());
+        assert!(decode_status(b"FAILxxxx").is_err());
+    }
+
+    #[test]
+    fn test_read_string() {
+        assert_eq!("0029", read_string(&mut Cursor::new(b"00040029")).unwrap());
+        assert!(read_string(&mut Cursor::new(b"0004002")).is_err());
+        assert!(read_string(&mut Cursor::new(b"0002\xff\xfe")).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! This module sets the global HTTP proxy of the device, e.g. an intercepting proxy on the
+//! host made reachable with [`Device::reverse`].
+//!
+//! The proxy is the `http_proxy` global setting, `<host>:<port>`. Deleting the setting only
+//! takes effect after a reboot, so clearing it sets `:0` instead, which reads back as no
+//! proxy like a missing setting (`null`). A [`ProxyGuard`] sets the proxy for the duration of
+//! a test and restores the previous one when dropped.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell::quote;
+use crate::socket::Tcp;
+
+/// The value of `http_proxy` clearing the proxy.
+const NO_PROXY: &str = ":0";
+
+/// Returns the `http_proxy` value of `proxy`, the loopback address if it has no IP.
+fn format_http_proxy(proxy: &Tcp) -> Result<String, AdbError> {
+    let port = proxy
+        .port
+        .filter(|&port| port != 0)
+        .ok_or_else(|| AdbError::Parse {
+            value: proxy.to_string(),
+            source_type: "Tcp",
+            target_type: "http_proxy",
+            source: None,
+        })?;
+    Ok(match proxy.ip.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)) {
+        IpAddr::V4(ip) => format!("{}:{}", ip, port),
+        IpAddr::V6(ip) => format!("[{}]:{}", ip, port),
+    })
+}
+
+/// Parses the `http_proxy` value `value`, `None` if there's no proxy.
+///
+/// Fails on hosts other than IP addresses.
+fn parse_http_proxy(value: &str) -> Result<Option<Tcp>, AdbError> {
+    let value = value.trim();
+    if matches!(value, "" | "null" | NO_PROXY) {
+        return Ok(None);
+    }
+    let error = || AdbError::Parse {
+        value: value.to_string(),
+        source_type: "&str",
+        target_type: "Tcp",
+        source: None,
+    };
+    let (host, port) = value.rsplit_once(':').ok_or_else(error)?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    let ip = host.parse().map_err(|_| error())?;
+    let port = port.parse().map_err(|_| error())?;
+    Ok(Some(Tcp::new(ip, port)))
+}
+
+impl Device {
+    /// Returns the raw `http_proxy` value, `None` if there's no proxy.
+    fn http_proxy_value(&self) -> Result<Option<String>, AdbError> {
+        let value = self.shell_checked("settings get global http_proxy")?;
+        let value = value.trim();
+        Ok((!matches!(value, "" | "null" | NO_PROXY)).then(|| value.to_string()))
+    }
+
+    /// Sets the raw `http_proxy` value, and checks that it reads back.
+    fn put_http_proxy_value(&self, value: &str) -> Result<(), AdbError> {
+        self.shell_checked(&format!("settings put global http_proxy {}", quote(value)))?;
+        let expected = (value != NO_PROXY).then(|| value.to_string());
+        let actual = self.http_proxy_value()?;
+        if actual != expected {
+            return Err(AdbError::Server {
+                message: format!(
+                    "http_proxy is `{}` after setting `{}`",
+                    actual.as_deref().unwrap_or(NO_PROXY),
+                    value
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the global HTTP proxy, `None` if there's none (`settings get global
+    /// http_proxy`).
+    ///
+    /// Fails if the proxy host isn't an IP address.
+    pub fn http_proxy(&self) -> Result<Option<Tcp>, AdbError> {
+        match self.http_proxy_value()? {
+            Some(value) => parse_http_proxy(&value),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the global HTTP proxy, and checks that it reads back (`settings put global
+    /// http_proxy`).
+    ///
+    /// A proxy without IP is the loopback address of the device. The port is required.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    /// use adb::socket::Tcp;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// // Reach the proxy listening on port 8080 of the host.
+    /// device.reverse(Tcp::from_port(8080), Tcp::from_port(8080)).unwrap();
+    /// device.set_http_proxy(&Tcp::from_port(8080)).unwrap();
+    /// ```
+    pub fn set_http_proxy(&self, proxy: &Tcp) -> Result<(), AdbError> {
+        self.put_http_proxy_value(&format_http_proxy(proxy)?)
+    }
+
+    /// Clears the global HTTP proxy, and checks that it reads back.
+    pub fn clear_http_proxy(&self) -> Result<(), AdbError> {
+        self.put_http_proxy_value(NO_PROXY)
+    }
+
+    /// Sets the global HTTP proxy like [`Device::set_http_proxy`], returning a guard
+    /// restoring the previous one.
+    pub fn http_proxy_guard(&self, proxy: &Tcp) -> Result<ProxyGuard, AdbError> {
+        let value = format_http_proxy(proxy)?;
+        let guard = ProxyGuard {
+            device: self.clone(),
+            previous: Some(self.http_proxy_value()?),
+        };
+        self.put_http_proxy_value(&value)?;
+        Ok(guard)
+    }
+}
+
+/// A guard setting the global HTTP proxy, created by [`Device::http_proxy_guard`].
+///
+/// The previous proxy is restored when the guard is dropped, ignoring errors, or by
+/// [`ProxyGuard::restore`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::server::AdbServer;
+/// use adb::socket::Tcp;
+///
+/// let device = AdbServer::default().any_device();
+/// let guard = device.http_proxy_guard(&Tcp::from_port(8080)).unwrap();
+/// // Run the test through the proxy.
+/// guard.restore().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ProxyGuard {
+    device: Device,
+    /// The previous raw `http_proxy` value, `None` once restored.
+    previous: Option<Option<String>>,
+}
+
+impl ProxyGuard {
+    /// Restores the previous proxy.
+    pub fn restore(mut self) -> Result<(), AdbError> {
+        self.restore_previous()
+    }
+
+    fn restore_previous(&mut self) -> Result<(), AdbError> {
+        match self.previous.take() {
+            Some(previous) => self
+                .device
+                .put_http_proxy_value(previous.as_deref().unwrap_or(NO_PROXY)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ProxyGuard {
+    fn drop(&mut self) {
+        let _ = self.restore_previous();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+
+    #[test]
+    fn test_format_http_proxy() {
+        assert_eq!(
+            "127.0.0.1:8080",
+            format_http_proxy(&Tcp::from_port(8080)).unwrap()
+        );
+        assert_eq!(
+            "10.0.2.2:3128",
+            format_http_proxy(&Tcp::new(Ipv4Addr::new(10, 0, 2, 2).into(), 3128)).unwrap()
+        );
+        assert_eq!(
+            "[::1]:8080",
+            format_http_proxy(&Tcp::new(Ipv6Addr::LOCALHOST.into(), 8080)).unwrap()
+        );
+        assert!(format_http_proxy(&Tcp::from_ipv4(Ipv4Addr::LOCALHOST)).is_err());
+        assert!(format_http_proxy(&Tcp::from_port(0)).is_err());
+    }
+
+    #[test]
+    fn test_parse_http_proxy() {
+        for value in ["null\n", ":0\n", ""] {
+            assert_eq!(None, parse_http_proxy(value).unwrap());
+        }
+        assert_eq!(
+            Some(Tcp::new(Ipv4Addr::new(10, 0, 2, 2).into(), 3128)),
+            parse_http_proxy("10.0.2.2:3128\n").unwrap()
+        );
+        assert_eq!(
+            Some(Tcp::new(Ipv6Addr::LOCALHOST.into(), 8080)),
+            parse_http_proxy("[::1]:8080").unwrap()
+        );
+        assert!(parse_http_proxy("proxy.example.com:3128").is_err());
+        assert!(parse_http_proxy("10.0.2.2").is_err());
+    }
+}
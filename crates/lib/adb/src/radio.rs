@@ -0,0 +1,191 @@
+//! This module provides the state of the Bluetooth and NFC radios, and toggles them.
+//!
+//! Toggling a radio only starts the transition, so [`Device::set_radio_enabled`] then polls
+//! the state in `dumpsys` until it's [`RadioState::On`] or [`RadioState::Off`]:
+//!
+//! - Bluetooth: `cmd bluetooth_manager enable` (Android 13 and later), or `svc bluetooth`,
+//!   and the `state:` line of `dumpsys bluetooth_manager`.
+//! - NFC: `svc nfc`, and the `mState=` line of `dumpsys nfc`.
+
+use std::fmt::{Display, Formatter};
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::wait::{PollOptions, Predicate};
+
+/// A radio of a device.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Radio {
+    Bluetooth,
+    Nfc,
+}
+
+impl Display for Radio {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Bluetooth => "Bluetooth",
+            Self::Nfc => "NFC",
+        })
+    }
+}
+
+/// The state of a [`Radio`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum RadioState {
+    Off,
+    TurningOn,
+    On,
+    TurningOff,
+}
+
+impl Display for RadioState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::TurningOn => "turning on",
+            Self::On => "on",
+            Self::TurningOff => "turning off",
+        })
+    }
+}
+
+/// Parses the state of `dumpsys bluetooth_manager`, e.g. `  state: ON`.
+///
+/// Bluetooth Low Energy only (`BLE_ON`), for scans while Bluetooth is off, counts as off.
+fn parse_bluetooth_state(dump: &str) -> Option<RadioState> {
+    let state = dump
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("state:"))?;
+    match state.trim() {
+        "OFF" | "BLE_ON" => Some(RadioState::Off),
+        "TURNING_ON" | "BLE_TURNING_ON" => Some(RadioState::TurningOn),
+        "ON" => Some(RadioState::On),
+        "TURNING_OFF" | "BLE_TURNING_OFF" => Some(RadioState::TurningOff),
+        _ => None,
+    }
+}
+
+/// Parses the state of `dumpsys nfc`, e.g. `mState=on`.
+fn parse_nfc_state(dump: &str) -> Option<RadioState> {
+    let state = dump
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("mState="))?;
+    match state.trim() {
+        "off" => Some(RadioState::Off),
+        "turning on" => Some(RadioState::TurningOn),
+        "on" => Some(RadioState::On),
+        "turning off" => Some(RadioState::TurningOff),
+        _ => None,
+    }
+}
+
+/// The radio is in the state, e.g. Bluetooth is on.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct RadioIs(pub Radio, pub RadioState);
+
+impl Predicate for RadioIs {
+    fn check(&self, device: &Device) -> Result<bool, AdbError> {
+        Ok(device.radio_state(self.0)? == self.1)
+    }
+
+    fn description(&self) -> String {
+        format!("{} {}", self.0, self.1)
+    }
+}
+
+impl Device {
+    /// Returns the state of `radio`.
+    pub fn radio_state(&self, radio: Radio) -> Result<RadioState, AdbError> {
+        let (service, parse): (_, fn(&str) -> _) = match radio {
+            Radio::Bluetooth => ("bluetooth_manager", parse_bluetooth_state),
+            Radio::Nfc => ("nfc", parse_nfc_state),
+        };
+        let dump = self.shell_checked(&format!("dumpsys {}", service))?;
+        parse(&dump).ok_or_else(|| AdbError::Server {
+            message: format!("no {} state in `dumpsys {}`", radio, service),
+        })
+    }
+
+    /// Turns `radio` on (`enabled` is `true`) or off, and waits for the transition to
+    /// complete, failing with [`AdbError::Timeout`] after the timeout of `options`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::radio::Radio;
+    /// use adb::server::AdbServer;
+    /// use adb::wait::PollOptions;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// device
+    ///     .set_radio_enabled(Radio::Bluetooth, false, &PollOptions::new())
+    ///     .unwrap();
+    /// ```
+    pub fn set_radio_enabled(
+        &self,
+        radio: Radio,
+        enabled: bool,
+        options: &PollOptions,
+    ) -> Result<(), AdbError> {
+        let action = if enabled { "enable" } else { "disable" };
+        match radio {
+            Radio::Bluetooth => {
+                // `svc bluetooth` is a no-op on Android 13 and later.
+                let output = self.shell(&format!("cmd bluetooth_manager {}", action))?;
+                if !output.success() || !output.stderr.is_empty() {
+                    self.shell_checked(&format!("svc bluetooth {}", action))?;
+                }
+            }
+            Radio::Nfc => {
+                self.shell_checked(&format!("svc nfc {}", action))?;
+            }
+        }
+        let state = if enabled {
+            RadioState::On
+        } else {
+            RadioState::Off
+        };
+        self.wait_until(&RadioIs(radio, state), options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bluetooth_state() {
+        let dump = "\
+Bluetooth Status
+  enabled: true
+  state: TURNING_ON
+  address: 3C:28:6D:00:00:00
+  name: Pixel 7
+";
+        assert_eq!(Some(RadioState::TurningOn), parse_bluetooth_state(dump));
+        assert_eq!(
+            Some(RadioState::Off),
+            parse_bluetooth_state("  enabled: false\n  state: BLE_ON\n")
+        );
+        assert_eq!(
+            None,
+            parse_bluetooth_state("Can't find service: bluetooth_manager")
+        );
+    }
+
+    #[test]
+    fn test_parse_nfc_state() {
+        let dump = "\
+mState=turning off
+mIsZeroClickRequested=false
+mScreenState=ON_UNLOCKED
+";
+        assert_eq!(Some(RadioState::TurningOff), parse_nfc_state(dump));
+        assert_eq!(Some(RadioState::On), parse_nfc_state("mState=on\n"));
+        assert_eq!(None, parse_nfc_state(""));
+        assert_eq!(
+            "NFC turning off",
+            RadioIs(Radio::Nfc, RadioState::TurningOff).description()
+        );
+    }
+}
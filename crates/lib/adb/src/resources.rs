@@ -0,0 +1,672 @@
+//! This module reads the string resources of the installed applications, to assert on
+//! localized UI text without hardcoding the translations in tests.
+//!
+//! The resource table `resources.arsc` is extracted from the base APK on the device with
+//! `unzip -p`, which Android ships since version 8, and parsed on the host. The table is a
+//! tree of chunks, each starting with a `type`, `header_size` and `size` header:
+//!
+//! - a global string pool holding the values of the string resources,
+//! - a package chunk per package, holding the pools of the type and entry names, and a
+//!   type chunk per type and configuration, e.g. `string` in `fr-rFR`, whose entries point
+//!   at the values.
+//!
+//! Only the locale of the configurations is considered: a locale falls back to its
+//! language, then to the default configuration, like the resource resolution of Android.
+
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_TABLE_TYPE: u16 = 0x0002;
+const RES_TABLE_PACKAGE_TYPE: u16 = 0x0200;
+const RES_TABLE_TYPE_TYPE: u16 = 0x0201;
+
+/// The strings of the pool are UTF-8 rather than UTF-16.
+const UTF8_FLAG: u32 = 0x100;
+/// The entries of the type chunk are `(index, offset / 4)` pairs.
+const FLAG_SPARSE: u8 = 0x01;
+/// The offsets of the type chunk are `u16`s counting 4 bytes, Android 14 and later.
+const FLAG_OFFSET16: u8 = 0x02;
+/// The entry is a bag of values, e.g. a style or a plural.
+const FLAG_COMPLEX: u16 = 0x0001;
+/// The entry holds its value in its header, Android 14 and later.
+const FLAG_COMPACT: u16 = 0x0008;
+/// An entry missing from the configuration.
+const NO_ENTRY: u32 = u32::MAX;
+
+const TYPE_REFERENCE: u8 = 0x01;
+const TYPE_STRING: u8 = 0x03;
+
+/// The maximum number of references followed to resolve a value.
+const MAX_REFERENCES: usize = 8;
+
+/// A locale of the resource configurations, e.g. `fr-FR`.
+///
+/// # Examples
+///
+/// ```
+/// use adb::resources::Locale;
+///
+/// let locale: Locale = "fr-rFR".parse().unwrap();
+/// assert_eq!(Locale::new("fr").region("FR"), locale);
+/// assert_eq!("fr-FR", locale.to_string());
+/// assert_eq!(locale, "fr_FR".parse().unwrap());
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Locale {
+    language: String,
+    region: Option<String>,
+}
+
+impl Locale {
+    /// Creates the locale of `language`, e.g. `fr`.
+    pub fn new(language: impl Into<String>) -> Self {
+        Self {
+            language: language.into().to_ascii_lowercase(),
+            region: None,
+        }
+    }
+
+    /// Sets the region, e.g. `FR`.
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into().to_ascii_uppercase());
+        self
+    }
+
+    pub fn get_language(&self) -> &str {
+        &self.language
+    }
+
+    pub fn get_region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.region {
+            Some(region) => write!(f, "{}-{}", self.language, region),
+            None => f.write_str(&self.language),
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = AdbError;
+
+    /// Parses `<language>[-<region>]`, also with `_` or the `-r` of resource directories.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (language, region) = match s.split_once(['-', '_']) {
+            Some((language, region)) => {
+                let region = match region.strip_prefix('r') {
+                    Some(stripped) if stripped.len() == 2 => stripped,
+                    _ => region,
+                };
+                (language, Some(region))
+            }
+            None => (s, None),
+        };
+        let valid =
+            |s: &str| (2..=3).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric());
+        if !valid(language) || !region.map_or(true, valid) {
+            return Err(AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Locale",
+                source: None,
+            });
+        }
+        let locale = Self::new(language);
+        Ok(match region {
+            Some(region) => locale.region(region),
+            None => locale,
+        })
+    }
+}
+
+/// A value of an entry, `Res_value`.
+#[derive(Copy, Clone, Debug)]
+struct Value {
+    data_type: u8,
+    data: u32,
+}
+
+/// A type chunk, the entries of a type in a configuration.
+#[derive(Debug)]
+struct TypeChunk {
+    id: u8,
+    /// The language and the region of the configuration, empty in the default one.
+    language: String,
+    region: String,
+    /// The entries of the configuration: index, key in the key pool, and value, `None`
+    /// for bags.
+    entries: Vec<(u16, u32, Option<Value>)>,
+}
+
+impl TypeChunk {
+    fn entry(&self, index: u16) -> Option<Option<Value>> {
+        self.entries
+            .iter()
+            .find(|(i, _, _)| *i == index)
+            .map(|(_, _, value)| *value)
+    }
+
+    /// Ranks the configuration for `locale`: 3 for the same locale, 2 for the same
+    /// language without region, 1 for the default configuration, and 0 if it doesn't
+    /// apply.
+    fn rank(&self, locale: Option<&Locale>) -> u8 {
+        match locale {
+            _ if self.language.is_empty() => 1,
+            None => 0,
+            Some(locale) if locale.language != self.language => 0,
+            Some(locale) => match (locale.region.as_deref(), self.region.as_str()) {
+                (_, "") => 2,
+                (Some(region), config) if region == config => 3,
+                _ => 0,
+            },
+        }
+    }
+}
+
+/// A package of a resource table.
+#[derive(Debug)]
+struct Package {
+    id: u8,
+    name: String,
+    type_names: Vec<String>,
+    key_names: Vec<String>,
+    types: Vec<TypeChunk>,
+}
+
+/// The resource table of an application, `resources.arsc`.
+#[derive(Debug)]
+pub struct ResourceTable {
+    strings: Vec<String>,
+    packages: Vec<Package>,
+}
+
+impl ResourceTable {
+    /// Parses a resource table.
+    pub fn parse(data: &[u8]) -> Result<Self, AdbError> {
+        let (kind, header_size, size) = chunk_header(data, 0)?;
+        if kind != RES_TABLE_TYPE {
+            return Err(malformed(format!(
+                "not a resource table, chunk type {:#06x}",
+                kind
+            )));
+        }
+        let end = size.min(data.len());
+        let mut table = Self {
+            strings: Vec::new(),
+            packages: Vec::new(),
+        };
+        let mut offset = header_size;
+        while offset < end {
+            let (kind, _, size) = chunk_header(data, offset)?;
+            match kind {
+                RES_STRING_POOL_TYPE => table.strings = string_pool(data, offset)?,
+                RES_TABLE_PACKAGE_TYPE => table.packages.push(package(data, offset)?),
+                _ => {}
+            }
+            offset += size;
+        }
+        Ok(table)
+    }
+
+    /// Returns the names of the packages of the table.
+    pub fn packages(&self) -> impl Iterator<Item = &str> {
+        self.packages.iter().map(|package| package.name.as_str())
+    }
+
+    /// Returns the string resource `name`, e.g. `app_name` for `@string/app_name`, in
+    /// `locale`, or in the default configuration if `None`.
+    ///
+    /// The value falls back to the language of `locale`, then to the default
+    /// configuration. References to other strings are followed.
+    pub fn string(&self, name: &str, locale: Option<&Locale>) -> Option<String> {
+        self.packages.iter().find_map(|package| {
+            let type_id = package.type_names.iter().position(|n| n == "string")? + 1;
+            let key = package.key_names.iter().position(|n| n == name)? as u32;
+            let value = package
+                .types
+                .iter()
+                .filter(|chunk| usize::from(chunk.id) == type_id && chunk.rank(locale) > 0)
+                .filter_map(|chunk| {
+                    let (_, _, value) = chunk.entries.iter().find(|(_, k, _)| *k == key)?;
+                    Some((chunk.rank(locale), (*value)?))
+                })
+                .max_by_key(|(rank, _)| *rank)
+                .map(|(_, value)| value)?;
+            self.resolve(package, value, locale)
+        })
+    }
+
+    /// Returns the value of the entry `index` of the type `type_id` in the best
+    /// configuration for `locale`.
+    fn lookup(
+        &self,
+        package: &Package,
+        type_id: u8,
+        index: u16,
+        locale: Option<&Locale>,
+    ) -> Option<Value> {
+        package
+            .types
+            .iter()
+            .filter(|chunk| chunk.id == type_id && chunk.rank(locale) > 0)
+            .filter_map(|chunk| Some((chunk.rank(locale), chunk.entry(index)??)))
+            .max_by_key(|(rank, _)| *rank)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the string of `value`, following references within `package`.
+    fn resolve(
+        &self,
+        package: &Package,
+        mut value: Value,
+        locale: Option<&Locale>,
+    ) -> Option<String> {
+        for _ in 0..MAX_REFERENCES {
+            match value.data_type {
+                TYPE_STRING => return self.strings.get(value.data as usize).cloned(),
+                TYPE_REFERENCE if (value.data >> 24) as u8 == package.id => {
+                    let type_id = (value.data >> 16) as u8;
+                    value = self.lookup(package, type_id, value.data as u16, locale)?;
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+}
+
+/// Returns the error of a malformed resource table.
+fn malformed(message: impl Into<String>) -> AdbError {
+    AdbError::Parse {
+        value: message.into(),
+        source_type: "&[u8]",
+        target_type: "ResourceTable",
+        source: None,
+    }
+}
+
+fn u8_at(data: &[u8], offset: usize) -> Result<u8, AdbError> {
+    data.get(offset)
+        .copied()
+        .ok_or_else(|| malformed(format!("truncated at {}", offset)))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, AdbError> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes(bytes.try_into().unwrap())),
+        None => Err(malformed(format!("truncated at {}", offset))),
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, AdbError> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes(bytes.try_into().unwrap())),
+        None => Err(malformed(format!("truncated at {}", offset))),
+    }
+}
+
+/// Returns the type, the header size and the size of the chunk at `offset`.
+fn chunk_header(data: &[u8], offset: usize) -> Result<(u16, usize, usize), AdbError> {
+    let kind = u16_at(data, offset)?;
+    let header_size = u16_at(data, offset + 2)? as usize;
+    let size = u32_at(data, offset + 4)? as usize;
+    if header_size < 8 || size < header_size {
+        return Err(malformed(format!("invalid chunk at {}", offset)));
+    }
+    Ok((kind, header_size, size))
+}
+
+/// Parses the string pool chunk at `offset`, `ResStringPool_header`.
+fn string_pool(data: &[u8], offset: usize) -> Result<Vec<String>, AdbError> {
+    let (_, header_size, _) = chunk_header(data, offset)?;
+    let count = u32_at(data, offset + 8)? as usize;
+    let flags = u32_at(data, offset + 16)?;
+    let strings_start = offset + u32_at(data, offset + 20)? as usize;
+    (0..count)
+        .map(|i| {
+            let start = strings_start + u32_at(data, offset + header_size + i * 4)? as usize;
+            if flags & UTF8_FLAG != 0 {
+                // The length in UTF-16 units, then in bytes, each on 1 or 2 bytes.
+                let (_, start) = utf8_length(data, start)?;
+                let (length, start) = utf8_length(data, start)?;
+                let bytes = data
+                    .get(start..start + length)
+                    .ok_or_else(|| malformed(format!("truncated string at {}", start)))?;
+                Ok(String::from_utf8_lossy(bytes).into_owned())
+            } else {
+                let mut length = u16_at(data, start)? as usize;
+                let mut start = start + 2;
+                if length & 0x8000 != 0 {
+                    length = ((length & 0x7fff) << 16) | u16_at(data, start)? as usize;
+                    start += 2;
+                }
+                let units = (0..length)
+                    .map(|i| u16_at(data, start + i * 2))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(String::from_utf16_lossy(&units))
+            }
+        })
+        .collect()
+}
+
+/// Returns a length of a UTF-8 string pool, and the offset following it.
+fn utf8_length(data: &[u8], offset: usize) -> Result<(usize, usize), AdbError> {
+    let first = u8_at(data, offset)? as usize;
+    if first & 0x80 == 0 {
+        Ok((first, offset + 1))
+    } else {
+        Ok((
+            ((first & 0x7f) << 8) | u8_at(data, offset + 1)? as usize,
+            offset + 2,
+        ))
+    }
+}
+
+/// Parses the package chunk at `offset`, `ResTable_package`.
+fn package(data: &[u8], offset: usize) -> Result<Package, AdbError> {
+    let (_, header_size, size) = chunk_header(data, offset)?;
+    let id = u32_at(data, offset + 8)? as u8;
+    let name = (0..128)
+        .map(|i| u16_at(data, offset + 12 + i * 2))
+        .take_while(|unit| !matches!(unit, Ok(0)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let type_strings = u32_at(data, offset + 268)? as usize;
+    let key_strings = u32_at(data, offset + 276)? as usize;
+    let mut package = Package {
+        id,
+        name: String::from_utf16_lossy(&name),
+        type_names: string_pool(data, offset + type_strings)?,
+        key_names: string_pool(data, offset + key_strings)?,
+        types: Vec::new(),
+    };
+    let end = (offset + size).min(data.len());
+    let mut chunk = offset + header_size;
+    while chunk < end {
+        let (kind, _, size) = chunk_header(data, chunk)?;
+        if kind == RES_TABLE_TYPE_TYPE {
+            package.types.push(type_chunk(data, chunk)?);
+        }
+        chunk += size;
+    }
+    Ok(package)
+}
+
+/// Parses the type chunk at `offset`, `ResTable_type`.
+fn type_chunk(data: &[u8], offset: usize) -> Result<TypeChunk, AdbError> {
+    let (_, header_size, _) = chunk_header(data, offset)?;
+    let id = u8_at(data, offset + 8)?;
+    let flags = u8_at(data, offset + 9)?;
+    let count = u32_at(data, offset + 12)? as usize;
+    let entries_start = offset + u32_at(data, offset + 16)? as usize;
+    // `ResTable_config`: size, mcc, mnc, language[2], country[2], ...
+    let config = offset + 20;
+    let language = unpack_locale(u8_at(data, config + 8)?, u8_at(data, config + 9)?, b'a');
+    let region = unpack_locale(u8_at(data, config + 10)?, u8_at(data, config + 11)?, b'0');
+    let offsets = offset + header_size;
+    let mut entries = Vec::new();
+    for i in 0..count {
+        let (index, entry) = if flags & FLAG_SPARSE != 0 {
+            let index = u16_at(data, offsets + i * 4)?;
+            (index, u16_at(data, offsets + i * 4 + 2)? as u32 * 4)
+        } else if flags & FLAG_OFFSET16 != 0 {
+            match u16_at(data, offsets + i * 2)? {
+                u16::MAX => continue,
+                entry => (i as u16, entry as u32 * 4),
+            }
+        } else {
+            match u32_at(data, offsets + i * 4)? {
+                NO_ENTRY => continue,
+                entry => (i as u16, entry),
+            }
+        };
+        let entry = entries_start + entry as usize;
+        let size = u16_at(data, entry)?;
+        let entry_flags = u16_at(data, entry + 2)?;
+        let (key, value) = if entry_flags & FLAG_COMPACT != 0 {
+            let value = Value {
+                data_type: (entry_flags >> 8) as u8,
+                data: u32_at(data, entry + 4)?,
+            };
+            (size as u32, Some(value))
+        } else if entry_flags & FLAG_COMPLEX != 0 {
+            (u32_at(data, entry + 4)?, None)
+        } else {
+            // `Res_value` follows the entry: size, res0, data type, data.
+            let value = entry + size as usize;
+            let value = Value {
+                data_type: u8_at(data, value + 3)?,
+                data: u32_at(data, value + 4)?,
+            };
+            (u32_at(data, entry + 4)?, Some(value))
+        };
+        entries.push((index, key, value));
+    }
+    Ok(TypeChunk {
+        id,
+        language,
+        region,
+        entries,
+    })
+}
+
+/// Unpacks the language or the region of a `ResTable_config`, 2 letters, or 3 packed on
+/// 5 bits each from `base` if the high bit is set.
+fn unpack_locale(first: u8, second: u8, base: u8) -> String {
+    if first == 0 {
+        String::new()
+    } else if first & 0x80 != 0 {
+        let letters = [
+            second & 0x1f,
+            ((second & 0xe0) >> 5) | ((first & 0x03) << 3),
+            (first & 0x7c) >> 2,
+        ];
+        letters
+            .iter()
+            .map(|letter| (base + letter) as char)
+            .collect()
+    } else {
+        [first as char, second as char].iter().collect()
+    }
+}
+
+impl Device {
+    /// Returns the resource table of the base APK of `package`.
+    pub fn resource_table(&self, package: &str) -> Result<ResourceTable, AdbError> {
+        let apk = self.package_paths(package)?.swap_remove(0);
+        let mut data = Vec::new();
+        self.open(&format!(
+            "exec:unzip -p {} resources.arsc",
+            shell::quote(&apk)
+        ))?
+        .read_to_end(&mut data)?;
+        if !data.starts_with(&RES_TABLE_TYPE.to_le_bytes()) {
+            return Err(AdbError::Server {
+                message: format!(
+                    "unzip -p {} resources.arsc: {}",
+                    apk,
+                    String::from_utf8_lossy(&data).trim_end()
+                ),
+            });
+        }
+        ResourceTable::parse(&data)
+    }
+
+    /// Returns the string resource `name` of `package`, e.g. `app_name` for
+    /// `@string/app_name`, in `locale`, or in the default configuration if `None`.
+    ///
+    /// Fetch the [`Device::resource_table`] once to look up many strings.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::resources::Locale;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let fr = "fr-FR".parse::<Locale>().unwrap();
+    /// let label = device
+    ///     .app_string("com.android.settings", "settings_label", Some(&fr))
+    ///     .unwrap();
+    /// assert_eq!("Paramètres", label);
+    /// ```
+    pub fn app_string(
+        &self,
+        package: &str,
+        name: &str,
+        locale: Option<&Locale>,
+    ) -> Result<String, AdbError> {
+        self.resource_table(package)?
+            .string(name, locale)
+            .ok_or_else(|| AdbError::Server {
+                message: format!("{} has no string resource `{}`", package, name),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a chunk of `kind` with `header` after the chunk header, then `body`.
+    fn chunk(kind: u16, header: &[u8], body: &[u8]) -> Vec<u8> {
+        let header_size = 8 + header.len();
+        let mut chunk = Vec::new();
+        chunk.extend(kind.to_le_bytes());
+        chunk.extend((header_size as u16).to_le_bytes());
+        chunk.extend(((header_size + body.len()) as u32).to_le_bytes());
+        chunk.extend(header);
+        chunk.extend(body);
+        chunk
+    }
+
+    /// Returns a UTF-8 string pool of `strings`.
+    fn pool(strings: &[&str]) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut data = Vec::new();
+        for s in strings {
+            offsets.extend((data.len() as u32).to_le_bytes());
+            data.extend([s.chars().count() as u8, s.len() as u8]);
+            data.extend(s.as_bytes());
+            data.push(0);
+        }
+        data.resize((data.len() + 3) / 4 * 4, 0);
+        let mut header = Vec::new();
+        header.extend((strings.len() as u32).to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        header.extend(UTF8_FLAG.to_le_bytes());
+        header.extend((28 + offsets.len() as u32).to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        chunk(RES_STRING_POOL_TYPE, &header, &[offsets, data].concat())
+    }
+
+    /// Returns a type chunk of `string` in `locale`, with `(key, data type, data)`
+    /// entries, `None` for missing ones.
+    fn strings(locale: &[u8; 4], entries: &[Option<(u32, u8, u32)>]) -> Vec<u8> {
+        let mut config = vec![0; 64];
+        config[..4].copy_from_slice(&64u32.to_le_bytes());
+        config[8..12].copy_from_slice(locale);
+        let mut offsets = Vec::new();
+        let mut data = Vec::new();
+        for entry in entries {
+            let Some((key, data_type, value)) = entry else {
+                offsets.extend(NO_ENTRY.to_le_bytes());
+                continue;
+            };
+            offsets.extend((data.len() as u32).to_le_bytes());
+            data.extend(8u16.to_le_bytes());
+            data.extend(0u16.to_le_bytes());
+            data.extend(key.to_le_bytes());
+            data.extend([8, 0, 0, *data_type]);
+            data.extend(value.to_le_bytes());
+        }
+        let mut header = vec![2, 0, 0, 0];
+        header.extend((entries.len() as u32).to_le_bytes());
+        header.extend((8 + 12 + 64 + offsets.len() as u32).to_le_bytes());
+        header.extend(config);
+        chunk(RES_TABLE_TYPE_TYPE, &header, &[offsets, data].concat())
+    }
+
+    /// Returns a table of `com.example` with `app_name` and `greeting` strings, the
+    /// default `app_name` referencing `greeting`.
+    fn example_table() -> Vec<u8> {
+        let globals = pool(&["Hello", "Bonjour", "Salut", "Grüezi"]);
+        let types = pool(&["attr", "string"]);
+        let keys = pool(&["app_name", "greeting"]);
+        let mut header = 0x7fu32.to_le_bytes().to_vec();
+        let mut name = [0u8; 256];
+        for (i, unit) in "com.example".encode_utf16().enumerate() {
+            name[i * 2..i * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        header.extend(name);
+        let header_size = 8 + 4 + 256 + 20;
+        for offset in [header_size, 0, header_size + types.len(), 0, 0] {
+            header.extend((offset as u32).to_le_bytes());
+        }
+        let body = [
+            types,
+            keys,
+            strings(
+                b"\0\0\0\0",
+                &[
+                    Some((0, TYPE_REFERENCE, 0x7f02_0001)),
+                    Some((1, TYPE_STRING, 0)),
+                ],
+            ),
+            strings(b"fr\0\0", &[None, Some((1, TYPE_STRING, 1))]),
+            strings(b"frCA", &[None, Some((1, TYPE_STRING, 2))]),
+            strings(b"deCH", &[Some((0, TYPE_STRING, 3))]),
+        ]
+        .concat();
+        let package = chunk(RES_TABLE_PACKAGE_TYPE, &header, &body);
+        chunk(
+            RES_TABLE_TYPE,
+            &1u32.to_le_bytes(),
+            &[globals, package].concat(),
+        )
+    }
+
+    #[test]
+    fn test_resource_table() {
+        let table = ResourceTable::parse(&example_table()).unwrap();
+        assert_eq!(vec!["com.example"], table.packages().collect::<Vec<_>>());
+        let string = |name, locale: Option<&str>| {
+            table.string(name, locale.map(|l| l.parse().unwrap()).as_ref())
+        };
+        assert_eq!(Some("Hello"), string("greeting", None).as_deref());
+        assert_eq!(Some("Bonjour"), string("greeting", Some("fr")).as_deref());
+        assert_eq!(
+            Some("Bonjour"),
+            string("greeting", Some("fr-FR")).as_deref()
+        );
+        assert_eq!(Some("Salut"), string("greeting", Some("fr-rCA")).as_deref());
+        assert_eq!(Some("Hello"), string("greeting", Some("de-CH")).as_deref());
+        // The reference is resolved in the locale.
+        assert_eq!(Some("Hello"), string("app_name", None).as_deref());
+        assert_eq!(Some("Salut"), string("app_name", Some("fr-CA")).as_deref());
+        assert_eq!(Some("Grüezi"), string("app_name", Some("de-CH")).as_deref());
+        assert_eq!(None, string("missing", None));
+
+        assert!(ResourceTable::parse(b"PK\x03\x04").is_err());
+        assert!(ResourceTable::parse(&example_table()[..100]).is_err());
+    }
+
+    #[test]
+    fn test_unpack_locale() {
+        assert_eq!("", unpack_locale(0, 0, b'a'));
+        assert_eq!("en", unpack_locale(b'e', b'n', b'a'));
+        // `fil`, packed by `ResTable_config::packLanguageOrRegion`.
+        assert_eq!("fil", unpack_locale(0xad, 0x05, b'a'));
+        assert!("english".parse::<Locale>().is_err());
+        assert_eq!(Locale::new("fil").region("PH"), "fil-PH".parse().unwrap());
+    }
+}
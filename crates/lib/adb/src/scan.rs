@@ -0,0 +1,433 @@
+//! This module provides the discovery of adb daemons listening on a subnet, for networks
+//! where mDNS is blocked.
+//!
+//! Every host of the subnet is probed on the port of `adb tcpip`, and on the ports of the
+//! wireless debugging services learned from mDNS, see [`AdbServer::scan_subnet`]. An open
+//! port is sent a `CNXN` message: adb daemons reply with their banner, `AUTH` if they
+//! require authentication, or `STLS` for wireless debugging.
+
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::AdbError;
+use crate::mdns::MdnsServiceKind;
+use crate::server::AdbServer;
+use crate::socket::Tcp;
+use crate::transport::message::{Command, Message, MAX_PAYLOAD, VERSION};
+use crate::transport::tcp::DEFAULT_PORT;
+use crate::transport::{self, HOST_BANNER};
+
+/// An IPv4 subnet, e.g. `192.168.1.0/24`.
+///
+/// # Examples
+///
+/// ```
+/// use adb::scan::Subnet;
+///
+/// let subnet: Subnet = "192.168.1.17/30".parse().unwrap();
+/// assert_eq!("192.168.1.16/30", subnet.to_string());
+/// let hosts: Vec<_> = subnet.hosts().map(|ip| ip.to_string()).collect();
+/// assert_eq!(hosts, ["192.168.1.17", "192.168.1.18"]);
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Subnet {
+    network: Ipv4Addr,
+    prefix: u8,
+}
+
+impl Subnet {
+    /// Creates the subnet of `addr` with a prefix of `prefix` bits.
+    ///
+    /// Fails if the prefix is longer than 32 bits.
+    pub fn new(addr: Ipv4Addr, prefix: u8) -> Result<Self, AdbError> {
+        if prefix > 32 {
+            return Err(AdbError::Parse {
+                value: prefix.to_string(),
+                source_type: "u8",
+                target_type: "Subnet",
+                source: Some("the prefix is longer than 32 bits".into()),
+            });
+        }
+        Ok(Self {
+            network: Ipv4Addr::from(u32::from(addr) & Self::mask(prefix)),
+            prefix,
+        })
+    }
+
+    fn mask(prefix: u8) -> u32 {
+        u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0)
+    }
+
+    /// Returns the network address.
+    pub fn network(&self) -> Ipv4Addr {
+        self.network
+    }
+
+    /// Returns the length of the prefix in bits.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Returns `true` if `ip` is in the subnet.
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        u32::from(ip) & Self::mask(self.prefix) == u32::from(self.network)
+    }
+
+    /// Returns the addresses of the hosts of the subnet, without the network and broadcast
+    /// addresses, unless the prefix is 31 or 32 bits long.
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let first = u32::from(self.network);
+        let last = first | !Self::mask(self.prefix);
+        let range = if self.prefix < 31 {
+            first + 1..=last - 1
+        } else {
+            first..=last
+        };
+        range.map(Ipv4Addr::from)
+    }
+}
+
+impl Display for Subnet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |source: Option<Box<dyn std::error::Error + Send + Sync>>| AdbError::Parse {
+            value: s.to_string(),
+            source_type: "&str",
+            target_type: "Subnet",
+            source,
+        };
+        let (addr, prefix) = s.split_once('/').ok_or_else(|| error(None))?;
+        let addr = addr.parse().map_err(|e| error(Some(Box::new(e))))?;
+        let prefix = prefix.parse().map_err(|e| error(Some(Box::new(e))))?;
+        Self::new(addr, prefix).map_err(|_| error(None))
+    }
+}
+
+/// How an open port replied to `CNXN`.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ProbeReply {
+    /// The daemon accepted the connection, with its banner, e.g.
+    /// `device::ro.product.name=...;features=...`.
+    Connected(String),
+    /// The daemon requires authentication (`AUTH`).
+    Auth,
+    /// The daemon requires TLS, as with wireless debugging (`STLS`).
+    Tls,
+    /// The port is open, but didn't reply with an adb message in time.
+    Silent,
+}
+
+/// An adb endpoint found by a scan.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct ScanCandidate {
+    /// The address of the endpoint, with both ip and port.
+    pub socket: Tcp,
+    pub reply: ProbeReply,
+}
+
+impl ScanCandidate {
+    /// Returns the banner of the daemon, if it accepted the connection.
+    pub fn banner(&self) -> Option<&str> {
+        match &self.reply {
+            ProbeReply::Connected(banner) => Some(banner),
+            _ => None,
+        }
+    }
+
+    /// Returns a property of the banner, e.g. `ro.product.model`.
+    pub fn banner_property(&self, key: &str) -> Option<&str> {
+        let (_, properties) = self.banner()?.split_once("::")?;
+        properties.split(';').find_map(|property| {
+            property
+                .split_once('=')
+                .filter(|(name, _)| *name == key)
+                .map(|(_, value)| value)
+        })
+    }
+
+    /// Returns `true` if the endpoint speaks the adb protocol.
+    pub fn is_adb(&self) -> bool {
+        self.reply != ProbeReply::Silent
+    }
+}
+
+/// The options of a scan.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::scan::ScanOptions;
+///
+/// let options = ScanOptions::new()
+///     .port(5556)
+///     .connect_timeout(Duration::from_millis(100))
+///     .threads(16);
+/// assert_eq!(options.get_ports(), [5555, 5556]);
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ScanOptions {
+    ports: Vec<u16>,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    threads: usize,
+}
+
+impl ScanOptions {
+    /// Creates options probing port 5555 with 64 threads, waiting 200 ms for connections and
+    /// 500 ms for replies.
+    pub fn new() -> Self {
+        Self {
+            ports: vec![DEFAULT_PORT],
+            connect_timeout: Duration::from_millis(200),
+            read_timeout: Duration::from_millis(500),
+            threads: 64,
+        }
+    }
+
+    /// Adds a port to probe.
+    pub fn port(mut self, port: u16) -> Self {
+        if !self.ports.contains(&port) {
+            self.ports.push(port);
+        }
+        self
+    }
+
+    /// Sets the time to wait for a connection to each port.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the time to wait for the reply to `CNXN` of an open port.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Sets how many ports are probed at the same time, at least 1.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Returns the ports to probe.
+    pub fn get_ports(&self) -> &[u16] {
+        &self.ports
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Classifies the first message replied to `CNXN`.
+fn classify(message: &Message) -> ProbeReply {
+    match message.command {
+        Command::Connect => ProbeReply::Connected(
+            String::from_utf8_lossy(&message.payload)
+                .trim_end_matches('\0')
+                .to_string(),
+        ),
+        Command::Auth => ProbeReply::Auth,
+        Command::StartTls => ProbeReply::Tls,
+        _ => ProbeReply::Silent,
+    }
+}
+
+/// Probes a single endpoint, returning `None` if connecting to it fails, e.g. if the port is
+/// closed or the host unreachable.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::scan::{self, ScanOptions};
+///
+/// let addr = "192.168.1.20:5555".parse().unwrap();
+/// if let Some(candidate) = scan::probe(addr, &ScanOptions::new()).unwrap() {
+///     println!("{:?}", candidate.reply);
+/// }
+/// ```
+pub fn probe(addr: SocketAddr, options: &ScanOptions) -> Result<Option<ScanCandidate>, AdbError> {
+    let mut stream = match TcpStream::connect_timeout(&addr, options.connect_timeout) {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None),
+    };
+    stream.set_read_timeout(Some(options.read_timeout))?;
+    stream.set_write_timeout(Some(options.read_timeout))?;
+    let mut banner = HOST_BANNER.as_bytes().to_vec();
+    banner.push(0);
+    let reply = stream
+        .write_all(&Message::new(Command::Connect, VERSION, MAX_PAYLOAD, banner).encode())
+        .map_err(AdbError::from)
+        .and_then(|()| transport::read_message(&mut stream))
+        .map_or(ProbeReply::Silent, |message| classify(&message));
+    Ok(Some(ScanCandidate {
+        socket: addr.into(),
+        reply,
+    }))
+}
+
+/// Probes every host of `subnet` on the ports of `options`, returning the open endpoints
+/// sorted by address.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::scan::{self, ScanOptions};
+///
+/// let subnet = "192.168.1.0/24".parse().unwrap();
+/// for candidate in scan::scan(&subnet, &ScanOptions::new()).unwrap() {
+///     if let Some(model) = candidate.banner_property("ro.product.model") {
+///         println!("{} {}", candidate.socket, model);
+///     }
+/// }
+/// ```
+pub fn scan(subnet: &Subnet, options: &ScanOptions) -> Result<Vec<ScanCandidate>, AdbError> {
+    let targets: Vec<_> = subnet
+        .hosts()
+        .flat_map(|ip| {
+            options
+                .ports
+                .iter()
+                .map(move |&port| SocketAddr::new(IpAddr::V4(ip), port))
+        })
+        .collect();
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..options.threads.min(targets.len()) {
+            scope.spawn(|| {
+                while let Some(&addr) = targets.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = probe(addr, options);
+                    let mut results = results.lock().unwrap_or_else(|e| e.into_inner());
+                    results.push(result);
+                }
+            });
+        }
+    });
+    let mut candidates = results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .filter_map(Result::transpose)
+        .collect::<Result<Vec<_>, _>>()?;
+    candidates.sort();
+    Ok(candidates)
+}
+
+impl AdbServer {
+    /// Probes every host of `subnet` on the ports of `options`, and on the ports of the adb
+    /// services discovered by the mDNS browser of the server, see [`scan()`].
+    ///
+    /// Wireless debugging listens on a random port, which mDNS may still reveal for some
+    /// devices of the network.
+    pub fn scan_subnet(
+        &self,
+        subnet: &Subnet,
+        options: &ScanOptions,
+    ) -> Result<Vec<ScanCandidate>, AdbError> {
+        let mut options = options.clone();
+        for service in self.mdns_services()? {
+            if service.kind != MdnsServiceKind::TlsPairing {
+                if let Some(port) = service.socket.port {
+                    options = options.port(port);
+                }
+            }
+        }
+        scan(subnet, &options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_subnet_parse() {
+        let subnet: Subnet = "10.0.3.7/24".parse().unwrap();
+        assert_eq!(Ipv4Addr::new(10, 0, 3, 0), subnet.network());
+        assert_eq!("10.0.3.0/24", subnet.to_string());
+        assert_eq!(254, subnet.hosts().count());
+        assert!(subnet.contains(Ipv4Addr::new(10, 0, 3, 255)));
+        assert!(!subnet.contains(Ipv4Addr::new(10, 0, 4, 1)));
+        let host: Subnet = "10.0.3.7/32".parse().unwrap();
+        assert_eq!(
+            vec![Ipv4Addr::new(10, 0, 3, 7)],
+            host.hosts().collect::<Vec<_>>()
+        );
+        assert_eq!(2, "10.0.3.7/31".parse::<Subnet>().unwrap().hosts().count());
+        assert!("0.0.0.0/0"
+            .parse::<Subnet>()
+            .unwrap()
+            .contains(Ipv4Addr::BROADCAST));
+        for s in ["", "10.0.3.7", "10.0.3.7/33", "10.0.3/24", "::1/128"] {
+            assert!(s.parse::<Subnet>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_banner_property() {
+        let candidate = ScanCandidate {
+            socket: Tcp::from_port(5555),
+            reply: ProbeReply::Connected(
+                "device::ro.product.name=sdk;ro.product.model=Pixel 8;features=cmd".to_string(),
+            ),
+        };
+        assert_eq!(
+            Some("Pixel 8"),
+            candidate.banner_property("ro.product.model")
+        );
+        assert_eq!(Some("cmd"), candidate.banner_property("features"));
+        assert_eq!(None, candidate.banner_property("ro.product"));
+        assert!(candidate.is_adb());
+    }
+
+    #[test]
+    fn test_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let daemon = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let message = transport::read_message(&mut stream).unwrap();
+            assert_eq!(Command::Connect, message.command);
+            let reply = Message::new(Command::Auth, 1, 0, vec![0; 20]);
+            stream.write_all(&reply.encode()).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 64]);
+        });
+        let options = ScanOptions::new().read_timeout(Duration::from_millis(50));
+        let candidate = probe(addr, &options).unwrap().unwrap();
+        assert_eq!(ProbeReply::Auth, candidate.reply);
+        assert_eq!(Tcp::from(addr), candidate.socket);
+        let candidate = probe(addr, &options).unwrap().unwrap();
+        assert_eq!(ProbeReply::Silent, candidate.reply);
+        assert!(!candidate.is_adb());
+        daemon.join().unwrap();
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+        assert_eq!(None, probe(addr, &options).unwrap());
+    }
+}
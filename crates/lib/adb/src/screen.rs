@@ -0,0 +1,392 @@
+//! This module provides screenshots (`screencap`) and screen recordings (`screenrecord`).
+//!
+//! Both commands run through the `exec:` service, which doesn't allocate a pty that would
+//! mangle the binary output. A recording is written to a file on the device, since
+//! `screenrecord` only streams raw H.264 to stdout, and pulled when it's stopped.
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use crate::cancel::{self, CancelGuard, CancellationToken};
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::server::ServerStream;
+use crate::shell;
+use crate::stream::{self, StreamDropPolicy};
+
+/// The signature starting every PNG file.
+pub const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// The directory recordings are written to on the device.
+pub const RECORDING_DIR: &str = "/data/local/tmp";
+
+/// The longest recording `screenrecord` allows, and its default time limit.
+pub const MAX_RECORDING_TIME: Duration = Duration::from_secs(180);
+
+/// The options of [`Device::screenrecord`].
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::screen::ScreenRecordOptions;
+///
+/// let options = ScreenRecordOptions::new()
+///     .size(720, 1280)
+///     .time_limit(Duration::from_secs(30));
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ScreenRecordOptions {
+    size: Option<(u32, u32)>,
+    bit_rate: Option<u32>,
+    time_limit: Option<Duration>,
+    bugreport: bool,
+}
+
+impl ScreenRecordOptions {
+    /// Creates options recording at the resolution of the display, for
+    /// [`MAX_RECORDING_TIME`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the resolution of the video (`--size`).
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    /// Sets the bit rate of the video in bit/s (`--bit-rate`), 20 Mbit/s by default.
+    pub fn bit_rate(mut self, bit_rate: u32) -> Self {
+        self.bit_rate = Some(bit_rate);
+        self
+    }
+
+    /// Sets the time limit of the recording (`--time-limit`), at most
+    /// [`MAX_RECORDING_TIME`].
+    pub fn time_limit(mut self, time_limit: Duration) -> Self {
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    /// Overlays the time and the frame number on the video (`--bugreport`).
+    pub fn bugreport(mut self, bugreport: bool) -> Self {
+        self.bugreport = bugreport;
+        self
+    }
+
+    /// Returns the `screenrecord` command writing to `path`.
+    fn command(&self, path: &str) -> String {
+        let mut command = "screenrecord".to_string();
+        if let Some((width, height)) = self.size {
+            command.push_str(&format!(" --size {}x{}", width, height));
+        }
+        if let Some(bit_rate) = self.bit_rate {
+            command.push_str(&format!(" --bit-rate {}", bit_rate));
+        }
+        if let Some(time_limit) = self.time_limit {
+            // `screenrecord` rejects 0, which it doesn't treat as unlimited.
+            let secs = time_limit.as_secs().clamp(1, MAX_RECORDING_TIME.as_secs());
+            command.push_str(&format!(" --time-limit {}", secs));
+        }
+        if self.bugreport {
+            command.push_str(" --bugreport");
+        }
+        command.push(' ');
+        command.push_str(path);
+        command
+    }
+}
+
+/// Returns the path of the recording of the `screenrecord` process `pid`.
+fn recording_path(pid: &str) -> String {
+    format!("{}/screenrecord-{}.mp4", RECORDING_DIR, pid)
+}
+
+/// Reads the first line of `reader` byte by byte, leaving the rest unread.
+fn read_line<R: Read>(reader: &mut R) -> Result<String, AdbError> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Err(AdbError::Protocol {
+                code: None,
+                message: "the device closed the connection".to_string(),
+            });
+        }
+        if byte[0] == b'\n' {
+            return Ok(String::from_utf8_lossy(&line).into_owned());
+        }
+        line.push(byte[0]);
+    }
+}
+
+/// A running screen recording, started by [`Device::screenrecord`].
+///
+/// The recording continues until [`Self::stop`] is called or its time limit is reached.
+/// Dropping the handle without stopping it ends `screenrecord` as set by
+/// [`Self::drop_policy`], and removes the file from the device.
+#[derive(Debug)]
+pub struct ScreenRecording {
+    device: Device,
+    stream: ServerStream,
+    pid: u32,
+    drop_policy: StreamDropPolicy,
+    stopped: bool,
+    cancel: Option<CancelGuard>,
+}
+
+impl ScreenRecording {
+    /// Sets how `screenrecord` is ended when the handle is dropped without being stopped,
+    /// [`StreamDropPolicy::Close`] by default:
+    ///
+    /// - [`StreamDropPolicy::Abort`] kills it (`SIGKILL`).
+    /// - [`StreamDropPolicy::Close`] interrupts it (`SIGINT`).
+    /// - [`StreamDropPolicy::Drain`] interrupts it and waits for it to exit, for at most the
+    ///   given time, before removing the file.
+    pub fn drop_policy(mut self, policy: StreamDropPolicy) -> Self {
+        self.drop_policy = policy;
+        self
+    }
+
+    /// Stops [`Self::stop`] when `token` is cancelled, failing it with
+    /// [`AdbError::Cancelled`].
+    ///
+    /// The handle of a cancelled recording is dropped, which ends `screenrecord` and removes
+    /// the partial file from the device.
+    pub fn cancel_on(mut self, token: &CancellationToken) -> Result<Self, AdbError> {
+        self.cancel = Some(token.register(&self.stream)?);
+        Ok(self)
+    }
+
+    /// Returns the pid of the `screenrecord` process.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Returns the path of the recording on the device.
+    pub fn path(&self) -> String {
+        recording_path(&self.pid.to_string())
+    }
+
+    /// Stops the recording, writes the mp4 file into `writer` and removes it from the
+    /// device.
+    ///
+    /// Returns the number of written bytes.
+    pub fn stop<W: Write>(mut self, writer: &mut W) -> Result<u64, AdbError> {
+        // SIGINT lets `screenrecord` finalize the file, and fails if the time limit was
+        // reached already.
+        let _ = self.device.shell(&format!("kill -INT {}", self.pid))?;
+        let mut output = Vec::new();
+        let result = self.stream.read_to_end(&mut output).map_err(AdbError::from);
+        cancel::map_result(&self.cancel, result)?;
+        let path = self.path();
+        let mut sync = self.device.sync()?;
+        if let Some(guard) = &self.cancel {
+            sync = sync.cancel_on(guard.token())?;
+        }
+        let received = match sync.recv(&path, writer) {
+            Ok(received) => received,
+            Err(e) if output.is_empty() || matches!(e, AdbError::Cancelled) => return Err(e),
+            Err(_) => {
+                return Err(AdbError::Server {
+                    message: format!(
+                        "screenrecord failed: {}",
+                        String::from_utf8_lossy(&output).trim_end()
+                    ),
+                })
+            }
+        };
+        sync.quit()?;
+        self.device
+            .shell_checked(&format!("rm -f {}", shell::quote(&path)))?;
+        self.stopped = true;
+        Ok(received)
+    }
+}
+
+impl Drop for ScreenRecording {
+    fn drop(&mut self) {
+        if self.stopped {
+            return;
+        }
+        let signal = match self.drop_policy {
+            StreamDropPolicy::Abort => "KILL",
+            StreamDropPolicy::Close | StreamDropPolicy::Drain(_) => "INT",
+        };
+        let _ = self.device.shell(&format!("kill -{} {}", signal, self.pid));
+        stream::close(&mut self.stream, self.drop_policy);
+        let _ = self
+            .device
+            .shell(&format!("rm -f {}", shell::quote(&self.path())));
+    }
+}
+
+/// The layout of the pixels of an [`Image`], with 8 bits per channel.
+#[cfg(feature = "png")]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum PixelFormat {
+    Gray8,
+    GrayAlpha8,
+    Rgb8,
+    Rgba8,
+}
+
+#[cfg(feature = "png")]
+impl PixelFormat {
+    /// Returns the number of bytes of a pixel.
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Gray8 => 1,
+            Self::GrayAlpha8 => 2,
+            Self::Rgb8 => 3,
+            Self::Rgba8 => 4,
+        }
+    }
+}
+
+/// A decoded screenshot.
+#[cfg(feature = "png")]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    /// The pixels, row by row from the top left corner, without padding.
+    pub data: Vec<u8>,
+}
+
+#[cfg(feature = "png")]
+impl Image {
+    /// Decodes a PNG image, expanding palettes and reducing 16-bit channels to 8 bits.
+    pub fn from_png(png: &[u8]) -> Result<Self, AdbError> {
+        let err = |e: png::DecodingError| AdbError::Parse {
+            value: format!("{} bytes of PNG", png.len()),
+            source_type: "&[u8]",
+            target_type: "Image",
+            source: Some(Box::new(e)),
+        };
+        let mut decoder = png::Decoder::new(png);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(err)?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(err)?;
+        data.truncate(info.buffer_size());
+        let format = match info.color_type {
+            png::ColorType::Grayscale => PixelFormat::Gray8,
+            png::ColorType::GrayscaleAlpha => PixelFormat::GrayAlpha8,
+            png::ColorType::Rgb | png::ColorType::Indexed => PixelFormat::Rgb8,
+            png::ColorType::Rgba => PixelFormat::Rgba8,
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            format,
+            data,
+        })
+    }
+}
+
+impl Device {
+    /// Takes a screenshot of the default display (`screencap -p`) and returns the PNG file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// std::fs::write("screen.png", device.screencap().unwrap()).unwrap();
+    /// ```
+    pub fn screencap(&self) -> Result<Vec<u8>, AdbError> {
+        let mut png = Vec::new();
+        self.open("exec:screencap -p")?.read_to_end(&mut png)?;
+        if !png.starts_with(PNG_SIGNATURE) {
+            return Err(AdbError::Server {
+                message: format!(
+                    "screencap failed: {}",
+                    String::from_utf8_lossy(&png).trim_end()
+                ),
+            });
+        }
+        Ok(png)
+    }
+
+    /// Takes a screenshot of the default display and decodes it.
+    #[cfg(feature = "png")]
+    pub fn screencap_image(&self) -> Result<Image, AdbError> {
+        Image::from_png(&self.screencap()?)
+    }
+
+    /// Starts recording the screen of the default display into a file in
+    /// [`RECORDING_DIR`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use adb::screen::ScreenRecordOptions;
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let recording = device.screenrecord(&ScreenRecordOptions::new()).unwrap();
+    /// // ...
+    /// recording.stop(&mut File::create("screen.mp4").unwrap()).unwrap();
+    /// ```
+    pub fn screenrecord(&self, options: &ScreenRecordOptions) -> Result<ScreenRecording, AdbError> {
+        // The shell prints its pid, which `exec` hands over to `screenrecord`.
+        let mut stream = self.open(&format!(
+            "exec:echo $$; exec {}",
+            options.command(&recording_path("$$"))
+        ))?;
+        let line = read_line(&mut stream)?;
+        let pid = line.trim().parse().map_err(|e| AdbError::Parse {
+            value: line.clone(),
+            source_type: "&str",
+            target_type: "u32",
+            source: Some(Box::new(e)),
+        })?;
+        Ok(ScreenRecording {
+            device: self.clone(),
+            stream,
+            pid,
+            drop_policy: StreamDropPolicy::Close,
+            stopped: false,
+            cancel: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_screenrecord_command() {
+        assert_eq!(
+            "screenrecord /data/local/tmp/screenrecord-$$.mp4",
+            ScreenRecordOptions::new().command(&recording_path("$$"))
+        );
+        let options = ScreenRecordOptions::new()
+            .size(720, 1280)
+            .bit_rate(4_000_000)
+            .time_limit(Duration::from_secs(600))
+            .bugreport(true);
+        assert_eq!(
+            "screenrecord --size 720x1280 --bit-rate 4000000 --time-limit 180 --bugreport out.mp4",
+            options.command("out.mp4")
+        );
+    }
+
+    #[test]
+    fn test_read_line() {
+        let mut reader = Cursor::new("4242\nscreenrecord: error\n");
+        assert_eq!("4242", read_line(&mut reader).unwrap());
+        assert_eq!("screenrecord: error", read_line(&mut reader).unwrap());
+        assert!(matches!(
+            read_line(&mut reader),
+            Err(AdbError::Protocol { .. })
+        ));
+    }
+}
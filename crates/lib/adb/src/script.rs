@@ -0,0 +1,654 @@
+//! This module records sequences of adb operations as a [`Plan`], and replays them on a
+//! device.
+//!
+//! A plan is a list of typed [`Step`]s: connecting to a network device, waiting for the
+//! device, pushing files, installing APKs, running shell commands and forwarding ports.
+//! [`Plan::run`] runs them in order and stops at the first failure, reporting the output of
+//! every completed step in a [`PlanReport`]. [`Plan::resume`] continues from the failed
+//! step, e.g. once the device is back online.
+//!
+//! Plans are saved as text files, one step per line written like the `adb` command, with
+//! blank lines and `#` comments ignored:
+//!
+//! ```text
+//! connect 192.168.1.20:5555
+//! wait-for-device 60
+//! push 'build/test data.bin' /sdcard/data.bin
+//! install -r -g app.apk
+//! shell am instrument -w com.example.test/androidx.test.runner.AndroidJUnitRunner
+//! forward tcp:8080 tcp:80
+//! ```
+//!
+//! Arguments containing spaces or quotes are quoted like [`shell::quote`] does, except the
+//! command of `shell`, which is the rest of the line as is. No step may contain a line break,
+//! even quoted. With the `serde` feature, a plan is serialized as the sequence of its steps in
+//! this string form.
+
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::device::{Device, DeviceState};
+use crate::error::AdbError;
+use crate::install::InstallOptions;
+use crate::pair::ConnectOutcome;
+use crate::shell;
+use crate::socket::{AdbSocketFamilies, Tcp};
+use crate::sync::{TransferStats, DEFAULT_MODE};
+
+/// An operation of a [`Plan`].
+///
+/// # Syntax
+///
+/// - `connect <host>[:<port>]`
+/// - `wait-for-device [<timeout in seconds>]`, waiting forever without a timeout
+/// - `push <local> <remote>`
+/// - `install [-r] [-d] [-g] [-t] [--user <user>] <path>`
+/// - `shell <command>`
+/// - `forward <local> <remote>`
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[non_exhaustive]
+pub enum Step {
+    /// Connects to the network device (`adb connect`), which runs the following steps.
+    Connect(Tcp),
+    /// Waits until the device is online, failing with [`AdbError::Timeout`] after
+    /// `timeout`. A zero `timeout` waits forever.
+    WaitForDevice { timeout: Duration },
+    /// Pushes the local file `local` to `remote`, see [`Device::push`].
+    Push { local: PathBuf, remote: String },
+    /// Installs the APK at `path`, see [`Device::install`].
+    Install {
+        path: PathBuf,
+        options: InstallOptions,
+    },
+    /// Runs `command` in the shell of the device, failing if it exits with a non-zero code.
+    ///
+    /// The command must fit on one line.
+    Shell { command: String },
+    /// Forwards `local` on the host to `remote` on the device, see [`Device::forward`].
+    Forward {
+        local: AdbSocketFamilies,
+        remote: AdbSocketFamilies,
+    },
+}
+
+impl Step {
+    /// Runs the step on `device`.
+    ///
+    /// A [`Step::Connect`] doesn't switch devices by itself, its [`StepOutput::Connected`]
+    /// gives the serial of the connected device.
+    pub fn run(&self, device: &Device) -> Result<StepOutput, AdbError> {
+        match self {
+            Self::Connect(tcp) => match device.server().connect_device(*tcp)? {
+                ConnectOutcome::Connected { serial }
+                | ConnectOutcome::AlreadyConnected { serial } => {
+                    Ok(StepOutput::Connected { serial })
+                }
+                ConnectOutcome::Unauthorized { .. } => Err(AdbError::Unauthorized),
+                ConnectOutcome::Failed { message } => Err(AdbError::Server { message }),
+            },
+            Self::WaitForDevice { timeout } => device
+                .wait_for(DeviceState::Device, *timeout)
+                .map(|()| StepOutput::Online),
+            Self::Push { local, remote } => device
+                .push(local, remote, DEFAULT_MODE)
+                .map(StepOutput::Pushed),
+            Self::Install { path, options } => device
+                .install(path, options)
+                .map(|()| StepOutput::Installed),
+            Self::Shell { command } => device.shell_checked(command).map(StepOutput::Shell),
+            Self::Forward { local, remote } => device
+                .forward(local.clone(), remote.clone())
+                .map(StepOutput::Forwarded),
+        }
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connect(tcp) => {
+                let spec = tcp.to_string();
+                write!(f, "connect {}", spec.strip_prefix("tcp:").unwrap_or(&spec))
+            }
+            Self::WaitForDevice { timeout } if timeout.is_zero() => f.write_str("wait-for-device"),
+            Self::WaitForDevice { timeout } => {
+                write!(f, "wait-for-device {}", timeout.as_secs_f64())
+            }
+            Self::Push { local, remote } => write!(
+                f,
+                "push {} {}",
+                quote_word(&local.to_string_lossy()),
+                quote_word(remote)
+            ),
+            Self::Install { path, options } => {
+                f.write_str("install")?;
+                for arg in options.args() {
+                    write!(f, " {}", quote_word(&arg))?;
+                }
+                write!(f, " {}", quote_word(&path.to_string_lossy()))
+            }
+            Self::Shell { command } => write!(f, "shell {}", command),
+            Self::Forward { local, remote } => write!(f, "forward {} {}", local, remote),
+        }
+    }
+}
+
+impl FromStr for Step {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = |reason: String| AdbError::Parse {
+            value: s.to_string(),
+            source_type: "&str",
+            target_type: "Step",
+            source: Some(reason.into()),
+        };
+        let line = s.trim();
+        if line.contains(['\n', '\r']) {
+            return Err(err("line break in the step".to_string()));
+        }
+        let (name, rest) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, rest)| (name, rest.trim_start()));
+        if name == "shell" {
+            return if rest.is_empty() {
+                Err(err("missing command".to_string()))
+            } else {
+                Ok(Self::Shell {
+                    command: rest.to_string(),
+                })
+            };
+        }
+        let args = split_words(rest).map_err(err)?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match (name, &args[..]) {
+            ("connect", [addr]) => format!("tcp:{}", addr)
+                .parse()
+                .map(Self::Connect)
+                .map_err(|e| err(e.to_string())),
+            ("wait-for-device", []) => Ok(Self::WaitForDevice {
+                timeout: Duration::ZERO,
+            }),
+            ("wait-for-device", [seconds]) => seconds
+                .parse()
+                .ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .map(|timeout| Self::WaitForDevice { timeout })
+                .ok_or_else(|| err(format!("invalid timeout `{}`", seconds))),
+            ("push", [local, remote]) => Ok(Self::Push {
+                local: PathBuf::from(local),
+                remote: remote.to_string(),
+            }),
+            ("install", [flags @ .., path]) => Ok(Self::Install {
+                path: PathBuf::from(path),
+                options: parse_install_flags(flags).map_err(err)?,
+            }),
+            ("forward", [local, remote]) => {
+                let parse =
+                    |s: &str| AdbSocketFamilies::try_from(s).map_err(|e| err(e.to_string()));
+                Ok(Self::Forward {
+                    local: parse(local)?,
+                    remote: parse(remote)?,
+                })
+            }
+            ("connect" | "wait-for-device" | "push" | "install" | "forward", _) => {
+                Err(err(format!("wrong number of arguments for `{}`", name)))
+            }
+            _ => Err(err(format!("unknown step `{}`", name))),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_str!(Step);
+
+/// Returns the options of the `install` flags of a step.
+fn parse_install_flags(flags: &[&str]) -> Result<InstallOptions, String> {
+    let mut options = InstallOptions::new();
+    let mut flags = flags.iter();
+    while let Some(&flag) = flags.next() {
+        options = match flag {
+            "-r" => options.replace(true),
+            "-d" => options.allow_downgrade(true),
+            "-g" => options.grant_permissions(true),
+            "-t" => options.allow_test(true),
+            "--user" => match flags.next() {
+                Some(user) => options.user(user),
+                None => return Err("missing user after `--user`".to_string()),
+            },
+            flag => return Err(format!("unknown install flag `{}`", flag)),
+        };
+    }
+    Ok(options)
+}
+
+/// Quotes `word` like [`shell::quote`] if it isn't a single plain word.
+fn quote_word(word: &str) -> Cow<'_, str> {
+    let plain = |c: char| !c.is_whitespace() && !matches!(c, '\'' | '"' | '\\' | '#');
+    if !word.is_empty() && word.chars().all(plain) {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(shell::quote(word))
+    }
+}
+
+/// Splits `s` into words separated by whitespace, removing the single quotes and backslashes
+/// of [`quote_word`].
+fn split_words(s: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing backslash".to_string()),
+            },
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// The output of a completed [`Step`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum StepOutput {
+    /// The server is connected to the network device with the serial `serial`.
+    Connected { serial: String },
+    /// The device is online.
+    Online,
+    /// The file was pushed.
+    Pushed(TransferStats),
+    /// The APK was installed.
+    Installed,
+    /// The stdout of the shell command.
+    Shell(String),
+    /// The local TCP socket listening, see [`Device::forward`].
+    Forwarded(Option<Tcp>),
+}
+
+/// A sequence of [`Step`]s run on a device.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use std::time::Duration;
+/// use adb::install::InstallOptions;
+/// use adb::script::Plan;
+/// use adb::server::AdbServer;
+/// use adb::socket::Tcp;
+///
+/// let plan = Plan::new()
+///     .wait_for_device(Duration::from_secs(60))
+///     .install("app.apk", InstallOptions::new().replace(true))
+///     .shell("am start -W -n com.example/.MainActivity")
+///     .forward(Tcp::from_port(8080), Tcp::from_port(80));
+/// let mut report = plan.run(&AdbServer::default().any_device());
+/// while let Some((step, e)) = &report.failed {
+///     eprintln!("step {} `{}` failed: {}", step, plan.steps()[*step], e);
+///     if !e.is_retryable() {
+///         break;
+///     }
+///     report = plan.resume(report);
+/// }
+/// plan.save(Path::new("smoke.adb")).unwrap();
+/// ```
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Plan {
+    steps: Vec<Step>,
+}
+
+impl Plan {
+    /// Creates an empty plan.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a plan from the text file at `path`.
+    pub fn load(path: &Path) -> Result<Self, AdbError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Writes the plan to the text file at `path`, one step per line.
+    pub fn save(&self, path: &Path) -> Result<(), AdbError> {
+        Ok(fs::write(path, self.to_string())?)
+    }
+
+    /// Appends `step`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the text form of `step` spans several lines, e.g. a shell command or a path
+    /// containing a line break, since it couldn't be read back.
+    pub fn step(mut self, step: Step) -> Self {
+        let line = step.to_string();
+        assert!(
+            !line.contains(['\n', '\r']),
+            "line break in the step `{}`",
+            line.escape_debug()
+        );
+        self.steps.push(step);
+        self
+    }
+
+    /// Appends a [`Step::Connect`] to `tcp`.
+    pub fn connect(self, tcp: Tcp) -> Self {
+        self.step(Step::Connect(tcp))
+    }
+
+    /// Appends a [`Step::WaitForDevice`], waiting forever if `timeout` is zero.
+    pub fn wait_for_device(self, timeout: Duration) -> Self {
+        self.step(Step::WaitForDevice { timeout })
+    }
+
+    /// Appends a [`Step::Push`] of `local` to `remote`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `local` or `remote` contains a line break.
+    pub fn push(self, local: impl Into<PathBuf>, remote: &str) -> Self {
+        self.step(Step::Push {
+            local: local.into(),
+            remote: remote.to_string(),
+        })
+    }
+
+    /// Appends a [`Step::Install`] of the APK at `path`.
+    pub fn install(self, path: impl Into<PathBuf>, options: InstallOptions) -> Self {
+        self.step(Step::Install {
+            path: path.into(),
+            options,
+        })
+    }
+
+    /// Appends a [`Step::Shell`] running `command`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `command` contains a line break: run the lines as separate steps, or join
+    /// them with `;`.
+    pub fn shell(self, command: &str) -> Self {
+        self.step(Step::Shell {
+            command: command.to_string(),
+        })
+    }
+
+    /// Appends a [`Step::Forward`] of `local` to `remote`.
+    pub fn forward(
+        self,
+        local: impl Into<AdbSocketFamilies>,
+        remote: impl Into<AdbSocketFamilies>,
+    ) -> Self {
+        self.step(Step::Forward {
+            local: local.into(),
+            remote: remote.into(),
+        })
+    }
+
+    /// Returns the steps, in order.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Runs the steps on `device` in order, stopping at the first failure.
+    ///
+    /// The steps following a [`Step::Connect`] run on the connected device.
+    pub fn run(&self, device: &Device) -> PlanReport {
+        self.resume(PlanReport {
+            device: device.clone(),
+            completed: Vec::new(),
+            failed: None,
+        })
+    }
+
+    /// Runs the steps following the completed ones of `report`, starting with the failed
+    /// step, on the device of the report.
+    pub fn resume(&self, mut report: PlanReport) -> PlanReport {
+        report.failed = None;
+        let start = report.completed.len();
+        for (index, step) in self.steps.iter().enumerate().skip(start) {
+            match step.run(&report.device) {
+                Ok(output) => {
+                    if let StepOutput::Connected { serial } = &output {
+                        report.device = report.device.server().device(serial);
+                    }
+                    report.completed.push(output);
+                }
+                Err(e) => {
+                    report.failed = Some((index, e));
+                    break;
+                }
+            }
+        }
+        report
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.steps
+            .iter()
+            .try_for_each(|step| writeln!(f, "{}", step))
+    }
+}
+
+impl FromStr for Plan {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = line.parse().map_err(|e| AdbError::Parse {
+                value: line.to_string(),
+                source_type: "&str",
+                target_type: "Plan",
+                source: Some(format!("line {}: {}", number + 1, e).into()),
+            })?;
+            steps.push(step);
+        }
+        Ok(Self { steps })
+    }
+}
+
+impl FromIterator<Step> for Plan {
+    /// Collects the steps into a plan, panicking like [`Plan::step`].
+    fn from_iter<I: IntoIterator<Item = Step>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::new(), Self::step)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Plan {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(&self.steps)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Plan {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let steps = <Vec<Step> as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(Self { steps })
+    }
+}
+
+/// The results of running a [`Plan`], returned by [`Plan::run`] and [`Plan::resume`].
+#[derive(Debug)]
+pub struct PlanReport {
+    device: Device,
+    /// The outputs of the completed steps, in order.
+    pub completed: Vec<StepOutput>,
+    /// The index and the error of the step which failed, `None` if the plan completed.
+    pub failed: Option<(usize, AdbError)>,
+}
+
+impl PlanReport {
+    /// Returns the device running the next step, switched by [`Step::Connect`].
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Returns `true` if every step completed.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{Ipv4Addr, TcpListener};
+
+    use super::*;
+    use crate::connect::{ConnectOptions, RetryPolicy};
+    use crate::server::AdbServer;
+    use crate::socket::LocalAbstract;
+
+    const PLAN: &str = "\
+connect 192.168.1.20:5555
+wait-for-device 1.5
+wait-for-device
+push 'build/test data.bin' /sdcard/data.bin
+install -r --user 'it'\\''s' app.apk
+shell echo 'hello world' > /sdcard/out.txt
+forward tcp:8080 localabstract:chrome_devtools_remote
+";
+
+    #[test]
+    fn test_plan_parse() {
+        let plan: Plan = format!("# smoke test\n\n{}", PLAN).parse().unwrap();
+        let expected = Plan::new()
+            .connect(Tcp::new(Ipv4Addr::new(192, 168, 1, 20).into(), 5555))
+            .wait_for_device(Duration::from_millis(1500))
+            .wait_for_device(Duration::ZERO)
+            .push("build/test data.bin", "/sdcard/data.bin")
+            .install("app.apk", InstallOptions::new().replace(true).user("it's"))
+            .shell("echo 'hello world' > /sdcard/out.txt")
+            .forward(
+                Tcp::from_port(8080),
+                LocalAbstract("chrome_devtools_remote".to_string()),
+            );
+        assert_eq!(expected, plan);
+        assert_eq!(PLAN, plan.to_string());
+    }
+
+    #[test]
+    fn test_step_parse_errors() {
+        for s in [
+            "",
+            "reboot",
+            "connect",
+            "connect host name",
+            "wait-for-device -1",
+            "wait-for-device soon",
+            "push /sdcard/a",
+            "push 'a /sdcard/a",
+            "install",
+            "install -x app.apk",
+            "install --user app.apk",
+            "shell",
+            "forward tcp:8080",
+            "forward tcp:8080 jdwp:0",
+            "shell echo a\necho b",
+            "shell echo a\recho b",
+            "push 'a\nb' /sdcard/a",
+        ] {
+            assert!(s.parse::<Step>().is_err(), "{}", s);
+        }
+        let err = "step 1\nreboot\n".parse::<Plan>().unwrap_err();
+        assert!(err.to_string().contains("line 1: "), "{}", err);
+        assert_eq!(
+            vec!["a b", "", "it's", "c"],
+            split_words(r"'a b' '' it\'s c").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_plan_round_trip() {
+        let plan = Plan::new()
+            .push("it's a \"file\" #1\\", "/sdcard/tab\tseparated")
+            .install(" ", InstallOptions::new().user("a b"))
+            .shell("printf '%s\\n' \"$HOME\" # comment");
+        assert_eq!(plan, plan.to_string().parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "line break in the step")]
+    fn test_plan_shell_line_break() {
+        let _ = Plan::new().shell("echo a\necho b");
+    }
+
+    #[test]
+    #[should_panic(expected = "line break in the step")]
+    fn test_plan_push_line_break() {
+        let _ = Plan::new().push("a\rb", "/sdcard/a");
+    }
+
+    #[test]
+    fn test_plan_resume() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let service = "host-serial:emulator-5554:forward:localabstract:a;tcp:80";
+        let request = format!("{:04x}{}", service.len(), service);
+        let handle = std::thread::spawn(move || {
+            for reply in [&b"OKAYOKAY"[..], b"OKAYFAIL0004busy", b"OKAYOKAY"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut received = vec![0; request.len()];
+                stream.read_exact(&mut received).unwrap();
+                assert_eq!(request.as_bytes(), &received[..]);
+                stream.write_all(reply).unwrap();
+            }
+        });
+        let server = AdbServer::new(Tcp::from_port(port))
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::none()));
+        let forward = Step::Forward {
+            local: LocalAbstract("a".to_string()).into(),
+            remote: Tcp::from_port(80).into(),
+        };
+        let plan: Plan = [forward.clone(), forward].into_iter().collect();
+
+        let report = plan.run(&server.device("emulator-5554"));
+        assert_eq!(vec![StepOutput::Forwarded(None)], report.completed);
+        assert!(matches!(
+            &report.failed,
+            Some((1, AdbError::Server { message })) if message == "busy"
+        ));
+        let report = plan.resume(report);
+        assert!(report.is_complete());
+        assert_eq!(2, report.completed.len());
+        assert_eq!(Some("emulator-5554"), report.device().serial());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_deserialize() {
+        use serde::de::value::{Error, SeqDeserializer};
+        use serde::Deserialize;
+
+        let lines: Vec<&str> = PLAN.lines().collect();
+        let deserializer = SeqDeserializer::<_, Error>::new(lines.into_iter());
+        let plan = Plan::deserialize(deserializer).unwrap();
+        assert_eq!(PLAN.parse::<Plan>().unwrap(), plan);
+    }
+}
@@ -0,0 +1,37 @@
+//! This module locates the tools of the Android SDK on the host.
+
+use std::env;
+use std::path::PathBuf;
+
+/// Returns the path of the `adb` executable, searched in `PATH`, then in the
+/// `platform-tools` directory of the SDK (`ANDROID_HOME`, or the deprecated
+/// `ANDROID_SDK_ROOT`).
+pub(crate) fn locate_adb() -> Option<PathBuf> {
+    locate("platform-tools", "adb")
+}
+
+/// Returns the path of the `emulator` executable, searched in `PATH`, then in the
+/// `emulator` directory of the SDK.
+#[cfg(feature = "client")]
+pub(crate) fn locate_emulator() -> Option<PathBuf> {
+    locate("emulator", "emulator")
+}
+
+/// Returns the path of the executable `name`, searched in `PATH`, then in the `dir`
+/// directory of the SDK.
+fn locate(dir: &str, name: &str) -> Option<PathBuf> {
+    let name = if cfg!(windows) {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    };
+    let path = env::var_os("PATH").unwrap_or_default();
+    let sdk = ["ANDROID_HOME", "ANDROID_SDK_ROOT"]
+        .into_iter()
+        .filter_map(env::var_os)
+        .map(|home| PathBuf::from(home).join(dir));
+    env::split_paths(&path)
+        .chain(sdk)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
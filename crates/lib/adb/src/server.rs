@@ -0,0 +1,1386 @@
+//! This module provides a client for the adb server, speaking the host wire protocol
+//! directly instead of spawning the adb command.
+
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::io::{AsFd, BorrowedFd, OwnedFd};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::process::{Child, Stdio};
+use std::process::{Command, ExitStatus};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{self, Clock};
+use crate::compat;
+use crate::connect::ConnectOptions;
+use crate::device::{Device, DeviceState, Transport};
+use crate::error::AdbError;
+use crate::op::{Op, OpObserver};
+use crate::protocol::{self, Status};
+use crate::sdk;
+#[cfg(unix)]
+use crate::socket::{AcceptFd, LocalAbstract, LocalFileSystem};
+use crate::socket::{AdbSocketFamilies, Tcp};
+use crate::version::{AdbVersion, ServerCapability, VersionAction, VersionMismatchPolicy};
+
+/// A client of the adb server.
+///
+/// Every request opens a new connection to the server, so an `AdbServer` is cheap to clone
+/// and never holds a connection by itself.
+///
+/// The server listens on TCP by default, or on a Unix socket on Unix hosts:
+/// `localfilesystem:<path>`, or `localabstract:<name>` on Linux and Android.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::server::AdbServer;
+///
+/// let server = AdbServer::connect(AdbServer::DEFAULT_ADDR).unwrap();
+/// for device in server.devices().unwrap() {
+///     println!("{:?}: {:?}", device.serial(), device.state());
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct AdbServer {
+    pub(crate) addr: AdbSocketFamilies,
+    pub(crate) version_policy: VersionMismatchPolicy,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) connect_options: ConnectOptions,
+    pub(crate) auto_start: bool,
+    pub(crate) start_timeout: Duration,
+    pub(crate) reissue_idempotent: bool,
+    pub(crate) observer: Option<Arc<dyn OpObserver>>,
+}
+
+impl AdbServer {
+    /// The default port of the adb server.
+    pub const DEFAULT_PORT: u16 = 5037;
+
+    /// The default address of the adb server, `tcp:127.0.0.1:5037`.
+    pub const DEFAULT_ADDR: Tcp = Tcp::new(IpAddr::V4(Ipv4Addr::LOCALHOST), Self::DEFAULT_PORT);
+
+    /// The default time [`Self::connect_or_start`] waits for a started server to listen.
+    pub const DEFAULT_START_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// The environment variable holding the port of the adb server.
+    pub const SERVER_PORT_ENV: &'static str = "ANDROID_ADB_SERVER_PORT";
+
+    /// The environment variable holding the socket of the adb server, e.g. `tcp:5037` or
+    /// `localfilesystem:/run/adb.sock`.
+    pub const SERVER_SOCKET_ENV: &'static str = "ADB_SERVER_SOCKET";
+
+    /// Creates a client of the adb server listening on `addr`, without connecting to it.
+    ///
+    /// For TCP, a missing IP address defaults to `127.0.0.1`, and a missing port to
+    /// [`Self::DEFAULT_PORT`]. Connections fail with [`ErrorKind::Unsupported`] if the server
+    /// can't listen on `addr`, e.g. on a `jdwp:` socket.
+    pub fn new(addr: impl Into<AdbSocketFamilies>) -> Self {
+        let addr = match addr.into() {
+            AdbSocketFamilies::Tcp(tcp) => AdbSocketFamilies::Tcp(Tcp::new(
+                tcp.ip.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                tcp.port.unwrap_or(Self::DEFAULT_PORT),
+            )),
+            addr => addr,
+        };
+        Self {
+            addr,
+            version_policy: VersionMismatchPolicy::default(),
+            clock: clock::system(),
+            connect_options: ConnectOptions::new(),
+            auto_start: true,
+            start_timeout: Self::DEFAULT_START_TIMEOUT,
+            reissue_idempotent: false,
+            observer: None,
+        }
+    }
+
+    /// Creates a client of the adb server listening on `addr`,
+    /// and [checks](Self::check_version) the server version.
+    pub fn connect(addr: impl Into<AdbSocketFamilies>) -> Result<Self, AdbError> {
+        let server = Self::new(addr);
+        server.check_version()?;
+        Ok(server)
+    }
+
+    /// Creates a client of the adb server the `adb` command would talk to, set by the
+    /// [`Self::SERVER_SOCKET_ENV`] or [`Self::SERVER_PORT_ENV`] environment variables, in
+    /// that order, and [`Self::DEFAULT_ADDR`] if neither is set.
+    pub fn from_env() -> Result<Self, AdbError> {
+        if let Ok(socket) = std::env::var(Self::SERVER_SOCKET_ENV) {
+            // The adb command also accepts host names, e.g. `tcp:localhost:5037`.
+            let addr = match socket.parse::<AdbSocketFamilies>() {
+                Ok(addr) => addr,
+                Err(e) => match socket.strip_prefix("tcp:") {
+                    Some(host) => Tcp::from_host(host)?.into(),
+                    None => return Err(e.into()),
+                },
+            };
+            return Ok(Self::new(addr));
+        }
+        match std::env::var(Self::SERVER_PORT_ENV) {
+            Ok(port) => {
+                let port = port.parse().map_err(|e| AdbError::Parse {
+                    value: port,
+                    source_type: "String",
+                    target_type: "u16",
+                    source: Some(Box::new(e)),
+                })?;
+                Ok(Self::new(Tcp::from_port(port)))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Sets the policy applied when the server version doesn't match this client.
+    pub fn version_policy(mut self, policy: VersionMismatchPolicy) -> Self {
+        self.version_policy = policy;
+        self
+    }
+
+    /// Sets the clock used for transfer rates, timeouts and retries, see [`crate::clock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the timeouts and retries of the connections to the server, see [`crate::connect`].
+    pub fn connect_options(mut self, options: ConnectOptions) -> Self {
+        self.connect_options = options;
+        self
+    }
+
+    /// Returns the timeouts and retries of the connections to the server.
+    pub fn get_connect_options(&self) -> &ConnectOptions {
+        &self.connect_options
+    }
+
+    /// Sets whether [`Self::connect_or_start`] starts a server when none listens, `true` by
+    /// default. Disable it where spawning processes isn't allowed.
+    pub fn auto_start(mut self, auto_start: bool) -> Self {
+        self.auto_start = auto_start;
+        self
+    }
+
+    /// Sets how long [`Self::connect_or_start`] waits for a started server to listen.
+    pub fn start_timeout(mut self, timeout: Duration) -> Self {
+        self.start_timeout = timeout;
+        self
+    }
+
+    /// Sets whether queries which change nothing, like [`Self::version`], [`Self::devices`]
+    /// or [`Device::get_state`], are sent again once when the server goes away before
+    /// replying, `false` by default.
+    ///
+    /// Another client, e.g. an IDE or `adb kill-server` in a script, may kill or restart the
+    /// server in the middle of a request, which fails with [`AdbError::ServerGone`]. Other
+    /// requests, like connecting a device or forwarding a port, are never sent again, since
+    /// the server may have handled them before going away.
+    pub fn reissue_idempotent(mut self, reissue: bool) -> Self {
+        self.reissue_idempotent = reissue;
+        self
+    }
+
+    /// Sets the observer of the operations of this client and its devices, see
+    /// [`crate::op`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::op::{OpEvent, OpObserver};
+    /// use adb::server::AdbServer;
+    ///
+    /// #[derive(Debug)]
+    /// struct Log;
+    ///
+    /// impl OpObserver for Log {
+    ///     fn on_event(&self, event: &OpEvent) {
+    ///         eprintln!("{}", event);
+    ///     }
+    /// }
+    ///
+    /// let server = AdbServer::default().observer(Log);
+    /// server.devices().unwrap();
+    /// ```
+    pub fn observer(mut self, observer: impl OpObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Starts an operation requesting `service`, on the device `serial` if any.
+    pub(crate) fn start_op<'a>(&'a self, serial: Option<&'a str>, service: &'a str) -> Op<'a> {
+        Op::start(self.observer.as_deref(), serial, service)
+    }
+
+    /// Returns the address of the adb server.
+    pub fn addr(&self) -> &AdbSocketFamilies {
+        &self.addr
+    }
+
+    /// Returns the address of the adb server if it listens on TCP.
+    pub(crate) fn tcp_addr(&self) -> Option<SocketAddr> {
+        match &self.addr {
+            AdbSocketFamilies::Tcp(Tcp {
+                ip: Some(ip),
+                port: Some(port),
+            }) => Some(SocketAddr::new(*ip, *port)),
+            _ => None,
+        }
+    }
+
+    /// Connects to the server, once.
+    fn connect_stream(&self) -> io::Result<ServerStream> {
+        let timeout = self.connect_options.get_connect_timeout();
+        if let Some(addr) = self.tcp_addr() {
+            return TcpStream::connect_timeout(&addr, timeout).map(ServerStream::Tcp);
+        }
+        match &self.addr {
+            #[cfg(unix)]
+            AdbSocketFamilies::LocalFileSystem(path) => {
+                UnixStream::connect(&path.0).map(ServerStream::Unix)
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            AdbSocketFamilies::LocalAbstract(name) => {
+                #[cfg(target_os = "android")]
+                use std::os::android::net::SocketAddrExt;
+                #[cfg(target_os = "linux")]
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(&name.0)?;
+                UnixStream::connect_addr(&addr).map(ServerStream::Unix)
+            }
+            addr => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("can't connect to an adb server on `{}`", addr),
+            )),
+        }
+    }
+
+    /// Opens a connection to the server and requests `service`.
+    ///
+    /// The returned stream is positioned right after the `OKAY` status. Connecting is retried
+    /// as the [connect options](Self::connect_options) say, the request itself never is. A
+    /// connection closed before the status fails with [`AdbError::ServerGone`].
+    pub(crate) fn open(&self, service: &str) -> Result<ServerStream, AdbError> {
+        let op = self.start_op(None, service);
+        let result = op.in_scope(|| self.open_untracked(service));
+        op.finish(result)
+    }
+
+    /// Like [`Self::open`], without reporting an operation, for the transport of a device
+    /// request reported as a whole.
+    pub(crate) fn open_untracked(&self, service: &str) -> Result<ServerStream, AdbError> {
+        self.raw_untracked(service)?.into_stream()
+    }
+
+    /// Requests the host service `service`, returning the connection with the status of the
+    /// request, whether `OKAY` or `FAIL`.
+    ///
+    /// An escape hatch for the services this crate doesn't wrap: the request is framed and
+    /// the status parsed, what follows is up to the caller. Connecting is retried as the
+    /// [connect options](Self::connect_options) say, and the request is reported as an
+    /// operation to the [observer](Self::observer). Only failing to connect or to read the
+    /// status is an error, a connection closed before the status failing with
+    /// [`AdbError::ServerGone`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::protocol::Status;
+    /// use adb::server::AdbServer;
+    ///
+    /// let raw = AdbServer::default().raw_host_service("host:features").unwrap();
+    /// match raw.status {
+    ///     Status::Okay => println!("accepted"),
+    ///     Status::Fail(message) => println!("rejected: {}", message),
+    /// }
+    /// ```
+    pub fn raw_host_service(&self, service: &str) -> Result<RawService, AdbError> {
+        let op = self.start_op(None, service);
+        match op.in_scope(|| self.raw_untracked(service)) {
+            Ok(raw) => {
+                op.finish_status(&raw.status);
+                Ok(raw)
+            }
+            Err(e) => op.finish(Err(e)),
+        }
+    }
+
+    /// Like [`Self::raw_host_service`], without reporting an operation.
+    fn raw_untracked(&self, service: &str) -> Result<RawService, AdbError> {
+        let options = &self.connect_options;
+        let mut stream = options.get_retry().run(
+            || Ok(self.connect_stream()?),
+            |delay| self.clock.sleep(delay),
+        )?;
+        stream.set_read_timeout(options.get_read_timeout())?;
+        stream.set_write_timeout(options.get_write_timeout())?;
+        protocol::send_request(&mut stream, service).map_err(|e| e.server_gone(service))?;
+        let status = protocol::read_decoded(&mut stream, protocol::decode_status)
+            .map_err(|e| e.server_gone(service))?;
+        Ok(RawService { status, stream })
+    }
+
+    /// Requests `service` and reads the length-prefixed reply as a string.
+    pub(crate) fn request_string(&self, service: &str) -> Result<String, AdbError> {
+        protocol::read_string(&mut self.open(service)?).map_err(|e| e.server_gone(service))
+    }
+
+    /// Requests `service`, which must change nothing on the server, and reads the reply as a
+    /// string, sending it again once if the server goes away and
+    /// [reissuing](Self::reissue_idempotent) is enabled.
+    pub(crate) fn query_string(&self, service: &str) -> Result<String, AdbError> {
+        match self.request_string(service) {
+            Err(e)
+                if self.reissue_idempotent && matches!(e.inner(), AdbError::ServerGone { .. }) =>
+            {
+                self.request_string(service)
+            }
+            result => result,
+        }
+    }
+
+    /// [Checks](Self::check_version) the server version, starting a server first if none
+    /// listens on the address of this client.
+    ///
+    /// The server is started with `adb start-server`, the adb command being searched in
+    /// `PATH` then in the Android SDK, unless [auto start](Self::auto_start) is disabled.
+    /// Then this waits for the server to accept connections, for at most the
+    /// [start timeout](Self::start_timeout).
+    ///
+    /// Returns the version of a mismatching server kept by [`VersionMismatchPolicy::Warn`],
+    /// like [`Self::check_version`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let server = AdbServer::from_env().unwrap();
+    /// if let Some(version) = server.connect_or_start().unwrap() {
+    ///     eprintln!("adb server version {} doesn't match this client", version);
+    /// }
+    /// ```
+    pub fn connect_or_start(&self) -> Result<Option<AdbVersion>, AdbError> {
+        match self.check_version() {
+            Err(e)
+                if self.auto_start
+                    && matches!(
+                        e.inner(),
+                        AdbError::Io(e) if e.kind() == ErrorKind::ConnectionRefused
+                    ) =>
+            {
+                self.start()?;
+                self.wait_listening()?;
+                self.check_version()
+            }
+            result => result,
+        }
+    }
+
+    /// Waits for the server to accept connections, for at most the start timeout.
+    fn wait_listening(&self) -> Result<(), AdbError> {
+        let deadline = self.clock.now() + self.start_timeout;
+        loop {
+            if self.connect_stream().is_ok() {
+                return Ok(());
+            }
+            if self.clock.now() >= deadline {
+                return Err(AdbError::Timeout {
+                    condition: format!("adb server listening on {}", self.addr),
+                    timeout: self.start_timeout,
+                });
+            }
+            self.clock.sleep(START_POLL_INTERVAL);
+        }
+    }
+
+    /// Returns the protocol version of the server (`host:version`).
+    pub fn version(&self) -> Result<AdbVersion, AdbError> {
+        self.query_string("host:version")?.parse()
+    }
+
+    /// Returns `true` if the server supports `capability`, querying its version.
+    pub fn supports(&self, capability: ServerCapability) -> Result<bool, AdbError> {
+        Ok(self.version()?.supports(capability))
+    }
+
+    /// Fails with [`AdbError::ServerUnsupported`] if the server doesn't support
+    /// `capability`.
+    pub(crate) fn require(&self, capability: ServerCapability) -> Result<(), AdbError> {
+        let version = self.version()?;
+        if !version.supports(capability) {
+            return Err(AdbError::ServerUnsupported {
+                needed: capability,
+                found: version,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks the server version and applies the [version policy](Self::version_policy).
+    ///
+    /// With [`VersionMismatchPolicy::Restart`], a mismatching server is killed and a new one
+    /// is started with the adb command found in `PATH`. With [`VersionMismatchPolicy::Warn`],
+    /// the version of the mismatching server is returned, and `None` otherwise.
+    pub fn check_version(&self) -> Result<Option<AdbVersion>, AdbError> {
+        match self.version_policy.check(self.version()?)? {
+            VersionAction::Continue => Ok(None),
+            VersionAction::Mismatch(version) => Ok(Some(version)),
+            VersionAction::Restart => {
+                self.kill()?;
+                self.start().map(|()| None)
+            }
+        }
+    }
+
+    /// Returns the devices known to the server (`host:devices`).
+    pub fn devices(&self) -> Result<Vec<Device>, AdbError> {
+        Ok(parse_devices(&self.query_string("host:devices")?)?
+            .into_iter()
+            .map(|info| {
+                Device::with_state(
+                    self.clone(),
+                    Transport::Serial(info.serial),
+                    Some(info.state),
+                )
+            })
+            .collect())
+    }
+
+    /// Returns a handle to the device with the given serial number.
+    pub fn device(&self, serial: &str) -> Device {
+        Device::new(self.clone(), Transport::Serial(serial.to_string()))
+    }
+
+    /// Returns a handle to the device with the given transport id, see
+    /// [`DeviceQualifiers::transport_id`].
+    ///
+    /// Unlike serial numbers, transport ids are unique, even among devices sharing a serial
+    /// number, but a device gets a new one when it reconnects.
+    pub fn device_by_transport_id(&self, id: u64) -> Device {
+        Device::new(self.clone(), Transport::TransportId(id))
+    }
+
+    /// Returns the devices known to the server, selected by transport id
+    /// (`host:devices-l`), see [`DeviceInfo::transport`].
+    pub fn devices_by_transport_id(&self) -> Result<Vec<Device>, AdbError> {
+        Ok(self
+            .devices_long()?
+            .into_iter()
+            .map(|info| Device::with_state(self.clone(), info.transport(), Some(info.state)))
+            .collect())
+    }
+
+    /// Returns a handle to the only connected device.
+    pub fn any_device(&self) -> Device {
+        Device::new(self.clone(), Transport::Any)
+    }
+
+    /// Returns the devices known to the server with their qualifiers (`host:devices-l`).
+    pub fn devices_long(&self) -> Result<Vec<DeviceInfo>, AdbError> {
+        parse_devices(&self.query_string("host:devices-l")?)
+    }
+
+    /// Kills the server (`host:kill`).
+    ///
+    /// Succeeds if the server goes away before replying, e.g. because another client killed it
+    /// first.
+    pub fn kill(&self) -> Result<(), AdbError> {
+        let mut stream = match self.open("host:kill") {
+            Err(e) if matches!(e.inner(), AdbError::ServerGone { .. }) => return Ok(()),
+            result => result?,
+        };
+        // The server closes the connection once it exits.
+        let _ = stream.read_to_end(&mut Vec::new());
+        Ok(())
+    }
+
+    /// Starts the server with `adb start-server`, using the adb command found in `PATH`, or
+    /// else in the Android SDK.
+    pub fn start(&self) -> Result<(), AdbError> {
+        check_start_status(self.start_command().status()?)
+    }
+
+    /// Spawns an adb server accepting its clients on `listener`
+    /// (`adb -L acceptfd:0 server nodaemon`), returning a client of it and the server
+    /// process.
+    ///
+    /// The adb command is searched like for [`Self::start`]. The server runs until it's
+    /// [killed](Self::kill) or the process is.
+    #[cfg(unix)]
+    pub fn spawn_embedded(listener: ServerListener) -> Result<(Self, Child), AdbError> {
+        let server = Self::new(listener.addr.clone());
+        let child = server
+            .embedded_command(&listener)
+            .stdin(Stdio::from(OwnedFd::from(listener)))
+            .spawn()?;
+        Ok((server, child))
+    }
+
+    /// Returns the command running an embedded server on `listener`.
+    #[cfg(unix)]
+    pub(crate) fn embedded_command(&self, listener: &ServerListener) -> Command {
+        let program = sdk::locate_adb().unwrap_or_else(|| "adb".into());
+        let mut command = Command::new(program);
+        command
+            .env_remove(AdbServer::SERVER_SOCKET_ENV)
+            .arg("-L")
+            .arg(listener.accept_fd().to_string())
+            .args(["server", "nodaemon"]);
+        command
+    }
+
+    /// Returns the `adb start-server` command starting a server on the address of this
+    /// client.
+    pub(crate) fn start_command(&self) -> Command {
+        let program = sdk::locate_adb().unwrap_or_else(|| "adb".into());
+        let mut command = Command::new(program);
+        // The socket variable takes precedence over `-P`, the server must listen where this
+        // client connects.
+        match self.tcp_addr() {
+            Some(addr) => command
+                .env_remove(AdbServer::SERVER_SOCKET_ENV)
+                .arg("-P")
+                .arg(addr.port().to_string()),
+            None => command.env(AdbServer::SERVER_SOCKET_ENV, self.addr.to_string()),
+        };
+        command.arg("start-server");
+        command
+    }
+}
+
+/// A listening socket handed to an embedded adb server, which accepts its clients on it
+/// instead of binding its own socket.
+///
+/// The socket is passed as the standard input of the server, `acceptfd:0`, so that it's
+/// inherited without unsafe code.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::net::TcpListener;
+/// use std::os::unix::io::OwnedFd;
+/// use adb::server::{AdbServer, ServerListener};
+///
+/// let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+/// let listener = ServerListener::new(OwnedFd::from(listener)).unwrap();
+/// let (server, mut child) = AdbServer::spawn_embedded(listener).unwrap();
+/// server.connect_or_start().unwrap();
+/// child.kill().unwrap();
+/// ```
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct ServerListener {
+    fd: OwnedFd,
+    addr: AdbSocketFamilies,
+}
+
+#[cfg(unix)]
+impl ServerListener {
+    /// Takes `fd`, failing if it isn't a bound TCP or Unix socket, or if it's connected.
+    ///
+    /// Without `getsockopt`, a bound socket which doesn't listen yet isn't detected.
+    pub fn new(fd: OwnedFd) -> Result<Self, AdbError> {
+        let invalid = |fd: &OwnedFd, reason: &str| AdbError::Parse {
+            value: format!("{:?}", fd),
+            source_type: "OwnedFd",
+            target_type: "ServerListener",
+            source: Some(reason.into()),
+        };
+        let tcp = std::net::TcpListener::from(fd);
+        if let Ok(addr) = tcp.local_addr() {
+            let fd = OwnedFd::from(tcp);
+            if TcpStream::from(fd.try_clone()?).peer_addr().is_ok() {
+                return Err(invalid(&fd, "connected socket"));
+            }
+            return Ok(Self {
+                fd,
+                addr: Tcp::from(addr).into(),
+            });
+        }
+        let unix = UnixListener::from(OwnedFd::from(tcp));
+        let addr = match unix.local_addr() {
+            Ok(addr) => addr,
+            Err(_) => return Err(invalid(&OwnedFd::from(unix), "not a bound socket")),
+        };
+        let fd = OwnedFd::from(unix);
+        if UnixStream::from(fd.try_clone()?).peer_addr().is_ok() {
+            return Err(invalid(&fd, "connected socket"));
+        }
+        let addr = match addr.as_pathname() {
+            Some(path) => LocalFileSystem(path.to_path_buf()).into(),
+            None => match abstract_name(&addr) {
+                Some(name) => LocalAbstract(name).into(),
+                None => return Err(invalid(&fd, "unnamed socket")),
+            },
+        };
+        Ok(Self { fd, addr })
+    }
+
+    /// Returns the address the socket is bound to.
+    pub fn addr(&self) -> &AdbSocketFamilies {
+        &self.addr
+    }
+
+    /// Returns the socket spec of the listener in the embedded server, `acceptfd:0`.
+    pub fn accept_fd(&self) -> AcceptFd {
+        AcceptFd(0)
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for ServerListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(unix)]
+impl From<ServerListener> for OwnedFd {
+    fn from(listener: ServerListener) -> Self {
+        listener.fd
+    }
+}
+
+/// Returns the abstract name of a Unix socket address, on Linux and Android.
+#[cfg(unix)]
+fn abstract_name(addr: &std::os::unix::net::SocketAddr) -> Option<String> {
+    #[cfg(target_os = "android")]
+    use std::os::android::net::SocketAddrExt;
+    #[cfg(target_os = "linux")]
+    use std::os::linux::net::SocketAddrExt;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return addr
+        .as_abstract_name()
+        .map(|name| String::from_utf8_lossy(name).into_owned());
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = addr;
+        None
+    }
+}
+
+/// A connection requested with [`AdbServer::raw_host_service`] or
+/// [`Device::raw_service`], with the status of the request.
+#[derive(Debug)]
+pub struct RawService {
+    /// The status of the request. On `OKAY`, the stream is positioned right after it, on
+    /// `FAIL` the server closes the connection.
+    pub status: Status,
+    pub stream: ServerStream,
+}
+
+impl RawService {
+    /// Returns the stream of an accepted request, or fails with the error of the `FAIL`
+    /// message, e.g. [`AdbError::DeviceNotFound`].
+    pub fn into_stream(self) -> Result<ServerStream, AdbError> {
+        protocol::check_status(self.status)?;
+        Ok(self.stream)
+    }
+}
+
+/// A connection to the adb server, over TCP or a Unix socket.
+#[derive(Debug)]
+pub enum ServerStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl ServerStream {
+    /// Bounds the time a blocking read waits, `None` waiting forever.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    /// Bounds the time a blocking write waits, `None` waiting forever.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_write_timeout(timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_write_timeout(timeout),
+        }
+    }
+
+    /// Shuts down the read, write, or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.shutdown(how),
+        }
+    }
+
+    /// Returns another handle to the same connection.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Self::Tcp(stream) => stream.try_clone().map(Self::Tcp),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.try_clone().map(Self::Unix),
+        }
+    }
+}
+
+impl Read for ServerStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.read(buf),
+        }?;
+        #[cfg(feature = "tracing")]
+        crate::trace::wire("read", &buf[..read]);
+        Ok(read)
+    }
+}
+
+impl Write for ServerStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match self {
+            Self::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.write(buf),
+        }?;
+        #[cfg(feature = "tracing")]
+        crate::trace::wire("write", &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// The interval between two connections while waiting for a started server.
+pub(crate) const START_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Checks the exit status of [`AdbServer::start_command`].
+pub(crate) fn check_start_status(status: ExitStatus) -> Result<(), AdbError> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(compat::io_other(format!("`adb start-server` exited with {}", status)).into())
+    }
+}
+
+/// Parses the list of devices replied to `host:devices` or `host:devices-l`.
+pub(crate) fn parse_devices(s: &str) -> Result<Vec<DeviceInfo>, AdbError> {
+    s.lines()
+        .filter(|line| !line.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+// The clock and the observer are left out, two clients of the same server with the same
+// policies are equal.
+impl PartialEq for AdbServer {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr
+            && self.version_policy == other.version_policy
+            && self.connect_options == other.connect_options
+            && self.auto_start == other.auto_start
+            && self.start_timeout == other.start_timeout
+            && self.reissue_idempotent == other.reissue_idempotent
+    }
+}
+
+impl Eq for AdbServer {}
+
+impl Hash for AdbServer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.addr.hash(state);
+        self.version_policy.hash(state);
+        self.connect_options.hash(state);
+        self.auto_start.hash(state);
+        self.start_timeout.hash(state);
+        self.reissue_idempotent.hash(state);
+    }
+}
+
+impl Default for AdbServer {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_ADDR)
+    }
+}
+
+/// A device listed by `host:devices` or `host:devices-l`.
+///
+/// # Syntax
+///
+/// - `<serial>\t<state>` for `host:devices`.
+/// - `<serial> <state> [<key>:<value> ...]` for `host:devices-l`, see [`DeviceQualifiers`].
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct DeviceInfo {
+    /// The serial number of the device.
+    pub serial: String,
+    /// The connection state of the device.
+    pub state: DeviceState,
+    /// The qualifiers of the device, only listed by `host:devices-l`.
+    pub qualifiers: DeviceQualifiers,
+}
+
+impl DeviceInfo {
+    /// Returns how to select the device: by transport id if listed, by serial number
+    /// otherwise.
+    pub fn transport(&self) -> Transport {
+        match self.qualifiers.transport_id {
+            Some(id) => Transport::TransportId(id),
+            None => Transport::Serial(self.serial.clone()),
+        }
+    }
+}
+
+impl Display for DeviceInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{}", self.serial, self.state)?;
+        if self.qualifiers != DeviceQualifiers::default() {
+            write!(f, " {}", self.qualifiers)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DeviceInfo {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || AdbError::Parse {
+            value: s.to_string(),
+            source_type: "&str",
+            target_type: "DeviceInfo",
+            source: None,
+        };
+        let (serial, rest) = s.split_once(char::is_whitespace).ok_or_else(err)?;
+        // The state may contain spaces (e.g. `no permissions (...)`), so the qualifiers
+        // are recognized by their keys from the end of the line.
+        let mut words: Vec<&str> = rest.split_whitespace().collect();
+        let qualifiers_start = words
+            .iter()
+            .rposition(|word| !DeviceQualifiers::is_qualifier(word))
+            .map_or(0, |i| i + 1);
+        let qualifiers = words.split_off(qualifiers_start).join(" ").parse()?;
+        let state = words.join(" ");
+        if serial.is_empty() || state.is_empty() {
+            return Err(err());
+        }
+        Ok(Self {
+            serial: serial.to_string(),
+            state: state.parse()?,
+            qualifiers,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_str!(DeviceInfo);
+
+/// The qualifiers of a device listed by `host:devices-l`.
+///
+/// # Syntax
+///
+/// Space separated `<key>:<value>` pairs, with the keys `usb`, `product`, `model`, `device`,
+/// `transport_id`, and on recent adb versions `negotiated_speed` and `max_speed` in Mbit/s.
+///
+/// ```
+/// # use adb::server::DeviceQualifiers;
+/// let qualifiers: DeviceQualifiers = "usb:1-1 model:Pixel_7 negotiated_speed:480 max_speed:5000"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(qualifiers.model.as_deref(), Some("Pixel_7"));
+/// assert!(qualifiers.slow_usb_warning().is_some());
+/// ```
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct DeviceQualifiers {
+    /// The USB bus path of the device, e.g. `1-1`.
+    pub usb: Option<String>,
+    pub product: Option<String>,
+    pub model: Option<String>,
+    pub device: Option<String>,
+    pub transport_id: Option<u64>,
+    /// The negotiated USB speed in Mbit/s.
+    pub negotiated_speed: Option<u64>,
+    /// The maximum USB speed supported by the device in Mbit/s.
+    pub max_speed: Option<u64>,
+}
+
+impl DeviceQualifiers {
+    const KEYS: [&'static str; 7] = [
+        "usb",
+        "product",
+        "model",
+        "device",
+        "transport_id",
+        "negotiated_speed",
+        "max_speed",
+    ];
+
+    /// The fastest USB 2.0 speed in Mbit/s.
+    pub const USB2_SPEED: u64 = 480;
+
+    /// The slowest USB 3.0 speed in Mbit/s.
+    pub const USB3_SPEED: u64 = 5000;
+
+    fn is_qualifier(word: &str) -> bool {
+        word.split_once(':')
+            .is_some_and(|(key, _)| Self::KEYS.contains(&key))
+    }
+
+    /// Returns a warning if the device supports USB 3 but negotiated USB 2.0 or slower,
+    /// which usually means a USB 2.0 cable or hub is in the way and transfers will be slow.
+    pub fn slow_usb_warning(&self) -> Option<String> {
+        match (self.negotiated_speed, self.max_speed) {
+            (Some(negotiated), Some(max))
+                if negotiated <= Self::USB2_SPEED && max >= Self::USB3_SPEED =>
+            {
+                Some(format!(
+                    "device {} negotiated {} Mbit/s although it supports {} Mbit/s, \
+                    check the USB cable and hub",
+                    self.usb.as_deref().unwrap_or("?"),
+                    negotiated,
+                    max
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for DeviceQualifiers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let qualifiers = [
+            self.usb.clone(),
+            self.product.clone(),
+            self.model.clone(),
+            self.device.clone(),
+            self.transport_id.map(|id| id.to_string()),
+            self.negotiated_speed.map(|speed| speed.to_string()),
+            self.max_speed.map(|speed| speed.to_string()),
+        ];
+        let mut first = true;
+        for (key, value) in Self::KEYS.iter().zip(qualifiers) {
+            if let Some(value) = value {
+                if !first {
+                    f.write_str(" ")?;
+                }
+                write!(f, "{}:{}", key, value)?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DeviceQualifiers {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut qualifiers = Self::default();
+        for word in s.split_whitespace() {
+            let err = |source: Option<Box<dyn std::error::Error + Send + Sync>>| AdbError::Parse {
+                value: word.to_string(),
+                source_type: "&str",
+                target_type: "DeviceQualifiers",
+                source,
+            };
+            let (key, value) = word.split_once(':').ok_or_else(|| err(None))?;
+            let number = || value.parse::<u64>().map_err(|e| err(Some(Box::new(e))));
+            match key {
+                "usb" => qualifiers.usb = Some(value.to_string()),
+                "product" => qualifiers.product = Some(value.to_string()),
+                "model" => qualifiers.model = Some(value.to_string()),
+                "device" => qualifiers.device = Some(value.to_string()),
+                "transport_id" => qualifiers.transport_id = Some(number()?),
+                "negotiated_speed" => qualifiers.negotiated_speed = Some(number()?),
+                "max_speed" => qualifiers.max_speed = Some(number()?),
+                _ => return Err(err(None)),
+            }
+        }
+        Ok(qualifiers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::connect::RetryPolicy;
+    use crate::op::{OpEvent, OpStatus};
+    use crate::socket::{Jdwp, LocalFileSystem};
+
+    #[test]
+    fn test_server_addr() {
+        let default = AdbSocketFamilies::from(AdbServer::DEFAULT_ADDR);
+        assert_eq!(&default, AdbServer::default().addr());
+        assert_eq!(
+            &default,
+            AdbServer::new(Tcp::from_ipv4(Ipv4Addr::LOCALHOST)).addr()
+        );
+        assert_eq!(
+            &AdbSocketFamilies::from(Tcp::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5038)),
+            AdbServer::new(Tcp::from_port(5038)).addr()
+        );
+        let unix = AdbSocketFamilies::LocalFileSystem(LocalFileSystem("/run/adb.sock".into()));
+        assert_eq!(&unix, AdbServer::new(unix.clone()).addr());
+        assert_eq!(None, AdbServer::new(unix).tcp_addr());
+    }
+
+    #[test]
+    fn test_server_unsupported_addr() {
+        let server = AdbServer::new(Jdwp(1234))
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::none()));
+        assert!(matches!(
+            server.version().map_err(AdbError::into_inner),
+            Err(AdbError::Io(e)) if e.kind() == ErrorKind::Unsupported
+        ));
+        let command = server.start_command();
+        assert!(command.get_envs().any(|(key, value)| {
+            key == AdbServer::SERVER_SOCKET_ENV && value == Some("jdwp:1234".as_ref())
+        }));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_server_listener() {
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = tcp.local_addr().unwrap().port();
+        let listener = ServerListener::new(OwnedFd::from(tcp)).unwrap();
+        assert_eq!(
+            &AdbSocketFamilies::from(Tcp::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)),
+            listener.addr()
+        );
+        let server = AdbServer::new(listener.addr().clone());
+        let command = server.embedded_command(&listener);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(["-L", "acceptfd:0", "server", "nodaemon"], args[..]);
+
+        let connected = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        assert!(ServerListener::new(OwnedFd::from(connected)).is_err());
+        let file = std::fs::File::open(std::env::current_exe().unwrap()).unwrap();
+        assert!(ServerListener::new(OwnedFd::from(file)).is_err());
+
+        let path = std::env::temp_dir().join(format!("adb-listener-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let unix = UnixListener::bind(&path).unwrap();
+        let listener = ServerListener::new(OwnedFd::from(unix)).unwrap();
+        assert_eq!(
+            &AdbSocketFamilies::from(LocalFileSystem(path.clone())),
+            listener.addr()
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_server_unix_socket() {
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("adb-server-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = AdbServer::new(LocalFileSystem(path.clone()));
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 16];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(b"000chost:version", &request);
+            stream.write_all(b"OKAY00040029").unwrap();
+        });
+        assert_eq!(
+            "0029".parse::<AdbVersion>().unwrap(),
+            server.version().unwrap()
+        );
+        handle.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_server_gone_reissue() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            // The first two connections are closed unanswered, as by a server being killed.
+            for reply in [None, None, Some(&b"OKAY00040029"[..])] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 16];
+                stream.read_exact(&mut request).unwrap();
+                assert_eq!(b"000chost:version", &request);
+                if let Some(reply) = reply {
+                    stream.write_all(reply).unwrap();
+                }
+            }
+        });
+        let server = AdbServer::new(Tcp::from_port(port))
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::none()));
+        assert!(matches!(
+            server.version().map_err(AdbError::into_inner),
+            Err(AdbError::ServerGone { service }) if service == "host:version"
+        ));
+        let server = server.reissue_idempotent(true);
+        assert_eq!(
+            "0029".parse::<AdbVersion>().unwrap(),
+            server.version().unwrap()
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_server_observer() {
+        #[derive(Debug, Default)]
+        struct Events(std::sync::Mutex<Vec<OpEvent>>);
+
+        impl OpObserver for Arc<Events> {
+            fn on_event(&self, event: &OpEvent) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            for reply in [Some(&b"OKAY00040029"[..]), None] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 16];
+                stream.read_exact(&mut request).unwrap();
+                if let Some(reply) = reply {
+                    stream.write_all(reply).unwrap();
+                }
+            }
+        });
+        let events = Arc::new(Events::default());
+        let server = AdbServer::new(Tcp::from_port(port))
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::none()))
+            .observer(events.clone());
+        server.version().unwrap();
+        assert!(server.version().is_err());
+        handle.join().unwrap();
+
+        let events = events.0.lock().unwrap();
+        let statuses: Vec<_> = events.iter().map(|e| (e.id, e.status.clone())).collect();
+        let (first, second) = (events[0].id, events[2].id);
+        assert!(first < second);
+        assert_eq!(
+            [
+                (first, OpStatus::Started),
+                (first, OpStatus::Accepted),
+                (second, OpStatus::Started),
+            ],
+            &statuses[..3]
+        );
+        assert!(matches!(&statuses[3], (id, OpStatus::Failed(_)) if *id == second));
+        assert!(events
+            .iter()
+            .all(|e| e.service == "host:version" && e.serial.is_none()));
+    }
+
+    #[test]
+    fn test_server_raw_host_service() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            for reply in [&b"OKAYpayload"[..], b"FAIL0007unknown"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 12];
+                stream.read_exact(&mut request).unwrap();
+                assert_eq!(b"0008host:foo", &request);
+                stream.write_all(reply).unwrap();
+            }
+        });
+        let server = AdbServer::new(Tcp::from_port(port))
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::none()));
+        let raw = server.raw_host_service("host:foo").unwrap();
+        assert_eq!(Status::Okay, raw.status);
+        let mut payload = String::new();
+        raw.into_stream()
+            .unwrap()
+            .read_to_string(&mut payload)
+            .unwrap();
+        assert_eq!("payload", payload);
+
+        let raw = server.raw_host_service("host:foo").unwrap();
+        assert_eq!(Status::Fail("unknown".to_string()), raw.status);
+        assert!(matches!(
+            raw.into_stream(),
+            Err(AdbError::Server { message }) if message == "unknown"
+        ));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_server_eq_ignores_clock() {
+        let server = AdbServer::default().clock(MockClock::new());
+        assert_eq!(AdbServer::default(), server);
+        assert_ne!(
+            server,
+            AdbServer::default().version_policy(VersionMismatchPolicy::Warn)
+        );
+    }
+
+    #[test]
+    fn test_server_open_retries() {
+        // A port nothing listens on refuses connections.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let clock = MockClock::new();
+        let start = clock.now();
+        let server = AdbServer::new(Tcp::from_port(port))
+            .clock(clock.clone())
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::new().retries(3)));
+        assert!(matches!(
+            server.version().map_err(AdbError::into_inner),
+            Err(AdbError::Io(_))
+        ));
+        assert_eq!(Duration::from_millis(700), clock.now() - start);
+        assert_ne!(AdbServer::default(), server);
+    }
+
+    #[test]
+    fn test_server_check_version_warn() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            for reply in [&b"OKAY00040028"[..], b"OKAY00040028"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                protocol::read_string(&mut stream).unwrap();
+                stream.write_all(reply).unwrap();
+            }
+        });
+        let server = AdbServer::new(Tcp::from_port(port))
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::none()))
+            .version_policy(VersionMismatchPolicy::Warn);
+        assert_eq!(Some(AdbVersion(40)), server.check_version().unwrap());
+        assert_eq!(Some(AdbVersion(40)), server.connect_or_start().unwrap());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_server_connect_or_start() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let clock = MockClock::new();
+        let server = AdbServer::new(Tcp::from_port(port))
+            .clock(clock.clone())
+            .connect_options(ConnectOptions::new().retry(RetryPolicy::none()))
+            .start_timeout(Duration::from_secs(1));
+        let disabled = server.clone().auto_start(false);
+        assert!(matches!(
+            disabled.connect_or_start().map_err(AdbError::into_inner),
+            Err(AdbError::Io(_))
+        ));
+        let start = clock.now();
+        assert!(matches!(
+            server.wait_listening(),
+            Err(AdbError::Timeout { .. })
+        ));
+        assert_eq!(Duration::from_secs(1), clock.now() - start);
+
+        let command = server.start_command();
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(["-P", &port.to_string(), "start-server"], args[..]);
+        assert!(command
+            .get_envs()
+            .any(|(key, value)| key == AdbServer::SERVER_SOCKET_ENV && value.is_none()));
+    }
+
+    #[test]
+    fn test_device_info_parse() {
+        let info = DeviceInfo {
+            serial: "emulator-5554".to_string(),
+            state: DeviceState::Device,
+            qualifiers: DeviceQualifiers::default(),
+        };
+        assert_eq!(info, "emulator-5554\tdevice".parse().unwrap());
+        assert_eq!("emulator-5554\tdevice", info.to_string());
+        let info: DeviceInfo = "emulator-5554\tonline".parse().unwrap();
+        assert_eq!(DeviceState::Other("online".to_string()), info.state);
+        let err = ["", "emulator-5554", "emulator-5554\t", "\tdevice"];
+        for s in err {
+            assert!(s.parse::<DeviceInfo>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_device_info_parse_long() {
+        let info: DeviceInfo = "0123456789ABCDEF       device usb:1-1 product:panther \
+            model:Pixel_7 device:panther transport_id:2 negotiated_speed:480 max_speed:5000"
+            .parse()
+            .unwrap();
+        assert_eq!("0123456789ABCDEF", info.serial);
+        assert_eq!(DeviceState::Device, info.state);
+        assert_eq!(
+            DeviceQualifiers {
+                usb: Some("1-1".to_string()),
+                product: Some("panther".to_string()),
+                model: Some("Pixel_7".to_string()),
+                device: Some("panther".to_string()),
+                transport_id: Some(2),
+                negotiated_speed: Some(480),
+                max_speed: Some(5000),
+            },
+            info.qualifiers
+        );
+        let info: DeviceInfo = "0123456789ABCDEF       no permissions (missing udev rules?); \
+            see [http://developer.android.com/tools/device.html] usb:1-1 transport_id:3"
+            .parse()
+            .unwrap();
+        assert_eq!(DeviceState::NoPermissions, info.state);
+        assert_eq!(Some(3), info.qualifiers.transport_id);
+    }
+
+    #[test]
+    fn test_device_info_transport() {
+        let info: DeviceInfo = "0123456789ABCDEF\tdevice transport_id:7".parse().unwrap();
+        assert_eq!(Transport::TransportId(7), info.transport());
+        let device = AdbServer::default().device_by_transport_id(7);
+        assert_eq!(Some(7), device.transport_id());
+        assert_eq!(None, device.serial());
+        let info: DeviceInfo = "emulator-5554\tdevice".parse().unwrap();
+        assert_eq!(
+            Transport::Serial("emulator-5554".to_string()),
+            info.transport()
+        );
+    }
+
+    #[test]
+    fn test_device_info_long_round_trip() {
+        let lines = [
+            "emulator-5554\tdevice product:sdk_gphone64_x86_64 model:sdk_gphone64_x86_64 \
+             device:emu64x transport_id:1",
+            "0123456789ABCDEF\tunauthorized usb:1-1 transport_id:2",
+            "0123456789ABCDEF\tno permissions usb:1-1 transport_id:3",
+        ];
+        for line in lines {
+            let info: DeviceInfo = line.parse().unwrap();
+            assert_eq!(line, info.to_string());
+            assert_eq!(info, info.to_string().parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_device_qualifiers_parse() {
+        let s = "usb:1-1 model:Pixel_7 transport_id:2 negotiated_speed:5000 max_speed:5000";
+        let qualifiers: DeviceQualifiers = s.parse().unwrap();
+        assert_eq!(s, qualifiers.to_string());
+        assert!(qualifiers.slow_usb_warning().is_none());
+        for s in ["usb", "transport_id:x", "max_speed:-1", "color:red"] {
+            assert!(s.parse::<DeviceQualifiers>().is_err(), "{}", s);
+        }
+    }
+}
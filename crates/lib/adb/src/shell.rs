@@ -0,0 +1,680 @@
+//! This module provides the shell services of a device.
+//!
+//! Devices advertising the `shell_v2` feature support the shell v2 protocol, which
+//! separates stdout from stderr and reports the exit code. Every message is a packet
+//! made of a 1 byte id, the length of its data as a little-endian `u32`, and the data.
+//! Older devices only support the legacy `shell:` service, merging stdout and stderr.
+//!
+//! Devices advertising the `abb` and `abb_exec` features also run binder commands, like
+//! `cmd <service> <args>`, through the binder bridge. Its arguments are separated by NUL
+//! bytes instead of being parsed by a shell, so they need no quoting.
+//!
+//! Long-running and interactive commands are driven through a [`ShellStream`], writing to
+//! their stdin and reading their output as it's produced.
+
+use std::io::{self, Read, Write};
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::features::Feature;
+use crate::protocol::{self, Decoded};
+use crate::server::ServerStream;
+
+/// The maximum size of a shell v2 packet accepted from a device.
+pub const MAX_PACKET_SIZE: usize = 1 << 20;
+
+/// The id of a shell v2 packet.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum PacketId {
+    Stdin,
+    Stdout,
+    Stderr,
+    /// The exit code of the command as a single byte.
+    Exit,
+    /// Closes the stdin of the command.
+    CloseStdin,
+    /// Resizes the terminal, as `<rows>x<cols>,<x_pixels>x<y_pixels>\0`.
+    WindowSizeChange,
+}
+
+impl PacketId {
+    /// Returns the id as sent over the wire.
+    pub const fn to_u8(self) -> u8 {
+        match self {
+            Self::Stdin => 0,
+            Self::Stdout => 1,
+            Self::Stderr => 2,
+            Self::Exit => 3,
+            Self::CloseStdin => 4,
+            Self::WindowSizeChange => 5,
+        }
+    }
+
+    /// Returns the id sent over the wire as `id`.
+    pub const fn from_u8(id: u8) -> Option<Self> {
+        Some(match id {
+            0 => Self::Stdin,
+            1 => Self::Stdout,
+            2 => Self::Stderr,
+            3 => Self::Exit,
+            4 => Self::CloseStdin,
+            5 => Self::WindowSizeChange,
+            _ => return None,
+        })
+    }
+}
+
+/// A shell v2 packet.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Packet {
+    pub id: PacketId,
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    /// Encodes the packet for the wire.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(5 + self.data.len());
+        packet.push(self.id.to_u8());
+        packet.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&self.data);
+        packet
+    }
+}
+
+/// Decodes a shell v2 packet.
+pub fn decode_packet(bytes: &[u8]) -> Result<Decoded<Packet>, AdbError> {
+    let Some(header) = bytes.get(..5) else {
+        return Ok(Decoded::Incomplete { needed: 5 });
+    };
+    let id = PacketId::from_u8(header[0]).ok_or_else(|| AdbError::Protocol {
+        code: Some(header[0].into()),
+        message: format!("unknown shell packet id {}", header[0]),
+    })?;
+    let length = u32::from_le_bytes(header[1..].try_into().unwrap()) as usize;
+    if length > MAX_PACKET_SIZE {
+        return Err(AdbError::Protocol {
+            code: None,
+            message: format!("shell packet of {} bytes is too large", length),
+        });
+    }
+    Ok(match bytes.get(5..5 + length) {
+        Some(data) => Decoded::Complete {
+            value: Packet {
+                id,
+                data: data.to_vec(),
+            },
+            length: 5 + length,
+        },
+        None => Decoded::Incomplete { needed: 5 + length },
+    })
+}
+
+/// Quotes `arg` for the device shell, e.g. `it's` into `'it'\''s'`.
+pub fn quote(arg: &str) -> String {
+    protocol::quote_arg(arg)
+}
+
+/// Encodes the arguments of a binder bridge command, separated by NUL bytes.
+///
+/// Fails if an argument contains a NUL byte.
+///
+/// # Examples
+///
+/// ```
+/// use adb::shell::encode_abb_args;
+///
+/// assert_eq!("package\0list\0packages", encode_abb_args(&["package", "list", "packages"]).unwrap());
+/// assert!(encode_abb_args(&["a\0b"]).is_err());
+/// ```
+pub fn encode_abb_args<S: AsRef<str>>(args: &[S]) -> Result<String, AdbError> {
+    if let Some(arg) = args.iter().find(|arg| arg.as_ref().contains('\0')) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "NUL byte in binder bridge argument `{}`",
+                arg.as_ref().escape_debug()
+            ),
+        )
+        .into());
+    }
+    Ok(args
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>()
+        .join("\0"))
+}
+
+/// The output of a shell command.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ShellOutput {
+    pub stdout: Vec<u8>,
+    /// Always empty with the legacy shell protocol, which merges stderr into stdout.
+    pub stderr: Vec<u8>,
+    pub exit_code: u8,
+}
+
+impl ShellOutput {
+    /// Returns `true` if the command exited with code 0.
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+
+    /// Returns stdout as a string, replacing invalid UTF-8 sequences.
+    pub fn stdout_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).into_owned()
+    }
+
+    /// Returns stderr as a string, replacing invalid UTF-8 sequences.
+    pub fn stderr_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
+}
+
+/// A stream of the stdin and stdout of a running shell command, created by
+/// [`Device::shell_stream`] and [`Device::shell_stream_pty`].
+///
+/// With the shell v2 protocol, reads return stdout and collect stderr separately, writes are
+/// sent as stdin packets, and the stream ends when the command exits, with its exit code.
+/// The legacy shell protocol carries raw bytes, with stderr merged into stdout and no exit
+/// code.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::{BufRead, BufReader, Write};
+/// use adb::server::AdbServer;
+///
+/// let device = AdbServer::default().any_device();
+/// let mut shell = device.shell_stream("sh").unwrap();
+/// shell.write_all(b"echo $((6 * 7))\n").unwrap();
+/// shell.close_stdin().unwrap();
+/// let mut line = String::new();
+/// BufReader::new(&mut shell).read_line(&mut line).unwrap();
+/// assert_eq!("42\n", line);
+/// ```
+#[derive(Debug)]
+pub struct ShellStream<S: Read + Write = ServerStream> {
+    stream: S,
+    v2: bool,
+    stdout: Vec<u8>,
+    position: usize,
+    stderr: Vec<u8>,
+    exit_code: Option<u8>,
+}
+
+impl<S: Read + Write> ShellStream<S> {
+    /// Wraps a stream of the `shell,v2` service if `v2` is set, or of the legacy `shell`
+    /// service otherwise.
+    pub fn new(stream: S, v2: bool) -> Self {
+        Self {
+            stream,
+            v2,
+            stdout: Vec::new(),
+            position: 0,
+            stderr: Vec::new(),
+            exit_code: None,
+        }
+    }
+
+    /// Returns `true` if the stream speaks the shell v2 protocol.
+    pub fn is_v2(&self) -> bool {
+        self.v2
+    }
+
+    /// Returns the exit code of the command, once it exited and its output was read.
+    ///
+    /// Always `None` with the legacy shell protocol.
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit_code
+    }
+
+    /// Returns the stderr received so far, and clears it.
+    ///
+    /// Always empty with the legacy shell protocol.
+    pub fn take_stderr(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.stderr)
+    }
+
+    fn write_packet(&mut self, id: PacketId, data: &[u8]) -> Result<(), AdbError> {
+        if !self.v2 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{:?} needs the shell v2 protocol", id),
+            )
+            .into());
+        }
+        let packet = Packet {
+            id,
+            data: data.to_vec(),
+        };
+        self.stream.write_all(&packet.encode())?;
+        Ok(self.stream.flush()?)
+    }
+
+    /// Resizes the terminal of a command run with a pty.
+    ///
+    /// Fails with the legacy shell protocol.
+    pub fn resize(&mut self, rows: u16, cols: u16) -> Result<(), AdbError> {
+        let size = format!("{}x{},0x0\0", rows, cols);
+        self.write_packet(PacketId::WindowSizeChange, size.as_bytes())
+    }
+
+    /// Closes the stdin of the command, which still runs until it exits.
+    ///
+    /// Fails with the legacy shell protocol, which can't close stdin alone.
+    pub fn close_stdin(&mut self) -> Result<(), AdbError> {
+        self.write_packet(PacketId::CloseStdin, &[])
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Reads packets until one carries stdout, or the command exits.
+    fn fill_stdout(&mut self) -> Result<(), AdbError> {
+        while self.position == self.stdout.len() && self.exit_code.is_none() {
+            let packet = protocol::read_decoded(&mut self.stream, decode_packet)?;
+            match packet.id {
+                PacketId::Stdout => {
+                    self.stdout = packet.data;
+                    self.position = 0;
+                }
+                PacketId::Stderr => self.stderr.extend(packet.data),
+                PacketId::Exit => {
+                    self.exit_code = Some(packet.data.first().copied().unwrap_or_default())
+                }
+                id => {
+                    return Err(AdbError::Protocol {
+                        code: None,
+                        message: format!("unexpected shell packet {:?} from device", id),
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> Read for ShellStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.v2 {
+            return self.stream.read(buf);
+        }
+        self.fill_stdout().map_err(AdbError::into_io)?;
+        let remaining = &self.stdout[self.position..];
+        let length = remaining.len().min(buf.len());
+        buf[..length].copy_from_slice(&remaining[..length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+impl<S: Read + Write> Write for ShellStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.v2 {
+            return self.stream.write(buf);
+        }
+        let length = buf.len().min(MAX_PACKET_SIZE);
+        self.write_packet(PacketId::Stdin, &buf[..length])
+            .map_err(AdbError::into_io)?;
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Marks the exit code appended to the output of legacy shell commands.
+const EXIT_CODE_MARKER: &str = "\x1fdisanger-exit:";
+
+/// Splits the exit code appended by [`Device::shell_legacy`] from the output.
+fn split_exit_code(mut output: Vec<u8>) -> Result<ShellOutput, AdbError> {
+    let marker = EXIT_CODE_MARKER.as_bytes();
+    let position = output
+        .windows(marker.len())
+        .rposition(|window| window == marker)
+        .ok_or_else(|| AdbError::Protocol {
+            code: None,
+            message: "missing exit code in legacy shell output".to_string(),
+        })?;
+    let exit_code = std::str::from_utf8(&output[position + marker.len()..])
+        .ok()
+        .and_then(|code| code.trim().parse::<u8>().ok())
+        .ok_or_else(|| AdbError::Protocol {
+            code: None,
+            message: "invalid exit code in legacy shell output".to_string(),
+        })?;
+    output.truncate(position);
+    // Remove the line break printed before the marker, translated to `\r\n` by a pty.
+    if output.ends_with(b"\n") {
+        output.pop();
+        if output.ends_with(b"\r") {
+            output.pop();
+        }
+    }
+    Ok(ShellOutput {
+        stdout: output,
+        stderr: Vec::new(),
+        exit_code,
+    })
+}
+
+impl Device {
+    /// Runs `command` on the device and waits for it to exit.
+    ///
+    /// Uses the shell v2 protocol if the device supports it, and falls back to
+    /// [`Self::shell_legacy`] otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let output = device.shell("getprop ro.product.model").unwrap();
+    /// assert!(output.success());
+    /// println!("{}", output.stdout_lossy().trim());
+    /// ```
+    pub fn shell(&self, command: &str) -> Result<ShellOutput, AdbError> {
+        if self.has_feature(Feature::ShellV2)? {
+            self.shell_v2(command)
+        } else {
+            self.shell_legacy(command)
+        }
+    }
+
+    /// Runs `command` and returns its stdout, failing if it exits with a non-zero code.
+    pub(crate) fn shell_checked(&self, command: &str) -> Result<String, AdbError> {
+        let output = self.shell(command)?;
+        if output.success() {
+            Ok(output.stdout_lossy())
+        } else {
+            // The legacy protocol merges stderr into stdout.
+            let message = if output.stderr.is_empty() {
+                output.stdout_lossy()
+            } else {
+                output.stderr_lossy()
+            };
+            Err(AdbError::Server {
+                message: format!(
+                    "`{}` exited with {}: {}",
+                    command,
+                    output.exit_code,
+                    message.trim_end()
+                ),
+            })
+        }
+    }
+
+    /// Runs `command` with the shell v2 protocol (`shell,v2,raw:<command>`).
+    ///
+    /// Fails with [`AdbError::Unsupported`] if the device or the server doesn't support the
+    /// shell v2 protocol.
+    pub fn shell_v2(&self, command: &str) -> Result<ShellOutput, AdbError> {
+        let mut stream = self
+            .open(&format!("shell,v2,raw:{}", command))
+            .map_err(|e| self.unsupported_or(Feature::ShellV2, e))?;
+        read_output(&mut stream)
+    }
+
+    /// Starts `command` without a pty, returning a stream of its stdin and stdout.
+    ///
+    /// Uses the shell v2 protocol if the device supports it, and falls back to the legacy
+    /// shell protocol otherwise, which always allocates a pty.
+    pub fn shell_stream(&self, command: &str) -> Result<ShellStream, AdbError> {
+        self.open_shell_stream("raw", command)
+    }
+
+    /// Starts `command` with a pty, e.g. for `top` or an interactive `sh`, returning a
+    /// stream of its stdin and its output.
+    pub fn shell_stream_pty(&self, command: &str) -> Result<ShellStream, AdbError> {
+        self.open_shell_stream("pty", command)
+    }
+
+    fn open_shell_stream(&self, mode: &str, command: &str) -> Result<ShellStream, AdbError> {
+        Ok(if self.has_feature(Feature::ShellV2)? {
+            ShellStream::new(self.open(&format!("shell,v2,{}:{}", mode, command))?, true)
+        } else {
+            ShellStream::new(self.open(&format!("shell:{}", command))?, false)
+        })
+    }
+
+    /// Runs a binder command through the binder bridge (`abb:<args>`) and waits for it to
+    /// exit, e.g. `["package", "list", "packages"]` for `cmd package list packages`.
+    ///
+    /// Fails with [`AdbError::Unsupported`] if the device or the server doesn't support the
+    /// `abb` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let output = device.abb(&["package", "path", "com.android.shell"]).unwrap();
+    /// println!("{}", output.stdout_lossy().trim());
+    /// ```
+    pub fn abb<S: AsRef<str>>(&self, args: &[S]) -> Result<ShellOutput, AdbError> {
+        let mut stream = self
+            .open(&format!("abb:{}", encode_abb_args(args)?))
+            .map_err(|e| self.unsupported_or(Feature::Abb, e))?;
+        read_output(&mut stream)
+    }
+
+    /// Opens a binder command without a pty nor the shell protocol (`abb_exec:<args>`),
+    /// returning a raw stream of its stdin and stdout, like `exec:`.
+    ///
+    /// Fails with [`AdbError::Unsupported`] if the device or the server doesn't support the
+    /// `abb_exec` feature.
+    pub fn abb_exec<S: AsRef<str>>(&self, args: &[S]) -> Result<ServerStream, AdbError> {
+        self.open(&format!("abb_exec:{}", encode_abb_args(args)?))
+            .map_err(|e| self.unsupported_or(Feature::AbbExec, e))
+    }
+
+    /// Runs `command` with the legacy shell protocol (`shell:<command>`).
+    ///
+    /// The protocol doesn't report the exit code, so it's printed after the output of
+    /// the command and removed from stdout. Stderr is merged into stdout.
+    pub fn shell_legacy(&self, command: &str) -> Result<ShellOutput, AdbError> {
+        // Run the command in a subshell on its own lines, so that neither `exit`
+        // nor a trailing comment can skip printing the exit code.
+        let mut stream = self.open(&format!(
+            "shell:(\n{}\n); printf '\\n{}%d' $?",
+            command, EXIT_CODE_MARKER
+        ))?;
+        let mut output = Vec::new();
+        stream.read_to_end(&mut output)?;
+        split_exit_code(output)
+    }
+}
+
+/// Reads the shell v2 packets of a command until it exits.
+fn read_output<S: Read>(stream: &mut S) -> Result<ShellOutput, AdbError> {
+    let mut output = ShellOutput::default();
+    loop {
+        let packet = protocol::read_decoded(stream, decode_packet)?;
+        match packet.id {
+            PacketId::Stdout => output.stdout.extend(packet.data),
+            PacketId::Stderr => output.stderr.extend(packet.data),
+            PacketId::Exit => {
+                output.exit_code = packet.data.first().copied().unwrap_or_default();
+                return Ok(output);
+            }
+            id => {
+                return Err(AdbError::Protocol {
+                    code: None,
+                    message: format!("unexpected shell packet {:?} from device", id),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!("'a b'", quote("a b"));
+        assert_eq!("'it'\\''s'", quote("it's"));
+        assert_eq!("''", quote(""));
+    }
+
+    #[test]
+    fn test_packet_encode_decode() {
+        let packet = Packet {
+            id: PacketId::Stdout,
+            data: b"hello".to_vec(),
+        };
+        let encoded = packet.encode();
+        assert_eq!(b"\x01\x05\x00\x00\x00hello", &encoded[..]);
+        assert_eq!(
+            Decoded::Complete {
+                value: packet,
+                length: 10
+            },
+            decode_packet(&encoded).unwrap()
+        );
+        assert_eq!(
+            Decoded::Incomplete { needed: 10 },
+            decode_packet(&encoded[..7]).unwrap()
+        );
+        assert_eq!(
+            Decoded::Incomplete { needed: 5 },
+            decode_packet(b"").unwrap()
+        );
+        assert!(decode_packet(b"\x09\x00\x00\x00\x00").is_err());
+        assert!(decode_packet(b"\x01\xff\xff\xff\xff").is_err());
+    }
+
+    #[test]
+    fn test_split_exit_code() {
+        let output = split_exit_code(format!("a\nb\n\n{}1", EXIT_CODE_MARKER).into()).unwrap();
+        assert_eq!(b"a\nb\n", &output.stdout[..]);
+        assert_eq!(1, output.exit_code);
+        let output = split_exit_code(format!("a\r\n\r\n{}0", EXIT_CODE_MARKER).into()).unwrap();
+        assert_eq!(b"a\r\n", &output.stdout[..]);
+        assert!(output.success());
+        assert!(split_exit_code(b"a\n".to_vec()).is_err());
+        assert!(split_exit_code(format!("\n{}256", EXIT_CODE_MARKER).into()).is_err());
+    }
+
+    /// A stream replaying canned packets and recording the written ones.
+    struct MockStream {
+        replies: io::Cursor<Vec<u8>>,
+        requests: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.requests.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packets(packets: &[(PacketId, &[u8])]) -> Vec<u8> {
+        packets
+            .iter()
+            .flat_map(|&(id, data)| {
+                Packet {
+                    id,
+                    data: data.to_vec(),
+                }
+                .encode()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_shell_stream() {
+        let replies = packets(&[
+            (PacketId::Stdout, b"hello "),
+            (PacketId::Stderr, b"oops"),
+            (PacketId::Stdout, b"world"),
+            (PacketId::Exit, b"\x03"),
+        ]);
+        let mut stream = ShellStream::new(
+            MockStream {
+                replies: io::Cursor::new(replies),
+                requests: Vec::new(),
+            },
+            true,
+        );
+        stream.write_all(b"ls\n").unwrap();
+        stream.resize(24, 80).unwrap();
+        stream.close_stdin().unwrap();
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+        assert_eq!("hello world", output);
+        assert_eq!(b"oops", &stream.take_stderr()[..]);
+        assert_eq!(Some(3), stream.exit_code());
+        let requests = packets(&[
+            (PacketId::Stdin, b"ls\n"),
+            (PacketId::WindowSizeChange, b"24x80,0x0\0"),
+            (PacketId::CloseStdin, b""),
+        ]);
+        assert_eq!(requests, stream.into_inner().requests);
+
+        let mut legacy = ShellStream::new(
+            MockStream {
+                replies: io::Cursor::new(b"raw".to_vec()),
+                requests: Vec::new(),
+            },
+            false,
+        );
+        legacy.write_all(b"ls\n").unwrap();
+        assert!(legacy.close_stdin().is_err());
+        let mut output = String::new();
+        legacy.read_to_string(&mut output).unwrap();
+        assert_eq!("raw", output);
+        assert_eq!(None, legacy.exit_code());
+        assert_eq!(b"ls\n", &legacy.into_inner().requests[..]);
+    }
+
+    #[test]
+    fn test_read_output() {
+        let mut bytes = Vec::new();
+        for (id, data) in [
+            (PacketId::Stdout, &b"out"[..]),
+            (PacketId::Stderr, b"err"),
+            (PacketId::Exit, b"\x02"),
+        ] {
+            bytes.extend(
+                Packet {
+                    id,
+                    data: data.to_vec(),
+                }
+                .encode(),
+            );
+        }
+        let output = read_output(&mut &bytes[..]).unwrap();
+        assert_eq!(b"out", &output.stdout[..]);
+        assert_eq!(b"err", &output.stderr[..]);
+        assert_eq!(2, output.exit_code);
+        assert!(read_output(&mut &bytes[..8]).is_err());
+    }
+
+    #[test]
+    fn test_encode_abb_args() {
+        assert_eq!(
+            "package\0install\0-S\x00100",
+            encode_abb_args(&["package", "install", "-S", "100"]).unwrap()
+        );
+        assert_eq!("a b\0'c'", encode_abb_args(&["a b", "'c'"]).unwrap());
+        assert_eq!("", encode_abb_args::<&str>(&[]).unwrap());
+        assert!(encode_abb_args(&["a", "b\0"]).is_err());
+    }
+}
@@ -0,0 +1,386 @@
+//! This module provides start-up latency measurements of applications, see
+//! [`Device::measure_app_startup`].
+//!
+//! Every launch starts the launcher activity with `am start -W`, which reports the time to
+//! draw the first frame, after bringing the application in the state of the [`StartupKind`]:
+//! force-stopped for a cold start, in the background with its activity destroyed by the back
+//! key for a warm start, and in the background with its activity stopped by the home key
+//! for a hot start. The frames drawn during each launch are read from `dumpsys gfxinfo`,
+//! whose counters are reset beforehand.
+//!
+//! Since Android 12, the back key moves root activities to the background instead of
+//! destroying them, so warm starts are reported as hot, see [`StartupSample::launch_state`].
+
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use crate::am::Intent;
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::input::KeyCode;
+use crate::shell;
+
+/// The time to let an application settle after moving it to the background, or launching it
+/// before a warm or hot start.
+pub const SETTLE_DELAY: Duration = Duration::from_secs(1);
+
+/// The state of an application before it's launched.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum StartupKind {
+    /// The process isn't running.
+    Cold,
+    /// The process is running, without any activity.
+    Warm,
+    /// The process is running, with its activity stopped in the background.
+    Hot,
+}
+
+impl StartupKind {
+    /// Returns the `LaunchState` reported by `am start -W` for this kind of start.
+    pub fn launch_state(&self) -> &'static str {
+        match self {
+            Self::Cold => "COLD",
+            Self::Warm => "WARM",
+            Self::Hot => "HOT",
+        }
+    }
+}
+
+impl Display for StartupKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.launch_state())
+    }
+}
+
+/// The frames drawn by an application, parsed from `dumpsys gfxinfo <package>`.
+///
+/// # Syntax
+///
+/// ```text
+/// Total frames rendered: 42
+/// Janky frames: 3 (7.14%)
+/// 50th percentile: 6ms
+/// 90th percentile: 12ms
+/// 95th percentile: 17ms
+/// 99th percentile: 40ms
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct FrameStats {
+    pub total_frames: u64,
+    /// The frames missing their deadline.
+    pub janky_frames: u64,
+    pub percentile_50: Option<Duration>,
+    pub percentile_90: Option<Duration>,
+    pub percentile_95: Option<Duration>,
+    pub percentile_99: Option<Duration>,
+}
+
+impl FrameStats {
+    /// Parses the output of `dumpsys gfxinfo <package>`, `None` without a frame count, e.g.
+    /// if the application isn't running.
+    pub fn parse(output: &str) -> Option<Self> {
+        let mut stats = Self::default();
+        let mut found = false;
+        for line in output.lines().map(str::trim) {
+            let millis = |value: &str| {
+                value
+                    .strip_suffix("ms")
+                    .and_then(|value| value.parse().ok())
+                    .map(Duration::from_millis)
+            };
+            let Some((key, value)) = line.split_once(": ") else {
+                continue;
+            };
+            match key {
+                "Total frames rendered" => {
+                    stats.total_frames = value.parse().ok()?;
+                    found = true;
+                }
+                "Janky frames" => {
+                    let count = value.split_whitespace().next().unwrap_or_default();
+                    stats.janky_frames = count.parse().ok()?;
+                }
+                "50th percentile" => stats.percentile_50 = millis(value),
+                "90th percentile" => stats.percentile_90 = millis(value),
+                "95th percentile" => stats.percentile_95 = millis(value),
+                "99th percentile" => stats.percentile_99 = millis(value),
+                _ => {}
+            }
+        }
+        found.then_some(stats)
+    }
+}
+
+/// A launch measured by [`Device::measure_app_startup`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct StartupSample {
+    /// How the activity was launched according to `am`, e.g. `HOT` for a warm start on
+    /// Android 12 and later.
+    pub launch_state: Option<String>,
+    /// The time to draw the first frame of the activity.
+    pub total_time: Duration,
+    /// The time until `am` was notified.
+    pub wait_time: Option<Duration>,
+    /// The frames drawn during the launch, `None` if `dumpsys gfxinfo` didn't report them.
+    pub frames: Option<FrameStats>,
+}
+
+/// The order statistics of durations.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct DurationStats {
+    pub min: Duration,
+    pub median: Duration,
+    /// The 90th percentile, by the nearest-rank method.
+    pub p90: Duration,
+    pub max: Duration,
+}
+
+impl DurationStats {
+    /// Computes the statistics of `durations`, `None` if it's empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use adb::startup::DurationStats;
+    ///
+    /// let durations: Vec<_> = (1..=10).map(Duration::from_millis).collect();
+    /// let stats = DurationStats::new(&durations).unwrap();
+    /// assert_eq!(Duration::from_micros(5500), stats.median);
+    /// assert_eq!(Duration::from_millis(9), stats.p90);
+    /// ```
+    pub fn new(durations: &[Duration]) -> Option<Self> {
+        let mut sorted = durations.to_vec();
+        sorted.sort_unstable();
+        let len = sorted.len();
+        let median = match len {
+            0 => return None,
+            len if len % 2 == 0 => (sorted[len / 2 - 1] + sorted[len / 2]) / 2,
+            len => sorted[len / 2],
+        };
+        // The smallest duration greater than or equal to 90% of the durations.
+        let rank = (len * 9 + 9) / 10;
+        Some(Self {
+            min: sorted[0],
+            median,
+            p90: sorted[rank - 1],
+            max: sorted[len - 1],
+        })
+    }
+}
+
+/// The start-up latency of an application, returned by [`Device::measure_app_startup`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct StartupReport {
+    pub package: String,
+    /// The launched activity.
+    pub activity: String,
+    pub kind: StartupKind,
+    /// The launches, in order.
+    pub samples: Vec<StartupSample>,
+}
+
+impl StartupReport {
+    /// Returns the statistics of the times to draw the first frame, `None` without samples.
+    pub fn total_time(&self) -> Option<DurationStats> {
+        let times: Vec<_> = self.samples.iter().map(|s| s.total_time).collect();
+        DurationStats::new(&times)
+    }
+
+    /// Returns the statistics of the times until `am` was notified, `None` if no sample
+    /// reports it.
+    pub fn wait_time(&self) -> Option<DurationStats> {
+        let times: Vec<_> = self.samples.iter().filter_map(|s| s.wait_time).collect();
+        DurationStats::new(&times)
+    }
+
+    /// Returns the number of samples whose launch state doesn't match [`Self::kind`].
+    pub fn mismatched_launches(&self) -> usize {
+        let expected = self.kind.launch_state();
+        self.samples
+            .iter()
+            .filter(|s| {
+                s.launch_state
+                    .as_deref()
+                    .is_some_and(|state| state != expected)
+            })
+            .count()
+    }
+}
+
+impl Device {
+    /// Measures the start-up latency of the application `package` over `iterations`
+    /// launches of its launcher activity.
+    ///
+    /// The application is left running. Fails if a launch isn't measured, e.g. if the
+    /// activity was only brought to the front.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    /// use adb::startup::StartupKind;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let report = device
+    ///     .measure_app_startup("com.example.app", 10, StartupKind::Cold)
+    ///     .unwrap();
+    /// let stats = report.total_time().unwrap();
+    /// println!("median {:?}, p90 {:?}", stats.median, stats.p90);
+    /// ```
+    pub fn measure_app_startup(
+        &self,
+        package: &str,
+        iterations: usize,
+        kind: StartupKind,
+    ) -> Result<StartupReport, AdbError> {
+        let app = self.app(package);
+        let activity = app.launcher_activity()?;
+        let intent = Intent::new()
+            .action("android.intent.action.MAIN")
+            .category("android.intent.category.LAUNCHER")
+            .component(activity.clone());
+        let clock = &self.server().clock;
+        if kind != StartupKind::Cold && iterations > 0 && !app.is_running()? {
+            app.launch()?;
+            clock.sleep(SETTLE_DELAY);
+        }
+        let gfxinfo = format!("dumpsys gfxinfo {}", shell::quote(package));
+        let mut samples = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            match kind {
+                StartupKind::Cold => app.stop()?,
+                StartupKind::Warm | StartupKind::Hot => {
+                    let key = match kind {
+                        StartupKind::Warm => KeyCode::Back,
+                        _ => KeyCode::Home,
+                    };
+                    self.keyevent(key)?;
+                    clock.sleep(SETTLE_DELAY);
+                    self.shell_checked(&format!("{} reset", gfxinfo))?;
+                }
+            }
+            let result = self.start_activity(&intent)?;
+            let total_time = result.total_time.ok_or_else(|| AdbError::Server {
+                message: format!(
+                    "the {} start of {} wasn't measured{}",
+                    kind,
+                    activity,
+                    if result.brought_to_front {
+                        ", it was only brought to the front"
+                    } else {
+                        ""
+                    }
+                ),
+            })?;
+            samples.push(StartupSample {
+                launch_state: result.launch_state,
+                total_time,
+                wait_time: result.wait_time,
+                frames: FrameStats::parse(&self.shell_checked(&gfxinfo)?),
+            });
+        }
+        Ok(StartupReport {
+            package: package.to_string(),
+            activity,
+            kind,
+            samples,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_stats_parse() {
+        let output = "\
+Applications Graphics Acceleration Info:
+Uptime: 2716042 Realtime: 2716042
+
+** Graphics info for pid 4242 [com.example.app] **
+
+Stats since: 2711936466215ns
+Total frames rendered: 42
+Janky frames: 3 (7.14%)
+Janky frames (legacy): 5 (11.90%)
+50th percentile: 6ms
+90th percentile: 12ms
+95th percentile: 17ms
+99th percentile: 40ms
+Number Missed Vsync: 1
+";
+        assert_eq!(
+            Some(FrameStats {
+                total_frames: 42,
+                janky_frames: 3,
+                percentile_50: Some(Duration::from_millis(6)),
+                percentile_90: Some(Duration::from_millis(12)),
+                percentile_95: Some(Duration::from_millis(17)),
+                percentile_99: Some(Duration::from_millis(40)),
+            }),
+            FrameStats::parse(output)
+        );
+        assert_eq!(
+            None,
+            FrameStats::parse("No process found for: com.example.app\n")
+        );
+    }
+
+    #[test]
+    fn test_duration_stats() {
+        assert_eq!(None, DurationStats::new(&[]));
+        let millis =
+            |ms: &[u64]| -> Vec<_> { ms.iter().map(|&ms| Duration::from_millis(ms)).collect() };
+        let stats = DurationStats::new(&millis(&[300, 100, 200])).unwrap();
+        assert_eq!(
+            DurationStats {
+                min: Duration::from_millis(100),
+                median: Duration::from_millis(200),
+                p90: Duration::from_millis(300),
+                max: Duration::from_millis(300),
+            },
+            stats
+        );
+        let stats = DurationStats::new(&millis(&[5])).unwrap();
+        assert_eq!(Duration::from_millis(5), stats.median);
+        assert_eq!(Duration::from_millis(5), stats.p90);
+        let durations = millis(&(1..=20).collect::<Vec<_>>());
+        assert_eq!(
+            Duration::from_millis(18),
+            DurationStats::new(&durations).unwrap().p90
+        );
+    }
+
+    #[test]
+    fn test_startup_report() {
+        let sample = |state: &str, ms: u64, wait: Option<u64>| StartupSample {
+            launch_state: Some(state.to_string()),
+            total_time: Duration::from_millis(ms),
+            wait_time: wait.map(Duration::from_millis),
+            frames: None,
+        };
+        let report = StartupReport {
+            package: "com.example.app".to_string(),
+            activity: "com.example.app/.MainActivity".to_string(),
+            kind: StartupKind::Warm,
+            samples: vec![
+                sample("WARM", 400, Some(410)),
+                sample("HOT", 200, None),
+                sample("WARM", 300, Some(320)),
+            ],
+        };
+        assert_eq!(
+            Duration::from_millis(300),
+            report.total_time().unwrap().median
+        );
+        assert_eq!(
+            Duration::from_millis(365),
+            report.wait_time().unwrap().median
+        );
+        assert_eq!(1, report.mismatched_launches());
+        assert_eq!("WARM", StartupKind::Warm.to_string());
+    }
+}
@@ -0,0 +1,254 @@
+//! This module provides [`Stats`], the metrics collected by statsd on top of the
+//! `cmd stats` shell command (Android 11 and later).
+//!
+//! A config, a `StatsdConfig` protobuf, tells statsd which atoms to collect, and is
+//! identified by a 64 bits id. [`Stats::update_config`] pushes it on the stdin of
+//! `cmd stats config update <id>`, and [`Stats::dump_report`] returns what was collected as a
+//! `ConfigMetricsReportList` protobuf, written by `cmd stats dump-report <id> --proto`.
+//! Configs and reports belong to the shell user, the caller of `cmd stats`.
+//!
+//! Reports are returned as raw bytes, to be decoded with the `.proto` files of statsd. With
+//! the `proto` feature, `Stats::dump_report_decoded` decodes the parts common to all
+//! metrics.
+
+use std::io::{self, Read, Write};
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::features::Feature;
+
+/// The options of `cmd stats dump-report`.
+///
+/// # Examples
+///
+/// ```
+/// use adb::stats::ReportOptions;
+///
+/// let options = ReportOptions::new().keep_data(true);
+/// assert_eq!(options.args(), ["--keep_data"]);
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ReportOptions {
+    keep_data: bool,
+    include_current_bucket: bool,
+}
+
+impl ReportOptions {
+    /// Creates options without any flag, erasing the reported data.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to keep the reported data, to be reported again (`--keep_data`).
+    pub fn keep_data(mut self, keep: bool) -> Self {
+        self.keep_data = keep;
+        self
+    }
+
+    /// Sets whether to report the current, partial bucket (`--include_current_bucket`).
+    pub fn include_current_bucket(mut self, include: bool) -> Self {
+        self.include_current_bucket = include;
+        self
+    }
+
+    /// Returns the arguments of `dump-report` for these options, without `--proto`.
+    pub fn args(&self) -> Vec<&'static str> {
+        [
+            (self.keep_data, "--keep_data"),
+            (self.include_current_bucket, "--include_current_bucket"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, flag)| enabled.then_some(flag))
+        .collect()
+    }
+}
+
+/// The metrics of a device, created by [`Device::stats`].
+#[derive(Clone, Debug)]
+pub struct Stats {
+    device: Device,
+}
+
+impl Stats {
+    /// Returns the statistics of statsd itself, e.g. the configs and the dropped atoms
+    /// (`print-stats`).
+    pub fn print_stats(&self) -> Result<String, AdbError> {
+        self.device.shell_checked("cmd stats print-stats")
+    }
+
+    /// Pulls the atom `atom` now and returns it as text (`pull-source <atom>`), e.g. 10000
+    /// for the bytes transferred over Wi-Fi.
+    pub fn pull_source(&self, atom: u32) -> Result<String, AdbError> {
+        self.device
+            .shell_checked(&format!("cmd stats pull-source {}", atom))
+    }
+
+    /// Adds or replaces the config `id`, an encoded `StatsdConfig` protobuf
+    /// (`config update <id>`).
+    ///
+    /// The config is written on the stdin of the command, which needs the shell v2
+    /// protocol, see [`Feature::ShellV2`].
+    pub fn update_config(&self, id: i64, config: &[u8]) -> Result<(), AdbError> {
+        self.device.require_feature(Feature::ShellV2)?;
+        let command = format!("cmd stats config update {}", id);
+        let mut stream = self.device.shell_stream(&command)?;
+        stream.write_all(config)?;
+        stream.close_stdin()?;
+        io::copy(&mut stream, &mut io::sink())?;
+        match stream.exit_code() {
+            Some(0) => Ok(()),
+            code => Err(AdbError::Server {
+                message: format!(
+                    "`{}` exited with {}: {}",
+                    command,
+                    code.map_or("no code".to_string(), |code| code.to_string()),
+                    String::from_utf8_lossy(&stream.take_stderr()).trim_end()
+                ),
+            }),
+        }
+    }
+
+    /// Removes the config `id` and its data (`config remove <id>`).
+    pub fn remove_config(&self, id: i64) -> Result<(), AdbError> {
+        self.device
+            .shell_checked(&format!("cmd stats config remove {}", id))
+            .map(drop)
+    }
+
+    /// Returns the data collected for the config `id`, an encoded `ConfigMetricsReportList`
+    /// protobuf (`dump-report <id> --proto`).
+    pub fn dump_report(&self, id: i64, options: &ReportOptions) -> Result<Vec<u8>, AdbError> {
+        let mut command = format!("exec:cmd stats dump-report {}", id);
+        for arg in options.args() {
+            command.push(' ');
+            command.push_str(arg);
+        }
+        command.push_str(" --proto");
+        let mut report = Vec::new();
+        self.device.open(&command)?.read_to_end(&mut report)?;
+        Ok(report)
+    }
+
+    /// Returns the data collected for the config `id`, decoded.
+    #[cfg(feature = "proto")]
+    pub fn dump_report_decoded(
+        &self,
+        id: i64,
+        options: &ReportOptions,
+    ) -> Result<ConfigMetricsReportList, AdbError> {
+        crate::proto::decode(&self.dump_report(id, options)?)
+    }
+}
+
+/// The owner and id of a config, in a [`ConfigMetricsReportList`].
+#[cfg(feature = "proto")]
+#[derive(Clone, Eq, PartialEq, Hash, prost::Message)]
+pub struct ConfigKey {
+    #[prost(int32, optional, tag = "1")]
+    pub uid: Option<i32>,
+    #[prost(int64, optional, tag = "2")]
+    pub id: Option<i64>,
+}
+
+/// The data of a metric, in a [`ConfigMetricsReport`].
+///
+/// Only the id is decoded, the data itself depends on the kind of metric.
+#[cfg(feature = "proto")]
+#[derive(Clone, Eq, PartialEq, Hash, prost::Message)]
+pub struct StatsLogReport {
+    #[prost(int64, optional, tag = "1")]
+    pub metric_id: Option<i64>,
+}
+
+/// The data collected for a config between two dumps.
+#[cfg(feature = "proto")]
+#[derive(Clone, Eq, PartialEq, Hash, prost::Message)]
+pub struct ConfigMetricsReport {
+    #[prost(message, repeated, tag = "1")]
+    pub metrics: Vec<StatsLogReport>,
+    #[prost(int64, optional, tag = "3")]
+    pub last_report_elapsed_nanos: Option<i64>,
+    #[prost(int64, optional, tag = "4")]
+    pub current_report_elapsed_nanos: Option<i64>,
+    #[prost(int64, optional, tag = "5")]
+    pub last_report_wall_clock_nanos: Option<i64>,
+    #[prost(int64, optional, tag = "6")]
+    pub current_report_wall_clock_nanos: Option<i64>,
+    /// The strings referenced by hash in the metrics.
+    #[prost(string, repeated, tag = "9")]
+    pub strings: Vec<String>,
+}
+
+/// The reports of a config, written by `cmd stats dump-report --proto`.
+#[cfg(feature = "proto")]
+#[derive(Clone, Eq, PartialEq, Hash, prost::Message)]
+pub struct ConfigMetricsReportList {
+    #[prost(message, optional, tag = "1")]
+    pub config_key: Option<ConfigKey>,
+    #[prost(message, repeated, tag = "2")]
+    pub reports: Vec<ConfigMetricsReport>,
+}
+
+impl Device {
+    /// Returns a handle to the metrics of statsd on the device.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    /// use adb::stats::ReportOptions;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let stats = device.stats();
+    /// stats
+    ///     .update_config(42, &std::fs::read("config.pb").unwrap())
+    ///     .unwrap();
+    /// // Run the scenario to measure.
+    /// let report = stats.dump_report(42, &ReportOptions::new()).unwrap();
+    /// std::fs::write("report.pb", report).unwrap();
+    /// stats.remove_config(42).unwrap();
+    /// ```
+    pub fn stats(&self) -> Stats {
+        Stats {
+            device: self.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_options_args() {
+        assert!(ReportOptions::new().args().is_empty());
+        let options = ReportOptions::new()
+            .keep_data(true)
+            .include_current_bucket(true);
+        assert_eq!(
+            ["--keep_data", "--include_current_bucket"],
+            &options.args()[..]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "proto")]
+    fn test_decode_report_list() {
+        // A report of the config 42 of the shell user, with the metric 7 and a field 2
+        // unknown to this crate.
+        let bytes =
+            b"\x0a\x05\x08\xd0\x0f\x10\x2a\x12\x0b\x0a\x04\x08\x07\x10\x01\x18\x64\x4a\x01a";
+        let list: ConfigMetricsReportList = crate::proto::decode(bytes).unwrap();
+        assert_eq!(
+            Some(ConfigKey {
+                uid: Some(2000),
+                id: Some(42),
+            }),
+            list.config_key
+        );
+        let report = &list.reports[0];
+        assert_eq!(Some(7), report.metrics[0].metric_id);
+        assert_eq!(Some(100), report.last_report_elapsed_nanos);
+        assert_eq!(["a"], &report.strings[..]);
+    }
+}
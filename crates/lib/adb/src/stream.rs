@@ -0,0 +1,117 @@
+//! This module provides what handles of long-running services do when dropped.
+//!
+//! A service runs on the device as long as its connection is open: dropping a
+//! [`LogReader`](crate::logcat::LogReader) or a
+//! [`SyncConnection`](crate::sync::SyncConnection) closes it, and adbd then ends the service,
+//! killing its process. A [`StreamDropPolicy`] chooses how: at once, after the close message
+//! of the protocol, or after reading the remaining output for a bounded time, so that the
+//! process can exit cleanly.
+
+use std::io::{self, Cursor, ErrorKind, Read};
+use std::net::{Shutdown, TcpStream};
+use std::time::{Duration, Instant};
+
+use crate::server::ServerStream;
+
+/// How a handle closes its connection when dropped, see the [module](self) docs.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum StreamDropPolicy {
+    /// Closes the connection at once, discarding pending output.
+    Abort,
+    /// Sends the close message of the protocol, e.g. `QUIT` for sync, then closes the
+    /// connection.
+    Close,
+    /// Like [`Self::Close`], then reads the remaining output until the service ends it, for
+    /// at most the given time.
+    Drain(Duration),
+}
+
+/// A connection to a service that can be closed before being dropped.
+///
+/// Implemented for [`TcpStream`] and [`ServerStream`], and trivially for in-memory readers.
+pub trait ServiceStream {
+    /// Bounds the time a blocking read waits, `None` waiting forever.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Closes the connection in both directions.
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+impl ServiceStream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+impl ServiceStream for ServerStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        ServerStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        ServerStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+impl<T> ServiceStream for Cursor<T> {
+    fn set_read_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads and discards the output of `stream` until it ends, for at most `timeout`, then
+/// closes it.
+pub(crate) fn drain<S: Read + ServiceStream>(stream: &mut S, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0; 4096];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        // A zero timeout means no timeout to the socket API.
+        if remaining.is_zero() || stream.set_read_timeout(Some(remaining)).is_err() {
+            break;
+        }
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+    let _ = stream.shutdown();
+}
+
+/// Closes `stream` as `policy` says, once the close message was sent if any.
+pub(crate) fn close<S: Read + ServiceStream>(stream: &mut S, policy: StreamDropPolicy) {
+    match policy {
+        StreamDropPolicy::Drain(timeout) => drain(stream, timeout),
+        StreamDropPolicy::Abort | StreamDropPolicy::Close => {
+            let _ = stream.shutdown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_close() {
+        let mut stream = Cursor::new(vec![0; 10000]);
+        close(&mut stream, StreamDropPolicy::Drain(Duration::from_secs(1)));
+        assert_eq!(10000, stream.position());
+        let mut stream = Cursor::new(vec![0; 10000]);
+        close(&mut stream, StreamDropPolicy::Abort);
+        assert_eq!(0, stream.position());
+        // A zero timeout doesn't read at all.
+        drain(&mut stream, Duration::ZERO);
+        assert_eq!(0, stream.position());
+    }
+}
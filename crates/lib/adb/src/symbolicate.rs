@@ -0,0 +1,297 @@
+//! This module provides parsing and symbolication of native backtraces, as printed in
+//! tombstones (`/data/tombstones`), by `debuggerd -b` and in the crash logs of logcat.
+//!
+//! Symbols are looked up in a [`SymbolStore`] provided by the user, typically indexing the
+//! unstripped libraries of a build by their build id.
+//!
+//! # Syntax
+//!
+//! `#<index> pc <pc> <map> [(offset 0x<offset>)] [(<symbol>+<offset>)] [(BuildId: <id>)]`
+//!
+//! ```
+//! use adb::symbolicate::Frame;
+//!
+//! let line = "#00 pc 000000000004c9a4  /apex/com.android.runtime/lib64/bionic/libc.so (abort+164) (BuildId: 3a9d)";
+//! let frame: Frame = line.parse().unwrap();
+//! assert_eq!(frame.pc, 0x4c9a4);
+//! assert_eq!(frame.map, "/apex/com.android.runtime/lib64/bionic/libc.so");
+//! assert_eq!(frame.symbol.as_deref(), Some("abort"));
+//! assert_eq!(frame.build_id.as_deref(), Some("3a9d"));
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::error::AdbError;
+
+/// A frame of a native backtrace.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Frame {
+    /// The position of the frame in the backtrace, 0 being the innermost.
+    pub index: u32,
+    /// The program counter, relative to the start of the mapped file.
+    pub pc: u64,
+    /// The mapped file, or the name of an anonymous mapping, e.g. `[anon:dalvik-jit-code-cache]`.
+    pub map: String,
+    /// The offset of the ELF file in the mapped file, for libraries loaded from an APK.
+    pub map_offset: Option<u64>,
+    /// The symbol found on the device, usually only for exported functions.
+    pub symbol: Option<String>,
+    /// The offset of the pc in [`Self::symbol`].
+    pub symbol_offset: Option<u64>,
+    /// The build id of the ELF file, as lowercase hex.
+    pub build_id: Option<String>,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02} pc {:016x}  {}", self.index, self.pc, self.map)?;
+        if let Some(offset) = self.map_offset {
+            write!(f, " (offset {:#x})", offset)?;
+        }
+        if let Some(symbol) = &self.symbol {
+            write!(f, " ({}", symbol)?;
+            if let Some(offset) = self.symbol_offset {
+                write!(f, "+{}", offset)?;
+            }
+            f.write_str(")")?;
+        }
+        if let Some(build_id) = &self.build_id {
+            write!(f, " (BuildId: {})", build_id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits the last parenthesized group from `s`, whose content may contain parentheses,
+/// e.g. `foo(int)+12` for C++ symbols.
+fn split_last_group(s: &str) -> Option<(&str, &str)> {
+    let s = s.strip_suffix(')')?;
+    let mut depth = 0;
+    for (i, c) in s.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth == 0 => return Some((s[..i].trim_end(), &s[i + 1..])),
+            '(' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+impl FromStr for Frame {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || AdbError::Parse {
+            value: s.to_string(),
+            source_type: "&str",
+            target_type: "Frame",
+            source: None,
+        };
+        let (index, rest) = s
+            .trim()
+            .strip_prefix('#')
+            .and_then(|s| s.split_once(" pc "))
+            .ok_or_else(err)?;
+        let (pc, mut rest) = rest.trim_start().split_once(' ').ok_or_else(err)?;
+        let mut frame = Self {
+            index: index.parse().map_err(|_| err())?,
+            pc: u64::from_str_radix(pc, 16).map_err(|_| err())?,
+            map: String::new(),
+            map_offset: None,
+            symbol: None,
+            symbol_offset: None,
+            build_id: None,
+        };
+        while let Some((before, group)) = split_last_group(rest) {
+            if let Some(build_id) = group.strip_prefix("BuildId: ") {
+                frame.build_id = Some(build_id.to_string());
+            } else if let Some(offset) = group.strip_prefix("offset 0x") {
+                frame.map_offset = Some(u64::from_str_radix(offset, 16).map_err(|_| err())?);
+            } else {
+                match group.rsplit_once('+') {
+                    Some((symbol, offset)) if offset.bytes().all(|b| b.is_ascii_digit()) => {
+                        frame.symbol = Some(symbol.to_string());
+                        frame.symbol_offset = offset.parse().ok();
+                    }
+                    _ => frame.symbol = Some(group.to_string()),
+                }
+            }
+            rest = before;
+        }
+        frame.map = rest.trim().to_string();
+        if frame.map.is_empty() {
+            return Err(err());
+        }
+        Ok(frame)
+    }
+}
+
+/// Parses the backtraces of a tombstone or crash log, in order.
+///
+/// In a tombstone, the first backtrace is the one of the crashing thread. Lines which
+/// aren't frames, like logcat prefixes and register dumps, are skipped.
+pub fn parse_backtraces(text: &str) -> Vec<Vec<Frame>> {
+    let mut backtraces: Vec<Vec<Frame>> = Vec::new();
+    for line in text.lines() {
+        // Skip prefixes like `DEBUG   : ` in logcat.
+        let Some(start) = line.find('#') else {
+            continue;
+        };
+        let Ok(frame) = line[start..].parse::<Frame>() else {
+            continue;
+        };
+        match backtraces.last_mut() {
+            Some(backtrace) if frame.index != 0 => backtrace.push(frame),
+            _ => backtraces.push(vec![frame]),
+        }
+    }
+    backtraces
+}
+
+/// A symbol resolved by a [`SymbolStore`].
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Symbol {
+    /// The function, demangled or not.
+    pub function: String,
+    /// The source file.
+    pub file: Option<String>,
+    /// The line in [`Self::file`].
+    pub line: Option<u32>,
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.function)?;
+        if let Some(file) = &self.file {
+            write!(f, " at {}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A source of symbols on the host, e.g. the unstripped libraries of a build.
+///
+/// Closures taking a [`Frame`] are symbol stores.
+pub trait SymbolStore {
+    /// Returns the symbol of the pc of `frame`, or `None` if it's unknown.
+    fn lookup(&self, frame: &Frame) -> Option<Symbol>;
+}
+
+impl<F: Fn(&Frame) -> Option<Symbol>> SymbolStore for F {
+    fn lookup(&self, frame: &Frame) -> Option<Symbol> {
+        self(frame)
+    }
+}
+
+/// A frame with the symbol found in a [`SymbolStore`].
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct SymbolizedFrame {
+    pub frame: Frame,
+    pub symbol: Option<Symbol>,
+}
+
+impl Display for SymbolizedFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.symbol {
+            Some(symbol) => write!(
+                f,
+                "#{:02} pc {:016x}  {} ({})",
+                self.frame.index, self.frame.pc, self.frame.map, symbol
+            ),
+            None => self.frame.fmt(f),
+        }
+    }
+}
+
+/// Looks up the symbols of `frames` in `store`.
+///
+/// # Examples
+///
+/// ```
+/// use adb::symbolicate::{self, Symbol};
+///
+/// let tombstone = "backtrace:\n      #00 pc 0000000000001234  /data/app/lib/arm64/libgame.so (BuildId: 77aa)";
+/// let backtraces = symbolicate::parse_backtraces(tombstone);
+/// let store = |frame: &symbolicate::Frame| {
+///     (frame.build_id.as_deref() == Some("77aa")).then(|| Symbol {
+///         function: "Game::tick()".to_string(),
+///         file: Some("game.cpp".to_string()),
+///         line: Some(42),
+///     })
+/// };
+/// let frames = symbolicate::symbolicate(&backtraces[0], &store);
+/// assert_eq!(
+///     frames[0].to_string(),
+///     "#00 pc 0000000000001234  /data/app/lib/arm64/libgame.so (Game::tick() at game.cpp:42)"
+/// );
+/// ```
+pub fn symbolicate(frames: &[Frame], store: &impl SymbolStore) -> Vec<SymbolizedFrame> {
+    frames
+        .iter()
+        .map(|frame| SymbolizedFrame {
+            frame: frame.clone(),
+            symbol: store.lookup(frame),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_parse() {
+        let s = "#03 pc 0000000000012340  /data/app/~~a==/com.example-b==/base.apk (offset 0x1000) (foo(int)+12) (BuildId: 0a1b)";
+        let frame: Frame = s.parse().unwrap();
+        assert_eq!(3, frame.index);
+        assert_eq!(0x12340, frame.pc);
+        assert_eq!("/data/app/~~a==/com.example-b==/base.apk", frame.map);
+        assert_eq!(Some(0x1000), frame.map_offset);
+        assert_eq!(Some("foo(int)"), frame.symbol.as_deref());
+        assert_eq!(Some(12), frame.symbol_offset);
+        assert_eq!(Some("0a1b"), frame.build_id.as_deref());
+        assert_eq!(s, frame.to_string());
+        let frame: Frame = "  #01 pc 00000000000e2a0c  [anon:dalvik-jit-code-cache]"
+            .parse()
+            .unwrap();
+        assert_eq!("[anon:dalvik-jit-code-cache]", frame.map);
+        assert_eq!(None, frame.symbol);
+        let err = [
+            "",
+            "#00",
+            "#00 pc",
+            "#00 pc zz /system/lib64/libc.so",
+            "#00 pc 1234  (abort+4)",
+        ];
+        for s in err {
+            assert!(s.parse::<Frame>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_backtraces() {
+        let tombstone = "\
+pid: 1234, tid: 1234, name: example  >>> com.example <<<
+signal 6 (SIGABRT), code -1 (SI_QUEUE), fault addr --------
+backtrace:
+      #00 pc 000000000004c9a4  /apex/com.android.runtime/lib64/bionic/libc.so (abort+164)
+      #01 pc 0000000000001234  /data/app/lib/arm64/libgame.so
+
+--- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+tid 1240, name: RenderThread
+backtrace:
+      #00 pc 00000000000a1b2c  /apex/com.android.runtime/lib64/bionic/libc.so (__epoll_pwait+8)
+";
+        let backtraces = parse_backtraces(tombstone);
+        assert_eq!(2, backtraces.len());
+        assert_eq!(2, backtraces[0].len());
+        assert_eq!("/data/app/lib/arm64/libgame.so", backtraces[0][1].map);
+        assert_eq!(Some("__epoll_pwait"), backtraces[1][0].symbol.as_deref());
+        let logcat = "F DEBUG   :       #00 pc 000000000004c9a4  /system/lib64/libc.so (abort+164)";
+        assert_eq!(1, parse_backtraces(logcat).len());
+    }
+}
@@ -0,0 +1,1019 @@
+//! This module provides the sync protocol used by `adb push`, `adb pull` and `adb ls`.
+//!
+//! A sync request is a 4 byte id followed by the length of its payload as a little-endian `u32`
+//! and the payload itself, usually a path on the device.
+//!
+//! The original `STAT` and `LIST` requests only report the mode, a 32-bit size and the
+//! modification time of files. Devices with the `stat_v2` and `ls_v2` features also answer
+//! `STA2`, `LST2` and `LIS2`, reporting owners, 64-bit sizes and all the timestamps, which
+//! [`Device::stat`] and [`Device::list_dir`] use when available.
+//!
+//! Transfers are stopped from another thread with a [`CancellationToken`], see
+//! [`Device::pull_cancellable`] and [`SyncConnection::cancel_on`].
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cancel::{self, CancelGuard, CancellationToken};
+use crate::compat;
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::features::Feature;
+use crate::protocol::{self, Decoded};
+use crate::server::ServerStream;
+use crate::stream::{self, ServiceStream, StreamDropPolicy};
+
+/// The maximum length of a remote path.
+pub const MAX_PATH_LENGTH: usize = 1024;
+
+/// The maximum size of a `DATA` chunk.
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The default mode of pushed files.
+pub const DEFAULT_MODE: u32 = 0o644;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// The metadata of a file on the device, as returned by the `STAT` request, or `STA2` and
+/// `LST2` for the fields only known to version 2.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct FileStat {
+    /// The unix mode bits, including the file type.
+    pub mode: u32,
+    /// The size in bytes.
+    pub size: u64,
+    /// The modification time in seconds since the unix epoch.
+    pub mtime: i64,
+    /// The user owning the file, version 2 only.
+    pub uid: Option<u32>,
+    /// The group owning the file, version 2 only.
+    pub gid: Option<u32>,
+    /// The access time in seconds since the unix epoch, version 2 only.
+    pub atime: Option<i64>,
+    /// The status change time in seconds since the unix epoch, version 2 only.
+    pub ctime: Option<i64>,
+}
+
+impl FileStat {
+    /// Returns `true` if the file exists.
+    ///
+    /// The device replies with all fields set to zero for a missing file.
+    pub fn exists(&self) -> bool {
+        self.mode != 0 || self.size != 0 || self.mtime != 0
+    }
+
+    /// Returns `true` if the file is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    /// Returns `true` if the file is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    /// Returns `true` if the file is a symbolic link.
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+
+    /// Returns the permission bits, e.g. `0o755`.
+    pub fn permissions(&self) -> u32 {
+        self.mode & 0o7777
+    }
+
+    /// Returns the modification time.
+    pub fn modified(&self) -> SystemTime {
+        system_time(self.mtime)
+    }
+
+    /// Returns the access time, version 2 only.
+    pub fn accessed(&self) -> Option<SystemTime> {
+        self.atime.map(system_time)
+    }
+}
+
+fn system_time(secs: i64) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs.unsigned_abs())
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    }
+}
+
+/// An entry of a directory on the device, as returned by the `LIST` or `LIS2` request.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct DirEntry {
+    /// The file name, without the directory.
+    pub name: String,
+    /// The metadata of the file.
+    pub stat: FileStat,
+}
+
+/// The statistics of a file transfer.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TransferStats {
+    /// The transferred bytes.
+    pub bytes: u64,
+    /// The duration of the transfer.
+    pub duration: Duration,
+}
+
+impl TransferStats {
+    /// Returns the average transfer rate in bytes per second.
+    pub fn bytes_per_second(&self) -> f64 {
+        let seconds = self.duration.as_secs_f64();
+        if seconds > 0.0 {
+            self.bytes as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The kind of a sync request, which determines the layout of its replies.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SyncRequest {
+    Stat,
+    List,
+    Send,
+    Recv,
+    /// `STA2` or `LST2`.
+    StatV2,
+    /// `LIS2`.
+    ListV2,
+}
+
+/// A reply of the sync service.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SyncReply {
+    /// `STAT`, `STA2` or `LST2`, the reply to [`SyncRequest::Stat`] or
+    /// [`SyncRequest::StatV2`].
+    Stat(FileStat),
+    /// `DENT` or `DNT2`, a directory entry replied to [`SyncRequest::List`] or
+    /// [`SyncRequest::ListV2`].
+    Dent(DirEntry),
+    /// `DATA`, a chunk of a file replied to [`SyncRequest::Recv`].
+    Data(Vec<u8>),
+    /// `DONE`, the end of a listing or a file.
+    Done,
+    /// `OKAY`, the reply to [`SyncRequest::Send`].
+    Okay,
+    /// `FAIL` with a message.
+    Fail(String),
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn le_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn le_i64(bytes: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn decode_stat(bytes: &[u8]) -> FileStat {
+    FileStat {
+        mode: le_u32(bytes, 0),
+        size: le_u32(bytes, 4).into(),
+        mtime: le_u32(bytes, 8).into(),
+        ..FileStat::default()
+    }
+}
+
+/// Decodes the fields of a version 2 stat following its error, skipping the device, inode
+/// and link count.
+fn decode_stat_v2(bytes: &[u8]) -> FileStat {
+    FileStat {
+        mode: le_u32(bytes, 16),
+        uid: Some(le_u32(bytes, 24)),
+        gid: Some(le_u32(bytes, 28)),
+        size: le_u64(bytes, 32),
+        atime: Some(le_i64(bytes, 40)),
+        mtime: le_i64(bytes, 48),
+        ctime: Some(le_i64(bytes, 56)),
+    }
+}
+
+/// The `ENOENT` error of a version 2 stat, reported like `STAT` reports missing files.
+const ENOENT: u32 = 2;
+
+/// Describes the error of a version 2 stat, a Linux `errno`.
+fn errno_message(errno: u32) -> String {
+    match errno {
+        1 => "operation not permitted".to_string(),
+        13 => "permission denied".to_string(),
+        20 => "not a directory".to_string(),
+        40 => "too many levels of symbolic links".to_string(),
+        errno => format!("stat failed with errno {}", errno),
+    }
+}
+
+/// Decodes a reply of the sync service to `request`.
+pub fn decode_reply(bytes: &[u8], request: SyncRequest) -> Result<Decoded<SyncReply>, AdbError> {
+    let Some(id) = bytes.get(..4) else {
+        return Ok(Decoded::Incomplete { needed: 4 });
+    };
+    // The fixed size of the reply, and whether it's followed by a payload
+    // whose length is the last `u32` of the fixed part.
+    let (fixed, payload) = match (id, request) {
+        (b"STAT", SyncRequest::Stat) => (16, false),
+        (b"DENT", SyncRequest::List) => (20, true),
+        (b"DONE", SyncRequest::List) => (20, false),
+        (b"STA2" | b"LST2", SyncRequest::StatV2) => (72, false),
+        (b"DNT2", SyncRequest::ListV2) => (76, true),
+        (b"DONE", SyncRequest::ListV2) => (76, false),
+        (b"DATA", SyncRequest::Recv) => (8, true),
+        (b"DONE", SyncRequest::Recv) | (b"OKAY", SyncRequest::Send) => (8, false),
+        (b"FAIL", _) => (8, true),
+        _ => {
+            return Err(AdbError::Protocol {
+                code: Some(u32::from_le_bytes(id.try_into().unwrap())),
+                message: format!(
+                    "unexpected sync reply `{}` to {:?}",
+                    String::from_utf8_lossy(id),
+                    request
+                ),
+            })
+        }
+    };
+    if bytes.len() < fixed {
+        return Ok(Decoded::Incomplete { needed: fixed });
+    }
+    let length = if payload {
+        let length = le_u32(bytes, fixed - 4) as usize;
+        if length > MAX_CHUNK_SIZE {
+            return Err(AdbError::Protocol {
+                code: None,
+                message: format!("sync payload of {} bytes is too large", length),
+            });
+        }
+        fixed + length
+    } else {
+        fixed
+    };
+    let Some(payload) = bytes.get(fixed..length) else {
+        return Ok(Decoded::Incomplete { needed: length });
+    };
+    let value = match id {
+        b"STAT" => SyncReply::Stat(decode_stat(&bytes[4..])),
+        b"STA2" | b"LST2" => match le_u32(bytes, 4) {
+            0 => SyncReply::Stat(decode_stat_v2(&bytes[8..])),
+            ENOENT => SyncReply::Stat(FileStat::default()),
+            errno => SyncReply::Fail(errno_message(errno)),
+        },
+        b"DNT2" => SyncReply::Dent(DirEntry {
+            name: String::from_utf8_lossy(payload).into_owned(),
+            stat: decode_stat_v2(&bytes[8..]),
+        }),
+        b"DENT" => SyncReply::Dent(DirEntry {
+            name: String::from_utf8_lossy(payload).into_owned(),
+            stat: decode_stat(&bytes[4..]),
+        }),
+        b"DATA" => SyncReply::Data(payload.to_vec()),
+        b"DONE" => SyncReply::Done,
+        b"OKAY" => SyncReply::Okay,
+        _ => SyncReply::Fail(String::from_utf8_lossy(payload).into_owned()),
+    };
+    Ok(Decoded::Complete { value, length })
+}
+
+/// Encodes a sync request with its payload.
+pub(crate) fn encode_request(id: &[u8; 4], payload: &[u8]) -> Result<Vec<u8>, AdbError> {
+    let length = u32::try_from(payload.len()).map_err(|_| AdbError::Protocol {
+        code: None,
+        message: format!("sync payload of {} bytes is too long", payload.len()),
+    })?;
+    let mut request = Vec::with_capacity(8 + payload.len());
+    request.extend_from_slice(id);
+    request.extend_from_slice(&length.to_le_bytes());
+    request.extend_from_slice(payload);
+    Ok(request)
+}
+
+/// Encodes a sync request on a remote path.
+pub(crate) fn encode_path_request(id: &[u8; 4], path: &str) -> Result<Vec<u8>, AdbError> {
+    if path.len() > MAX_PATH_LENGTH {
+        return Err(AdbError::Protocol {
+            code: None,
+            message: format!("remote path `{}` is too long", path),
+        });
+    }
+    encode_request(id, path.as_bytes())
+}
+
+/// Encodes the `SEND` request creating `path` with `mode`.
+pub(crate) fn encode_send(path: &str, mode: u32) -> Result<Vec<u8>, AdbError> {
+    encode_path_request(b"SEND", &format!("{},{}", path, mode))
+}
+
+/// Encodes the `DONE` request ending a `SEND`, setting the modification time to `mtime`.
+pub(crate) fn encode_done(mtime: SystemTime) -> Vec<u8> {
+    let mtime = mtime
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u32::try_from(d.as_secs()).unwrap_or(u32::MAX));
+    let mut request = b"DONE".to_vec();
+    request.extend_from_slice(&mtime.to_le_bytes());
+    request
+}
+
+/// Turns a `FAIL` reply into an error.
+pub(crate) fn check_reply(reply: SyncReply) -> Result<SyncReply, AdbError> {
+    match reply {
+        SyncReply::Fail(message) => Err(AdbError::Server { message }),
+        reply => Ok(reply),
+    }
+}
+
+/// Returns the error for a reply the request doesn't expect.
+pub(crate) fn unexpected(reply: SyncReply) -> AdbError {
+    AdbError::Protocol {
+        code: None,
+        message: format!("unexpected sync reply {:?}", reply),
+    }
+}
+
+/// Returns `true` if a listed entry is `.` or `..`, which [`SyncConnection::list`] skips.
+pub(crate) fn is_dot_entry(entry: &DirEntry) -> bool {
+    entry.name == "." || entry.name == ".."
+}
+
+/// Returns the error for pulling a remote file that doesn't exist, worded like adb.
+pub(crate) fn missing(remote: &str) -> AdbError {
+    AdbError::Server {
+        message: format!("remote object '{}' does not exist", remote),
+    }
+}
+
+/// Returns the file a pull writes to until it succeeds, `local` with a `.part` suffix.
+pub(crate) fn partial_path(local: &Path) -> PathBuf {
+    let mut name = local.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    local.with_file_name(name)
+}
+
+/// A connection to the sync service of a device.
+///
+/// The connection can serve any number of requests, and is closed with [`Self::quit`] or on
+/// drop, as set by [`Self::drop_policy`].
+#[derive(Debug)]
+pub struct SyncConnection<S: Read + Write + ServiceStream = ServerStream> {
+    stream: S,
+    drop_policy: StreamDropPolicy,
+    quit: bool,
+    cancel: Option<CancelGuard>,
+}
+
+impl<S: Read + Write + ServiceStream> SyncConnection<S> {
+    /// Wraps a stream already switched to the sync service, sending `QUIT` when dropped.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            drop_policy: StreamDropPolicy::Close,
+            quit: false,
+            cancel: None,
+        }
+    }
+
+    /// Sets how the connection is closed when dropped, [`StreamDropPolicy::Close`] by
+    /// default.
+    ///
+    /// Aborting a transfer leaves a partial file on the device.
+    pub fn drop_policy(mut self, policy: StreamDropPolicy) -> Self {
+        self.drop_policy = policy;
+        self
+    }
+
+    /// Fails with [`AdbError::Cancelled`] once the token of the connection is cancelled.
+    fn check_cancelled(&self) -> Result<(), AdbError> {
+        match &self.cancel {
+            Some(guard) => guard.token().check(),
+            None => Ok(()),
+        }
+    }
+
+    fn send_request(&mut self, request: Vec<u8>) -> Result<(), AdbError> {
+        let result = self.stream.write_all(&request).map_err(AdbError::from);
+        cancel::map_result(&self.cancel, result)
+    }
+
+    fn read_reply(&mut self, request: SyncRequest) -> Result<SyncReply, AdbError> {
+        let result = protocol::read_decoded(&mut self.stream, |bytes| decode_reply(bytes, request));
+        check_reply(cancel::map_result(&self.cancel, result)?)
+    }
+
+    /// Returns the metadata of a file on the device (`STAT`).
+    pub fn stat(&mut self, path: &str) -> Result<FileStat, AdbError> {
+        self.send_request(encode_path_request(b"STAT", path)?)?;
+        match self.read_reply(SyncRequest::Stat)? {
+            SyncReply::Stat(stat) => Ok(stat),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    /// Returns the metadata of a file on the device, following symbolic links (`STA2`).
+    ///
+    /// The device must support [`Feature::StatV2`].
+    pub fn stat_v2(&mut self, path: &str) -> Result<FileStat, AdbError> {
+        self.send_request(encode_path_request(b"STA2", path)?)?;
+        match self.read_reply(SyncRequest::StatV2)? {
+            SyncReply::Stat(stat) => Ok(stat),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    /// Returns the metadata of a file on the device, not following symbolic links (`LST2`).
+    ///
+    /// The device must support [`Feature::StatV2`].
+    pub fn lstat_v2(&mut self, path: &str) -> Result<FileStat, AdbError> {
+        self.send_request(encode_path_request(b"LST2", path)?)?;
+        match self.read_reply(SyncRequest::StatV2)? {
+            SyncReply::Stat(stat) => Ok(stat),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    /// Lists a directory on the device (`LIST`), excluding `.` and `..`.
+    pub fn list(&mut self, path: &str) -> Result<Vec<DirEntry>, AdbError> {
+        self.list_with(path, b"LIST", SyncRequest::List)
+    }
+
+    /// Lists a directory on the device (`LIS2`), excluding `.` and `..`.
+    ///
+    /// The device must support [`Feature::LsV2`].
+    pub fn list_v2(&mut self, path: &str) -> Result<Vec<DirEntry>, AdbError> {
+        self.list_with(path, b"LIS2", SyncRequest::ListV2)
+    }
+
+    fn list_with(
+        &mut self,
+        path: &str,
+        id: &[u8; 4],
+        request: SyncRequest,
+    ) -> Result<Vec<DirEntry>, AdbError> {
+        self.send_request(encode_path_request(id, path)?)?;
+        let mut entries = Vec::new();
+        loop {
+            match self.read_reply(request)? {
+                SyncReply::Dent(entry) => {
+                    if !is_dot_entry(&entry) {
+                        entries.push(entry);
+                    }
+                }
+                SyncReply::Done => return Ok(entries),
+                reply => return Err(unexpected(reply)),
+            }
+        }
+    }
+
+    /// Sends the content of `reader` to `path` on the device (`SEND`),
+    /// creating the file with `mode` and setting its modification time to `mtime`.
+    ///
+    /// Returns the number of sent bytes.
+    pub fn send<R: Read>(
+        &mut self,
+        reader: &mut R,
+        path: &str,
+        mode: u32,
+        mtime: SystemTime,
+    ) -> Result<u64, AdbError> {
+        self.send_request(encode_send(path, mode)?)?;
+        let mut buffer = vec![0; MAX_CHUNK_SIZE];
+        let mut sent = 0;
+        loop {
+            self.check_cancelled()?;
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            self.send_request(encode_request(b"DATA", &buffer[..read])?)?;
+            sent += read as u64;
+        }
+        self.send_request(encode_done(mtime))?;
+        match self.read_reply(SyncRequest::Send)? {
+            SyncReply::Okay => Ok(sent),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    /// Receives the content of `path` on the device into `writer` (`RECV`).
+    ///
+    /// Returns the number of received bytes.
+    pub fn recv<W: Write>(&mut self, path: &str, writer: &mut W) -> Result<u64, AdbError> {
+        self.send_request(encode_path_request(b"RECV", path)?)?;
+        let mut received = 0;
+        loop {
+            self.check_cancelled()?;
+            match self.read_reply(SyncRequest::Recv)? {
+                SyncReply::Data(data) => {
+                    writer.write_all(&data)?;
+                    received += data.len() as u64;
+                }
+                SyncReply::Done => return Ok(received),
+                reply => return Err(unexpected(reply)),
+            }
+        }
+    }
+
+    /// Closes the sync connection (`QUIT`).
+    pub fn quit(mut self) -> Result<(), AdbError> {
+        self.quit = true;
+        self.send_request(encode_request(b"QUIT", &[])?)
+    }
+}
+
+impl SyncConnection<ServerStream> {
+    /// Stops the requests of the connection when `token` is cancelled, failing them with
+    /// [`AdbError::Cancelled`].
+    ///
+    /// Cancelling shuts the connection down, which wakes a transfer blocked on it. adbd
+    /// removes the partial file of a cancelled `SEND`, while the writer of a cancelled
+    /// `RECV` keeps what it received.
+    pub fn cancel_on(mut self, token: &CancellationToken) -> Result<Self, AdbError> {
+        self.cancel = Some(token.register(&self.stream)?);
+        Ok(self)
+    }
+}
+
+impl<S: Read + Write + ServiceStream> Drop for SyncConnection<S> {
+    fn drop(&mut self) {
+        if !self.quit && self.drop_policy != StreamDropPolicy::Abort {
+            let _ = self.stream.write_all(b"QUIT\0\0\0\0");
+        }
+        stream::close(&mut self.stream, self.drop_policy);
+    }
+}
+
+impl Device {
+    /// Opens a connection to the sync service of the device (`sync:`).
+    pub fn sync(&self) -> Result<SyncConnection, AdbError> {
+        Ok(SyncConnection::new(self.open("sync:")?))
+    }
+
+    fn sync_with(&self, token: Option<&CancellationToken>) -> Result<SyncConnection, AdbError> {
+        match token {
+            Some(token) => {
+                token.check()?;
+                self.sync()?.cancel_on(token)
+            }
+            None => self.sync(),
+        }
+    }
+
+    /// Returns the metadata of `path` on the device, not following symbolic links, with the
+    /// owners and all the timestamps if the device supports [`Feature::StatV2`].
+    ///
+    /// A missing file is reported as a [`FileStat`] which doesn't [exist](FileStat::exists).
+    pub fn stat(&self, path: &str) -> Result<FileStat, AdbError> {
+        let v2 = self.has_feature(Feature::StatV2)?;
+        let mut sync = self.sync()?;
+        let stat = if v2 {
+            sync.lstat_v2(path)?
+        } else {
+            sync.stat(path)?
+        };
+        sync.quit()?;
+        Ok(stat)
+    }
+
+    /// Lists the directory `path` on the device, excluding `.` and `..`, with the owners and
+    /// all the timestamps of the entries if the device supports [`Feature::LsV2`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// for entry in device.list_dir("/sdcard").unwrap() {
+    ///     let kind = if entry.stat.is_dir() { 'd' } else { '-' };
+    ///     println!("{}{:o} {:?} {}", kind, entry.stat.permissions(), entry.stat.uid, entry.name);
+    /// }
+    /// ```
+    pub fn list_dir(&self, path: &str) -> Result<Vec<DirEntry>, AdbError> {
+        let v2 = self.has_feature(Feature::LsV2)?;
+        let mut sync = self.sync()?;
+        let entries = if v2 {
+            sync.list_v2(path)?
+        } else {
+            sync.list(path)?
+        };
+        sync.quit()?;
+        Ok(entries)
+    }
+
+    /// Pushes the local file `local` to `remote` on the device, creating it with `mode`.
+    ///
+    /// The modification time of the local file is preserved, and missing parent directories
+    /// are created.
+    pub fn push(&self, local: &Path, remote: &str, mode: u32) -> Result<TransferStats, AdbError> {
+        self.push_with(local, remote, mode, None)
+    }
+
+    /// Like [`Self::push`], failing with [`AdbError::Cancelled`] once `token` is cancelled.
+    ///
+    /// adbd removes the partial remote file when the transfer is cancelled.
+    pub fn push_cancellable(
+        &self,
+        local: &Path,
+        remote: &str,
+        mode: u32,
+        token: &CancellationToken,
+    ) -> Result<TransferStats, AdbError> {
+        self.push_with(local, remote, mode, Some(token))
+    }
+
+    fn push_with(
+        &self,
+        local: &Path,
+        remote: &str,
+        mode: u32,
+        token: Option<&CancellationToken>,
+    ) -> Result<TransferStats, AdbError> {
+        let mut file = File::open(local)?;
+        let mtime = file.metadata()?.modified()?;
+        let clock = &self.server().clock;
+        let start = clock.now();
+        if !self.has_feature(Feature::FixedPushMkdir)? {
+            if let Some((parent, _)) = remote
+                .rsplit_once('/')
+                .filter(|(parent, _)| !parent.is_empty())
+            {
+                self.open(&format!("exec:mkdir -p {}", protocol::quote_arg(parent)))?
+                    .read_to_end(&mut Vec::new())?;
+            }
+        }
+        let mut sync = self.sync_with(token)?;
+        let bytes = sync.send(&mut file, remote, mode, mtime)?;
+        sync.quit()?;
+        let stats = TransferStats {
+            bytes,
+            duration: clock.now() - start,
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            target: "adb::sync",
+            remote,
+            bytes,
+            elapsed_us = stats.duration.as_micros() as u64,
+            "pushed"
+        );
+        Ok(stats)
+    }
+
+    /// Pulls `remote` on the device to the local file `local`.
+    ///
+    /// The file is written next to `local` with a `.part` suffix, and renamed to `local` once
+    /// complete: a failed pull leaves `local` untouched. The modification time of the remote
+    /// file is preserved on Rust 1.75 and later.
+    pub fn pull(&self, remote: &str, local: &Path) -> Result<TransferStats, AdbError> {
+        self.pull_with(remote, local, None)
+    }
+
+    /// Like [`Self::pull`], failing with [`AdbError::Cancelled`] once `token` is cancelled.
+    pub fn pull_cancellable(
+        &self,
+        remote: &str,
+        local: &Path,
+        token: &CancellationToken,
+    ) -> Result<TransferStats, AdbError> {
+        self.pull_with(remote, local, Some(token))
+    }
+
+    fn pull_with(
+        &self,
+        remote: &str,
+        local: &Path,
+        token: Option<&CancellationToken>,
+    ) -> Result<TransferStats, AdbError> {
+        let clock = &self.server().clock;
+        let start = clock.now();
+        let mut sync = self.sync_with(token)?;
+        let stat = sync.stat(remote)?;
+        if !stat.exists() {
+            return Err(missing(remote));
+        }
+        let partial = partial_path(local);
+        let bytes = recv_file(sync, remote, &partial, stat.modified()).and_then(|bytes| {
+            std::fs::rename(&partial, local)?;
+            Ok(bytes)
+        });
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                return Err(e);
+            }
+        };
+        let stats = TransferStats {
+            bytes,
+            duration: clock.now() - start,
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            target: "adb::sync",
+            remote,
+            bytes,
+            elapsed_us = stats.duration.as_micros() as u64,
+            "pulled"
+        );
+        Ok(stats)
+    }
+}
+
+/// Receives `remote` into a new file at `path`, with the modification time `modified`.
+fn recv_file(
+    mut sync: SyncConnection,
+    remote: &str,
+    path: &Path,
+    modified: SystemTime,
+) -> Result<u64, AdbError> {
+    let mut file = File::create(path)?;
+    let bytes = sync.recv(remote, &mut file)?;
+    sync.quit()?;
+    compat::set_modified(&file, modified)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A stream replaying canned replies and recording the requests.
+    struct MockStream {
+        replies: Cursor<Vec<u8>>,
+        requests: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(replies: &[u8]) -> Self {
+            Self {
+                replies: Cursor::new(replies.to_vec()),
+                requests: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.requests.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl ServiceStream for MockStream {
+        fn set_read_timeout(&self, _: Option<Duration>) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn shutdown(&self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn message(id: &[u8; 4], values: &[u32]) -> Vec<u8> {
+        let mut message = id.to_vec();
+        for value in values {
+            message.extend_from_slice(&value.to_le_bytes());
+        }
+        message
+    }
+
+    /// A version 2 stat reply, with its error and the fields following it.
+    fn message_v2(id: &[u8; 4], error: u32, mode: u32, size: u64, mtime: i64) -> Vec<u8> {
+        let mut message = message(id, &[error, 0, 0, 0, 0, mode, 1, 1000, 1000]);
+        message.extend_from_slice(&size.to_le_bytes());
+        for time in [mtime - 1, mtime, mtime + 1] {
+            message.extend_from_slice(&time.to_le_bytes());
+        }
+        message
+    }
+
+    #[test]
+    fn test_decode_reply() {
+        assert_eq!(
+            Decoded::Incomplete { needed: 4 },
+            decode_reply(b"DA", SyncRequest::Recv).unwrap()
+        );
+        assert_eq!(
+            Decoded::Incomplete { needed: 11 },
+            decode_reply(b"DATA\x03\x00\x00\x00a", SyncRequest::Recv).unwrap()
+        );
+        assert_eq!(
+            Decoded::Complete {
+                value: SyncReply::Data(b"abc".to_vec()),
+                length: 11
+            },
+            decode_reply(b"DATA\x03\x00\x00\x00abcDONE", SyncRequest::Recv).unwrap()
+        );
+        assert!(decode_reply(b"DATA", SyncRequest::Stat).is_err());
+        assert!(decode_reply(b"DATA\xff\xff\xff\xff", SyncRequest::Recv).is_err());
+        assert!(decode_reply(b"WHAT", SyncRequest::List).is_err());
+    }
+
+    #[test]
+    fn test_sync_stat() {
+        let mut sync = SyncConnection::new(MockStream::new(&message(
+            b"STAT",
+            &[0o100644, 42, 1700000000],
+        )));
+        let stat = sync.stat("/sdcard/a").unwrap();
+        assert!(stat.exists());
+        assert!(stat.is_file());
+        assert_eq!(42, stat.size);
+        assert_eq!(b"STAT\x09\x00\x00\x00/sdcard/a", &sync.stream.requests[..]);
+    }
+
+    #[test]
+    fn test_sync_list() {
+        let mut replies = Vec::new();
+        for (name, mode) in [(".", 0o040755), ("..", 0o040755), ("dir", 0o040755)] {
+            replies.extend(message(b"DENT", &[mode, 0, 0, name.len() as u32]));
+            replies.extend_from_slice(name.as_bytes());
+        }
+        replies.extend(message(b"DONE", &[0, 0, 0, 0]));
+        let mut sync = SyncConnection::new(MockStream::new(&replies));
+        let entries = sync.list("/sdcard").unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!("dir", entries[0].name);
+        assert!(entries[0].stat.is_dir());
+    }
+
+    #[test]
+    fn test_sync_stat_v2() {
+        let mut replies = message_v2(b"LST2", 0, 0o100600, 5 << 32, 1700000000);
+        replies.extend(message_v2(b"STA2", 2, 0, 0, 0));
+        replies.extend(message_v2(b"STA2", 13, 0, 0, 0));
+        let mut sync = SyncConnection::new(MockStream::new(&replies));
+        let stat = sync.lstat_v2("/sdcard/big").unwrap();
+        assert!(stat.is_file());
+        assert_eq!(0o600, stat.permissions());
+        assert_eq!(5 << 32, stat.size);
+        assert_eq!((Some(1000), Some(1000)), (stat.uid, stat.gid));
+        assert_eq!(
+            (Some(1699999999), 1700000000, Some(1700000001)),
+            (stat.atime, stat.mtime, stat.ctime)
+        );
+        assert!(!sync.stat_v2("/sdcard/missing").unwrap().exists());
+        match sync.stat_v2("/data/secret") {
+            Err(AdbError::Server { message }) => assert_eq!("permission denied", message),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            b"LST2\x0b\x00\x00\x00/sdcard/big",
+            &sync.stream.requests[..19]
+        );
+    }
+
+    #[test]
+    fn test_sync_list_v2() {
+        let mut replies = Vec::new();
+        for (name, mode) in [(".", 0o040755), ("link", 0o120777)] {
+            replies.extend(message_v2(b"DNT2", 0, mode, 0, -1));
+            replies.extend((name.len() as u32).to_le_bytes());
+            replies.extend_from_slice(name.as_bytes());
+        }
+        replies.extend(message_v2(b"DONE", 0, 0, 0, 0));
+        replies.extend(0u32.to_le_bytes());
+        let mut sync = SyncConnection::new(MockStream::new(&replies));
+        let entries = sync.list_v2("/sdcard").unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!("link", entries[0].name);
+        assert!(entries[0].stat.is_symlink());
+        assert_eq!(
+            UNIX_EPOCH - Duration::from_secs(1),
+            entries[0].stat.modified()
+        );
+    }
+
+    #[test]
+    fn test_sync_send() {
+        let mut sync = SyncConnection::new(MockStream::new(&message(b"OKAY", &[0])));
+        let mtime = UNIX_EPOCH + Duration::from_secs(7);
+        let sent = sync
+            .send(&mut &b"hello"[..], "/data/local/tmp/a", 0o644, mtime)
+            .unwrap();
+        assert_eq!(5, sent);
+        let mut expected = message(b"SEND", &[21]);
+        expected.extend_from_slice(b"/data/local/tmp/a,420");
+        expected.extend(message(b"DATA", &[5]));
+        expected.extend_from_slice(b"hello");
+        expected.extend(message(b"DONE", &[7]));
+        assert_eq!(expected, sync.stream.requests);
+
+        let mut replies = message(b"FAIL", &[6]);
+        replies.extend_from_slice(b"denied");
+        let mut sync = SyncConnection::new(MockStream::new(&replies));
+        match sync.send(&mut &b""[..], "/a", 0o644, mtime) {
+            Err(AdbError::Server { message }) => assert_eq!("denied", message),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sync_recv() {
+        let mut replies = message(b"DATA", &[3]);
+        replies.extend_from_slice(b"abc");
+        replies.extend(message(b"DATA", &[2]));
+        replies.extend_from_slice(b"de");
+        replies.extend(message(b"DONE", &[0]));
+        let mut sync = SyncConnection::new(MockStream::new(&replies));
+        let mut received = Vec::new();
+        assert_eq!(5, sync.recv("/a", &mut received).unwrap());
+        assert_eq!(b"abcde", &received[..]);
+
+        let mut sync = SyncConnection::new(MockStream::new(&message(b"DATA", &[u32::MAX])));
+        assert!(sync.recv("/a", &mut Vec::new()).is_err());
+    }
+
+    /// Pulls `/a` to `local` from a fake server, replying `replies` to the `STAT` and `RECV`
+    /// requests of the sync service.
+    fn pull_from(local: &Path, replies: [Vec<u8>; 2]) -> Result<TransferStats, AdbError> {
+        use std::net::TcpListener;
+
+        use crate::server::AdbServer;
+        use crate::socket::Tcp;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            protocol::read_string(&mut stream).unwrap();
+            stream.write_all(b"OKAY00040029").unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            protocol::read_string(&mut stream).unwrap();
+            stream.write_all(b"OKAY\x07\0\0\0\0\0\0\0").unwrap();
+            protocol::read_string(&mut stream).unwrap();
+            stream.write_all(b"OKAY").unwrap();
+            for reply in replies {
+                let mut header = [0; 8];
+                if stream.read_exact(&mut header).is_err() || &header[..4] == b"QUIT" {
+                    break;
+                }
+                let length = u32::from_le_bytes(header[4..].try_into().unwrap());
+                std::io::copy(&mut (&mut stream).take(length.into()), &mut std::io::sink())
+                    .unwrap();
+                stream.write_all(&reply).unwrap();
+            }
+            let _ = std::io::copy(&mut stream, &mut std::io::sink());
+        });
+        let result = AdbServer::new(Tcp::from_port(port))
+            .device("emulator-5554")
+            .pull("/a", local);
+        handle.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn test_device_pull() {
+        let dir = std::env::temp_dir().join(format!("adb-pull-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let local = dir.join("a");
+        std::fs::write(&local, "old").unwrap();
+
+        let missing = pull_from(&local, [message(b"STAT", &[0, 0, 0]), Vec::new()]);
+        assert!(matches!(
+            missing.map_err(AdbError::into_inner),
+            Err(AdbError::Server { message }) if message == "remote object '/a' does not exist"
+        ));
+        assert_eq!("old", std::fs::read_to_string(&local).unwrap());
+
+        let mut failed = message(b"DATA", &[3]);
+        failed.extend_from_slice(b"new");
+        failed.extend(message(b"FAIL", &[4]));
+        failed.extend_from_slice(b"boom");
+        let stat = message(b"STAT", &[0o100644, 3, 1]);
+        assert!(pull_from(&local, [stat.clone(), failed]).is_err());
+        assert_eq!("old", std::fs::read_to_string(&local).unwrap());
+        assert!(!partial_path(&local).exists());
+
+        let mut done = message(b"DATA", &[3]);
+        done.extend_from_slice(b"new");
+        done.extend(message(b"DONE", &[1]));
+        assert_eq!(3, pull_from(&local, [stat, done]).unwrap().bytes);
+        assert_eq!("new", std::fs::read_to_string(&local).unwrap());
+        assert!(!partial_path(&local).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
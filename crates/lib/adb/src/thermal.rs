@@ -0,0 +1,545 @@
+//! This module provides the temperatures of a device and its thermal throttling status, from
+//! `dumpsys thermalservice` (Android 10 and later).
+//!
+//! The thermal service prints its status and the temperatures of its sensors as
+//! `Temperature{mValue=34.6, mType=0, mName=cpu0, mStatus=0}`, where types and statuses are
+//! the constants of `android.os.Temperature`.
+//!
+//! A [`ThermalMonitor`] polls the thermal service, and reports to a [`ThermalObserver`] when
+//! the throttling status changes or a sensor crosses a threshold, e.g. for benchmarks to
+//! annotate their results or pause until the device cools down.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::clock::{self, Clock};
+use crate::compat;
+use crate::device::Device;
+use crate::error::AdbError;
+
+/// The default interval between two polls of a [`ThermalMonitor`].
+pub const DEFAULT_MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The throttling status of a device or a sensor, from the least to the most severe.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[non_exhaustive]
+pub enum ThrottlingStatus {
+    #[default]
+    None,
+    /// Throttling doesn't impact the user experience yet.
+    Light,
+    Moderate,
+    Severe,
+    /// The platform does everything to reduce the power.
+    Critical,
+    /// Key components are shutting down.
+    Emergency,
+    /// The device is shutting down.
+    Shutdown,
+    /// A status unknown to this library, with its code.
+    Other(i32),
+}
+
+impl ThrottlingStatus {
+    /// Returns the status of `code`, a `THROTTLING_*` constant of `PowerManager`.
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            0 => Self::None,
+            1 => Self::Light,
+            2 => Self::Moderate,
+            3 => Self::Severe,
+            4 => Self::Critical,
+            5 => Self::Emergency,
+            6 => Self::Shutdown,
+            code => Self::Other(code),
+        }
+    }
+
+    /// Returns the code of the status, ordered by severity.
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::None => 0,
+            Self::Light => 1,
+            Self::Moderate => 2,
+            Self::Severe => 3,
+            Self::Critical => 4,
+            Self::Emergency => 5,
+            Self::Shutdown => 6,
+            Self::Other(code) => *code,
+        }
+    }
+}
+
+impl PartialOrd for ThrottlingStatus {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ThrottlingStatus {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.code().cmp(&other.code())
+    }
+}
+
+/// The kind of component a temperature sensor measures.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[non_exhaustive]
+pub enum SensorType {
+    Cpu,
+    Gpu,
+    Battery,
+    Skin,
+    UsbPort,
+    PowerAmplifier,
+    /// Battery voltage, for brownout protection.
+    BclVoltage,
+    /// Battery current, for brownout protection.
+    BclCurrent,
+    /// Battery level, for brownout protection.
+    BclPercentage,
+    Npu,
+    /// A type unknown to this library, with its code, `-1` being `TYPE_UNKNOWN`.
+    Other(i32),
+}
+
+impl SensorType {
+    /// Returns the type of `code`, a `TYPE_*` constant of `Temperature`.
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            0 => Self::Cpu,
+            1 => Self::Gpu,
+            2 => Self::Battery,
+            3 => Self::Skin,
+            4 => Self::UsbPort,
+            5 => Self::PowerAmplifier,
+            6 => Self::BclVoltage,
+            7 => Self::BclCurrent,
+            8 => Self::BclPercentage,
+            9 => Self::Npu,
+            code => Self::Other(code),
+        }
+    }
+}
+
+/// A reading of a temperature sensor.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Temperature {
+    /// The name of the sensor, e.g. `cpu0` or `battery`.
+    pub name: String,
+    pub sensor_type: SensorType,
+    /// The temperature in degrees Celsius, or the value of a brownout sensor.
+    pub value: f32,
+    /// The throttling status derived from this sensor.
+    pub status: ThrottlingStatus,
+}
+
+impl Temperature {
+    /// Parses `Temperature{mValue=34.6, mType=0, mName=cpu0, mStatus=0}`.
+    fn parse(s: &str) -> Option<Self> {
+        let fields = s.trim().strip_prefix("Temperature{")?.strip_suffix('}')?;
+        let (mut value, mut sensor_type, mut name, mut status) = (None, None, None, None);
+        for field in fields.split(", ") {
+            match field.split_once('=') {
+                Some(("mValue", v)) => value = v.parse().ok(),
+                Some(("mType", v)) => sensor_type = v.parse().ok().map(SensorType::from_code),
+                Some(("mName", v)) => name = Some(v.to_string()),
+                Some(("mStatus", v)) => status = v.parse().ok().map(ThrottlingStatus::from_code),
+                _ => {}
+            }
+        }
+        Some(Self {
+            name: name?,
+            sensor_type: sensor_type?,
+            value: value?,
+            status: status?,
+        })
+    }
+}
+
+/// The thermal state of a device, returned by [`Device::thermal`].
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ThermalStatus {
+    /// The throttling status of the whole device.
+    pub status: ThrottlingStatus,
+    /// The current readings of the sensors, or the last cached ones if the thermal HAL
+    /// didn't report any.
+    pub temperatures: Vec<Temperature>,
+}
+
+impl ThermalStatus {
+    /// Parses the output of `dumpsys thermalservice`.
+    pub fn parse(dump: &str) -> Result<Self, AdbError> {
+        let mut status = None;
+        let mut cached = Vec::new();
+        let mut current = Vec::new();
+        let mut section = "";
+        for line in dump.lines() {
+            if !line.starts_with(char::is_whitespace) {
+                if let Some(code) = line.strip_prefix("Thermal Status: ") {
+                    status = code.trim().parse().ok().map(ThrottlingStatus::from_code);
+                }
+                section = line.trim_end();
+                continue;
+            }
+            let Some(temperature) = Temperature::parse(line) else {
+                continue;
+            };
+            match section {
+                "Cached temperatures:" => cached.push(temperature),
+                "Current temperatures from HAL:" => current.push(temperature),
+                _ => {}
+            }
+        }
+        Ok(Self {
+            status: status.ok_or_else(|| AdbError::Parse {
+                value: dump.to_string(),
+                source_type: "&str",
+                target_type: "ThermalStatus",
+                source: None,
+            })?,
+            temperatures: if current.is_empty() { cached } else { current },
+        })
+    }
+
+    /// Returns the reading of the sensor `name`.
+    pub fn temperature(&self, name: &str) -> Option<&Temperature> {
+        self.temperatures.iter().find(|t| t.name == name)
+    }
+
+    /// Returns `true` if the device throttles at least moderately, noticeably slowing down
+    /// benchmarks.
+    pub fn is_throttling(&self) -> bool {
+        self.status >= ThrottlingStatus::Moderate
+    }
+}
+
+/// What a [`ThermalMonitor`] observed, reported to a [`ThermalObserver`].
+#[derive(Clone, PartialEq, Debug)]
+pub enum ThermalEvent {
+    /// The throttling status of the device changed.
+    StatusChanged {
+        from: ThrottlingStatus,
+        to: ThrottlingStatus,
+    },
+    /// The sensor `name` went above (`rising`) or back below `threshold`.
+    ThresholdCrossed {
+        name: String,
+        value: f32,
+        threshold: f32,
+        rising: bool,
+    },
+}
+
+/// Receives the [`ThermalEvent`]s of a [`ThermalMonitor`].
+pub trait ThermalObserver: Debug + Send + Sync {
+    fn on_event(&self, event: &ThermalEvent);
+}
+
+/// Polls the thermal state of a device, reporting changes to a [`ThermalObserver`].
+///
+/// The first poll reports the throttling status if the device throttles, and the sensors
+/// already above their threshold.
+///
+/// # Examples
+///
+/// ```no_run
+/// use adb::server::AdbServer;
+/// use adb::thermal::{ThermalEvent, ThermalMonitor, ThermalObserver};
+///
+/// #[derive(Debug)]
+/// struct Log;
+///
+/// impl ThermalObserver for Log {
+///     fn on_event(&self, event: &ThermalEvent) {
+///         eprintln!("thermal: {:?}", event);
+///     }
+/// }
+///
+/// let device = AdbServer::default().any_device();
+/// let monitor = ThermalMonitor::new()
+///     .threshold("skin", 40.0)
+///     .observer(Log)
+///     .spawn(device);
+/// // Run the benchmark.
+/// monitor.stop().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ThermalMonitor {
+    interval: Duration,
+    thresholds: HashMap<String, f32>,
+    clock: Arc<dyn Clock>,
+    observer: Option<Arc<dyn ThermalObserver>>,
+    last: Option<ThermalStatus>,
+}
+
+impl ThermalMonitor {
+    /// Creates a monitor polling every [`DEFAULT_MONITOR_INTERVAL`], without thresholds.
+    pub fn new() -> Self {
+        Self {
+            interval: DEFAULT_MONITOR_INTERVAL,
+            thresholds: HashMap::new(),
+            clock: clock::system(),
+            observer: None,
+            last: None,
+        }
+    }
+
+    /// Sets the interval between two polls.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Reports when the sensor `name` crosses `threshold`, in degrees Celsius.
+    pub fn threshold(mut self, name: impl Into<String>, threshold: f32) -> Self {
+        self.thresholds.insert(name.into(), threshold);
+        self
+    }
+
+    /// Sets the clock timing the polls.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the observer of the events.
+    pub fn observer(mut self, observer: impl ThermalObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Returns the last polled state.
+    pub fn get_last(&self) -> Option<&ThermalStatus> {
+        self.last.as_ref()
+    }
+
+    /// Polls the device once, reporting and returning the events since the last poll.
+    pub fn poll(&mut self, device: &Device) -> Result<Vec<ThermalEvent>, AdbError> {
+        let status = device.thermal()?;
+        Ok(self.update(status))
+    }
+
+    /// Records a new state, reporting and returning the events since the last one.
+    fn update(&mut self, status: ThermalStatus) -> Vec<ThermalEvent> {
+        let mut events = Vec::new();
+        let last_status = self
+            .last
+            .as_ref()
+            .map_or_else(Default::default, |s| s.status);
+        if status.status != last_status {
+            events.push(ThermalEvent::StatusChanged {
+                from: last_status,
+                to: status.status,
+            });
+        }
+        for temperature in &status.temperatures {
+            let Some(&threshold) = self.thresholds.get(&temperature.name) else {
+                continue;
+            };
+            let was_above = self
+                .last
+                .as_ref()
+                .and_then(|last| last.temperature(&temperature.name))
+                .is_some_and(|last| last.value >= threshold);
+            let is_above = temperature.value >= threshold;
+            if was_above != is_above {
+                events.push(ThermalEvent::ThresholdCrossed {
+                    name: temperature.name.clone(),
+                    value: temperature.value,
+                    threshold,
+                    rising: is_above,
+                });
+            }
+        }
+        if let Some(observer) = &self.observer {
+            for event in &events {
+                observer.on_event(event);
+            }
+        }
+        self.last = Some(status);
+        events
+    }
+
+    /// Polls `device` on a thread until [`ThermalMonitorHandle::stop`].
+    pub fn spawn(mut self, device: Device) -> ThermalMonitorHandle {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let stopped = stopped.clone();
+            move || {
+                while !stopped.load(Ordering::Relaxed) {
+                    self.poll(&device).map_err(AdbError::into_io)?;
+                    self.clock.sleep(self.interval);
+                }
+                Ok(self)
+            }
+        });
+        ThermalMonitorHandle { stopped, thread }
+    }
+}
+
+impl Default for ThermalMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`ThermalMonitor`] polling on a thread, returned by [`ThermalMonitor::spawn`].
+#[derive(Debug)]
+pub struct ThermalMonitorHandle {
+    stopped: Arc<AtomicBool>,
+    thread: JoinHandle<io::Result<ThermalMonitor>>,
+}
+
+impl ThermalMonitorHandle {
+    /// Returns `true` if the monitor stopped, after a failed poll.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Stops polling after the current interval, returning the monitor, or the error which
+    /// stopped it.
+    pub fn stop(self) -> io::Result<ThermalMonitor> {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(compat::io_other("thermal monitor panicked")))
+    }
+}
+
+impl Device {
+    /// Returns the throttling status and the temperatures of the device, from
+    /// `dumpsys thermalservice`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let thermal = device.thermal().unwrap();
+    /// for temperature in &thermal.temperatures {
+    ///     println!("{}: {:.1}", temperature.name, temperature.value);
+    /// }
+    /// ```
+    pub fn thermal(&self) -> Result<ThermalStatus, AdbError> {
+        ThermalStatus::parse(&self.shell_checked("dumpsys thermalservice")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const DUMP: &str = "\
+IsStatusOverride: false
+ThermalEventListeners:
+\tcallbacks: 1
+\tkilled: false
+Thermal Status: 2
+Cached temperatures:
+\tTemperature{mValue=30.0, mType=2, mName=battery, mStatus=0}
+HAL Ready: true
+HAL connection:
+\tThermalHAL 2.0 connected: yes
+Current temperatures from HAL:
+\tTemperature{mValue=31.5, mType=2, mName=battery, mStatus=0}
+\tTemperature{mValue=45.25, mType=3, mName=skin, mStatus=2}
+\tTemperature{mValue=1.0, mType=-1, mName=ambient, mStatus=0}
+Current cooling devices from HAL:
+\tCoolingDevice{mValue=0, mType=2, mName=battery}
+";
+
+    #[derive(Debug, Default)]
+    struct Events(Mutex<Vec<ThermalEvent>>);
+
+    impl ThermalObserver for Arc<Events> {
+        fn on_event(&self, event: &ThermalEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn status(status: ThrottlingStatus, skin: f32) -> ThermalStatus {
+        ThermalStatus {
+            status,
+            temperatures: vec![Temperature {
+                name: "skin".to_string(),
+                sensor_type: SensorType::Skin,
+                value: skin,
+                status,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_thermal_status_parse() {
+        let thermal = ThermalStatus::parse(DUMP).unwrap();
+        assert_eq!(ThrottlingStatus::Moderate, thermal.status);
+        assert!(thermal.is_throttling());
+        assert_eq!(3, thermal.temperatures.len());
+        assert_eq!(
+            &Temperature {
+                name: "skin".to_string(),
+                sensor_type: SensorType::Skin,
+                value: 45.25,
+                status: ThrottlingStatus::Moderate,
+            },
+            thermal.temperature("skin").unwrap()
+        );
+        assert_eq!(
+            SensorType::Other(-1),
+            thermal.temperature("ambient").unwrap().sensor_type
+        );
+
+        let cached = DUMP.split("HAL Ready").next().unwrap();
+        let thermal = ThermalStatus::parse(cached).unwrap();
+        assert_eq!(30.0, thermal.temperature("battery").unwrap().value);
+        assert!(ThermalStatus::parse("Can't find service: thermalservice").is_err());
+    }
+
+    #[test]
+    fn test_thermal_monitor_update() {
+        let events = Arc::new(Events::default());
+        let mut monitor = ThermalMonitor::new()
+            .threshold("skin", 40.0)
+            .observer(events.clone());
+        assert!(monitor
+            .update(status(ThrottlingStatus::None, 35.0))
+            .is_empty());
+        assert_eq!(
+            vec![
+                ThermalEvent::StatusChanged {
+                    from: ThrottlingStatus::None,
+                    to: ThrottlingStatus::Severe,
+                },
+                ThermalEvent::ThresholdCrossed {
+                    name: "skin".to_string(),
+                    value: 42.0,
+                    threshold: 40.0,
+                    rising: true,
+                },
+            ],
+            monitor.update(status(ThrottlingStatus::Severe, 42.0))
+        );
+        assert!(monitor
+            .update(status(ThrottlingStatus::Severe, 43.0))
+            .is_empty());
+        assert_eq!(
+            vec![ThermalEvent::ThresholdCrossed {
+                name: "skin".to_string(),
+                value: 39.0,
+                threshold: 40.0,
+                rising: false,
+            }],
+            monitor.update(status(ThrottlingStatus::Severe, 39.0))
+        );
+        assert_eq!(3, events.0.lock().unwrap().len());
+        assert!(ThrottlingStatus::Other(7) > ThrottlingStatus::Shutdown);
+    }
+}
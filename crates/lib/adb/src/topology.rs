@@ -0,0 +1,214 @@
+//! This module maps USB devices to the physical ports of the host they're plugged in, e.g.
+//! for labs to power-cycle a device through the relay or the smart hub of its port.
+//!
+//! On Linux, the server knows the sysfs name of each USB device, like `1-1.3` for port 3 of
+//! the hub in port 1 of bus 1, and reports it as `usb:1-1.3`
+//! (`host-serial:<serial>:get-devpath`). Servers on other systems, or not reporting it, are
+//! worked around by looking for the serial number of the device in `/sys/bus/usb/devices`,
+//! if the server runs on this host.
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::device::Device;
+use crate::error::AdbError;
+
+/// The directory of the USB devices in sysfs.
+pub const SYSFS_USB_DEVICES: &str = "/sys/bus/usb/devices";
+
+/// The location of a USB device: a bus, and the chain of hub ports leading to the device.
+///
+/// # Examples
+///
+/// ```
+/// use adb::topology::UsbPort;
+///
+/// let port: UsbPort = "1-1.3".parse().unwrap();
+/// assert_eq!(Some(3), port.port());
+/// assert_eq!("1-1", port.hub().unwrap().to_string());
+/// ```
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct UsbPort {
+    bus: u8,
+    ports: Vec<u8>,
+}
+
+impl UsbPort {
+    /// Creates the location of the device at `ports` of `bus`, from the root hub down.
+    pub fn new(bus: u8, ports: Vec<u8>) -> Self {
+        Self { bus, ports }
+    }
+
+    /// Returns the number of the bus.
+    pub fn bus(&self) -> u8 {
+        self.bus
+    }
+
+    /// Returns the ports leading to the device, from the root hub down.
+    pub fn ports(&self) -> &[u8] {
+        &self.ports
+    }
+
+    /// Returns the port of the hub the device is plugged in, `None` for a root hub.
+    pub fn port(&self) -> Option<u8> {
+        self.ports.last().copied()
+    }
+
+    /// Returns the location of the hub the device is plugged in, `None` for a device
+    /// plugged in a root hub, whose hub has no port.
+    pub fn hub(&self) -> Option<UsbPort> {
+        match self.ports.len() {
+            0 | 1 => None,
+            n => Some(Self::new(self.bus, self.ports[..n - 1].to_vec())),
+        }
+    }
+
+    /// Returns the sysfs directory of the device, e.g. `/sys/bus/usb/devices/1-1.3`.
+    pub fn sysfs_path(&self) -> PathBuf {
+        Path::new(SYSFS_USB_DEVICES).join(self.to_string())
+    }
+
+    /// Looks for the USB device with the serial number `serial` plugged in this host, in
+    /// sysfs.
+    pub fn find(serial: &str) -> io::Result<Option<UsbPort>> {
+        find_in(Path::new(SYSFS_USB_DEVICES), serial)
+    }
+}
+
+impl Display for UsbPort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-", self.bus)?;
+        for (i, port) in self.ports.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for UsbPort {
+    type Err = AdbError;
+
+    /// Parses a sysfs name like `1-1.3`, or a devpath like `usb:1-1.3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || AdbError::Parse {
+            value: s.to_string(),
+            source_type: "&str",
+            target_type: "UsbPort",
+            source: None,
+        };
+        let name = s.strip_prefix("usb:").unwrap_or(s);
+        let (bus, ports) = name.split_once('-').ok_or_else(error)?;
+        Ok(Self {
+            bus: bus.parse().map_err(|_| error())?,
+            ports: ports
+                .split('.')
+                .map(|port| port.parse().map_err(|_| error()))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Looks for the USB device with the serial number `serial` in `devices`, a copy of
+/// [`SYSFS_USB_DEVICES`].
+fn find_in(devices: &Path, serial: &str) -> io::Result<Option<UsbPort>> {
+    for entry in fs::read_dir(devices)? {
+        let entry = entry?;
+        // Skips the root hubs (`usb1`) and the interfaces (`1-1.3:1.0`).
+        let Ok(port) = entry.file_name().to_string_lossy().parse::<UsbPort>() else {
+            continue;
+        };
+        match fs::read_to_string(entry.path().join("serial")) {
+            Ok(s) if s.trim_end() == serial => return Ok(Some(port)),
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+impl Device {
+    /// Returns the path of the device as known by the server, e.g. `usb:1-1.3` on Linux or
+    /// `unknown` for network devices (`host-serial:<serial>:get-devpath`).
+    pub fn get_devpath(&self) -> Result<String, AdbError> {
+        self.host_request_string("get-devpath")
+    }
+
+    /// Returns the USB port of the device, or `None` if it isn't connected over USB.
+    ///
+    /// The port is reported by the server, or looked up in sysfs if the server doesn't know
+    /// it, which is only meaningful if the server runs on this host.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().device("R58M123ABC");
+    /// if let Some(port) = device.usb_port().unwrap() {
+    ///     // e.g. `uhubctl -l 1-1 -p 3 -a cycle`
+    ///     println!("plugged in port {:?} of hub {:?}", port.port(), port.hub());
+    /// }
+    /// ```
+    pub fn usb_port(&self) -> Result<Option<UsbPort>, AdbError> {
+        let devpath = self.get_devpath()?;
+        if let Some(name) = devpath.strip_prefix("usb:") {
+            return name.parse().map(Some);
+        }
+        if !cfg!(target_os = "linux") {
+            return Ok(None);
+        }
+        let serial = match self.serial() {
+            Some(serial) => serial.to_string(),
+            None => self.get_serialno()?,
+        };
+        match UsbPort::find(&serial) {
+            Ok(port) => Ok(port),
+            // No sysfs, e.g. in a container.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usb_port_parse() {
+        let port: UsbPort = "usb:3-1.4.2".parse().unwrap();
+        assert_eq!(UsbPort::new(3, vec![1, 4, 2]), port);
+        assert_eq!("3-1.4.2", port.to_string());
+        assert_eq!(Some(UsbPort::new(3, vec![1, 4])), port.hub());
+        assert_eq!(None, UsbPort::new(1, vec![2]).hub());
+        assert_eq!(Path::new("/sys/bus/usb/devices/3-1.4.2"), port.sysfs_path());
+        for s in ["usb1", "1-1.3:1.0", "1-", "unknown"] {
+            assert!(s.parse::<UsbPort>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_find_in() {
+        let devices = std::env::temp_dir().join(format!("adb-topology-{}", std::process::id()));
+        for (name, serial) in [
+            ("usb1", "0000:00:14.0"),
+            ("1-1", ""),
+            ("1-1.3", "R58M123ABC"),
+        ] {
+            fs::create_dir_all(devices.join(name)).unwrap();
+            fs::write(devices.join(name).join("serial"), format!("{}\n", serial)).unwrap();
+        }
+        fs::create_dir_all(devices.join("1-1.3:1.0")).unwrap();
+        assert_eq!(
+            Some(UsbPort::new(1, vec![1, 3])),
+            find_in(&devices, "R58M123ABC").unwrap()
+        );
+        assert_eq!(None, find_in(&devices, "emulator-5554").unwrap());
+        fs::remove_dir_all(&devices).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+//! This module provides the instrumentation of the protocol traffic with the `tracing` crate,
+//! to diagnose how a server or a device answers without rebuilding with prints.
+//!
+//! Nothing is recorded unless a subscriber is installed, e.g. with `tracing-subscriber`:
+//!
+//! - Every request to the server or a device is a `request` span of the `adb::request`
+//!   target, with the `op` id, the `service` and the `serial` of the device, see
+//!   [`crate::op`]. Its events report when the request started, then when it was accepted
+//!   or failed, with the elapsed time in microseconds.
+//! - Sync transfers report their byte counts and durations on the `adb::sync` target.
+//! - A server whose version differs from the client, kept with
+//!   [`VersionMismatchPolicy::Warn`](crate::version::VersionMismatchPolicy::Warn), is a
+//!   warning on the `adb::server` target.
+//! - The direct [transports](crate::transport) report every message they send and receive on
+//!   the `adb::transport` target, with its command, arguments and payload length.
+//! - Once enabled with [`set_wire_dump`], the bytes read from and written to the server and
+//!   the payloads of messages are hex dumped on the `adb::wire` target, at the `TRACE`
+//!   level. This is meant to compare what different server versions send, and is toggled
+//!   at runtime since it slows transfers down.
+//!
+//! Connections of the async client are traced as requests, their bytes aren't dumped.
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static WIRE_DUMP: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether the protocol traffic is hex dumped, `false` by default.
+pub fn set_wire_dump(enabled: bool) {
+    WIRE_DUMP.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns `true` if the protocol traffic is hex dumped.
+pub fn wire_dump() -> bool {
+    WIRE_DUMP.load(Ordering::Relaxed)
+}
+
+/// Formats `bytes` as `hexdump -C` does, 16 bytes per line with their offset and their
+/// printable ASCII characters.
+///
+/// # Examples
+///
+/// ```
+/// use adb::trace::hex_dump;
+///
+/// assert_eq!(
+///     "00000000  4f 4b 41 59 00 00                                 |OKAY..|\n",
+///     hex_dump(b"OKAY\0\0"),
+/// );
+/// ```
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let _ = write!(dump, "{:08x} ", i * 16);
+        for column in 0..16 {
+            if column == 8 {
+                dump.push(' ');
+            }
+            match line.get(column) {
+                Some(byte) => {
+                    let _ = write!(dump, " {:02x}", byte);
+                }
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        dump.extend(line.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+/// Dumps the bytes read or written on a connection, `direction` being `read` or `write`, if
+/// [enabled](set_wire_dump).
+pub(crate) fn wire(direction: &'static str, bytes: &[u8]) {
+    if wire_dump() && !bytes.is_empty() {
+        tracing::trace!(
+            target: "adb::wire",
+            direction,
+            len = bytes.len(),
+            "\n{}",
+            hex_dump(bytes)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump() {
+        assert_eq!("", hex_dump(b""));
+        let dump = hex_dump(b"0008host:foo\x01\x02\x03\x04 and more\n");
+        assert_eq!(
+            "00000000  30 30 30 38 68 6f 73 74  3a 66 6f 6f 01 02 03 04  |0008host:foo....|\n\
+             00000010  20 61 6e 64 20 6d 6f 72  65 0a                    | and more.|\n",
+            dump
+        );
+    }
+}
@@ -0,0 +1,717 @@
+//! This module provides tracking of devices through `host:track-devices-l`,
+//! and of debuggable apps on a device through `track-app`.
+//!
+//! After the request is accepted, the server keeps the connection open and sends the full
+//! list of devices, length-prefixed, whenever a device appears, disappears or changes state.
+//! `track-app` works the same way, with the list encoded as an `AppProcesses` protobuf.
+//! With the `proto` feature, `crate::proto` tracks devices in protobuf too, and hands out the
+//! decoded listings themselves.
+//!
+//! A [`DeviceJournal`] keeps the device events on disk, one JSON object per line with the
+//! time and the qualifiers of the device, e.g. to find out when a flaky USB device dropped
+//! overnight:
+//!
+//! ```text
+//! {"time_ms":1700000000123,"event":"disconnect","serial":"0123456789ABCDEF","old_state":"device","new_state":null,"qualifiers":{"usb":"1-1","transport_id":3}}
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cancel::{CancelGuard, CancellationToken};
+use crate::device::{Device, DeviceState};
+use crate::error::AdbError;
+use crate::protocol;
+use crate::server::{self, AdbServer, DeviceQualifiers, ServerStream};
+
+/// The delay between two attempts to reconnect to a restarting server.
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The service tracking devices, listed with their qualifiers like `host:devices-l`.
+pub(crate) const TRACK_DEVICES_SERVICE: &str = "host:track-devices-l";
+
+/// A change of the state of a device.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct DeviceEvent {
+    /// The serial number of the device.
+    pub serial: String,
+    /// The previous state, `None` if the device just appeared.
+    pub old_state: Option<DeviceState>,
+    /// The new state, `None` if the device disappeared.
+    pub new_state: Option<DeviceState>,
+}
+
+impl DeviceEvent {
+    /// Returns the kind of the event in journals: `connect`, `disconnect` or `state`.
+    fn kind(&self) -> &'static str {
+        match (&self.old_state, &self.new_state) {
+            (None, _) => "connect",
+            (_, None) => "disconnect",
+            _ => "state",
+        }
+    }
+}
+
+/// The state of a device tracker, shared by the blocking and async trackers.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub(crate) struct TrackerState {
+    devices: BTreeMap<String, (DeviceState, DeviceQualifiers)>,
+    /// The queued events, with the last qualifiers of their device.
+    events: VecDeque<(DeviceEvent, DeviceQualifiers)>,
+}
+
+impl TrackerState {
+    /// Queues the events between the known devices and the list sent by the server.
+    ///
+    /// Only state changes are events, not changes of the qualifiers.
+    pub(crate) fn update(&mut self, list: &str) -> Result<(), AdbError> {
+        let devices: BTreeMap<_, _> = server::parse_devices(list)?
+            .into_iter()
+            .map(|info| (info.serial, (info.state, info.qualifiers)))
+            .collect();
+        for (serial, (state, qualifiers)) in &self.devices {
+            if !devices.contains_key(serial) {
+                let event = DeviceEvent {
+                    serial: serial.clone(),
+                    old_state: Some(state.clone()),
+                    new_state: None,
+                };
+                self.events.push_back((event, qualifiers.clone()));
+            }
+        }
+        for (serial, (state, qualifiers)) in &devices {
+            let old_state = self.devices.get(serial).map(|(state, _)| state);
+            if old_state != Some(state) {
+                let event = DeviceEvent {
+                    serial: serial.clone(),
+                    old_state: old_state.cloned(),
+                    new_state: Some(state.clone()),
+                };
+                self.events.push_back((event, qualifiers.clone()));
+            }
+        }
+        self.devices = devices;
+        Ok(())
+    }
+
+    /// Returns the next queued event.
+    #[cfg(any(test, feature = "async"))]
+    pub(crate) fn pop(&mut self) -> Option<DeviceEvent> {
+        self.pop_with_qualifiers().map(|(event, _)| event)
+    }
+
+    /// Returns the next queued event, with the qualifiers of its device.
+    pub(crate) fn pop_with_qualifiers(&mut self) -> Option<(DeviceEvent, DeviceQualifiers)> {
+        self.events.pop_front()
+    }
+}
+
+/// Appends `value` to `out` as a JSON string.
+fn write_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Formats the journal line of `event` at `time`, without the trailing newline.
+fn journal_line(event: &DeviceEvent, qualifiers: &DeviceQualifiers, time: SystemTime) -> String {
+    let time_ms = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis());
+    let mut line = format!("{{\"time_ms\":{},\"event\":\"{}\"", time_ms, event.kind());
+    line.push_str(",\"serial\":");
+    write_json_string(&mut line, &event.serial);
+    for (key, state) in [
+        ("old_state", &event.old_state),
+        ("new_state", &event.new_state),
+    ] {
+        let _ = write!(line, ",\"{}\":", key);
+        match state {
+            Some(state) => write_json_string(&mut line, &state.to_string()),
+            None => line.push_str("null"),
+        }
+    }
+    line.push_str(",\"qualifiers\":{");
+    let strings = [
+        ("usb", &qualifiers.usb),
+        ("product", &qualifiers.product),
+        ("model", &qualifiers.model),
+        ("device", &qualifiers.device),
+    ];
+    let numbers = [
+        ("transport_id", qualifiers.transport_id),
+        ("negotiated_speed", qualifiers.negotiated_speed),
+        ("max_speed", qualifiers.max_speed),
+    ];
+    let mut first = true;
+    let mut separator = |line: &mut String| {
+        if !std::mem::take(&mut first) {
+            line.push(',');
+        }
+    };
+    for (key, value) in strings {
+        if let Some(value) = value {
+            separator(&mut line);
+            let _ = write!(line, "\"{}\":", key);
+            write_json_string(&mut line, value);
+        }
+    }
+    for (key, value) in numbers {
+        if let Some(value) = value {
+            separator(&mut line);
+            let _ = write!(line, "\"{}\":{}", key, value);
+        }
+    }
+    line.push_str("}}");
+    line
+}
+
+/// A journal of device events on disk, one JSON object per line, written by a
+/// [`DeviceTracker`].
+///
+/// Every line has the time in milliseconds since the unix epoch (`time_ms`), the kind of
+/// event (`connect`, `disconnect` or `state`), the serial, the old and new states, `null`
+/// when the device appeared or disappeared, and the qualifiers the device was last listed
+/// with.
+#[derive(Debug)]
+pub struct DeviceJournal {
+    file: File,
+}
+
+impl DeviceJournal {
+    /// Opens the journal at `path`, appending to it, and creating it if it doesn't exist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    /// Appends `event`, with the qualifiers of its device, at the current time.
+    ///
+    /// The line is written at once, so journals shared by several trackers don't interleave
+    /// within lines.
+    pub fn record(&mut self, event: &DeviceEvent, qualifiers: &DeviceQualifiers) -> io::Result<()> {
+        let mut line = journal_line(event, qualifiers, SystemTime::now());
+        line.push('\n');
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+/// Returns `true` if a tracker should reconnect after `error`.
+///
+/// Only IO errors and [`AdbError::ServerGone`] are retried, they're caused by the server
+/// going away.
+pub(crate) fn should_reconnect(error: &AdbError, reconnect: bool) -> bool {
+    reconnect && matches!(error.inner(), AdbError::Io(_) | AdbError::ServerGone { .. })
+}
+
+/// An iterator of [`DeviceEvent`]s, created by [`AdbServer::track_devices`].
+///
+/// The first events report the devices connected when tracking starts. The iterator ends after
+/// the first error, unless it [reconnects](Self::reconnect) to a restarting server.
+#[derive(Debug)]
+pub struct DeviceTracker {
+    server: AdbServer,
+    stream: Option<ServerStream>,
+    state: TrackerState,
+    reconnect: bool,
+    journal: Option<DeviceJournal>,
+    done: bool,
+    cancel: Option<CancellationToken>,
+    /// The registration of the current connection on `cancel`.
+    guard: Option<CancelGuard>,
+}
+
+impl DeviceTracker {
+    /// Sets whether to reconnect when the server goes away, e.g. when it restarts.
+    ///
+    /// The tracker then retries every [`RECONNECT_DELAY`] until the server is back,
+    /// and reports the changes that happened in between.
+    pub fn reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Records the events in `journal` as they're returned.
+    ///
+    /// Errors writing the journal are ignored, so that a full disk doesn't stop tracking.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use adb::server::AdbServer;
+    /// use adb::track::DeviceJournal;
+    ///
+    /// let journal = DeviceJournal::open(Path::new("devices.jsonl")).unwrap();
+    /// let tracker = AdbServer::default().track_devices().unwrap();
+    /// for event in tracker.reconnect(true).journal(journal) {
+    ///     println!("{:?}", event.unwrap());
+    /// }
+    /// ```
+    pub fn journal(mut self, journal: DeviceJournal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Ends the iterator when `token` is cancelled, with a last [`AdbError::Cancelled`],
+    /// instead of reconnecting.
+    ///
+    /// Cancelling shuts the connection down, which wakes a tracker waiting for changes.
+    pub fn cancel_on(mut self, token: &CancellationToken) -> Result<Self, AdbError> {
+        if let Some(stream) = &self.stream {
+            self.guard = Some(token.register(stream)?);
+        }
+        self.cancel = Some(token.clone());
+        Ok(self)
+    }
+
+    /// Uses `stream` as the current connection, registering it on the cancellation token.
+    fn set_stream(&mut self, stream: ServerStream) -> Result<(), AdbError> {
+        if let Some(token) = &self.cancel {
+            self.guard = Some(token.register(&stream)?);
+        }
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
+
+impl Iterator for DeviceTracker {
+    type Item = Result<DeviceEvent, AdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((event, qualifiers)) = self.state.pop_with_qualifiers() {
+                if let Some(journal) = &mut self.journal {
+                    let _ = journal.record(&event, &qualifiers);
+                }
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            let result = match &mut self.stream {
+                Some(stream) => {
+                    protocol::read_string(stream).and_then(|list| self.state.update(&list))
+                }
+                None => match self.server.open(TRACK_DEVICES_SERVICE) {
+                    Ok(stream) => match self.set_stream(stream) {
+                        Ok(()) => continue,
+                        Err(e) => Err(e),
+                    },
+                    Err(e) if should_reconnect(&e, self.reconnect) => {
+                        self.server.clock.sleep(RECONNECT_DELAY);
+                        Err(e)
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+            // Checked before reconnecting, the closed connection reads as an I/O error.
+            let result = match &self.cancel {
+                Some(token) => token.map_result(result),
+                None => result,
+            };
+            if let Err(e) = result {
+                self.stream = None;
+                self.guard = None;
+                if !should_reconnect(&e, self.reconnect) {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl AdbServer {
+    /// Tracks the devices known to the server (`host:track-devices-l`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// for event in AdbServer::default().track_devices().unwrap().reconnect(true) {
+    ///     let event = event.unwrap();
+    ///     println!("{}: {:?} -> {:?}", event.serial, event.old_state, event.new_state);
+    /// }
+    /// ```
+    pub fn track_devices(&self) -> Result<DeviceTracker, AdbError> {
+        Ok(DeviceTracker {
+            server: self.clone(),
+            stream: Some(self.open(TRACK_DEVICES_SERVICE)?),
+            state: TrackerState::default(),
+            reconnect: false,
+            journal: None,
+            done: false,
+            cancel: None,
+            guard: None,
+        })
+    }
+}
+
+/// A change of a debuggable or profileable process, reported by [`Device::track_app`].
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct AppProcessEvent {
+    pub pid: i64,
+    /// The package name, read from the command line of the process when it starts.
+    /// `None` if the process exited before it could be read.
+    pub package: Option<String>,
+    pub debuggable: bool,
+    pub profileable: bool,
+    /// The ABI of the process, e.g. `arm64`.
+    pub arch: String,
+    /// `false` if the process exited.
+    pub alive: bool,
+}
+
+/// A value of a protobuf field.
+enum ProtoValue<'a> {
+    Varint(u64),
+    /// A length-delimited or fixed-size value.
+    Bytes(&'a [u8]),
+}
+
+/// Reads a protobuf varint, advancing past it.
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Reads the next field of a protobuf message, advancing past it.
+fn read_field<'a>(bytes: &mut &'a [u8]) -> Option<(u64, ProtoValue<'a>)> {
+    let key = read_varint(bytes)?;
+    let value = match key & 7 {
+        0 => ProtoValue::Varint(read_varint(bytes)?),
+        wire_type => {
+            let length = match wire_type {
+                1 => 8,
+                2 => usize::try_from(read_varint(bytes)?).ok()?,
+                5 => 4,
+                _ => return None,
+            };
+            let (value, rest) = (bytes.get(..length)?, bytes.get(length..)?);
+            *bytes = rest;
+            ProtoValue::Bytes(value)
+        }
+    };
+    Some((key >> 3, value))
+}
+
+/// Decodes an `AppProcesses` protobuf message into alive processes without package names.
+pub fn decode_app_processes(mut bytes: &[u8]) -> Result<Vec<AppProcessEvent>, AdbError> {
+    let err = || AdbError::Protocol {
+        code: None,
+        message: "malformed `AppProcesses` message".to_string(),
+    };
+    let mut processes = Vec::new();
+    while !bytes.is_empty() {
+        // Field 1 holds the processes, other fields are ignored.
+        if let (1, ProtoValue::Bytes(mut entry)) = read_field(&mut bytes).ok_or_else(err)? {
+            let mut process = AppProcessEvent {
+                pid: 0,
+                package: None,
+                debuggable: false,
+                profileable: false,
+                arch: String::new(),
+                alive: true,
+            };
+            while !entry.is_empty() {
+                match read_field(&mut entry).ok_or_else(err)? {
+                    (1, ProtoValue::Varint(pid)) => process.pid = pid as i64,
+                    (2, ProtoValue::Varint(value)) => process.debuggable = value != 0,
+                    (3, ProtoValue::Varint(value)) => process.profileable = value != 0,
+                    (4, ProtoValue::Bytes(arch)) => {
+                        process.arch = String::from_utf8_lossy(arch).into_owned()
+                    }
+                    // Fields added by newer devices.
+                    _ => {}
+                }
+            }
+            processes.push(process);
+        }
+    }
+    Ok(processes)
+}
+
+/// An iterator of [`AppProcessEvent`]s, created by [`Device::track_app`].
+///
+/// The first events report the processes running when tracking starts.
+/// The iterator ends after the first error.
+#[derive(Debug)]
+pub struct AppTracker {
+    device: Device,
+    stream: ServerStream,
+    processes: BTreeMap<i64, AppProcessEvent>,
+    events: VecDeque<AppProcessEvent>,
+    done: bool,
+}
+
+impl AppTracker {
+    /// Queues the events between the known processes and the list sent by the device.
+    fn update(&mut self, processes: Vec<AppProcessEvent>) {
+        let processes: BTreeMap<_, _> = processes
+            .into_iter()
+            .map(|process| (process.pid, process))
+            .collect();
+        for (pid, process) in &self.processes {
+            if !processes.contains_key(pid) {
+                self.events.push_back(AppProcessEvent {
+                    alive: false,
+                    ..process.clone()
+                });
+            }
+        }
+        let mut known = BTreeMap::new();
+        for (pid, mut process) in processes {
+            match self.processes.remove(&pid) {
+                Some(old) => {
+                    process.package = old.package.clone();
+                    if process != old {
+                        self.events.push_back(process.clone());
+                    }
+                }
+                None => {
+                    process.package = self.device.process_name(pid);
+                    self.events.push_back(process.clone());
+                }
+            }
+            known.insert(pid, process);
+        }
+        self.processes = known;
+    }
+}
+
+impl Iterator for AppTracker {
+    type Item = Result<AppProcessEvent, AdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            match protocol::read_length_prefixed(&mut self.stream)
+                .and_then(|message| decode_app_processes(&message))
+            {
+                Ok(processes) => self.update(processes),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl Device {
+    /// Tracks the debuggable and profileable processes of the device (`track-app`).
+    ///
+    /// Requires Android 11 or later.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// for event in device.track_app().unwrap() {
+    ///     let event = event.unwrap();
+    ///     if event.alive && event.debuggable {
+    ///         println!("{} ({:?}) can be debugged", event.pid, event.package);
+    ///     }
+    /// }
+    /// ```
+    pub fn track_app(&self) -> Result<AppTracker, AdbError> {
+        Ok(AppTracker {
+            device: self.clone(),
+            stream: self.open("track-app")?,
+            processes: BTreeMap::new(),
+            events: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// Returns the name of a process from its command line, usually the package name for apps.
+    fn process_name(&self, pid: i64) -> Option<String> {
+        let mut cmdline = Vec::new();
+        self.open(&format!("exec:cat /proc/{}/cmdline", pid))
+            .ok()?
+            .read_to_end(&mut cmdline)
+            .ok()?;
+        let name = cmdline.split(|&b| b == 0).next()?;
+        (!name.is_empty()).then(|| String::from_utf8_lossy(name).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(serial: &str, old: Option<DeviceState>, new: Option<DeviceState>) -> DeviceEvent {
+        DeviceEvent {
+            serial: serial.to_string(),
+            old_state: old,
+            new_state: new,
+        }
+    }
+
+    #[test]
+    fn test_tracker_state_update() {
+        use DeviceState::*;
+        let mut state = TrackerState::default();
+        state.update("a\tdevice\nb\tunauthorized\n").unwrap();
+        assert_eq!(Some(event("a", None, Some(Device))), state.pop());
+        assert_eq!(Some(event("b", None, Some(Unauthorized))), state.pop());
+        assert_eq!(None, state.pop());
+        state.update("b\tdevice\nc\toffline\n").unwrap();
+        assert_eq!(Some(event("a", Some(Device), None)), state.pop());
+        assert_eq!(
+            Some(event("b", Some(Unauthorized), Some(Device))),
+            state.pop()
+        );
+        assert_eq!(Some(event("c", None, Some(Offline))), state.pop());
+        assert_eq!(None, state.pop());
+        state.update("b\tdevice\nc\toffline\n").unwrap();
+        assert_eq!(None, state.pop());
+        state.update("").unwrap();
+        assert_eq!(2, state.events.len());
+        assert!(state.update("b\tno device").is_err());
+        state.update("b\tunknown").unwrap();
+        assert_eq!(
+            Some(&event("b", None, Some(Other("unknown".to_string())))),
+            state.events.back().map(|(event, _)| event)
+        );
+    }
+
+    #[test]
+    fn test_tracker_state_qualifiers() {
+        let mut state = TrackerState::default();
+        state
+            .update("a device usb:1-1 product:sdk transport_id:3\n")
+            .unwrap();
+        let (event, qualifiers) = state.pop_with_qualifiers().unwrap();
+        assert_eq!(event, self::event("a", None, Some(DeviceState::Device)));
+        assert_eq!(Some(3), qualifiers.transport_id);
+        // A device disappearing keeps the qualifiers it was last listed with.
+        state.update("").unwrap();
+        let (_, qualifiers) = state.pop_with_qualifiers().unwrap();
+        assert_eq!(Some("1-1"), qualifiers.usb.as_deref());
+    }
+
+    #[test]
+    fn test_journal_line() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let qualifiers: DeviceQualifiers = "usb:1-1 model:Pixel_7 transport_id:3".parse().unwrap();
+        assert_eq!(
+            "{\"time_ms\":1700000000123,\"event\":\"disconnect\",\"serial\":\"0123456789ABCDEF\",\
+            \"old_state\":\"device\",\"new_state\":null,\
+            \"qualifiers\":{\"usb\":\"1-1\",\"model\":\"Pixel_7\",\"transport_id\":3}}",
+            journal_line(
+                &event("0123456789ABCDEF", Some(DeviceState::Device), None),
+                &qualifiers,
+                time
+            )
+        );
+        let state = DeviceState::NoPermissions;
+        let line = journal_line(
+            &event("a\"b", Some(DeviceState::Offline), Some(state)),
+            &DeviceQualifiers::default(),
+            time,
+        );
+        assert!(
+            line.contains("\"event\":\"state\",\"serial\":\"a\\\"b\""),
+            "{}",
+            line
+        );
+        assert!(line.ends_with("\"qualifiers\":{}}"), "{}", line);
+        let mut s = String::new();
+        write_json_string(&mut s, "tab\t\\ \u{1}");
+        assert_eq!("\"tab\\t\\\\ \\u0001\"", s);
+    }
+
+    #[test]
+    fn test_device_journal() {
+        let path = std::env::temp_dir().join(format!("adb-devices-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let qualifiers = DeviceQualifiers::default();
+        for _ in 0..2 {
+            let mut journal = DeviceJournal::open(&path).unwrap();
+            journal
+                .record(&event("a", None, Some(DeviceState::Device)), &qualifiers)
+                .unwrap();
+        }
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(2, content.lines().count());
+        assert!(content
+            .lines()
+            .all(|line| line.contains("\"event\":\"connect\"")));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_decode_app_processes() {
+        // Two processes, the second one with an unknown varint field 9 and a fixed64 field 10.
+        let mut message = b"\x0a\x0c\x08\x90\x4e\x10\x01\x22\x05arm64".to_vec();
+        message.extend_from_slice(b"\x0a\x0f\x08\x01\x18\x01\x48\x07\x51");
+        message.extend_from_slice(&[0; 8]);
+        let processes = decode_app_processes(&message).unwrap();
+        assert_eq!(2, processes.len());
+        assert_eq!(
+            AppProcessEvent {
+                pid: 10000,
+                package: None,
+                debuggable: true,
+                profileable: false,
+                arch: "arm64".to_string(),
+                alive: true,
+            },
+            processes[0]
+        );
+        assert!(processes[1].profileable && !processes[1].debuggable);
+        assert!(decode_app_processes(b"").unwrap().is_empty());
+        for bytes in [&b"\x0a\x05\x08"[..], b"\x0a\x02\x08\x80", b"\x0b"] {
+            assert!(decode_app_processes(bytes).is_err(), "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn test_should_reconnect() {
+        let io = AdbError::Io(std::io::ErrorKind::ConnectionReset.into());
+        let server = AdbError::Server {
+            message: "failed".to_string(),
+        };
+        assert!(should_reconnect(&io, true));
+        assert!(!should_reconnect(&io, false));
+        let gone = AdbError::ServerGone {
+            service: TRACK_DEVICES_SERVICE.to_string(),
+        };
+        assert!(should_reconnect(&gone, true));
+        assert!(!should_reconnect(&server, true));
+    }
+}
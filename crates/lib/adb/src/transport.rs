@@ -0,0 +1,488 @@
+//! This module provides direct transports to the adb daemon of a device, without an adb
+//! server, speaking the adb [message](message) protocol.
+//!
+//! After the `CNXN` handshake, a service is opened with `OPEN`, and data flows in `WRTE`
+//! messages, each acknowledged with `OKAY` before the next one is sent, until either side
+//! sends `CLSE`.
+//!
+//! Devices requiring authentication (`ro.adb.secure=1`, the default on user builds) reply to
+//! `CNXN` with `AUTH`, handled with the keys of the `auth` module when the
+//! `auth` feature is enabled.
+//!
+//! Wireless debugging (Android 11 and later) replies to `CNXN` with `STLS` instead: both
+//! sides send `STLS`, then the rest of the connection is wrapped in TLS 1.3, the client
+//! authenticating with a certificate of its adb key. This requires the `tls` feature.
+
+pub mod message;
+pub mod tcp;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "usb")]
+pub mod usb;
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "auth")]
+use crate::auth::{AdbKey, AuthType, KeyStore};
+use crate::error::AdbError;
+use crate::protocol;
+
+use message::{Command, Message, MAX_PAYLOAD, VERSION};
+
+/// The banner this client sends in `CNXN`.
+pub const HOST_BANNER: &str = "host::features=shell_v2,cmd,stat_v2,ls_v2,fixed_push_mkdir";
+
+/// A channel exchanging messages with a device, e.g. a USB interface.
+pub trait MessageTransport {
+    /// Reads the next message from the device.
+    fn read_message(&mut self) -> Result<Message, AdbError>;
+
+    /// Writes `message` to the device.
+    fn write_message(&mut self, message: &Message) -> Result<(), AdbError>;
+
+    /// Wraps the rest of the connection in TLS after the `STLS` exchange, authenticating
+    /// with `key`.
+    ///
+    /// Fails by default, for transports which can't carry TLS like USB.
+    #[cfg(feature = "tls")]
+    fn start_tls(&mut self, key: &AdbKey) -> Result<(), AdbError> {
+        let _ = key;
+        Err(AdbError::Protocol {
+            code: None,
+            message: "the transport doesn't support TLS".to_string(),
+        })
+    }
+}
+
+/// Writes `message` to `transport`, tracing it with the `tracing` feature.
+fn send<T: MessageTransport>(transport: &mut T, message: &Message) -> Result<(), AdbError> {
+    #[cfg(feature = "tracing")]
+    trace_message("write", message);
+    transport.write_message(message)
+}
+
+/// Reads a message from `transport`, tracing it with the `tracing` feature.
+fn receive<T: MessageTransport>(transport: &mut T) -> Result<Message, AdbError> {
+    let message = transport.read_message()?;
+    #[cfg(feature = "tracing")]
+    trace_message("read", &message);
+    Ok(message)
+}
+
+#[cfg(feature = "tracing")]
+fn trace_message(direction: &'static str, message: &Message) {
+    tracing::trace!(
+        target: "adb::transport",
+        direction,
+        command = ?message.command,
+        arg0 = message.arg0,
+        arg1 = message.arg1,
+        len = message.payload.len(),
+    );
+    crate::trace::wire(direction, &message.payload);
+}
+
+/// Reads a message from a byte stream, e.g. a TCP connection to `adbd`.
+pub fn read_message<R: Read>(reader: &mut R) -> Result<Message, AdbError> {
+    protocol::read_decoded(reader, message::decode_message)
+}
+
+/// A connection to the adb daemon of a device over a [`MessageTransport`].
+///
+/// Only one service is open at a time, borrowing the connection.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::Read;
+/// use adb::transport::usb::UsbTransport;
+/// use adb::transport::AdbConnection;
+///
+/// let mut connection = AdbConnection::connect(UsbTransport::open_any().unwrap()).unwrap();
+/// let mut output = String::new();
+/// connection.open("shell:getprop ro.product.model").unwrap().read_to_string(&mut output).unwrap();
+/// println!("{}", output.trim());
+/// ```
+#[derive(Debug)]
+pub struct AdbConnection<T> {
+    transport: T,
+    banner: String,
+    max_payload: usize,
+    last_id: u32,
+    /// The key the device accepted, `None` if it required no authentication.
+    #[cfg(feature = "auth")]
+    key: Option<AdbKey>,
+}
+
+impl<T: MessageTransport> AdbConnection<T> {
+    /// Connects to the device with a `CNXN` handshake.
+    ///
+    /// Fails if the device requires authentication, see [`Self::connect_with_keys`].
+    pub fn connect(transport: T) -> Result<Self, AdbError> {
+        Self::handshake(transport, |_, _| {
+            Err(AdbError::Protocol {
+                code: None,
+                message: "the device requires authentication".to_string(),
+            })
+        })
+    }
+
+    /// Connects to the device, authenticating with `keys` if the device requires it.
+    ///
+    /// If the device accepts none of the keys, the public key of the first one is sent, and
+    /// the device asks the user to allow it. The transport must not time out meanwhile.
+    ///
+    /// If the device asks for TLS, the first key is the client certificate, the device
+    /// closing the connection if it wasn't paired with it.
+    ///
+    /// The key the device accepted, and so its identity, is then
+    /// [`Self::authenticated_key`].
+    #[cfg(feature = "auth")]
+    pub fn connect_with_keys(transport: T, keys: &KeyStore) -> Result<Self, AdbError> {
+        let mut tried = 0;
+        // The index of the last key sent, accepted if the device replies with `CNXN`.
+        let mut sent = None;
+        let mut connection = Self::handshake(transport, |transport, message| {
+            if message.command == Command::StartTls {
+                sent = Some(0);
+                return Self::start_tls(transport, keys);
+            }
+            if AuthType::from_u32(message.arg0) != Some(AuthType::Token) {
+                return Ok(());
+            }
+            let reply = match keys.keys().get(tried) {
+                Some(key) => Message::new(
+                    Command::Auth,
+                    AuthType::Signature.to_u32(),
+                    0,
+                    key.sign(&message.payload)?,
+                ),
+                None if tried == keys.keys().len() && !keys.keys().is_empty() => {
+                    let mut public_key = keys.keys()[0].public_key().into_bytes();
+                    public_key.push(0);
+                    Message::new(
+                        Command::Auth,
+                        AuthType::RsaPublicKey.to_u32(),
+                        0,
+                        public_key,
+                    )
+                }
+                // The device rejected every key and shows the prompt for the last one.
+                None => return Err(AdbError::Unauthorized),
+            };
+            // After every signature, the public key sent is the first key's.
+            sent = Some(tried % keys.keys().len());
+            tried += 1;
+            send(transport, &reply)
+        })?;
+        connection.key = sent.map(|index| keys.keys()[index].clone());
+        Ok(connection)
+    }
+
+    /// Replies to the `STLS` of the device and wraps the transport in TLS.
+    #[cfg(feature = "tls")]
+    fn start_tls(transport: &mut T, keys: &KeyStore) -> Result<(), AdbError> {
+        let key = keys.keys().first().ok_or_else(|| AdbError::Protocol {
+            code: None,
+            message: "the device requires TLS, but no key was given".to_string(),
+        })?;
+        let reply = Message::new(Command::StartTls, message::STLS_VERSION, 0, Vec::new());
+        send(transport, &reply)?;
+        transport.start_tls(key)
+    }
+
+    #[cfg(all(feature = "auth", not(feature = "tls")))]
+    fn start_tls(_: &mut T, _: &KeyStore) -> Result<(), AdbError> {
+        Err(AdbError::Protocol {
+            code: None,
+            message: "the device requires TLS, enable the `tls` feature".to_string(),
+        })
+    }
+
+    /// Sends `CNXN` and waits for the `CNXN` of the device, handing `AUTH` and `STLS` to
+    /// `on_auth`.
+    fn handshake<F>(mut transport: T, mut on_auth: F) -> Result<Self, AdbError>
+    where
+        F: FnMut(&mut T, &Message) -> Result<(), AdbError>,
+    {
+        let mut banner = HOST_BANNER.as_bytes().to_vec();
+        banner.push(0);
+        send(
+            &mut transport,
+            &Message::new(Command::Connect, VERSION, MAX_PAYLOAD, banner),
+        )?;
+        loop {
+            let message = receive(&mut transport)?;
+            match message.command {
+                Command::Connect => {
+                    let banner = String::from_utf8_lossy(&message.payload);
+                    return Ok(Self {
+                        transport,
+                        banner: banner.trim_end_matches('\0').to_string(),
+                        max_payload: message.arg1.min(MAX_PAYLOAD) as usize,
+                        last_id: 0,
+                        #[cfg(feature = "auth")]
+                        key: None,
+                    });
+                }
+                Command::Auth | Command::StartTls => on_auth(&mut transport, &message)?,
+                // Leftovers of a previous connection.
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the banner of the device, e.g. `device::ro.product.name=...;features=...`.
+    pub fn banner(&self) -> &str {
+        &self.banner
+    }
+
+    /// Returns the features listed in the banner of the device.
+    pub fn features(&self) -> Vec<&str> {
+        self.banner
+            .split(';')
+            .find_map(|property| property.strip_prefix("features="))
+            .map(|features| features.split(',').collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the key the device accepted, `None` if it required no authentication.
+    ///
+    /// With TLS, this is the key of the client certificate.
+    #[cfg(feature = "auth")]
+    pub fn authenticated_key(&self) -> Option<&AdbKey> {
+        self.key.as_ref()
+    }
+
+    /// Returns the identity of the key the device accepted, see [`AdbKey::identity`].
+    #[cfg(feature = "auth")]
+    pub fn identity(&self) -> Option<&str> {
+        self.key.as_ref().and_then(AdbKey::identity)
+    }
+
+    /// Returns the maximum payload size of a message, negotiated in `CNXN`.
+    pub fn max_payload(&self) -> usize {
+        self.max_payload
+    }
+
+    /// Opens `service` on the device, e.g. `shell:ls`.
+    pub fn open(&mut self, service: &str) -> Result<MessageStream<'_, T>, AdbError> {
+        self.last_id += 1;
+        let local_id = self.last_id;
+        let mut payload = service.as_bytes().to_vec();
+        payload.push(0);
+        send(
+            &mut self.transport,
+            &Message::new(Command::Open, local_id, 0, payload),
+        )?;
+        loop {
+            let message = receive(&mut self.transport)?;
+            if message.arg1 != local_id {
+                continue;
+            }
+            match message.command {
+                Command::Okay => {
+                    return Ok(MessageStream {
+                        connection: self,
+                        local_id,
+                        remote_id: message.arg0,
+                        buffer: VecDeque::new(),
+                        closed: false,
+                    })
+                }
+                Command::Close => {
+                    return Err(AdbError::Server {
+                        message: format!("the device closed `{}`", service),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Closes the connection and returns the transport.
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+}
+
+/// A service opened with [`AdbConnection::open`].
+///
+/// The service is closed when the stream is dropped.
+#[derive(Debug)]
+pub struct MessageStream<'a, T: MessageTransport> {
+    connection: &'a mut AdbConnection<T>,
+    local_id: u32,
+    remote_id: u32,
+    buffer: VecDeque<u8>,
+    closed: bool,
+}
+
+impl<'a, T: MessageTransport> MessageStream<'a, T> {
+    /// Handles a message of the device, returning `true` if it acknowledges a write.
+    fn handle(&mut self, message: Message) -> Result<bool, AdbError> {
+        if message.arg1 != self.local_id {
+            return Ok(false);
+        }
+        match message.command {
+            Command::Write => {
+                self.buffer.extend(message.payload);
+                send(
+                    &mut self.connection.transport,
+                    &Message::new(Command::Okay, self.local_id, self.remote_id, Vec::new()),
+                )?;
+                Ok(false)
+            }
+            Command::Okay => Ok(true),
+            Command::Close => {
+                self.closed = true;
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn read_message(&mut self) -> Result<Message, AdbError> {
+        receive(&mut self.connection.transport)
+    }
+}
+
+impl<'a, T: MessageTransport> Read for MessageStream<'a, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.buffer.is_empty() && !self.closed {
+            let message = self.read_message().map_err(AdbError::into_io)?;
+            self.handle(message).map_err(AdbError::into_io)?;
+        }
+        let length = buf.len().min(self.buffer.len());
+        for (dest, byte) in buf.iter_mut().zip(self.buffer.drain(..length)) {
+            *dest = byte;
+        }
+        Ok(length)
+    }
+}
+
+impl<'a, T: MessageTransport> Write for MessageStream<'a, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let length = buf.len().min(self.connection.max_payload);
+        let message = Message::new(
+            Command::Write,
+            self.local_id,
+            self.remote_id,
+            &buf[..length],
+        );
+        send(&mut self.connection.transport, &message).map_err(AdbError::into_io)?;
+        // The next write has to wait for the acknowledgement.
+        loop {
+            let message = self.read_message().map_err(AdbError::into_io)?;
+            if self.handle(message).map_err(AdbError::into_io)? {
+                return Ok(length);
+            }
+            if self.closed {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, T: MessageTransport> Drop for MessageStream<'a, T> {
+    fn drop(&mut self) {
+        if !self.closed {
+            let close = Message::new(Command::Close, self.local_id, self.remote_id, Vec::new());
+            let _ = send(&mut self.connection.transport, &close);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A device replying with scripted messages, recording the messages of the client.
+    #[derive(Default)]
+    struct MockTransport {
+        replies: VecDeque<Message>,
+        sent: Vec<Message>,
+    }
+
+    impl MessageTransport for MockTransport {
+        fn read_message(&mut self) -> Result<Message, AdbError> {
+            self.replies
+                .pop_front()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof).into())
+        }
+
+        fn write_message(&mut self, message: &Message) -> Result<(), AdbError> {
+            self.sent.push(message.clone());
+            Ok(())
+        }
+    }
+
+    fn connected(replies: Vec<Message>) -> AdbConnection<MockTransport> {
+        let banner = &b"device::ro.product.name=sdk;features=shell_v2,cmd\0"[..];
+        let mut transport = MockTransport::default();
+        transport
+            .replies
+            .push_back(Message::new(Command::Connect, VERSION, 4096, banner));
+        transport.replies.extend(replies);
+        AdbConnection::connect(transport).unwrap()
+    }
+
+    #[test]
+    fn test_connect() {
+        let connection = connected(Vec::new());
+        assert_eq!(["shell_v2", "cmd"], &connection.features()[..]);
+        assert_eq!(4096, connection.max_payload());
+        assert_eq!(Command::Connect, connection.transport.sent[0].command);
+        let mut transport = MockTransport::default();
+        transport
+            .replies
+            .push_back(Message::new(Command::Auth, 1, 0, vec![0; 20]));
+        assert!(AdbConnection::connect(transport).is_err());
+        let mut transport = MockTransport::default();
+        transport.replies.push_back(Message::new(
+            Command::StartTls,
+            message::STLS_VERSION,
+            0,
+            Vec::new(),
+        ));
+        assert!(AdbConnection::connect(transport).is_err());
+    }
+
+    #[test]
+    fn test_open_read_write() {
+        let mut connection = connected(vec![
+            Message::new(Command::Okay, 7, 1, Vec::new()),
+            Message::new(Command::Okay, 7, 1, Vec::new()),
+            Message::new(Command::Write, 7, 1, &b"hello"[..]),
+            Message::new(Command::Close, 7, 1, Vec::new()),
+        ]);
+        let mut stream = connection.open("shell:cat").unwrap();
+        stream.write_all(b"hi").unwrap();
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+        assert_eq!("hello", output);
+        drop(stream);
+        let sent: Vec<_> = connection.transport.sent[1..]
+            .iter()
+            .map(|message| (message.command, message.arg0, message.arg1))
+            .collect();
+        assert_eq!(
+            [
+                (Command::Open, 1, 0),
+                (Command::Write, 1, 7),
+                (Command::Okay, 1, 7),
+            ],
+            &sent[..]
+        );
+        let mut connection = connected(vec![Message::new(Command::Close, 0, 1, Vec::new())]);
+        assert!(connection.open("shell:missing").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! This module provides the framing of the adb message protocol, spoken between an adb
+//! server and the adb daemon of a device.
+//!
+//! A message is a 24 bytes header of six little-endian `u32`, followed by a payload:
+//! the command, two arguments, the payload length, the payload checksum and the command
+//! xor `0xffffffff`. Since [`VERSION_SKIP_CHECKSUM`], the checksum may be zero.
+
+use crate::error::AdbError;
+use crate::protocol::Decoded;
+
+/// The protocol version sent by this client.
+pub const VERSION: u32 = 0x0100_0001;
+
+/// The minimum protocol version of devices which don't check the payload checksum.
+pub const VERSION_SKIP_CHECKSUM: u32 = 0x0100_0001;
+
+/// The version of the TLS upgrade, sent in `STLS`.
+pub const STLS_VERSION: u32 = 0x0100_0000;
+
+/// The maximum payload size this client accepts, advertised in `CNXN`.
+pub const MAX_PAYLOAD: u32 = 1024 * 1024;
+
+/// The size of the message header.
+pub const HEADER_SIZE: usize = 24;
+
+/// The command of a message.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Command {
+    /// `SYNC`, obsolete.
+    Sync,
+    /// `CNXN(version, max_payload, "<system-type>:<serial>:<banner>")`
+    Connect,
+    /// `AUTH(type, 0, data)`
+    Auth,
+    /// `OPEN(local_id, 0, "<service>\0")`
+    Open,
+    /// `OKAY(local_id, remote_id, "")`
+    Okay,
+    /// `CLSE(local_id, remote_id, "")`
+    Close,
+    /// `WRTE(local_id, remote_id, data)`
+    Write,
+    /// `STLS(type, version, "")`, starting TLS.
+    StartTls,
+}
+
+impl Command {
+    /// Returns the command as sent over the wire.
+    pub const fn to_u32(self) -> u32 {
+        u32::from_le_bytes(*self.name())
+    }
+
+    /// Returns the command sent over the wire as `command`.
+    pub const fn from_u32(command: u32) -> Option<Self> {
+        Some(match &command.to_le_bytes() {
+            b"SYNC" => Self::Sync,
+            b"CNXN" => Self::Connect,
+            b"AUTH" => Self::Auth,
+            b"OPEN" => Self::Open,
+            b"OKAY" => Self::Okay,
+            b"CLSE" => Self::Close,
+            b"WRTE" => Self::Write,
+            b"STLS" => Self::StartTls,
+            _ => return None,
+        })
+    }
+
+    /// Returns the name of the command, e.g. `CNXN`.
+    pub const fn name(self) -> &'static [u8; 4] {
+        match self {
+            Self::Sync => b"SYNC",
+            Self::Connect => b"CNXN",
+            Self::Auth => b"AUTH",
+            Self::Open => b"OPEN",
+            Self::Okay => b"OKAY",
+            Self::Close => b"CLSE",
+            Self::Write => b"WRTE",
+            Self::StartTls => b"STLS",
+        }
+    }
+}
+
+/// A message of the adb message protocol.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Message {
+    pub command: Command,
+    pub arg0: u32,
+    pub arg1: u32,
+    pub payload: Vec<u8>,
+}
+
+/// Returns the checksum of a payload, the sum of its bytes.
+pub fn checksum(payload: &[u8]) -> u32 {
+    payload
+        .iter()
+        .fold(0u32, |sum, &byte| sum.wrapping_add(byte as u32))
+}
+
+impl Message {
+    /// Creates a message.
+    pub fn new(command: Command, arg0: u32, arg1: u32, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            command,
+            arg0,
+            arg1,
+            payload: payload.into(),
+        }
+    }
+
+    /// Encodes the header of the message, always with a checksum for older devices.
+    pub fn encode_header(&self) -> [u8; HEADER_SIZE] {
+        let command = self.command.to_u32();
+        let fields = [
+            command,
+            self.arg0,
+            self.arg1,
+            self.payload.len() as u32,
+            checksum(&self.payload),
+            command ^ 0xffff_ffff,
+        ];
+        let mut header = [0; HEADER_SIZE];
+        for (chunk, field) in header.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        header
+    }
+
+    /// Encodes the message for the wire.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut message = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        message.extend_from_slice(&self.encode_header());
+        message.extend_from_slice(&self.payload);
+        message
+    }
+}
+
+/// Decodes a message, rejecting payloads larger than [`MAX_PAYLOAD`].
+pub fn decode_message(bytes: &[u8]) -> Result<Decoded<Message>, AdbError> {
+    let Some(header) = bytes.get(..HEADER_SIZE) else {
+        return Ok(Decoded::Incomplete {
+            needed: HEADER_SIZE,
+        });
+    };
+    let field = |i: usize| u32::from_le_bytes(header[i * 4..i * 4 + 4].try_into().unwrap());
+    let (raw_command, length, sum, magic) = (field(0), field(3), field(4), field(5));
+    let command = Command::from_u32(raw_command).ok_or_else(|| AdbError::Protocol {
+        code: Some(raw_command),
+        message: format!("unknown message command {:#010x}", raw_command),
+    })?;
+    if magic != raw_command ^ 0xffff_ffff {
+        return Err(AdbError::Protocol {
+            code: None,
+            message: format!("invalid magic {:#010x} of a {:?} message", magic, command),
+        });
+    }
+    if length > MAX_PAYLOAD {
+        return Err(AdbError::Protocol {
+            code: None,
+            message: format!("message payload of {} bytes is too large", length),
+        });
+    }
+    let length = length as usize;
+    let Some(payload) = bytes.get(HEADER_SIZE..HEADER_SIZE + length) else {
+        return Ok(Decoded::Incomplete {
+            needed: HEADER_SIZE + length,
+        });
+    };
+    if sum != 0 && sum != checksum(payload) {
+        return Err(AdbError::Protocol {
+            code: None,
+            message: format!("invalid checksum of a {:?} message", command),
+        });
+    }
+    Ok(Decoded::Complete {
+        value: Message::new(command, field(1), field(2), payload),
+        length: HEADER_SIZE + length,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_to_u32() {
+        assert_eq!(0x4e584e43, Command::Connect.to_u32());
+        assert_eq!(0x45545257, Command::Write.to_u32());
+        assert_eq!(Some(Command::StartTls), Command::from_u32(0x534c5453));
+        assert_eq!(None, Command::from_u32(0));
+    }
+
+    #[test]
+    fn test_message_encode_decode() {
+        let message = Message::new(Command::Open, 1, 0, &b"shell:ls\0"[..]);
+        let encoded = message.encode();
+        assert_eq!(
+            b"OPEN\x01\x00\x00\x00\x00\x00\x00\x00\x09\x00\x00\x00",
+            &encoded[..16]
+        );
+        assert_eq!(
+            Decoded::Complete {
+                value: message,
+                length: 33
+            },
+            decode_message(&encoded).unwrap()
+        );
+        assert_eq!(
+            Decoded::Incomplete { needed: 33 },
+            decode_message(&encoded[..30]).unwrap()
+        );
+        assert_eq!(
+            Decoded::Incomplete { needed: 24 },
+            decode_message(b"OPEN").unwrap()
+        );
+        // Devices may skip the checksum.
+        let mut unchecked = encoded.clone();
+        unchecked[16..20].fill(0);
+        assert!(decode_message(&unchecked).is_ok());
+        let mut corrupted = encoded.clone();
+        corrupted[24] ^= 1;
+        assert!(decode_message(&corrupted).is_err());
+        let mut corrupted = encoded;
+        corrupted[20] ^= 1;
+        assert!(decode_message(&corrupted).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! This module provides the TCP transport, talking to the adb daemon of a device listening
+//! on the network, e.g. after `adb tcpip 5555` or with wireless debugging.
+//!
+//! Messages are sent back to back over the stream, wrapped in TLS after `STLS` when the
+//! `tls` feature is enabled.
+
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::error::AdbError;
+use crate::transport::message::Message;
+use crate::transport::{self, MessageTransport};
+
+/// The port of `adb tcpip` by default.
+pub const DEFAULT_PORT: u16 = 5555;
+
+/// A TCP connection to the adb daemon of a device.
+#[derive(Debug)]
+pub struct TcpTransport {
+    stream: TcpStream,
+    #[cfg(feature = "tls")]
+    tls: Option<Box<rustls::ClientConnection>>,
+}
+
+impl TcpTransport {
+    /// Connects to the adb daemon listening at `addr`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, AdbError> {
+        Ok(Self::from_stream(TcpStream::connect(addr)?))
+    }
+
+    /// Uses a connected stream.
+    pub fn from_stream(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Sets the read and write timeouts of the stream.
+    pub fn timeout(self, timeout: Option<Duration>) -> Result<Self, AdbError> {
+        self.stream.set_read_timeout(timeout)?;
+        self.stream.set_write_timeout(timeout)?;
+        Ok(self)
+    }
+
+    /// Returns `true` if the connection is wrapped in TLS.
+    pub fn is_tls(&self) -> bool {
+        #[cfg(feature = "tls")]
+        return self.tls.is_some();
+        #[cfg(not(feature = "tls"))]
+        false
+    }
+}
+
+impl MessageTransport for TcpTransport {
+    fn read_message(&mut self) -> Result<Message, AdbError> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &mut self.tls {
+            return transport::read_message(&mut rustls::Stream::new(&mut **tls, &mut self.stream));
+        }
+        transport::read_message(&mut self.stream)
+    }
+
+    fn write_message(&mut self, message: &Message) -> Result<(), AdbError> {
+        let encoded = message.encode();
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &mut self.tls {
+            let mut stream = rustls::Stream::new(&mut **tls, &mut self.stream);
+            stream.write_all(&encoded)?;
+            return Ok(stream.flush()?);
+        }
+        Ok(self.stream.write_all(&encoded)?)
+    }
+
+    #[cfg(feature = "tls")]
+    fn start_tls(&mut self, key: &crate::auth::AdbKey) -> Result<(), AdbError> {
+        let mut tls = transport::tls::client_connection(key)?;
+        while tls.is_handshaking() {
+            tls.complete_io(&mut self.stream)?;
+        }
+        self.tls = Some(Box::new(tls));
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! This module provides the TLS layer of wireless debugging connections, on top of rustls.
+//!
+//! Like adb, the client presents a self-signed certificate of its adb key
+//! (`C=US, O=Android, CN=Adb`), built on the fly, which the device checks against the keys
+//! it was paired with. The certificate of the device is self-signed as well and isn't
+//! verified, the pairing being the trust anchor, but its handshake signature is.
+
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rsa::pkcs8::{EncodePrivateKey, EncodePublicKey};
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme};
+use sha2::{Digest, Sha256};
+
+use crate::auth::{key_error, AdbKey};
+use crate::error::AdbError;
+
+/// The validity of client certificates, like adb.
+pub const CERTIFICATE_VALIDITY: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+/// The DER encoded OID of `sha256WithRSAEncryption`, 1.2.840.113549.1.1.11.
+const SHA256_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+
+fn tls_error<E: Display>(e: E) -> AdbError {
+    AdbError::Protocol {
+        code: None,
+        message: format!("TLS error: {}", e),
+    }
+}
+
+/// Encodes a DER value.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut value = vec![tag];
+    if content.len() < 0x80 {
+        value.push(content.len() as u8);
+    } else {
+        let length = (content.len() as u64).to_be_bytes();
+        let skip = length.iter().take_while(|&&byte| byte == 0).count();
+        value.push(0x80 | (length.len() - skip) as u8);
+        value.extend_from_slice(&length[skip..]);
+    }
+    value.extend_from_slice(content);
+    value
+}
+
+fn sequence(items: &[Vec<u8>]) -> Vec<u8> {
+    der(0x30, &items.concat())
+}
+
+/// Encodes a time as `UTCTime` until 2049, then as `GeneralizedTime`, as required by X.509.
+fn time(time: SystemTime) -> Vec<u8> {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    // Converts days to a civil date (Howard Hinnant's `civil_from_days`).
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    let clock = format!(
+        "{:02}{:02}{:02}{:02}{:02}Z",
+        month,
+        day,
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    );
+    if (1950..2050).contains(&year) {
+        der(0x17, format!("{:02}{}", year % 100, clock).as_bytes())
+    } else {
+        der(0x18, format!("{:04}{}", year, clock).as_bytes())
+    }
+}
+
+/// Encodes the subject and issuer of client certificates.
+fn name() -> Vec<u8> {
+    let rdn = |oid: &[u8], value: &str| {
+        der(
+            0x31,
+            &sequence(&[der(0x06, oid), der(0x13, value.as_bytes())]),
+        )
+    };
+    sequence(&[
+        rdn(&[0x55, 0x04, 0x06], "US"),
+        rdn(&[0x55, 0x04, 0x0a], "Android"),
+        rdn(&[0x55, 0x04, 0x03], "Adb"),
+    ])
+}
+
+/// Builds the DER encoded, self-signed X.509 certificate of `key`, valid from now for
+/// [`CERTIFICATE_VALIDITY`].
+pub fn certificate(key: &AdbKey) -> Result<Vec<u8>, AdbError> {
+    let public_key = RsaPublicKey::from(key.rsa_key())
+        .to_public_key_der()
+        .map_err(key_error)?;
+    let algorithm = sequence(&[der(0x06, SHA256_WITH_RSA), der(0x05, &[])]);
+    let now = SystemTime::now();
+    let tbs = sequence(&[
+        // [0] EXPLICIT version v3
+        der(0xa0, &der(0x02, &[2])),
+        // serial number
+        der(0x02, &[1]),
+        algorithm.clone(),
+        name(),
+        sequence(&[time(now), time(now + CERTIFICATE_VALIDITY)]),
+        name(),
+        public_key.as_bytes().to_vec(),
+    ]);
+    let mut signature = vec![0];
+    signature.extend(
+        key.rsa_key()
+            .sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(&tbs))
+            .map_err(key_error)?,
+    );
+    Ok(sequence(&[tbs, algorithm, der(0x03, &signature)]))
+}
+
+/// Accepts the certificate of any device, checking only its handshake signatures.
+#[derive(Debug)]
+struct PairedDevice {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PairedDevice {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// Creates the client side of a TLS 1.3 connection to a device, authenticating with `key`.
+pub fn client_connection(key: &AdbKey) -> Result<ClientConnection, AdbError> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let verifier = Arc::new(PairedDevice {
+        algorithms: provider.signature_verification_algorithms,
+    });
+    let private_key = key.rsa_key().to_pkcs8_der().map_err(key_error)?;
+    let private_key =
+        PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(private_key.as_bytes().to_vec()));
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(tls_error)?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_client_auth_cert(vec![CertificateDer::from(certificate(key)?)], private_key)
+        .map_err(tls_error)?;
+    // adbd ignores the server name.
+    let server_name = ServerName::try_from("adb").map_err(tls_error)?;
+    ClientConnection::new(Arc::new(config), server_name).map_err(tls_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_der() {
+        assert_eq!([0x02, 0x01, 0x01], &der(0x02, &[1])[..]);
+        assert_eq!([0x04, 0x81, 0xc8], &der(0x04, &[0; 200])[..3]);
+        assert_eq!([0x04, 0x82, 0x01, 0x2c], &der(0x04, &[0; 300])[..4]);
+    }
+
+    #[test]
+    fn test_time() {
+        let at = |secs| time(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(der(0x17, b"700101000000Z"), at(0));
+        assert_eq!(der(0x17, b"240229123456Z"), at(1709210096));
+        assert_eq!(der(0x18, b"20500101000000Z"), at(2524608000));
+    }
+
+    #[test]
+    fn test_name() {
+        let name = name();
+        assert_eq!(0x30, name[0]);
+        assert!(name.windows(7).any(|window| window == b"Android"));
+    }
+}
@@ -0,0 +1,237 @@
+//! This module provides the USB transport, talking to the adb interface of a device with
+//! libusb through `rusb`.
+//!
+//! The adb interface has the class `0xff`, subclass `0x42` and protocol `0x01`, and a pair of
+//! bulk endpoints. The header and the payload of a message are sent in separate transfers.
+//!
+//! A running adb server claims the interface of every device, so it has to be killed first.
+
+use std::io;
+use std::time::Duration;
+
+use rusb::{Device, DeviceHandle, Direction, GlobalContext, TransferType};
+
+use crate::compat;
+use crate::error::AdbError;
+use crate::protocol::Decoded;
+use crate::transport::message::{self, Message, HEADER_SIZE};
+use crate::transport::MessageTransport;
+
+/// The class of the adb interface.
+pub const ADB_CLASS: u8 = 0xff;
+/// The subclass of the adb interface.
+pub const ADB_SUBCLASS: u8 = 0x42;
+/// The protocol of the adb interface.
+pub const ADB_PROTOCOL: u8 = 0x01;
+
+/// The default timeout of a bulk transfer.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Converts a libusb error.
+fn usb_error(e: rusb::Error) -> AdbError {
+    let kind = match e {
+        rusb::Error::Timeout => io::ErrorKind::TimedOut,
+        rusb::Error::NoDevice => io::ErrorKind::NotConnected,
+        rusb::Error::Access => io::ErrorKind::PermissionDenied,
+        rusb::Error::NotFound => io::ErrorKind::NotFound,
+        _ => return compat::io_other(e).into(),
+    };
+    io::Error::new(kind, e).into()
+}
+
+/// The adb interface of a USB device.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+struct AdbInterface {
+    number: u8,
+    endpoint_in: u8,
+    endpoint_out: u8,
+}
+
+/// Finds the adb interface of `device`.
+fn find_interface(device: &Device<GlobalContext>) -> Result<Option<AdbInterface>, AdbError> {
+    let config = device.active_config_descriptor().map_err(usb_error)?;
+    for interface in config.interfaces() {
+        for descriptor in interface.descriptors() {
+            if (
+                descriptor.class_code(),
+                descriptor.sub_class_code(),
+                descriptor.protocol_code(),
+            ) != (ADB_CLASS, ADB_SUBCLASS, ADB_PROTOCOL)
+            {
+                continue;
+            }
+            let bulk = |direction| {
+                descriptor
+                    .endpoint_descriptors()
+                    .find(|endpoint| {
+                        endpoint.transfer_type() == TransferType::Bulk
+                            && endpoint.direction() == direction
+                    })
+                    .map(|endpoint| endpoint.address())
+            };
+            if let (Some(endpoint_in), Some(endpoint_out)) =
+                (bulk(Direction::In), bulk(Direction::Out))
+            {
+                return Ok(Some(AdbInterface {
+                    number: descriptor.interface_number(),
+                    endpoint_in,
+                    endpoint_out,
+                }));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// A USB device exposing an adb interface.
+#[derive(Debug)]
+pub struct UsbDevice {
+    device: Device<GlobalContext>,
+    interface: AdbInterface,
+}
+
+impl UsbDevice {
+    /// Returns the USB devices exposing an adb interface.
+    pub fn list() -> Result<Vec<Self>, AdbError> {
+        let mut devices = Vec::new();
+        for device in rusb::devices().map_err(usb_error)?.iter() {
+            // Devices we can't access are skipped, like `adb devices` reports them without
+            // permissions.
+            if let Ok(Some(interface)) = find_interface(&device) {
+                devices.push(Self { device, interface });
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Returns the bus number of the device.
+    pub fn bus_number(&self) -> u8 {
+        self.device.bus_number()
+    }
+
+    /// Returns the address of the device on its bus.
+    pub fn address(&self) -> u8 {
+        self.device.address()
+    }
+
+    /// Reads the serial number of the device, the serial number reported by `adb devices`.
+    pub fn serial(&self) -> Result<String, AdbError> {
+        let descriptor = self.device.device_descriptor().map_err(usb_error)?;
+        let handle = self.device.open().map_err(usb_error)?;
+        handle
+            .read_serial_number_string_ascii(&descriptor)
+            .map_err(usb_error)
+    }
+
+    /// Opens the device and claims its adb interface.
+    pub fn open(&self) -> Result<UsbTransport, AdbError> {
+        let handle = self.device.open().map_err(usb_error)?;
+        // Not supported on every platform.
+        let _ = handle.set_auto_detach_kernel_driver(true);
+        handle
+            .claim_interface(self.interface.number)
+            .map_err(usb_error)?;
+        Ok(UsbTransport {
+            handle,
+            interface: self.interface,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+}
+
+/// The adb interface of a USB device, claimed until dropped.
+#[derive(Debug)]
+pub struct UsbTransport {
+    handle: DeviceHandle<GlobalContext>,
+    interface: AdbInterface,
+    timeout: Duration,
+}
+
+impl UsbTransport {
+    /// Opens the only USB device exposing an adb interface.
+    pub fn open_any() -> Result<Self, AdbError> {
+        match &UsbDevice::list()?[..] {
+            [device] => device.open(),
+            [] => Err(AdbError::DeviceNotFound { serial: None }),
+            _ => Err(AdbError::Server {
+                message: "more than one USB device found".to_string(),
+            }),
+        }
+    }
+
+    /// Opens the USB device with the given serial number.
+    pub fn open_serial(serial: &str) -> Result<Self, AdbError> {
+        for device in UsbDevice::list()? {
+            if device.serial().is_ok_and(|s| s == serial) {
+                return device.open();
+            }
+        }
+        Err(AdbError::DeviceNotFound {
+            serial: Some(serial.to_string()),
+        })
+    }
+
+    /// Sets the timeout of a bulk transfer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn read_bulk(&self, buffer: &mut [u8]) -> Result<(), AdbError> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let read = self
+                .handle
+                .read_bulk(
+                    self.interface.endpoint_in,
+                    &mut buffer[filled..],
+                    self.timeout,
+                )
+                .map_err(usb_error)?;
+            filled += read;
+        }
+        Ok(())
+    }
+
+    fn write_bulk(&self, data: &[u8]) -> Result<(), AdbError> {
+        let mut written = 0;
+        while written < data.len() {
+            written += self
+                .handle
+                .write_bulk(self.interface.endpoint_out, &data[written..], self.timeout)
+                .map_err(usb_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl MessageTransport for UsbTransport {
+    fn read_message(&mut self) -> Result<Message, AdbError> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        self.read_bulk(&mut bytes)?;
+        loop {
+            match message::decode_message(&bytes)? {
+                Decoded::Complete { value, .. } => return Ok(value),
+                Decoded::Incomplete { needed } => {
+                    let filled = bytes.len();
+                    bytes.resize(needed, 0);
+                    self.read_bulk(&mut bytes[filled..])?;
+                }
+            }
+        }
+    }
+
+    fn write_message(&mut self, message: &Message) -> Result<(), AdbError> {
+        self.write_bulk(&message.encode_header())?;
+        if !message.payload.is_empty() {
+            self.write_bulk(&message.payload)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UsbTransport {
+    fn drop(&mut self) {
+        let _ = self.handle.release_interface(self.interface.number);
+    }
+}
@@ -0,0 +1,244 @@
+//! This module switches the UI mode of the device, e.g. the dark theme for screenshot tests
+//! of both themes, with `cmd uimode`.
+//!
+//! `cmd uimode night` prints `Night mode: <mode>`, and sets the mode when given one.
+//! `cmd uimode car` enters and exits car mode. The current state is read back from
+//! `dumpsys uimode`, whose `key=value` fields include `mNightMode`, `mCarModeEnabled` and
+//! `mCurUiMode`, the `Configuration.uiMode` bits: the type in the low 4 bits and the night
+//! bits in `0x30`. Desk and television modes follow the dock and the device and can't be set
+//! from the shell.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::device::Device;
+use crate::error::AdbError;
+
+/// The mask of the type bits of `Configuration.uiMode`.
+const UI_MODE_TYPE_MASK: u32 = 0x0f;
+/// The mask of the night bits of `Configuration.uiMode`.
+const UI_MODE_NIGHT_MASK: u32 = 0x30;
+/// The night bits of the dark theme.
+const UI_MODE_NIGHT_YES: u32 = 0x20;
+
+/// The night mode setting.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum NightMode {
+    /// Switches with the time of day.
+    Auto,
+    /// The light theme.
+    No,
+    /// The dark theme.
+    Yes,
+    /// Switches at custom times, Android 11 and later.
+    Custom,
+}
+
+impl NightMode {
+    /// Returns the mode of the `mNightMode` value of `dumpsys uimode`.
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Self::Auto),
+            1 => Some(Self::No),
+            2 => Some(Self::Yes),
+            3 => Some(Self::Custom),
+            _ => None,
+        }
+    }
+}
+
+impl Display for NightMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::No => "no",
+            Self::Yes => "yes",
+            Self::Custom => "custom",
+        })
+    }
+}
+
+impl FromStr for NightMode {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "no" => Ok(Self::No),
+            "yes" => Ok(Self::Yes),
+            "custom" => Ok(Self::Custom),
+            _ => Err(AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "NightMode",
+                source: None,
+            }),
+        }
+    }
+}
+
+/// The type of UI, the `UI_MODE_TYPE_*` constants of `Configuration`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[non_exhaustive]
+pub enum UiModeType {
+    Normal,
+    Desk,
+    Car,
+    Television,
+    Appliance,
+    Watch,
+    VrHeadset,
+    Other(u32),
+}
+
+impl UiModeType {
+    /// Returns the type of the `uiMode` bits `ui_mode`.
+    pub fn from_ui_mode(ui_mode: u32) -> Self {
+        match ui_mode & UI_MODE_TYPE_MASK {
+            1 => Self::Normal,
+            2 => Self::Desk,
+            3 => Self::Car,
+            4 => Self::Television,
+            5 => Self::Appliance,
+            6 => Self::Watch,
+            7 => Self::VrHeadset,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// The UI mode state of a device, parsed from `dumpsys uimode`.
+///
+/// Fields missing from the dump of older versions are `None`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct UiModeState {
+    /// The night mode setting.
+    pub night_mode: Option<NightMode>,
+    /// `true` if the dark theme is applied, e.g. by [`NightMode::Auto`] at night.
+    pub night: Option<bool>,
+    /// `true` if car mode is enabled.
+    pub car_mode: Option<bool>,
+    /// The current type of UI.
+    pub mode_type: Option<UiModeType>,
+}
+
+impl UiModeState {
+    /// Parses the output of `dumpsys uimode`.
+    pub fn parse(dump: &str) -> Self {
+        let mut state = Self::default();
+        for (key, value) in dump
+            .split_whitespace()
+            .filter_map(|field| field.split_once('='))
+        {
+            match key {
+                "mNightMode" => {
+                    state.night_mode = value.parse().ok().and_then(NightMode::from_code)
+                }
+                "mCarModeEnabled" => state.car_mode = value.parse().ok(),
+                "mCurUiMode" => {
+                    let ui_mode = value
+                        .strip_prefix("0x")
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+                    if let Some(ui_mode) = ui_mode {
+                        state.mode_type = Some(UiModeType::from_ui_mode(ui_mode));
+                        state.night = Some(ui_mode & UI_MODE_NIGHT_MASK == UI_MODE_NIGHT_YES);
+                    }
+                }
+                _ => {}
+            }
+        }
+        state
+    }
+}
+
+/// Parses the `Night mode: <mode>` output of `cmd uimode night`.
+fn parse_night_mode(output: &str) -> Result<NightMode, AdbError> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Night mode: "))
+        .ok_or_else(|| AdbError::Parse {
+            value: output.to_string(),
+            source_type: "&str",
+            target_type: "NightMode",
+            source: None,
+        })?
+        .parse()
+}
+
+impl Device {
+    /// Returns the night mode setting (`cmd uimode night`).
+    pub fn night_mode(&self) -> Result<NightMode, AdbError> {
+        parse_night_mode(&self.shell_checked("cmd uimode night")?)
+    }
+
+    /// Sets the night mode setting, and returns the new one (`cmd uimode night <mode>`).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    /// use adb::uimode::NightMode;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// for mode in [NightMode::No, NightMode::Yes] {
+    ///     device.set_night_mode(mode).unwrap();
+    ///     // Take the screenshot of the theme.
+    /// }
+    /// ```
+    pub fn set_night_mode(&self, mode: NightMode) -> Result<NightMode, AdbError> {
+        parse_night_mode(&self.shell_checked(&format!("cmd uimode night {}", mode))?)
+    }
+
+    /// Enters or exits car mode (`cmd uimode car`).
+    pub fn set_car_mode(&self, enabled: bool) -> Result<(), AdbError> {
+        let arg = if enabled { "yes" } else { "no" };
+        self.shell_checked(&format!("cmd uimode car {}", arg))
+            .map(drop)
+    }
+
+    /// Returns the UI mode state (`dumpsys uimode`).
+    pub fn ui_mode(&self) -> Result<UiModeState, AdbError> {
+        Ok(UiModeState::parse(&self.shell_checked("dumpsys uimode")?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_night_mode() {
+        assert_eq!(
+            NightMode::Yes,
+            parse_night_mode("Night mode: yes\n").unwrap()
+        );
+        assert_eq!(
+            NightMode::Custom,
+            parse_night_mode("Night mode: custom\r\n").unwrap()
+        );
+        assert!(parse_night_mode("Error: unknown command 'night'").is_err());
+        assert_eq!("auto", NightMode::Auto.to_string());
+    }
+
+    #[test]
+    fn test_ui_mode_state() {
+        let dump = "Current UI Mode Service state:\n  \
+            mDockState=0 mLastBroadcastState=0\n  \
+            mNightMode=2 (yes) locked=false mCarModeEnabled=false mComputedNightMode=true\n  \
+            mCurUiMode=0x21 mUiModeLocked=false mSetUiMode=0x21\n  \
+            mHoldingConfiguration=false mSystemReady=true\n";
+        assert_eq!(
+            UiModeState {
+                night_mode: Some(NightMode::Yes),
+                night: Some(true),
+                car_mode: Some(false),
+                mode_type: Some(UiModeType::Normal),
+            },
+            UiModeState::parse(dump)
+        );
+        let state = UiModeState::parse("mNightMode=0 mCarModeEnabled=true mCurUiMode=0x13");
+        assert_eq!(Some(NightMode::Auto), state.night_mode);
+        assert_eq!(Some(false), state.night);
+        assert_eq!(Some(UiModeType::Car), state.mode_type);
+        assert_eq!(UiModeState::default(), UiModeState::parse(""));
+    }
+}
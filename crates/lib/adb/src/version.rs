@@ -0,0 +1,205 @@
+//! This module provides the adb server protocol version and the policy applied
+//! when the server and this client disagree on it.
+//!
+//! Host services added over time are [`ServerCapability`]s, each with the first protocol
+//! version supporting it, so that APIs can degrade or fail with
+//! [`AdbError::ServerUnsupported`] on older servers, e.g. with
+//! [`VersionMismatchPolicy::Warn`].
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::error::AdbError;
+
+/// The adb server protocol version this client speaks.
+pub const CLIENT_VERSION: AdbVersion = AdbVersion(41);
+
+/// The protocol version reported by `host:version`.
+///
+/// # Syntax
+///
+/// The server replies with the version as 4 hexadecimal digits, e.g. `0029` for version 41.
+///
+/// ```
+/// # use adb::version::AdbVersion;
+/// assert_eq!("0029".parse::<AdbVersion>().unwrap(), AdbVersion(41));
+/// assert_eq!(AdbVersion(41).to_string(), "0029");
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct AdbVersion(pub u32);
+
+impl Display for AdbVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04x}", self.0)
+    }
+}
+
+impl FromStr for AdbVersion {
+    type Err = AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u32::from_str_radix(s, 16)
+            .map(Self)
+            .map_err(|e| AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "AdbVersion",
+                source: Some(Box::new(e)),
+            })
+    }
+}
+
+impl AdbVersion {
+    /// Returns `true` if a server of this version supports `capability`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use adb::version::{AdbVersion, ServerCapability};
+    ///
+    /// assert!(AdbVersion(41).supports(ServerCapability::Tport));
+    /// assert!(!AdbVersion(40).supports(ServerCapability::Tport));
+    /// ```
+    pub fn supports(self, capability: ServerCapability) -> bool {
+        self >= capability.min_version()
+    }
+}
+
+/// A host service which only newer adb servers support.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ServerCapability {
+    /// mDNS discovery, `host:mdns:check` and `host:mdns:services`.
+    Mdns,
+    /// Connections bound to a transport replying with its id, `host:tport`.
+    Tport,
+    /// Device tracking in protobuf, `host:track-devices-proto-binary`.
+    TrackDevicesProto,
+}
+
+impl ServerCapability {
+    /// Returns the first protocol version supporting the capability.
+    pub const fn min_version(self) -> AdbVersion {
+        match self {
+            // adb 1.0.40.
+            Self::Mdns => AdbVersion(40),
+            // adb 1.0.41.
+            Self::Tport | Self::TrackDevicesProto => AdbVersion(41),
+        }
+    }
+
+    /// Returns the host service of the capability, e.g. `host:tport`.
+    pub const fn service(self) -> &'static str {
+        match self {
+            Self::Mdns => "host:mdns",
+            Self::Tport => "host:tport",
+            Self::TrackDevicesProto => "host:track-devices-proto-binary",
+        }
+    }
+}
+
+impl Display for ServerCapability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.service())
+    }
+}
+
+/// What to do when the server reports a different protocol version than [`CLIENT_VERSION`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum VersionMismatchPolicy {
+    /// Fail with [`AdbError::VersionMismatch`].
+    #[default]
+    Error,
+    /// Keep talking to the running server, returning [`VersionAction::Mismatch`]: the
+    /// `check_version` of the clients returns the version of the server, which is also a
+    /// warning on the `adb::server` target with the `tracing` feature.
+    Warn,
+    /// Kill the running server and start a new one, like the adb command does.
+    Restart,
+}
+
+/// The action to take after [checking](VersionMismatchPolicy::check) the server version.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum VersionAction {
+    /// Keep using the running server.
+    Continue,
+    /// Keep using the running server, whose version differs from [`CLIENT_VERSION`].
+    Mismatch(AdbVersion),
+    /// Kill the running server and start a new one.
+    Restart,
+}
+
+impl VersionMismatchPolicy {
+    /// Checks the version reported by the server against [`CLIENT_VERSION`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use adb::version::{AdbVersion, VersionAction, VersionMismatchPolicy, CLIENT_VERSION};
+    ///
+    /// let policy = VersionMismatchPolicy::Restart;
+    /// assert_eq!(policy.check(CLIENT_VERSION).unwrap(), VersionAction::Continue);
+    /// assert_eq!(policy.check(AdbVersion(40)).unwrap(), VersionAction::Restart);
+    /// assert!(VersionMismatchPolicy::Error.check(AdbVersion(40)).is_err());
+    /// assert_eq!(
+    ///     VersionMismatchPolicy::Warn.check(AdbVersion(40)).unwrap(),
+    ///     VersionAction::Mismatch(AdbVersion(40))
+    /// );
+    /// ```
+    pub fn check(self, server: AdbVersion) -> Result<VersionAction, AdbError> {
+        if server == CLIENT_VERSION {
+            return Ok(VersionAction::Continue);
+        }
+        match self {
+            Self::Error => Err(AdbError::VersionMismatch {
+                server: server.0,
+                client: CLIENT_VERSION.0,
+            }),
+            Self::Warn => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    target: "adb::server",
+                    server = server.0,
+                    client = CLIENT_VERSION.0,
+                    "server version mismatch, continuing"
+                );
+                Ok(VersionAction::Mismatch(server))
+            }
+            Self::Restart => Ok(VersionAction::Restart),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_parse() {
+        assert_eq!(AdbVersion(41), "0029".parse().unwrap());
+        assert_eq!(AdbVersion(0x1f), "001f".parse().unwrap());
+        for s in ["", "zzzz", "-1", "0x29"] {
+            assert!(s.parse::<AdbVersion>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_version_display() {
+        assert_eq!("0029", AdbVersion(41).to_string());
+        assert_eq!("10000", AdbVersion(0x10000).to_string());
+    }
+
+    #[test]
+    fn test_server_capability() {
+        assert!(CLIENT_VERSION.supports(ServerCapability::Mdns));
+        assert!(CLIENT_VERSION.supports(ServerCapability::TrackDevicesProto));
+        assert!(!AdbVersion(39).supports(ServerCapability::Mdns));
+        assert_eq!("host:tport", ServerCapability::Tport.to_string());
+        let error = AdbError::ServerUnsupported {
+            needed: ServerCapability::Tport,
+            found: AdbVersion(40),
+        };
+        assert_eq!(
+            "unsupported by the adb server: host:tport needs version 41, found 40",
+            error.to_string()
+        );
+    }
+}
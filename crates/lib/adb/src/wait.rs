@@ -0,0 +1,289 @@
+//! This module provides [`Device::wait_until`], polling a [`Predicate`] on the device until
+//! it holds.
+//!
+//! The prebuilt predicates each cost one shell command per poll, and parse its output on the
+//! host, so the device only runs stock toolbox commands:
+//!
+//! - [`ActivityFocused`]: the resumed activities in `dumpsys activity activities`.
+//! - [`FileExists`]: the exit status of `test -e`.
+//! - [`PropEquals`]: the output of `getprop`.
+//! - [`PortListening`]: the sockets in the `LISTEN` state in `/proc/net/tcp{,6}`.
+
+use std::time::Duration;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+
+/// The default interval between two checks of a predicate.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The default time to wait for a predicate.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How to poll a predicate in [`Device::wait_until`].
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use adb::wait::PollOptions;
+///
+/// let options = PollOptions::new().timeout(Duration::from_secs(5));
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct PollOptions {
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl PollOptions {
+    /// Creates options polling every [`DEFAULT_POLL_INTERVAL`] for [`DEFAULT_TIMEOUT`].
+    pub fn new() -> Self {
+        Self {
+            interval: DEFAULT_POLL_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the interval between two checks.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets how long to wait before failing with [`AdbError::Timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Default for PollOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A condition on the state of a device.
+///
+/// Closures taking a [`Device`] are predicates.
+pub trait Predicate {
+    /// Checks the condition once.
+    fn check(&self, device: &Device) -> Result<bool, AdbError>;
+
+    /// Describes the condition for timeout errors, e.g. `file /sdcard/done exists`.
+    fn description(&self) -> String {
+        "custom predicate".to_string()
+    }
+}
+
+impl<F: Fn(&Device) -> Result<bool, AdbError>> Predicate for F {
+    fn check(&self, device: &Device) -> Result<bool, AdbError> {
+        self(device)
+    }
+}
+
+/// The activity `component` is resumed, e.g. `com.example/.MainActivity`.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct ActivityFocused(pub String);
+
+/// A file or directory exists at the path.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct FileExists(pub String);
+
+/// The system property has the value, e.g. `sys.boot_completed` is `1`.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct PropEquals(pub String, pub String);
+
+/// A TCP socket listens on the port, on any address.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct PortListening(pub u16);
+
+/// Expands the short form of a component, e.g. `com.example/.Main` into
+/// `com.example/com.example.Main`.
+fn expand_component(component: &str) -> String {
+    match component.split_once('/') {
+        Some((package, class)) if class.starts_with('.') => {
+            format!("{}/{}{}", package, package, class)
+        }
+        _ => component.to_string(),
+    }
+}
+
+/// Parses the resumed activities of `dumpsys activity activities`, from lines like
+/// `mResumedActivity: ActivityRecord{6a5c2e1 u0 com.example/.MainActivity t42}`.
+fn parse_resumed_activities(dump: &str) -> Vec<String> {
+    dump.lines()
+        .filter(|line| line.contains("ResumedActivity"))
+        .filter_map(|line| {
+            let record = line.split_once("ActivityRecord{")?.1;
+            let record = record.split('}').next()?;
+            record.split_whitespace().find(|token| token.contains('/'))
+        })
+        .map(expand_component)
+        .collect()
+}
+
+/// Parses the ports of the sockets in the `LISTEN` state in `/proc/net/tcp` or
+/// `/proc/net/tcp6`.
+fn parse_listening_ports(table: &str) -> Vec<u16> {
+    table
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            // sl local_address rem_address st ...
+            if fields.get(3) != Some(&"0A") {
+                return None;
+            }
+            let (_, port) = fields.get(1)?.rsplit_once(':')?;
+            u16::from_str_radix(port, 16).ok()
+        })
+        .collect()
+}
+
+impl Predicate for ActivityFocused {
+    fn check(&self, device: &Device) -> Result<bool, AdbError> {
+        let dump = device.shell_checked("dumpsys activity activities")?;
+        let component = expand_component(&self.0);
+        Ok(parse_resumed_activities(&dump).contains(&component))
+    }
+
+    fn description(&self) -> String {
+        format!("activity {} focused", self.0)
+    }
+}
+
+impl Predicate for FileExists {
+    fn check(&self, device: &Device) -> Result<bool, AdbError> {
+        Ok(device
+            .shell(&format!("test -e {}", shell::quote(&self.0)))?
+            .success())
+    }
+
+    fn description(&self) -> String {
+        format!("file {} exists", self.0)
+    }
+}
+
+impl Predicate for PropEquals {
+    fn check(&self, device: &Device) -> Result<bool, AdbError> {
+        let value = device.shell_checked(&format!("getprop {}", shell::quote(&self.0)))?;
+        Ok(value.trim_end() == self.1)
+    }
+
+    fn description(&self) -> String {
+        format!("property {} = {}", self.0, self.1)
+    }
+}
+
+impl Predicate for PortListening {
+    fn check(&self, device: &Device) -> Result<bool, AdbError> {
+        // `tcp6` is missing on kernels without IPv6.
+        let output = device.shell("cat /proc/net/tcp /proc/net/tcp6")?;
+        Ok(parse_listening_ports(&output.stdout_lossy()).contains(&self.0))
+    }
+
+    fn description(&self) -> String {
+        format!("port {} listening", self.0)
+    }
+}
+
+impl Device {
+    /// Polls `predicate` until it holds, failing with [`AdbError::Timeout`] if it still
+    /// doesn't after the timeout of `options`.
+    ///
+    /// Errors checking the predicate are returned right away.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    /// use adb::wait::{PollOptions, PropEquals};
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let booted = PropEquals("sys.boot_completed".to_string(), "1".to_string());
+    /// device.wait_until(&booted, &PollOptions::new()).unwrap();
+    /// ```
+    pub fn wait_until<P: Predicate + ?Sized>(
+        &self,
+        predicate: &P,
+        options: &PollOptions,
+    ) -> Result<(), AdbError> {
+        let clock = &self.server().clock;
+        let start = clock.now();
+        loop {
+            if predicate.check(self)? {
+                return Ok(());
+            }
+            if clock.now() - start >= options.timeout {
+                return Err(AdbError::Timeout {
+                    condition: predicate.description(),
+                    timeout: options.timeout,
+                });
+            }
+            clock.sleep(options.interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::device::Transport;
+    use crate::server::AdbServer;
+
+    #[test]
+    fn test_parse_resumed_activities() {
+        let dump = "\
+ACTIVITY MANAGER ACTIVITIES (dumpsys activity activities)
+Display #0 (activities from top to bottom):
+    * Task{8c1f2 #42 type=standard A=10123:com.example U=0 visible=true}
+      mResumedActivity: ActivityRecord{6a5c2e1 u0 com.example/.MainActivity t42}
+  ResumedActivity: ActivityRecord{6a5c2e1 u0 com.example/.MainActivity t42}
+  mLastPausedActivity: ActivityRecord{1b2 u0 com.android.launcher3/.Launcher t1}
+";
+        assert_eq!(
+            [
+                "com.example/com.example.MainActivity",
+                "com.example/com.example.MainActivity"
+            ],
+            &parse_resumed_activities(dump)[..]
+        );
+        assert_eq!(
+            "com.example/org.example.Main",
+            expand_component("com.example/org.example.Main")
+        );
+    }
+
+    #[test]
+    fn test_parse_listening_ports() {
+        let table = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1234
+   1: 0100007F:A2C4 0100007F:1F90 01 00000000:00000000 00:00000000 00000000 10123        0 5678
+";
+        assert_eq!([8080], &parse_listening_ports(table)[..]);
+    }
+
+    #[test]
+    fn test_wait_until_timeout() {
+        let clock = MockClock::new();
+        let server = AdbServer::default().clock(clock.clone());
+        let device = Device::new(server, Transport::Any);
+        let start = clock.now();
+        let never = |_: &Device| Ok(false);
+        let options = PollOptions::new().timeout(Duration::from_secs(3));
+        match device.wait_until(&never, &options) {
+            Err(AdbError::Timeout { condition, timeout }) => {
+                assert_eq!("custom predicate", condition);
+                assert_eq!(Duration::from_secs(3), timeout);
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+        assert_eq!(Duration::from_secs(3), clock.now() - start);
+        let always = |_: &Device| Ok(true);
+        assert!(device.wait_until(&always, &options).is_ok());
+    }
+}
@@ -0,0 +1,290 @@
+//! This module connects devices to Wi-Fi networks, e.g. fresh devices before switching adbd
+//! to TCP/IP.
+//!
+//! Android 11 and later connect with `cmd wifi connect-network`. Older devices have no
+//! shell command for it, so the network is added to the supplicant with `wpa_cli`, which
+//! needs adbd to run as root (`adb root`).
+//!
+//! Connecting only starts the association, so [`Device::wifi_connect`] then polls the
+//! IPv4 address of the Wi-Fi interface in `ip -f inet addr show` until DHCP assigns one.
+
+use std::cell::Cell;
+use std::net::Ipv4Addr;
+
+use crate::device::Device;
+use crate::error::AdbError;
+use crate::shell;
+use crate::wait::PollOptions;
+
+/// The SDK version of Android 11, which added `cmd wifi connect-network`.
+const CONNECT_NETWORK_SDK: u32 = 30;
+/// The SDK version of Android 8, which moved the supplicant sockets to `/data/vendor`.
+const VENDOR_SOCKETS_SDK: u32 = 26;
+/// The Wi-Fi interface of devices without the `wifi.interface` property.
+const DEFAULT_INTERFACE: &str = "wlan0";
+
+/// The security of a Wi-Fi network, with its passphrase or key.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum WifiSecurity {
+    Open,
+    /// WPA2 personal, with a passphrase.
+    Wpa2(String),
+    /// WPA3 personal (SAE), with a passphrase.
+    Wpa3(String),
+    /// WEP, with a key of 5 or 13 characters, or 10 or 26 hexadecimal digits.
+    Wep(String),
+}
+
+impl WifiSecurity {
+    /// Returns the arguments following the SSID of `cmd wifi connect-network`.
+    fn connect_network_args(&self) -> String {
+        match self {
+            Self::Open => "open".to_string(),
+            Self::Wpa2(passphrase) => format!("wpa2 {}", shell::quote(passphrase)),
+            Self::Wpa3(passphrase) => format!("wpa3 {}", shell::quote(passphrase)),
+            Self::Wep(key) => format!("wep {}", shell::quote(key)),
+        }
+    }
+
+    /// Returns the `set_network` variables of the network in `wpa_cli`.
+    fn supplicant_variables(&self) -> Vec<(&'static str, String)> {
+        let quoted = |s: &str| format!("\"{}\"", s);
+        match self {
+            Self::Open => vec![("key_mgmt", "NONE".to_string())],
+            Self::Wpa2(passphrase) => vec![
+                ("key_mgmt", "WPA-PSK".to_string()),
+                ("psk", quoted(passphrase)),
+            ],
+            Self::Wpa3(passphrase) => vec![
+                ("key_mgmt", "SAE".to_string()),
+                ("sae_password", quoted(passphrase)),
+                ("ieee80211w", "2".to_string()),
+            ],
+            Self::Wep(key) => {
+                let hex =
+                    matches!(key.len(), 10 | 26) && key.chars().all(|c| c.is_ascii_hexdigit());
+                vec![
+                    ("key_mgmt", "NONE".to_string()),
+                    ("wep_key0", if hex { key.clone() } else { quoted(key) }),
+                    ("wep_tx_keyidx", "0".to_string()),
+                ]
+            }
+        }
+    }
+}
+
+/// Returns the `wpa_cli` commands configuring and selecting the network `id`.
+fn supplicant_commands(wpa_cli: &str, id: u32, ssid: &str, security: &WifiSecurity) -> Vec<String> {
+    let mut commands = vec![format!(
+        "{} set_network {} ssid {}",
+        wpa_cli,
+        id,
+        shell::quote(&format!("\"{}\"", ssid))
+    )];
+    for (variable, value) in security.supplicant_variables() {
+        commands.push(format!(
+            "{} set_network {} {} {}",
+            wpa_cli,
+            id,
+            variable,
+            shell::quote(&value)
+        ));
+    }
+    for command in ["enable_network", "select_network"] {
+        commands.push(format!("{} {} {}", wpa_cli, command, id));
+    }
+    commands.push(format!("{} save_config", wpa_cli));
+    commands
+}
+
+/// Fails if `wpa_cli` replied `FAIL`, or if `cmd wifi` reported an error.
+fn check_wifi_output(command: &str, output: &str) -> Result<(), AdbError> {
+    match output.lines().map(str::trim).find(|line| {
+        *line == "FAIL"
+            || line.starts_with("Error")
+            || line.starts_with("Invalid")
+            || line.contains("Exception")
+    }) {
+        Some(line) => Err(AdbError::Server {
+            message: format!("`{}`: {}", command, line),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Parses the first IPv4 address of `ip -f inet addr show`, e.g. `inet 192.168.1.23/24`.
+fn parse_inet_address(output: &str) -> Option<Ipv4Addr> {
+    output.lines().find_map(|line| {
+        let address = line
+            .trim()
+            .strip_prefix("inet ")?
+            .split(['/', ' '])
+            .next()?;
+        address.parse().ok()
+    })
+}
+
+impl Device {
+    /// Runs a Wi-Fi command, failing if it reports an error, and returns its output.
+    fn wifi_command(&self, command: &str) -> Result<String, AdbError> {
+        let output = self.shell_checked(command)?;
+        check_wifi_output(command, &output)?;
+        Ok(output)
+    }
+
+    /// Returns the Wi-Fi interface, e.g. `wlan0` (`wifi.interface`).
+    fn wifi_interface(&self) -> Result<String, AdbError> {
+        Ok(self
+            .properties()?
+            .get("wifi.interface")
+            .unwrap_or(DEFAULT_INTERFACE)
+            .to_string())
+    }
+
+    /// Returns the IPv4 address of the Wi-Fi interface, `None` if it has none.
+    pub fn wifi_address(&self) -> Result<Option<Ipv4Addr>, AdbError> {
+        let interface = self.wifi_interface()?;
+        let output = self.shell(&format!(
+            "ip -f inet addr show {}",
+            shell::quote(&interface)
+        ))?;
+        Ok(parse_inet_address(&output.stdout_lossy()))
+    }
+
+    /// Turns Wi-Fi on and connects to the network `ssid`, then waits until the Wi-Fi
+    /// interface has an IPv4 address and returns it, failing with [`AdbError::Timeout`]
+    /// after the timeout of `options`.
+    ///
+    /// Before Android 11, adbd must run as root.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use adb::server::AdbServer;
+    /// use adb::wait::PollOptions;
+    /// use adb::wifi::WifiSecurity;
+    ///
+    /// let device = AdbServer::default().any_device();
+    /// let security = WifiSecurity::Wpa2("passphrase".to_string());
+    /// let address = device
+    ///     .wifi_connect("lab", &security, &PollOptions::new())
+    ///     .unwrap();
+    /// println!("connect to {}:5555 after `adb tcpip 5555`", address);
+    /// ```
+    pub fn wifi_connect(
+        &self,
+        ssid: &str,
+        security: &WifiSecurity,
+        options: &PollOptions,
+    ) -> Result<Ipv4Addr, AdbError> {
+        self.shell_checked("svc wifi enable")?;
+        let sdk = self.properties()?.sdk().unwrap_or(0);
+        if sdk >= CONNECT_NETWORK_SDK {
+            self.wifi_command(&format!(
+                "cmd wifi connect-network {} {}",
+                shell::quote(ssid),
+                security.connect_network_args()
+            ))?;
+        } else {
+            let sockets = if sdk >= VENDOR_SOCKETS_SDK {
+                "/data/vendor/wifi/wpa/sockets"
+            } else {
+                "/data/misc/wifi/sockets"
+            };
+            let wpa_cli = format!("wpa_cli -i {} -p {}", self.wifi_interface()?, sockets);
+            let command = format!("{} add_network", wpa_cli);
+            let output = self.wifi_command(&command)?;
+            let id = output
+                .lines()
+                .last()
+                .and_then(|line| line.trim().parse().ok())
+                .ok_or_else(|| AdbError::Server {
+                    message: format!("`{}`: {}", command, output.trim()),
+                })?;
+            for command in supplicant_commands(&wpa_cli, id, ssid, security) {
+                self.wifi_command(&command)?;
+            }
+        }
+        let address = Cell::new(None);
+        let connected = |device: &Device| {
+            address.set(device.wifi_address()?);
+            Ok(address.get().is_some())
+        };
+        self.wait_until(&connected, options).map_err(|e| match e {
+            AdbError::Timeout { timeout, .. } => AdbError::Timeout {
+                condition: format!("IPv4 address on Wi-Fi network {}", ssid),
+                timeout,
+            },
+            e => e,
+        })?;
+        Ok(address.get().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_inet_address() {
+        let output = "\
+30: wlan0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc mq state UP group default qlen 3000
+    inet 192.168.1.23/24 brd 192.168.1.255 scope global wlan0
+       valid_lft forever preferred_lft forever
+";
+        assert_eq!(
+            Some(Ipv4Addr::new(192, 168, 1, 23)),
+            parse_inet_address(output)
+        );
+        assert_eq!(
+            None,
+            parse_inet_address("30: wlan0: <NO-CARRIER> mtu 1500\n")
+        );
+        assert_eq!(
+            None,
+            parse_inet_address("Device \"wlan0\" does not exist.\n")
+        );
+    }
+
+    #[test]
+    fn test_connect_network_args() {
+        assert_eq!("open", WifiSecurity::Open.connect_network_args());
+        assert_eq!(
+            "wpa2 'it'\\''s'",
+            WifiSecurity::Wpa2("it's".to_string()).connect_network_args()
+        );
+        assert!(check_wifi_output(
+            "cmd wifi connect-network lab open",
+            "Connection initiated \n"
+        )
+        .is_ok());
+        assert!(check_wifi_output(
+            "cmd wifi connect-network lab wpa2",
+            "Invalid argument: short passphrase\n"
+        )
+        .is_err());
+        assert!(check_wifi_output("wpa_cli select_network 3", "FAIL\n").is_err());
+    }
+
+    #[test]
+    fn test_supplicant_commands() {
+        let wpa_cli = "wpa_cli -i wlan0 -p /data/misc/wifi/sockets";
+        let commands =
+            supplicant_commands(wpa_cli, 3, "lab", &WifiSecurity::Wpa2("secret".to_string()));
+        assert_eq!(
+            [
+                format!("{} set_network 3 ssid '\"lab\"'", wpa_cli),
+                format!("{} set_network 3 key_mgmt 'WPA-PSK'", wpa_cli),
+                format!("{} set_network 3 psk '\"secret\"'", wpa_cli),
+                format!("{} enable_network 3", wpa_cli),
+                format!("{} select_network 3", wpa_cli),
+                format!("{} save_config", wpa_cli),
+            ],
+            &commands[..]
+        );
+        let wep = WifiSecurity::Wep("0123456789".to_string()).supplicant_variables();
+        assert_eq!(("wep_key0", "0123456789".to_string()), wep[1]);
+        let wep = WifiSecurity::Wep("abcde".to_string()).supplicant_variables();
+        assert_eq!(("wep_key0", "\"abcde\"".to_string()), wep[1]);
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use adb::device::Device;
+use adb::server::AdbServer;
+
+/// The environment variable holding the serial number of the device under test.
+pub const SERIAL_VAR: &str = "DISANGER_TEST_SERIAL";
+
+/// Returns the device under test.
+pub fn device() -> Device {
+    let serial = std::env::var(SERIAL_VAR)
+        .unwrap_or_else(|_| panic!("set `{}` to run the live tests", SERIAL_VAR));
+    AdbServer::default().device(&serial)
+}
+
+/// Runs a shell command on the device, ignoring its output.
+pub fn shell(device: &Device, command: &str) {
+    let mut stream = device.open(&format!("shell:{}", command)).unwrap();
+    stream.read_to_end(&mut Vec::new()).unwrap();
+}
+
+fn unique_name(prefix: &str) -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "{}-{}-{}",
+        prefix,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// A directory on the device, removed on drop.
+pub struct RemoteDir {
+    device: Device,
+    pub path: String,
+}
+
+impl RemoteDir {
+    pub fn new(device: &Device) -> Self {
+        let path = format!("/data/local/tmp/{}", unique_name("disanger"));
+        shell(device, &format!("mkdir -p {}", path));
+        Self {
+            device: device.clone(),
+            path,
+        }
+    }
+
+    pub fn join(&self, name: &str) -> String {
+        format!("{}/{}", self.path, name)
+    }
+}
+
+impl Drop for RemoteDir {
+    fn drop(&mut self) {
+        shell(&self.device, &format!("rm -rf {}", self.path));
+    }
+}
+
+/// A file on the host, removed on drop.
+pub struct LocalFile {
+    pub path: PathBuf,
+}
+
+impl LocalFile {
+    pub fn new(content: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(unique_name("disanger"));
+        std::fs::write(&path, content).unwrap();
+        Self { path }
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            path: std::env::temp_dir().join(unique_name("disanger")),
+        }
+    }
+}
+
+impl Drop for LocalFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
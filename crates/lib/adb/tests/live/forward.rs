@@ -0,0 +1,47 @@
+use adb::socket::{AdbSocketFamilies, LocalAbstract, Tcp};
+
+use crate::fixtures;
+
+#[test]
+#[ignore]
+fn test_forward() {
+    let device = fixtures::device();
+    let local = Tcp::from_port(47001);
+    let remote = LocalAbstract("disanger_forward_test".to_string());
+    device.forward(local, remote.clone()).unwrap();
+
+    let forwards = device.forward_list().unwrap();
+    let forward = forwards
+        .iter()
+        .find(|f| f.local == AdbSocketFamilies::from(local))
+        .expect("the forward is not listed");
+    assert_eq!(AdbSocketFamilies::from(remote.clone()), forward.remote);
+    assert!(device.forward_no_rebind(local, remote).is_err());
+
+    device.forward_remove(local).unwrap();
+    assert!(!device
+        .forward_list()
+        .unwrap()
+        .iter()
+        .any(|f| f.local == AdbSocketFamilies::from(local)));
+}
+
+#[test]
+#[ignore]
+fn test_reverse() {
+    let device = fixtures::device();
+    let remote = Tcp::from_port(47002);
+    let local = Tcp::from_port(47003);
+    device.reverse(remote, local).unwrap();
+    assert!(device
+        .reverse_list()
+        .unwrap()
+        .iter()
+        .any(|f| f.local == AdbSocketFamilies::from(remote)));
+    device.reverse_remove(remote).unwrap();
+    assert!(!device
+        .reverse_list()
+        .unwrap()
+        .iter()
+        .any(|f| f.local == AdbSocketFamilies::from(remote)));
+}
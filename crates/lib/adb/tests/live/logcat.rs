@@ -0,0 +1,34 @@
+use adb::logcat::{LogBuffer, LogPriority, LogcatOptions};
+
+use crate::fixtures;
+
+#[test]
+#[ignore]
+fn test_logcat() {
+    let device = fixtures::device();
+    let tag = "disanger-live";
+    fixtures::shell(&device, &format!("log -p w -t {} hello", tag));
+    let options = LogcatOptions::new()
+        .buffer(LogBuffer::Main)
+        .filter(tag, LogPriority::Verbose)
+        .min_priority(LogPriority::Silent)
+        .dump(true);
+    let entries: Vec<_> = device
+        .logcat(&options)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let entry = entries.last().expect("the logged entry");
+    assert_eq!(tag, entry.tag);
+    assert_eq!(LogPriority::Warn, entry.priority);
+    assert_eq!("hello", entry.message.trim_end());
+}
+
+#[test]
+#[ignore]
+fn test_log_buffer_stats() {
+    let device = fixtures::device();
+    let sizes = device.log_buffer_sizes().unwrap();
+    assert!(sizes.iter().any(|size| size.buffer == LogBuffer::Main));
+    assert!(!device.log_buffer_stats().unwrap().is_empty());
+}
@@ -0,0 +1,17 @@
+//! End-to-end tests against a real device or emulator.
+//!
+//! The tests are ignored by default, run them against the device with serial number
+//! `DISANGER_TEST_SERIAL` with:
+//!
+//! ```text
+//! DISANGER_TEST_SERIAL=emulator-5554 cargo test -p adb --test live -- --ignored --test-threads 1
+//! ```
+//!
+//! Every test cleans up the files and forwards it creates on the device.
+
+mod fixtures;
+mod forward;
+mod logcat;
+mod server;
+mod shell;
+mod sync;
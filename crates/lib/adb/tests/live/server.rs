@@ -0,0 +1,24 @@
+use adb::device::DeviceState;
+use adb::server::AdbServer;
+use adb::version::CLIENT_VERSION;
+
+use crate::fixtures;
+
+#[test]
+#[ignore]
+fn test_version() {
+    assert_eq!(CLIENT_VERSION, AdbServer::default().version().unwrap());
+}
+
+#[test]
+#[ignore]
+fn test_devices() {
+    let device = fixtures::device();
+    let devices = AdbServer::default().devices().unwrap();
+    let listed = devices
+        .iter()
+        .find(|d| d.serial() == device.serial())
+        .expect("the device under test is not listed");
+    assert_eq!(Some(DeviceState::Device), listed.state());
+    assert_eq!(DeviceState::Device, device.get_state().unwrap());
+}
@@ -0,0 +1,20 @@
+use crate::fixtures;
+
+#[test]
+#[ignore]
+fn test_shell() {
+    let device = fixtures::device();
+    let output = device.shell("echo out; echo err >&2; exit 3").unwrap();
+    assert_eq!(3, output.exit_code);
+    assert!(output.stdout_lossy().contains("out"));
+}
+
+#[test]
+#[ignore]
+fn test_shell_legacy() {
+    let device = fixtures::device();
+    let output = device.shell_legacy("echo legacy # comment").unwrap();
+    assert!(output.success());
+    assert_eq!("legacy", output.stdout_lossy().trim_end());
+    assert_eq!(7, device.shell_legacy("exit 7").unwrap().exit_code);
+}
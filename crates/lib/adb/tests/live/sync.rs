@@ -0,0 +1,44 @@
+use adb::sync::DEFAULT_MODE;
+
+use crate::fixtures::{self, LocalFile, RemoteDir};
+
+#[test]
+#[ignore]
+fn test_push_pull() {
+    let device = fixtures::device();
+    let dir = RemoteDir::new(&device);
+    let content = b"hello from disanger\n".repeat(10000);
+    let local = LocalFile::new(&content);
+    let remote = dir.join("file.txt");
+
+    let pushed = device.push(&local.path, &remote, DEFAULT_MODE).unwrap();
+    assert_eq!(content.len() as u64, pushed.bytes);
+
+    let pulled_file = LocalFile::empty();
+    let pulled = device.pull(&remote, &pulled_file.path).unwrap();
+    assert_eq!(content.len() as u64, pulled.bytes);
+    assert_eq!(content, std::fs::read(&pulled_file.path).unwrap());
+}
+
+#[test]
+#[ignore]
+fn test_stat_list() {
+    let device = fixtures::device();
+    let dir = RemoteDir::new(&device);
+    let local = LocalFile::new(b"stat me");
+    device
+        .push(&local.path, &dir.join("a.txt"), DEFAULT_MODE)
+        .unwrap();
+
+    let mut sync = device.sync().unwrap();
+    let stat = sync.stat(&dir.join("a.txt")).unwrap();
+    assert!(stat.is_file());
+    assert_eq!(7, stat.size);
+    assert!(!sync.stat(&dir.join("missing")).unwrap().exists());
+    assert!(sync.stat(&dir.path).unwrap().is_dir());
+
+    let entries = sync.list(&dir.path).unwrap();
+    assert_eq!(1, entries.len());
+    assert_eq!("a.txt", entries[0].name);
+    sync.quit().unwrap();
+}
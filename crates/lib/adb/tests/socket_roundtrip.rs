@@ -0,0 +1,110 @@
+//! Property-based tests asserting that every socket family round-trips
+//! through its `Display` and `FromStr` implementations.
+
+use std::fmt::Debug;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use adb::socket::*;
+use proptest::prelude::*;
+
+fn assert_roundtrip<T>(value: T) -> Result<(), TestCaseError>
+where
+    T: AdbSocketFamily + Eq + Debug,
+    <T as FromStr>::Err: Debug,
+{
+    let s = value.to_string();
+    let parsed = s.parse::<T>();
+    prop_assert!(parsed.is_ok(), "`{}` failed to parse: {:?}", s, parsed);
+    prop_assert_eq!(value, parsed.unwrap(), "`{}`", s);
+    Ok(())
+}
+
+/// Socket names with colons, unicode and other characters that are easy to mishandle.
+fn name() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<String>(),
+        "[a-z:_./]{0,32}",
+        "(:|@|\\[|\\]|\u{00e9}|\u{4e2d}|\u{1f600}| ){0,16}",
+    ]
+}
+
+fn path() -> impl Strategy<Value = PathBuf> {
+    name().prop_map(PathBuf::from)
+}
+
+fn tcp() -> impl Strategy<Value = Tcp> {
+    prop_oneof![
+        any::<u16>().prop_map(Tcp::from_port),
+        any::<Ipv4Addr>().prop_map(Tcp::from_ipv4),
+        any::<Ipv6Addr>().prop_map(Tcp::from_ipv6),
+        (any::<IpAddr>(), any::<u16>()).prop_map(|(ip, port)| Tcp::new(ip, port)),
+    ]
+}
+
+fn families() -> impl Strategy<Value = AdbSocketFamilies> {
+    prop_oneof![
+        tcp().prop_map(AdbSocketFamilies::from),
+        name().prop_map(|name| LocalAbstract(name).into()),
+        name().prop_map(|name| LocalReserved(name).into()),
+        path().prop_map(|path| LocalFileSystem(path).into()),
+        path().prop_map(|path| Dev(path).into()),
+        path().prop_map(|path| DevRaw(path).into()),
+        any::<u32>().prop_map(|pid| Jdwp(pid).into()),
+        any::<(u32, u32)>().prop_map(|(cid, port)| Vsock { cid, port }.into()),
+        any::<u32>().prop_map(|fd| AcceptFd(fd).into()),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn tcp_roundtrip(tcp in tcp()) {
+        assert_roundtrip(tcp)?;
+    }
+
+    #[test]
+    fn local_abstract_roundtrip(name in name()) {
+        assert_roundtrip(LocalAbstract(name))?;
+    }
+
+    #[test]
+    fn local_reserved_roundtrip(name in name()) {
+        assert_roundtrip(LocalReserved(name))?;
+    }
+
+    #[test]
+    fn local_file_system_roundtrip(path in path()) {
+        assert_roundtrip(LocalFileSystem(path))?;
+    }
+
+    #[test]
+    fn dev_roundtrip(path in path()) {
+        assert_roundtrip(Dev(path))?;
+    }
+
+    #[test]
+    fn dev_raw_roundtrip(path in path()) {
+        assert_roundtrip(DevRaw(path))?;
+    }
+
+    #[test]
+    fn jdwp_roundtrip(pid in any::<u32>()) {
+        assert_roundtrip(Jdwp(pid))?;
+    }
+
+    #[test]
+    fn vsock_roundtrip(cid in any::<u32>(), port in any::<u32>()) {
+        assert_roundtrip(Vsock { cid, port })?;
+    }
+
+    #[test]
+    fn accept_fd_roundtrip(fd in any::<u32>()) {
+        assert_roundtrip(AcceptFd(fd))?;
+    }
+
+    #[test]
+    fn families_roundtrip(family in families()) {
+        assert_roundtrip(family)?;
+    }
+}
@@ -1,48 +1,113 @@
+use proc_macro2::TokenTree;
 use proc_macro2::{Ident, TokenStream};
 use proc_macro_error::{abort, abort_if_dirty, emit_error};
 use quote::{format_ident, quote, ToTokens};
-use syn::{Data, DeriveInput, Fields, Index};
+use syn::{
+    parse_quote, Attribute, Data, DeriveInput, Field, Fields, GenericArgument, GenericParam,
+    Generics, Index, Lit, LitStr, Path, PathArguments, Type, Variant,
+};
 
 use macro_core_impl::attributed_field;
 
-attributed_field! { struct AdbSocketFamilyField; }
+attributed_field! {
+    #[namespace(adb)]
+    struct AdbSocketFamilyField {
+        /// `#[adb(skip)]`: neither displayed nor parsed, but `Default::default()`.
+        skip: bool,
+        /// `#[adb(default)]`: `Default::default()` if missing from the string. `Option`
+        /// fields are also left out of the string when `None`.
+        default: bool,
+    }
+}
 
 pub fn impl_adb_socket_family(input: DeriveInput) -> TokenStream {
     let ident = &input.ident;
+    let options = StructOptions::parse(&input.attrs);
+    let error = ErrorPath::new(options.error.clone());
+    let (error_ty, parse_error) = (&error.ty, &error.parse);
     match input.data {
         Data::Struct(ds) => {
-            let fields: Vec<AdbSocketFamilyField> = match ds.fields {
+            let fields: Vec<FamilyField> = match ds.fields {
                 Fields::Named(named) => named.named,
                 Fields::Unnamed(unnamed) => unnamed.unnamed,
                 Fields::Unit => abort!(
-                    ds.fields, "`AdbSocketFamily` can only be derived for structs with fields";
+                    ident, "`AdbSocketFamily` can only be derived for structs with fields";
                     note = "`{}` has no fields", input.ident;
                     help = "add fields to the struct";
                 ),
             }
             .into_iter()
-            .map(|f| f.into())
+            .map(FamilyField::new)
             .collect();
-            let family = ident.to_string().to_lowercase();
-            let display = impl_display(&family, &input.ident, &fields);
-            let from_str = impl_from_str(&family, &input.ident, &fields);
+            check_fields(ident, &fields);
+            abort_if_dirty();
+            let family = options
+                .rename
+                .unwrap_or_else(|| ident.to_string().to_lowercase());
+            let separator = options.separator.unwrap_or_else(|| ":".to_string());
+            let generics = with_field_bounds(
+                &input.generics,
+                fields.iter().filter(|f| !f.skip).map(FamilyField::value_ty),
+            );
+            let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+            let display = impl_display(&family, &separator, ident, &generics, &fields);
+            let from_str = impl_from_str(&family, &separator, ident, &generics, &fields, &error);
+            let try_from =
+                impl_try_from(ident, &generics, &fields, &error, options.validate.as_ref());
+            let serde = options.serde.then(|| impl_serde(ident, &generics));
             quote! {
                 #display
                 #from_str
-                impl AdbSocketFamily for #ident {}
+                #try_from
+                #serde
+                impl #impl_generics AdbSocketFamily for #ident #ty_generics #where_clause {
+                    const FAMILY: &'static str = #family;
+                }
             }
         }
         Data::Enum(de) => {
+            if options.rename.is_some() || options.separator.is_some() || options.validate.is_some()
+            {
+                emit_error!(
+                    ident, "`rename`, `separator` and `validate` only apply to structs";
+                    help = "set them on the structs of the variants";
+                );
+            }
             let mut from_variants = Vec::new();
             let mut display_arms = Vec::new();
             let mut from_str_arms = Vec::new();
+            let mut try_from_arms = Vec::new();
+            let mut family_name_arms = Vec::new();
+            let mut families = Vec::new();
+            let mut other_arm = None;
+            let mut field_tys = Vec::new();
             for variant in de.variants {
                 let variant_ident = &variant.ident;
+                if is_other(&variant) {
+                    if other_arm.is_some() {
+                        emit_error!(
+                            variant, "only one variant can be `#[adb(other)]`";
+                            help = "remove `#[adb(other)]` from one of the variants";
+                        );
+                    }
+                    display_arms.push(quote! {
+                        Self::#variant_ident(value) => f.write_str(value),
+                    });
+                    family_name_arms.push(quote! {
+                        Self::#variant_ident(_) => Self::FAMILY,
+                    });
+                    other_arm = Some(quote! {
+                        family if !family.is_empty() && family.len() < s.len() => {
+                            Ok(Self::#variant_ident(s.to_string()))
+                        }
+                    });
+                    continue;
+                }
                 let fields = match variant.fields {
                     Fields::Named(named) => named.named,
                     Fields::Unnamed(unnamed) => unnamed.unnamed,
                     Fields::Unit => abort!(
-                        variant.fields, "`AdbSocketFamily` can only be derived for structs with fields";
+                        variant_ident, "`AdbSocketFamily` can only be derived for structs with fields";
                         note = "`{}` has no fields", variant_ident;
                         help = "add fields to the struct";
                     ),
@@ -56,45 +121,104 @@ pub fn impl_adb_socket_family(input: DeriveInput) -> TokenStream {
                 }
                 let field = fields.first().unwrap();
                 let field_ty = &field.ty;
-                from_variants.push(quote! {
-                    impl From<#field_ty> for #ident {
-                            fn from(value: #field_ty) -> Self {
-                                Self::#variant_ident(value)
-                        }
-                    }
-                });
+                field_tys.push(field_ty.clone());
+                from_variants.push((field_ty.clone(), variant_ident.clone()));
                 display_arms.push(quote! {
                     Self::#variant_ident(value) => write!(f, "{}", value),
                 });
+                // Names the variant, keeping the error of its type as the source.
+                let variant_err = quote! {
+                    #parse_error {
+                        value: s.to_string(),
+                        source_type: "&str",
+                        target_type: concat!(stringify!(#ident), "::", stringify!(#variant_ident)),
+                        source: Some(Box::new(e)),
+                    }
+                };
                 from_str_arms.push(quote! {
-                    if let Ok(value) = s.parse() {
-                        return Ok(Self::#variant_ident(value));
+                    family if family == <#field_ty as AdbSocketFamily>::FAMILY => {
+                        s.parse().map(Self::#variant_ident).map_err(|e| #variant_err)
+                    }
+                });
+                try_from_arms.push(quote! {
+                    family if family == <#field_ty as AdbSocketFamily>::FAMILY => {
+                        <#field_ty>::try_from(s).map(Self::#variant_ident).map_err(|e| #variant_err)
                     }
                 });
+                families.push(quote! { <#field_ty as AdbSocketFamily>::FAMILY });
+                family_name_arms.push(quote! {
+                    Self::#variant_ident(value) => value.family_name(),
+                });
             }
             abort_if_dirty();
+            let generics = with_field_bounds(&input.generics, field_tys.iter());
+            let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+            let from_variants = from_variants.iter().map(|(field_ty, variant_ident)| {
+                quote! {
+                    impl #impl_generics From<#field_ty> for #ident #ty_generics #where_clause {
+                        fn from(value: #field_ty) -> Self {
+                            Self::#variant_ident(value)
+                        }
+                    }
+                }
+            });
+            let unknown = quote! {
+                #parse_error {
+                    value: s.to_string(),
+                    source_type: "&str",
+                    target_type: stringify!(#ident),
+                    source: Some(
+                        format!(
+                            "unknown family `{}`, expected one of `{}`",
+                            family,
+                            Self::FAMILIES.join("`, `")
+                        )
+                        .into(),
+                    ),
+                }
+            };
+            let serde = options.serde.then(|| impl_serde(ident, &generics));
             quote! {
                 #(#from_variants)*
-                impl std::fmt::Display for #ident {
-                    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                impl #impl_generics #ident #ty_generics #where_clause {
+                    /// The families of the variants, in declaration order.
+                    pub const FAMILIES: &'static [&'static str] = &[#(#families),*];
+                }
+                impl #impl_generics ::core::fmt::Display for #ident #ty_generics #where_clause {
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
                         match self {
                             #(#display_arms)*
                         }
                     }
                 }
-                impl std::str::FromStr for #ident {
-                    type Err = crate::error::AdbError;
+                impl #impl_generics ::core::str::FromStr for #ident #ty_generics #where_clause {
+                    type Err = #error_ty;
                     fn from_str(s: &str) -> Result<Self, Self::Err> {
-                        #(#from_str_arms)*
-                        Err(crate::error::AdbError::Parse {
-                            value: s.to_string(),
-                            source_type: "&str",
-                            target_type: stringify!(#ident),
-                            source: None,
-                        })
+                        match s.split_once(':').map_or(s, |(family, _)| family) {
+                            #(#from_str_arms)*
+                            #other_arm
+                            family => Err(#unknown),
+                        }
+                    }
+                }
+                impl #impl_generics TryFrom<&str> for #ident #ty_generics #where_clause {
+                    type Error = #error_ty;
+                    fn try_from(s: &str) -> Result<Self, Self::Error> {
+                        match s.split_once(':').map_or(s, |(family, _)| family) {
+                            #(#try_from_arms)*
+                            family => Err(#unknown),
+                        }
+                    }
+                }
+                #serde
+                impl #impl_generics AdbSocketFamily for #ident #ty_generics #where_clause {
+                    const FAMILY: &'static str = "";
+                    fn family_name(&self) -> &'static str {
+                        match self {
+                            #(#family_name_arms)*
+                        }
                     }
                 }
-                impl AdbSocketFamily for #ident {}
             }
         }
         Data::Union(_) => abort!(
@@ -104,37 +228,375 @@ pub fn impl_adb_socket_family(input: DeriveInput) -> TokenStream {
     }
 }
 
-fn impl_display(family: &str, ident: &Ident, fields: &[AdbSocketFamilyField]) -> TokenStream {
-    let mut format = format!("{}:", family);
-    let fields = fields
+/// Returns `true` if `variant` is marked `#[adb(other)]`, holding the strings of unknown
+/// families.
+fn is_other(variant: &Variant) -> bool {
+    let mut other = false;
+    for attr in variant
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("adb"))
+    {
+        let result = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("other") {
+                other = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown `adb` attribute, expected `other`"))
+            }
+        });
+        if let Err(e) = result {
+            emit_error!(e.span(), "{}", e);
+        }
+    }
+    other
+}
+
+/// The `#[adb(...)]` options of a struct.
+#[derive(Default)]
+struct StructOptions {
+    /// `#[adb(rename = "...")]`, the family instead of the lowercase struct name.
+    rename: Option<String>,
+    /// `#[adb(separator = ...)]`, the separator of the fields instead of `:`.
+    separator: Option<String>,
+    /// `#[adb(error = "...")]`, the path of the error type, on structs and enums.
+    error: Option<Path>,
+    /// `#[adb(serde)]`, implementing `Serialize` and `Deserialize`, on structs and enums.
+    serde: bool,
+    /// `#[adb(validate)]` or `#[adb(validate = "...")]`, the constructor validating the fields
+    /// parsed by `TryFrom`, `Self::new` by default.
+    validate: Option<Path>,
+}
+
+impl StructOptions {
+    fn parse(attrs: &[Attribute]) -> Self {
+        let mut options = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("adb")) {
+            let result = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    let family: LitStr = meta.value()?.parse()?;
+                    let value = family.value();
+                    if value.is_empty() || value.contains(':') {
+                        return Err(syn::Error::new(
+                            family.span(),
+                            "the family must be non-empty and contain no `:`",
+                        ));
+                    }
+                    options.rename = Some(value);
+                    Ok(())
+                } else if meta.path.is_ident("separator") {
+                    let separator = match meta.value()?.parse()? {
+                        Lit::Str(s) => (s.value(), s.span()),
+                        Lit::Char(c) => (c.value().to_string(), c.span()),
+                        lit => {
+                            return Err(syn::Error::new(lit.span(), "expected a string or a char"))
+                        }
+                    };
+                    if separator.0.is_empty() {
+                        return Err(syn::Error::new(
+                            separator.1,
+                            "the separator must be non-empty",
+                        ));
+                    }
+                    options.separator = Some(separator.0);
+                    Ok(())
+                } else if meta.path.is_ident("error") {
+                    let path: LitStr = meta.value()?.parse()?;
+                    options.error = Some(path.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("serde") {
+                    options.serde = true;
+                    Ok(())
+                } else if meta.path.is_ident("validate") {
+                    options.validate = Some(if meta.input.peek(syn::Token![=]) {
+                        let path: LitStr = meta.value()?.parse()?;
+                        path.parse()?
+                    } else {
+                        parse_quote!(Self::new)
+                    });
+                    Ok(())
+                } else {
+                    Err(meta.error(
+                        "unknown `adb` attribute, expected `rename`, `separator`, `error`, \
+                        `serde` or `validate`",
+                    ))
+                }
+            });
+            if let Err(e) = result {
+                emit_error!(e.span(), "{}", e);
+            }
+        }
+        options
+    }
+}
+
+/// The error of the generated conversions.
+struct ErrorPath {
+    /// The type of the error.
+    ty: TokenStream,
+    /// The struct or variant built for parse errors, with the `value`, `source_type`,
+    /// `target_type` and `source` fields.
+    parse: TokenStream,
+}
+
+impl ErrorPath {
+    /// Returns the error at `path`, or `crate::error::AdbError` and its `Parse` variant.
+    fn new(path: Option<Path>) -> Self {
+        match path {
+            Some(path) => Self {
+                ty: path.to_token_stream(),
+                parse: path.to_token_stream(),
+            },
+            None => Self {
+                ty: quote! { crate::error::AdbError },
+                parse: quote! { crate::error::AdbError::Parse },
+            },
+        }
+    }
+}
+
+/// A field of a struct, with its `#[adb(...)]` options.
+struct FamilyField {
+    field: AdbSocketFamilyField,
+    skip: bool,
+    default: bool,
+}
+
+impl FamilyField {
+    fn new(field: Field) -> Self {
+        let field = AdbSocketFamilyField::from(field);
+        Self {
+            skip: field.skip,
+            default: field.default,
+            field,
+        }
+    }
+
+    /// Returns the binding of the field `i`, its name or `field<i>`.
+    fn binding(&self, i: usize) -> Ident {
+        self.field
+            .ident()
+            .cloned()
+            .unwrap_or_else(|| format_ident!("field{}", i))
+    }
+
+    /// Returns the expression accessing the field `i` of `self`.
+    fn member(&self, i: usize) -> TokenStream {
+        self.field
+            .ident()
+            .map(Ident::to_token_stream)
+            .unwrap_or_else(|| Index::from(i).to_token_stream())
+    }
+
+    /// Returns `true` if the field is an `Option`.
+    fn is_option(&self) -> bool {
+        option_inner(self.field.ty()).is_some()
+    }
+
+    /// Returns the type of the values displayed and parsed, the type of the field, or the
+    /// inner type of `Option` fields with defaults.
+    fn value_ty(&self) -> &Type {
+        match option_inner(self.field.ty()) {
+            Some(inner) if self.default => inner,
+            _ => self.field.ty(),
+        }
+    }
+}
+
+/// Returns the inner type of `Option<T>`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns `generics` with the bounds the generated impls need on `tys` that mention type
+/// parameters: `Display`, and `FromStr` with an error that can be the source of a parse
+/// error. Bounds on concrete types are left to the compiler.
+fn with_field_bounds<'a>(generics: &Generics, tys: impl Iterator<Item = &'a Type>) -> Generics {
+    let params = generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(ty) => Some(ty.ident.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut generics = generics.clone();
+    if params.is_empty() {
+        return generics;
+    }
+    let where_clause = generics.make_where_clause();
+    for ty in tys.filter(|ty| mentions(ty.to_token_stream(), &params)) {
+        where_clause.predicates.push(parse_quote! {
+            #ty: ::core::fmt::Display + ::core::str::FromStr
+        });
+        where_clause.predicates.push(parse_quote! {
+            <#ty as ::core::str::FromStr>::Err: std::error::Error + Send + Sync + 'static
+        });
+    }
+    generics
+}
+
+/// Returns `true` if `tokens` contain one of `idents`.
+fn mentions(tokens: TokenStream, idents: &[Ident]) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ident) => idents.contains(&ident),
+        TokenTree::Group(group) => mentions(group.stream(), idents),
+        _ => false,
+    })
+}
+
+/// Checks that some fields are parsed, and that fields with defaults are trailing.
+fn check_fields(ident: &Ident, fields: &[FamilyField]) {
+    let mut parsed = fields.iter().filter(|f| !f.skip);
+    let Some(first) = parsed.next() else {
+        emit_error!(
+            ident, "`AdbSocketFamily` needs a field that isn't skipped";
+            help = "remove `#[adb(skip)]` from one of the fields";
+        );
+        return;
+    };
+    let mut default = first.default;
+    for f in parsed {
+        if default && !f.default {
+            emit_error!(
+                f.field.ty(), "a field without default follows a field with `#[adb(default)]`";
+                help = "add `#[adb(default)]` to the field, or move it before";
+            );
+        }
+        default |= f.default;
+    }
+}
+
+fn impl_display(
+    family: &str,
+    separator: &str,
+    ident: &Ident,
+    generics: &Generics,
+    fields: &[FamilyField],
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut first = true;
+    let writes = fields
         .iter()
         .enumerate()
+        .filter(|(_, f)| !f.skip)
         .map(|(i, f)| {
-            if i > 0 {
-                format.push(':');
-            }
-            format.push_str("{}");
-            let ident = f
-                .ident()
-                .map(Ident::to_token_stream)
-                .unwrap_or_else(|| Index::from(i).to_token_stream());
-            if f.ty().to_token_stream().to_string() == "PathBuf" {
-                quote! { #ident.display() }
+            let prefix = if first { "" } else { separator };
+            first = false;
+            let member = f.member(i);
+            if f.default && f.is_option() {
+                quote! {
+                    if let Some(value) = &self.#member {
+                        write!(f, "{}{}", #prefix, value)?;
+                    }
+                }
+            } else if f.field.ty().to_token_stream().to_string() == "PathBuf" {
+                quote! { write!(f, "{}{}", #prefix, self.#member.display())?; }
             } else {
-                ident
+                quote! { write!(f, "{}{}", #prefix, self.#member)?; }
             }
         })
         .collect::<Vec<_>>();
+    let family = format!("{}:", family);
     quote! {
-        impl std::fmt::Display for #ident {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                write!(f, #format #(, self.#fields)*)
+        impl #impl_generics ::core::fmt::Display for #ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.write_str(#family)?;
+                #(#writes)*
+                Ok(())
             }
         }
     }
 }
 
-fn err<T: ToTokens>(ident: &str, ty: &T, source: bool) -> TokenStream {
+/// Implements `TryFrom<&str>` by parsing the string, then passing the fields that aren't
+/// skipped to the `validate` constructor of the struct, if any.
+fn impl_try_from(
+    ident: &Ident,
+    generics: &Generics,
+    fields: &[FamilyField],
+    error: &ErrorPath,
+    validate: Option<&Path>,
+) -> TokenStream {
+    let error_ty = &error.ty;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let Some(validate) = validate else {
+        return quote! {
+            impl #impl_generics TryFrom<&str> for #ident #ty_generics #where_clause {
+                type Error = #error_ty;
+                fn try_from(s: &str) -> Result<Self, Self::Error> {
+                    s.parse()
+                }
+            }
+        };
+    };
+    let named = fields.first().unwrap().field.ident().is_some();
+    let mut args = Vec::new();
+    let mut pattern = Vec::new();
+    for (i, f) in fields.iter().enumerate() {
+        let binding = f.binding(i);
+        if f.skip {
+            if !named {
+                pattern.push(quote! { _ });
+            }
+        } else {
+            pattern.push(binding.to_token_stream());
+            args.push(binding);
+        }
+    }
+    let pattern = if named {
+        quote! { {#(#pattern,)* ..} }
+    } else {
+        quote! { (#(#pattern),*) }
+    };
+    quote! {
+        impl #impl_generics TryFrom<&str> for #ident #ty_generics #where_clause {
+            type Error = #error_ty;
+            fn try_from(s: &str) -> Result<Self, Self::Error> {
+                let Self #pattern = s.parse()?;
+                #validate(#(#args),*)
+            }
+        }
+    }
+}
+
+/// Implements `Serialize` and `Deserialize` through `Display` and `FromStr`, for types marked
+/// `#[adb(serde)]`.
+fn impl_serde(ident: &Ident, generics: &Generics) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut de_generics = generics.clone();
+    de_generics.params.insert(0, parse_quote!('de));
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+    quote! {
+        impl #impl_generics serde::Serialize for #ident #ty_generics #where_clause {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+        impl #de_impl_generics serde::Deserialize<'de> for #ident #ty_generics #where_clause {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    }
+}
+
+fn err<T: ToTokens>(ident: &str, ty: &T, source: bool, error: &ErrorPath) -> TokenStream {
+    let parse_error = &error.parse;
     let ident = format_ident!("{}", ident);
     let source = if source {
         quote! { source: Some(Box::new(e)), }
@@ -142,7 +604,7 @@ fn err<T: ToTokens>(ident: &str, ty: &T, source: bool) -> TokenStream {
         quote! { source: None, }
     };
     quote! {
-        crate::error::AdbError::Parse {
+        #parse_error {
             value: #ident.to_string(),
             source_type: "&str",
             target_type: stringify!(#ty),
@@ -151,45 +613,75 @@ fn err<T: ToTokens>(ident: &str, ty: &T, source: bool) -> TokenStream {
     }
 }
 
-fn impl_from_str(family: &str, ident: &Ident, fields: &[AdbSocketFamilyField]) -> TokenStream {
-    let fields_count = fields.len();
-    let mut decls = Vec::with_capacity(fields_count);
-    let mut args = Vec::with_capacity(fields_count);
+fn impl_from_str(
+    family: &str,
+    separator: &str,
+    ident: &Ident,
+    generics: &Generics,
+    fields: &[FamilyField],
+    error: &ErrorPath,
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let error_ty = &error.ty;
+    let last = fields.iter().rposition(|f| !f.skip).unwrap();
+    let mut decls = Vec::with_capacity(fields.len());
+    let mut args = Vec::with_capacity(fields.len());
     for (i, f) in fields.iter().enumerate() {
-        let f_ident = f
-            .ident()
-            .cloned()
-            .unwrap_or_else(|| format_ident!("field{}", i));
-        let some = err("rest", f.ty(), true);
-        decls.push(if i < fields_count - 1 {
-            let none = err("rest", f.ty(), false);
+        let binding = f.binding(i);
+        args.push(binding.clone());
+        if f.skip {
+            decls.push(quote! { let #binding = Default::default(); });
+            continue;
+        }
+        let split = if i < last {
             quote! {
-                let (#f_ident, rest) = match rest.split_once(':') {
-                    Some((value, rest)) => (value.parse().map_err(|e| #some)?, rest),
-                    None => return Err(#none),
+                let (value, rest) = match rest.map(|rest| rest.split_once(#separator)) {
+                    Some(Some((value, rest))) => (Some(value), Some(rest)),
+                    Some(None) => (rest, None),
+                    None => (None, None),
                 };
             }
         } else {
-            quote! { let #f_ident = rest.parse().map_err(|e| #some)?; }
+            quote! { let value = rest; }
+        };
+        let some = err("value", f.field.ty(), true, error);
+        let parse = if f.default && f.is_option() {
+            quote! { value.parse().map(Some).map_err(|e| #some)? }
+        } else {
+            quote! { value.parse().map_err(|e| #some)? }
+        };
+        let missing = if f.default {
+            quote! { Default::default() }
+        } else {
+            let none = err("s", f.field.ty(), false, error);
+            quote! { return Err(#none) }
+        };
+        decls.push(quote! {
+            #split
+            let #binding = match value {
+                Some(value) => #parse,
+                None => #missing,
+            };
         });
-        args.push(f_ident);
     }
-    let new = if fields.first().unwrap().ident().is_some() {
-        quote! { {#(#args),*}}
+    let new = if fields.first().unwrap().field.ident().is_some() {
+        quote! { {#(#args),*} }
     } else {
         quote! { (#(#args),*) }
     };
-    let none = err("s", ident, false);
+    let none = err("s", ident, false, error);
+    let prefix = format!("{}:", family);
     quote! {
-        impl std::str::FromStr for #ident {
-            type Err = crate::error::AdbError;
+        impl #impl_generics ::core::str::FromStr for #ident #ty_generics #where_clause {
+            type Err = #error_ty;
             fn from_str(s: &str) -> Result<Self, Self::Err> {
-                match s.split_once(':') {
-                    Some((#family, rest)) => {
+                match s.strip_prefix(#prefix) {
+                    Some(rest) => {
+                        let rest = Some(rest);
                         #(#decls)*
                         Ok(Self #new)
                     }
-                    _ => Err(#none),
+                    None => Err(#none),
                 }
             }
         }
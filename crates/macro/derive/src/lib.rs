@@ -6,16 +6,56 @@ mod adb_socket_family;
 /// Derive the `AdbSocketFamily` trait for a struct or enum.
 ///
 /// For structs, the trait generates:
-/// - [`std::fmt::Display`] implementation.
-/// - [`std::str::FromStr`] implementation.
-/// - [`adb::socket::AdbSocketFamily`] implementation.
+/// - [`core::fmt::Display`] implementation.
+/// - [`core::str::FromStr`] implementation.
+/// - `TryFrom<&str>` implementation, parsing like `FromStr`, or passing the parsed fields to
+///   a validating constructor `fn new(<fields>) -> Result<Self, AdbError>` if the struct is
+///   marked `#[adb(validate)]`, or to the function at `path` if marked
+///   `#[adb(validate = "path")]`.
+/// - [`adb::socket::AdbSocketFamily`] implementation, with the lowercase struct name as the
+///   family.
+/// - `serde::Serialize` and `serde::Deserialize` implementations as the string form, if
+///   marked `#[adb(serde)]`.
+///
+/// The struct and its fields take `#[adb(...)]` options:
+/// - `#[adb(rename = "dev-raw")]` on the struct sets the family, e.g. for hyphenated ones.
+/// - `#[adb(separator = ',')]` on the struct separates the fields with a char or a string
+///   instead of `:`. The last field takes the rest of the string, separators included.
+/// - `#[adb(skip)]` on a field leaves it out of the string, parsing it as
+///   `Default::default()`. Skipped fields aren't passed to `new`.
+/// - `#[adb(default)]` on trailing fields parses them as `Default::default()` when missing.
+///   `Option` fields are also left out of the string when `None`.
+///
 /// For enums, the trait generates:
 /// - [`From`] implementations for each variant.
-/// - [`std::fmt::Display`] implementation. (calls variant's `Display` implementation)
-/// - [`std::str::FromStr`] implementation. (calls variant's `FromStr` implementation)
-/// - [`adb::socket::AdbSocketFamily`] implementation.
+/// - [`core::fmt::Display`] implementation. (calls variant's `Display` implementation)
+/// - [`core::str::FromStr`] implementation. (calls the `FromStr` implementation of the variant
+///   whose family is the prefix of the string)
+/// - `TryFrom<&str>` implementation. (likewise with `TryFrom<&str>`)
+/// - A `FAMILIES` constant listing the families of the variants.
+///
+/// The error of the variant is the source of a parse error naming the variant, e.g. into
+/// `AdbSocketFamilies::Jdwp`. Strings of unknown families fail listing the known ones.
+/// - [`adb::socket::AdbSocketFamily`] implementation, with an empty family and `family_name`
+///   returning the family of the variant.
+/// - `serde::Serialize` and `serde::Deserialize` implementations as the string form, if
+///   marked `#[adb(serde)]`.
+///
+/// Serde support is opt-in, so that the generated code doesn't depend on the features of the
+/// deriving crate: crates with an optional `serde` dependency mark their types
+/// `#[cfg_attr(feature = "serde", adb(serde))]`.
+///
+/// The errors are `crate::error::AdbError`, built with its `Parse` variant. Structs and enums
+/// marked `#[adb(error = "crate::error::ParseError")]` use the error at that path instead, a
+/// struct with the same fields as the variant, for crates without an `AdbError`. The
+/// generated code only uses `core` and `alloc` items, so it also builds in `no_std` crates
+/// importing `String`, `Box`, `ToString` and `format!` from `alloc`.
+///
+/// An enum variant marked `#[adb(other)]`, holding a `String`, keeps the strings of unknown
+/// families parsed with `FromStr`, while `TryFrom<&str>` still rejects them. It has no `From`
+/// implementation, and its `family_name` is empty.
 #[proc_macro_error]
-#[proc_macro_derive(AdbSocketFamily)]
+#[proc_macro_derive(AdbSocketFamily, attributes(adb))]
 pub fn derive_adb_socket_family(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as syn::DeriveInput);
     adb_socket_family::impl_adb_socket_family(input).into()
@@ -0,0 +1,4 @@
+#[test]
+fn expand() {
+    macrotest::expand("tests/expand/*.rs");
+}
@@ -0,0 +1,283 @@
+use derive::AdbSocketFamily;
+use error::AdbError;
+
+// The items of `adb` the generated code refers to.
+mod error {
+    pub enum AdbError {
+        Parse {
+            value: String,
+            source_type: &'static str,
+            target_type: &'static str,
+            source: Option<Box<dyn std::error::Error + Send + Sync>>,
+        },
+    }
+    #[automatically_derived]
+    impl ::core::fmt::Debug for AdbError {
+        #[inline]
+        fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+            match self {
+                AdbError::Parse {
+                    value: __self_0,
+                    source_type: __self_1,
+                    target_type: __self_2,
+                    source: __self_3,
+                } => ::core::fmt::Formatter::debug_struct_field4_finish(
+                    f,
+                    "Parse",
+                    "value",
+                    __self_0,
+                    "source_type",
+                    __self_1,
+                    "target_type",
+                    __self_2,
+                    "source",
+                    &__self_3,
+                ),
+            }
+        }
+    }
+}
+
+trait AdbSocketFamily: std::str::FromStr + std::fmt::Display {
+    const FAMILY: &'static str;
+
+    fn family_name(&self) -> &'static str {
+        Self::FAMILY
+    }
+}
+
+#[adb(validate)]
+struct Tcp(u16);
+impl ::core::fmt::Display for Tcp {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        f.write_str("tcp:")?;
+
+        f.write_fmt(format_args!("{0}{1}", "", self.0))?;
+        Ok(())
+    }
+}
+impl ::core::str::FromStr for Tcp {
+    type Err = crate::error::AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("tcp:") {
+            Some(rest) => {
+                let rest = Some(rest);
+                let value = rest;
+                let field0 = match value {
+                    Some(value) => value.parse().map_err(|e| crate::error::AdbError::Parse {
+                        value: value.to_string(),
+                        source_type: "&str",
+                        target_type: "u16",
+                        source: Some(Box::new(e)),
+                    })?,
+                    None => {
+                        return Err(crate::error::AdbError::Parse {
+                            value: s.to_string(),
+                            source_type: "&str",
+                            target_type: "u16",
+                            source: None,
+                        })
+                    }
+                };
+                Ok(Self(field0))
+            }
+            None => Err(crate::error::AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Tcp",
+                source: None,
+            }),
+        }
+    }
+}
+impl TryFrom<&str> for Tcp {
+    type Error = crate::error::AdbError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let Self(field0) = s.parse()?;
+        Self::new(field0)
+    }
+}
+impl AdbSocketFamily for Tcp {
+    const FAMILY: &'static str = "tcp";
+}
+impl Tcp {
+    fn new(port: u16) -> Result<Self, AdbError> {
+        Ok(Self(port))
+    }
+}
+struct Jdwp(u32);
+impl ::core::fmt::Display for Jdwp {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        f.write_str("jdwp:")?;
+        f.write_fmt(format_args!("{0}{1}", "", self.0))?;
+        Ok(())
+    }
+}
+impl ::core::str::FromStr for Jdwp {
+    type Err = crate::error::AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("jdwp:") {
+            Some(rest) => {
+                let rest = Some(rest);
+                let value = rest;
+                let field0 = match value {
+                    Some(value) => value.parse().map_err(|e| crate::error::AdbError::Parse {
+                        value: value.to_string(),
+                        source_type: "&str",
+                        target_type: "u32",
+                        source: Some(Box::new(e)),
+                    })?,
+                    None => {
+                        return Err(crate::error::AdbError::Parse {
+                            value: s.to_string(),
+                            source_type: "&str",
+                            target_type: "u32",
+                            source: None,
+                        })
+                    }
+                };
+                Ok(Self(field0))
+            }
+            None => Err(crate::error::AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Jdwp",
+                source: None,
+            }),
+        }
+    }
+}
+impl TryFrom<&str> for Jdwp {
+    type Error = crate::error::AdbError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+impl AdbSocketFamily for Jdwp {
+    const FAMILY: &'static str = "jdwp";
+}
+enum Families {
+    Tcp(Tcp),
+    Jdwp(Jdwp),
+
+    #[adb(other)]
+    Other(String),
+}
+impl From<Tcp> for Families {
+    fn from(value: Tcp) -> Self {
+        Self::Tcp(value)
+    }
+}
+impl From<Jdwp> for Families {
+    fn from(value: Jdwp) -> Self {
+        Self::Jdwp(value)
+    }
+}
+impl Families {
+    #[doc = r" The families of the variants, in declaration order."]
+    pub const FAMILIES: &'static [&'static str] = &[
+        <Tcp as AdbSocketFamily>::FAMILY,
+        <Jdwp as AdbSocketFamily>::FAMILY,
+    ];
+}
+impl ::core::fmt::Display for Families {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        match self {
+            Self::Tcp(value) => f.write_fmt(format_args!("{0}", value)),
+            Self::Jdwp(value) => f.write_fmt(format_args!("{0}", value)),
+            Self::Other(value) => f.write_str(value),
+        }
+    }
+}
+impl ::core::str::FromStr for Families {
+    type Err = crate::error::AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':').map_or(s, |(family, _)| family) {
+            family if family == <Tcp as AdbSocketFamily>::FAMILY => s
+                .parse()
+                .map(Self::Tcp)
+                .map_err(|e| crate::error::AdbError::Parse {
+                    value: s.to_string(),
+                    source_type: "&str",
+                    target_type: "Families::Tcp",
+                    source: Some(Box::new(e)),
+                }),
+            family if family == <Jdwp as AdbSocketFamily>::FAMILY => s
+                .parse()
+                .map(Self::Jdwp)
+                .map_err(|e| crate::error::AdbError::Parse {
+                    value: s.to_string(),
+                    source_type: "&str",
+                    target_type: "Families::Jdwp",
+                    source: Some(Box::new(e)),
+                }),
+            family if !family.is_empty() && family.len() < s.len() => {
+                Ok(Self::Other(s.to_string()))
+            }
+            family => Err(crate::error::AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Families",
+                source: Some(
+                    ::alloc::__export::must_use({
+                        ::alloc::fmt::format(format_args!(
+                            "unknown family `{0}`, expected one of `{1}`",
+                            family,
+                            Self::FAMILIES.join("`, `")
+                        ))
+                    })
+                    .into(),
+                ),
+            }),
+        }
+    }
+}
+impl TryFrom<&str> for Families {
+    type Error = crate::error::AdbError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.split_once(':').map_or(s, |(family, _)| family) {
+            family if family == <Tcp as AdbSocketFamily>::FAMILY => <Tcp>::try_from(s)
+                .map(Self::Tcp)
+                .map_err(|e| crate::error::AdbError::Parse {
+                    value: s.to_string(),
+                    source_type: "&str",
+                    target_type: "Families::Tcp",
+                    source: Some(Box::new(e)),
+                }),
+            family if family == <Jdwp as AdbSocketFamily>::FAMILY => <Jdwp>::try_from(s)
+                .map(Self::Jdwp)
+                .map_err(|e| crate::error::AdbError::Parse {
+                    value: s.to_string(),
+                    source_type: "&str",
+                    target_type: "Families::Jdwp",
+                    source: Some(Box::new(e)),
+                }),
+            family => Err(crate::error::AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Families",
+                source: Some(
+                    ::alloc::__export::must_use({
+                        ::alloc::fmt::format(format_args!(
+                            "unknown family `{0}`, expected one of `{1}`",
+                            family,
+                            Self::FAMILIES.join("`, `")
+                        ))
+                    })
+                    .into(),
+                ),
+            }),
+        }
+    }
+}
+impl AdbSocketFamily for Families {
+    const FAMILY: &'static str = "";
+    fn family_name(&self) -> &'static str {
+        match self {
+            Self::Tcp(value) => value.family_name(),
+            Self::Jdwp(value) => value.family_name(),
+            Self::Other(_) => Self::FAMILY,
+        }
+    }
+}
+fn main() {}
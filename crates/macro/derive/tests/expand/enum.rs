@@ -0,0 +1,46 @@
+use derive::AdbSocketFamily;
+use error::AdbError;
+
+// The items of `adb` the generated code refers to.
+mod error {
+    #[derive(Debug)]
+    pub enum AdbError {
+        Parse {
+            value: String,
+            source_type: &'static str,
+            target_type: &'static str,
+            source: Option<Box<dyn std::error::Error + Send + Sync>>,
+        },
+    }
+}
+
+trait AdbSocketFamily: std::str::FromStr + std::fmt::Display {
+    const FAMILY: &'static str;
+
+    fn family_name(&self) -> &'static str {
+        Self::FAMILY
+    }
+}
+
+#[derive(AdbSocketFamily)]
+#[adb(validate)]
+struct Tcp(u16);
+
+impl Tcp {
+    fn new(port: u16) -> Result<Self, AdbError> {
+        Ok(Self(port))
+    }
+}
+
+#[derive(AdbSocketFamily)]
+struct Jdwp(u32);
+
+#[derive(AdbSocketFamily)]
+enum Families {
+    Tcp(Tcp),
+    Jdwp(Jdwp),
+    #[adb(other)]
+    Other(String),
+}
+
+fn main() {}
@@ -0,0 +1,201 @@
+use derive::AdbSocketFamily;
+use error::AdbError;
+
+// The items of `adb` the generated code refers to.
+mod error {
+    pub enum AdbError {
+        Parse {
+            value: String,
+            source_type: &'static str,
+            target_type: &'static str,
+            source: Option<Box<dyn std::error::Error + Send + Sync>>,
+        },
+    }
+    #[automatically_derived]
+    impl ::core::fmt::Debug for AdbError {
+        #[inline]
+        fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+            match self {
+                AdbError::Parse {
+                    value: __self_0,
+                    source_type: __self_1,
+                    target_type: __self_2,
+                    source: __self_3,
+                } => ::core::fmt::Formatter::debug_struct_field4_finish(
+                    f,
+                    "Parse",
+                    "value",
+                    __self_0,
+                    "source_type",
+                    __self_1,
+                    "target_type",
+                    __self_2,
+                    "source",
+                    &__self_3,
+                ),
+            }
+        }
+    }
+}
+
+trait AdbSocketFamily: std::str::FromStr + std::fmt::Display {
+    const FAMILY: &'static str;
+}
+
+#[adb(rename = "local-abstract", validate)]
+struct LocalAbstract {
+    name: String,
+}
+impl ::core::fmt::Display for LocalAbstract {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        f.write_str("local-abstract:")?;
+
+        f.write_fmt(format_args!("{0}{1}", "", self.name))?;
+        Ok(())
+    }
+}
+impl ::core::str::FromStr for LocalAbstract {
+    type Err = crate::error::AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("local-abstract:") {
+            Some(rest) => {
+                let rest = Some(rest);
+                let value = rest;
+                let name = match value {
+                    Some(value) => value.parse().map_err(|e| crate::error::AdbError::Parse {
+                        value: value.to_string(),
+                        source_type: "&str",
+                        target_type: "String",
+                        source: Some(Box::new(e)),
+                    })?,
+                    None => {
+                        return Err(crate::error::AdbError::Parse {
+                            value: s.to_string(),
+                            source_type: "&str",
+                            target_type: "String",
+                            source: None,
+                        })
+                    }
+                };
+                Ok(Self { name })
+            }
+            None => Err(crate::error::AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "LocalAbstract",
+                source: None,
+            }),
+        }
+    }
+}
+impl TryFrom<&str> for LocalAbstract {
+    type Error = crate::error::AdbError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let Self { name, .. } = s.parse()?;
+        Self::new(name)
+    }
+}
+impl AdbSocketFamily for LocalAbstract {
+    const FAMILY: &'static str = "local-abstract";
+}
+impl LocalAbstract {
+    fn new(name: String) -> Result<Self, AdbError> {
+        Ok(Self { name })
+    }
+}
+#[adb(separator = ',', validate = "Endpoint::new")]
+struct Endpoint {
+    host: String,
+    #[adb(skip)]
+    retries: u32,
+    #[adb(default)]
+    port: Option<u16>,
+}
+impl ::core::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        f.write_str("endpoint:")?;
+        f.write_fmt(format_args!("{0}{1}", "", self.host))?;
+        if let Some(value) = &self.port {
+            f.write_fmt(format_args!("{0}{1}", ",", value))?;
+        }
+        Ok(())
+    }
+}
+impl ::core::str::FromStr for Endpoint {
+    type Err = crate::error::AdbError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("endpoint:") {
+            Some(rest) => {
+                let rest = Some(rest);
+                let (value, rest) = match rest.map(|rest| rest.split_once(",")) {
+                    Some(Some((value, rest))) => (Some(value), Some(rest)),
+                    Some(None) => (rest, None),
+                    None => (None, None),
+                };
+                let host = match value {
+                    Some(value) => value.parse().map_err(|e| crate::error::AdbError::Parse {
+                        value: value.to_string(),
+                        source_type: "&str",
+                        target_type: "String",
+                        source: Some(Box::new(e)),
+                    })?,
+                    None => {
+                        return Err(crate::error::AdbError::Parse {
+                            value: s.to_string(),
+                            source_type: "&str",
+                            target_type: "String",
+                            source: None,
+                        })
+                    }
+                };
+                let retries = Default::default();
+                let value = rest;
+                let port = match value {
+                    Some(value) => {
+                        value
+                            .parse()
+                            .map(Some)
+                            .map_err(|e| crate::error::AdbError::Parse {
+                                value: value.to_string(),
+                                source_type: "&str",
+                                target_type: "Option < u16 >",
+                                source: Some(Box::new(e)),
+                            })?
+                    }
+                    None => Default::default(),
+                };
+                Ok(Self {
+                    host,
+                    retries,
+                    port,
+                })
+            }
+            None => Err(crate::error::AdbError::Parse {
+                value: s.to_string(),
+                source_type: "&str",
+                target_type: "Endpoint",
+                source: None,
+            }),
+        }
+    }
+}
+impl TryFrom<&str> for Endpoint {
+    type Error = crate::error::AdbError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let Self { host, port, .. } = s.parse()?;
+        Endpoint::new(host, port)
+    }
+}
+impl AdbSocketFamily for Endpoint {
+    const FAMILY: &'static str = "endpoint";
+}
+impl Endpoint {
+    fn new(host: String, port: Option<u16>) -> Result<Self, AdbError> {
+        Ok(Self {
+            host,
+            retries: 0,
+            port,
+        })
+    }
+}
+fn main() {}
@@ -0,0 +1,53 @@
+use derive::AdbSocketFamily;
+use error::AdbError;
+
+// The items of `adb` the generated code refers to.
+mod error {
+    #[derive(Debug)]
+    pub enum AdbError {
+        Parse {
+            value: String,
+            source_type: &'static str,
+            target_type: &'static str,
+            source: Option<Box<dyn std::error::Error + Send + Sync>>,
+        },
+    }
+}
+
+trait AdbSocketFamily: std::str::FromStr + std::fmt::Display {
+    const FAMILY: &'static str;
+}
+
+#[derive(AdbSocketFamily)]
+#[adb(rename = "local-abstract", validate)]
+struct LocalAbstract {
+    name: String,
+}
+
+impl LocalAbstract {
+    fn new(name: String) -> Result<Self, AdbError> {
+        Ok(Self { name })
+    }
+}
+
+#[derive(AdbSocketFamily)]
+#[adb(separator = ',', validate = "Endpoint::new")]
+struct Endpoint {
+    host: String,
+    #[adb(skip)]
+    retries: u32,
+    #[adb(default)]
+    port: Option<u16>,
+}
+
+impl Endpoint {
+    fn new(host: String, port: Option<u16>) -> Result<Self, AdbError> {
+        Ok(Self {
+            host,
+            retries: 0,
+            port,
+        })
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use derive::AdbSocketFamily;
+
+#[derive(AdbSocketFamily)]
+struct Tcp {
+    #[adb(default)]
+    host: Option<String>,
+    port: u16,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use derive::AdbSocketFamily;
+
+#[derive(AdbSocketFamily)]
+enum Families {
+    Tcp(String, u16),
+}
+
+fn main() {}
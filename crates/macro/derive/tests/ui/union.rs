@@ -0,0 +1,9 @@
+use derive::AdbSocketFamily;
+
+#[derive(AdbSocketFamily)]
+union Port {
+    tcp: u16,
+    jdwp: u32,
+}
+
+fn main() {}
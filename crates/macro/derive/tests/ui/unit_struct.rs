@@ -0,0 +1,6 @@
+use derive::AdbSocketFamily;
+
+#[derive(AdbSocketFamily)]
+struct Local;
+
+fn main() {}
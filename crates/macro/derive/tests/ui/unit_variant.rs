@@ -0,0 +1,9 @@
+use derive::AdbSocketFamily;
+
+#[derive(AdbSocketFamily)]
+enum Families {
+    Tcp(u16),
+    Local,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use derive::AdbSocketFamily;
+
+#[derive(AdbSocketFamily)]
+struct Tcp {
+    #[adb(skipped)]
+    port: u16,
+}
+
+fn main() {}
@@ -0,0 +1,229 @@
+use syn::parse::Parser;
+use syn::{Expr, ExprLit, Lit, LitBool, LitStr, Meta, MetaNameValue, Path};
+
+/// A type of the fields of structs generated by `attributed_field`, parsed from the
+/// attribute of the same name.
+///
+/// The attribute is `#[name = value]` or `#[name(value)]`. `#[name]` alone only sets `bool`
+/// fields, to `true`.
+pub trait AttrValue: Sized {
+    /// Parses the value of the attribute `meta`.
+    fn parse_attr(meta: &Meta) -> syn::Result<Self>;
+
+    /// Sets the field from another occurrence of the attribute. The last occurrence wins,
+    /// except for [`Vec`] fields, which collect all of them.
+    fn merge_attr(&mut self, meta: &Meta) -> syn::Result<()> {
+        *self = Self::parse_attr(meta)?;
+        Ok(())
+    }
+}
+
+/// Returns the literal of `#[name = <lit>]` or `#[name(<lit>)]`.
+fn lit(meta: &Meta) -> syn::Result<Lit> {
+    match meta {
+        Meta::NameValue(MetaNameValue {
+            value: Expr::Lit(ExprLit { lit, .. }),
+            ..
+        }) => Ok(lit.clone()),
+        Meta::NameValue(MetaNameValue { value, .. }) => {
+            Err(syn::Error::new_spanned(value, "expected a literal"))
+        }
+        Meta::List(list) => list.parse_args(),
+        Meta::Path(path) => Err(syn::Error::new_spanned(
+            path,
+            format!(
+                "expected a value, e.g. `#[{0} = ...]` or `#[{0}(...)]`",
+                path_name(path)
+            ),
+        )),
+    }
+}
+
+fn path_name(path: &Path) -> String {
+    path.segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+impl AttrValue for bool {
+    fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+        match meta {
+            Meta::Path(_) => Ok(true),
+            Meta::List(list) => Ok(list.parse_args::<LitBool>()?.value),
+            _ => match lit(meta)? {
+                Lit::Bool(b) => Ok(b.value),
+                lit => Err(syn::Error::new_spanned(lit, "expected `true` or `false`")),
+            },
+        }
+    }
+}
+
+impl AttrValue for String {
+    fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+        match lit(meta)? {
+            Lit::Str(s) => Ok(s.value()),
+            lit => Err(syn::Error::new_spanned(lit, "expected a string literal")),
+        }
+    }
+}
+
+impl AttrValue for LitStr {
+    fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+        match lit(meta)? {
+            Lit::Str(s) => Ok(s),
+            lit => Err(syn::Error::new_spanned(lit, "expected a string literal")),
+        }
+    }
+}
+
+impl AttrValue for char {
+    fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+        match lit(meta)? {
+            Lit::Char(c) => Ok(c.value()),
+            lit => Err(syn::Error::new_spanned(lit, "expected a char literal")),
+        }
+    }
+}
+
+macro_rules! impl_attr_value_int {
+    ($($ty:ty),*) => {$(
+        impl AttrValue for $ty {
+            fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+                match lit(meta)? {
+                    Lit::Int(int) => int.base10_parse(),
+                    lit => Err(syn::Error::new_spanned(lit, "expected an integer literal")),
+                }
+            }
+        }
+    )*};
+}
+
+impl_attr_value_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+macro_rules! impl_attr_value_float {
+    ($($ty:ty),*) => {$(
+        impl AttrValue for $ty {
+            fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+                match lit(meta)? {
+                    Lit::Float(float) => float.base10_parse(),
+                    Lit::Int(int) => int.base10_parse(),
+                    lit => Err(syn::Error::new_spanned(lit, "expected a number literal")),
+                }
+            }
+        }
+    )*};
+}
+
+impl_attr_value_float!(f32, f64);
+
+/// A path, e.g. `#[with = my::module]`, or `#[with = "my::module"]` as in serde.
+impl AttrValue for Path {
+    fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+        match meta {
+            Meta::NameValue(MetaNameValue {
+                value: Expr::Path(path),
+                ..
+            }) => Ok(path.path.clone()),
+            Meta::List(list) => list.parse_args(),
+            _ => match lit(meta)? {
+                Lit::Str(s) => s.parse(),
+                lit => Err(syn::Error::new_spanned(lit, "expected a path")),
+            },
+        }
+    }
+}
+
+/// The whole attribute, e.g. to parse nested lists with [`Meta::require_list`] and
+/// `parse_nested_meta`.
+impl AttrValue for Meta {
+    fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+        Ok(meta.clone())
+    }
+}
+
+/// An optional attribute, `None` if missing.
+impl<T: AttrValue> AttrValue for Option<T> {
+    fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+        T::parse_attr(meta).map(Some)
+    }
+}
+
+/// A repeatable attribute, e.g. `#[alias = "a"] #[alias = "b"]`. A list of values is also
+/// accepted, e.g. `#[alias("a", "b")]`.
+impl<T: AttrValue> AttrValue for Vec<T> {
+    fn parse_attr(meta: &Meta) -> syn::Result<Self> {
+        let mut values = Vec::new();
+        values.merge_attr(meta)?;
+        Ok(values)
+    }
+
+    fn merge_attr(&mut self, meta: &Meta) -> syn::Result<()> {
+        let Meta::List(list) = meta else {
+            self.push(T::parse_attr(meta)?);
+            return Ok(());
+        };
+        // Each item of the list is parsed as `#[name = item]`.
+        let items = syn::punctuated::Punctuated::<Expr, syn::Token![,]>::parse_terminated
+            .parse2(list.tokens.clone())?;
+        for item in items {
+            let item = Meta::NameValue(MetaNameValue {
+                path: list.path.clone(),
+                eq_token: Default::default(),
+                value: item,
+            });
+            self.push(T::parse_attr(&item)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::{parse_quote, Attribute};
+
+    fn parse<T: AttrValue>(attrs: &[Attribute]) -> syn::Result<T> {
+        let mut value = T::parse_attr(&attrs[0].meta)?;
+        for attr in &attrs[1..] {
+            value.merge_attr(&attr.meta)?;
+        }
+        Ok(value)
+    }
+
+    #[test]
+    fn test_parse_attr() {
+        assert!(parse::<bool>(&[parse_quote!(#[skip])]).unwrap());
+        assert!(!parse::<bool>(&[parse_quote!(#[skip = false])]).unwrap());
+        assert!(parse::<bool>(&[parse_quote!(#[skip(true)])]).unwrap());
+        assert_eq!(
+            "dev-raw:a::b",
+            parse::<String>(&[parse_quote!(#[rename = "dev-raw:a::b"])]).unwrap()
+        );
+        assert_eq!(
+            ',',
+            parse::<char>(&[parse_quote!(#[separator(',')])]).unwrap()
+        );
+        assert_eq!(42u16, parse(&[parse_quote!(#[port = 42])]).unwrap());
+        assert!(parse::<u8>(&[parse_quote!(#[port = 256])]).is_err());
+        assert!(parse::<u8>(&[parse_quote!(#[port = "42"])]).is_err());
+        assert!(parse::<String>(&[parse_quote!(#[rename])]).is_err());
+        let path: Path = parse(&[parse_quote!(#[with = std::string::String])]).unwrap();
+        assert_eq!("std::string::String", path_name(&path));
+        let path: Path = parse(&[parse_quote!(#[with = "my::module"])]).unwrap();
+        assert_eq!("my::module", path_name(&path));
+        assert_eq!(
+            Some(1.5),
+            parse::<Option<f64>>(&[parse_quote!(#[ratio = 1.5])]).unwrap()
+        );
+        let aliases: Vec<String> = parse(&[
+            parse_quote!(#[alias = "a"]),
+            parse_quote!(#[alias("b", "c")]),
+        ])
+        .unwrap();
+        assert_eq!(["a", "b", "c"], &aliases[..]);
+        let meta: Meta = parse(&[parse_quote!(#[adb(rename = "x", skip)])]).unwrap();
+        assert!(meta.require_list().is_ok());
+    }
+}
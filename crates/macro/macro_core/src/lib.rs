@@ -1,7 +1,13 @@
-use proc_macro2::{Ident, TokenStream};
+use proc_macro2::Ident;
 use syn::spanned::Spanned;
 use syn::{Field, Fields, FieldsNamed, FieldsUnnamed};
 
+mod attr_value;
+mod pretty;
+
+pub use attr_value::AttrValue;
+pub use pretty::pretty;
+
 /// Convert unnamed fields to named fields with default names `field0`, `field1`, etc.
 pub fn add_default_field_name(unnamed: &FieldsUnnamed) -> Fields {
     Fields::Named(FieldsNamed {
@@ -18,13 +24,3 @@ pub fn add_default_field_name(unnamed: &FieldsUnnamed) -> Fields {
         brace_token: Default::default(),
     })
 }
-
-/// Display a struct with named fields in a pretty format.
-pub fn pretty_named_struct(input: TokenStream) -> String {
-    input
-        .to_string()
-        .replace("{ ", "{\n\t")
-        .replace(" }", "\n}")
-        .replace(", ", ",\n\t")
-        .replace(" :", ":")
-}
@@ -0,0 +1,324 @@
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+
+/// Keywords followed by a space before groups and operands, e.g. `impl (A, B)`.
+const KEYWORDS: &[&str] = &[
+    "as", "break", "const", "dyn", "else", "enum", "fn", "for", "if", "impl", "in", "let", "loop",
+    "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct", "trait", "type",
+    "union", "unsafe", "use", "where", "while",
+];
+
+/// Formats `item` as Rust source, e.g. for the help messages of diagnostics.
+///
+/// Unlike `TokenStream::to_string`, items are laid out like rustfmt would: the contents of
+/// braces are indented on their own lines, one field, statement or attribute per line, and
+/// paths, generics, references and lifetimes aren't spaced out, e.g. `Option<&'a str>`
+/// instead of `Option < & 'a str >`. Expressions are formatted on one line.
+pub fn pretty(item: &impl ToTokens) -> String {
+    let mut printer = Printer::default();
+    printer.stream(item.to_token_stream(), Context::Top);
+    printer.out.trim_end().to_string()
+}
+
+/// Where tokens are printed.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Context {
+    /// Outside any group: items follow each other on their own lines.
+    Top,
+    /// In braces: fields, statements and attributes each go on their own line.
+    Block,
+    /// In parentheses or brackets: everything stays on the line.
+    Inline,
+}
+
+/// What the last printed token expects of the next one.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Prev {
+    /// The start of a line or a group: no space.
+    Start,
+    /// An identifier, literal or group, which generics, calls and postfix operators attach
+    /// to.
+    Operand,
+    /// A keyword, followed by a space.
+    Keyword,
+    /// Punctuation followed by a space, e.g. `,` or `=`.
+    Spaced,
+    /// Punctuation the next token attaches to, e.g. `::`, `#`, `'` or unary `&`.
+    Attached,
+}
+
+struct Printer {
+    out: String,
+    indent: usize,
+    prev: Prev,
+    /// The last identifier, if it's the last token.
+    last_ident: Option<String>,
+    /// The depth of the generics being printed.
+    angles: usize,
+    /// `true` in a `where` clause, whose commas stay on the line.
+    in_where: bool,
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self {
+            out: String::new(),
+            indent: 0,
+            prev: Prev::Start,
+            last_ident: None,
+            angles: 0,
+            in_where: false,
+        }
+    }
+}
+
+impl Printer {
+    /// Writes `s`, preceded by the indentation at the start of a line, or by a space.
+    fn write(&mut self, s: &str, space: bool) {
+        if self.out.is_empty() || self.out.ends_with('\n') {
+            self.out.push_str(&"    ".repeat(self.indent));
+        } else if space && self.prev != Prev::Start && self.prev != Prev::Attached {
+            self.out.push(' ');
+        }
+        self.out.push_str(s);
+        self.last_ident = None;
+    }
+
+    fn newline(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+        self.prev = Prev::Start;
+    }
+
+    fn stream(&mut self, stream: TokenStream, context: Context) {
+        let tokens: Vec<TokenTree> = stream.into_iter().collect();
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i] {
+                TokenTree::Ident(ident) => {
+                    let ident = ident.to_string();
+                    self.write(&ident, true);
+                    if ident == "where" {
+                        self.in_where = true;
+                    }
+                    self.prev = if KEYWORDS.contains(&ident.as_str()) {
+                        Prev::Keyword
+                    } else {
+                        Prev::Operand
+                    };
+                    self.last_ident = Some(ident);
+                }
+                TokenTree::Literal(literal) => {
+                    self.write(&literal.to_string(), true);
+                    self.prev = Prev::Operand;
+                }
+                TokenTree::Punct(_) => {
+                    // Joint punctuation forms a single operator, e.g. `::` or `->`.
+                    let mut op = String::new();
+                    while let Some(TokenTree::Punct(punct)) = tokens.get(i) {
+                        op.push(punct.as_char());
+                        i += 1;
+                        // Lifetimes are joint to the punctuation before, e.g. `&'a`.
+                        let lifetime = matches!(
+                            tokens.get(i),
+                            Some(TokenTree::Punct(next)) if next.as_char() == '\''
+                        );
+                        if punct.spacing() == Spacing::Alone || punct.as_char() == '\'' || lifetime
+                        {
+                            break;
+                        }
+                    }
+                    i -= 1;
+                    self.punct(&op, context);
+                }
+                TokenTree::Group(group) => {
+                    let after_hash = self.prev == Prev::Attached && self.out.ends_with('#');
+                    self.group(group.delimiter(), group.stream(), context);
+                    let next = tokens.get(i + 1);
+                    if context != Context::Inline
+                        && (after_hash && group.delimiter() == Delimiter::Bracket
+                            || group.delimiter() == Delimiter::Brace && ends_item(next))
+                    {
+                        self.newline();
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+
+    fn punct(&mut self, op: &str, context: Context) {
+        let operand = self.prev == Prev::Operand;
+        match op {
+            "," | ";" => {
+                self.write(op, false);
+                self.prev = Prev::Spaced;
+                if op == ";" {
+                    self.in_where = false;
+                }
+                if context != Context::Inline && self.angles == 0 && !self.in_where {
+                    self.newline();
+                }
+            }
+            ":" => {
+                self.write(op, false);
+                self.prev = Prev::Spaced;
+            }
+            "?" => {
+                self.write(op, false);
+                self.prev = Prev::Operand;
+            }
+            "::" | "." | ".." | "..=" => {
+                // Leading `::` and `..` are spaced, e.g. `impl ::std::fmt::Display`.
+                self.write(op, !operand);
+                self.prev = Prev::Attached;
+            }
+            "'" | "#" | "#!" => {
+                self.write(op, true);
+                self.prev = Prev::Attached;
+            }
+            "!" if operand && self.last_ident.is_some() => {
+                // A macro call, e.g. `vec!`.
+                self.write(op, false);
+                self.prev = Prev::Attached;
+            }
+            "<" if self.last_ident.is_some() && (operand || self.generic_keyword())
+                || self.out.ends_with("::") =>
+            {
+                self.write(op, false);
+                self.angles += 1;
+                self.prev = Prev::Attached;
+            }
+            _ if self.angles > 0 && op.chars().all(|c| c == '>') => {
+                self.write(op, false);
+                self.angles = self.angles.saturating_sub(op.len());
+                self.prev = Prev::Operand;
+            }
+            "!" | "&" | "&&" | "*" | "-" if !operand => {
+                // A unary operator, e.g. `&self`.
+                self.write(op, true);
+                self.prev = Prev::Attached;
+            }
+            _ => {
+                self.write(op, true);
+                self.prev = Prev::Spaced;
+            }
+        }
+    }
+
+    /// Returns `true` if the last identifier is a keyword taking generics, e.g. `impl<T>`.
+    fn generic_keyword(&self) -> bool {
+        matches!(self.last_ident.as_deref(), Some("impl" | "for"))
+    }
+
+    fn group(&mut self, delimiter: Delimiter, stream: TokenStream, context: Context) {
+        let (open, close) = match delimiter {
+            Delimiter::Parenthesis => ("(", ")"),
+            Delimiter::Bracket => ("[", "]"),
+            Delimiter::Brace => ("{", "}"),
+            Delimiter::None => {
+                self.stream(stream, context);
+                return;
+            }
+        };
+        // Calls and indexing attach to their operand, and `pub(crate)` to `pub`.
+        let attached = delimiter != Delimiter::Brace
+            && (self.prev == Prev::Operand || self.last_ident.as_deref() == Some("pub"));
+        let angles = std::mem::take(&mut self.angles);
+        let in_where = std::mem::take(&mut self.in_where);
+        self.write(open, !attached);
+        self.prev = Prev::Start;
+        if stream.is_empty() {
+            self.write(close, false);
+        } else if delimiter == Delimiter::Brace && context != Context::Inline {
+            self.newline();
+            self.indent += 1;
+            self.stream(stream, Context::Block);
+            self.newline();
+            self.indent -= 1;
+            self.write(close, false);
+        } else if delimiter == Delimiter::Brace {
+            self.out.push(' ');
+            self.stream(stream, Context::Inline);
+            self.write(close, true);
+        } else {
+            self.stream(stream, Context::Inline);
+            self.write(close, false);
+        }
+        self.angles = angles;
+        self.in_where = in_where && delimiter != Delimiter::Brace;
+        self.prev = Prev::Operand;
+    }
+}
+
+/// Returns `true` if a block followed by `next` ends an item or a statement, e.g. not
+/// `} else {` or `},`.
+fn ends_item(next: Option<&TokenTree>) -> bool {
+    match next {
+        None => true,
+        Some(TokenTree::Punct(punct)) => !matches!(punct.as_char(), ',' | ';' | '.' | '?' | ')'),
+        Some(TokenTree::Ident(ident)) => ident != "else",
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+
+    #[test]
+    fn test_pretty() {
+        let item = quote! {
+            #[derive(Debug)]
+            pub(crate) struct Pair<'a, T: Display> where T: Clone, {
+                #[adb(skip)]
+                first: &'a T,
+                second: Option<Vec<T>>,
+                map: std::collections::HashMap<String, (u8, [u16; 4])>,
+            }
+        };
+        assert_eq!(
+            "\
+#[derive(Debug)]
+pub(crate) struct Pair<'a, T: Display> where T: Clone, {
+    #[adb(skip)]
+    first: &'a T,
+    second: Option<Vec<T>>,
+    map: std::collections::HashMap<String, (u8, [u16; 4])>,
+}",
+            pretty(&item)
+        );
+        let item = quote! {
+            impl<T> Pair<T> {
+                fn new(first: &mut T) -> Result<Self, crate::Error> {
+                    let x = vec![1, 2];
+                    if !x.is_empty() { return Ok(Self { first }); } else { x.len()?; }
+                    Err(Error::new())
+                }
+            }
+            struct Unit;
+        };
+        assert_eq!(
+            "\
+impl<T> Pair<T> {
+    fn new(first: &mut T) -> Result<Self, crate::Error> {
+        let x = vec![1, 2];
+        if !x.is_empty() {
+            return Ok(Self { first });
+        } else {
+            x.len()?;
+        }
+        Err(Error::new())
+    }
+}
+struct Unit;",
+            pretty(&item)
+        );
+        assert_eq!(
+            "(bool, Option<String>)",
+            pretty(&quote!((bool, Option<String>)))
+        );
+    }
+}
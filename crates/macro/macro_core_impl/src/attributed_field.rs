@@ -1,43 +1,105 @@
-use macro_core::{add_default_field_name, pretty_named_struct};
+use macro_core::{add_default_field_name, pretty};
 use proc_macro2::{Ident, TokenStream};
 use proc_macro_error::abort;
 use quote::{format_ident, quote, ToTokens};
+use syn::ext::IdentExt;
 use syn::punctuated::Punctuated;
-use syn::{parse_quote, Field, Fields, FieldsNamed, ItemStruct, Token};
+use syn::{parse_quote, Field, Fields, FieldsNamed, Generics, ItemStruct, Path, Token};
+
+/// Removes the `#[namespace(path)]` attribute of the struct, and returns its path.
+fn take_namespace(input: &mut ItemStruct) -> Option<Path> {
+    let mut namespace = None;
+    let mut error = None;
+    input.attrs.retain(|attr| {
+        if !attr.path().is_ident("namespace") {
+            return true;
+        }
+        match attr.parse_args::<Path>() {
+            Ok(path) if namespace.is_none() => namespace = Some(path),
+            Ok(_) => {
+                error = Some(syn::Error::new_spanned(
+                    attr.path(),
+                    "duplicate `namespace`",
+                ))
+            }
+            Err(e) => error = Some(e),
+        }
+        false
+    });
+    if let Some(e) = error {
+        abort!(
+            e.span(), "{}", e;
+            help = "declare the namespace once, e.g. `#[namespace(adb)]`";
+        );
+    }
+    namespace
+}
+
+/// Returns the string form of `path`, e.g. `serde::rename`.
+fn path_string(path: &Path) -> String {
+    path.segments
+        .iter()
+        .map(|segment| segment.ident.unraw().to_string())
+        .collect::<Vec<_>>()
+        .join("::")
+}
 
 pub fn impl_attributed_field(mut input: ItemStruct) -> TokenStream {
+    let namespace = take_namespace(&mut input);
+    impl_fields(input, namespace.as_ref())
+}
+
+fn impl_fields(mut input: ItemStruct, namespace: Option<&Path>) -> TokenStream {
     let ident = &input.ident;
     match &mut input.fields {
-        Fields::Unit => impl_attributed_field(ItemStruct {
-            fields: Fields::Named(FieldsNamed {
-                named: Punctuated::new(),
-                brace_token: Default::default(),
-            }),
-            ..input
-        }),
+        Fields::Unit => impl_fields(
+            ItemStruct {
+                fields: Fields::Named(FieldsNamed {
+                    named: Punctuated::new(),
+                    brace_token: Default::default(),
+                }),
+                ..input
+            },
+            namespace,
+        ),
         Fields::Unnamed(unnamed) => {
             let output = ItemStruct {
                 ident: ident.clone(),
                 fields: add_default_field_name(unnamed),
+                semi_token: None,
                 ..input
-            }
-            .into_token_stream();
+            };
             abort!(
                 unnamed,
-                "struct `{}` has unnamed fields `{}`", ident, unnamed.to_token_stream();
+                "struct `{}` has unnamed fields `{}`", ident, pretty(unnamed);
                 note = "attributed_field can only be applied to structs with named fields";
-                help = "add names to the fields:\n{}", pretty_named_struct(output);
+                help = "add names to the fields:\n{}", pretty(&output);
             );
         }
         Fields::Named(FieldsNamed { named, .. }) => {
-            let from_field = impl_from_field(ident, named);
+            if let Some(original) = named
+                .iter()
+                .find(|field| *field.ident.as_ref().unwrap() == "__original")
+            {
+                abort!(
+                    original,
+                    "struct `{}` has a field named `__original`", ident;
+                    note = "attributed_field adds the field `__original: syn::Field` holding the original field";
+                    help = "rename the field";
+                );
+            }
+            let from_field = impl_from_field(ident, &input.generics, named, namespace);
             named.push(parse_quote!(__original: syn::Field));
             let extra_getters = impl_extra_getters(named);
-            quote! {
-                #input
-                impl #ident {
+            let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+            let getters = quote! {
+                impl #impl_generics #ident #ty_generics #where_clause {
                     #extra_getters
                 }
+            };
+            quote! {
+                #input
+                #getters
                 #from_field
             }
         }
@@ -77,10 +139,16 @@ fn impl_extra_getters(fields: &Punctuated<Field, Token![,]>) -> TokenStream {
     quote! { #(#getters)* }
 }
 
-fn impl_from_field(name: &Ident, fields: &Punctuated<Field, Token![,]>) -> TokenStream {
-    if fields.is_empty() {
+fn impl_from_field(
+    name: &Ident,
+    generics: &Generics,
+    fields: &Punctuated<Field, Token![,]>,
+    namespace: Option<&Path>,
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    if fields.is_empty() && namespace.is_none() {
         return quote! {
-            impl From<syn::Field> for #name {
+            impl #impl_generics From<syn::Field> for #name #ty_generics #where_clause {
                 fn from(field: syn::Field) -> Self {
                     Self { __original: field }
                 }
@@ -93,58 +161,90 @@ fn impl_from_field(name: &Ident, fields: &Punctuated<Field, Token![,]>) -> Token
     for field in fields {
         let ident = field.ident.as_ref().unwrap();
         let ty = &field.ty;
-        let ident_str = ident.to_string();
-        decl.push(quote! { let mut #ident = #ty::default();});
+        let ident_str = ident.unraw().to_string();
+        let ty_str = pretty(ty);
+        decl.push(quote! { let mut #ident = <#ty as Default>::default(); });
         arms.push(quote! {
             #ident_str => {
-                let evaluated = evalexpr::eval(&*tokens_str)
-                    .unwrap_or_else(|e| proc_macro_error::abort!(
-                        tokens,
-                        "failed to evaluate `{}`: {}", tokens, e;
-                        note = "the value of attribute `{}` is not a valid Rust expression", #ident_str;
-                        )
-                    );
-                #ident = evaluated
-                    .clone()
-                    .try_into()
-                    .unwrap_or_else(|e| proc_macro_error::abort!(
-                        tokens,
-                        "failed to convert `{}` to type `{}`: {}", tokens, stringify!(#ty), e;
-                        note = "the evaluated value of attribute `{}` is `{}`", #ident_str, evaluated;
-                        )
-                    );
+                macro_core::AttrValue::merge_attr(&mut #ident, meta).unwrap_or_else(|e| {
+                    proc_macro_error::abort!(
+                        e.span(),
+                        "invalid attribute `{}`: {}", #ident_str, e;
+                        note = "the field `{}` has type `{}`", #ident_str, #ty_str;
+                    )
+                });
             }
         });
         assign.push(quote! { #ident });
     }
-    let matches = quote! {
-        match ident.as_str() {
-            #(#arms)*
-            _ => {}
+    // The path of each attribute is matched as a whole, e.g. `#[serde::rename]` doesn't set
+    // a field `rename`.
+    let to_string = quote! {
+        |path: &syn::Path| {
+            path.segments
+                .iter()
+                .map(|segment| syn::ext::IdentExt::unraw(&segment.ident).to_string())
+                .collect::<Vec<_>>()
+                .join("::")
+        }
+    };
+    let body = match namespace {
+        // Attributes of other namespaces are left to their own macros.
+        None => quote! {
+            for attr in &field.attrs {
+                let meta = &attr.meta;
+                match (#to_string)(attr.path()).as_str() {
+                    #(#arms)*
+                    _ => {}
+                }
+            }
+        },
+        // Only the items of `#[namespace(...)]` set fields, and unknown ones are errors.
+        Some(namespace) => {
+            let namespace_str = path_string(namespace);
+            let expected = if fields.is_empty() {
+                "no attribute is expected".to_string()
+            } else {
+                let keys: Vec<_> = fields
+                    .iter()
+                    .map(|field| format!("`{}`", field.ident.as_ref().unwrap().unraw()))
+                    .collect();
+                format!("expected one of {}", keys.join(", "))
+            };
+            quote! {
+                for attr in &field.attrs {
+                    if (#to_string)(attr.path()) != #namespace_str {
+                        continue;
+                    }
+                    let items = attr
+                        .parse_args_with(
+                            syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated,
+                        )
+                        .unwrap_or_else(|e| {
+                            proc_macro_error::abort!(
+                                e.span(), "invalid attribute `{}`: {}", #namespace_str, e
+                            )
+                        });
+                    for meta in &items {
+                        let key = (#to_string)(meta.path());
+                        match key.as_str() {
+                            #(#arms)*
+                            _ => proc_macro_error::abort!(
+                                meta.path(),
+                                "unknown attribute `{}({})`", #namespace_str, key;
+                                help = #expected;
+                            ),
+                        }
+                    }
+                }
+            }
         }
     };
     quote! {
-        impl From<syn::Field> for #name {
+        impl #impl_generics From<syn::Field> for #name #ty_generics #where_clause {
             fn from(field: syn::Field) -> Self {
                 #(#decl)*
-                for attr in &field.attrs {
-                    let (s, tokens) = match &attr.meta {
-                        syn::Meta::Path(syn::Path { segments, .. }) => (segments, quote! { true }),
-                        syn::Meta::List(syn::MetaList {
-                            path: syn::Path { segments, .. },
-                            tokens,
-                            ..
-                        }) => (segments, tokens.clone()),
-                        syn::Meta::NameValue(syn::MetaNameValue {
-                            path: syn::Path { segments, .. },
-                            value,
-                            ..
-                        }) => (segments, value.to_token_stream()),
-                    };
-                    let ident = s.last().unwrap().ident.to_string();
-                    let tokens_str = tokens.to_string();
-                    #matches
-                }
+                #body
                 Self { #(#assign,)* __original: field }
             }
         }
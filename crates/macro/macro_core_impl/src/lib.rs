@@ -20,6 +20,19 @@ mod attributed_field;
 /// # Note
 ///
 /// - The struct must have named or no fields.
+/// - The fields are parsed from the attributes of the same name with
+///   `macro_core::AttrValue`, implemented for `bool`, strings, chars, numbers, paths,
+///   `syn::Meta`, and `Option` and `Vec` of them. `#[name]` alone sets `bool` fields, and
+///   `Vec` fields collect repeated attributes. The crate using the macro depends on
+///   `macro_core`, `proc-macro-error` and `syn`.
+/// - Attributes are matched on their whole path, so `#[serde::rename]` doesn't set a field
+///   `rename`. Attributes matching no field are ignored, as they belong to other macros.
+/// - With `#[namespace(ns)]` on the struct, the fields are parsed from the items of
+///   `#[ns(...)]` instead, e.g. `#[ns(skip, rename = "x")]`, and an item matching no field
+///   is a compile error at its span. `#[namespace]` is removed from the output struct.
+/// - Generic parameters and `where` clauses of the struct are carried to the generated
+///   impls. The types of the fields must implement `Default` and `AttrValue`, which generic
+///   fields state in the bounds of the struct.
 /// - The struct **must not** have a field named `__original`.
 /// - If the struct has fields named `vis`, `mutability`, `ident`, `ty`,
 ///   the metadata (not the field) getters will be generated with a prefix `__`.
@@ -102,6 +115,21 @@ mod attributed_field;
 ///     fn from(__original: syn::Field) -> Self;
 /// }
 ///```
+///
+/// Helper attributes are usually grouped under the name of the derive instead, e.g.
+/// `#[trait_a(helper1, helper2)]`:
+///
+/// ```ignore
+/// attributed_field! {
+///     #[namespace(trait_a)]
+///     struct TraitAField {
+///         helper1: bool,
+///         helper2: bool,
+///     }
+/// }
+///
+/// // `#[trait_a(helper3)]` fails with "unknown attribute `trait_a(helper3)`".
+/// ```
 #[proc_macro_error]
 #[proc_macro]
 pub fn attributed_field(input: TokenStream) -> TokenStream {
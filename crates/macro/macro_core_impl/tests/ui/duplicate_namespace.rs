@@ -0,0 +1,11 @@
+use macro_core_impl::attributed_field;
+
+attributed_field! {
+    #[namespace(helper)]
+    #[namespace(other)]
+    struct HelperField {
+        skip: bool,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use macro_core_impl::attributed_field;
+
+attributed_field! {
+    struct HelperField {
+        skip: bool,
+        __original: bool,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use macro_core_impl::attributed_field;
+
+attributed_field! {
+    struct HelperField(bool, Option<String>);
+}
+
+fn main() {}
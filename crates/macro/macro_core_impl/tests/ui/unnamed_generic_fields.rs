@@ -0,0 +1,10 @@
+use macro_core_impl::attributed_field;
+
+attributed_field! {
+    #[derive(Debug)]
+    struct HelperField<'a, T>(Option<&'a T>, Vec<(T, u8)>)
+    where
+        T: Clone + Default;
+}
+
+fn main() {}
@@ -0,0 +1,19 @@
+#![no_main]
+
+use adb::error::AdbError;
+use adb::protocol::{decode_length_prefixed, decode_status, Decoded};
+use libfuzzer_sys::fuzz_target;
+
+fn check<T>(data: &[u8], decoded: Result<Decoded<T>, AdbError>) {
+    match decoded {
+        Ok(Decoded::Complete { length, .. }) => assert!(length <= data.len()),
+        // Decoders must always ask for more than they were given, or readers would spin.
+        Ok(Decoded::Incomplete { needed }) => assert!(needed > data.len()),
+        Err(_) => {}
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    check(data, decode_status(data));
+    check(data, decode_length_prefixed(data));
+});
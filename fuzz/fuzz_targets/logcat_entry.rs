@@ -0,0 +1,14 @@
+#![no_main]
+
+use adb::logcat::decode_entry;
+use adb::protocol::Decoded;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    match decode_entry(data) {
+        Ok(Decoded::Complete { length, .. }) => assert!(length <= data.len()),
+        // Decoders must always ask for more than they were given, or readers would spin.
+        Ok(Decoded::Incomplete { needed }) => assert!(needed > data.len()),
+        Err(_) => {}
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use adb::socket::AdbSocketFamilies;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(family) = data.parse::<AdbSocketFamilies>() {
+        let _ = family.to_string();
+    }
+});
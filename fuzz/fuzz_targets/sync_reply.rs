@@ -0,0 +1,21 @@
+#![no_main]
+
+use adb::protocol::Decoded;
+use adb::sync::{decode_reply, SyncRequest};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for request in [
+        SyncRequest::Stat,
+        SyncRequest::List,
+        SyncRequest::Send,
+        SyncRequest::Recv,
+    ] {
+        match decode_reply(data, request) {
+            Ok(Decoded::Complete { length, .. }) => assert!(length <= data.len()),
+            // Decoders must always ask for more than they were given, or readers would spin.
+            Ok(Decoded::Incomplete { needed }) => assert!(needed > data.len()),
+            Err(_) => {}
+        }
+    }
+});